@@ -67,7 +67,7 @@ async fn add_items_and_sync_again(provider: &mut CalDavProvider) {
         .lock().unwrap().add_item(Item::Task(new_task)).await.unwrap();
 
 
-    if provider.sync().await == false {
+    if !provider.sync().await.is_success() {
         log::warn!("Sync did not complete, see the previous log lines for more info. You can safely start a new sync. The new task may not have been synced.");
     } else {
         println!("Done syncing the new task '{}' and the new calendar '{}'", new_task_name, new_calendar_name);
@@ -91,7 +91,7 @@ async fn complete_item_and_sync_again(
         .unwrap_task_mut()
         .set_completion_status(completion_status);
 
-    if provider.sync().await == false {
+    if !provider.sync().await.is_success() {
         log::warn!("Sync did not complete, see the previous log lines for more info. You can safely start a new sync. The new task may not have been synced.");
     } else {
         println!("Done syncing the completed task");
@@ -114,7 +114,7 @@ async fn remove_items_and_sync_again(
         .lock().unwrap()
         .mark_for_deletion(id_to_remove).await.unwrap();
 
-    if provider.sync().await == false {
+    if !provider.sync().await.is_success() {
         log::warn!("Sync did not complete, see the previous log lines for more info. You can safely start a new sync. The new task may not have been synced.");
     } else {
         println!("Done syncing the deleted task");
@@ -43,7 +43,7 @@ pub async fn initial_sync() -> CalDavProvider {
     println!("Starting a sync...");
     println!("Depending on your RUST_LOG value, you may see more or less details about the progress.");
     // Note that we could use sync_with_feedback() to have better and formatted feedback
-    if provider.sync().await == false {
+    if !provider.sync().await.is_success() {
         log::warn!("Sync did not complete, see the previous log lines for more info. You can safely start a new sync.");
     }
     provider.local().save_to_folder().unwrap();
@@ -46,7 +46,7 @@ async fn toggle_all_tasks_and_sync_again(provider: &mut CalDavProvider) -> Resul
                     };
                     n_toggled += 1;
                 }
-                Item::Event(_) => {
+                Item::Event(_) | Item::Contact(_) => {
                     // Not doing anything with calendar events
                 },
             }
@@ -0,0 +1,297 @@
+//! Python bindings of kitchen-fridge
+//!
+//! They wrap the [`blocking`](kitchen_fridge::blocking) API, so that every Python call returns once it is done.
+//! Calendars, tasks and events are snapshots of the cache: changes are made through the `Provider`, and are pushed at the next sync.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use pyo3::exceptions::{PyKeyError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use url::Url;
+
+use kitchen_fridge::blocking;
+use kitchen_fridge::cache::Cache;
+use kitchen_fridge::error::KFError;
+use kitchen_fridge::calendar::cached_calendar::CachedCalendar;
+use kitchen_fridge::item::Item;
+use kitchen_fridge::task::CompletionStatus;
+use kitchen_fridge::traits::BaseCalendar;
+
+fn to_py_err(err: KFError) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+fn parse_url(url: &str) -> PyResult<Url> {
+    url.parse().map_err(|err| PyValueError::new_err(format!("Invalid URL {:?}: {}", url, err)))
+}
+
+
+/// A to-do task
+#[pyclass(name = "Task")]
+#[derive(Clone)]
+struct PyTask {
+    inner: kitchen_fridge::Task,
+}
+
+#[pymethods]
+impl PyTask {
+    #[getter]
+    fn url(&self) -> String {
+        self.inner.url().to_string()
+    }
+
+    #[getter]
+    fn uid(&self) -> &str {
+        self.inner.uid()
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    #[getter]
+    fn completed(&self) -> bool {
+        self.inner.completed()
+    }
+
+    /// The last modification date, as an ISO 8601 string
+    #[getter]
+    fn last_modified(&self) -> String {
+        self.inner.last_modified().to_rfc3339()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Task({:?}, completed={})", self.inner.name(), if self.inner.completed() { "True" } else { "False" })
+    }
+}
+
+
+/// A calendar event
+#[pyclass(name = "Event")]
+#[derive(Clone)]
+struct PyEvent {
+    inner: kitchen_fridge::Event,
+}
+
+#[pymethods]
+impl PyEvent {
+    #[getter]
+    fn url(&self) -> String {
+        self.inner.url().to_string()
+    }
+
+    #[getter]
+    fn uid(&self) -> &str {
+        self.inner.uid()
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Event({:?})", self.inner.name())
+    }
+}
+
+
+/// A snapshot of a calendar of the cache
+#[pyclass(name = "Calendar")]
+#[derive(Clone)]
+struct PyCalendar {
+    url: Url,
+    name: String,
+    color: Option<String>,
+    description: Option<String>,
+    timezone: Option<String>,
+    read_only: bool,
+    tasks: Vec<PyTask>,
+    events: Vec<PyEvent>,
+}
+
+impl PyCalendar {
+    fn from_calendar(cal: &CachedCalendar) -> PyResult<Self> {
+        let mut tasks = Vec::new();
+        let mut events = Vec::new();
+        for item in cal.get_items_sync().map_err(to_py_err)?.into_values() {
+            match item {
+                Item::Task(task) => tasks.push(PyTask{ inner: task.clone() }),
+                Item::Event(event) => events.push(PyEvent{ inner: event.clone() }),
+                // Calendars do not contain contacts
+                Item::Contact(_) => (),
+            }
+        }
+        tasks.sort_by(|a, b| a.inner.name().cmp(b.inner.name()));
+        events.sort_by(|a, b| a.inner.name().cmp(b.inner.name()));
+
+        Ok(Self {
+            url: cal.url().clone(),
+            name: cal.name().to_string(),
+            color: cal.color().map(|color| color.to_hex_string()),
+            description: cal.description().map(String::from),
+            timezone: cal.timezone().map(|timezone| timezone.tzid().to_string()),
+            read_only: cal.is_read_only(),
+            tasks,
+            events,
+        })
+    }
+}
+
+#[pymethods]
+impl PyCalendar {
+    #[getter]
+    fn url(&self) -> String {
+        self.url.to_string()
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The color of the calendar, as a `#rrggbb` string
+    #[getter]
+    fn color(&self) -> Option<&str> {
+        self.color.as_deref()
+    }
+
+    #[getter]
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The identifier of the default time zone of the calendar (e.g. `Europe/Paris`), for its floating and all-day items
+    #[getter]
+    fn timezone(&self) -> Option<&str> {
+        self.timezone.as_deref()
+    }
+
+    /// Whether tasks cannot be added, modified or deleted in this calendar
+    #[getter]
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// The tasks of this calendar, sorted by name
+    fn tasks(&self) -> Vec<PyTask> {
+        self.tasks.clone()
+    }
+
+    /// The events of this calendar, sorted by name
+    fn events(&self) -> Vec<PyEvent> {
+        self.events.clone()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Calendar({:?}, {} tasks, {} events)", self.name, self.tasks.len(), self.events.len())
+    }
+}
+
+
+/// Syncs the calendars of a CalDAV server with a local cache
+#[pyclass(name = "Provider", unsendable)]
+struct PyProvider {
+    inner: blocking::CalDavProvider,
+}
+
+#[pymethods]
+impl PyProvider {
+    /// Connect to a server, and use the cache stored in `cache_folder` (that is created if needed)
+    #[new]
+    fn new(url: &str, username: &str, password: &str, cache_folder: &str) -> PyResult<Self> {
+        let client = kitchen_fridge::Client::new(url, username, password).map_err(to_py_err)?;
+        let cache_folder = Path::new(cache_folder);
+        // An existing cache that cannot be loaded must not be replaced by an empty one
+        let cache = match cache_folder.exists() {
+            true => Cache::from_folder(cache_folder).map_err(to_py_err)?,
+            false => Cache::new(cache_folder),
+        };
+        Ok(Self { inner: blocking::Provider::new(client, cache) })
+    }
+
+    /// Sync every calendar, and save the cache. Returns whether the sync has succeeded
+    fn sync(&mut self) -> PyResult<bool> {
+        let success = self.inner.sync().is_success();
+        self.inner.local().save_to_folder().map_err(to_py_err)?;
+        Ok(success)
+    }
+
+    /// Snapshots of the calendars of the cache, in the order the user has set (or by name)
+    fn calendars(&self) -> PyResult<Vec<PyCalendar>> {
+        let mut calendars = Vec::new();
+        for cal in self.inner.local().get_ordered_calendars_sync().map_err(to_py_err)? {
+            calendars.push(PyCalendar::from_calendar(&cal.lock().unwrap())?);
+        }
+        Ok(calendars)
+    }
+
+    /// Create a task in a calendar of the cache. It is pushed to the server at the next sync
+    fn add_task(&mut self, calendar_url: &str, name: &str) -> PyResult<PyTask> {
+        let cal = self.calendar(calendar_url)?;
+        let task = kitchen_fridge::Task::new(name.to_string(), false, &parse_url(calendar_url)?);
+        cal.lock().unwrap().add_item_sync(Item::Task(task.clone())).map_err(to_py_err)?;
+        Ok(PyTask{ inner: task })
+    }
+
+    /// Mark a task as completed (or not)
+    fn set_completed(&mut self, task_url: &str, completed: bool) -> PyResult<()> {
+        self.with_task(task_url, |task| task.set_completion_status(match completed {
+            true => CompletionStatus::Completed(Some(Utc::now())),
+            false => CompletionStatus::Uncompleted,
+        }))
+    }
+
+    fn rename_task(&mut self, task_url: &str, name: &str) -> PyResult<()> {
+        self.with_task(task_url, |task| task.set_name(name.to_string()))
+    }
+
+    /// Delete an item. It is deleted from the server at the next sync
+    fn delete_item(&mut self, item_url: &str) -> PyResult<()> {
+        let url = parse_url(item_url)?;
+        let cal = self.calendar_of_item(&url)?;
+        let result = cal.lock().unwrap().mark_for_deletion_sync(&url);
+        result.map_err(to_py_err)
+    }
+}
+
+impl PyProvider {
+    fn calendar(&self, calendar_url: &str) -> PyResult<Arc<Mutex<CachedCalendar>>> {
+        self.inner.local().get_calendar_sync(&parse_url(calendar_url)?)
+            .ok_or_else(|| PyKeyError::new_err(format!("No calendar at {}", calendar_url)))
+    }
+
+    fn calendar_of_item(&self, item_url: &Url) -> PyResult<Arc<Mutex<CachedCalendar>>> {
+        self.inner.local().get_calendars_sync().map_err(to_py_err)?
+            .into_values()
+            .find(|cal| cal.lock().unwrap().get_item_by_url_sync(item_url).is_some())
+            .ok_or_else(|| PyKeyError::new_err(format!("No item at {}", item_url)))
+    }
+
+    fn with_task<F: FnOnce(&mut kitchen_fridge::Task)>(&mut self, task_url: &str, f: F) -> PyResult<()> {
+        let url = parse_url(task_url)?;
+        let cal = self.calendar_of_item(&url)?;
+        let mut cal = cal.lock().unwrap();
+        match cal.get_item_by_url_mut_sync(&url) {
+            Some(Item::Task(task)) => {
+                f(task);
+                Ok(())
+            },
+            _ => Err(PyKeyError::new_err(format!("{} is not a task", task_url))),
+        }
+    }
+}
+
+
+#[pymodule]
+#[pyo3(name = "kitchen_fridge")]
+fn kitchen_fridge_py(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyProvider>()?;
+    m.add_class::<PyCalendar>()?;
+    m.add_class::<PyTask>()?;
+    m.add_class::<PyEvent>()?;
+    Ok(())
+}
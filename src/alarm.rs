@@ -0,0 +1,422 @@
+//! Alarms (iCal `VALARM` components), that remind users of items
+
+use chrono::{DateTime, Duration, Utc};
+use ical::property::Property;
+use serde::{Deserialize, Serialize};
+
+use crate::error::KFError;
+
+/// What an alarm does when it is triggered
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AlarmAction {
+    /// Display a message to the user
+    Display,
+    /// Play a sound
+    Audio,
+    /// Send an email
+    Email,
+    /// Any other action (e.g. an `X-` action), that apps may not support
+    Other(String),
+}
+
+impl AlarmAction {
+    fn from_ical(value: &str) -> Self {
+        match value.to_ascii_uppercase().as_str() {
+            "DISPLAY" => Self::Display,
+            "AUDIO" => Self::Audio,
+            "EMAIL" => Self::Email,
+            _ => Self::Other(value.to_string()),
+        }
+    }
+
+    /// The value of the iCal `ACTION` property
+    pub fn as_ical(&self) -> &str {
+        match self {
+            Self::Display => "DISPLAY",
+            Self::Audio => "AUDIO",
+            Self::Email => "EMAIL",
+            Self::Other(action) => action,
+        }
+    }
+}
+
+/// When an alarm is triggered
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AlarmTrigger {
+    /// Some time after (or, with a negative offset, before) the start of the item, or its end (or due date, for tasks) if `related_to_end` is set
+    Relative { offset: Duration, related_to_end: bool },
+    /// At a given time
+    Absolute(DateTime<Utc>),
+}
+
+impl AlarmTrigger {
+    /// Some time before the start of the item
+    pub fn before_start(duration: Duration) -> Self {
+        Self::Relative{ offset: -duration, related_to_end: false }
+    }
+
+    fn from_property(prop: &Property) -> Option<Self> {
+        let value = prop.value.as_deref()?;
+        let param = |name: &str| prop.params.iter().flatten()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .and_then(|(_, values)| values.first());
+        if param("VALUE").is_some_and(|value| value.eq_ignore_ascii_case("DATE-TIME")) {
+            return crate::ical::parse_date_value(value).map(Self::Absolute);
+        }
+        let offset = crate::ical::parse_duration(value).ok()?;
+        let related_to_end = param("RELATED").is_some_and(|related| related.eq_ignore_ascii_case("END"));
+        Some(Self::Relative{ offset, related_to_end })
+    }
+
+    fn to_property(&self) -> Property {
+        let (params, value) = match self {
+            Self::Relative{ offset, related_to_end: false } => (None, format_duration(offset)),
+            Self::Relative{ offset, related_to_end: true } => (Some(vec![("RELATED".to_string(), vec!["END".to_string()])]), format_duration(offset)),
+            Self::Absolute(date) => (Some(vec![("VALUE".to_string(), vec!["DATE-TIME".to_string()])]), date.format("%Y%m%dT%H%M%SZ").to_string()),
+        };
+        Property{ name: "TRIGGER".to_string(), params, value: Some(value) }
+    }
+}
+
+/// Format a duration as an iCal `DURATION` value (e.g. `-PT15M`)
+pub(crate) fn format_duration(duration: &Duration) -> String {
+    let sign = if *duration < Duration::zero() { "-" } else { "" };
+    let seconds = duration.num_seconds().abs();
+    let (days, hours, minutes, seconds) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60, seconds % 60);
+
+    let mut formatted = format!("{}P", sign);
+    if days > 0 {
+        formatted.push_str(&format!("{}D", days));
+    }
+    if hours > 0 || minutes > 0 || seconds > 0 || days == 0 {
+        formatted.push('T');
+        if hours > 0 { formatted.push_str(&format!("{}H", hours)); }
+        if minutes > 0 { formatted.push_str(&format!("{}M", minutes)); }
+        if seconds > 0 || (hours == 0 && minutes == 0) { formatted.push_str(&format!("{}S", seconds)); }
+    }
+    formatted
+}
+
+/// A file attached to an alarm (its `ATTACH` property), e.g. the sound of an `AUDIO` alarm
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    /// The URI of the file, or its base64-encoded content if it is `inline`
+    pub value: String,
+    /// Whether the content of the file is included in the alarm (`ENCODING=BASE64;VALUE=BINARY`), rather than referenced by a URI
+    pub inline: bool,
+    /// The media type of the file (its `FMTTYPE`, e.g. `audio/basic`), if it is known
+    pub format_type: Option<String>,
+}
+
+impl Attachment {
+    /// A file referenced by its URI
+    pub fn uri(uri: &str, format_type: Option<&str>) -> Self {
+        Self{ value: uri.to_string(), inline: false, format_type: format_type.map(str::to_string) }
+    }
+
+    fn from_property(prop: &Property) -> Option<Self> {
+        let param = |name: &str| prop.params.iter().flatten()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .and_then(|(_, values)| values.first());
+        Some(Self{
+            value: prop.value.clone()?,
+            inline: param("VALUE").is_some_and(|value| value.eq_ignore_ascii_case("BINARY")),
+            format_type: param("FMTTYPE").cloned(),
+        })
+    }
+
+    fn to_property(&self) -> Property {
+        let mut params = Vec::new();
+        if let Some(format_type) = &self.format_type {
+            params.push(("FMTTYPE".to_string(), vec![format_type.clone()]));
+        }
+        if self.inline {
+            params.push(("ENCODING".to_string(), vec!["BASE64".to_string()]));
+            params.push(("VALUE".to_string(), vec!["BINARY".to_string()]));
+        }
+        let params = if params.is_empty() { None } else { Some(params) };
+        Property{ name: "ATTACH".to_string(), params, value: Some(self.value.clone()) }
+    }
+}
+
+/// What an alarm does, with the properties its action uses (see [`Alarm::content`])
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AlarmContent {
+    /// Display a message to the user
+    Display{ description: Option<String> },
+    /// Play a sound, or the default sound of the app if there is no `sound`
+    Audio{ sound: Option<Attachment> },
+    /// Send an email to `attendees` (as `mailto:` URIs)
+    Email{ attendees: Vec<String>, summary: Option<String>, description: Option<String>, attachments: Vec<Attachment> },
+    /// Any other action, whose properties can be read with [`Alarm::properties`]
+    Other(String),
+}
+
+/// An alarm of an item.
+///
+/// All of its properties are kept as they have been parsed, so that alarms are not altered when items are synced
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Alarm {
+    properties: Vec<Property>,
+}
+
+impl PartialEq for Alarm {
+    fn eq(&self, other: &Self) -> bool {
+        self.properties.len() == other.properties.len()
+            && self.properties.iter().zip(&other.properties).all(|(a, b)| a.name.eq_ignore_ascii_case(&b.name) && a.params == b.params && a.value == b.value)
+    }
+}
+
+impl Alarm {
+    pub fn new(action: AlarmAction, trigger: AlarmTrigger) -> Self {
+        Self{ properties: vec![
+            Property{ name: "ACTION".to_string(), params: None, value: Some(action.as_ical().to_string()) },
+            trigger.to_property(),
+        ]}
+    }
+
+    /// An alarm that displays a message
+    pub fn display(trigger: AlarmTrigger, description: &str) -> Self {
+        let mut alarm = Self::new(AlarmAction::Display, trigger);
+        alarm.properties.push(Property{ name: "DESCRIPTION".to_string(), params: None, value: Some(description.to_string()) });
+        alarm
+    }
+
+    /// An alarm that plays a sound (e.g. `Attachment::uri("file:///usr/share/sounds/bell.oga", Some("audio/ogg"))`), or the default sound of the app
+    pub fn audio(trigger: AlarmTrigger, sound: Option<Attachment>) -> Self {
+        let mut alarm = Self::new(AlarmAction::Audio, trigger);
+        alarm.properties.extend(sound.map(|sound| sound.to_property()));
+        alarm
+    }
+
+    /// An alarm that sends an email to `attendees` (email addresses, or `mailto:` URIs)
+    pub fn email(trigger: AlarmTrigger, summary: &str, description: &str, attendees: &[&str]) -> Self {
+        let mut alarm = Self::new(AlarmAction::Email, trigger);
+        alarm.properties.push(Property{ name: "SUMMARY".to_string(), params: None, value: Some(summary.to_string()) });
+        alarm.properties.push(Property{ name: "DESCRIPTION".to_string(), params: None, value: Some(description.to_string()) });
+        for attendee in attendees {
+            let uri = match attendee.get(..7).is_some_and(|scheme| scheme.eq_ignore_ascii_case("mailto:")) {
+                true => attendee.to_string(),
+                false => format!("mailto:{}", attendee),
+            };
+            alarm.properties.push(Property{ name: "ATTENDEE".to_string(), params: None, value: Some(uri) });
+        }
+        alarm
+    }
+
+    /// Attach a file to this alarm (the sound of an `AUDIO` alarm, or an attachment of the mail of an `EMAIL` alarm)
+    pub fn add_attachment(&mut self, attachment: Attachment) {
+        self.properties.push(attachment.to_property());
+    }
+
+    pub(crate) fn from_properties(properties: Vec<Property>) -> Self {
+        Self{ properties }
+    }
+
+    /// Parse a `VALARM` component (e.g. a default alarm of a calendar, see [`DefaultAlarms`](crate::calendar::DefaultAlarms))
+    pub fn from_ical(ical: &str) -> Result<Self, KFError> {
+        crate::ical::parse_alarm(ical)
+    }
+
+    /// This alarm, as a `VALARM` component
+    pub fn to_ical(&self) -> String {
+        let mut ical = String::from("BEGIN:VALARM\r\n");
+        for prop in &self.properties {
+            ical.push_str(&prop.name);
+            for (param, values) in prop.params.iter().flatten() {
+                ical.push_str(&format!(";{}={}", param, crate::ical::format_param_values(values)));
+            }
+            ical.push(':');
+            ical.push_str(prop.value.as_deref().unwrap_or_default());
+            ical.push_str("\r\n");
+        }
+        ical.push_str("END:VALARM\r\n");
+        ical
+    }
+
+    /// Every property of this alarm
+    pub fn properties(&self) -> &[Property] {
+        &self.properties
+    }
+
+    fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|prop| prop.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn action(&self) -> AlarmAction {
+        AlarmAction::from_ical(self.property("ACTION").and_then(|prop| prop.value.as_deref()).unwrap_or_default())
+    }
+
+    /// When this alarm is triggered, or `None` if its trigger is invalid
+    pub fn trigger(&self) -> Option<AlarmTrigger> {
+        self.property("TRIGGER").and_then(AlarmTrigger::from_property)
+    }
+
+    /// The message of this alarm
+    pub fn description(&self) -> Option<&str> {
+        self.property("DESCRIPTION").and_then(|prop| prop.value.as_deref())
+    }
+
+    /// The subject of the mail of an `EMAIL` alarm
+    pub fn summary(&self) -> Option<&str> {
+        self.property("SUMMARY").and_then(|prop| prop.value.as_deref())
+    }
+
+    /// Who the mail of an `EMAIL` alarm is sent to (as `mailto:` URIs)
+    pub fn attendees(&self) -> Vec<&str> {
+        self.properties.iter()
+            .filter(|prop| prop.name.eq_ignore_ascii_case("ATTENDEE"))
+            .filter_map(|prop| prop.value.as_deref())
+            .collect()
+    }
+
+    /// The files attached to this alarm
+    pub fn attachments(&self) -> Vec<Attachment> {
+        self.properties.iter()
+            .filter(|prop| prop.name.eq_ignore_ascii_case("ATTACH"))
+            .filter_map(Attachment::from_property)
+            .collect()
+    }
+
+    /// What this alarm does when it is triggered, so that apps can handle each action in its own way
+    pub fn content(&self) -> AlarmContent {
+        match self.action() {
+            AlarmAction::Display => AlarmContent::Display{ description: self.description().map(str::to_string) },
+            AlarmAction::Audio => AlarmContent::Audio{ sound: self.attachments().into_iter().next() },
+            AlarmAction::Email => AlarmContent::Email{
+                attendees: self.attendees().into_iter().map(str::to_string).collect(),
+                summary: self.summary().map(str::to_string),
+                description: self.description().map(str::to_string),
+                attachments: self.attachments(),
+            },
+            AlarmAction::Other(action) => AlarmContent::Other(action),
+        }
+    }
+
+    /// The UID of this alarm, if it has one. Alarms are given one when they are snoozed, so that their snooze alarm can refer to them
+    pub fn uid(&self) -> Option<&str> {
+        self.property("UID").and_then(|prop| prop.value.as_deref())
+    }
+
+    /// When this alarm has last been dismissed (or snoozed) by the user (its `ACKNOWLEDGED`, see [RFC 9074](https://www.rfc-editor.org/rfc/rfc9074)). Its triggers up to this date should not remind the user again
+    pub fn acknowledged(&self) -> Option<DateTime<Utc>> {
+        self.property("ACKNOWLEDGED").and_then(|prop| crate::ical::parse_date_value(prop.value.as_deref()?))
+    }
+
+    /// If this alarm has been added to snooze another alarm, the UID of this other alarm (its `RELATED-TO;RELTYPE=SNOOZE`)
+    pub fn snoozed_alarm(&self) -> Option<&str> {
+        self.properties.iter()
+            .filter(|prop| prop.name.eq_ignore_ascii_case("RELATED-TO"))
+            .find(|prop| prop.params.iter().flatten().any(|(param, values)| param.eq_ignore_ascii_case("RELTYPE") && values.iter().any(|value| value.eq_ignore_ascii_case("SNOOZE"))))
+            .and_then(|prop| prop.value.as_deref())
+    }
+
+    /// Replace the value of a property, or add it if this alarm does not have it
+    fn set_property(&mut self, prop: Property) {
+        match self.properties.iter_mut().find(|existing| existing.name.eq_ignore_ascii_case(&prop.name)) {
+            Some(existing) => *existing = prop,
+            None => self.properties.push(prop),
+        }
+    }
+
+    pub(crate) fn acknowledge(&mut self, at: DateTime<Utc>) {
+        self.set_property(Property{ name: "ACKNOWLEDGED".to_string(), params: None, value: Some(at.format("%Y%m%dT%H%M%SZ").to_string()) });
+    }
+
+    /// The UID of this alarm, that is generated if it has none yet
+    pub(crate) fn ensure_uid(&mut self) -> String {
+        if let Some(uid) = self.uid() {
+            return uid.to_string();
+        }
+        let uid = uuid::Uuid::new_v4().to_hyphenated().to_string();
+        self.properties.push(Property{ name: "UID".to_string(), params: None, value: Some(uid.clone()) });
+        uid
+    }
+
+    /// An alarm that reminds of this alarm again at `until`, and that refers to it if its UID is given
+    pub(crate) fn snoozed_until(&self, uid: Option<&str>, until: DateTime<Utc>) -> Self {
+        const NOT_COPIED: [&str; 7] = ["ACTION", "TRIGGER", "UID", "ACKNOWLEDGED", "RELATED-TO", "REPEAT", "DURATION"];
+        let mut snooze = Self::new(self.action(), AlarmTrigger::Absolute(until));
+        snooze.properties.extend(self.properties.iter()
+            .filter(|prop| !NOT_COPIED.iter().any(|name| prop.name.eq_ignore_ascii_case(name)))
+            .cloned());
+        if let Some(uid) = uid {
+            snooze.ensure_uid();
+            let params = Some(vec![("RELTYPE".to_string(), vec!["SNOOZE".to_string()])]);
+            snooze.properties.push(Property{ name: "RELATED-TO".to_string(), params, value: Some(uid.to_string()) });
+        }
+        snooze
+    }
+
+    /// How many more times this alarm is triggered after its trigger (its `REPEAT`), and the delay between two of them (its `DURATION`), if it is repeated
+    pub fn repetitions(&self) -> Option<(u32, Duration)> {
+        let repeat = self.property("REPEAT").and_then(|prop| prop.value.as_deref()?.parse().ok()).filter(|repeat| *repeat > 0)?;
+        let delay = self.property("DURATION").and_then(|prop| crate::ical::parse_duration(prop.value.as_deref()?).ok())?;
+        Some((repeat, delay))
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alarm_round_trip() {
+        let alarm = Alarm::display(AlarmTrigger::before_start(Duration::minutes(15)), "Reminder");
+        assert_eq!(alarm.to_ical(), "BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:-PT15M\r\nDESCRIPTION:Reminder\r\nEND:VALARM\r\n");
+        assert_eq!(Alarm::from_ical(&alarm.to_ical()).unwrap(), alarm);
+        assert_eq!(alarm.action(), AlarmAction::Display);
+        assert_eq!(alarm.description(), Some("Reminder"));
+
+        // e.g. the default alarm of all-day events of an Apple calendar
+        let alarm = Alarm::from_ical("BEGIN:VALARM\nX-WR-ALARMUID:1\nACTION:AUDIO\nTRIGGER;RELATED=END:-P1DT9H\nEND:VALARM\n").unwrap();
+        assert_eq!(alarm.action(), AlarmAction::Audio);
+        assert_eq!(alarm.trigger(), Some(AlarmTrigger::Relative{ offset: -(Duration::days(1) + Duration::hours(9)), related_to_end: true }));
+        assert!(alarm.to_ical().contains("X-WR-ALARMUID:1\r\n"));
+
+        let date = "2021-11-10T07:00:00Z".parse().unwrap();
+        let alarm = Alarm::new(AlarmAction::Display, AlarmTrigger::Absolute(date));
+        assert!(alarm.to_ical().contains("TRIGGER;VALUE=DATE-TIME:20211110T070000Z\r\n"));
+        assert_eq!(alarm.trigger(), Some(AlarmTrigger::Absolute(date)));
+
+        assert!(Alarm::from_ical("BEGIN:VTODO\nEND:VTODO\n").is_err());
+    }
+
+    #[test]
+    fn test_email_and_audio_alarms() {
+        let trigger = AlarmTrigger::before_start(Duration::hours(1));
+        let alarm = Alarm::email(trigger.clone(), "Meeting soon", "The meeting starts in one hour", &["john@example.com", "mailto:jane@example.com"]);
+        assert_eq!(alarm.to_ical(), "BEGIN:VALARM\r\nACTION:EMAIL\r\nTRIGGER:-PT1H\r\nSUMMARY:Meeting soon\r\nDESCRIPTION:The meeting starts in one hour\r\n\
+                                     ATTENDEE:mailto:john@example.com\r\nATTENDEE:mailto:jane@example.com\r\nEND:VALARM\r\n");
+        assert_eq!(alarm.content(), AlarmContent::Email{
+            attendees: vec!["mailto:john@example.com".to_string(), "mailto:jane@example.com".to_string()],
+            summary: Some("Meeting soon".to_string()),
+            description: Some("The meeting starts in one hour".to_string()),
+            attachments: Vec::new(),
+        });
+
+        let alarm = Alarm::audio(trigger.clone(), Some(Attachment::uri("ftp://example.com/pub/sounds/bell-01.aud", Some("audio/basic"))));
+        assert_eq!(alarm.content(), AlarmContent::Audio{ sound: Some(Attachment::uri("ftp://example.com/pub/sounds/bell-01.aud", Some("audio/basic"))) });
+        assert!(alarm.to_ical().contains("ATTACH;FMTTYPE=audio/basic:ftp://example.com/pub/sounds/bell-01.aud\r\n"));
+        assert_eq!(Alarm::audio(trigger, None).content(), AlarmContent::Audio{ sound: None });
+
+        // Parameters that contain separators survive a round trip
+        let ical = "BEGIN:VALARM\r\nACTION:EMAIL\r\nTRIGGER;RELATED=END:PT0S\r\nSUMMARY:Due\r\nDESCRIPTION:The task is due\r\n\
+                    ATTENDEE;CN=\"Doe, John\";ROLE=REQ-PARTICIPANT:mailto:john@example.com\r\n\
+                    ATTACH;FMTTYPE=text/plain;ENCODING=BASE64;VALUE=BINARY:SGVsbG8=\r\nEND:VALARM\r\n";
+        let alarm = Alarm::from_ical(ical).unwrap();
+        assert_eq!(alarm.to_ical(), ical);
+        assert_eq!(alarm.attendees(), vec!["mailto:john@example.com"]);
+        assert_eq!(alarm.attachments(), vec![Attachment{ value: "SGVsbG8=".to_string(), inline: true, format_type: Some("text/plain".to_string()) }]);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(&Duration::zero()), "PT0S");
+        assert_eq!(format_duration(&Duration::days(2)), "P2D");
+        assert_eq!(format_duration(&-Duration::seconds(90)), "-PT1M30S");
+        assert_eq!(format_duration(&(Duration::days(1) + Duration::hours(2))), "P1DT2H");
+    }
+}
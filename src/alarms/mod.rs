@@ -0,0 +1,243 @@
+//! Reminders: when the alarms of items are triggered (see [`alarms_between`]), and a [`Scheduler`] that fires them at the right moment
+//!
+//! The alarms themselves (the `VALARM` components of items) are described in the [`alarm`](crate::alarm) module.
+
+mod scheduler;
+pub use scheduler::{AlarmSettings, Scheduler, SchedulerHandle};
+#[cfg(feature = "desktop_notifications")]
+pub mod notifications;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::alarm::{Alarm, AlarmTrigger};
+use crate::item::SyncStatus;
+use crate::Item;
+
+/// Recurrences are not expanded further than this, so that e.g. a `FREQ=SECONDLY` rule does not keep a scan busy forever
+const MAX_INSTANCES: usize = 100_000;
+
+/// Identifies a trigger of an alarm: the same alarm of a recurring item (or a repeated alarm) is triggered several times
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AlarmKey {
+    /// The URL of the item the alarm belongs to
+    pub item: Url,
+    /// The index of the alarm in [`Task::alarms`](crate::Task::alarms)
+    pub alarm: usize,
+    /// When the alarm is triggered
+    pub trigger: DateTime<Utc>,
+}
+
+/// An alarm, at the time it is triggered
+#[derive(Clone, Debug, PartialEq)]
+pub struct DueAlarm {
+    pub key: AlarmKey,
+    /// The URL of the calendar of the item
+    pub calendar: Url,
+    /// The name of the item
+    pub summary: String,
+    pub alarm: Alarm,
+    /// The instance of the item this alarm is about (its start, or its due date if it has no start). This is `None` for items that have no date
+    pub instance: Option<DateTime<Utc>>,
+    /// Whether this alarm has been fired before the [`Scheduler`] has been started, and has not been dismissed since (see [`SchedulerHandle::dismiss`])
+    pub already_fired: bool,
+}
+
+impl DueAlarm {
+    /// When the alarm is triggered
+    pub fn trigger(&self) -> &DateTime<Utc> {
+        &self.key.trigger
+    }
+}
+
+/// Where a [`Scheduler`] sends the alarms it fires. This is implemented for closures
+///
+/// Alarms are fired whatever their action: sinks should handle each of them according to [`Alarm::content`] (e.g. send the mail of `EMAIL` alarms, and play the sound of `AUDIO` alarms)
+pub trait AlarmSink: Send + Sync {
+    fn alarm_fired(&self, alarm: &DueAlarm);
+}
+
+impl<F: Fn(&DueAlarm) + Send + Sync> AlarmSink for F {
+    fn alarm_fired(&self, alarm: &DueAlarm) {
+        self(alarm)
+    }
+}
+
+/// The alarms of an item that are triggered between `from` and `to` (both included), in chronological order.
+///
+/// Alarms of recurring items are triggered once per instance (see the [`recurrence`](crate::recurrence) module), and repeated alarms once per repetition.
+/// Triggers that have been acknowledged (see [`Alarm::acknowledged`] and [`Task::alarms_last_acknowledged`](crate::Task::alarms_last_acknowledged)) are left out, and snooze alarms are triggered when their snooze ends.
+/// Completed tasks and items that are marked for deletion have no alarms
+pub fn alarms_between(item: &Item, calendar: &Url, from: &DateTime<Utc>, to: &DateTime<Utc>) -> Vec<DueAlarm> {
+    let task = match item {
+        Item::Task(task) if !task.completed() && !matches!(task.sync_status(), SyncStatus::LocallyDeleted(_)) => task,
+        _ => return Vec::new(),
+    };
+    if task.alarms().is_empty() {
+        return Vec::new();
+    }
+    let date = |name: &str| task.extra_parameters().iter()
+        .find(|prop| prop.name.eq_ignore_ascii_case(name))
+        .and_then(|prop| crate::ical::parse_date_value(prop.value.as_deref()?));
+    let (start, due) = (date("DTSTART"), date("DUE"));
+    let anchor = start.or(due);
+    let last_acknowledged = task.alarms_last_acknowledged();
+
+    let mut alarms = Vec::new();
+    let mut push = |index: usize, alarm: &Alarm, trigger: DateTime<Utc>, instance: Option<DateTime<Utc>>| {
+        let (repeat, delay) = alarm.repetitions().unwrap_or((0, Duration::zero()));
+        // Snooze alarms are added when the task is acknowledged, precisely to be triggered afterwards
+        let acknowledged = match alarm.snoozed_alarm() {
+            Some(_) => alarm.acknowledged(),
+            None => alarm.acknowledged().max(last_acknowledged),
+        };
+        for repetition in 0..=repeat {
+            let trigger = trigger + delay * repetition as i32;
+            if &trigger >= from && &trigger <= to && acknowledged.is_none_or(|acknowledged| trigger > acknowledged) {
+                let key = AlarmKey{ item: task.url().clone(), alarm: index, trigger };
+                alarms.push(DueAlarm{ key, calendar: calendar.clone(), summary: task.name().to_string(), alarm: alarm.clone(), instance, already_fired: false });
+            }
+        }
+    };
+
+    // How long after the start of an instance its relative alarms are triggered
+    let mut shifts = Vec::new();
+    for (index, alarm) in task.alarms().iter().enumerate() {
+        match alarm.trigger() {
+            // Absolute alarms are only triggered once, whatever the recurrence of the item
+            Some(AlarmTrigger::Absolute(trigger)) => push(index, alarm, trigger, anchor),
+            Some(AlarmTrigger::Relative{ offset, related_to_end }) => {
+                let reference = match related_to_end {
+                    false => start.or(due),
+                    true => due.or(start),
+                };
+                if let (Some(reference), Some(anchor)) = (reference, anchor) {
+                    shifts.push((index, alarm, reference - anchor + offset));
+                }
+            },
+            None => log::warn!("An alarm of {} has an invalid trigger, it is ignored", task.url()),
+        }
+    }
+
+    // Thunderbird snoozes items rather than alarms, without adding snooze alarms
+    if let Some(until) = date("X-MOZ-SNOOZE-TIME") {
+        if task.alarms().iter().all(|alarm| alarm.snoozed_alarm().is_none()) {
+            push(0, &task.alarms()[0].snoozed_until(None, until), until, anchor);
+        }
+    }
+
+    let earliest_shift = shifts.iter().map(|(_, _, shift)| *shift).min();
+    let latest_shift = shifts.iter().map(|(_, alarm, shift)| {
+        *shift + alarm.repetitions().map(|(repeat, delay)| delay * repeat as i32).unwrap_or_else(Duration::zero)
+    }).max();
+    if let (Some(anchor), Some(earliest_shift), Some(latest_shift)) = (anchor, earliest_shift, latest_shift) {
+        for instance in crate::recurrence::instances(anchor, task.extra_parameters()).take(MAX_INSTANCES) {
+            if instance + earliest_shift > *to {
+                break;
+            }
+            if instance + latest_shift < *from {
+                // Every alarm of this instance has been triggered already
+                continue;
+            }
+            for (index, alarm, shift) in &shifts {
+                push(*index, alarm, instance + *shift, Some(instance));
+            }
+        }
+    }
+
+    alarms.sort_by(|a, b| a.key.trigger.cmp(&b.key.trigger).then_with(|| a.key.alarm.cmp(&b.key.alarm)));
+    alarms
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Timelike;
+    use crate::alarm::AlarmAction;
+    use crate::task::CompletionStatus;
+
+    fn date(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_alarms_between() {
+        let cal: Url = "https://some.server/cal/".parse().unwrap();
+        let ical = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Test//EN\r\nBEGIN:VTODO\r\nUID:weekly\r\nDTSTAMP:20210201T000000Z\r\nSUMMARY:Water the plants\r\n\
+                    DTSTART:20210301T090000Z\r\nDUE:20210301T100000Z\r\nRRULE:FREQ=WEEKLY;COUNT=3\r\n\
+                    BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:-PT15M\r\nEND:VALARM\r\n\
+                    BEGIN:VALARM\r\nACTION:AUDIO\r\nTRIGGER;RELATED=END:PT0S\r\nREPEAT:1\r\nDURATION:PT5M\r\nEND:VALARM\r\n\
+                    BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER;VALUE=DATE-TIME:20210305T120000Z\r\nEND:VALARM\r\n\
+                    END:VTODO\r\nEND:VCALENDAR\r\n";
+        let mut item = crate::ical::parse(ical, cal.join("weekly.ics").unwrap(), SyncStatus::NotSynced).unwrap();
+
+        let alarms = alarms_between(&item, &cal, &date("2021-03-01T00:00:00Z"), &date("2021-03-31T00:00:00Z"));
+        let triggers: Vec<_> = alarms.iter().map(|alarm| (alarm.key.alarm, *alarm.trigger())).collect();
+        assert_eq!(triggers, vec![
+            (0, date("2021-03-01T08:45:00Z")), (1, date("2021-03-01T10:00:00Z")), (1, date("2021-03-01T10:05:00Z")),
+            (2, date("2021-03-05T12:00:00Z")),
+            (0, date("2021-03-08T08:45:00Z")), (1, date("2021-03-08T10:00:00Z")), (1, date("2021-03-08T10:05:00Z")),
+            (0, date("2021-03-15T08:45:00Z")), (1, date("2021-03-15T10:00:00Z")), (1, date("2021-03-15T10:05:00Z")),
+        ]);
+        assert_eq!(alarms[1].alarm.action(), AlarmAction::Audio);
+        assert_eq!(alarms[4].instance, Some(date("2021-03-08T09:00:00Z")));
+        assert_eq!(alarms[4].summary, "Water the plants");
+
+        // Only the alarms of the second instance
+        let alarms = alarms_between(&item, &cal, &date("2021-03-06T00:00:00Z"), &date("2021-03-08T10:00:00Z"));
+        assert_eq!(alarms.len(), 2);
+        assert!(alarms.iter().all(|alarm| alarm.instance == Some(date("2021-03-08T09:00:00Z"))));
+
+        item.unwrap_task_mut().set_completion_status(CompletionStatus::Completed(None));
+        assert!(alarms_between(&item, &cal, &date("2021-03-01T00:00:00Z"), &date("2021-03-31T00:00:00Z")).is_empty());
+    }
+
+    #[test]
+    fn test_dismiss_and_snooze() {
+        let cal: Url = "https://some.server/cal/".parse().unwrap();
+        let now = Utc::now();
+        let mut task = crate::Task::new("Take out the trash".to_string(), false, &cal);
+        task.add_alarm(Alarm::display(AlarmTrigger::Absolute(now - Duration::minutes(5)), "Trash"));
+        let (from, to) = (now - Duration::hours(1), now + Duration::hours(1));
+        assert_eq!(alarms_between(&Item::Task(task.clone()), &cal, &from, &to).len(), 1);
+
+        let until = task.snooze(0, Duration::minutes(10)).unwrap();
+        assert_eq!(task.alarms().len(), 2);
+        assert!(task.alarms()[0].acknowledged().is_some());
+        assert_eq!(task.alarms()[1].snoozed_alarm(), task.alarms()[0].uid());
+        assert!(task.alarms_last_acknowledged().is_some());
+        let item = Item::Task(task.clone());
+        let alarms = alarms_between(&item, &cal, &from, &to);
+        assert_eq!(alarms.len(), 1);
+        assert_eq!((alarms[0].key.alarm, *alarms[0].trigger()), (1, until.with_nanosecond(0).unwrap()));
+        assert_eq!(alarms[0].alarm.description(), Some("Trash"));
+        // The snooze is written in a way Thunderbird understands
+        let ical = crate::ical::build_from(&item).unwrap();
+        assert!(ical.contains("X-MOZ-SNOOZE-TIME:") && ical.contains("RELATED-TO;RELTYPE=SNOOZE:"));
+
+        // Snoozing again replaces the snooze alarm
+        task.snooze(1, Duration::minutes(10)).unwrap();
+        assert_eq!(task.alarms().len(), 2);
+
+        task.dismiss(1).unwrap();
+        assert_eq!(task.alarms().len(), 1);
+        assert!(alarms_between(&Item::Task(task.clone()), &cal, &from, &to).is_empty());
+        assert!(task.extra_parameters().iter().all(|prop| prop.name != "X-MOZ-SNOOZE-TIME"));
+        assert!(task.dismiss(1).is_err());
+
+        // Items snoozed by Thunderbird
+        let ical = format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Test//EN\r\nBEGIN:VTODO\r\nUID:moz\r\nDTSTAMP:20210201T000000Z\r\nSUMMARY:Pay the rent\r\n\
+                            X-MOZ-LASTACK:{}\r\nX-MOZ-SNOOZE-TIME:{}\r\n\
+                            BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER;VALUE=DATE-TIME:{}\r\nEND:VALARM\r\n\
+                            END:VTODO\r\nEND:VCALENDAR\r\n",
+                            (now - Duration::minutes(1)).format("%Y%m%dT%H%M%SZ"), (now + Duration::minutes(4)).format("%Y%m%dT%H%M%SZ"), (now - Duration::minutes(5)).format("%Y%m%dT%H%M%SZ"));
+        let item = crate::ical::parse(&ical, cal.join("moz.ics").unwrap(), SyncStatus::NotSynced).unwrap();
+        let alarms = alarms_between(&item, &cal, &from, &to);
+        assert_eq!(alarms.len(), 1);
+        assert_eq!(*alarms[0].trigger(), (now + Duration::minutes(4)).with_nanosecond(0).unwrap());
+    }
+}
@@ -0,0 +1,261 @@
+//! Desktop notifications for the alarms fired by a [`Scheduler`](super::Scheduler), with buttons to dismiss or snooze them
+//!
+//! A [`DesktopNotifications`] sink is enough for a simple tray app to remind its user of their tasks:
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use std::time::Duration;
+//! # use kitchen_fridge::alarms::{AlarmSettings, Scheduler};
+//! # use kitchen_fridge::alarms::notifications::{DesktopNotifications, NotifyRust};
+//! # async fn run(provider: Arc<tokio::sync::Mutex<kitchen_fridge::CalDavProvider>>) {
+//! let notifications = Arc::new(DesktopNotifications::new(Arc::new(NotifyRust::new("My tasks")), Duration::from_secs(10 * 60)));
+//! let (scheduler, handle) = Scheduler::new(provider, AlarmSettings::default(), notifications.clone());
+//! notifications.set_handle(handle);
+//! scheduler.run().await;
+//! # }
+//! ```
+
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+
+use crate::alarm::{AlarmAction, AlarmContent};
+use super::{AlarmSink, DueAlarm, SchedulerHandle};
+
+/// A notification about an alarm
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    /// The name of the item
+    pub summary: String,
+    /// The message of the alarm, or when the item starts
+    pub body: String,
+    /// The sound to play (for `AUDIO` alarms)
+    pub sound: Option<NotificationSound>,
+}
+
+/// The sound of a notification
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotificationSound {
+    /// The sound the desktop plays for alarms
+    Default,
+    /// A local sound file, that an `AUDIO` alarm is attached to
+    File(PathBuf),
+}
+
+/// What the user has done with a notification
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationResponse {
+    /// The user has clicked the "dismiss" button
+    Dismissed,
+    /// The user has clicked the "snooze" button
+    Snoozed,
+    /// The notification has been closed (or has expired) without any button being clicked
+    Closed,
+}
+
+/// Shows notifications on the desktop.
+///
+/// [`NotifyRust`] works on any desktop that implements the freedesktop notification specification (e.g. GNOME, KDE, or most Linux window managers with a notification daemon).
+/// Apps that want to show notifications another way (e.g. on macOS or Windows, whose notifications have no buttons in `notify-rust`) can implement this trait instead
+pub trait Notifier: Send + Sync {
+    /// Show a notification with a "dismiss" and a "snooze" button, and wait until the user responds to it.
+    ///
+    /// This is called in a thread where blocking is fine
+    fn show(&self, notification: &Notification) -> Result<NotificationResponse, Box<dyn Error + Send + Sync>>;
+}
+
+/// A [`Notifier`] that sends notifications to the notification server of the desktop over D-Bus, with the [`notify-rust`](https://docs.rs/notify-rust) crate
+///
+/// This is only available on Linux and the BSDs, where notifications can have buttons
+#[cfg(all(unix, not(target_os = "macos")))]
+#[derive(Clone, Debug)]
+pub struct NotifyRust {
+    app_name: String,
+    dismiss_label: String,
+    snooze_label: String,
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+impl NotifyRust {
+    /// Show notifications on behalf of an app, with buttons labelled "Dismiss" and "Snooze"
+    pub fn new(app_name: &str) -> Self {
+        Self {
+            app_name: app_name.to_string(),
+            dismiss_label: "Dismiss".to_string(),
+            snooze_label: "Snooze".to_string(),
+        }
+    }
+
+    /// Change the labels of the buttons, e.g. to translate them
+    pub fn set_labels(&mut self, dismiss_label: &str, snooze_label: &str) {
+        self.dismiss_label = dismiss_label.to_string();
+        self.snooze_label = snooze_label.to_string();
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+impl Notifier for NotifyRust {
+    fn show(&self, notification: &Notification) -> Result<NotificationResponse, Box<dyn Error + Send + Sync>> {
+        let mut desktop_notification = notify_rust::Notification::new();
+        desktop_notification
+            .appname(&self.app_name)
+            .icon("appointment-soon")
+            .summary(&notification.summary)
+            .body(&notification.body)
+            .action("dismiss", &self.dismiss_label)
+            .action("snooze", &self.snooze_label);
+        match &notification.sound {
+            None => (),
+            Some(NotificationSound::Default) => { desktop_notification.sound_name("alarm-clock-elapsed"); },
+            Some(NotificationSound::File(path)) => { desktop_notification.hint(notify_rust::Hint::SoundFile(path.display().to_string())); },
+        }
+
+        let mut response = NotificationResponse::Closed;
+        // Closing the notification without clicking a button is reported as the `__closed` action
+        desktop_notification.show()?.wait_for_action(|action| {
+            response = match action {
+                "dismiss" => NotificationResponse::Dismissed,
+                "snooze" => NotificationResponse::Snoozed,
+                _ => NotificationResponse::Closed,
+            };
+        });
+        Ok(response)
+    }
+}
+
+/// An [`AlarmSink`] that shows a desktop notification for every alarm that is fired, and dismisses or snoozes the alarm when the user clicks on the buttons of the notification.
+///
+/// `EMAIL` alarms are not meant to be displayed, they are ignored.
+/// `AUDIO` alarms play the sound they are attached to if it is a local file (and the default sound of the desktop otherwise).
+/// Since this sink needs the handle of the [`Scheduler`](super::Scheduler) it is given to, the handle must be set with [`Self::set_handle`] once the scheduler is created.
+/// The scheduler then only stops when [`SchedulerHandle::stop`] is called
+pub struct DesktopNotifications {
+    notifier: Arc<dyn Notifier>,
+    snooze_duration: Duration,
+    handle: OnceCell<SchedulerHandle>,
+}
+
+impl DesktopNotifications {
+    /// Show notifications with `notifier`. Alarms are fired again `snooze_duration` after the user has clicked on "snooze"
+    pub fn new(notifier: Arc<dyn Notifier>, snooze_duration: Duration) -> Self {
+        Self{ notifier, snooze_duration, handle: OnceCell::new() }
+    }
+
+    /// Set the handle of the scheduler, so that alarms can be dismissed or snoozed from their notifications. This can only be set once
+    pub fn set_handle(&self, handle: SchedulerHandle) {
+        if self.handle.set(handle).is_err() {
+            log::warn!("The scheduler of these notifications has been set already");
+        }
+    }
+
+    fn notification(alarm: &DueAlarm) -> Notification {
+        let body = match (alarm.alarm.description(), alarm.instance) {
+            (Some(description), _) if description != alarm.summary => description.to_string(),
+            (_, Some(instance)) => instance.format("%Y-%m-%d %H:%M UTC").to_string(),
+            _ => String::new(),
+        };
+        let sound = match alarm.alarm.content() {
+            AlarmContent::Audio{ sound: Some(sound) } if !sound.inline => {
+                let path = url::Url::parse(&sound.value).ok().filter(|url| url.scheme() == "file").and_then(|url| url.to_file_path().ok());
+                Some(path.map(NotificationSound::File).unwrap_or(NotificationSound::Default))
+            },
+            AlarmContent::Audio{ .. } => Some(NotificationSound::Default),
+            _ => None,
+        };
+        Notification{ summary: alarm.summary.clone(), body, sound }
+    }
+}
+
+impl AlarmSink for DesktopNotifications {
+    fn alarm_fired(&self, alarm: &DueAlarm) {
+        if alarm.alarm.action() == AlarmAction::Email {
+            return;
+        }
+        let notifier = self.notifier.clone();
+        let handle = self.handle.get().cloned();
+        let snooze_duration = self.snooze_duration;
+        let notification = Self::notification(alarm);
+        let key = alarm.key.clone();
+        tokio::task::spawn_blocking(move || {
+            match (notifier.show(&notification), handle) {
+                (Err(err), _) => log::error!("Unable to show a notification for {}: {}", key.item, err),
+                (Ok(NotificationResponse::Dismissed), Some(handle)) => handle.dismiss(key),
+                (Ok(NotificationResponse::Snoozed), Some(handle)) => handle.snooze(key, snooze_duration),
+                (Ok(NotificationResponse::Closed), _) => (),
+                (Ok(_), None) => log::warn!("Notifications cannot act on alarms until DesktopNotifications::set_handle is called"),
+            }
+        });
+    }
+}
+
+
+#[cfg(all(test, feature = "local_calendar_mocks_remote_calendars"))]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+    use std::sync::Mutex;
+    use chrono::Utc;
+    use crate::alarm::{Alarm, AlarmTrigger, Attachment};
+    use crate::alarms::{AlarmSettings, Scheduler};
+    use crate::calendar::SupportedComponents;
+    use crate::cache::Cache;
+    use crate::provider::Provider;
+    use crate::traits::CalDavSource;
+    use crate::{Item, Task};
+
+    /// Snoozes the first notification, and dismisses the next one
+    struct FakeNotifier {
+        shown: Mutex<Vec<Notification>>,
+        sender: tokio::sync::mpsc::UnboundedSender<()>,
+    }
+
+    impl Notifier for FakeNotifier {
+        fn show(&self, notification: &Notification) -> Result<NotificationResponse, Box<dyn Error + Send + Sync>> {
+            let mut shown = self.shown.lock().unwrap();
+            shown.push(notification.clone());
+            self.sender.send(()).unwrap();
+            Ok(match shown.len() {
+                1 => NotificationResponse::Snoozed,
+                _ => NotificationResponse::Dismissed,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_desktop_notifications() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut local = Cache::new_in_memory();
+        let cal_url: url::Url = "https://some.server/cal/".parse().unwrap();
+        let calendar = local.create_calendar(cal_url.clone(), "Reminders".to_string(), SupportedComponents::TODO, None).await.unwrap();
+        let mut task = Task::new("Feed the cat".to_string(), false, &cal_url);
+        let sound = Attachment::uri("file:///usr/share/sounds/bell.oga", Some("audio/ogg"));
+        task.add_alarm(Alarm::audio(AlarmTrigger::Absolute(Utc::now() - chrono::Duration::minutes(1)), Some(sound)));
+        task.add_alarm(Alarm::new(AlarmAction::Email, AlarmTrigger::Absolute(Utc::now() - chrono::Duration::minutes(1))));
+        calendar.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        let provider = Arc::new(tokio::sync::Mutex::new(Provider::new(Cache::new(Path::new("test_cache/notifications_remote")), local)));
+
+        let (sender, mut shown) = tokio::sync::mpsc::unbounded_channel();
+        let notifier = Arc::new(FakeNotifier{ shown: Mutex::new(Vec::new()), sender });
+        let notifications = Arc::new(DesktopNotifications::new(notifier.clone(), Duration::from_millis(100)));
+        let settings = AlarmSettings::default();
+        let (scheduler, handle) = Scheduler::new(provider.clone(), settings.clone(), notifications.clone());
+        notifications.set_handle(handle.clone());
+
+        let controller = async move {
+            // Shown a first time, then again once it has been snoozed
+            shown.recv().await.unwrap();
+            shown.recv().await.unwrap();
+            // Give the scheduler some time to receive the dismissal
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            handle.stop();
+        };
+        tokio::join!(scheduler.run(), controller);
+
+        let shown = notifier.shown.lock().unwrap();
+        assert_eq!(shown.len(), 2);
+        assert_eq!(shown[0], Notification{ summary: "Feed the cat".to_string(), body: String::new(), sound: Some(NotificationSound::File(PathBuf::from("/usr/share/sounds/bell.oga"))) });
+    }
+}
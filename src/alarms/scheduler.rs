@@ -0,0 +1,371 @@
+//! Fires the alarms of the items of a local source at the right moment
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::provider::Provider;
+use crate::traits::{CalDavSource, CompleteCalendar, DavCalendar};
+use crate::time::Instant;
+use crate::{Item, Task};
+use super::{alarms_between, AlarmKey, AlarmSink, DueAlarm};
+
+/// How a [`Scheduler`] looks for alarms
+#[derive(Clone, Debug)]
+pub struct AlarmSettings {
+    /// How often the items are scanned again, so that items that have changed (e.g. during a sync) are taken into account. See also [`SchedulerHandle::rescan`]
+    pub rescan_interval: Duration,
+    /// Alarms that have been triggered while the scheduler was not running are still fired when it starts, unless they are older than this
+    pub catch_up: Duration,
+    /// The file where the scheduler remembers which alarms have been fired and dismissed, so that they are not fired again after a restart.
+    /// When this is `None`, this is only remembered as long as the scheduler runs
+    pub state_file: Option<PathBuf>,
+}
+
+impl Default for AlarmSettings {
+    fn default() -> Self {
+        Self {
+            rescan_interval: Duration::from_secs(60),
+            catch_up: Duration::from_secs(24 * 3600),
+            state_file: None,
+        }
+    }
+}
+
+/// The alarms that have been fired, dismissed and snoozed, as saved in [`AlarmSettings::state_file`]
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AlarmState {
+    fired: HashSet<AlarmKey>,
+    dismissed: HashSet<AlarmKey>,
+    /// When snoozed alarms are fired again
+    #[serde(default)]
+    snoozed: HashMap<AlarmKey, DateTime<Utc>>,
+}
+
+impl AlarmState {
+    fn load(settings: &AlarmSettings) -> Self {
+        let path = match &settings.state_file {
+            None => return Self::default(),
+            Some(path) => path,
+        };
+        match std::fs::read(path) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(err) => {
+                log::warn!("Unable to read the state of the alarms from {:?}: {}. Alarms may be fired again", path, err);
+                Self::default()
+            },
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|err| {
+                log::warn!("Invalid state of the alarms in {:?}: {}. Alarms may be fired again", path, err);
+                Self::default()
+            }),
+        }
+    }
+
+    fn save(&mut self, settings: &AlarmSettings) {
+        // Older alarms are not looked for anyway
+        let oldest = Utc::now() - chrono::Duration::from_std(settings.catch_up).unwrap_or_else(|_| chrono::Duration::max_value());
+        self.fired.retain(|key| key.trigger >= oldest);
+        self.dismissed.retain(|key| key.trigger >= oldest);
+        self.snoozed.retain(|key, _| key.trigger >= oldest);
+
+        let path = match &settings.state_file {
+            None => return,
+            Some(path) => path,
+        };
+        let result = path.parent().map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(|err| err.to_string())
+            .and_then(|_| serde_json::to_vec(self).map_err(|err| err.to_string()))
+            .and_then(|content| std::fs::write(path, content).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            log::error!("Unable to save the state of the alarms to {:?}: {}", path, err);
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Command {
+    Rescan,
+    Dismiss(AlarmKey),
+    Snooze(AlarmKey, Duration),
+    Stop,
+}
+
+/// Controls a running alarm [`Scheduler`].
+///
+/// The scheduler stops when every handle has been dropped
+#[derive(Clone, Debug)]
+pub struct SchedulerHandle {
+    commands: UnboundedSender<Command>,
+}
+
+impl SchedulerHandle {
+    /// Scan the items again, e.g. because they have been changed by a sync (see [`Cache::subscribe`](crate::cache::Cache::subscribe))
+    pub fn rescan(&self) {
+        self.send(Command::Rescan);
+    }
+
+    /// Tell that the user has seen an alarm. It will not be fired again, even after a restart. \
+    /// Alarms can also be dismissed before they are triggered.
+    ///
+    /// The alarm is dismissed in its item as well (see [`Task::dismiss`]), so that other clients know about it once it is synced
+    pub fn dismiss(&self, alarm: AlarmKey) {
+        self.send(Command::Dismiss(alarm));
+    }
+
+    /// Fire an alarm again in some time, e.g. because the user has clicked on a "remind me later" button.
+    ///
+    /// Like dismissals, snoozes are saved in items (see [`Task::snooze`]), unless their calendar is read-only
+    pub fn snooze(&self, alarm: AlarmKey, duration: Duration) {
+        self.send(Command::Snooze(alarm, duration));
+    }
+
+    /// Stop the scheduler for good
+    pub fn stop(&self) {
+        self.send(Command::Stop);
+    }
+
+    fn send(&self, command: Command) {
+        // The scheduler may have stopped already, in which case there is nothing to do
+        let _ = self.commands.send(command);
+    }
+}
+
+/// Fires the alarms of the items of the local source of a [`Provider`] (e.g. a [`Cache`](crate::cache::Cache)) when they are triggered (see [`alarms_between`]).
+///
+/// The provider is shared behind an async mutex, just like with a [`SyncScheduler`](crate::provider::scheduler::SyncScheduler), so that both can run at the same time.
+/// The scheduler does nothing until [`Self::run`] is awaited (e.g. in a dedicated task). \
+/// Every alarm is fired once. Alarms that have been fired but not dismissed (see [`SchedulerHandle::dismiss`]) before the scheduler was stopped are fired again when it restarts,
+/// so that apps can show them again (see [`DueAlarm::already_fired`])
+pub struct Scheduler<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    provider: Arc<tokio::sync::Mutex<Provider<L, T, R, U>>>,
+    settings: AlarmSettings,
+    sink: Arc<dyn AlarmSink>,
+    commands: UnboundedReceiver<Command>,
+    state: AlarmState,
+    /// The alarms that have been fired since the scheduler has started
+    delivered: HashSet<AlarmKey>,
+}
+
+impl<L, T, R, U> Scheduler<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    /// Create a scheduler that sends the alarms it fires to `sink`, and a handle to control it
+    pub fn new(provider: Arc<tokio::sync::Mutex<Provider<L, T, R, U>>>, settings: AlarmSettings, sink: Arc<dyn AlarmSink>) -> (Self, SchedulerHandle) {
+        let (command_sender, commands) = unbounded_channel();
+        let state = AlarmState::load(&settings);
+        let scheduler = Self { provider, settings, sink, commands, state, delivered: HashSet::new() };
+        (scheduler, SchedulerHandle{ commands: command_sender })
+    }
+
+    /// Fire alarms until the scheduler is stopped
+    pub async fn run(mut self) {
+        let mut upcoming = Vec::new();
+        let mut next_scan = Instant::now();
+
+        loop {
+            if Instant::now() >= next_scan {
+                upcoming = self.scan().await;
+                next_scan = Instant::now() + self.settings.rescan_interval;
+            }
+            self.fire_triggered(&mut upcoming);
+
+            let next_alarm = upcoming.first().map(|(fire_at, _)| {
+                Instant::now() + (*fire_at - Utc::now()).to_std().unwrap_or_default()
+            });
+            let wake_up = next_alarm.map_or(next_scan, |next_alarm| next_alarm.min(next_scan));
+
+            let command = tokio::select! {
+                command = self.commands.recv() => command,
+                _ = crate::time::sleep_until(wake_up) => continue,
+            };
+            match command {
+                None | Some(Command::Stop) => return,
+                Some(Command::Rescan) => next_scan = Instant::now(),
+                Some(Command::Dismiss(key)) => {
+                    self.update_task(&key, |task| task.dismiss(key.alarm)).await;
+                    upcoming.retain(|(_, alarm)| alarm.key != key);
+                    self.state.snoozed.remove(&key);
+                    self.state.dismissed.insert(key);
+                    self.state.save(&self.settings);
+                    // Dismissing a snooze alarm removes it, which changes the indices of the alarms after it
+                    next_scan = Instant::now();
+                },
+                Some(Command::Snooze(key, duration)) => {
+                    let duration = chrono::Duration::from_std(duration).unwrap_or_else(|_| chrono::Duration::max_value());
+                    if !self.update_task(&key, |task| task.snooze(key.alarm, duration).map(|_| ())).await {
+                        // The snooze is only remembered by this scheduler then
+                        self.state.snoozed.insert(key, Utc::now() + duration);
+                        self.state.save(&self.settings);
+                    }
+                    next_scan = Instant::now();
+                },
+            }
+        }
+    }
+
+    /// The alarms that have not been fired (nor dismissed) yet, and that are triggered before the next scan, with the time they should be fired at
+    async fn scan(&self) -> Vec<(DateTime<Utc>, DueAlarm)> {
+        let now = Utc::now();
+        let from = now - chrono::Duration::from_std(self.settings.catch_up).unwrap_or_else(|_| chrono::Duration::max_value());
+        let to = now + chrono::Duration::from_std(self.settings.rescan_interval).unwrap_or_else(|_| chrono::Duration::max_value());
+
+        let provider = self.provider.lock().await;
+        let calendars = match provider.local().get_calendars().await {
+            Ok(calendars) => calendars,
+            Err(err) => {
+                log::warn!("Unable to look for alarms: {}", err);
+                return Vec::new();
+            },
+        };
+        let mut alarms = Vec::new();
+        for calendar in calendars.values() {
+            let calendar = calendar.lock().unwrap();
+            for (_, item) in calendar.iter_items() {
+                alarms.extend(alarms_between(item, calendar.url(), &from, &to).into_iter()
+                    .filter(|alarm| !self.state.dismissed.contains(&alarm.key))
+                    .filter_map(|alarm| {
+                        let fire_at = match self.state.snoozed.get(&alarm.key) {
+                            Some(until) => *until,
+                            None if self.delivered.contains(&alarm.key) => return None,
+                            None => alarm.key.trigger,
+                        };
+                        Some((fire_at, DueAlarm{ already_fired: self.state.fired.contains(&alarm.key), ..alarm }))
+                    }));
+            }
+        }
+        alarms.sort_by_key(|(fire_at, _)| *fire_at);
+        log::debug!("{} alarms are pending until {}", alarms.len(), to);
+        alarms
+    }
+
+    /// Dismiss or snooze an alarm in its task, in the local source. Returns whether this has been done
+    async fn update_task<F>(&self, key: &AlarmKey, update: F) -> bool
+    where
+        F: FnOnce(&mut Task) -> Result<(), Box<dyn Error>>,
+    {
+        let provider = self.provider.lock().await;
+        let calendars = match provider.local().get_calendars().await {
+            Ok(calendars) => calendars,
+            Err(err) => {
+                log::warn!("Unable to update the alarms of {}: {}", key.item, err);
+                return false;
+            },
+        };
+        for calendar in calendars.values() {
+            let mut calendar = calendar.lock().unwrap();
+            let read_only = calendar.is_read_only();
+            let task = match calendar.iter_items_mut().find(|(url, _)| *url == &key.item) {
+                None => continue,
+                Some((_, Item::Task(task))) if !read_only => task,
+                Some(_) => return false,
+            };
+            return match update(task) {
+                Ok(()) => true,
+                Err(err) => {
+                    log::warn!("Unable to update the alarms of {}: {}", key.item, err);
+                    false
+                },
+            };
+        }
+        false
+    }
+
+    fn fire_triggered(&mut self, upcoming: &mut Vec<(DateTime<Utc>, DueAlarm)>) {
+        let now = Utc::now();
+        let triggered = upcoming.iter().take_while(|(fire_at, _)| fire_at <= &now).count();
+        if triggered == 0 {
+            return;
+        }
+        for (_, alarm) in upcoming.drain(..triggered) {
+            self.sink.alarm_fired(&alarm);
+            self.state.snoozed.remove(&alarm.key);
+            self.delivered.insert(alarm.key.clone());
+            self.state.fired.insert(alarm.key);
+        }
+        self.state.save(&self.settings);
+    }
+}
+
+
+#[cfg(all(test, feature = "local_calendar_mocks_remote_calendars"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_alarm_scheduler() {
+        use std::path::Path;
+        use crate::alarm::{Alarm, AlarmTrigger};
+        use crate::calendar::SupportedComponents;
+        use crate::cache::Cache;
+        use crate::{Item, Task};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let state_file = PathBuf::from("test_cache/alarm_scheduler/state.json");
+        let _ = std::fs::remove_file(&state_file);
+
+        let mut local = Cache::new_in_memory();
+        let cal_url: url::Url = "https://some.server/cal/".parse().unwrap();
+        let calendar = local.create_calendar(cal_url.clone(), "Reminders".to_string(), SupportedComponents::TODO, None).await.unwrap();
+        let mut task = Task::new("Call mom".to_string(), false, &cal_url);
+        task.add_alarm(Alarm::display(AlarmTrigger::Absolute(Utc::now() - chrono::Duration::minutes(1)), "Call mom"));
+        calendar.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        let provider = Arc::new(tokio::sync::Mutex::new(Provider::new(Cache::new(Path::new("test_cache/alarm_scheduler_remote")), local)));
+
+        let settings = AlarmSettings{ state_file: Some(state_file), ..AlarmSettings::default() };
+        let (fired_sender, mut fired) = unbounded_channel();
+        let sink: Arc<dyn AlarmSink> = Arc::new(move |alarm: &DueAlarm| { fired_sender.send(alarm.clone()).unwrap(); });
+
+        // The alarm is fired as soon as the scheduler starts
+        let (scheduler, handle) = Scheduler::new(provider.clone(), settings.clone(), sink.clone());
+        handle.stop();
+        scheduler.run().await;
+        let alarm = fired.try_recv().unwrap();
+        assert_eq!(alarm.summary, "Call mom");
+        assert!(!alarm.already_fired);
+        assert!(fired.try_recv().is_err());
+
+        // It has not been dismissed, so that it is fired again after a restart (and after it has been snoozed), until it is dismissed
+        let (scheduler, handle) = Scheduler::new(provider.clone(), settings.clone(), sink.clone());
+        handle.snooze(alarm.key.clone(), Duration::from_secs(0));
+        handle.dismiss(alarm.key.clone());
+        handle.stop();
+        scheduler.run().await;
+        assert!(fired.try_recv().unwrap().already_fired);
+        let snoozed = fired.try_recv().unwrap();
+        assert_eq!(snoozed.key.item, alarm.key.item);
+        assert!(snoozed.alarm.snoozed_alarm().is_some());
+        assert!(fired.try_recv().is_err());
+
+        // The dismissal has been saved in the task, that has no snooze alarm anymore
+        {
+            let provider = provider.lock().await;
+            let calendar = provider.local().get_calendar(&cal_url).await.unwrap();
+            let calendar = calendar.lock().unwrap();
+            let task = calendar.iter_items().next().unwrap().1.unwrap_task();
+            assert_eq!(task.alarms().len(), 1);
+            assert!(task.alarms()[0].acknowledged().is_some());
+        }
+
+        let (scheduler, handle) = Scheduler::new(provider.clone(), settings.clone(), sink.clone());
+        handle.rescan();
+        handle.stop();
+        scheduler.run().await;
+        assert!(fired.try_recv().is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//! A synchronous API, for apps that do not run an async runtime (e.g. CLI tools, or most GUI frameworks)
+//!
+//! The types of this module wrap their async counterparts, and run their futures to completion on a runtime that is shared by the whole crate.
+//! Hence, they must not be used from async code (this would panic): use the async API instead.
+//!
+//! The [`Cache`] needs no wrapper, since it already provides non-async versions of its methods (e.g. [`Cache::get_calendars_sync`](crate::cache::Cache::get_calendars_sync))
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use csscolorparser::Color;
+use once_cell::sync::Lazy;
+use tokio::runtime::Runtime;
+use url::Url;
+
+use crate::traits::{CalDavSource, CompleteCalendar, DavCalendar};
+use crate::calendar::{CalendarTimezone, SupportedComponents};
+use crate::calendar::remote_calendar::RemoteCalendar;
+use crate::client::{Quota, ServerCapabilities};
+use crate::freebusy::FreeBusy;
+use crate::provider::duplicates::{DuplicateCriteria, DuplicateGroup};
+use crate::provider::pending::PendingChange;
+use crate::provider::plan::SyncPlan;
+use crate::provider::search::{SearchMatch, SearchOptions};
+use crate::provider::sync_progress::ProgressSender;
+use crate::provider::sync_result::SyncResult;
+use crate::error::KFError;
+
+pub use crate::cache::Cache;
+
+type SharedCalendar = Arc<Mutex<RemoteCalendar>>;
+
+/// The runtime every blocking call is run on. HTTP connections are bound to the runtime they have been opened on, that is why a single runtime is used
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Unable to start the runtime of blocking calls")
+});
+
+/// Run a future of the async API to completion, e.g. for methods that have no blocking version
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    RUNTIME.block_on(future)
+}
+
+
+/// The blocking version of a [`Client`](crate::client::Client)
+#[derive(Debug)]
+pub struct Client {
+    inner: crate::client::Client,
+}
+
+impl Client {
+    /// Create a client. See [`Client::new`](crate::client::Client::new)
+    pub fn new<S: AsRef<str>, T: ToString, U: ToString>(url: S, username: T, password: U) -> Result<Self, KFError> {
+        Ok(Self::from_async(crate::client::Client::new(url, username, password)?))
+    }
+
+    /// Wrap an async client, e.g. one that has been created by a [`ClientBuilder`](crate::client::ClientBuilder)
+    pub fn from_async(inner: crate::client::Client) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &crate::client::Client {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> crate::client::Client {
+        self.inner
+    }
+
+    pub fn get_calendars(&self) -> Result<HashMap<Url, SharedCalendar>, KFError> {
+        block_on(self.inner.get_calendars())
+    }
+
+    pub fn get_calendar(&self, url: &Url) -> Option<SharedCalendar> {
+        block_on(self.inner.get_calendar(url))
+    }
+
+    pub fn create_calendar(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>) -> Result<SharedCalendar, KFError> {
+        block_on(self.inner.create_calendar(url, name, supported_components, color))
+    }
+
+    pub fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, timezone: Option<CalendarTimezone>) -> Result<SharedCalendar, KFError> {
+        block_on(self.inner.create_calendar_with_timezone(url, name, supported_components, color, timezone))
+    }
+
+    pub fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        block_on(CalDavSource::server_capabilities(&self.inner))
+    }
+
+    pub fn delegated_principals(&self) -> Result<Vec<Url>, KFError> {
+        block_on(self.inner.delegated_principals())
+    }
+
+    pub fn free_busy(&self, calendar_url: &Url, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<FreeBusy, KFError> {
+        block_on(self.inner.free_busy(calendar_url, start, end))
+    }
+
+    pub fn get_quota(&self, calendar_url: &Url) -> Result<Quota, KFError> {
+        block_on(self.inner.get_quota(calendar_url))
+    }
+}
+
+
+/// The blocking version of a [`Provider`](crate::provider::Provider)
+///
+/// Its settings are set on the async provider, see [`Self::inner_mut`]
+pub struct Provider<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    inner: crate::provider::Provider<L, T, R, U>,
+}
+
+/// The blocking version of a [`CalDavProvider`](crate::CalDavProvider)
+pub type CalDavProvider = Provider<Cache, crate::calendar::cached_calendar::CachedCalendar, crate::client::Client, RemoteCalendar>;
+
+impl<L, T, R, U> Provider<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    /// Create a provider. See [`Provider::new`](crate::provider::Provider::new)
+    pub fn new(remote: R, local: L) -> Self {
+        Self::from_async(crate::provider::Provider::new(remote, local))
+    }
+
+    pub fn from_async(inner: crate::provider::Provider<L, T, R, U>) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &crate::provider::Provider<L, T, R, U> {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut crate::provider::Provider<L, T, R, U> {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> crate::provider::Provider<L, T, R, U> {
+        self.inner
+    }
+
+    /// Returns the data source described as `local`
+    pub fn local(&self) -> &L { self.inner.local() }
+    /// Returns the data source described as `local`
+    pub fn local_mut(&mut self) -> &mut L { self.inner.local_mut() }
+    /// Returns the data source described as `remote`
+    pub fn remote(&self) -> &R { self.inner.remote() }
+
+    pub fn sync(&mut self) -> SyncResult {
+        block_on(self.inner.sync())
+    }
+
+    /// Performs a sync, and sends its progress into a channel. The channel can be read by another thread (e.g. with `blocking_recv`)
+    pub fn sync_with_progress(&mut self, progress_sender: ProgressSender) -> SyncResult {
+        block_on(self.inner.sync_with_progress(progress_sender))
+    }
+
+    pub fn pending_changes(&self) -> Result<Vec<PendingChange>, KFError> {
+        block_on(self.inner.pending_changes())
+    }
+
+    pub fn plan_sync(&self) -> Result<SyncPlan, KFError> {
+        block_on(self.inner.plan_sync())
+    }
+
+    pub fn rename_calendar(&mut self, calendar: &Url, new_name: String) -> Result<(), KFError> {
+        block_on(self.inner.rename_calendar(calendar, new_name))
+    }
+
+    pub fn move_item(&mut self, item_url: &Url, target_calendar: &Url) -> Result<Url, KFError> {
+        block_on(self.inner.move_item(item_url, target_calendar))
+    }
+
+    pub fn fetch_evicted_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        block_on(self.inner.fetch_evicted_item(item_url))
+    }
+
+    pub fn find_duplicates(&self, criteria: &DuplicateCriteria) -> Result<Vec<DuplicateGroup>, KFError> {
+        block_on(self.inner.find_duplicates(criteria))
+    }
+
+    pub fn merge_duplicates(&mut self, groups: &[DuplicateGroup]) -> Result<usize, KFError> {
+        block_on(self.inner.merge_duplicates(groups))
+    }
+
+    pub fn search(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchMatch>, KFError> {
+        block_on(self.inner.search(query, options))
+    }
+}
@@ -0,0 +1,71 @@
+//! A portable, single-file copy of a whole cache (see [`Cache::export_archive`](super::Cache::export_archive))
+//!
+//! An archive is a zip file with the same layout as the folder of a [`FolderStorage`](super::storage::FolderStorage): a `data.json` file
+//! that tells the [`SCHEMA_VERSION`](super::migration::SCHEMA_VERSION) it has been written with, and a `.cal` file for every calendar
+//! (with its items, its tombstones and its sync state). It can therefore be extracted and opened with [`Cache::from_folder`](super::Cache::from_folder). \
+//! Calendars are always stored as JSON (whatever the codec of the storage they have been exported from),
+//! and archives written by older versions of this crate are migrated when they are imported.
+
+use std::error::Error;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use zip::write::SimpleFileOptions;
+
+use crate::calendar::cached_calendar::CachedCalendar;
+use super::codec::{CalendarCodec, JsonCodec, JSON_CODEC};
+use super::migration::CacheHeader;
+use super::storage::{calendar_file_name, decode_calendar, MAIN_FILE};
+
+/// Write calendars into an archive at `path`, replacing it atomically if it already exists
+pub(crate) fn write_archive(path: &Path, calendars: &[&CachedCalendar]) -> Result<(), Box<dyn Error>> {
+    let tmp_path = path.with_extension("tmp");
+    let mut zip = ZipWriter::new(BufWriter::new(std::fs::File::create(&tmp_path)?));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file(MAIN_FILE, options)?;
+    zip.write_all(&serde_json::to_vec(&CacheHeader::current(JSON_CODEC))?)?;
+    for cal in calendars {
+        zip.start_file(calendar_file_name(cal), options)?;
+        zip.write_all(&JsonCodec.encode(cal)?)?;
+    }
+
+    let file = zip.finish()?.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    if let Some(parent) = path.parent() {
+        let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+        super::storage::sync_dir(parent)?;
+    }
+    Ok(())
+}
+
+/// Read the calendars of an archive, and migrate them to the current schema if needed
+pub(crate) fn read_archive(path: &Path) -> Result<Vec<CachedCalendar>, Box<dyn Error>> {
+    let file = std::fs::File::open(path)?;
+    let mut zip = ZipArchive::new(BufReader::new(file))
+        .map_err(|err| format!("{} is not a valid archive: {}", path.display(), err))?;
+
+    let header: CacheHeader = match zip.by_name(MAIN_FILE) {
+        Err(_) => return Err(format!("{} is not a kitchen-fridge archive", path.display()).into()),
+        Ok(main_file) => serde_json::from_reader(main_file)?,
+    };
+    // Archives are always written as JSON
+    header.needs_rewrite(JSON_CODEC)
+        .map_err(|err| format!("Unable to import {}: {}", path.display(), err))?;
+
+    let mut calendars = Vec::new();
+    for index in 0..zip.len() {
+        let mut entry = zip.by_index(index)?;
+        if !entry.is_file() || !entry.name().ends_with(".cal") {
+            continue;
+        }
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        let cal = decode_calendar(&content, &header, &JsonCodec)
+            .map_err(|err| format!("Unable to import calendar {} from {}: {}", entry.name(), path.display(), err))?;
+        calendars.push(cal);
+    }
+    Ok(calendars)
+}
@@ -0,0 +1,78 @@
+//! How calendars are encoded in the cache files, see [`CalendarCodec`]
+
+use std::error::Error;
+use std::fmt::Debug;
+
+use serde_json::Value;
+
+use crate::calendar::cached_calendar::CachedCalendar;
+
+/// The name of [`JsonCodec`]. Caches written before codecs were configurable use it
+pub const JSON_CODEC: &str = "json";
+/// The name of [`CborCodec`]
+pub const CBOR_CODEC: &str = "cbor";
+
+/// Encodes calendars into the bytes that [`FolderStorage`](super::storage::FolderStorage)s and [`KvStorage`](super::storage::KvStorage)s write.
+///
+/// Storages use [`JsonCodec`] unless configured otherwise (see e.g. [`FolderStorage::set_codec`](super::storage::FolderStorage::set_codec)).
+/// [`CborCodec`] is a more compact binary format. Apps can implement this trait with other formats as well, since [`CachedCalendar`] is `Serialize` and `Deserialize`.
+///
+/// Storages record the name of the codec their calendars have been written with.
+/// A cache that has been written as JSON is converted the first time it is loaded with another codec
+pub trait CalendarCodec: Debug + Send + Sync {
+    /// A short name that identifies this format (e.g. `"bincode"`)
+    fn name(&self) -> &str;
+
+    fn encode(&self, calendar: &CachedCalendar) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    fn decode(&self, data: &[u8]) -> Result<CachedCalendar, Box<dyn Error>>;
+
+    /// Decode a calendar into a generic value, so that it can be migrated when it has been written with an older schema (see [`super::migration`])
+    fn decode_value(&self, data: &[u8]) -> Result<Value, Box<dyn Error>>;
+}
+
+/// Encodes calendars as JSON. This is the default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonCodec;
+
+impl CalendarCodec for JsonCodec {
+    fn name(&self) -> &str {
+        JSON_CODEC
+    }
+
+    fn encode(&self, calendar: &CachedCalendar) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(serde_json::to_vec(calendar)?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<CachedCalendar, Box<dyn Error>> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    fn decode_value(&self, data: &[u8]) -> Result<Value, Box<dyn Error>> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// Encodes calendars as [CBOR](https://www.rfc-editor.org/rfc/rfc8949), a binary format that is smaller and faster to parse than JSON
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CborCodec;
+
+impl CalendarCodec for CborCodec {
+    fn name(&self) -> &str {
+        CBOR_CODEC
+    }
+
+    fn encode(&self, calendar: &CachedCalendar) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut data = Vec::new();
+        ciborium::into_writer(calendar, &mut data)?;
+        Ok(data)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<CachedCalendar, Box<dyn Error>> {
+        Ok(ciborium::from_reader(data)?)
+    }
+
+    fn decode_value(&self, data: &[u8]) -> Result<Value, Box<dyn Error>> {
+        Ok(ciborium::from_reader(data)?)
+    }
+}
@@ -0,0 +1,34 @@
+//! Notifications about the changes of a cache, so that apps can refresh their views without comparing the whole cache (see [`Cache::subscribe`](super::Cache::subscribe))
+
+use url::Url;
+
+/// How many events are kept for receivers that are late. Receivers that lag behind further miss the oldest events (and are told so, see [`tokio::sync::broadcast::error::RecvError::Lagged`])
+pub(crate) const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// A change of the content of a [`Cache`](super::Cache), either made locally or by a sync
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CacheEvent {
+    /// An item has been added to a calendar (e.g. it has been downloaded, restored, or created locally)
+    ItemAdded{ calendar: Url, item: Url },
+    /// An item has been replaced by another version
+    ItemModified{ calendar: Url, item: Url },
+    /// An item has been deleted, or marked for deletion
+    ItemDeleted{ calendar: Url, item: Url },
+    /// A calendar has been added to the cache
+    CalendarAdded{ calendar: Url },
+    /// The sync of a calendar is over, see [`CompleteCalendar::record_sync`](crate::traits::CompleteCalendar::record_sync)
+    SyncCompleted{ calendar: Url, errors: usize },
+}
+
+/// See [`Cache::subscribe`](super::Cache::subscribe)
+pub type CacheEventSender = tokio::sync::broadcast::Sender<CacheEvent>;
+/// See [`Cache::subscribe`](super::Cache::subscribe)
+pub type CacheEventReceiver = tokio::sync::broadcast::Receiver<CacheEvent>;
+
+/// Send an event, if anyone is listening
+pub(crate) fn notify(sender: &Option<CacheEventSender>, event: CacheEvent) {
+    if let Some(sender) = sender {
+        // This only fails when there is no receiver
+        let _ = sender.send(event);
+    }
+}
@@ -0,0 +1,81 @@
+//! Dropping the content of old items, so that the caches of huge calendars stay small (see [`Cache::set_eviction_policy`](super::Cache::set_eviction_policy))
+//!
+//! Evicted items are still known by their calendars (with their summaries, see [`CachedCalendar::evicted_items`](crate::calendar::cached_calendar::CachedCalendar::evicted_items)),
+//! so that they are not downloaded again at every sync. Their content can be downloaded again on demand (see [`Provider::fetch_evicted_item`](crate::provider::Provider::fetch_evicted_item)).
+
+use std::borrow::Cow;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use url::Url;
+
+use crate::calendar::lazy_items::ItemSummary;
+use crate::item::SyncStatus;
+
+/// Which items should be evicted from a calendar.
+///
+/// Only items that have been synced can be evicted. Items that recur endlessly, and tasks that have no date and are not completed yet, are never evicted
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EvictionPolicy {
+    older_than: Option<Duration>,
+    max_items: Option<usize>,
+}
+
+impl EvictionPolicy {
+    /// A policy that evicts nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Evict the items that have ended for longer than this (e.g. events of past years).
+    /// Completed tasks that have no date are evicted when they have not been modified for longer than this
+    pub fn set_older_than(&mut self, older_than: Option<Duration>) {
+        self.older_than = older_than;
+    }
+
+    /// Evict the oldest items (in the sense of [`Self::set_older_than`]), so that calendars contain at most this many items
+    pub fn set_max_items(&mut self, max_items: Option<usize>) {
+        self.max_items = max_items;
+    }
+
+    pub fn older_than(&self) -> Option<Duration> {
+        self.older_than
+    }
+
+    pub fn max_items(&self) -> Option<usize> {
+        self.max_items
+    }
+
+    /// The items (out of `summaries`, which describe every item of a calendar) that should be evicted
+    pub(crate) fn select<'a>(&self, summaries: impl Iterator<Item = (&'a Url, Cow<'a, ItemSummary>)>) -> Vec<Url> {
+        let mut total: usize = 0;
+        let mut candidates: Vec<(DateTime<Utc>, &Url)> = summaries
+            .inspect(|_| total += 1)
+            .filter(|(_, summary)| matches!(summary.sync_status, SyncStatus::Synced(_)))
+            .filter_map(|(url, summary)| Some((eviction_date(&summary)?, url)))
+            .collect();
+        candidates.sort();
+
+        let oldest_kept = self.older_than
+            .and_then(|age| chrono::Duration::from_std(age).ok())
+            .and_then(|age| Utc::now().checked_sub_signed(age));
+        let too_old = candidates.iter()
+            .take_while(|(date, _)| oldest_kept.is_some_and(|oldest| *date < oldest))
+            .count();
+        let too_many = self.max_items.map(|max| total.saturating_sub(max)).unwrap_or(0);
+
+        candidates.into_iter()
+            .take(too_old.max(too_many))
+            .map(|(_, url)| url.clone())
+            .collect()
+    }
+}
+
+/// The date an item is considered old from, or `None` if it should never be evicted
+fn eviction_date(summary: &ItemSummary) -> Option<DateTime<Utc>> {
+    match summary.date_range() {
+        Some((_, end)) => end,
+        None if summary.completed => summary.last_modified,
+        None => None,
+    }
+}
@@ -0,0 +1,96 @@
+//! Consistency checks of the content of a cache (see [`Cache::check_integrity`](super::Cache::check_integrity))
+
+use std::fmt::{Display, Formatter};
+
+use url::Url;
+
+/// Something wrong in the content of a cache
+#[derive(Clone, Debug, PartialEq)]
+pub enum IntegrityIssue {
+    /// An item cannot be deserialized
+    UnreadableItem { calendar: Url, item: Url },
+    /// An item is stored under another URL than its own
+    MisplacedItem { calendar: Url, key: Url, item: Url },
+    /// The summary that has been stored along with an item (see [`ItemSummary`](crate::calendar::lazy_items::ItemSummary)) does not match its content
+    StaleSummary { calendar: Url, item: Url },
+    /// Several items of a calendar have the same UID
+    DuplicateUid { calendar: Url, uid: String, items: Vec<Url> },
+    /// An item is stored in several calendars
+    DuplicateUrl { item: Url, calendars: Vec<Url> },
+    /// An item has been synced, but its version tag (i.e. its ETag) is empty
+    MissingVersionTag { calendar: Url, item: Url },
+    /// The base version of an item (see [`CompleteCalendar::base_version`](crate::traits::CompleteCalendar::base_version))
+    /// does not have the version tag the item has been synced with, or the item does not exist anymore
+    StaleBaseVersion { calendar: Url, item: Url },
+    /// An item has a tombstone, but has not been deleted
+    TombstonedItem { calendar: Url, item: Url },
+    /// A local change is queued for an item that does not exist
+    UnknownQueuedChange { calendar: Url, item: Url },
+    /// An item is an event in a calendar that only supports tasks, or the other way round
+    UnsupportedComponent { calendar: Url, item: Url },
+}
+
+impl IntegrityIssue {
+    /// Whether [`Cache::repair`](super::Cache::repair) fixes this issue.
+    ///
+    /// The other issues cannot be fixed without losing data, and are left for the user to sort out
+    pub fn is_repairable(&self) -> bool {
+        !matches!(self,
+            IntegrityIssue::DuplicateUid { .. }
+            | IntegrityIssue::DuplicateUrl { .. }
+            | IntegrityIssue::MissingVersionTag { .. }
+            | IntegrityIssue::UnsupportedComponent { .. }
+        )
+    }
+}
+
+impl Display for IntegrityIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntegrityIssue::UnreadableItem { calendar, item } => write!(f, "{}: item {} cannot be deserialized", calendar, item),
+            IntegrityIssue::MisplacedItem { calendar, key, item } => write!(f, "{}: item {} is stored as {}", calendar, item, key),
+            IntegrityIssue::StaleSummary { calendar, item } => write!(f, "{}: the summary of item {} is out of date", calendar, item),
+            IntegrityIssue::DuplicateUid { calendar, uid, items } => write!(f, "{}: UID {} is used by {} items", calendar, uid, items.len()),
+            IntegrityIssue::DuplicateUrl { item, calendars } => write!(f, "item {} is stored in {} calendars", item, calendars.len()),
+            IntegrityIssue::MissingVersionTag { calendar, item } => write!(f, "{}: item {} has been synced without a version tag", calendar, item),
+            IntegrityIssue::StaleBaseVersion { calendar, item } => write!(f, "{}: the base version of item {} is out of date", calendar, item),
+            IntegrityIssue::TombstonedItem { calendar, item } => write!(f, "{}: item {} has a tombstone but still exists", calendar, item),
+            IntegrityIssue::UnknownQueuedChange { calendar, item } => write!(f, "{}: a change is queued for unknown item {}", calendar, item),
+            IntegrityIssue::UnsupportedComponent { calendar, item } => write!(f, "{}: item {} is not supported by this calendar", calendar, item),
+        }
+    }
+}
+
+/// The result of [`Cache::check_integrity`](super::Cache::check_integrity) or [`Cache::repair`](super::Cache::repair)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IntegrityReport {
+    issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    pub(crate) fn new(issues: Vec<IntegrityIssue>) -> Self {
+        Self { issues }
+    }
+
+    /// Returns `true` if no issue has been found
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn issues(&self) -> &[IntegrityIssue] {
+        &self.issues
+    }
+}
+
+impl Display for IntegrityReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_ok() {
+            return write!(f, "No integrity issue");
+        }
+        writeln!(f, "{} integrity issue(s):", self.issues.len())?;
+        for issue in &self.issues {
+            writeln!(f, "  * {}", issue)?;
+        }
+        Ok(())
+    }
+}
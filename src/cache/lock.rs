@@ -0,0 +1,109 @@
+//! Advisory file locks, so that several processes (e.g. a CLI tool and a daemon) sharing the same cache do not corrupt each other's writes
+
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::KFError;
+use crate::time::Instant;
+
+/// How long to wait before trying again to lock a file that is already locked
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// How long storages wait for another process to release the cache, unless configured otherwise
+pub(crate) const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An exclusive lock on a file, that is released when this is dropped.
+///
+/// This relies on `flock` on Unix and `LockFileEx` on Windows. Locks are advisory: they only protect against processes that use them as well
+#[derive(Debug)]
+pub(crate) struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Lock the file at `path` (that is created if needed), and wait up to `timeout` if another process has already locked it.
+    ///
+    /// Returns a [`KFError::CacheInUse`] if it is still locked after that
+    pub(crate) fn acquire(path: &Path, timeout: Duration) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let start = Instant::now();
+        loop {
+            if try_lock(&file)? {
+                return Ok(Self { _file: file });
+            }
+            if start.elapsed() >= timeout {
+                return Err(KFError::CacheInUse{ path: path.to_path_buf() }.into());
+            }
+            std::thread::sleep(RETRY_DELAY);
+        }
+    }
+}
+
+/// Try to lock a file without blocking. Returns whether it has been locked
+#[cfg(unix)]
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    // Safety: the file descriptor is valid as long as `file` is alive
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Try to lock a file without blocking. Returns whether it has been locked
+#[cfg(windows)]
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    use std::os::windows::io::AsRawHandle;
+    use winapi::shared::winerror::ERROR_LOCK_VIOLATION;
+    use winapi::um::fileapi::LockFileEx;
+    use winapi::um::minwinbase::{OVERLAPPED, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY};
+
+    // Safety: the handle is valid as long as `file` is alive, and `overlapped` outlives this (synchronous) call
+    let locked = unsafe {
+        let mut overlapped: OVERLAPPED = std::mem::zeroed();
+        LockFileEx(file.as_raw_handle() as _, LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY, 0, !0, !0, &mut overlapped)
+    };
+    if locked != 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(code) if code == ERROR_LOCK_VIOLATION as i32 => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Platforms without file locks: nothing is locked.
+///
+/// Browsers (wasm32) have no files at all, so opening the lock file already fails there, and caches should use a [`WebStorage`](super::web::WebStorage) instead
+#[cfg(not(any(unix, windows)))]
+fn try_lock(_file: &File) -> std::io::Result<bool> {
+    Ok(true)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_lock() {
+        let folder = Path::new("test_cache/file_lock");
+        std::fs::create_dir_all(folder).unwrap();
+        let path = folder.join(".lock");
+
+        let lock = FileLock::acquire(&path, Duration::from_secs(1)).unwrap();
+        let err = FileLock::acquire(&path, Duration::from_millis(100)).unwrap_err();
+        assert!(matches!(err.downcast_ref::<KFError>(), Some(KFError::CacheInUse{ .. })));
+
+        drop(lock);
+        assert!(FileLock::acquire(&path, Duration::from_millis(100)).is_ok());
+    }
+}
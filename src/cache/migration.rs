@@ -0,0 +1,147 @@
+//! Versioning of the format of the cache files, and migrations of caches written by older versions of this crate
+//!
+//! Every storage records the [`SCHEMA_VERSION`] its calendars have been written with.
+//! When a cache written with an older schema is loaded, its calendars are upgraded by [`migrate_calendar`]
+//! (after the original files have been backed up), and written back with the current schema.
+//!
+//! Changing the serialized structs in a way that older caches cannot be read anymore requires to bump [`SCHEMA_VERSION`]
+//! and to append a migration to `MIGRATIONS`.
+
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::Item;
+use crate::calendar::lazy_items::ItemSummary;
+use super::codec::JSON_CODEC;
+
+/// The version of the format calendars are currently serialized with
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Upgrades a serialized calendar from a version to the next one
+type Migration = fn(&mut Value) -> Result<(), Box<dyn Error>>;
+
+/// `MIGRATIONS[n]` upgrades a calendar from version `n` to version `n + 1`
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+];
+
+/// The data that is stored alongside the calendars
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CacheHeader {
+    /// Caches written before schemas were versioned do not have this field
+    #[serde(default)]
+    pub(crate) schema_version: u32,
+    /// The name of the [`CalendarCodec`](super::codec::CalendarCodec) calendars have been written with.
+    /// Caches written before codecs were configurable do not have this field
+    #[serde(default = "json_codec")]
+    pub(crate) codec: String,
+}
+
+fn json_codec() -> String {
+    JSON_CODEC.to_string()
+}
+
+impl CacheHeader {
+    /// The header of calendars written by this version of the crate, with a given codec
+    pub(crate) fn current(codec: &str) -> Self {
+        Self { schema_version: SCHEMA_VERSION, codec: codec.to_string() }
+    }
+
+    /// Returns whether the calendars must be rewritten (because they must be migrated, or converted to `codec`).
+    ///
+    /// Returns an error if they cannot be read: they have been written by a newer version of this crate, or with another codec than JSON or `codec`
+    pub(crate) fn needs_rewrite(&self, codec: &str) -> Result<bool, Box<dyn Error>> {
+        if self.schema_version > SCHEMA_VERSION {
+            return Err(format!("This cache has been written with schema version {}, which is newer than what this version of the crate supports ({})",
+                self.schema_version, SCHEMA_VERSION).into());
+        }
+        if self.codec != codec && self.codec != JSON_CODEC {
+            return Err(format!("This cache has been written with the {} codec, and cannot be read with the {} codec", self.codec, codec).into());
+        }
+        Ok(self.schema_version < SCHEMA_VERSION || self.codec != codec)
+    }
+}
+
+/// Upgrade a serialized calendar from `from_version` to [`SCHEMA_VERSION`].
+///
+/// This is used by the storages of this crate, and can be used by custom [`CacheStorage`](crate::cache::storage::CacheStorage)s as well
+pub fn migrate_calendar(calendar: &mut Value, from_version: u32) -> Result<(), Box<dyn Error>> {
+    if from_version > SCHEMA_VERSION {
+        return Err(format!("Unable to migrate a calendar from schema version {}, which is newer than the current one ({})", from_version, SCHEMA_VERSION).into());
+    }
+    for migration in &MIGRATIONS[from_version as usize..] {
+        migration(calendar)?;
+    }
+    Ok(())
+}
+
+/// Version 0 is the format used before schemas were versioned.
+/// Every field that has been added since then has a default value, so that there is nothing to change
+fn migrate_v0_to_v1(calendar: &mut Value) -> Result<(), Box<dyn Error>> {
+    if !calendar.is_object() {
+        return Err("A calendar should be serialized as a JSON object".into());
+    }
+    Ok(())
+}
+
+/// Version 2 stores items along with their summary, so that they can be deserialized lazily (see [`ItemSummary`])
+fn migrate_v1_to_v2(calendar: &mut Value) -> Result<(), Box<dyn Error>> {
+    let items = calendar.get_mut("items")
+        .and_then(|items| items.as_object_mut())
+        .ok_or("A calendar should contain a map of items")?;
+    for value in items.values_mut() {
+        let item: Item = serde_json::from_value(value.take())?;
+        *value = serde_json::json!({
+            "summary": ItemSummary::from_item(&item),
+            "data": serde_json::to_string(&item)?,
+        });
+    }
+    Ok(())
+}
+
+/// Version 3 adds the recurrence bounds of items to their summaries, so that the summaries must be computed again
+fn migrate_v2_to_v3(calendar: &mut Value) -> Result<(), Box<dyn Error>> {
+    let items = calendar.get_mut("items")
+        .and_then(|items| items.as_object_mut())
+        .ok_or("A calendar should contain a map of items")?;
+    for value in items.values_mut() {
+        let data = value.get("data")
+            .and_then(|data| data.as_str())
+            .ok_or("An item should be stored along with its summary")?;
+        let item: Item = serde_json::from_str(data)?;
+        value["summary"] = serde_json::to_value(ItemSummary::from_item(&item))?;
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions() {
+        let legacy: CacheHeader = serde_json::from_str("{}").unwrap();
+        assert_eq!(legacy.schema_version, 0);
+        assert_eq!(legacy.codec, JSON_CODEC);
+        assert!(legacy.needs_rewrite(JSON_CODEC).unwrap());
+        assert!(!CacheHeader::current(JSON_CODEC).needs_rewrite(JSON_CODEC).unwrap());
+        assert!(CacheHeader{ schema_version: SCHEMA_VERSION + 1, codec: json_codec() }.needs_rewrite(JSON_CODEC).is_err());
+
+        // JSON caches can be converted to other codecs, but not the other way round
+        assert!(CacheHeader::current(JSON_CODEC).needs_rewrite("binary").unwrap());
+        assert!(CacheHeader::current("binary").needs_rewrite(JSON_CODEC).is_err());
+
+        let cal_url: url::Url = "https://some.server/cal/".parse().unwrap();
+        let task = crate::Task::new("A task".to_string(), false, &cal_url);
+        let mut calendar = serde_json::json!({"name": "My calendar", "items": {task.url().as_str(): Item::Task(task.clone())}});
+        migrate_calendar(&mut calendar, 0).unwrap();
+        assert_eq!(calendar["items"][task.url().as_str()]["summary"]["name"], "A task");
+        assert!(migrate_calendar(&mut Value::Null, 0).is_err());
+        assert!(migrate_calendar(&mut calendar, SCHEMA_VERSION + 1).is_err());
+    }
+}
@@ -0,0 +1,989 @@
+//! This module provides a local cache for CalDAV data
+
+pub mod storage;
+pub mod migration;
+pub mod codec;
+pub mod integrity;
+pub mod vdir;
+pub mod web;
+pub mod eviction;
+pub mod stats;
+pub mod events;
+mod archive;
+mod lock;
+
+use std::path::PathBuf;
+use std::path::Path;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use async_trait::async_trait;
+use csscolorparser::Color;
+use chrono::Utc;
+use url::Url;
+
+use crate::traits::CalDavSource;
+use crate::traits::BaseCalendar;
+use crate::traits::CompleteCalendar;
+use crate::calendar::cached_calendar::{CachedCalendar, Tombstone};
+use crate::calendar::SupportedComponents;
+use crate::calendar::{CalendarProperties, CalendarTimezone};
+use crate::client::ServerCapabilities;
+use crate::error::KFError;
+use crate::provider::audit::{AuditEntry, AuditQuery};
+use integrity::{IntegrityIssue, IntegrityReport};
+use eviction::EvictionPolicy;
+use stats::CacheStats;
+use events::{CacheEvent, CacheEventReceiver, CacheEventSender};
+use storage::{CacheLayout, CacheStorage, CompactionStats, FolderStorage, KvStorage, MemoryStorage, StorageBatch};
+
+#[cfg(feature = "local_calendar_mocks_remote_calendars")]
+use crate::mock_behaviour::MockBehaviour;
+
+/// See [`Cache::set_tombstone_retention`]
+const DEFAULT_TOMBSTONE_RETENTION: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// A CalDAV source that stores its items in a local folder (or in a single file, see [`Cache::open_kv_file`], or in any other [`CacheStorage`]).
+///
+/// It automatically updates the content of the folder when dropped (see its `Drop` implementation), but you can also manually call [`Cache::save_to_folder`]
+///
+/// Most of its methods are part of the `CalDavSource` trait implementation
+#[derive(Debug)]
+pub struct Cache {
+    storage: Mutex<Box<dyn CacheStorage>>,
+    data: CachedData,
+    /// How long deleted items can be restored
+    tombstone_retention: Duration,
+    /// Which items have their content dropped when saving
+    eviction_policy: EvictionPolicy,
+    /// Where the changes of the cache are notified, see [`Cache::subscribe`]
+    events: CacheEventSender,
+
+    /// In tests, we may add forced errors to this object
+    #[cfg(feature = "local_calendar_mocks_remote_calendars")]
+    mock_behaviour: Option<Arc<Mutex<MockBehaviour>>>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+struct CachedData {
+    #[serde(skip)]
+    calendars: HashMap<Url, Arc<Mutex<CachedCalendar>>>,
+}
+
+impl Cache {
+    /// Activate the "mocking remote source" features (i.e. tell its children calendars that they are mocked remote calendars)
+    #[cfg(feature = "local_calendar_mocks_remote_calendars")]
+    pub fn set_mock_behaviour(&mut self, mock_behaviour: Option<Arc<Mutex<MockBehaviour>>>) {
+        self.mock_behaviour = mock_behaviour;
+    }
+
+
+    /// Get the path to the cache folder
+    pub fn cache_folder() -> PathBuf {
+        return PathBuf::from(String::from("~/.config/my-tasks/cache/"))
+    }
+
+    /// Initialize a cache from the content of a valid backing folder if it exists.
+    /// Returns an error otherwise
+    pub fn from_folder(folder: &Path) -> Result<Self, KFError> {
+        Self::with_storage(Box::new(FolderStorage::new(folder)))
+    }
+
+    /// Initialize a cache that is stored in a single file (see [`KvStorage`]), rather than in a folder.
+    ///
+    /// The content of the file is loaded if it exists, otherwise the cache starts empty.
+    /// Saving only writes the calendars that have changed since the last save, in a single batch
+    pub fn open_kv_file(path: &Path) -> Result<Self, KFError> {
+        Self::with_storage(Box::new(KvStorage::open(path)?))
+    }
+
+    /// Initialize a cache stored at `path`, with a given layout (see [`CacheLayout`]).
+    ///
+    /// The content of the cache is loaded if it exists, otherwise the cache starts empty
+    pub fn open(path: &Path, layout: CacheLayout) -> Result<Self, KFError> {
+        let storage = layout.storage(path)?;
+        if path.exists() {
+            Self::with_storage(storage)
+        } else {
+            Ok(Self::new_with_storage(storage))
+        }
+    }
+
+    /// Initialize a cache from the content of a storage (that may be empty).
+    ///
+    /// This is how apps can store the cache in their own database (see [`CacheStorage`])
+    pub fn with_storage(mut storage: Box<dyn CacheStorage>) -> Result<Self, KFError> {
+        let calendars = storage.load_calendars().map_err(KFError::from_storage)?;
+        let mut cache = Self::new_with_storage(storage);
+        for cal in calendars {
+            cache.insert_calendar(cal);
+        }
+        Ok(cache)
+    }
+
+    /// Initialize a cache with the default contents
+    pub fn new(folder_path: &Path) -> Self {
+        Self::new_with_storage(Box::new(FolderStorage::new(folder_path)))
+    }
+
+    /// Initialize an empty cache that only lives in memory: nothing is written to the disk, unless [`Self::snapshot_to`] is called.
+    ///
+    /// This suits e.g. ephemeral jobs, or sessions that must not leave any trace
+    pub fn new_in_memory() -> Self {
+        Self::new_with_storage(Box::new(MemoryStorage))
+    }
+
+    /// Initialize a cache that only lives in memory (see [`Self::new_in_memory`]), with the content of a storage (e.g. a previous snapshot)
+    pub fn load_in_memory(storage: &mut dyn CacheStorage) -> Result<Self, KFError> {
+        let mut cache = Self::new_in_memory();
+        for cal in storage.load_calendars().map_err(KFError::from_storage)? {
+            cache.insert_calendar(cal);
+        }
+        Ok(cache)
+    }
+
+    fn new_with_storage(storage: Box<dyn CacheStorage>) -> Self {
+        Self{
+            storage: Mutex::new(storage),
+            data: CachedData::default(),
+            tombstone_retention: DEFAULT_TOMBSTONE_RETENTION,
+            eviction_policy: EvictionPolicy::default(),
+            events: tokio::sync::broadcast::channel(events::EVENT_CHANNEL_CAPACITY).0,
+
+            #[cfg(feature = "local_calendar_mocks_remote_calendars")]
+            mock_behaviour: None,
+        }
+    }
+
+    /// Receive an event every time the content of this cache changes, whether this is a local change or a change made by a sync.
+    ///
+    /// This is meant for apps that only refresh what has changed (rather than the whole cache) after a sync. Events that happen before this call are not received.
+    /// Receivers that do not keep up miss the oldest events (see [`tokio::sync::broadcast::Receiver::recv`]) and should then refresh everything.
+    ///
+    /// Note that items that are modified in place (e.g. with [`CompleteCalendar::get_item_by_url_mut`]) are not notified, unlike items that are replaced with [`BaseCalendar::update_item`]
+    pub fn subscribe(&self) -> CacheEventReceiver {
+        self.events.subscribe()
+    }
+
+    /// Add a calendar, whose changes will be notified to the subscribers of this cache
+    fn insert_calendar(&mut self, mut cal: CachedCalendar) {
+        cal.set_event_sender(Some(self.events.clone()));
+        self.data.calendars.insert(cal.url().clone(), Arc::new(Mutex::new(cal)));
+    }
+
+    /// Set how long deleted items (either locally or on the server) can be restored with [`Self::undelete`]. Defaults to 30 days.
+    ///
+    /// Older deleted items are forgotten when the cache is saved
+    pub fn set_tombstone_retention(&mut self, retention: Duration) {
+        self.tombstone_retention = retention;
+    }
+
+    /// Set which items should have their content dropped to save space (e.g. on mobile devices that sync calendars with years of history).
+    /// This is applied every time the cache is saved. By default, nothing is evicted.
+    ///
+    /// See [`CachedCalendar::evict`]
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        self.eviction_policy = policy;
+    }
+
+    /// Set how many previous versions of each item are kept by every calendar of this cache (see [`CachedCalendar::history`])
+    pub fn set_history_length(&self, length: usize) {
+        for cal in self.data.calendars.values() {
+            cal.lock().unwrap().set_history_length(length);
+        }
+    }
+
+    /// Restore a deleted item, whatever calendar it belonged to. It will be uploaded again at the next sync.
+    ///
+    /// See [`CachedCalendar::undelete`]
+    pub fn undelete(&self, item_url: &Url) -> Result<(), KFError> {
+        for cal in self.data.calendars.values() {
+            let mut cal = cal.lock().unwrap();
+            if cal.tombstones().contains_key(item_url) || cal.get_item_by_url_sync(item_url).is_some() {
+                return cal.undelete(item_url);
+            }
+        }
+        Err(format!("No deleted item {} is known (it may have been purged already)", item_url).into())
+    }
+
+    /// The deleted items of every calendar (either locally or on the server), along with the URL of their calendars, the most recently deleted first.
+    ///
+    /// Items stay in this trash until the tombstone retention period is over (see [`Self::set_tombstone_retention`]), or until [`Self::empty_trash`] is called
+    pub fn list_trash(&self) -> Vec<(Url, Tombstone)> {
+        let mut trash: Vec<(Url, Tombstone)> = self.data.calendars.iter()
+            .flat_map(|(cal_url, cal)| {
+                let cal = cal.lock().unwrap();
+                cal.tombstones().values()
+                    .map(|tombstone| (cal_url.clone(), tombstone.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        trash.sort_by(|(_, a), (_, b)| b.deleted_at().cmp(a.deleted_at()));
+        trash
+    }
+
+    /// Move an item from the trash back to its calendar. It will be uploaded again at the next sync.
+    ///
+    /// Unlike [`Self::undelete`], this does not cancel deletions that have not been synced yet
+    pub fn restore(&self, item_url: &Url) -> Result<(), KFError> {
+        for cal in self.data.calendars.values() {
+            let mut cal = cal.lock().unwrap();
+            if cal.tombstones().contains_key(item_url) {
+                return cal.undelete(item_url);
+            }
+        }
+        Err(format!("Item {} is not in the trash", item_url).into())
+    }
+
+    /// Permanently forget the items that have been in the trash for longer than `older_than` (use `Duration::ZERO` to empty it completely).
+    ///
+    /// This returns how many items have been removed. The storage is updated the next time the cache is saved
+    pub fn empty_trash(&self, older_than: Duration) -> usize {
+        let date = match chrono::Duration::from_std(older_than).ok().and_then(|age| Utc::now().checked_sub_signed(age)) {
+            None => return 0,
+            Some(date) => date,
+        };
+        let mut purged = 0;
+        for cal in self.data.calendars.values() {
+            let mut cal = cal.lock().unwrap();
+            let tombstones = cal.tombstones().len();
+            cal.purge_tombstones(&date);
+            purged += tombstones - cal.tombstones().len();
+        }
+        purged
+    }
+
+    /// Store the current Cache to its backing folder (or to its [`CacheStorage`])
+    ///
+    /// Note that this is automatically called when `self` is `drop`ped
+    pub fn save_to_folder(&self) -> Result<(), KFError> {
+        self.commit(None)
+    }
+
+    /// Write the cache to its storage. If `changed_calendar` is set, the other calendars have not changed since they were last written, and are left as they are stored
+    fn commit(&self, changed_calendar: Option<&Url>) -> Result<(), KFError> {
+        let (calendars, _) = self.lock_calendars_for_saving(changed_calendar);
+        let batch = StorageBatch{ calendars: calendars.iter().map(|cal| &**cal).collect(), changed_calendar: changed_calendar.cloned() };
+        self.storage.lock().unwrap().commit(batch).map_err(KFError::from_storage)
+    }
+
+    /// Write the current content of the cache to another storage (e.g. a [`FolderStorage`]), regardless of where the cache is usually saved.
+    ///
+    /// This is mostly useful for caches that live in memory (see [`Self::new_in_memory`])
+    pub fn snapshot_to(&self, storage: &mut dyn CacheStorage) -> Result<(), KFError> {
+        let (calendars, _) = self.lock_calendars_for_saving(None);
+        storage.commit(StorageBatch{ calendars: calendars.iter().map(|cal| &**cal).collect(), changed_calendar: None }).map_err(KFError::from_storage)
+    }
+
+    /// Save the cache (see [`Self::save_to_folder`]), and clean up its storage: unused files, expired tombstones, fragmented files...
+    ///
+    /// Long-lived caches should call this from time to time. This returns what has been cleaned up
+    pub fn compact(&self) -> Result<CompactionStats, KFError> {
+        let (calendars, purged_tombstones) = self.lock_calendars_for_saving(None);
+        let batch = StorageBatch{ calendars: calendars.iter().map(|cal| &**cal).collect(), changed_calendar: None };
+        let stats = self.storage.lock().unwrap().compact(batch).map_err(KFError::from_storage)?;
+        Ok(CompactionStats{ purged_tombstones, ..stats })
+    }
+
+    /// Write every calendar (with its items and its tombstones) into a single, portable zip archive at `path`, that can also be extracted as a cache folder.
+    ///
+    /// This can be used for backups, or to move a profile to another machine without syncing everything again (see [`Self::import_archive`])
+    pub fn export_archive(&self, path: &Path) -> Result<(), KFError> {
+        let (calendars, _) = self.lock_calendars_for_saving(None);
+        let calendars: Vec<&CachedCalendar> = calendars.iter().map(|cal| &**cal).collect();
+        archive::write_archive(path, &calendars).map_err(KFError::from)
+    }
+
+    /// Replace the content of this cache with the calendars of an archive written by [`Self::export_archive`] (possibly by an older version of this crate).
+    ///
+    /// The storage of this cache is only updated the next time it is saved
+    pub fn import_archive(&mut self, path: &Path) -> Result<(), KFError> {
+        let calendars = archive::read_archive(path)?;
+        self.data.calendars.clear();
+        for cal in calendars {
+            let url = cal.url().clone();
+            self.insert_calendar(cal);
+            // This only fails when there is no subscriber
+            let _ = self.events.send(CacheEvent::CalendarAdded{ calendar: url });
+        }
+        Ok(())
+    }
+
+    /// Look for inconsistencies in the content of the cache: items that cannot be read, duplicate URLs or UIDs, sync statuses
+    /// that do not match the version tags that are stored, items that their calendars do not support...
+    ///
+    /// This deserializes every item
+    pub fn check_integrity(&self) -> IntegrityReport {
+        self.check_calendars(|cal| cal.check_integrity())
+    }
+
+    /// Fix the issues [`Self::check_integrity`] finds, when it is possible without losing data (see [`IntegrityIssue::is_repairable`]).
+    ///
+    /// Items that cannot be read are moved to the quarantine of their calendars (see [`CachedCalendar::quarantined_items`]), so that they do not break syncs.
+    /// This returns every issue that has been found, and the changes are written to the storage the next time the cache is saved
+    pub fn repair(&self) -> IntegrityReport {
+        self.check_calendars(CachedCalendar::repair)
+    }
+
+    /// Figures about the content of this cache: items of every calendar, disk usage, when calendars have been synced...
+    pub fn stats(&self) -> CacheStats {
+        let mut calendars: Vec<_> = self.data.calendars.values()
+            .map(|cal| cal.lock().unwrap().stats())
+            .collect();
+        calendars.sort_by(|a, b| a.url.cmp(&b.url));
+        CacheStats {
+            calendars,
+            disk_usage: self.storage.lock().unwrap().disk_usage(),
+        }
+    }
+
+    /// The entries of the audit logs of every calendar (see [`CompleteCalendar::audit_log`]) that match a query, the oldest first
+    pub fn audit_log(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        let mut entries: Vec<AuditEntry> = self.data.calendars.values()
+            .flat_map(|cal| cal.lock().unwrap().audit_log().query(query).cloned().collect::<Vec<_>>())
+            .collect();
+        entries.sort_by_key(|entry| entry.at);
+        entries
+    }
+
+    fn check_calendars<F>(&self, mut check: F) -> IntegrityReport
+    where F: FnMut(&mut CachedCalendar) -> Vec<IntegrityIssue>
+    {
+        let mut issues = Vec::new();
+        let mut calendars_of_items: HashMap<Url, Vec<Url>> = HashMap::new();
+        for (cal_url, cal) in &self.data.calendars {
+            let mut cal = cal.lock().unwrap();
+            issues.extend(check(&mut cal));
+            for item_url in cal.get_item_urls_sync().unwrap_or_default() {
+                calendars_of_items.entry(item_url).or_default().push(cal_url.clone());
+            }
+        }
+        for (item, mut calendars) in calendars_of_items {
+            if calendars.len() > 1 {
+                calendars.sort();
+                issues.push(IntegrityIssue::DuplicateUrl{ item, calendars });
+            }
+        }
+        IntegrityReport::new(issues)
+    }
+
+    /// Lock every calendar, and purge the expired tombstones and evict the items (according to the eviction policy) of the ones that will be written,
+    /// i.e. every calendar unless `changed_calendar` is set. Returns how many tombstones have been purged
+    fn lock_calendars_for_saving(&self, changed_calendar: Option<&Url>) -> (Vec<MutexGuard<'_, CachedCalendar>>, usize) {
+        let oldest_tombstone = chrono::Duration::from_std(self.tombstone_retention).ok()
+            .and_then(|retention| Utc::now().checked_sub_signed(retention));
+
+        let mut calendars = Vec::new();
+        let mut purged_tombstones = 0;
+        for cal_mutex in self.data.calendars.values() {
+            let mut cal = cal_mutex.lock().unwrap();
+            if changed_calendar.is_some_and(|url| cal.url() != url) {
+                calendars.push(cal);
+                continue;
+            }
+            if let Some(date) = &oldest_tombstone {
+                let tombstones = cal.tombstones().len();
+                cal.purge_tombstones(date);
+                purged_tombstones += tombstones - cal.tombstones().len();
+            }
+            cal.evict(&self.eviction_policy);
+            calendars.push(cal);
+        }
+        (calendars, purged_tombstones)
+    }
+
+
+    /// Compares two Caches to check they have the same current content
+    ///
+    /// This is not a complete equality test: some attributes (sync status...) may differ. This should mostly be used in tests
+    #[cfg(any(test, feature = "integration_tests"))]
+    pub async fn has_same_observable_content_as(&self, other: &Self) -> Result<bool, KFError> {
+        let calendars_l = self.get_calendars().await?;
+        let calendars_r = other.get_calendars().await?;
+
+        if crate::utils::keys_are_the_same(&calendars_l, &calendars_r) == false {
+            log::debug!("Different keys for calendars");
+            return Ok(false);
+        }
+
+        for (calendar_url, cal_l) in calendars_l {
+            log::debug!("Comparing calendars {}", calendar_url);
+            let cal_l = cal_l.lock().unwrap();
+            let cal_r = match calendars_r.get(&calendar_url) {
+                Some(c) => c.lock().unwrap(),
+                None => return Err("should not happen, we've just tested keys are the same".into()),
+            };
+
+            // TODO: check calendars have the same names/ID/whatever
+            if cal_l.has_same_observable_content_as(&cal_r).await? == false {
+                log::debug!("Different calendars");
+                return Ok(false)
+            }
+
+        }
+        Ok(true)
+    }
+}
+
+impl Drop for Cache {
+    fn drop(&mut self) {
+        if let Err(err) = self.save_to_folder() {
+            log::error!("Unable to automatically save the cache when it's no longer required: {}", err);
+        }
+    }
+}
+
+impl Cache {
+    /// The non-async version of [`crate::traits::CalDavSource::get_calendars`]
+    pub fn get_calendars_sync(&self) -> Result<HashMap<Url, Arc<Mutex<CachedCalendar>>>, KFError> {
+        #[cfg(feature = "local_calendar_mocks_remote_calendars")]
+        self.mock_behaviour.as_ref().map_or(Ok(()), |b| b.lock().unwrap().can_get_calendars())?;
+
+        Ok(self.data.calendars.iter()
+            .map(|(url, cal)| (url.clone(), cal.clone()))
+            .collect()
+        )
+    }
+
+    /// The calendars of this cache, in the order the user has set (see [`BaseCalendar::order`](crate::traits::BaseCalendar::order)).
+    /// Calendars that have no order come last, and calendars of the same order are sorted by name
+    pub fn get_ordered_calendars_sync(&self) -> Result<Vec<Arc<Mutex<CachedCalendar>>>, KFError> {
+        let mut calendars: Vec<_> = self.get_calendars_sync()?.into_values().collect();
+        calendars.sort_by_cached_key(|cal| {
+            let cal = cal.lock().unwrap();
+            (cal.order().is_none(), cal.order(), cal.name().to_string())
+        });
+        Ok(calendars)
+    }
+
+    /// The non-async version of [`crate::traits::CalDavSource::get_calendar`]
+    pub fn get_calendar_sync(&self, url: &Url) -> Option<Arc<Mutex<CachedCalendar>>> {
+        self.data.calendars.get(url).map(|arc| arc.clone())
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<CachedCalendar> for Cache {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<CachedCalendar>>>, KFError> {
+        self.get_calendars_sync()
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<CachedCalendar>>> {
+        self.get_calendar_sync(url)
+    }
+
+    async fn create_calendar(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>) -> Result<Arc<Mutex<CachedCalendar>>, KFError> {
+        log::debug!("Inserting local calendar {}", url);
+        #[cfg(feature = "local_calendar_mocks_remote_calendars")]
+        self.mock_behaviour.as_ref().map_or(Ok(()), |b| b.lock().unwrap().can_create_calendar())?;
+
+        let mut new_calendar = CachedCalendar::new(name, url.clone(), supported_components, color);
+        new_calendar.set_event_sender(Some(self.events.clone()));
+        let arc = Arc::new(Mutex::new(new_calendar));
+
+        #[cfg(feature = "local_calendar_mocks_remote_calendars")]
+        if let Some(behaviour) = &self.mock_behaviour {
+            arc.lock().unwrap().set_mock_behaviour(Some(Arc::clone(behaviour)));
+        };
+
+        match self.data.calendars.insert(url.clone(), arc.clone()) {
+            Some(_) => Err("Attempt to insert calendar failed: there is alredy such a calendar.".into()),
+            None => {
+                let _ = self.events.send(CacheEvent::CalendarAdded{ calendar: url });
+                Ok(arc)
+            },
+        }
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<CachedCalendar>>, KFError> {
+        let arc = self.create_calendar(url, name, supported_components, color).await?;
+        {
+            // This is not a local change that should be pushed to the server
+            let mut cal = arc.lock().unwrap();
+            cal.set_timezone(timezone);
+            cal.clear_modified_properties(CalendarProperties::TIMEZONE);
+        }
+        Ok(arc)
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        Ok(self.save_to_folder()?)
+    }
+
+    async fn save_calendar(&self, url: &Url) -> Result<(), KFError> {
+        self.commit(Some(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use url::Url;
+    use crate::calendar::SupportedComponents;
+    use crate::item::{Item, SyncStatus, SyncStatusKind, VersionTag};
+    use crate::task::Task;
+
+    async fn populate_cache(cache_path: &Path) -> Cache {
+        let mut cache = Cache::new(&cache_path);
+
+        let _shopping_list = cache.create_calendar(
+            Url::parse("https://caldav.com/shopping").unwrap(),
+            "My shopping list".to_string(),
+            SupportedComponents::TODO,
+            Some(csscolorparser::parse("lime").unwrap()),
+        ).await.unwrap();
+
+        let bucket_list = cache.create_calendar(
+            Url::parse("https://caldav.com/bucket-list").unwrap(),
+            "My bucket list".to_string(),
+            SupportedComponents::TODO,
+            Some(csscolorparser::parse("#ff8000").unwrap()),
+        ).await.unwrap();
+
+        {
+            let mut bucket_list = bucket_list.lock().unwrap();
+            let cal_url = bucket_list.url().clone();
+            bucket_list.add_item(Item::Task(Task::new(
+                String::from("Attend a concert of JS Bach"), false, &cal_url
+            ))).await.unwrap();
+
+            bucket_list.add_item(Item::Task(Task::new(
+                String::from("Climb the Lighthouse of Alexandria"), true, &cal_url
+            ))).await.unwrap();
+        }
+
+        cache
+    }
+
+    #[tokio::test]
+    async fn cache_serde() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/serde_test"));
+        let cache = populate_cache(&cache_path).await;
+
+        cache.save_to_folder().unwrap();
+
+        let retrieved_cache = Cache::from_folder(&cache_path).unwrap();
+        assert_eq!(format!("{:?}", cache.storage), format!("{:?}", retrieved_cache.storage));
+        let test = cache.has_same_observable_content_as(&retrieved_cache).await;
+        println!("Equal? {:?}", test);
+        assert_eq!(test.unwrap(), true);
+    }
+
+    #[tokio::test]
+    async fn cache_sanity_checks() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/sanity_tests"));
+        let mut cache = populate_cache(&cache_path).await;
+
+        // We should not be able to add a second calendar with the same URL
+        let second_addition_same_calendar = cache.create_calendar(
+            Url::parse("https://caldav.com/shopping").unwrap(),
+            "My shopping list".to_string(),
+            SupportedComponents::TODO,
+            None,
+        ).await;
+        assert!(second_addition_same_calendar.is_err());
+    }
+
+    #[tokio::test]
+    async fn cache_calendar_order() {
+        let cache_path = PathBuf::from(String::from("test_cache/calendar_order"));
+        let mut cache = populate_cache(&cache_path).await;
+        let names = |cache: &Cache| -> Vec<String> {
+            cache.get_ordered_calendars_sync().unwrap().iter().map(|cal| cal.lock().unwrap().name().to_string()).collect()
+        };
+        // Sorted by name by default
+        assert_eq!(names(&cache), vec!["My bucket list", "My shopping list"]);
+
+        let work = cache.create_calendar(Url::parse("https://caldav.com/work").unwrap(), "Work".to_string(), SupportedComponents::TODO, None).await.unwrap();
+        work.lock().unwrap().set_order(Some(2));
+        let shopping = cache.get_calendar_sync(&Url::parse("https://caldav.com/shopping").unwrap()).unwrap();
+        shopping.lock().unwrap().set_order(Some(1));
+        assert_eq!(names(&cache), vec!["My shopping list", "Work", "My bucket list"]);
+        assert_eq!(shopping.lock().unwrap().modified_properties(), crate::calendar::CalendarProperties::ORDER);
+    }
+
+    #[tokio::test]
+    async fn cache_tombstones() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/tombstones"));
+        let mut cache = populate_cache(&cache_path).await;
+        let bucket_list = cache.get_calendar(&Url::parse("https://caldav.com/bucket-list").unwrap()).await.unwrap();
+
+        let (new_url, synced_url) = {
+            let mut bucket_list = bucket_list.lock().unwrap();
+            let mut urls: Vec<Url> = bucket_list.get_item_urls_sync().unwrap().into_iter().collect();
+            let (new_url, synced_url) = (urls.pop().unwrap(), urls.pop().unwrap());
+            bucket_list.get_item_by_url_mut_sync(&synced_url).unwrap().set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("some-tag"))));
+
+            // Never-synced items are deleted immediately, synced ones are deleted once the server has deleted them
+            bucket_list.mark_for_deletion_sync(&new_url).unwrap();
+            bucket_list.immediately_delete_item_sync(&synced_url).unwrap();
+            assert!(bucket_list.get_items_sync().unwrap().is_empty());
+            assert_eq!(bucket_list.tombstones().len(), 2);
+            assert!(bucket_list.tombstones()[&synced_url].deleted_remotely());
+            assert!(!bucket_list.tombstones()[&new_url].deleted_remotely());
+            (new_url, synced_url)
+        };
+
+        cache.undelete(&synced_url).unwrap();
+        assert!(cache.undelete(&synced_url).is_err());
+        {
+            let bucket_list = bucket_list.lock().unwrap();
+            assert_eq!(bucket_list.get_item_by_url_sync(&synced_url).unwrap().sync_status(), &SyncStatus::NotSynced);
+            assert_eq!(bucket_list.tombstones().len(), 1);
+        }
+
+        // Expired tombstones are purged when saving
+        cache.set_tombstone_retention(Duration::from_secs(0));
+        cache.save_to_folder().unwrap();
+        assert!(cache.undelete(&new_url).is_err());
+    }
+
+    #[tokio::test]
+    async fn cache_trash() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/trash"));
+        let cache = populate_cache(&cache_path).await;
+        let bucket_list_url = Url::parse("https://caldav.com/bucket-list").unwrap();
+        let bucket_list = cache.get_calendar(&bucket_list_url).await.unwrap();
+
+        let (first_url, second_url) = {
+            let mut bucket_list = bucket_list.lock().unwrap();
+            let mut urls: Vec<Url> = bucket_list.get_item_urls_sync().unwrap().into_iter().collect();
+            let (first_url, second_url) = (urls.pop().unwrap(), urls.pop().unwrap());
+            bucket_list.immediately_delete_item_sync(&first_url).unwrap();
+            bucket_list.immediately_delete_item_sync(&second_url).unwrap();
+            (first_url, second_url)
+        };
+
+        // The most recently deleted items come first
+        let trash: Vec<(Url, Url)> = cache.list_trash().into_iter()
+            .map(|(cal_url, tombstone)| (cal_url, tombstone.url().clone()))
+            .collect();
+        assert_eq!(trash, vec![(bucket_list_url.clone(), second_url.clone()), (bucket_list_url.clone(), first_url.clone())]);
+
+        cache.restore(&first_url).unwrap();
+        assert!(cache.restore(&first_url).is_err());
+        assert!(bucket_list.lock().unwrap().get_item_by_url_sync(&first_url).is_some());
+
+        assert_eq!(cache.empty_trash(Duration::from_secs(3600)), 0);
+        assert_eq!(cache.empty_trash(Duration::ZERO), 1);
+        assert!(cache.list_trash().is_empty());
+        assert!(cache.restore(&second_url).is_err());
+    }
+
+    #[tokio::test]
+    async fn cache_eviction() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut cache = Cache::new_in_memory();
+        let cal_url = Url::parse("https://caldav.com/agenda").unwrap();
+        let cal = cache.create_calendar(cal_url.clone(), "Agenda".to_string(), SupportedComponents::TODO, None).await.unwrap();
+
+        let task_due = |due: &str, sync_status: SyncStatus| {
+            let task = Task::new(format!("Due {}", due), false, &cal_url);
+            let due = ical::property::Property{ name: "DUE".to_string(), params: None, value: Some(due.to_string()) };
+            Item::Task(Task::new_with_parameters(task.name().to_string(), task.uid().to_string(), task.url().clone(), task.completion_status().clone(),
+                sync_status, None, Utc::now(), task.ical_prod_id().to_string(), vec![due]))
+        };
+        let synced = || SyncStatus::Synced(VersionTag::from(String::from("some-tag")));
+        let old_synced = task_due("20100101T090000Z", synced());
+        let old_modified = task_due("20100101T090000Z", SyncStatus::LocallyModified(VersionTag::from(String::from("some-tag"))));
+        let recent_synced = task_due(&(Utc::now() + chrono::Duration::days(1)).format("%Y%m%dT%H%M%SZ").to_string(), synced());
+        let old_url = old_synced.url().clone();
+        {
+            let mut cal = cal.lock().unwrap();
+            for item in [old_synced.clone(), old_modified, recent_synced] {
+                cal.add_item_sync(item).unwrap();
+            }
+        }
+
+        // Only old items that have been synced are evicted, when the cache is saved
+        let mut policy = EvictionPolicy::new();
+        policy.set_older_than(Some(Duration::from_secs(365 * 24 * 3600)));
+        cache.set_eviction_policy(policy);
+        cache.save_to_folder().unwrap();
+        {
+            let cal = cal.lock().unwrap();
+            assert_eq!(cal.get_items_sync().unwrap().len(), 2);
+            assert!(cal.get_item_by_url_sync(&old_url).is_none());
+            assert_eq!(cal.evicted_items()[&old_url].name, "Due 20100101T090000Z");
+            assert_eq!(cal.evicted_version_tags().len(), 1);
+        }
+
+        // Evicted items can be downloaded again (see Provider::fetch_evicted_item)
+        cal.lock().unwrap().update_item_sync(old_synced).unwrap();
+        assert!(cal.lock().unwrap().evicted_items().is_empty());
+
+        let mut policy = EvictionPolicy::new();
+        policy.set_max_items(Some(2));
+        // The oldest items are evicted first
+        assert_eq!(cal.lock().unwrap().evict(&policy), 1);
+        assert!(cal.lock().unwrap().get_item_by_url_sync(&old_url).is_none());
+    }
+
+    #[tokio::test]
+    async fn cache_stats() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/stats"));
+        let cache = populate_cache(&cache_path).await;
+        let bucket_list_url = Url::parse("https://caldav.com/bucket-list").unwrap();
+        let bucket_list = cache.get_calendar(&bucket_list_url).await.unwrap();
+        {
+            let mut bucket_list = bucket_list.lock().unwrap();
+            let url = bucket_list.get_item_urls_sync().unwrap().into_iter().next().unwrap();
+            bucket_list.get_item_by_url_mut_sync(&url).unwrap().set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("some-tag"))));
+            bucket_list.record_sync(Utc::now(), 2);
+            assert_eq!(bucket_list.count_items_with_status(SyncStatusKind::NotSynced), 1);
+            assert_eq!(bucket_list.get_items_with_status_sync(SyncStatusKind::Synced).keys().collect::<Vec<_>>(), vec![&url]);
+            assert_eq!(bucket_list.count_items_with_status(SyncStatusKind::LocallyModified), 0);
+        }
+
+        let stats = cache.stats();
+        assert_eq!(stats.calendars.len(), 2);
+        assert_eq!(stats.items(), 2);
+        assert_eq!(stats.sync_errors(), 2);
+        assert_eq!(stats.last_successful_sync(), None);
+        let bucket_list_stats = stats.calendars.iter().find(|cal| cal.url == bucket_list_url).unwrap();
+        assert_eq!(bucket_list_stats.tasks, 2);
+        assert_eq!(bucket_list_stats.sync_statuses, stats::SyncStatusCounts{ synced: 1, not_synced: 1, locally_modified: 0, locally_deleted: 0 });
+        assert_eq!(bucket_list_stats.pending_changes, 1);
+        assert!(bucket_list_stats.sync.last_attempt.is_some());
+
+        cache.save_to_folder().unwrap();
+        assert!(cache.stats().disk_usage.unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn cache_item_iterators() {
+        use futures_util::stream::StreamExt;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/iterators"));
+        let cache = populate_cache(&cache_path).await;
+        let bucket_list_url = Url::parse("https://caldav.com/bucket-list").unwrap();
+        let mut bucket_list = cache.get_calendar(&bucket_list_url).await.unwrap().lock().unwrap().clone();
+        assert_eq!(bucket_list.iter_items().count(), 2);
+
+        for (_, item) in bucket_list.iter_items_mut() {
+            item.unwrap_task_mut().set_name("Renamed".to_string());
+        }
+        let names: Vec<String> = bucket_list.stream_items()
+            .map(|(_, item)| item.name().to_string())
+            .collect().await;
+        assert_eq!(names, vec!["Renamed", "Renamed"]);
+    }
+
+    #[tokio::test]
+    async fn cache_in_memory() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let snapshot_path = PathBuf::from(String::from("test_cache/in_memory_snapshot"));
+        let _ = std::fs::remove_dir_all(&snapshot_path);
+
+        let mut cache = Cache::new_in_memory();
+        let reference = populate_cache(Path::new("test_cache/in_memory_reference")).await;
+        for (url, cal) in reference.get_calendars_sync().unwrap() {
+            let cal = cal.lock().unwrap().clone();
+            cache.data.calendars.insert(url, Arc::new(Mutex::new(cal)));
+        }
+        cache.save_to_folder().unwrap();
+        assert!(!snapshot_path.exists());
+
+        let mut snapshot = FolderStorage::new(&snapshot_path);
+        cache.snapshot_to(&mut snapshot).unwrap();
+        drop(cache);
+        let retrieved_cache = Cache::load_in_memory(&mut snapshot).unwrap();
+        assert!(retrieved_cache.has_same_observable_content_as(&reference).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn cache_compaction() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/compaction"));
+        let _ = std::fs::remove_dir_all(&cache_path);
+        let mut cache = populate_cache(&cache_path).await;
+        let bucket_list = cache.get_calendar_sync(&Url::parse("https://caldav.com/bucket-list").unwrap()).unwrap();
+        {
+            let mut bucket_list = bucket_list.lock().unwrap();
+            let url = bucket_list.get_item_urls_sync().unwrap().into_iter().next().unwrap();
+            bucket_list.immediately_delete_item_sync(&url).unwrap();
+        }
+        cache.save_to_folder().unwrap();
+
+        // Leftovers of a calendar that does not exist anymore, and of an interrupted write
+        std::fs::write(cache_path.join("removed-calendar.cal"), "{}").unwrap();
+        std::fs::write(cache_path.join("leftover.tmp"), "garbage").unwrap();
+
+        cache.set_tombstone_retention(Duration::from_secs(0));
+        let stats = cache.compact().unwrap();
+        assert_eq!(stats, CompactionStats{ removed_files: 2, purged_tombstones: 1, bytes_reclaimed: 9 });
+        assert!(!cache_path.join("removed-calendar.cal").exists());
+        assert!(Cache::from_folder(&cache_path).unwrap().has_same_observable_content_as(&cache).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn cache_kv_file() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache_path = PathBuf::from(String::from("test_cache/kv_file/cache.kv"));
+        let _ = std::fs::remove_file(&cache_path);
+
+        let mut cache = Cache::open_kv_file(&cache_path).unwrap();
+        let reference = populate_cache(Path::new("test_cache/kv_file_reference")).await;
+        for (url, cal) in reference.get_calendars_sync().unwrap() {
+            let cal = cal.lock().unwrap().clone();
+            cache.data.calendars.insert(url, Arc::new(Mutex::new(cal)));
+        }
+        cache.save_to_folder().unwrap();
+
+        // Saving again without any change does not write anything
+        let len = std::fs::metadata(&cache_path).unwrap().len();
+        cache.save_to_folder().unwrap();
+        assert_eq!(std::fs::metadata(&cache_path).unwrap().len(), len);
+        drop(cache);
+
+        let retrieved_cache = Cache::open_kv_file(&cache_path).unwrap();
+        assert!(retrieved_cache.has_same_observable_content_as(&reference).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn cache_archive() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let archive_path = PathBuf::from(String::from("test_cache/archive/cache.archive"));
+        std::fs::create_dir_all(archive_path.parent().unwrap()).unwrap();
+
+        let cache = populate_cache(Path::new("test_cache/archive_source")).await;
+        let bucket_list_url = Url::parse("https://caldav.com/bucket-list").unwrap();
+        {
+            let bucket_list = cache.get_calendar_sync(&bucket_list_url).unwrap();
+            let mut bucket_list = bucket_list.lock().unwrap();
+            let url = bucket_list.get_item_urls_sync().unwrap().into_iter().next().unwrap();
+            bucket_list.mark_for_deletion_sync(&url).unwrap();
+        }
+        cache.export_archive(&archive_path).unwrap();
+
+        let mut imported = Cache::new_in_memory();
+        imported.import_archive(&archive_path).unwrap();
+        assert!(imported.has_same_observable_content_as(&cache).await.unwrap());
+        let bucket_list = imported.get_calendar_sync(&bucket_list_url).unwrap();
+        assert_eq!(bucket_list.lock().unwrap().tombstones().len(), 1);
+
+        // An extracted archive is a cache folder
+        let extracted_path = Path::new("test_cache/archive/extracted");
+        let _ = std::fs::remove_dir_all(extracted_path);
+        zip::ZipArchive::new(std::fs::File::open(&archive_path).unwrap()).unwrap().extract(extracted_path).unwrap();
+        let extracted = Cache::from_folder(extracted_path).unwrap();
+        assert!(extracted.has_same_observable_content_as(&cache).await.unwrap());
+
+        std::fs::write(&archive_path, "not an archive").unwrap();
+        assert!(imported.import_archive(&archive_path).is_err());
+    }
+
+    #[tokio::test]
+    async fn cache_integrity() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache = populate_cache(Path::new("test_cache/integrity")).await;
+        assert!(cache.check_integrity().is_ok());
+
+        // Corrupt an item, and copy another one into a second calendar
+        let bucket_list_url = Url::parse("https://caldav.com/bucket-list").unwrap();
+        let shopping_list_url = Url::parse("https://caldav.com/shopping").unwrap();
+        let (broken_url, copied_item) = {
+            let bucket_list = cache.get_calendar_sync(&bucket_list_url).unwrap();
+            let mut bucket_list = bucket_list.lock().unwrap();
+            let mut urls: Vec<Url> = bucket_list.get_item_urls_sync().unwrap().into_iter().collect();
+            let (broken_url, copied_url) = (urls.pop().unwrap(), urls.pop().unwrap());
+            let copied_item = bucket_list.get_item_by_url_sync(&copied_url).unwrap().clone();
+
+            let mut value = serde_json::to_value(&*bucket_list).unwrap();
+            value["items"][broken_url.as_str()]["data"] = serde_json::json!("garbage");
+            *bucket_list = serde_json::from_value(value).unwrap();
+            (broken_url, copied_item)
+        };
+        let copied_url = copied_item.url().clone();
+        cache.get_calendar_sync(&shopping_list_url).unwrap().lock().unwrap().add_item_sync(copied_item).unwrap();
+
+        let report = cache.check_integrity();
+        assert_eq!(report.issues().len(), 2);
+        assert!(report.issues().contains(&IntegrityIssue::UnreadableItem{ calendar: bucket_list_url.clone(), item: broken_url.clone() }));
+        let mut calendars = vec![bucket_list_url.clone(), shopping_list_url];
+        calendars.sort();
+        assert!(report.issues().contains(&IntegrityIssue::DuplicateUrl{ item: copied_url, calendars }));
+
+        // Only the unreadable item can be repaired
+        assert_eq!(cache.repair(), report);
+        let bucket_list = cache.get_calendar_sync(&bucket_list_url).unwrap();
+        assert!(bucket_list.lock().unwrap().quarantined_items().contains_key(&broken_url));
+        let report = cache.check_integrity();
+        assert_eq!(report.issues().len(), 1);
+        assert!(!report.issues()[0].is_repairable());
+    }
+
+    #[tokio::test]
+    async fn cache_item_history() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cache = populate_cache(Path::new("test_cache/item_history")).await;
+        let bucket_list = cache.get_calendar_sync(&Url::parse("https://caldav.com/bucket-list").unwrap()).unwrap();
+        let mut bucket_list = bucket_list.lock().unwrap();
+        let url = bucket_list.get_item_urls_sync().unwrap().into_iter().next().unwrap();
+        let mut item = bucket_list.get_item_by_url_sync(&url).unwrap().clone();
+        let original_name = item.name().to_string();
+        item.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("v1"))));
+        bucket_list.update_item_sync(item.clone()).unwrap();
+
+        // A newer version is downloaded
+        item.unwrap_task_mut().set_name("Renamed on the server".to_string());
+        item.set_sync_status(SyncStatus::Synced(VersionTag::from(String::from("v2"))));
+        bucket_list.update_item_sync(item).unwrap();
+        assert_eq!(bucket_list.history(&url).len(), 2);
+        assert_eq!(bucket_list.history(&url)[0].item().name(), original_name);
+
+        bucket_list.revert_to(&url, 0).unwrap();
+        let reverted = bucket_list.get_item_by_url_sync(&url).unwrap();
+        assert_eq!(reverted.name(), original_name);
+        assert_eq!(reverted.sync_status(), &SyncStatus::LocallyModified(VersionTag::from(String::from("v2"))));
+        assert_eq!(bucket_list.history(&url)[0].item().name(), "Renamed on the server");
+        assert!(bucket_list.revert_to(&url, 10).is_err());
+
+        bucket_list.set_history_length(1);
+        assert_eq!(bucket_list.history(&url).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn cache_events() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut cache = Cache::new_in_memory();
+        let mut events = cache.subscribe();
+
+        let cal_url = Url::parse("https://caldav.com/events").unwrap();
+        let calendar = cache.create_calendar(cal_url.clone(), "Events".to_string(), SupportedComponents::TODO, None).await.unwrap();
+        assert_eq!(events.try_recv().unwrap(), CacheEvent::CalendarAdded{ calendar: cal_url.clone() });
+
+        let mut calendar = calendar.lock().unwrap();
+        let mut task = Task::new(String::from("Water the plants"), false, &cal_url);
+        let item = task.url().clone();
+        calendar.add_item_sync(Item::Task(task.clone())).unwrap();
+        task.set_name(String::from("Water the garden"));
+        calendar.update_item_sync(Item::Task(task)).unwrap();
+        calendar.mark_for_deletion_sync(&item).unwrap();
+        calendar.undelete(&item).unwrap();
+        calendar.record_sync(Utc::now(), 0);
+
+        let received: Vec<CacheEvent> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(received, vec![
+            CacheEvent::ItemAdded{ calendar: cal_url.clone(), item: item.clone() },
+            CacheEvent::ItemModified{ calendar: cal_url.clone(), item: item.clone() },
+            CacheEvent::ItemDeleted{ calendar: cal_url.clone(), item: item.clone() },
+            CacheEvent::ItemAdded{ calendar: cal_url.clone(), item },
+            CacheEvent::SyncCompleted{ calendar: cal_url, errors: 0 },
+        ]);
+    }
+}
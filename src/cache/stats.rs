@@ -0,0 +1,90 @@
+//! Figures about the content of a cache, e.g. for settings screens or health checks (see [`Cache::stats`](super::Cache::stats))
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::calendar::CalendarVersion;
+
+/// When a calendar has been synced, see [`CompleteCalendar::record_sync`](crate::traits::CompleteCalendar::record_sync)
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncRecord {
+    /// When the last sync of this calendar has ended, whether it has succeeded or not
+    pub last_attempt: Option<DateTime<Utc>>,
+    /// When the last sync of this calendar that had no error has ended
+    pub last_success: Option<DateTime<Utc>>,
+    /// How many errors have happened during the last sync of this calendar
+    pub last_errors: usize,
+    /// The version (i.e. the ctag and sync token) of the remote calendar as of the last complete sync, see [`CompleteCalendar::synced_remote_version`](crate::traits::CompleteCalendar::synced_remote_version)
+    #[serde(default)]
+    pub remote_version: Option<CalendarVersion>,
+}
+
+impl SyncRecord {
+    pub(crate) fn record(&mut self, at: DateTime<Utc>, errors: usize) {
+        self.last_attempt = Some(at);
+        if errors == 0 {
+            self.last_success = Some(at);
+        }
+        self.last_errors = errors;
+    }
+}
+
+/// How many items have each sync status
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyncStatusCounts {
+    pub synced: usize,
+    pub not_synced: usize,
+    pub locally_modified: usize,
+    pub locally_deleted: usize,
+}
+
+/// Figures about a calendar, see [`CachedCalendar::stats`](crate::calendar::cached_calendar::CachedCalendar::stats)
+#[derive(Clone, Debug, PartialEq)]
+pub struct CalendarStats {
+    pub url: Url,
+    pub name: String,
+    pub tasks: usize,
+    pub events: usize,
+    pub sync_statuses: SyncStatusCounts,
+    /// Items that are only known by some of their properties, see [`CachedCalendar::partial_items`](crate::calendar::cached_calendar::CachedCalendar::partial_items)
+    pub partial_items: usize,
+    /// Items whose content has been dropped, see [`CachedCalendar::evict`](crate::calendar::cached_calendar::CachedCalendar::evict)
+    pub evicted_items: usize,
+    /// Deleted items that can still be restored
+    pub tombstones: usize,
+    /// Items that have been removed by [`Cache::repair`](super::Cache::repair)
+    pub quarantined_items: usize,
+    /// Local changes that have not been pushed yet
+    pub pending_changes: usize,
+    pub sync: SyncRecord,
+}
+
+/// Figures about a cache, see [`Cache::stats`](super::Cache::stats)
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheStats {
+    /// The calendars, sorted by URL
+    pub calendars: Vec<CalendarStats>,
+    /// How many bytes the cache uses on the disk, if its storage can tell (see [`CacheStorage::disk_usage`](super::storage::CacheStorage::disk_usage))
+    pub disk_usage: Option<u64>,
+}
+
+impl CacheStats {
+    pub fn items(&self) -> usize {
+        self.calendars.iter().map(|cal| cal.tasks + cal.events).sum()
+    }
+
+    /// How many errors have happened during the last sync of every calendar
+    pub fn sync_errors(&self) -> usize {
+        self.calendars.iter().map(|cal| cal.sync.last_errors).sum()
+    }
+
+    /// When every calendar has been successfully synced for the last time, i.e. the oldest of their last successful syncs.
+    /// This is `None` if a calendar has never been successfully synced
+    pub fn last_successful_sync(&self) -> Option<DateTime<Utc>> {
+        let dates: Option<Vec<DateTime<Utc>>> = self.calendars.iter()
+            .map(|cal| cal.sync.last_success)
+            .collect();
+        dates?.into_iter().min()
+    }
+}
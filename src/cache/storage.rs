@@ -0,0 +1,739 @@
+//! Where a [`Cache`](crate::cache::Cache) stores its content, see [`CacheStorage`]
+
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt::Debug;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use url::Url;
+
+use crate::traits::BaseCalendar;
+use crate::calendar::cached_calendar::CachedCalendar;
+use crate::kv_store::{KvStore, WriteBatch};
+use super::lock::{FileLock, DEFAULT_LOCK_TIMEOUT};
+use super::migration::{self, CacheHeader};
+use super::codec::{CalendarCodec, JsonCodec};
+use super::vdir::{ItemFileNaming, VdirStorage};
+
+pub(crate) const MAIN_FILE: &str = "data.json";
+/// The folder where a [`FolderStorage`] prepares its files before moving them into place
+const STAGING_FOLDER: &str = ".pending";
+/// Written into the staging folder once every file of a commit has been completely written
+const COMMITTED_MARKER: &str = "COMMITTED";
+/// Locked by a [`FolderStorage`] while it reads or writes its folder
+const LOCK_FILE: &str = ".lock";
+
+/// Keys used in a [`KvStore`]
+const KV_MAIN_KEY: &str = "data";
+const KV_CALENDAR_PREFIX: &str = "calendar:";
+
+/// The content of a [`Cache`](crate::cache::Cache) that must be written, see [`CacheStorage::commit`]
+#[derive(Debug)]
+pub struct StorageBatch<'a> {
+    /// Every calendar of the cache. Stored calendars that are not part of this list must be removed
+    pub calendars: Vec<&'a CachedCalendar>,
+    /// The only calendar that has changed since the previous commit, if it is known (e.g. during a sync, that saves its progress after each step).
+    /// Storages that write calendars separately can leave the other ones as they are
+    pub changed_calendar: Option<Url>,
+}
+
+impl<'a> StorageBatch<'a> {
+    /// The calendars of this batch that must be written, i.e. all of them unless [`Self::changed_calendar`] is set
+    pub fn calendars_to_write(&self) -> impl Iterator<Item = &'a CachedCalendar> + '_ {
+        self.calendars.iter()
+            .copied()
+            .filter(move |cal| self.changed_calendar.as_ref().is_none_or(|url| cal.url() == url))
+    }
+}
+
+/// The persistence layer of a [`Cache`](crate::cache::Cache).
+///
+/// Storages should record the [`SCHEMA_VERSION`](super::migration::SCHEMA_VERSION) of the calendars they write,
+/// so that they can upgrade them with [`migrate_calendar`](super::migration::migrate_calendar) when the crate is updated.
+///
+/// The cache keeps its calendars in memory, and relies on its storage to load them at startup and to write them when it is saved
+/// (see [`Cache::with_storage`](crate::cache::Cache::with_storage)). \
+/// This crate provides a storage in a folder ([`FolderStorage`], the default), in a vdir-like folder ([`VdirStorage`]), in a single file ([`KvStorage`])
+/// and in any key-value store of strings ([`WebStorage`](super::web::WebStorage)),
+/// but apps can implement this trait to store their data in their own database, while reusing all the sync logic.
+///
+/// Calendars can be stored either as a whole (they are `Serialize` and `Deserialize`),
+/// or field by field, using the accessors of [`CachedCalendar`] and the [`CompleteCalendar`](crate::traits::CompleteCalendar) trait
+pub trait CacheStorage: Debug + Send {
+    /// Load every stored calendar, with their items
+    fn load_calendars(&mut self) -> Result<Vec<CachedCalendar>, Box<dyn Error>>;
+
+    /// Write the content of the cache.
+    ///
+    /// Implementations should write a batch all at once (e.g. in a single database transaction) whenever they can,
+    /// so that an interrupted write does not leave the storage half-updated
+    fn commit(&mut self, batch: StorageBatch<'_>) -> Result<(), Box<dyn Error>>;
+
+    /// Write the content of the cache (see [`Self::commit`]), and reclaim the space used by data that is not needed anymore
+    /// (e.g. leftovers of removed calendars, temporary files, fragmented files).
+    ///
+    /// The default implementation only commits the batch
+    fn compact(&mut self, batch: StorageBatch<'_>) -> Result<CompactionStats, Box<dyn Error>> {
+        self.commit(batch)?;
+        Ok(CompactionStats::default())
+    }
+
+    /// How many bytes this storage uses on the disk, if it is known (see [`Cache::stats`](crate::cache::Cache::stats)).
+    ///
+    /// The default implementation returns `None`
+    fn disk_usage(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// The total size of the files of a folder and its subfolders
+pub(crate) fn folder_size(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() { folder_size(&entry.path())? } else { metadata.len() };
+    }
+    Ok(size)
+}
+
+/// What has been cleaned up by [`Cache::compact`](crate::cache::Cache::compact)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    /// Files that were not used anymore, and have been removed
+    pub removed_files: usize,
+    /// Deleted items that have been forgotten, because they have been deleted for longer than the tombstone retention
+    pub purged_tombstones: usize,
+    /// How much disk space has been freed, in bytes
+    pub bytes_reclaimed: u64,
+}
+
+
+/// How a cache is laid out on the disk, see [`Cache::open`](crate::cache::Cache::open)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CacheLayout {
+    /// A folder with one file per calendar (see [`FolderStorage`]). This is the default
+    #[default]
+    FilePerCalendar,
+    /// A folder with one subfolder per calendar, that contains one iCal file per item (see [`VdirStorage`]).
+    /// Other apps that read vdirs can use these subfolders
+    FilePerItem(ItemFileNaming),
+    /// A single file (see [`KvStorage`])
+    SingleFile,
+}
+
+impl CacheLayout {
+    /// Create the storage that uses this layout at `path`
+    pub fn storage(&self, path: &Path) -> Result<Box<dyn CacheStorage>, Box<dyn Error>> {
+        Ok(match self {
+            CacheLayout::FilePerCalendar => Box::new(FolderStorage::new(path)),
+            CacheLayout::FilePerItem(naming) => Box::new(VdirStorage::new(path, *naming)),
+            CacheLayout::SingleFile => Box::new(KvStorage::open(path)?),
+        })
+    }
+}
+
+
+/// Does not store anything: the cache only lives in memory (see [`Cache::new_in_memory`](crate::cache::Cache::new_in_memory))
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryStorage;
+
+impl CacheStorage for MemoryStorage {
+    fn load_calendars(&mut self) -> Result<Vec<CachedCalendar>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+
+    fn commit(&mut self, _batch: StorageBatch<'_>) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn disk_usage(&self) -> Option<u64> {
+        Some(0)
+    }
+}
+
+
+/// Stores the cache in a folder, as one JSON file per calendar.
+///
+/// Commits are crash-safe: files are first completely written (and flushed to the disk) into a staging folder, that is then marked as committed,
+/// and only then moved into place. \
+/// A commit that has been interrupted before being marked is discarded, and one that has been interrupted after that is completed,
+/// the next time the storage is used. This way, the folder always contains the calendars of a single commit, and never a half-written file.
+///
+/// The folder is locked while it is read or written, so that several processes can share it (see [`Self::set_lock_timeout`])
+#[derive(Debug)]
+pub struct FolderStorage {
+    folder: PathBuf,
+    lock_timeout: Duration,
+    codec: Arc<dyn CalendarCodec>,
+}
+
+impl FolderStorage {
+    pub fn new(folder: &Path) -> Self {
+        Self { folder: folder.to_path_buf(), lock_timeout: DEFAULT_LOCK_TIMEOUT, codec: Arc::new(JsonCodec) }
+    }
+
+    /// Set how calendars are encoded in their files. Defaults to [`JsonCodec`].
+    ///
+    /// A folder that has been written as JSON is converted the next time it is loaded
+    pub fn set_codec(&mut self, codec: Arc<dyn CalendarCodec>) {
+        self.codec = codec;
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// Set how long to wait when another process is reading or writing the folder, before failing with a [`KFError::CacheInUse`](crate::error::KFError::CacheInUse).
+    /// Defaults to 10 seconds
+    pub fn set_lock_timeout(&mut self, timeout: Duration) {
+        self.lock_timeout = timeout;
+    }
+
+    fn lock(&self) -> Result<FileLock, Box<dyn Error>> {
+        FileLock::acquire(&self.folder.join(LOCK_FILE), self.lock_timeout)
+    }
+
+    fn load_calendar(&self, path: &Path, header: &CacheHeader) -> Result<CachedCalendar, Box<dyn Error>> {
+        decode_calendar(&std::fs::read(path)?, header, &*self.codec)
+    }
+
+    /// Copy the files of the cache into a `backup-<version or codec>` subfolder, before they are migrated
+    fn backup(&self, header: &CacheHeader) -> std::io::Result<()> {
+        let backup = self.folder.join(format!("backup-{}", backup_label(header)));
+        std::fs::create_dir_all(&backup)?;
+        for entry in std::fs::read_dir(&self.folder)? {
+            let path = entry?.path();
+            if path.is_file() && (path.extension() == Some(OsStr::new("cal")) || path.file_name() == Some(OsStr::new(MAIN_FILE))) {
+                if let Some(file_name) = path.file_name() {
+                    std::fs::copy(&path, backup.join(file_name))?;
+                }
+            }
+        }
+        log::info!("The cache in {:?} has been backed up to {:?} before being migrated", self.folder, backup);
+        Ok(())
+    }
+
+    /// Complete or discard a commit that has been interrupted
+    fn recover(&self) -> std::io::Result<()> {
+        let staging = self.folder.join(STAGING_FOLDER);
+        if !staging.exists() {
+            return Ok(());
+        }
+        if staging.join(COMMITTED_MARKER).exists() {
+            log::info!("Completing an interrupted write of the cache in {:?}", self.folder);
+            self.move_staged_files()
+        } else {
+            log::warn!("Discarding an incomplete write of the cache in {:?}", self.folder);
+            std::fs::remove_dir_all(&staging)
+        }
+    }
+
+    /// Move the files of a committed staging folder into place
+    fn move_staged_files(&self) -> std::io::Result<()> {
+        let staging = self.folder.join(STAGING_FOLDER);
+        for entry in std::fs::read_dir(&staging)? {
+            let entry = entry?;
+            if entry.file_name() != COMMITTED_MARKER {
+                std::fs::rename(entry.path(), self.folder.join(entry.file_name()))?;
+            }
+        }
+        sync_dir(&self.folder)?;
+        std::fs::remove_dir_all(&staging)
+    }
+}
+
+impl CacheStorage for FolderStorage {
+    fn load_calendars(&mut self) -> Result<Vec<CachedCalendar>, Box<dyn Error>> {
+        // Check the folder is a valid cache...
+        let main_file = self.folder.join(MAIN_FILE);
+        if !self.folder.is_dir() {
+            return Err(format!("Unable to open file {:?}: the folder does not exist", main_file).into());
+        }
+        let _lock = self.lock()?;
+        self.recover()?;
+        let header: CacheHeader = match std::fs::read(&main_file) {
+            Err(err) => return Err(format!("Unable to open file {:?}: {}", main_file, err).into()),
+            Ok(content) => serde_json::from_slice(&content)?,
+        };
+        let needs_rewrite = header.needs_rewrite(self.codec.name())?;
+        if needs_rewrite {
+            self.backup(&header)?;
+        }
+
+        // ...and load every calendar
+        let mut calendars = Vec::new();
+        for entry in std::fs::read_dir(&self.folder)? {
+            match entry {
+                Err(err) => {
+                    log::error!("Unable to read dir: {:?}", err);
+                    continue;
+                },
+                Ok(entry) => {
+                    let cal_path = entry.path();
+                    log::debug!("Considering {:?}", cal_path);
+                    if cal_path.extension() == Some(OsStr::new("cal")) {
+                        // Skipping this calendar would remove it from the cache the next time it is saved
+                        let cal = self.load_calendar(&cal_path, &header)
+                            .map_err(|err| format!("Unable to load calendar {:?} from cache: {}", cal_path, err))?;
+                        calendars.push(cal);
+                    }
+                },
+            }
+        }
+
+        if needs_rewrite {
+            log::info!("Migrating the cache in {:?} from schema version {} ({}) to {} ({})", self.folder, header.schema_version, header.codec, migration::SCHEMA_VERSION, self.codec.name());
+            self.commit_locked(StorageBatch{ calendars: calendars.iter().collect(), changed_calendar: None })?;
+        }
+        Ok(calendars)
+    }
+
+    fn commit(&mut self, batch: StorageBatch<'_>) -> Result<(), Box<dyn Error>> {
+        std::fs::create_dir_all(&self.folder)?;
+        let _lock = self.lock()?;
+        self.commit_locked(batch)
+    }
+
+    fn compact(&mut self, batch: StorageBatch<'_>) -> Result<CompactionStats, Box<dyn Error>> {
+        std::fs::create_dir_all(&self.folder)?;
+        let _lock = self.lock()?;
+        let used_files: Vec<_> = batch.calendars.iter()
+            .map(|cal| calendar_file_name(cal))
+            .collect();
+        self.commit_locked(batch)?;
+
+        // Calendar files that do not belong to any calendar anymore, and temporary files
+        let mut stats = CompactionStats::default();
+        for entry in std::fs::read_dir(&self.folder)? {
+            let entry = entry?;
+            let path = entry.path();
+            let is_orphan = path.extension() == Some(OsStr::new("cal")) && !used_files.iter().any(|name| Some(OsStr::new(name)) == path.file_name());
+            let is_temporary = path.extension() == Some(OsStr::new("tmp"));
+            if path.is_file() && (is_orphan || is_temporary) {
+                log::debug!("Removing unused file {:?}", path);
+                stats.bytes_reclaimed += entry.metadata()?.len();
+                stats.removed_files += 1;
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(stats)
+    }
+
+    fn disk_usage(&self) -> Option<u64> {
+        folder_size(&self.folder).ok()
+    }
+}
+
+impl FolderStorage {
+    /// Same as [`CacheStorage::commit`], for callers that have already locked the folder
+    fn commit_locked(&self, batch: StorageBatch<'_>) -> Result<(), Box<dyn Error>> {
+        // A previous commit may have been interrupted. It is superseded by this one anyway
+        let staging = self.folder.join(STAGING_FOLDER);
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging)?;
+        }
+        std::fs::create_dir_all(&staging)?;
+
+        // Save the general data...
+        write_synced(&staging.join(MAIN_FILE), &serde_json::to_vec(&CacheHeader::current(self.codec.name()))?)?;
+
+        // ...and each calendar that has changed
+        for cal in batch.calendars_to_write() {
+            write_synced(&staging.join(calendar_file_name(cal)), &self.codec.encode(cal)?)?;
+        }
+
+        // From now on, this commit will be completed even if we are interrupted
+        write_synced(&staging.join(COMMITTED_MARKER), b"")?;
+        sync_dir(&staging)?;
+        self.move_staged_files()?;
+
+        Ok(())
+    }
+}
+
+
+/// Stores the cache in a single [redb](https://docs.rs/redb) database file (see [`KvStore`]).
+///
+/// Only the calendars that have changed since the last commit are written, in a single transaction. \
+/// The file is locked while it is read or written (using a `.lock` file next to it), so that several processes can share it
+#[derive(Debug)]
+pub struct KvStorage {
+    store: KvStore,
+    lock_timeout: Duration,
+    codec: Arc<dyn CalendarCodec>,
+}
+
+impl KvStorage {
+    /// Open the file at `path`, or create an empty storage if this file does not exist
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let _lock = FileLock::acquire(&path.with_extension("lock"), DEFAULT_LOCK_TIMEOUT)?;
+        Ok(Self { store: KvStore::open(path)?, lock_timeout: DEFAULT_LOCK_TIMEOUT, codec: Arc::new(JsonCodec) })
+    }
+
+    /// See [`FolderStorage::set_codec`]
+    pub fn set_codec(&mut self, codec: Arc<dyn CalendarCodec>) {
+        self.codec = codec;
+    }
+
+    /// See [`FolderStorage::set_lock_timeout`]
+    pub fn set_lock_timeout(&mut self, timeout: Duration) {
+        self.lock_timeout = timeout;
+    }
+
+    pub fn path(&self) -> &Path {
+        self.store.path()
+    }
+}
+
+impl CacheStorage for KvStorage {
+    fn load_calendars(&mut self) -> Result<Vec<CachedCalendar>, Box<dyn Error>> {
+        let _lock = self.lock()?;
+        let header = match self.store.get(KV_MAIN_KEY)? {
+            Some(value) => serde_json::from_slice(&value)?,
+            // This is a brand new store
+            None => CacheHeader::current(self.codec.name()),
+        };
+        let needs_rewrite = header.needs_rewrite(self.codec.name())?;
+        if needs_rewrite {
+            let backup = self.path().with_extension(format!("{}.bak", backup_label(&header)));
+            std::fs::copy(self.path(), &backup)?;
+            log::info!("The cache in {:?} has been backed up to {:?} before being migrated", self.path(), backup);
+        }
+
+        let mut calendars = Vec::new();
+        for (key, value) in self.store.entries()? {
+            if key.starts_with(KV_CALENDAR_PREFIX) {
+                // Skipping this calendar would remove it from the cache the next time it is saved
+                let cal = decode_calendar(&value, &header, &*self.codec)
+                    .map_err(|err| format!("Unable to load calendar {} from cache: {}", key, err))?;
+                calendars.push(cal);
+            }
+        }
+
+        if needs_rewrite {
+            log::info!("Migrating the cache in {:?} from schema version {} ({}) to {} ({})", self.path(), header.schema_version, header.codec, migration::SCHEMA_VERSION, self.codec.name());
+            self.commit_locked(StorageBatch{ calendars: calendars.iter().collect(), changed_calendar: None })?;
+        }
+        Ok(calendars)
+    }
+
+    fn commit(&mut self, batch: StorageBatch<'_>) -> Result<(), Box<dyn Error>> {
+        let _lock = self.lock()?;
+        self.commit_locked(batch)
+    }
+
+    fn compact(&mut self, batch: StorageBatch<'_>) -> Result<CompactionStats, Box<dyn Error>> {
+        let _lock = self.lock()?;
+        self.commit_locked(batch)?;
+
+        let mut stats = CompactionStats::default();
+        let size_before = std::fs::metadata(self.path())?.len();
+        self.store.compact()?;
+        stats.bytes_reclaimed += size_before.saturating_sub(std::fs::metadata(self.path())?.len());
+        Ok(stats)
+    }
+
+    fn disk_usage(&self) -> Option<u64> {
+        std::fs::metadata(self.path()).ok().map(|metadata| metadata.len())
+    }
+}
+
+impl KvStorage {
+    fn lock(&self) -> Result<FileLock, Box<dyn Error>> {
+        FileLock::acquire(&self.store.path().with_extension("lock"), self.lock_timeout)
+    }
+
+    /// Same as [`CacheStorage::commit`], for callers that have already locked the file
+    fn commit_locked(&mut self, batch: StorageBatch<'_>) -> Result<(), Box<dyn Error>> {
+        let mut write_batch = WriteBatch::new();
+        write_batch.put(KV_MAIN_KEY.to_string(), serde_json::to_vec(&CacheHeader::current(self.codec.name()))?);
+
+        for cal in batch.calendars_to_write() {
+            write_batch.put(format!("{}{}", KV_CALENDAR_PREFIX, cal.url()), self.codec.encode(cal)?);
+        }
+
+        // Calendars that are not known anymore
+        for (key, _) in self.store.entries()? {
+            if let Some(url) = key.strip_prefix(KV_CALENDAR_PREFIX) {
+                let still_exists = Url::parse(url).is_ok_and(|url| batch.calendars.iter().any(|cal| cal.url() == &url));
+                if !still_exists {
+                    write_batch.delete(key);
+                }
+            }
+        }
+
+        self.store.commit(write_batch)
+    }
+}
+
+/// The name of the file a [`FolderStorage`] stores a calendar into
+pub(crate) fn calendar_file_name(cal: &CachedCalendar) -> String {
+    sanitize_filename::sanitize(cal.url().as_str()) + ".cal"
+}
+
+/// Decode a calendar that has been written with the schema version and the codec of `header`.
+///
+/// Calendars that have been written with another codec than `codec` are JSON (see [`CacheHeader::needs_rewrite`])
+pub(crate) fn decode_calendar(content: &[u8], header: &CacheHeader, codec: &dyn CalendarCodec) -> Result<CachedCalendar, Box<dyn Error>> {
+    let written_with = if header.codec == codec.name() { codec } else { &JsonCodec };
+    if header.schema_version == migration::SCHEMA_VERSION {
+        return written_with.decode(content);
+    }
+    let mut value = written_with.decode_value(content)?;
+    migration::migrate_calendar(&mut value, header.schema_version)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Identifies the backup made before rewriting calendars that have been written with `header`
+fn backup_label(header: &CacheHeader) -> String {
+    if header.schema_version < migration::SCHEMA_VERSION {
+        format!("v{}", header.schema_version)
+    } else {
+        header.codec.clone()
+    }
+}
+
+/// Write a file and flush it to the disk
+pub(crate) fn write_synced(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+/// Flush the entries of a folder (e.g. after a file has been renamed) to the disk.
+///
+/// This is a no-op on platforms where folders cannot be opened
+pub(crate) fn sync_dir(folder: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    std::fs::File::open(folder)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = folder;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::cache::Cache;
+    use crate::calendar::SupportedComponents;
+    use crate::traits::CalDavSource;
+
+    /// What an app would write to plug its own database
+    #[derive(Debug, Default)]
+    struct MemoryStorage {
+        calendars: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CacheStorage for MemoryStorage {
+        fn load_calendars(&mut self) -> Result<Vec<CachedCalendar>, Box<dyn Error>> {
+            self.calendars.lock().unwrap().iter()
+                .map(|cal| Ok(serde_json::from_str(cal)?))
+                .collect()
+        }
+
+        fn commit(&mut self, batch: StorageBatch<'_>) -> Result<(), Box<dyn Error>> {
+            let calendars = batch.calendars.iter()
+                .map(serde_json::to_string)
+                .collect::<Result<_, _>>()?;
+            *self.calendars.lock().unwrap() = calendars;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_storage() {
+        let database = Arc::new(Mutex::new(Vec::new()));
+        let url: Url = "https://caldav.com/shopping".parse().unwrap();
+
+        let mut cache = Cache::with_storage(Box::new(MemoryStorage{ calendars: database.clone() })).unwrap();
+        cache.create_calendar(url.clone(), "My shopping list".to_string(), SupportedComponents::TODO, None).await.unwrap();
+        cache.save_to_folder().unwrap();
+        assert_eq!(database.lock().unwrap().len(), 1);
+
+        let retrieved_cache = Cache::with_storage(Box::new(MemoryStorage{ calendars: database })).unwrap();
+        assert!(retrieved_cache.has_same_observable_content_as(&cache).await.unwrap());
+        assert_eq!(retrieved_cache.get_calendar(&url).await.unwrap().lock().unwrap().name(), "My shopping list");
+    }
+
+    #[test]
+    fn test_legacy_folder_migration() {
+        let folder = Path::new("test_cache/legacy_folder");
+        let _ = std::fs::remove_dir_all(folder);
+        std::fs::create_dir_all(folder).unwrap();
+
+        // This is what caches looked like before schemas were versioned
+        let url: Url = "https://caldav.com/shopping".parse().unwrap();
+        let cal = <CachedCalendar as crate::traits::CompleteCalendar>::new("My shopping list".to_string(), url, SupportedComponents::TODO, None);
+        std::fs::write(folder.join(MAIN_FILE), "{}").unwrap();
+        let file_name = sanitize_filename::sanitize(cal.url().as_str()) + ".cal";
+        std::fs::write(folder.join(&file_name), serde_json::to_vec(&cal).unwrap()).unwrap();
+
+        let mut storage = FolderStorage::new(folder);
+        assert_eq!(storage.load_calendars().unwrap().len(), 1);
+        assert!(folder.join("backup-v0").join(&file_name).exists());
+        let header: CacheHeader = serde_json::from_slice(&std::fs::read(folder.join(MAIN_FILE)).unwrap()).unwrap();
+        assert_eq!(header, CacheHeader::current(crate::cache::codec::JSON_CODEC));
+
+        // Caches written by newer versions are not loaded
+        std::fs::write(folder.join(MAIN_FILE), format!("{{\"schema_version\": {}}}", migration::SCHEMA_VERSION + 1)).unwrap();
+        assert!(storage.load_calendars().is_err());
+    }
+
+    /// A codec that is not JSON
+    #[derive(Debug)]
+    struct ReversedJsonCodec;
+
+    impl CalendarCodec for ReversedJsonCodec {
+        fn name(&self) -> &str { "reversed-json" }
+
+        fn encode(&self, calendar: &CachedCalendar) -> Result<Vec<u8>, Box<dyn Error>> {
+            Ok(serde_json::to_vec(calendar)?.into_iter().rev().collect())
+        }
+
+        fn decode(&self, data: &[u8]) -> Result<CachedCalendar, Box<dyn Error>> {
+            Ok(serde_json::from_slice(&data.iter().rev().copied().collect::<Vec<_>>())?)
+        }
+
+        fn decode_value(&self, data: &[u8]) -> Result<serde_json::Value, Box<dyn Error>> {
+            Ok(serde_json::from_slice(&data.iter().rev().copied().collect::<Vec<_>>())?)
+        }
+    }
+
+    #[test]
+    fn test_codec_conversion() {
+        let path = Path::new("test_cache/codec/cache.kv");
+        let _ = std::fs::remove_file(path);
+        let url: Url = "https://caldav.com/shopping".parse().unwrap();
+        let cal = <CachedCalendar as crate::traits::CompleteCalendar>::new("My shopping list".to_string(), url, SupportedComponents::TODO, None);
+        KvStorage::open(path).unwrap().commit(StorageBatch{ calendars: vec![&cal], changed_calendar: None }).unwrap();
+
+        // JSON caches are converted...
+        let mut storage = KvStorage::open(path).unwrap();
+        storage.set_codec(Arc::new(ReversedJsonCodec));
+        assert_eq!(storage.load_calendars().unwrap()[0].name(), "My shopping list");
+        assert!(path.with_extension("json.bak").exists());
+
+        let mut storage = KvStorage::open(path).unwrap();
+        storage.set_codec(Arc::new(ReversedJsonCodec));
+        assert_eq!(storage.load_calendars().unwrap()[0].name(), "My shopping list");
+
+        // ...but they cannot be read as JSON anymore
+        assert!(KvStorage::open(path).unwrap().load_calendars().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cbor_codec() {
+        use crate::cache::codec::{CborCodec, CBOR_CODEC};
+
+        let folder = Path::new("test_cache/cbor");
+        let _ = std::fs::remove_dir_all(folder);
+        let url: Url = "https://caldav.com/shopping".parse().unwrap();
+        let mut cache = Cache::new_in_memory();
+        let cal = cache.create_calendar(url.clone(), "My shopping list".to_string(), SupportedComponents::TODO, None).await.unwrap();
+        let mut task = crate::Task::new("Buy milk".to_string(), false, &url);
+        task.add_alarm(crate::alarm::Alarm::display(crate::alarm::AlarmTrigger::before_start(chrono::Duration::minutes(10)), "Milk"));
+        let task_url = task.url().clone();
+        cal.lock().unwrap().add_item_sync(crate::Item::Task(task)).unwrap();
+        let cal = cal.lock().unwrap().clone();
+
+        let mut storage = FolderStorage::new(folder);
+        storage.set_codec(Arc::new(CborCodec));
+        storage.commit(StorageBatch{ calendars: vec![&cal], changed_calendar: None }).unwrap();
+        let loaded = storage.load_calendars().unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].name(), "My shopping list");
+        assert_eq!(loaded[0].get_item_by_url_sync(&task_url).unwrap().unwrap_task().alarms(), cal.get_item_by_url_sync(&task_url).unwrap().unwrap_task().alarms());
+        let file = folder.join(calendar_file_name(&cal));
+        assert!(std::fs::read(&file).unwrap().len() < serde_json::to_vec(&cal).unwrap().len());
+
+        // Calendars that cannot be decoded are not silently dropped from the cache
+        std::fs::write(&file, b"garbage").unwrap();
+        assert!(storage.load_calendars().is_err());
+
+        // Calendars written with an older schema are decoded with their codec before being migrated
+        let path = Path::new("test_cache/cbor/cache.kv");
+        let mut legacy = serde_json::to_value(&cal).unwrap();
+        legacy["items"] = serde_json::json!({ task_url.as_str(): cal.get_item_by_url_sync(&task_url).unwrap() });
+        let mut legacy_data = Vec::new();
+        ciborium::into_writer(&legacy, &mut legacy_data).unwrap();
+        let mut write_batch = WriteBatch::new();
+        write_batch.put(KV_MAIN_KEY.to_string(), serde_json::to_vec(&CacheHeader{ schema_version: 1, codec: CBOR_CODEC.to_string() }).unwrap());
+        write_batch.put(format!("{}{}", KV_CALENDAR_PREFIX, url), legacy_data);
+        KvStore::open(path).unwrap().commit(write_batch).unwrap();
+
+        let mut storage = KvStorage::open(path).unwrap();
+        storage.set_codec(Arc::new(CborCodec));
+        let migrated = storage.load_calendars().unwrap();
+        assert_eq!(migrated[0].get_item_by_url_sync(&task_url).unwrap().name(), "Buy milk");
+        assert!(path.with_extension("v1.bak").exists());
+        let mut storage = KvStorage::open(path).unwrap();
+        storage.set_codec(Arc::new(CborCodec));
+        assert_eq!(storage.load_calendars().unwrap()[0].get_item_by_url_sync(&task_url).unwrap().name(), "Buy milk");
+    }
+
+    #[test]
+    fn test_interrupted_folder_commit() {
+        let folder = Path::new("test_cache/interrupted_commit");
+        let _ = std::fs::remove_dir_all(folder);
+        let url: Url = "https://caldav.com/shopping".parse().unwrap();
+        let cal = <CachedCalendar as crate::traits::CompleteCalendar>::new("My shopping list".to_string(), url, SupportedComponents::TODO, None);
+
+        let mut storage = FolderStorage::new(folder);
+        storage.commit(StorageBatch{ calendars: vec![&cal], changed_calendar: None }).unwrap();
+        assert!(!folder.join(STAGING_FOLDER).exists());
+
+        // A commit that has been interrupted before being marked is discarded...
+        std::fs::create_dir_all(folder.join(STAGING_FOLDER)).unwrap();
+        std::fs::write(folder.join(STAGING_FOLDER).join("half-written.cal"), "{\"name\": ").unwrap();
+        assert_eq!(storage.load_calendars().unwrap().len(), 1);
+        assert!(!folder.join(STAGING_FOLDER).exists());
+
+        // ...while a marked one is completed
+        let other_url: Url = "https://caldav.com/groceries".parse().unwrap();
+        let other = <CachedCalendar as crate::traits::CompleteCalendar>::new("Groceries".to_string(), other_url, SupportedComponents::TODO, None);
+        std::fs::create_dir_all(folder.join(STAGING_FOLDER)).unwrap();
+        let file_name = sanitize_filename::sanitize(other.url().as_str()) + ".cal";
+        std::fs::write(folder.join(STAGING_FOLDER).join(file_name), serde_json::to_vec(&other).unwrap()).unwrap();
+        std::fs::write(folder.join(STAGING_FOLDER).join(COMMITTED_MARKER), "").unwrap();
+        assert_eq!(storage.load_calendars().unwrap().len(), 2);
+        assert!(!folder.join(STAGING_FOLDER).exists());
+    }
+
+    #[test]
+    fn test_partial_commit() {
+        let shopping_url: Url = "https://caldav.com/shopping".parse().unwrap();
+        let groceries_url: Url = "https://caldav.com/groceries".parse().unwrap();
+        let mut shopping = <CachedCalendar as crate::traits::CompleteCalendar>::new("My shopping list".to_string(), shopping_url.clone(), SupportedComponents::TODO, None);
+        let mut groceries = <CachedCalendar as crate::traits::CompleteCalendar>::new("Groceries".to_string(), groceries_url.clone(), SupportedComponents::TODO, None);
+
+        let folder = Path::new("test_cache/partial_commit");
+        let _ = std::fs::remove_dir_all(folder);
+        let kv_path = Path::new("test_cache/partial_commit.kv");
+        let _ = std::fs::remove_file(kv_path);
+        let mut storages: Vec<Box<dyn CacheStorage>> = vec![Box::new(FolderStorage::new(folder)), Box::new(KvStorage::open(kv_path).unwrap())];
+        for storage in &mut storages {
+            storage.commit(StorageBatch{ calendars: vec![&shopping, &groceries], changed_calendar: None }).unwrap();
+        }
+
+        // Only the changed calendar is written, the other one keeps its stored content
+        shopping.add_item_sync(crate::Item::Task(crate::Task::new("Buy milk".to_string(), false, &shopping_url))).unwrap();
+        groceries.add_item_sync(crate::Item::Task(crate::Task::new("Buy eggs".to_string(), false, &groceries_url))).unwrap();
+        for storage in &mut storages {
+            storage.commit(StorageBatch{ calendars: vec![&shopping, &groceries], changed_calendar: Some(shopping_url.clone()) }).unwrap();
+            let loaded = storage.load_calendars().unwrap();
+            assert_eq!(loaded.len(), 2);
+            let item_count = |url: &Url| loaded.iter().find(|cal| cal.url() == url).unwrap().get_items_sync().unwrap().len();
+            assert_eq!(item_count(&shopping_url), 1);
+            assert_eq!(item_count(&groceries_url), 0);
+        }
+    }
+}
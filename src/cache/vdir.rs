@@ -0,0 +1,316 @@
+//! A storage that lays out calendars like a vdir, so that other software can read them (see [`VdirStorage`])
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::Item;
+use crate::item::SyncStatus;
+use crate::traits::BaseCalendar;
+use crate::calendar::cached_calendar::CachedCalendar;
+use crate::calendar::lazy_items::ItemSummary;
+use crate::kv_store::checksum;
+use super::codec::JSON_CODEC;
+use super::lock::{FileLock, DEFAULT_LOCK_TIMEOUT};
+use super::migration::{self, CacheHeader};
+use super::storage::{folder_size, sync_dir, write_synced, CacheStorage, StorageBatch};
+
+const MAIN_FILE: &str = "data.json";
+const LOCK_FILE: &str = ".lock";
+/// The file of a calendar folder that contains everything but the content of the items
+const METADATA_FILE: &str = ".kitchen-fridge.json";
+/// Files that vdir tools read to display a calendar
+pub(crate) const DISPLAYNAME_FILE: &str = "displayname";
+pub(crate) const COLOR_FILE: &str = "color";
+
+/// How a [`VdirStorage`] names the file of an item
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemFileNaming {
+    /// `<UID>.ics`, which is what most vdir tools expect.
+    /// Items whose UIDs are not valid file names, or are shared with another item, are named after their URL hash instead
+    Uid,
+    /// `<hash of the item URL>.ics`
+    UrlHash,
+}
+
+impl ItemFileNaming {
+    fn file_name(&self, item: &Item) -> String {
+        match self {
+            ItemFileNaming::Uid => sanitize_filename::sanitize(item.uid()) + ".ics",
+            ItemFileNaming::UrlHash => url_hash_file_name(item.url()),
+        }
+    }
+}
+
+fn url_hash_file_name(url: &Url) -> String {
+    format!("{:016x}.ics", checksum(url.as_str().as_bytes()))
+}
+
+/// What a [`VdirStorage`] remembers about an item, besides its iCal file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct VdirEntry {
+    file: String,
+    /// The checksum of the file, to detect changes made by other apps
+    checksum: u64,
+    summary: ItemSummary,
+}
+
+/// Stores the cache in a folder, as one subfolder per calendar that contains one iCal file per item.
+///
+/// This is the layout of a [vdir](https://vdirsyncer.pimutils.org/en/stable/vdir.html), so that other software (e.g. khal) can be pointed at the calendar subfolders.
+/// What iCal files cannot hold (sync statuses, tombstones, etc.) is stored in a hidden file of each subfolder. \
+/// Items that have been changed by another app are marked as locally modified when the storage is loaded, and new iCal files are added as new items.
+/// Items whose files have been removed are forgotten, and will be downloaded again at the next sync if they still exist on the server.
+///
+/// Unlike [`FolderStorage`](super::storage::FolderStorage), files are replaced one by one, so that only changed items are written.
+#[derive(Debug)]
+pub struct VdirStorage {
+    folder: PathBuf,
+    naming: ItemFileNaming,
+    lock_timeout: Duration,
+}
+
+impl VdirStorage {
+    pub fn new(folder: &Path, naming: ItemFileNaming) -> Self {
+        Self { folder: folder.to_path_buf(), naming, lock_timeout: DEFAULT_LOCK_TIMEOUT }
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// See [`FolderStorage::set_lock_timeout`](super::storage::FolderStorage::set_lock_timeout)
+    pub fn set_lock_timeout(&mut self, timeout: Duration) {
+        self.lock_timeout = timeout;
+    }
+
+    fn lock(&self) -> Result<FileLock, Box<dyn Error>> {
+        FileLock::acquire(&self.folder.join(LOCK_FILE), self.lock_timeout)
+    }
+
+    fn calendar_folder(&self, cal: &CachedCalendar) -> PathBuf {
+        self.folder.join(sanitize_filename::sanitize(cal.url().as_str()))
+    }
+
+    fn load_calendar(&self, cal_folder: &Path, header: &CacheHeader) -> Result<CachedCalendar, Box<dyn Error>> {
+        let mut value: serde_json::Value = serde_json::from_slice(&std::fs::read(cal_folder.join(METADATA_FILE))?)?;
+        let cal_url: Url = serde_json::from_value(value["url"].clone())?;
+        let entries: HashMap<Url, VdirEntry> = serde_json::from_value(value["items"].take())?;
+
+        let mut items = serde_json::Map::new();
+        let mut known_files = HashSet::new();
+        for (url, entry) in entries {
+            known_files.insert(entry.file.clone());
+            let content = match std::fs::read_to_string(cal_folder.join(&entry.file)) {
+                Ok(content) => content,
+                Err(_) => {
+                    log::warn!("The file of item {} has been removed from {:?}", url, cal_folder);
+                    continue;
+                },
+            };
+            let sync_status = match entry.summary.sync_status {
+                SyncStatus::Synced(tag) if checksum(content.as_bytes()) != entry.checksum => {
+                    log::info!("Item {} has been modified by another app", url);
+                    SyncStatus::LocallyModified(tag)
+                },
+                sync_status => sync_status,
+            };
+            let item = crate::ical::parse(&content, url.clone(), sync_status)?;
+            items.insert(url.to_string(), stored_item(&item)?);
+        }
+
+        // Items that have been created by other apps
+        for dir_entry in std::fs::read_dir(cal_folder)? {
+            let path = dir_entry?.path();
+            let file_name = match path.file_name().and_then(OsStr::to_str) {
+                Some(file_name) if path.extension() == Some(OsStr::new("ics")) && !known_files.contains(file_name) => file_name,
+                _ => continue,
+            };
+            let url = cal_url.join(file_name)?;
+            log::info!("Adding item {} that has been created by another app", url);
+            let item = crate::ical::parse(&std::fs::read_to_string(&path)?, url.clone(), SyncStatus::NotSynced)?;
+            items.insert(url.to_string(), stored_item(&item)?);
+        }
+
+        value["items"] = serde_json::Value::Object(items);
+        migration::migrate_calendar(&mut value, header.schema_version)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Write the files of a calendar that have changed since the last commit
+    fn commit_calendar(&self, cal: &CachedCalendar) -> Result<(), Box<dyn Error>> {
+        let cal_folder = self.calendar_folder(cal);
+        std::fs::create_dir_all(&cal_folder)?;
+        let mut previous_entries: HashMap<Url, VdirEntry> = match std::fs::read(cal_folder.join(METADATA_FILE)) {
+            Err(_) => HashMap::new(),
+            Ok(content) => {
+                let mut value: serde_json::Value = serde_json::from_slice(&content)?;
+                serde_json::from_value(value["items"].take())?
+            },
+        };
+
+        let mut entries = HashMap::new();
+        let mut used_files = HashSet::new();
+        for (url, item) in cal.get_items_sync()? {
+            let previous = previous_entries.remove(&url);
+            let mut file = match &previous {
+                Some(previous) => previous.file.clone(),
+                None => self.naming.file_name(item),
+            };
+            if used_files.contains(&file) {
+                file = url_hash_file_name(&url);
+            }
+
+            let content = crate::ical::build_from(item)?;
+            let checksum = checksum(content.as_bytes());
+            let path = cal_folder.join(&file);
+            let is_unchanged = previous.is_some_and(|previous| previous.file == file && previous.checksum == checksum) && path.exists();
+            if !is_unchanged {
+                write_atomically(&path, content.as_bytes())?;
+            }
+            used_files.insert(file.clone());
+            entries.insert(url, VdirEntry{ file, checksum, summary: ItemSummary::from_item(item) });
+        }
+
+        // Items that have been removed
+        for entry in previous_entries.values() {
+            if !used_files.contains(&entry.file) {
+                let _ = std::fs::remove_file(cal_folder.join(&entry.file));
+            }
+        }
+
+        let mut value = serde_json::to_value(cal)?;
+        value["items"] = serde_json::to_value(entries)?;
+        write_atomically(&cal_folder.join(METADATA_FILE), &serde_json::to_vec(&value)?)?;
+        write_atomically(&cal_folder.join(DISPLAYNAME_FILE), cal.name().as_bytes())?;
+        match cal.color() {
+            Some(color) => write_atomically(&cal_folder.join(COLOR_FILE), color.to_hex_string().as_bytes())?,
+            None => { let _ = std::fs::remove_file(cal_folder.join(COLOR_FILE)); },
+        }
+        Ok(())
+    }
+}
+
+impl CacheStorage for VdirStorage {
+    fn load_calendars(&mut self) -> Result<Vec<CachedCalendar>, Box<dyn Error>> {
+        let main_file = self.folder.join(MAIN_FILE);
+        if !self.folder.is_dir() {
+            return Err(format!("Unable to open file {:?}: the folder does not exist", main_file).into());
+        }
+        let _lock = self.lock()?;
+        let header: CacheHeader = match std::fs::read(&main_file) {
+            Err(err) => return Err(format!("Unable to open file {:?}: {}", main_file, err).into()),
+            Ok(content) => serde_json::from_slice(&content)?,
+        };
+        header.needs_rewrite(JSON_CODEC)?;
+
+        let mut calendars = Vec::new();
+        for entry in std::fs::read_dir(&self.folder)? {
+            let cal_folder = entry?.path();
+            if cal_folder.join(METADATA_FILE).is_file() {
+                match self.load_calendar(&cal_folder, &header) {
+                    Err(err) => {
+                        log::error!("Unable to load calendar {:?} from cache: {:?}", cal_folder, err);
+                        continue;
+                    },
+                    Ok(cal) => calendars.push(cal),
+                }
+            }
+        }
+        Ok(calendars)
+    }
+
+    fn commit(&mut self, batch: StorageBatch<'_>) -> Result<(), Box<dyn Error>> {
+        std::fs::create_dir_all(&self.folder)?;
+        let _lock = self.lock()?;
+        write_atomically(&self.folder.join(MAIN_FILE), &serde_json::to_vec(&CacheHeader::current(JSON_CODEC))?)?;
+
+        for cal in batch.calendars_to_write() {
+            self.commit_calendar(cal)?;
+        }
+        let used_folders: HashSet<PathBuf> = batch.calendars.iter().map(|cal| self.calendar_folder(cal)).collect();
+
+        // Calendars that are not known anymore
+        for entry in std::fs::read_dir(&self.folder)? {
+            let cal_folder = entry?.path();
+            if cal_folder.join(METADATA_FILE).is_file() && !used_folders.contains(&cal_folder) {
+                log::debug!("Removing calendar folder {:?}", cal_folder);
+                std::fs::remove_dir_all(&cal_folder)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn disk_usage(&self) -> Option<u64> {
+        folder_size(&self.folder).ok()
+    }
+}
+
+/// The format of the items of a serialized [`CachedCalendar`]
+fn stored_item(item: &Item) -> Result<serde_json::Value, Box<dyn Error>> {
+    Ok(serde_json::json!({
+        "summary": ItemSummary::from_item(item),
+        "data": serde_json::to_string(item)?,
+    }))
+}
+
+/// Replace a file, so that readers never see it half-written
+pub(crate) fn write_atomically(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    write_synced(&tmp_path, content)?;
+    std::fs::rename(&tmp_path, path)?;
+    match path.parent() {
+        Some(parent) => sync_dir(parent),
+        None => Ok(()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Task;
+    use crate::calendar::SupportedComponents;
+    use crate::item::VersionTag;
+    use crate::traits::CompleteCalendar;
+
+    #[test]
+    fn test_vdir_storage() {
+        let folder = Path::new("test_cache/vdir");
+        let _ = std::fs::remove_dir_all(folder);
+
+        let url: Url = "https://caldav.com/shopping/".parse().unwrap();
+        let mut cal = CachedCalendar::new("My shopping list".to_string(), url.clone(), SupportedComponents::TODO, None);
+        let mut task = Task::new("Buy milk".to_string(), false, &url);
+        task.set_sync_status(SyncStatus::Synced(VersionTag::from("some-tag".to_string())));
+        let task_url = task.url().clone();
+        let uid = task.uid().to_string();
+        let file_name = sanitize_filename::sanitize(&uid) + ".ics";
+        cal.add_item_sync(Item::Task(task)).unwrap();
+
+        let mut storage = VdirStorage::new(folder, ItemFileNaming::Uid);
+        storage.commit(StorageBatch{ calendars: vec![&cal], changed_calendar: None }).unwrap();
+        let cal_folder = storage.calendar_folder(&cal);
+        assert_eq!(std::fs::read_to_string(cal_folder.join(DISPLAYNAME_FILE)).unwrap(), "My shopping list");
+        assert!(cal_folder.join(&file_name).is_file());
+
+        let loaded = storage.load_calendars().unwrap();
+        assert_eq!(loaded[0].get_item_by_url_sync(&task_url).unwrap().sync_status(), &SyncStatus::Synced(VersionTag::from("some-tag".to_string())));
+
+        // Changes made by other apps
+        let content = std::fs::read_to_string(cal_folder.join(&file_name)).unwrap();
+        std::fs::write(cal_folder.join(&file_name), content.replace("Buy milk", "Buy oat milk")).unwrap();
+        std::fs::write(cal_folder.join("new-task.ics"), content.replace("Buy milk", "Buy bread").replace(&uid, "other-uid")).unwrap();
+        let loaded = storage.load_calendars().unwrap();
+        let item = loaded[0].get_item_by_url_sync(&task_url).unwrap();
+        assert_eq!(item.name(), "Buy oat milk");
+        assert_eq!(item.sync_status(), &SyncStatus::LocallyModified(VersionTag::from("some-tag".to_string())));
+        let new_item = loaded[0].get_item_by_url_sync(&url.join("new-task.ics").unwrap()).unwrap();
+        assert_eq!(new_item.sync_status(), &SyncStatus::NotSynced);
+    }
+}
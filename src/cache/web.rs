@@ -0,0 +1,228 @@
+//! A storage for apps that keep their data in a key-value store of strings rather than in files (see [`WebStorage`])
+//!
+//! Such stores include the `localStorage` of browsers (see `LocalStorage`, that is only available when building for `wasm32-unknown-unknown`).
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Debug;
+
+use url::Url;
+
+use crate::traits::BaseCalendar;
+use crate::calendar::cached_calendar::CachedCalendar;
+use super::codec::{JsonCodec, JSON_CODEC};
+use super::migration::{self, CacheHeader};
+use super::storage::{decode_calendar, CacheStorage, StorageBatch};
+
+/// Keys used in a [`StringStore`], after the prefix of the [`WebStorage`]
+const MAIN_KEY: &str = "data";
+const CALENDAR_PREFIX: &str = "calendar:";
+
+/// A synchronous key-value store of strings, such as the [`localStorage`](https://developer.mozilla.org/en-US/docs/Web/API/Window/localStorage) of a browser (see `LocalStorage`)
+pub trait StringStore: Debug + Send {
+    fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>>;
+    fn set(&mut self, key: &str, value: &str) -> Result<(), Box<dyn Error>>;
+    fn remove(&mut self, key: &str) -> Result<(), Box<dyn Error>>;
+    /// Every key of the store
+    fn keys(&self) -> Result<Vec<String>, Box<dyn Error>>;
+}
+
+impl StringStore for BTreeMap<String, String> {
+    fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(BTreeMap::get(self, key).cloned())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        self.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), Box<dyn Error>> {
+        BTreeMap::remove(self, key);
+        Ok(())
+    }
+
+    fn keys(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(BTreeMap::keys(self).cloned().collect())
+    }
+}
+
+
+/// Stores the cache in a [`StringStore`], as one JSON value per calendar.
+///
+/// Every key is prefixed, so that several caches (or other data of the app) can share the same store. \
+/// Such stores cannot write several values at once: calendars are written before the header that tells which schema they use,
+/// and removed calendars are only deleted after that, so that an interrupted commit leaves every calendar readable.
+#[derive(Debug)]
+pub struct WebStorage<S: StringStore> {
+    store: S,
+    prefix: String,
+}
+
+impl<S: StringStore> WebStorage<S> {
+    /// Store the cache in `store`, under keys that start with `prefix` (e.g. `"kitchen-fridge:"`)
+    pub fn new(store: S, prefix: &str) -> Self {
+        Self { store, prefix: prefix.to_string() }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn main_key(&self) -> String {
+        format!("{}{}", self.prefix, MAIN_KEY)
+    }
+
+    fn calendar_prefix(&self) -> String {
+        format!("{}{}", self.prefix, CALENDAR_PREFIX)
+    }
+}
+
+impl<S: StringStore> CacheStorage for WebStorage<S> {
+    fn load_calendars(&mut self) -> Result<Vec<CachedCalendar>, Box<dyn Error>> {
+        let header = match self.store.get(&self.main_key())? {
+            Some(value) => serde_json::from_str(&value)?,
+            // This is a brand new store
+            None => CacheHeader::current(JSON_CODEC),
+        };
+        let needs_rewrite = header.needs_rewrite(JSON_CODEC)?;
+
+        let calendar_prefix = self.calendar_prefix();
+        let mut calendars = Vec::new();
+        for key in self.store.keys()? {
+            if !key.starts_with(&calendar_prefix) {
+                continue;
+            }
+            let value = match self.store.get(&key)? {
+                None => continue,
+                Some(value) => value,
+            };
+            // Skipping this calendar would remove it from the cache the next time it is saved
+            let cal = decode_calendar(value.as_bytes(), &header, &JsonCodec)
+                .map_err(|err| format!("Unable to load calendar {} from cache: {}", key, err))?;
+            calendars.push(cal);
+        }
+
+        if needs_rewrite {
+            log::info!("Migrating the cache in {:?} from schema version {} to {}", self.prefix, header.schema_version, migration::SCHEMA_VERSION);
+            self.commit(StorageBatch{ calendars: calendars.iter().collect(), changed_calendar: None })?;
+        }
+        Ok(calendars)
+    }
+
+    fn commit(&mut self, batch: StorageBatch<'_>) -> Result<(), Box<dyn Error>> {
+        let calendar_prefix = self.calendar_prefix();
+        for cal in batch.calendars_to_write() {
+            self.store.set(&format!("{}{}", calendar_prefix, cal.url()), &serde_json::to_string(cal)?)?;
+        }
+        self.store.set(&self.main_key(), &serde_json::to_string(&CacheHeader::current(JSON_CODEC))?)?;
+
+        // Calendars that are not known anymore
+        for key in self.store.keys()? {
+            if let Some(url) = key.strip_prefix(&calendar_prefix) {
+                let still_exists = Url::parse(url).is_ok_and(|url| batch.calendars.iter().any(|cal| cal.url() == &url));
+                if !still_exists {
+                    self.store.remove(&key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Browsers limit the size of their storages (usually to a few megabytes per site), that is why this counts the bytes of the keys and values of this cache
+    fn disk_usage(&self) -> Option<u64> {
+        let keys = self.store.keys().ok()?;
+        let mut size = 0;
+        for key in keys.iter().filter(|key| key.starts_with(&self.prefix)) {
+            size += key.len() + self.store.get(key).ok()??.len();
+        }
+        Some(size as u64)
+    }
+}
+
+
+/// The `localStorage` of the browser the app runs in
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocalStorage;
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorage {
+    /// The storage is fetched at every call, because JS objects cannot be sent across threads
+    fn storage() -> Result<web_sys::Storage, Box<dyn Error>> {
+        let window = web_sys::window().ok_or("No browser window")?;
+        match window.local_storage() {
+            Ok(Some(storage)) => Ok(storage),
+            _ => Err("The localStorage is not available".into()),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl StringStore for LocalStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Self::storage()?.get_item(key).map_err(|err| format!("Unable to read {} from the localStorage: {:?}", key, err).into())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        Self::storage()?.set_item(key, value).map_err(|err| format!("Unable to write {} into the localStorage (it may be full): {:?}", key, err).into())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), Box<dyn Error>> {
+        Self::storage()?.remove_item(key).map_err(|err| format!("Unable to remove {} from the localStorage: {:?}", key, err).into())
+    }
+
+    fn keys(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let storage = Self::storage()?;
+        let len = storage.length().map_err(|err| format!("Unable to list the localStorage: {:?}", err))?;
+        let mut keys = Vec::new();
+        for i in 0..len {
+            if let Ok(Some(key)) = storage.key(i) {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::Cache;
+    use crate::calendar::SupportedComponents;
+    use crate::traits::CalDavSource;
+
+    #[tokio::test]
+    async fn test_web_storage() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let mut cache = Cache::new_in_memory();
+        let url_a: Url = "https://some.calend.ar/a/".parse().unwrap();
+        let url_b: Url = "https://some.calend.ar/b/".parse().unwrap();
+        cache.create_calendar(url_a.clone(), "A".to_string(), SupportedComponents::TODO, None).await.unwrap();
+        cache.create_calendar(url_b.clone(), "B".to_string(), SupportedComponents::TODO, None).await.unwrap();
+
+        let mut store = BTreeMap::new();
+        store.insert("other app data".to_string(), "untouched".to_string());
+        let mut storage = WebStorage::new(store, "kf:");
+        cache.snapshot_to(&mut storage).unwrap();
+        assert_eq!(storage.store().len(), 4);
+        assert!(storage.disk_usage().unwrap() > 0);
+
+        let reloaded = Cache::load_in_memory(&mut storage).unwrap();
+        assert!(cache.has_same_observable_content_as(&reloaded).await.unwrap());
+
+        // Removed calendars are removed from the store, and other keys are left untouched
+        let cal_a = cache.get_calendar_sync(&url_a).unwrap().lock().unwrap().clone();
+        storage.commit(StorageBatch{ calendars: vec![&cal_a], changed_calendar: None }).unwrap();
+        assert_eq!(storage.store().len(), 3);
+        assert_eq!(storage.store().get("other app data").unwrap(), "untouched");
+        let reloaded = Cache::load_in_memory(&mut storage).unwrap();
+        assert_eq!(reloaded.get_calendars_sync().unwrap().keys().collect::<Vec<_>>(), vec![&url_a]);
+    }
+}
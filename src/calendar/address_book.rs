@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use futures_util::stream::{self, StreamExt};
+use reqwest::{Method, Response, StatusCode, header::CONTENT_TYPE, header::CONTENT_LENGTH, header::ETAG};
+use csscolorparser::Color;
+use url::Url;
+
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::calendar::remote_calendar::{sendable, SendError, CALENDAR_VERSION_BODY};
+use crate::item::Item;
+use crate::item::VersionTag;
+use crate::item::SyncStatus;
+use crate::resource::Resource;
+use crate::provider::filter::SyncFilter;
+use crate::error::KFError;
+use crate::utils::{find_elem, same_resource};
+
+static CONTACTS_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:">
+        <d:prop>
+            <d:getetag />
+            <d:resourcetype />
+        </d:prop>
+    </d:propfind>
+"#;
+
+static MULTIGET_BODY_PREFIX: &str = r#"
+    <card:addressbook-multiget xmlns:d="DAV:" xmlns:card="urn:ietf:params:xml:ns:carddav">
+        <d:prop>
+            <d:getetag />
+            <card:address-data />
+        </d:prop>
+"#;
+static MULTIGET_BODY_SUFFIX: &str = r#"
+    </card:addressbook-multiget>
+"#;
+
+
+
+/// A CardDAV address book (see [RFC 6352](https://datatracker.ietf.org/doc/html/rfc6352)), created by an [`AddressBooks`](crate::client::carddav::AddressBooks) source.
+///
+/// Its items are [`Contact`](crate::contact::Contact)s, i.e. vCards. Address books have no color, time zone or default alarms: changing them is refused with a [`KFError::Forbidden`].
+#[derive(Debug)]
+pub struct AddressBook {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+    description: Option<String>,
+
+    cached_version_tags: Mutex<Option<HashMap<Url, VersionTag>>>,
+}
+
+impl AddressBook {
+    /// Set the description of this address book, as reported by the server
+    pub(crate) fn set_description(&mut self, description: Option<String>) {
+        self.description = description;
+    }
+
+    /// Get the new version tag of a contact that has just been written (see `RemoteCalendar::version_tag_after_write`)
+    async fn version_tag_after_write(&self, response: &Response, item_url: &Url) -> Result<VersionTag, KFError> {
+        if let Some(etag) = response.headers().get(ETAG) {
+            return Ok(VersionTag::from(etag.to_str()?.to_string()));
+        }
+
+        log::debug!("No ETag in the response headers for {}, asking for it", item_url);
+        self.cached_version_tags.lock().unwrap().take();
+        self.get_item_version_tags().await?
+            .remove(item_url)
+            .ok_or_else(|| format!("No ETag in the response for item {}", item_url).into())
+    }
+
+    /// Upload a contact. `old_etag` is the version tag the server must still have, or `None` for new contacts
+    async fn put_contact(&self, item: &Item, old_etag: Option<&VersionTag>) -> Result<SyncStatus, SendError> {
+        let vcard = crate::ical::build_from(item).map_err(sendable)?;
+
+        let request = self.resource.request(Method::PUT, item.url().clone());
+        let request = match old_etag {
+            None => request.header("If-None-Match", "*"),
+            Some(etag) => request.header("If-Match", etag.as_str()),
+        };
+        let request = request
+            .header(CONTENT_TYPE, "text/vcard; charset=utf-8")
+            .header(CONTENT_LENGTH, vcard.len())
+            .body(vcard);
+        let response = self.resource.send(request).await.map_err(sendable)?;
+
+        match response.status() {
+            StatusCode::PRECONDITION_FAILED if old_etag.is_some() => {
+                *self.cached_version_tags.lock().unwrap() = None;
+                return Err(KFError::Conflict{ url: item.url().clone() }.into());
+            },
+            StatusCode::INSUFFICIENT_STORAGE => return Err(KFError::InsufficientStorage{ url: item.url().clone() }.into()),
+            StatusCode::FORBIDDEN => return Err(KFError::Forbidden{ url: item.url().clone() }.into()),
+            status if !status.is_success() => return Err(Box::new(KFError::from_status(status, item.url().clone()))),
+            _ => (),
+        }
+
+        let vtag = self.version_tag_after_write(&response, item.url()).await?;
+        Ok(SyncStatus::Synced(vtag))
+    }
+
+    async fn put_new_item(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        self.put_contact(item, None).await
+    }
+
+    async fn put_changed_item(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let old_etag = match item.sync_status() {
+            SyncStatus::NotSynced => return Err("Cannot update an item that has not been synced already".into()),
+            SyncStatus::Synced(_) => return Err("Cannot update an item that has not changed".into()),
+            SyncStatus::LocallyModified(etag) => etag,
+            SyncStatus::LocallyDeleted(etag) => etag,
+        };
+        self.put_contact(item, Some(old_etag)).await
+    }
+
+    async fn delete_remote_item(&self, item_url: &Url) -> Result<(), SendError> {
+        let request = self.resource.request(Method::DELETE, item_url.clone());
+        let response = self.resource.send(request).await.map_err(sendable)?;
+
+        match response.status() {
+            StatusCode::FORBIDDEN => Err(KFError::Forbidden{ url: item_url.clone() }.into()),
+            status if !status.is_success() => Err(Box::new(KFError::from_status(status, item_url.clone()))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Change properties of this address book on the server. `update` is the content of a `<d:propertyupdate>` element
+    async fn proppatch(&self, update: &str) -> Result<(), KFError> {
+        let url = self.resource.url();
+        let body = format!(r#"<?xml version="1.0" encoding="utf-8" ?>
+            <d:propertyupdate xmlns:d="DAV:" xmlns:card="urn:ietf:params:xml:ns:carddav">
+                {}
+            </d:propertyupdate>
+            "#, update);
+        let request = self.resource.request(Method::from_bytes(b"PROPPATCH")?, url.clone())
+            .header(CONTENT_TYPE, "application/xml")
+            .body(body);
+        let response = self.resource.send(request).await?;
+
+        match response.status() {
+            StatusCode::FORBIDDEN => Err(KFError::Forbidden{ url: url.clone() }),
+            status if !status.is_success() => Err(KFError::from_status(status, url.clone())),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for AddressBook {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.put_new_item(&item).await.map_err(KFError::from)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.put_changed_item(&item).await.map_err(KFError::from)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for AddressBook {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        Self {
+            name, resource, supported_components, color,
+            description: None,
+            cached_version_tags: Mutex::new(None),
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        if let Some(map) = &*self.cached_version_tags.lock().unwrap() {
+            log::debug!("Version tags are already cached.");
+            return Ok(map.clone());
+        };
+
+        let responses = crate::client::sub_request_and_extract_elems(&self.resource, "PROPFIND", CONTACTS_BODY.to_string(), "response").await?;
+        let mut items = HashMap::new();
+        for response in responses {
+            // The address book itself is listed as well
+            let is_collection = find_elem(&response, "resourcetype")
+                .map(|types| types.children().any(|child| child.name() == "collection"))
+                .unwrap_or(false);
+            if is_collection {
+                continue;
+            }
+            let item_url = match find_elem(&response, "href") {
+                None => {
+                    log::warn!("Unable to extract HREF");
+                    continue;
+                },
+                Some(href) => self.resource.combine(&href.text()).url().clone(),
+            };
+            match find_elem(&response, "getetag") {
+                None => log::warn!("Unable to extract ETAG for item {}, ignoring it", item_url),
+                Some(etag) => { items.insert(item_url, VersionTag::from(etag.text())); },
+            }
+        }
+
+        *self.cached_version_tags.lock().unwrap() = Some(items.clone());
+        Ok(items)
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        // Contacts have no dates: they are either all synced, or none of them
+        if !filter.components.contains(SupportedComponents::CONTACT) {
+            return Ok(HashMap::new());
+        }
+        self.get_item_version_tags().await
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        let text = crate::client::sub_request(&self.resource, "PROPFIND", CALENDAR_VERSION_BODY.to_string(), 0).await?;
+        Ok(CalendarVersion::from_xml(&text.parse()?))
+    }
+
+    async fn update_color(&mut self, _color: Option<Color>) -> Result<(), KFError> {
+        Err(KFError::Forbidden{ url: self.resource.url().clone() })
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(KFError::Forbidden{ url: self.resource.url().clone() })
+    }
+
+    async fn update_description(&mut self, description: Option<String>) -> Result<(), KFError> {
+        let update = match &description {
+            Some(description) => format!("<d:set><d:prop><card:addressbook-description>{}</card:addressbook-description></d:prop></d:set>", crate::utils::xml_escape(description)),
+            None => "<d:remove><d:prop><card:addressbook-description/></d:prop></d:remove>".to_string(),
+        };
+        self.proppatch(&update).await?;
+        self.description = description;
+        Ok(())
+    }
+
+    async fn update_timezone(&mut self, _timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        Err(KFError::Forbidden{ url: self.resource.url().clone() })
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        let update = format!("<d:set><d:prop><d:displayname>{}</d:displayname></d:prop></d:set>", crate::utils::xml_escape(&name));
+        self.proppatch(&update).await?;
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(KFError::Forbidden{ url: self.resource.url().clone() })
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        Ok(self.get_items_by_url(std::slice::from_ref(url)).await?.pop().flatten())
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        let hrefs: String = urls.iter()
+            .map(|url| format!("        <d:href>{}</d:href>\n", url.path()))
+            .collect();
+        let body = format!("{}{}{}", MULTIGET_BODY_PREFIX, hrefs, MULTIGET_BODY_SUFFIX);
+        let xml_replies = crate::client::sub_request_and_extract_elems(&self.resource, "REPORT", body, "response").await?;
+
+        let mut results = vec![None; urls.len()];
+        for xml_reply in xml_replies {
+            let href = find_elem(&xml_reply, "href").ok_or("Missing HREF")?.text();
+            let reply_url = self.resource.combine(&href).url().clone();
+            let index = match urls.iter().position(|url| same_resource(url, &reply_url)) {
+                Some(index) => index,
+                None => {
+                    log::warn!("The server has returned item {}, that has not been requested", reply_url);
+                    continue;
+                },
+            };
+            // Missing contacts are reported with a 404 status and no data
+            let vcard = match find_elem(&xml_reply, "address-data") {
+                None => continue,
+                Some(data) => data.text(),
+            };
+            let vt = find_elem(&xml_reply, "getetag").ok_or_else(|| format!("Inconsistent data: {} has no version tag", reply_url))?.text();
+            let url = &urls[index];
+            let item = crate::ical::parse(&vcard, url.clone(), SyncStatus::Synced(VersionTag::from(vt)))?;
+            results[index] = Some(item);
+        }
+
+        Ok(results)
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.delete_remote_item(item_url).await.map_err(KFError::from)
+    }
+
+    async fn move_item(&mut self, item_url: &Url, destination: &Url) -> Result<SyncStatus, KFError> {
+        let request = self.resource.request(Method::from_bytes(b"MOVE")?, item_url.clone())
+            .header("Destination", destination.as_str())
+            .header("Overwrite", "F");
+        let response = self.resource.send(request).await?;
+
+        match response.status() {
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED | StatusCode::BAD_GATEWAY | StatusCode::FORBIDDEN => {
+                return Err(KFError::MoveNotSupported{ url: item_url.clone() });
+            },
+            status if !status.is_success() => {
+                return Err(KFError::from_status(status, item_url.clone()));
+            },
+            _ => (),
+        }
+        *self.cached_version_tags.lock().unwrap() = None;
+
+        let vtag = self.version_tag_after_write(&response, destination).await?;
+        Ok(SyncStatus::Synced(vtag))
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.put_new_item(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.put_changed_item(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(item_urls.to_vec())
+            .map(|url| async move { this.delete_remote_item(&url).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+}
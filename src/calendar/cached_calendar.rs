@@ -1,15 +1,32 @@
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
-use std::error::Error;
 
 use serde::{Deserialize, Serialize};
 use async_trait::async_trait;
 use csscolorparser::Color;
+use chrono::{DateTime, Utc};
 use url::Url;
 
-use crate::item::SyncStatus;
+use crate::item::{SyncStatus, SyncStatusKind};
+use crate::error::KFError;
 use crate::traits::{BaseCalendar, CompleteCalendar};
 use crate::calendar::SupportedComponents;
+use crate::calendar::Privileges;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarProperties;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::calendar::sharing::Sharing;
+use crate::cache::integrity::IntegrityIssue;
+use crate::cache::eviction::EvictionPolicy;
+use crate::cache::stats::{CalendarStats, SyncRecord, SyncStatusCounts};
+use crate::cache::events::{self, CacheEvent, CacheEventSender};
 use crate::Item;
+use crate::partial::PartialItem;
+use crate::provider::journal::SyncJournal;
+use crate::provider::audit::AuditLog;
+use crate::provider::pending::{PendingChange, PendingChangeKind};
+use super::lazy_items::{ItemSummary, LazyItems};
 
 #[cfg(feature = "local_calendar_mocks_remote_calendars")]
 use std::sync::{Arc, Mutex};
@@ -26,14 +43,398 @@ pub struct CachedCalendar {
     url: Url,
     supported_components: SupportedComponents,
     color: Option<Color>,
+    #[serde(default)]
+    order: Option<u32>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    timezone: Option<CalendarTimezone>,
+    #[serde(default)]
+    default_alarms: Option<DefaultAlarms>,
+    #[serde(default)]
+    privileges: Privileges,
+    /// Whether the app has marked this calendar as read-only
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    owner: Option<Url>,
+    #[serde(default)]
+    sharing: Option<Sharing>,
+    /// The properties that have been changed locally since the last sync
+    #[serde(default)]
+    modified_properties: CalendarProperties,
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
     #[serde(skip)]
     mock_behaviour: Option<Arc<Mutex<MockBehaviour>>>,
 
-    items: HashMap<Url, Item>,
+    /// Items are only deserialized when they are accessed
+    items: LazyItems,
+    /// Lightweight versions of items that have not been fully downloaded yet
+    #[serde(default)]
+    partial_items: HashMap<Url, PartialItem>,
+    /// The versions of items as they were after the last sync, used as the base of three-way merges
+    #[serde(default)]
+    base_versions: HashMap<Url, Item>,
+    /// Items that have been deleted, and that can still be restored
+    #[serde(default)]
+    tombstones: HashMap<Url, Tombstone>,
+    /// The remaining operations of an interrupted sync
+    #[serde(default)]
+    sync_journal: Option<SyncJournal>,
+    /// The items that have local changes to push, in the order they have been changed
+    #[serde(default)]
+    change_queue: Vec<(Url, DateTime<Utc>)>,
+    /// The serialized versions of items that have been removed by [`Self::repair`]
+    #[serde(default)]
+    quarantine: HashMap<Url, String>,
+    /// The previous versions of items, the most recent first
+    #[serde(default)]
+    history: HashMap<Url, Vec<ItemVersion>>,
+    /// How many versions of each item are kept in `history`
+    #[serde(default = "default_history_length")]
+    history_length: usize,
+    /// What the syncs have done to the items of this calendar
+    #[serde(default)]
+    audit_log: AuditLog,
+    /// The summaries of the items whose content has been dropped by [`Self::evict`]
+    #[serde(default)]
+    evicted: HashMap<Url, ItemSummary>,
+    /// When this calendar has been synced
+    #[serde(default)]
+    sync_record: SyncRecord,
+    /// Where the changes of this calendar are notified, see [`Cache::subscribe`](crate::cache::Cache::subscribe)
+    #[serde(skip)]
+    events: Option<CacheEventSender>,
+}
+
+/// How many previous versions of each item are kept, unless configured otherwise (see [`CachedCalendar::set_history_length`])
+const DEFAULT_HISTORY_LENGTH: usize = 10;
+
+fn default_history_length() -> usize {
+    DEFAULT_HISTORY_LENGTH
+}
+
+/// What remains of a deleted item, so that it can be restored with [`CachedCalendar::undelete`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tombstone {
+    deleted_at: DateTime<Utc>,
+    deleted_remotely: bool,
+    item: Item,
+}
+
+impl Tombstone {
+    pub fn url(&self) -> &Url { self.item.url() }
+    pub fn uid(&self) -> &str { self.item.uid() }
+    pub fn deleted_at(&self) -> &DateTime<Utc> { &self.deleted_at }
+    /// Whether the item has been deleted from the server (rather than locally)
+    pub fn deleted_remotely(&self) -> bool { self.deleted_remotely }
+    /// The last known content of the item
+    pub fn item(&self) -> &Item { &self.item }
+}
+
+/// A previous version of an item, see [`CachedCalendar::history`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemVersion {
+    replaced_at: DateTime<Utc>,
+    item: Item,
+}
+
+impl ItemVersion {
+    /// When this version has been replaced by a newer one
+    pub fn replaced_at(&self) -> &DateTime<Utc> { &self.replaced_at }
+    pub fn item(&self) -> &Item { &self.item }
 }
 
 impl CachedCalendar {
+    /// Store lightweight versions of items (see [`RemoteCalendar::get_partial_items`](crate::calendar::remote_calendar::RemoteCalendar::get_partial_items)).
+    ///
+    /// Items that are already completely known are not stored
+    pub fn store_partial_items(&mut self, partial_items: Vec<PartialItem>) {
+        for partial in partial_items {
+            if !self.items.contains_key(partial.url()) {
+                self.partial_items.insert(partial.url().clone(), partial);
+            }
+        }
+    }
+
+    /// Returns the lightweight versions of the items that have not been fully downloaded yet
+    pub fn partial_items(&self) -> &HashMap<Url, PartialItem> {
+        &self.partial_items
+    }
+
+    /// Forget the lightweight version of an item (e.g. because it has been deleted on the server)
+    pub fn remove_partial_item(&mut self, url: &Url) -> Option<PartialItem> {
+        self.partial_items.remove(url)
+    }
+
+    /// The items that have been deleted, and that can still be restored
+    pub fn tombstones(&self) -> &HashMap<Url, Tombstone> {
+        &self.tombstones
+    }
+
+    /// Restore a deleted item. It will be uploaded again at the next sync.
+    ///
+    /// This works both for items that have been deleted (as long as their tombstone has not been purged, see [`Self::purge_tombstones`]),
+    /// and for items that have been marked for deletion, but not synced yet
+    pub fn undelete(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.refuse_if_read_only()?;
+        if let Some(item) = self.items.get_mut(item_url) {
+            return match item.sync_status().clone() {
+                SyncStatus::LocallyDeleted(prev_ss) => {
+                    item.set_sync_status(SyncStatus::LocallyModified(prev_ss));
+                    self.track_change(item_url);
+                    self.notify(|calendar| CacheEvent::ItemAdded{ calendar, item: item_url.clone() });
+                    Ok(())
+                },
+                _ => Err(format!("Item {} has not been deleted", item_url).into()),
+            };
+        }
+
+        let mut item = match self.tombstones.remove(item_url) {
+            None => return Err(format!("No deleted item {} is known (it may have been purged already)", item_url).into()),
+            Some(tombstone) => tombstone.item,
+        };
+        item.set_sync_status(SyncStatus::NotSynced);
+        self.items.insert(item_url.clone(), item);
+        self.track_change(item_url);
+        self.notify(|calendar| CacheEvent::ItemAdded{ calendar, item: item_url.clone() });
+        Ok(())
+    }
+
+    /// Forget the items that have been deleted before `date`. They cannot be restored anymore
+    pub fn purge_tombstones(&mut self, date: &DateTime<Utc>) {
+        self.tombstones.retain(|_, tombstone| &tombstone.deleted_at >= date);
+        let (items, tombstones) = (&self.items, &self.tombstones);
+        self.history.retain(|url, _| items.contains_key(url) || tombstones.contains_key(url));
+    }
+
+    /// The previous versions of an item, the most recent first.
+    ///
+    /// A version is recorded every time an item is replaced (e.g. by a newer version that has been downloaded during a sync,
+    /// or by [`Self::update_item`]), so that one can tell what an item looked like before the last sync
+    pub fn history(&self, item_url: &Url) -> &[ItemVersion] {
+        self.history.get(item_url).map(|versions| versions.as_slice()).unwrap_or_default()
+    }
+
+    /// Set how many previous versions of each item are kept (see [`Self::history`]). Defaults to 10
+    pub fn set_history_length(&mut self, length: usize) {
+        self.history_length = length;
+        self.history.retain(|_, versions| {
+            versions.truncate(length);
+            !versions.is_empty()
+        });
+    }
+
+    /// Replace an item with one of its previous versions (`version` is an index in [`Self::history`]).
+    ///
+    /// This is a local modification, that will be uploaded at the next sync. The current version is added to the history, so that this can be undone
+    pub fn revert_to(&mut self, item_url: &Url, version: usize) -> Result<(), KFError> {
+        self.refuse_if_read_only()?;
+        let current_status = match self.items.get(item_url) {
+            None => return Err(format!("Item {} is absent from this calendar", item_url).into()),
+            Some(current) => current.sync_status().clone(),
+        };
+        let mut reverted = match self.history(item_url).get(version) {
+            None => return Err(format!("Item {} has no version {}", item_url, version).into()),
+            Some(previous) => previous.item.clone(),
+        };
+        reverted.set_sync_status(match current_status {
+            SyncStatus::NotSynced => SyncStatus::NotSynced,
+            SyncStatus::Synced(tag) | SyncStatus::LocallyModified(tag) | SyncStatus::LocallyDeleted(tag) => SyncStatus::LocallyModified(tag),
+        });
+        if let Item::Task(task) = &mut reverted {
+            task.update_last_modified();
+        }
+        self.regular_add_or_update_item(reverted)?;
+        Ok(())
+    }
+
+    /// Add the current version of an item (if any) to its history, before it is replaced
+    fn record_version(&mut self, item_url: &Url) {
+        if self.history_length == 0 {
+            return;
+        }
+        if let Some(current) = self.items.get(item_url) {
+            let versions = self.history.entry(item_url.clone()).or_default();
+            versions.insert(0, ItemVersion{ replaced_at: Utc::now(), item: current.clone() });
+            versions.truncate(self.history_length);
+        }
+    }
+
+    /// Look for inconsistencies in this calendar. This deserializes every item
+    pub fn check_integrity(&self) -> Vec<IntegrityIssue> {
+        let mut issues: Vec<IntegrityIssue> = self.items.unreadable().into_iter()
+            .map(|url| IntegrityIssue::UnreadableItem{ calendar: self.url.clone(), item: url })
+            .collect();
+
+        let mut uids: HashMap<&str, Vec<Url>> = HashMap::new();
+        for (url, item) in self.items.iter() {
+            let calendar = self.url.clone();
+            if item.url() != url {
+                issues.push(IntegrityIssue::MisplacedItem{ calendar, key: url.clone(), item: item.url().clone() });
+                continue;
+            }
+            if self.items.has_stale_summary(url) {
+                issues.push(IntegrityIssue::StaleSummary{ calendar: calendar.clone(), item: url.clone() });
+            }
+            if item.sync_status().version_tag().is_some_and(|tag| tag.as_str().is_empty()) {
+                issues.push(IntegrityIssue::MissingVersionTag{ calendar: calendar.clone(), item: url.clone() });
+            }
+            if self.tombstones.contains_key(url) {
+                issues.push(IntegrityIssue::TombstonedItem{ calendar: calendar.clone(), item: url.clone() });
+            }
+            let component = if item.is_task() { SupportedComponents::TODO } else { SupportedComponents::EVENT };
+            if !self.supported_components.contains(component) {
+                issues.push(IntegrityIssue::UnsupportedComponent{ calendar, item: url.clone() });
+            }
+            uids.entry(item.uid()).or_default().push(url.clone());
+        }
+        for (uid, mut items) in uids {
+            if items.len() > 1 {
+                items.sort();
+                issues.push(IntegrityIssue::DuplicateUid{ calendar: self.url.clone(), uid: uid.to_string(), items });
+            }
+        }
+
+        for (url, base) in &self.base_versions {
+            let is_stale = match self.items.get(url) {
+                None => true,
+                Some(item) => item.sync_status().version_tag() != base.sync_status().version_tag(),
+            };
+            if is_stale {
+                issues.push(IntegrityIssue::StaleBaseVersion{ calendar: self.url.clone(), item: url.clone() });
+            }
+        }
+        for (url, _) in &self.change_queue {
+            if !self.items.contains_key(url) {
+                issues.push(IntegrityIssue::UnknownQueuedChange{ calendar: self.url.clone(), item: url.clone() });
+            }
+        }
+        issues
+    }
+
+    /// Fix the issues found by [`Self::check_integrity`], when possible (see [`IntegrityIssue::is_repairable`]).
+    ///
+    /// Items that cannot be read (or that are stored under a wrong URL) are moved to the quarantine (see [`Self::quarantined_items`]).
+    /// This returns every issue that has been found, including the ones that have not been repaired
+    pub fn repair(&mut self) -> Vec<IntegrityIssue> {
+        let issues = self.check_integrity();
+        for issue in &issues {
+            match issue {
+                IntegrityIssue::UnreadableItem{ item, .. } | IntegrityIssue::MisplacedItem{ key: item, .. } => {
+                    if let Some(raw) = self.items.remove_raw(item) {
+                        log::warn!("Moving item {} to the quarantine", item);
+                        self.quarantine.insert(item.clone(), raw);
+                        self.base_versions.remove(item);
+                        // The next sync must not skip this calendar, so that the item is downloaded again
+                        self.sync_record.remote_version = None;
+                        self.track_change(item);
+                    }
+                },
+                IntegrityIssue::StaleSummary{ item, .. } => self.items.refresh_summary(item),
+                IntegrityIssue::StaleBaseVersion{ item, .. } => { self.base_versions.remove(item); },
+                IntegrityIssue::TombstonedItem{ item, .. } => { self.tombstones.remove(item); },
+                IntegrityIssue::UnknownQueuedChange{ item, .. } => self.change_queue.retain(|(url, _)| url != item),
+                _ => (),
+            }
+        }
+        issues
+    }
+
+    /// The serialized versions of the items that have been moved out of this calendar by [`Self::repair`]
+    pub fn quarantined_items(&self) -> &HashMap<Url, String> {
+        &self.quarantine
+    }
+
+    /// Drop the content of the items that an eviction policy selects, in order to save space. Only their summaries are kept.
+    ///
+    /// Evicted items are not downloaded again at the next syncs, unless they change on the server. Returns how many items have been evicted
+    pub fn evict(&mut self, policy: &EvictionPolicy) -> usize {
+        let to_evict = policy.select(self.items.summaries());
+        for url in &to_evict {
+            if let Some(summary) = self.items.summary(url).map(Cow::into_owned) {
+                self.items.remove_raw(url);
+                self.base_versions.remove(url);
+                self.history.remove(url);
+                self.evicted.insert(url.clone(), summary);
+            }
+        }
+        to_evict.len()
+    }
+
+    /// The summaries of the items whose content has been evicted (see [`Self::evict`])
+    pub fn evicted_items(&self) -> &HashMap<Url, ItemSummary> {
+        &self.evicted
+    }
+
+    /// Figures about the content of this calendar. This does not deserialize any item
+    pub fn stats(&self) -> CalendarStats {
+        let mut stats = CalendarStats {
+            url: self.url.clone(),
+            name: self.name.clone(),
+            tasks: 0,
+            events: 0,
+            sync_statuses: SyncStatusCounts::default(),
+            partial_items: self.partial_items.len(),
+            evicted_items: self.evicted.len(),
+            tombstones: self.tombstones.len(),
+            quarantined_items: self.quarantine.len(),
+            pending_changes: 0,
+            sync: self.sync_record.clone(),
+        };
+        for (_, summary) in self.items.summaries() {
+            if summary.is_task { stats.tasks += 1; } else { stats.events += 1; }
+            match summary.sync_status {
+                SyncStatus::Synced(_) => stats.sync_statuses.synced += 1,
+                SyncStatus::NotSynced => stats.sync_statuses.not_synced += 1,
+                SyncStatus::LocallyModified(_) => stats.sync_statuses.locally_modified += 1,
+                SyncStatus::LocallyDeleted(_) => stats.sync_statuses.locally_deleted += 1,
+            }
+        }
+        let counts = stats.sync_statuses;
+        stats.pending_changes = counts.not_synced + counts.locally_modified + counts.locally_deleted;
+        stats
+    }
+
+    /// Keep the change queue up to date after an item has been changed (or deleted)
+    fn track_change(&mut self, item_url: &Url) {
+        let items = &self.items;
+        let is_pending = |url: &Url| items.summary(url).is_some_and(|summary| PendingChangeKind::from_sync_status(&summary.sync_status).is_some());
+        self.change_queue.retain(|(url, _)| is_pending(url));
+        let is_pending = is_pending(item_url);
+        if is_pending && !self.change_queue.iter().any(|(url, _)| url == item_url) {
+            self.change_queue.push((item_url.clone(), Utc::now()));
+        }
+    }
+
+    /// Tell the subscribers of the cache (if any) that this calendar has changed
+    fn notify(&self, event: impl FnOnce(Url) -> CacheEvent) {
+        if self.events.is_some() {
+            events::notify(&self.events, event(self.url.clone()));
+        }
+    }
+
+    /// Send the changes of this calendar to `sender` (see [`Cache::subscribe`](crate::cache::Cache::subscribe))
+    pub(crate) fn set_event_sender(&mut self, sender: Option<CacheEventSender>) {
+        self.events = sender;
+    }
+
+    /// The event to notify when an item is about to be stored
+    fn stored_event(&self, item_url: &Url) -> CacheEvent {
+        let item = item_url.clone();
+        let calendar = self.url.clone();
+        match self.items.contains_key(item_url) || self.evicted.contains_key(item_url) {
+            true => CacheEvent::ItemModified{ calendar, item },
+            false => CacheEvent::ItemAdded{ calendar, item },
+        }
+    }
+
+    fn add_tombstone(&mut self, item: Item) {
+        // Items that were not marked for deletion are removed because the server said so
+        let deleted_remotely = matches!(item.sync_status(), SyncStatus::Synced(_) | SyncStatus::LocallyModified(_));
+        self.tombstones.insert(item.url().clone(), Tombstone{ deleted_at: Utc::now(), deleted_remotely, item });
+    }
+
     /// Activate the "mocking remote calendar" feature (i.e. ignore sync statuses, since this is what an actual CalDAV sever would do)
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
     pub fn set_mock_behaviour(&mut self, mock_behaviour: Option<Arc<Mutex<MockBehaviour>>>) {
@@ -42,7 +443,7 @@ impl CachedCalendar {
 
 
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
-    fn add_item_maybe_mocked(&mut self, item: Item) -> Result<SyncStatus, Box<dyn Error>> {
+    fn add_item_maybe_mocked(&mut self, item: Item) -> Result<SyncStatus, KFError> {
         if self.mock_behaviour.is_some() {
             self.mock_behaviour.as_ref().map_or(Ok(()), |b| b.lock().unwrap().can_add_item())?;
             self.add_or_update_item_force_synced(item)
@@ -52,9 +453,15 @@ impl CachedCalendar {
     }
 
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
-    fn update_item_maybe_mocked(&mut self, item: Item) -> Result<SyncStatus, Box<dyn Error>> {
+    fn update_item_maybe_mocked(&mut self, item: Item) -> Result<SyncStatus, KFError> {
         if self.mock_behaviour.is_some() {
             self.mock_behaviour.as_ref().map_or(Ok(()), |b| b.lock().unwrap().can_update_item())?;
+            // Just like a server would do with a `If-Match` header, refuse updates of an item that has changed in the meantime
+            if let (SyncStatus::LocallyModified(expected_vt), Some(current)) = (item.sync_status(), self.items.get(item.url())) {
+                if current.sync_status() != &SyncStatus::Synced(expected_vt.clone()) {
+                    return Err(KFError::Conflict{ url: item.url().clone() });
+                }
+            }
             self.add_or_update_item_force_synced(item)
         } else {
             self.regular_add_or_update_item(item)
@@ -62,29 +469,40 @@ impl CachedCalendar {
     }
 
     /// Add or update an item
-    fn regular_add_or_update_item(&mut self, item: Item) -> Result<SyncStatus, Box<dyn Error>> {
+    fn regular_add_or_update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
         let ss_clone = item.sync_status().clone();
         log::debug!("Adding or updating an item with {:?}", ss_clone);
-        self.items.insert(item.url().clone(), item);
+        let url = item.url().clone();
+        let event = self.stored_event(&url);
+        // The full item supersedes its partial (or evicted) version
+        self.partial_items.remove(&url);
+        self.evicted.remove(&url);
+        self.record_version(&url);
+        self.items.insert(url.clone(), item);
+        self.track_change(&url);
+        events::notify(&self.events, event);
         Ok(ss_clone)
     }
 
     /// Add or update an item, but force a "synced" SyncStatus. This is the normal behaviour that would happen on a server
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
-    fn add_or_update_item_force_synced(&mut self, mut item: Item) -> Result<SyncStatus, Box<dyn Error>> {
+    fn add_or_update_item_force_synced(&mut self, mut item: Item) -> Result<SyncStatus, KFError> {
         log::debug!("Adding or updating an item, but forces a synced SyncStatus");
         match item.sync_status() {
             SyncStatus::Synced(_) => (),
             _ => item.set_sync_status(SyncStatus::random_synced()),
         };
         let ss_clone = item.sync_status().clone();
+        let event = self.stored_event(item.url());
+        self.record_version(item.url());
         self.items.insert(item.url().clone(), item);
+        events::notify(&self.events, event);
         Ok(ss_clone)
     }
 
     /// Some kind of equality check
     #[cfg(any(test, feature = "integration_tests"))]
-    pub async fn has_same_observable_content_as(&self, other: &CachedCalendar) -> Result<bool, Box<dyn Error>> {
+    pub async fn has_same_observable_content_as(&self, other: &CachedCalendar) -> Result<bool, KFError> {
         if self.name != other.name
         || self.url != other.url
         || self.supported_components != other.supported_components
@@ -119,23 +537,66 @@ impl CachedCalendar {
     }
 
     /// The non-async version of [`Self::get_item_urls`]
-    pub fn get_item_urls_sync(&self) -> Result<HashSet<Url>, Box<dyn Error>> {
-        Ok(self.items.iter()
-            .map(|(url, _)| url.clone())
+    pub fn get_item_urls_sync(&self) -> Result<HashSet<Url>, KFError> {
+        Ok(self.items.keys().cloned().collect())
+    }
+
+    /// A summary of an item, that is available without deserializing the whole item (which happens the first time it is accessed)
+    pub fn get_item_summary(&self, url: &Url) -> Option<Cow<'_, ItemSummary>> {
+        self.items.summary(url)
+    }
+
+    /// The summaries of every item (see [`Self::get_item_summary`])
+    pub fn get_item_summaries(&self) -> HashMap<Url, Cow<'_, ItemSummary>> {
+        self.items.summaries()
+            .map(|(url, summary)| (url.clone(), summary))
+            .collect()
+    }
+
+    /// The items that may happen between two dates, i.e. the items whose dates (see [`ItemSummary::date_range`]) overlap this range.
+    ///
+    /// Recurring items are returned if their recurrence set overlaps this range, even though none of their occurrences may happen in it.
+    /// Only the returned items are deserialized, which makes this suitable to render e.g. a month view of a large calendar
+    pub fn get_items_between(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> HashMap<Url, &Item> {
+        self.items_between(start, end, |_| true)
+    }
+
+    /// The non-async version of [`Self::get_events_between`]
+    pub fn get_events_between_sync(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> HashMap<Url, &Item> {
+        self.items_between(start, end, |summary| !summary.is_task)
+    }
+
+    /// The non-async version of [`Self::get_tasks_due_between`]
+    pub fn get_tasks_due_between_sync(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> HashMap<Url, &Item> {
+        self.items_between(start, end, |summary| summary.is_due_between(start, end))
+    }
+
+    /// The items that may happen between two dates, and whose summaries match a predicate. Only these items are deserialized
+    fn items_between(&self, start: &DateTime<Utc>, end: &DateTime<Utc>, predicate: impl Fn(&ItemSummary) -> bool) -> HashMap<Url, &Item> {
+        self.items.urls_between(start, end).into_iter()
+            .filter(|url| self.items.summary(url).is_some_and(|summary| predicate(&summary)))
+            .filter_map(|url| Some((url.clone(), self.items.get(url)?)))
             .collect()
-        )
     }
 
     /// The non-async version of [`Self::get_items`]
-    pub fn get_items_sync(&self) -> Result<HashMap<Url, &Item>, Box<dyn Error>> {
+    pub fn get_items_sync(&self) -> Result<HashMap<Url, &Item>, KFError> {
         Ok(self.items.iter()
             .map(|(url, item)| (url.clone(), item))
             .collect()
         )
     }
 
+    /// The non-async version of [`Self::get_items_with_status`]. Only the returned items are deserialized
+    pub fn get_items_with_status_sync(&self, status: SyncStatusKind) -> HashMap<Url, &Item> {
+        self.items.summaries()
+            .filter(|(_, summary)| summary.sync_status.kind() == status)
+            .filter_map(|(url, _)| Some((url.clone(), self.items.get(url)?)))
+            .collect()
+    }
+
     /// The non-async version of [`Self::get_items_mut`]
-    pub fn get_items_mut_sync(&mut self) -> Result<HashMap<Url, &mut Item>, Box<dyn Error>> {
+    pub fn get_items_mut_sync(&mut self) -> Result<HashMap<Url, &mut Item>, KFError> {
         Ok(self.items.iter_mut()
             .map(|(url, item)| (url.clone(), item))
             .collect()
@@ -153,10 +614,17 @@ impl CachedCalendar {
     }
 
     /// The non-async version of [`Self::add_item`]
-    pub fn add_item_sync(&mut self, item: Item) -> Result<SyncStatus, Box<dyn Error>> {
+    pub fn add_item_sync(&mut self, mut item: Item) -> Result<SyncStatus, KFError> {
         if self.items.contains_key(item.url()) {
             return Err(format!("Item {:?} cannot be added, it exists already", item.url()).into());
         }
+        if item.sync_status().kind() != SyncStatusKind::Synced {
+            self.refuse_if_read_only()?;
+            if !self.supports_item(&item) {
+                return Err(KFError::UnsupportedComponent{ calendar: self.url.clone(), item: item.url().clone() });
+            }
+            self.attach_default_alarm(&mut item);
+        }
         #[cfg(not(feature = "local_calendar_mocks_remote_calendars"))]
         return self.regular_add_or_update_item(item);
 
@@ -164,11 +632,32 @@ impl CachedCalendar {
         return self.add_item_maybe_mocked(item);
     }
 
+    /// New local tasks get the default alarm of this calendar, if the app has enabled it (see [`crate::config::ATTACH_DEFAULT_ALARMS`])
+    fn attach_default_alarm(&self, item: &mut Item) {
+        if !*crate::config::ATTACH_DEFAULT_ALARMS.lock().unwrap() {
+            return;
+        }
+        if let (Some(defaults), Item::Task(task)) = (&self.default_alarms, item) {
+            task.attach_default_alarm(defaults);
+        }
+    }
+
+    /// Local changes cannot even be staged in read-only calendars. Items downloaded from the server (that are synced) are still accepted
+    fn refuse_if_read_only(&self) -> Result<(), KFError> {
+        match self.is_read_only() {
+            true => Err(KFError::ReadOnlyCalendar{ calendar: self.url.clone() }),
+            false => Ok(()),
+        }
+    }
+
     /// The non-async version of [`Self::update_item`]
-    pub fn update_item_sync(&mut self, item: Item) -> Result<SyncStatus, Box<dyn Error>> {
-        if self.items.contains_key(item.url()) == false {
+    pub fn update_item_sync(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        if self.items.contains_key(item.url()) == false && !self.evicted.contains_key(item.url()) {
             return Err(format!("Item {:?} cannot be updated, it does not already exist", item.url()).into());
         }
+        if item.sync_status().kind() != SyncStatusKind::Synced {
+            self.refuse_if_read_only()?;
+        }
         #[cfg(not(feature = "local_calendar_mocks_remote_calendars"))]
         return self.regular_add_or_update_item(item);
 
@@ -177,7 +666,8 @@ impl CachedCalendar {
     }
 
     /// The non-async version of [`Self::mark_for_deletion`]
-    pub fn mark_for_deletion_sync(&mut self, item_url: &Url) -> Result<(), Box<dyn Error>> {
+    pub fn mark_for_deletion_sync(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.refuse_if_read_only()?;
         match self.items.get_mut(item_url) {
             None => Err("no item for this key".into()),
             Some(item) => {
@@ -196,26 +686,43 @@ impl CachedCalendar {
                     },
                     SyncStatus::NotSynced => {
                         // This was never synced to the server, we can safely delete it as soon as now
-                        self.items.remove(item_url);
+                        if let Some(item) = self.items.remove(item_url) {
+                            self.add_tombstone(item);
+                        }
                     },
                 };
+                self.track_change(item_url);
+                self.notify(|calendar| CacheEvent::ItemDeleted{ calendar, item: item_url.clone() });
                 Ok(())
             }
         }
     }
 
     /// The non-async version of [`Self::immediately_delete_item`]
-    pub fn immediately_delete_item_sync(&mut self, item_url: &Url) -> Result<(), Box<dyn Error>> {
+    pub fn immediately_delete_item_sync(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.base_versions.remove(item_url);
+        if self.evicted.remove(item_url).is_some() {
+            // There is no content to keep in a tombstone
+            self.notify(|calendar| CacheEvent::ItemDeleted{ calendar, item: item_url.clone() });
+            return Ok(());
+        }
         match self.items.remove(item_url) {
             None => Err(format!("Item {} is absent from this calendar", item_url).into()),
-            Some(_) => Ok(())
+            Some(item) => {
+                self.add_tombstone(item);
+                self.track_change(item_url);
+                self.notify(|calendar| CacheEvent::ItemDeleted{ calendar, item: item_url.clone() });
+                Ok(())
+            },
         }
     }
 
 }
 
 
-#[async_trait]
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl BaseCalendar for CachedCalendar {
     fn name(&self) -> &str {
         &self.name
@@ -233,38 +740,235 @@ impl BaseCalendar for CachedCalendar {
         self.color.as_ref()
     }
 
-    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, Box<dyn Error>> {
+    fn order(&self) -> Option<u32> {
+        self.order
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    fn timezone(&self) -> Option<&CalendarTimezone> {
+        self.timezone.as_ref()
+    }
+
+    fn default_alarms(&self) -> Option<&DefaultAlarms> {
+        self.default_alarms.as_ref()
+    }
+
+    fn privileges(&self) -> Privileges {
+        self.privileges
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only || self.privileges.is_read_only()
+    }
+
+    fn owner(&self) -> Option<&Url> {
+        self.owner.as_ref()
+    }
+
+    fn sharing(&self) -> Option<&Sharing> {
+        self.sharing.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
         self.add_item_sync(item)
     }
 
-    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, Box<dyn Error>> {
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
         self.update_item_sync(item)
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl CompleteCalendar for CachedCalendar {
     fn new(name: String, url: Url, supported_components: SupportedComponents, color: Option<Color>) -> Self {
         Self {
             name, url, supported_components, color,
+            order: None,
+            description: None,
+            timezone: None,
+            default_alarms: None,
+            privileges: Privileges::default(),
+            read_only: false,
+            owner: None,
+            sharing: None,
+            modified_properties: CalendarProperties::empty(),
             #[cfg(feature = "local_calendar_mocks_remote_calendars")]
             mock_behaviour: None,
-            items: HashMap::new(),
+            items: LazyItems::default(),
+            partial_items: HashMap::new(),
+            base_versions: HashMap::new(),
+            tombstones: HashMap::new(),
+            sync_journal: None,
+            change_queue: Vec::new(),
+            quarantine: HashMap::new(),
+            history: HashMap::new(),
+            history_length: DEFAULT_HISTORY_LENGTH,
+            audit_log: AuditLog::default(),
+            evicted: HashMap::new(),
+            sync_record: SyncRecord::default(),
+            events: None,
         }
     }
 
-    async fn get_item_urls(&self) -> Result<HashSet<Url>, Box<dyn Error>> {
+    fn set_privileges(&mut self, privileges: Privileges) {
+        self.privileges = privileges;
+    }
+
+    fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    fn set_sharing(&mut self, sharing: Option<Sharing>) {
+        self.sharing = sharing;
+    }
+
+    fn set_owner(&mut self, owner: Option<Url>) {
+        self.owner = owner;
+    }
+
+    fn set_color(&mut self, color: Option<Color>) {
+        self.color = color;
+        self.modified_properties |= CalendarProperties::COLOR;
+    }
+
+    fn set_order(&mut self, order: Option<u32>) {
+        self.order = order;
+        self.modified_properties |= CalendarProperties::ORDER;
+    }
+
+    fn set_description(&mut self, description: Option<String>) {
+        self.description = description;
+        self.modified_properties |= CalendarProperties::DESCRIPTION;
+    }
+
+    fn set_timezone(&mut self, timezone: Option<CalendarTimezone>) {
+        self.timezone = timezone;
+        self.modified_properties |= CalendarProperties::TIMEZONE;
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.name = name;
+        self.modified_properties |= CalendarProperties::NAME;
+    }
+
+    fn set_default_alarms(&mut self, default_alarms: Option<DefaultAlarms>) {
+        self.default_alarms = default_alarms;
+        self.modified_properties |= CalendarProperties::DEFAULT_ALARMS;
+    }
+
+    fn modified_properties(&self) -> CalendarProperties {
+        self.modified_properties
+    }
+
+    fn clear_modified_properties(&mut self, properties: CalendarProperties) {
+        self.modified_properties &= !properties;
+    }
+
+    fn base_version(&self, url: &Url) -> Option<&Item> {
+        self.base_versions.get(url)
+    }
+
+    fn set_base_version(&mut self, url: &Url, base: Option<Item>) {
+        match base {
+            Some(item) => self.base_versions.insert(url.clone(), item),
+            None => self.base_versions.remove(url),
+        };
+    }
+
+    fn pending_changes(&self) -> Vec<PendingChange> {
+        let mut changes: Vec<PendingChange> = self.items.summaries()
+            .filter_map(|(item_url, summary)| {
+                let kind = PendingChangeKind::from_sync_status(&summary.sync_status)?;
+                // Items can be modified without the calendar knowing (e.g. with `get_item_by_url_mut`)
+                let queued_at = self.change_queue.iter()
+                    .find(|(url, _)| url == item_url)
+                    .map(|(_, date)| *date)
+                    .or(summary.last_modified)
+                    .unwrap_or_else(Utc::now);
+                Some(PendingChange{ calendar: self.url.clone(), item: item_url.clone(), kind, queued_at })
+            })
+            .collect();
+        changes.sort_by(|a, b| a.queued_at.cmp(&b.queued_at).then_with(|| a.item.cmp(&b.item)));
+        changes
+    }
+
+    fn sync_journal(&self) -> Option<&SyncJournal> {
+        self.sync_journal.as_ref()
+    }
+
+    fn sync_journal_mut(&mut self) -> &mut Option<SyncJournal> {
+        &mut self.sync_journal
+    }
+
+    fn audit_log(&self) -> &AuditLog {
+        &self.audit_log
+    }
+
+    fn audit_log_mut(&mut self) -> &mut AuditLog {
+        &mut self.audit_log
+    }
+
+    fn record_sync(&mut self, at: DateTime<Utc>, errors: usize) {
+        self.sync_record.record(at, errors);
+        self.notify(|calendar| CacheEvent::SyncCompleted{ calendar, errors });
+    }
+
+    fn synced_remote_version(&self) -> Option<&CalendarVersion> {
+        self.sync_record.remote_version.as_ref()
+    }
+
+    fn set_synced_remote_version(&mut self, version: Option<CalendarVersion>) {
+        self.sync_record.remote_version = version;
+    }
+
+    fn evicted_version_tags(&self) -> HashMap<Url, crate::item::VersionTag> {
+        self.evicted.iter()
+            .filter_map(|(url, summary)| Some((url.clone(), summary.sync_status.version_tag()?.clone())))
+            .collect()
+    }
+
+    async fn get_item_urls(&self) -> Result<HashSet<Url>, KFError> {
         self.get_item_urls_sync()
     }
 
-    async fn get_items(&self) -> Result<HashMap<Url, &Item>, Box<dyn Error>> {
+    async fn get_items<'a>(&'a self) -> Result<HashMap<Url, &'a Item>, KFError> {
         self.get_items_sync()
     }
 
-    async fn get_items_mut(&mut self) -> Result<HashMap<Url, &mut Item>, Box<dyn Error>> {
+    async fn get_items_mut<'a>(&'a mut self) -> Result<HashMap<Url, &'a mut Item>, KFError> {
         self.get_items_mut_sync()
     }
 
+    async fn get_items_with_status<'a>(&'a self, status: SyncStatusKind) -> Result<HashMap<Url, &'a Item>, KFError> {
+        Ok(self.get_items_with_status_sync(status))
+    }
+
+    fn count_items_with_status(&self, status: SyncStatusKind) -> usize {
+        self.items.summaries()
+            .filter(|(_, summary)| summary.sync_status.kind() == status)
+            .count()
+    }
+
+    fn iter_items(&self) -> Box<dyn Iterator<Item = (&Url, &Item)> + Send + '_> {
+        Box::new(self.items.iter())
+    }
+
+    fn iter_items_mut(&mut self) -> Box<dyn Iterator<Item = (&Url, &mut Item)> + Send + '_> {
+        Box::new(self.items.iter_mut())
+    }
+
+    async fn get_events_between<'a>(&'a self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> Result<HashMap<Url, &'a Item>, KFError> {
+        Ok(self.get_events_between_sync(start, end))
+    }
+
+    async fn get_tasks_due_between<'a>(&'a self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> Result<HashMap<Url, &'a Item>, KFError> {
+        Ok(self.get_tasks_due_between_sync(start, end))
+    }
+
     async fn get_item_by_url<'a>(&'a self, url: &Url) -> Option<&'a Item> {
         self.get_item_by_url_sync(url)
     }
@@ -273,11 +977,11 @@ impl CompleteCalendar for CachedCalendar {
         self.get_item_by_url_mut_sync(url)
     }
 
-    async fn mark_for_deletion(&mut self, item_url: &Url) -> Result<(), Box<dyn Error>> {
+    async fn mark_for_deletion(&mut self, item_url: &Url) -> Result<(), KFError> {
         self.mark_for_deletion_sync(item_url)
     }
 
-    async fn immediately_delete_item(&mut self, item_url: &Url) -> Result<(), Box<dyn Error>> {
+    async fn immediately_delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
         self.immediately_delete_item_sync(item_url)
     }
 }
@@ -292,13 +996,14 @@ use crate::{item::VersionTag,
             resource::Resource};
 
 #[cfg(feature = "local_calendar_mocks_remote_calendars")]
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl DavCalendar for CachedCalendar {
     fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
         crate::traits::CompleteCalendar::new(name, resource.url().clone(), supported_components, color)
     }
 
-    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, Box<dyn Error>> {
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
         #[cfg(feature = "local_calendar_mocks_remote_calendars")]
         self.mock_behaviour.as_ref().map_or(Ok(()), |b| b.lock().unwrap().can_get_item_version_tags())?;
 
@@ -306,11 +1011,11 @@ impl DavCalendar for CachedCalendar {
 
         let mut result = HashMap::new();
 
-        for (url, item) in self.items.iter() {
-            let vt = match item.sync_status() {
+        for (url, summary) in self.items.summaries() {
+            let vt = match &summary.sync_status {
                 SyncStatus::Synced(vt) => vt.clone(),
                 _ => {
-                    panic!("Mock calendars must contain only SyncStatus::Synced. Got {:?}", item);
+                    panic!("Mock calendars must contain only SyncStatus::Synced. Got {:?}", summary);
                 }
             };
             result.insert(url.clone(), vt);
@@ -319,14 +1024,65 @@ impl DavCalendar for CachedCalendar {
         Ok(result)
     }
 
-    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, Box<dyn Error>> {
+    async fn get_filtered_item_version_tags(&self, filter: &crate::provider::filter::SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        let all_tags = DavCalendar::get_item_version_tags(self).await?;
+        Ok(all_tags.into_iter()
+            .filter(|(url, _)| self.items.get(url).is_some_and(|item| filter.matches(item)))
+            .collect())
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        use std::hash::{Hash, Hasher};
+
+        // Like a ctag, this changes whenever an item is added, modified or deleted
+        let mut tags: Vec<(&Url, Option<String>)> = self.items.summaries()
+            .map(|(url, summary)| (url, summary.sync_status.version_tag().map(|tag| tag.as_str().to_string())))
+            .collect();
+        tags.sort();
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        tags.hash(&mut hasher);
+        Ok(CalendarVersion{ ctag: Some(format!("{:x}", hasher.finish())), sync_token: None })
+    }
+
+    async fn update_color(&mut self, color: Option<Color>) -> Result<(), KFError> {
+        // Mocked servers have no pending changes
+        self.color = color;
+        Ok(())
+    }
+
+    async fn update_order(&mut self, order: Option<u32>) -> Result<(), KFError> {
+        self.order = order;
+        Ok(())
+    }
+
+    async fn update_description(&mut self, description: Option<String>) -> Result<(), KFError> {
+        self.description = description;
+        Ok(())
+    }
+
+    async fn update_timezone(&mut self, timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        self.timezone = timezone;
+        Ok(())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        self.default_alarms = default_alarms;
+        Ok(())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
         #[cfg(feature = "local_calendar_mocks_remote_calendars")]
         self.mock_behaviour.as_ref().map_or(Ok(()), |b| b.lock().unwrap().can_get_item_by_url())?;
 
         Ok(self.items.get(url).cloned())
     }
 
-    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, Box<dyn Error>> {
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
         let mut v = Vec::new();
         for url in urls {
             v.push(DavCalendar::get_item_by_url(self, url).await?);
@@ -334,10 +1090,33 @@ impl DavCalendar for CachedCalendar {
         Ok(v)
     }
 
-    async fn delete_item(&mut self, item_url: &Url) -> Result<(), Box<dyn Error>> {
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
         #[cfg(feature = "local_calendar_mocks_remote_calendars")]
         self.mock_behaviour.as_ref().map_or(Ok(()), |b| b.lock().unwrap().can_delete_item())?;
 
         self.immediately_delete_item(item_url).await
     }
+
+    async fn move_item(&mut self, item_url: &Url, _destination: &Url) -> Result<SyncStatus, KFError> {
+        // A mocked calendar has no access to other calendars. This behaves like servers that cannot move items across calendars
+        Err(KFError::MoveNotSupported{ url: item_url.clone() })
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, _max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        // There is no network involved, so there is nothing to gain from running these concurrently
+        items.into_iter().map(|item| self.add_item_sync(item)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, _max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        items.into_iter().map(|item| self.update_item_sync(item)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], _max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        item_urls.iter()
+            .map(|url| {
+                self.mock_behaviour.as_ref().map_or(Ok(()), |b| b.lock().unwrap().can_delete_item())?;
+                self.immediately_delete_item_sync(url)
+            })
+            .collect()
+    }
 }
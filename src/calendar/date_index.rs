@@ -0,0 +1,50 @@
+//! An index of the items of a calendar by date, see [`DateIndex`]
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use url::Url;
+
+use super::lazy_items::ItemSummary;
+
+/// Items, indexed by the day they start on, so that the items of a date range can be found without deserializing every item.
+///
+/// This is built from [`ItemSummary`]s, which are stored along with the items
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DateIndex {
+    /// The items that start on a day, with the day they end on (or `None` for endless recurrences)
+    by_first_day: BTreeMap<NaiveDate, HashMap<Url, Option<NaiveDate>>>,
+    first_days: HashMap<Url, NaiveDate>,
+}
+
+impl DateIndex {
+    /// Index an item (or update its index entry)
+    pub(crate) fn insert(&mut self, url: &Url, summary: &ItemSummary) {
+        self.remove(url);
+        if let Some((first, last)) = summary.date_range() {
+            let first_day = first.naive_utc().date();
+            self.by_first_day.entry(first_day).or_default().insert(url.clone(), last.map(|last| last.naive_utc().date()));
+            self.first_days.insert(url.clone(), first_day);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, url: &Url) {
+        if let Some(first_day) = self.first_days.remove(url) {
+            if let Some(urls) = self.by_first_day.get_mut(&first_day) {
+                urls.remove(url);
+                if urls.is_empty() {
+                    self.by_first_day.remove(&first_day);
+                }
+            }
+        }
+    }
+
+    /// The items that may happen between two dates (the days of these dates are considered as a whole)
+    pub(crate) fn between(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> impl Iterator<Item = &Url> {
+        let start_day = start.naive_utc().date();
+        self.by_first_day.range(..=end.naive_utc().date())
+            .flat_map(|(_, urls)| urls.iter())
+            .filter(move |(_, last_day)| last_day.is_none_or(|last_day| last_day >= start_day))
+            .map(|(url, _)| url)
+    }
+}
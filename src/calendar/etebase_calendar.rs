@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use csscolorparser::Color;
+use futures_util::stream::{self, StreamExt};
+use url::Url;
+
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::calendar::remote_calendar::{sendable, SendError};
+use crate::etebase::{EtebaseAccount, EtebaseItem};
+use crate::item::Item;
+use crate::item::VersionTag;
+use crate::item::SyncStatus;
+use crate::resource::Resource;
+use crate::provider::filter::SyncFilter;
+use crate::error::KFError;
+use crate::kv_store::checksum;
+
+/// The items of a collection, as they have last been downloaded
+#[derive(Debug)]
+struct Listing {
+    items: HashMap<Url, Item>,
+    /// The sync token the next changes are fetched from
+    stoken: Option<String>,
+}
+
+
+
+/// A collection of an Etebase account, that is synced through an [`EtebaseAccount`].
+///
+/// It is created by an [`Etebase`](crate::etebase::Etebase) source. \
+/// The content of an Etebase item is an iCal file: it is decrypted by the account, and parsed like the items of CalDAV calendars (so that only tasks are supported for now). \
+/// Etebase chooses the UIDs of new items: the URL of an item is built from its UID, and a [`Provider`](crate::provider::Provider) moves the items it adds
+/// to these URLs (see [`DavCalendar::take_assigned_url`]). The version tag of an item is its Etebase etag.
+///
+/// The whole collection is downloaded at the first sync. Later syncs only download the changes made since (with the sync token of the collection).
+#[derive(Debug)]
+pub struct EtebaseCalendar {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+
+    account: Option<(Arc<dyn EtebaseAccount>, String)>,
+    listing: Mutex<Option<Listing>>,
+    assigned_urls: Mutex<HashMap<Url, Url>>,
+}
+
+impl EtebaseCalendar {
+    /// Use an account to sync the collection that has the Etebase UID `collection_uid`
+    pub(crate) fn with_account(mut self, account: Arc<dyn EtebaseAccount>, collection_uid: String) -> Self {
+        self.account = Some((account, collection_uid));
+        self
+    }
+
+    /// The URL of the item that has a given Etebase UID
+    pub fn item_url(&self, uid: &str) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(uid);
+        }
+        url
+    }
+
+    /// The account and the collection UID of this calendar
+    fn account(&self) -> Result<(&dyn EtebaseAccount, &str), SendError> {
+        match &self.account {
+            None => Err(format!("Calendar {} has not been created by an Etebase source", self.resource.url()).into()),
+            Some((account, uid)) => Ok((account.as_ref(), uid.as_str())),
+        }
+    }
+
+    /// Download the changes made since the last download (or every item, the first time)
+    async fn refresh(&self) -> Result<(), SendError> {
+        let (account, collection_uid) = self.account()?;
+        let mut stoken = self.listing.lock().unwrap().as_ref().and_then(|listing| listing.stoken.clone());
+        loop {
+            let changes = account.fetch_changes(collection_uid, stoken.as_deref()).await?;
+            let mut listing = self.listing.lock().unwrap();
+            let listing = listing.get_or_insert_with(|| Listing{ items: HashMap::new(), stoken: None });
+            for item in changes.items {
+                let url = self.item_url(&item.uid);
+                match item.deleted {
+                    true => { listing.items.remove(&url); },
+                    false => match self.parse(&item, url.clone()) {
+                        None => { listing.items.remove(&url); },
+                        Some(parsed) => { listing.items.insert(url, parsed); },
+                    },
+                }
+            }
+            listing.stoken = changes.stoken.clone();
+            stoken = changes.stoken;
+            if changes.done {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Parse the content of an Etebase item, or return `None` if it is not supported
+    fn parse(&self, item: &EtebaseItem, url: Url) -> Option<Item> {
+        if !item.content.contains("BEGIN:VTODO") {
+            // Like for CalDAV calendars, only tasks are supported for now
+            log::debug!("Ignoring item {} of {}, that is not a task", item.uid, self.resource.url());
+            return None;
+        }
+        match crate::ical::parse(&item.content, url, SyncStatus::Synced(VersionTag::from(item.etag.clone()))) {
+            Err(err) => {
+                log::warn!("Ignoring item {} of {}: {}", item.uid, self.resource.url(), err);
+                None
+            },
+            Ok(parsed) => Some(parsed),
+        }
+    }
+
+    /// Download the items, unless they have been downloaded already
+    async fn ensure_listing(&self) -> Result<(), SendError> {
+        if self.listing.lock().unwrap().is_none() {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    /// Update the downloaded items with an item that has just been uploaded, and return its URL and its sync status
+    fn store_uploaded(&self, uploaded: EtebaseItem) -> (Url, SyncStatus) {
+        let url = self.item_url(&uploaded.uid);
+        let sync_status = SyncStatus::Synced(VersionTag::from(uploaded.etag.clone()));
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            match self.parse(&uploaded, url.clone()) {
+                None => { listing.items.remove(&url); },
+                Some(item) => { listing.items.insert(url.clone(), item); },
+            }
+        }
+        (url, sync_status)
+    }
+
+    async fn insert_item(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let (account, collection_uid) = self.account()?;
+        let content = crate::ical::build_from(item).map_err(sendable)?;
+        let uploaded = account.create_item(collection_uid, &content).await?;
+        let (url, sync_status) = self.store_uploaded(uploaded);
+        self.assigned_urls.lock().unwrap().insert(item.url().clone(), url);
+        Ok(sync_status)
+    }
+
+    async fn update_remote_item(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let old_etag = match item.sync_status() {
+            SyncStatus::NotSynced => return Err("Cannot update an item that has not been synced already".into()),
+            SyncStatus::Synced(_) => return Err("Cannot update an item that has not changed".into()),
+            SyncStatus::LocallyModified(etag) => etag,
+            SyncStatus::LocallyDeleted(etag) => etag,
+        };
+        let (account, collection_uid) = self.account()?;
+        let changed = EtebaseItem {
+            uid: self.item_uid(item.url())?,
+            etag: old_etag.as_str().to_string(),
+            content: crate::ical::build_from(item).map_err(sendable)?,
+            deleted: false,
+        };
+        let uploaded = account.update_item(collection_uid, &changed).await?;
+        let (_, sync_status) = self.store_uploaded(uploaded);
+        Ok(sync_status)
+    }
+
+    async fn delete_remote_item(&self, item_url: &Url) -> Result<(), SendError> {
+        let (account, collection_uid) = self.account()?;
+        // The changes of the server have been downloaded at the start of this sync
+        let etag = self.listing.lock().unwrap().as_ref()
+            .and_then(|listing| listing.items.get(item_url))
+            .and_then(|item| item.sync_status().version_tag().cloned())
+            .ok_or_else(|| format!("Item {} is unknown, and cannot be deleted", item_url))?;
+        let deleted = EtebaseItem {
+            uid: self.item_uid(item_url)?,
+            etag: etag.as_str().to_string(),
+            content: String::new(),
+            deleted: true,
+        };
+        account.update_item(collection_uid, &deleted).await?;
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.items.remove(item_url);
+        }
+        Ok(())
+    }
+
+    /// The Etebase UID of an item of this collection
+    fn item_uid(&self, url: &Url) -> Result<String, SendError> {
+        url.path_segments().and_then(|mut segments| segments.next_back())
+            .filter(|_| url.as_str().starts_with(self.resource.url().as_str()))
+            .map(|segment| percent_encoding::percent_decode_str(segment).decode_utf8_lossy().to_string())
+            .ok_or_else(|| format!("Item {} does not belong to collection {}", url, self.resource.url()).into())
+    }
+
+    fn forbidden(&self) -> KFError {
+        KFError::Forbidden{ url: self.resource.url().clone() }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for EtebaseCalendar {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.insert_item(&item).await.map_err(KFError::from)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.update_remote_item(&item).await.map_err(KFError::from)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for EtebaseCalendar {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        Self {
+            name, resource, supported_components, color,
+            account: None,
+            listing: Mutex::new(None),
+            assigned_urls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| &listing.items)
+            .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| &listing.items)
+            .filter(|(_, item)| filter.matches(item))
+            .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        self.ensure_listing().await?;
+        Ok(self.listing.lock().unwrap().as_ref().and_then(|listing| listing.items.get(url).cloned()))
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(urls.iter()
+            .map(|url| listing.as_ref().and_then(|listing| listing.items.get(url).cloned()))
+            .collect())
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.delete_remote_item(item_url).await.map_err(KFError::from)
+    }
+
+    async fn move_item(&mut self, item_url: &Url, _destination: &Url) -> Result<SyncStatus, KFError> {
+        // The destination URL cannot be chosen
+        Err(KFError::MoveNotSupported{ url: item_url.clone() })
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.insert_item(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.update_remote_item(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(item_urls.to_vec())
+            .map(|url| async move { this.delete_remote_item(&url).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    fn take_assigned_url(&mut self, url: &Url) -> Option<Url> {
+        self.assigned_urls.lock().unwrap().remove(url)
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        // This is the first request of a sync, that always downloads the latest changes
+        self.refresh().await?;
+        let mut tags: Vec<_> = self.get_item_version_tags().await?.into_iter()
+            .map(|(url, tag)| format!("{} {}\n", url, tag.as_str()))
+            .collect();
+        tags.sort();
+        Ok(CalendarVersion{ ctag: Some(format!("{:x}", checksum(tags.concat().as_bytes()))), sync_token: None })
+    }
+
+    async fn update_color(&mut self, _color: Option<Color>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_description(&mut self, _description: Option<String>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_timezone(&mut self, _timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_name(&mut self, _name: String) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+}
@@ -0,0 +1,1206 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use csscolorparser::Color;
+use ical::property::Property;
+use minidom::Element;
+use url::Url;
+
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::calendar::remote_calendar::SendError;
+use crate::alarm::{Alarm, AlarmTrigger};
+use crate::item::Item;
+use crate::item::VersionTag;
+use crate::item::SyncStatus;
+use crate::task::{CompletionStatus, Task};
+use crate::event::Event;
+use crate::resource::Resource;
+use crate::provider::filter::SyncFilter;
+use crate::error::KFError;
+use crate::ews::{response_messages, soap_request};
+use crate::kv_store::checksum;
+use crate::utils::{find_elem, xml_escape};
+
+/// How many changes a `SyncFolderItems` request asks for
+const MAX_CHANGES_RETURNED: usize = 512;
+
+/// The version tags of the items of a folder, as they have last been synced
+#[derive(Debug)]
+struct FolderState {
+    version_tags: HashMap<Url, VersionTag>,
+    /// The EWS sync state, that tells the server which changes have already been downloaded
+    sync_state: Option<String>,
+}
+
+
+
+/// A task folder of an Exchange mailbox, that is synced with Exchange Web Services.
+///
+/// It is created by an [`Ews`](crate::ews::Ews) source. \
+/// Tasks are converted to iCal items: their subjects, statuses, importances (as `PRIORITY`), bodies (as `DESCRIPTION`),
+/// due dates (as `DUE`) and reminders (as alarms) are synced. Exchange sets the completion dates of tasks itself.
+/// Other iCal properties have no counterpart in Exchange tasks, and are lost when tasks are downloaded. \
+/// Exchange chooses the IDs of new items: the URL of an item is built from its EWS ID, and its UID is this ID. A [`Provider`](crate::provider::Provider) moves the tasks it adds
+/// to these URLs (see [`DavCalendar::take_assigned_url`]). The version tags of items are their EWS change keys.
+///
+/// The whole folder is listed at the first sync. Later syncs only download the changes made since (with `SyncFolderItems` requests).
+#[derive(Debug)]
+pub struct EwsTaskFolder {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+
+    state: Mutex<Option<FolderState>>,
+    assigned_urls: Mutex<HashMap<Url, Url>>,
+}
+
+impl EwsTaskFolder {
+    /// The EWS ID of this folder
+    pub fn id(&self) -> String {
+        folder_id(self.resource.url())
+    }
+
+    /// The URL of the item that has a given EWS ID
+    pub fn item_url(&self, id: &str) -> Url {
+        folder_item_url(self.resource.url(), id)
+    }
+
+    /// Download the changes made since the last sync (or the ID of every item, the first time)
+    async fn refresh(&self) -> Result<(), SendError> {
+        sync_folder_items(&self.resource, &self.state, "Task").await
+    }
+
+    /// Download the IDs of the items, unless they have been downloaded already
+    async fn ensure_state(&self) -> Result<(), SendError> {
+        if self.state.lock().unwrap().is_none() {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    async fn get_tasks(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, SendError> {
+        get_folder_items(&self.resource, urls, "Task", &[], element_to_item).await
+    }
+
+    async fn create_task(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let task = ews_task(item, self.resource.url())?;
+        let fields: String = task_fields(task).into_iter().filter_map(|(_, element)| element).collect();
+        let (url, sync_status) = create_folder_item(&self.resource, &self.state, "", &format!("<t:Task>{}</t:Task>", fields), item.url()).await?;
+        self.assigned_urls.lock().unwrap().insert(item.url().clone(), url);
+        Ok(sync_status)
+    }
+
+    async fn update_task(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let task = ews_task(item, self.resource.url())?;
+        update_folder_item(&self.resource, &self.state, "", item, "Task", task_fields(task)).await
+    }
+
+    async fn delete_task(&self, item_url: &Url) -> Result<(), SendError> {
+        delete_folder_item(&self.resource, &self.state, r#" AffectedTaskOccurrences="AllOccurrences""#, item_url).await
+    }
+
+    fn forbidden(&self) -> KFError {
+        KFError::Forbidden{ url: self.resource.url().clone() }
+    }
+}
+
+/// The EWS ID of a folder, whose URL ends with `/folders/{id}/`
+fn folder_id(folder_url: &Url) -> String {
+    let segments = folder_url.path_segments().into_iter().flatten().rev().filter(|segment| !segment.is_empty());
+    segments.take(1).map(percent_decode).collect()
+}
+
+/// The URL of the item of a folder that has a given EWS ID
+fn folder_item_url(folder_url: &Url, id: &str) -> Url {
+    let mut url = folder_url.clone();
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments.pop_if_empty().push(id);
+    }
+    url
+}
+
+/// The EWS ID of an item of a folder
+fn folder_item_id(folder_url: &Url, url: &Url) -> Result<String, SendError> {
+    url.path_segments().and_then(|mut segments| segments.next_back())
+        .filter(|_| url.as_str().starts_with(folder_url.as_str()))
+        .map(percent_decode)
+        .ok_or_else(|| format!("Item {} does not belong to folder {}", url, folder_url).into())
+}
+
+/// The EWS endpoint requests about a folder are sent to (the URL of this folder, without its `/folders/{id}/` suffix)
+fn folder_endpoint(resource: &Resource) -> Resource {
+    let mut url = resource.url().clone();
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments.pop_if_empty().pop().pop();
+    }
+    resource.combine(url.path())
+}
+
+/// Download the changes made to the folder of `resource` since `state` has been synced (or the ID of every item, when it has not been synced yet), and apply them to `state`.
+///
+/// Folders may contain items of other kinds (e.g. messages): only the items whose elements are named `item_element` (e.g. `Task`) are kept
+async fn sync_folder_items(resource: &Resource, state: &Mutex<Option<FolderState>>, item_element: &str) -> Result<(), SendError> {
+    let folder_url = resource.url();
+    let mut sync_state = state.lock().unwrap().as_ref().and_then(|state| state.sync_state.clone());
+    let mut changes = Vec::new();
+    loop {
+        let body = format!(r#"<m:SyncFolderItems>
+                <m:ItemShape><t:BaseShape>IdOnly</t:BaseShape></m:ItemShape>
+                <m:SyncFolderId><t:FolderId Id="{}"/></m:SyncFolderId>
+                {}
+                <m:MaxChangesReturned>{}</m:MaxChangesReturned>
+            </m:SyncFolderItems>"#,
+            xml_escape(&folder_id(folder_url)),
+            sync_state.as_ref().map(|state| format!("<m:SyncState>{}</m:SyncState>", xml_escape(state))).unwrap_or_default(),
+            MAX_CHANGES_RETURNED);
+        let reply = soap_request(&folder_endpoint(resource), &body).await?;
+        let message = match response_messages(&reply, folder_url).into_iter().next() {
+            None => return Err(format!("Invalid reply to SyncFolderItems for {}", folder_url).into()),
+            Some(Err(err)) if sync_state.is_some() && err.to_string().contains("ErrorInvalidSyncStateData") => {
+                log::info!("The sync state of {} is not valid any more, listing every item again", folder_url);
+                *state.lock().unwrap() = None;
+                sync_state = None;
+                changes.clear();
+                continue;
+            },
+            Some(message) => message?,
+        };
+
+        if let Some(changes_elem) = find_elem(message, "Changes") {
+            for change in changes_elem.children() {
+                let id = change.children()
+                    .find(|child| child.name() == item_element || child.name() == "ItemId")
+                    .and_then(|child| if child.name() == "ItemId" { Some(child) } else { find_elem(child, "ItemId") });
+                if let Some(id) = id {
+                    changes.push((change.name().to_string(), id.attr("Id").unwrap_or_default().to_string(), id.attr("ChangeKey").map(String::from)));
+                }
+            }
+        }
+        sync_state = find_elem(message, "SyncState").map(|state| state.text());
+        if find_elem(message, "IncludesLastItemInRange").is_none_or(|last| last.text() == "true") {
+            break;
+        }
+    }
+
+    let mut state = state.lock().unwrap();
+    let state = state.get_or_insert_with(|| FolderState{ version_tags: HashMap::new(), sync_state: None });
+    for (kind, id, change_key) in changes {
+        let url = folder_item_url(folder_url, &id);
+        match (kind.as_str(), change_key) {
+            ("Delete", _) => { state.version_tags.remove(&url); },
+            ("Create", Some(change_key)) | ("Update", Some(change_key)) => { state.version_tags.insert(url, VersionTag::from(change_key)); },
+            _ => (),
+        }
+    }
+    state.sync_state = sync_state;
+    Ok(())
+}
+
+/// Download items of the folder of `resource`, whose elements are named `item_element`, with their `additional_properties` (as EWS field URIs)
+async fn get_folder_items(resource: &Resource, urls: &[Url], item_element: &str, additional_properties: &[&str],
+                          to_item: fn(&Element, Url) -> Option<Item>) -> Result<Vec<Option<Item>>, SendError>
+{
+    if urls.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut ids = String::new();
+    for url in urls {
+        ids.push_str(&format!(r#"<t:ItemId Id="{}"/>"#, xml_escape(&folder_item_id(resource.url(), url)?)));
+    }
+    let additional_properties = match additional_properties.is_empty() {
+        true => String::new(),
+        false => format!("<t:AdditionalProperties>{}</t:AdditionalProperties>",
+            additional_properties.iter().map(|field_uri| format!(r#"<t:FieldURI FieldURI="{}"/>"#, field_uri)).collect::<String>()),
+    };
+    let body = format!(r#"<m:GetItem>
+            <m:ItemShape><t:BaseShape>AllProperties</t:BaseShape><t:BodyType>Text</t:BodyType>{}</m:ItemShape>
+            <m:ItemIds>{}</m:ItemIds>
+        </m:GetItem>"#, additional_properties, ids);
+    let reply = soap_request(&folder_endpoint(resource), &body).await?;
+
+    let messages = response_messages(&reply, resource.url());
+    if messages.len() != urls.len() {
+        return Err(format!("Invalid reply to GetItem for {}", resource.url()).into());
+    }
+    let mut items = Vec::new();
+    for (url, message) in urls.iter().zip(messages) {
+        match message {
+            Err(err) if err.downcast_ref::<KFError>().is_some_and(|err| matches!(err, KFError::NotFound{ .. })) => items.push(None),
+            Err(err) => return Err(err),
+            Ok(message) => items.push(find_elem(message, item_element).and_then(|element| to_item(element, url.clone()))),
+        }
+    }
+    Ok(items)
+}
+
+/// Create an item (e.g. a `t:Task` element) in the folder of `resource`, and return its URL and its sync status
+///
+/// `request_attributes` are added to the `CreateItem` request, and `item_url` is the URL errors are about
+async fn create_folder_item(resource: &Resource, state: &Mutex<Option<FolderState>>, request_attributes: &str, item_xml: &str, item_url: &Url)
+    -> Result<(Url, SyncStatus), SendError>
+{
+    let body = format!(r#"<m:CreateItem{}>
+            <m:SavedItemFolderId><t:FolderId Id="{}"/></m:SavedItemFolderId>
+            <m:Items>{}</m:Items>
+        </m:CreateItem>"#, request_attributes, xml_escape(&folder_id(resource.url())), item_xml);
+    let reply = soap_request(&folder_endpoint(resource), &body).await?;
+    let (id, change_key) = created_item_id(&reply, item_url)?;
+
+    let url = folder_item_url(resource.url(), &id);
+    let version_tag = VersionTag::from(change_key);
+    if let Some(state) = state.lock().unwrap().as_mut() {
+        state.version_tags.insert(url.clone(), version_tag.clone());
+    }
+    Ok((url, SyncStatus::Synced(version_tag)))
+}
+
+/// Update the `fields` of an item of the folder of `resource` (as (EWS field URI, child element of a `t:{item_element}` element) pairs, see [`task_fields`])
+async fn update_folder_item(resource: &Resource, state: &Mutex<Option<FolderState>>, request_attributes: &str, item: &Item,
+                            item_element: &str, fields: Vec<(&str, Option<String>)>) -> Result<SyncStatus, SendError>
+{
+    let old_tag = match item.sync_status() {
+        SyncStatus::NotSynced => return Err("Cannot update an item that has not been synced already".into()),
+        SyncStatus::Synced(_) => return Err("Cannot update an item that has not changed".into()),
+        SyncStatus::LocallyModified(tag) => tag,
+        SyncStatus::LocallyDeleted(tag) => tag,
+    };
+    let updates: String = fields.into_iter()
+        .map(|(field_uri, element)| match element {
+            Some(element) => format!(r#"<t:SetItemField><t:FieldURI FieldURI="{0}"/><t:{1}>{2}</t:{1}></t:SetItemField>"#, field_uri, item_element, element),
+            None => format!(r#"<t:DeleteItemField><t:FieldURI FieldURI="{}"/></t:DeleteItemField>"#, field_uri),
+        })
+        .collect();
+    // Changes made on the server since the last sync are conflicts, rather than being overwritten
+    let body = format!(r#"<m:UpdateItem ConflictResolution="NeverOverwrite"{}>
+            <m:ItemChanges><t:ItemChange>
+                <t:ItemId Id="{}" ChangeKey="{}"/>
+                <t:Updates>{}</t:Updates>
+            </t:ItemChange></m:ItemChanges>
+        </m:UpdateItem>"#, request_attributes, xml_escape(&folder_item_id(resource.url(), item.url())?), xml_escape(old_tag.as_str()), updates);
+    let reply = soap_request(&folder_endpoint(resource), &body).await?;
+    let (_, change_key) = created_item_id(&reply, item.url())?;
+
+    let version_tag = VersionTag::from(change_key);
+    if let Some(state) = state.lock().unwrap().as_mut() {
+        state.version_tags.insert(item.url().clone(), version_tag.clone());
+    }
+    Ok(SyncStatus::Synced(version_tag))
+}
+
+/// Move an item of the folder of `resource` to the Deleted Items folder
+async fn delete_folder_item(resource: &Resource, state: &Mutex<Option<FolderState>>, request_attributes: &str, item_url: &Url) -> Result<(), SendError> {
+    let body = format!(r#"<m:DeleteItem DeleteType="MoveToDeletedItems"{}>
+            <m:ItemIds><t:ItemId Id="{}"/></m:ItemIds>
+        </m:DeleteItem>"#, request_attributes, xml_escape(&folder_item_id(resource.url(), item_url)?));
+    let reply = soap_request(&folder_endpoint(resource), &body).await?;
+    for message in response_messages(&reply, item_url) {
+        message?;
+    }
+    if let Some(state) = state.lock().unwrap().as_mut() {
+        state.version_tags.remove(item_url);
+    }
+    Ok(())
+}
+
+/// The version of a folder, whose ctag is a checksum of the version tags of its items
+fn folder_version(version_tags: &HashMap<Url, VersionTag>) -> String {
+    let mut tags: Vec<_> = version_tags.iter()
+        .map(|(url, tag)| format!("{} {}\n", url, tag.as_str()))
+        .collect();
+    tags.sort();
+    format!("{:x}", checksum(tags.concat().as_bytes()))
+}
+
+fn percent_decode(segment: &str) -> String {
+    percent_encoding::percent_decode_str(segment).decode_utf8_lossy().to_string()
+}
+
+fn ews_task<'a>(item: &'a Item, calendar_url: &Url) -> Result<&'a Task, SendError> {
+    match item {
+        Item::Task(task) => Ok(task),
+        Item::Event(_) | Item::Contact(_) => Err(format!("Item {} cannot be uploaded to {}: only tasks are supported in Exchange task folders", item.url(), calendar_url).into()),
+    }
+}
+
+/// The ID and change key of the item a `CreateItem` or an `UpdateItem` request has created or updated
+fn created_item_id(reply: &Element, url: &Url) -> Result<(String, String), SendError> {
+    let message = response_messages(reply, url).into_iter().next()
+        .ok_or_else(|| format!("Invalid EWS reply for {}", url))??;
+    find_elem(message, "ItemId")
+        .and_then(|id| Some((id.attr("Id")?.to_string(), id.attr("ChangeKey")?.to_string())))
+        .ok_or_else(|| format!("Invalid EWS reply for {}: the item has no ID", url).into())
+}
+
+fn parse_date(element: &Element, name: &str) -> Option<DateTime<Utc>> {
+    let date = find_elem(element, name)?.text();
+    DateTime::parse_from_rfc3339(date.trim()).ok().map(|date| date.with_timezone(&Utc))
+}
+
+/// Convert a `t:Task` element to an item, or return `None` if it is invalid
+fn element_to_item(task: &Element, url: Url) -> Option<Item> {
+    let id = find_elem(task, "ItemId")?;
+    let (uid, change_key) = (id.attr("Id")?.to_string(), id.attr("ChangeKey")?.to_string());
+    let text = |name: &str| find_elem(task, name).map(|elem| elem.text()).filter(|text| !text.is_empty());
+
+    let completion_status = match text("Status").as_deref() {
+        Some("Completed") => CompletionStatus::Completed(parse_date(task, "CompleteDate")),
+        _ => CompletionStatus::Uncompleted,
+    };
+    let mut extra_parameters = Vec::new();
+    let priority = match text("Importance").as_deref() {
+        Some("High") => Some("1"),
+        Some("Low") => Some("9"),
+        _ => None,
+    };
+    if let Some(priority) = priority {
+        extra_parameters.push(Property{ name: "PRIORITY".to_string(), params: None, value: Some(priority.to_string()) });
+    }
+    if let Some(body) = text("Body") {
+        extra_parameters.push(Property{ name: "DESCRIPTION".to_string(), params: None, value: Some(body) });
+    }
+    if let Some(due) = parse_date(task, "DueDate") {
+        let params = Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])]);
+        extra_parameters.push(Property{ name: "DUE".to_string(), params, value: Some(due.format("%Y%m%d").to_string()) });
+    }
+
+    let name = text("Subject").unwrap_or_default();
+    let reminder = match text("ReminderIsSet").as_deref() {
+        Some("true") => parse_date(task, "ReminderDueBy"),
+        _ => None,
+    };
+    let mut new_task = Task::new_with_parameters(
+        name.clone(),
+        uid,
+        url,
+        completion_status,
+        SyncStatus::Synced(VersionTag::from(change_key)),
+        parse_date(task, "DateTimeCreated"),
+        parse_date(task, "LastModifiedTime").unwrap_or_else(Utc::now),
+        crate::ical::default_prod_id(),
+        extra_parameters,
+    );
+    if let Some(reminder) = reminder {
+        new_task.set_alarms(vec![Alarm::display(AlarmTrigger::Absolute(reminder), &name)]);
+    }
+    Some(Item::Task(new_task))
+}
+
+/// The fields of a task that are synced, as (EWS field URI, `t:Task` child element) pairs, in the order of the EWS schema.
+///
+/// Fields that the task does not have have no element, so that they are deleted when an existing item is updated
+fn task_fields(task: &Task) -> Vec<(&'static str, Option<String>)> {
+    let property = |name: &str| task.extra_parameters().iter()
+        .find(|prop| prop.name.eq_ignore_ascii_case(name))
+        .and_then(|prop| prop.value.as_deref());
+    let format_date = |date: DateTime<Utc>| date.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+
+    let importance = match property("PRIORITY").and_then(|priority| priority.trim().parse::<u8>().ok()) {
+        Some(1..=4) => "High",
+        Some(6..=9) => "Low",
+        _ => "Normal",
+    };
+    // Only alarms at a given time can be reminders
+    let reminder = task.alarms().iter().find_map(|alarm| match alarm.trigger() {
+        Some(AlarmTrigger::Absolute(date)) => Some(date),
+        _ => None,
+    });
+    let due = property("DUE").and_then(crate::ical::parse_date_value);
+    let status = if task.completed() { "Completed" } else { "NotStarted" };
+
+    vec![
+        ("item:Subject", Some(format!("<t:Subject>{}</t:Subject>", xml_escape(task.name())))),
+        ("item:Body", Some(format!(r#"<t:Body BodyType="Text">{}</t:Body>"#, xml_escape(property("DESCRIPTION").unwrap_or_default())))),
+        ("item:Importance", Some(format!("<t:Importance>{}</t:Importance>", importance))),
+        ("item:ReminderDueBy", reminder.map(|date| format!("<t:ReminderDueBy>{}</t:ReminderDueBy>", format_date(date)))),
+        ("item:ReminderIsSet", Some(format!("<t:ReminderIsSet>{}</t:ReminderIsSet>", reminder.is_some()))),
+        ("task:DueDate", due.map(|date| format!("<t:DueDate>{}</t:DueDate>", format_date(date)))),
+        ("task:Status", Some(format!("<t:Status>{}</t:Status>", status))),
+    ]
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for EwsTaskFolder {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.create_task(&item).await.map_err(KFError::from)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.update_task(&item).await.map_err(KFError::from)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for EwsTaskFolder {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        Self {
+            name, resource, supported_components, color,
+            state: Mutex::new(None),
+            assigned_urls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_state().await?;
+        Ok(self.state.lock().unwrap().as_ref().map(|state| state.version_tags.clone()).unwrap_or_default())
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        // Items have to be downloaded to be filtered
+        let all_tags = self.get_item_version_tags().await?;
+        let urls: Vec<Url> = all_tags.keys().cloned().collect();
+        let items = self.get_tasks(&urls).await?;
+        Ok(items.into_iter().flatten()
+            .filter(|item| filter.matches(item))
+            .filter_map(|item| all_tags.get(item.url()).map(|tag| (item.url().clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        let items = self.get_tasks(std::slice::from_ref(url)).await?;
+        Ok(items.into_iter().next().flatten())
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        self.get_tasks(urls).await.map_err(KFError::from)
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.delete_task(item_url).await.map_err(KFError::from)
+    }
+
+    async fn move_item(&mut self, item_url: &Url, _destination: &Url) -> Result<SyncStatus, KFError> {
+        // Exchange gives moved items new IDs
+        Err(KFError::MoveNotSupported{ url: item_url.clone() })
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, _max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let mut results = Vec::new();
+        for item in items {
+            // Exchange servers throttle concurrent requests of the same user
+            let result = self.create_task(&item).await;
+            results.push(result);
+        }
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, _max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let mut results = Vec::new();
+        for item in items {
+            let result = self.update_task(&item).await;
+            results.push(result);
+        }
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], _max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let mut results = Vec::new();
+        for url in item_urls {
+            let result = self.delete_task(url).await;
+            results.push(result);
+        }
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    fn take_assigned_url(&mut self, url: &Url) -> Option<Url> {
+        self.assigned_urls.lock().unwrap().remove(url)
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        // This is the first request of a sync, that always downloads the latest changes
+        self.refresh().await?;
+        let version_tags = self.get_item_version_tags().await?;
+        Ok(CalendarVersion{ ctag: Some(folder_version(&version_tags)), sync_token: None })
+    }
+
+    async fn update_color(&mut self, _color: Option<Color>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_description(&mut self, _description: Option<String>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_timezone(&mut self, _timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        let body = format!(r#"<m:UpdateFolder>
+                <m:FolderChanges><t:FolderChange>
+                    <t:FolderId Id="{}"/>
+                    <t:Updates><t:SetFolderField>
+                        <t:FieldURI FieldURI="folder:DisplayName"/>
+                        <t:TasksFolder><t:DisplayName>{}</t:DisplayName></t:TasksFolder>
+                    </t:SetFolderField></t:Updates>
+                </t:FolderChange></m:FolderChanges>
+            </m:UpdateFolder>"#, xml_escape(&self.id()), xml_escape(&name));
+        let reply = soap_request(&folder_endpoint(&self.resource), &body).await?;
+        for message in response_messages(&reply, self.resource.url()) {
+            message?;
+        }
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+}
+
+
+/// The days of the week, as named by iCal and by EWS
+const WEEKDAYS: [(&str, &str); 7] = [("MO", "Monday"), ("TU", "Tuesday"), ("WE", "Wednesday"), ("TH", "Thursday"), ("FR", "Friday"), ("SA", "Saturday"), ("SU", "Sunday")];
+/// The positions of days in a month (as in iCal `BYSETPOS` parts), as named by EWS
+const WEEK_INDEXES: [(i32, &str); 5] = [(1, "First"), (2, "Second"), (3, "Third"), (4, "Fourth"), (-1, "Last")];
+const MONTHS: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December"];
+
+/// A calendar folder of an Exchange mailbox, that is synced with Exchange Web Services.
+///
+/// It is created by an [`EwsCalendars`](crate::ews::EwsCalendars) source. \
+/// Calendar items are converted to iCal events: their subjects, bodies (as `DESCRIPTION`), locations, start and end dates, categories, recurrences (as `RRULE`),
+/// free/busy statuses (as `TRANSP` and tentative `STATUS`), cancellations and reminders (as alarms) are synced.
+/// Times are uploaded in UTC (times of other time zones are read as UTC times, see [`Event::start`]), and whole-day events are rounded to the nearest midnight (in UTC) when they are downloaded,
+/// since Exchange stores them from the midnight of the time zone they have been created in.
+/// Other iCal properties (e.g. `EXDATE`, or recurrence rules that Exchange cannot describe) have no counterpart in Exchange, and are lost when events are downloaded.
+/// Events whose recurrence rules cannot be described fail to be uploaded. Meeting invitations and cancellations are never sent. \
+/// Exchange chooses the IDs of new items: the URL of an item is built from its EWS ID, and its UID is its iCal UID. A [`Provider`](crate::provider::Provider) moves the events it adds
+/// to these URLs (see [`DavCalendar::take_assigned_url`]). The version tags of items are their EWS change keys.
+///
+/// The whole folder is listed at the first sync. Later syncs only download the changes made since (with `SyncFolderItems` requests),
+/// even after the app is restarted, since the EWS sync state is kept as the sync token of the calendar (see [`DavCalendar::resume_from`]).
+/// When Exchange does not accept a sync state any more, the whole folder is listed again.
+#[derive(Debug)]
+pub struct EwsCalendar {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+
+    state: Mutex<Option<FolderState>>,
+    assigned_urls: Mutex<HashMap<Url, Url>>,
+}
+
+impl EwsCalendar {
+    /// The EWS ID of this folder
+    pub fn id(&self) -> String {
+        folder_id(self.resource.url())
+    }
+
+    /// The URL of the item that has a given EWS ID
+    pub fn item_url(&self, id: &str) -> Url {
+        folder_item_url(self.resource.url(), id)
+    }
+
+    /// Download the changes made since the last sync (or the ID of every item, the first time)
+    async fn refresh(&self) -> Result<(), SendError> {
+        sync_folder_items(&self.resource, &self.state, "CalendarItem").await
+    }
+
+    /// Download the IDs of the items, unless they have been downloaded already
+    async fn ensure_state(&self) -> Result<(), SendError> {
+        if self.state.lock().unwrap().is_none() {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    async fn get_events(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, SendError> {
+        get_folder_items(&self.resource, urls, "CalendarItem", &["calendar:UID", "calendar:Recurrence"], calendar_item_to_item).await
+    }
+
+    async fn create_event(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let event = ews_event(item, self.resource.url())?;
+        let fields: String = calendar_item_fields(event)?.into_iter().filter_map(|(_, element)| element).collect();
+        let (url, sync_status) = create_folder_item(&self.resource, &self.state, r#" SendMeetingInvitations="SendToNone""#,
+            &format!("<t:CalendarItem>{}</t:CalendarItem>", fields), item.url()).await?;
+        self.assigned_urls.lock().unwrap().insert(item.url().clone(), url);
+        Ok(sync_status)
+    }
+
+    async fn update_event(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let event = ews_event(item, self.resource.url())?;
+        // The UID of an item cannot be changed
+        let fields = calendar_item_fields(event)?.into_iter().filter(|(field_uri, _)| *field_uri != "calendar:UID").collect();
+        update_folder_item(&self.resource, &self.state, r#" SendMeetingInvitationsOrCancellations="SendToNone""#, item, "CalendarItem", fields).await
+    }
+
+    async fn delete_event(&self, item_url: &Url) -> Result<(), SendError> {
+        delete_folder_item(&self.resource, &self.state, r#" SendMeetingCancellations="SendToNone""#, item_url).await
+    }
+
+    fn forbidden(&self) -> KFError {
+        KFError::Forbidden{ url: self.resource.url().clone() }
+    }
+}
+
+fn ews_event<'a>(item: &'a Item, calendar_url: &Url) -> Result<&'a Event, SendError> {
+    match item {
+        Item::Event(event) => Ok(event),
+        Item::Task(_) | Item::Contact(_) => Err(format!("Item {} cannot be uploaded to {}: only events are supported in Exchange calendar folders", item.url(), calendar_url).into()),
+    }
+}
+
+/// The `RRULE` of a `t:Recurrence` element, or `None` if this crate does not know its pattern
+fn recurrence_to_rrule(recurrence: &Element, is_all_day: bool) -> Option<String> {
+    let mut children = recurrence.children();
+    let (pattern, range) = (children.next()?, children.next()?);
+    let text = |element: &Element, name: &str| find_elem(element, name).map(|elem| elem.text());
+
+    let days = text(pattern, "DaysOfWeek").unwrap_or_default().split_whitespace()
+        // Exchange also knows `Day`, `Weekday` and `WeekendDay`, that have no counterpart in iCal
+        .map(|day| WEEKDAYS.iter().find(|(_, name)| *name == day).map(|(code, _)| *code))
+        .collect::<Option<Vec<_>>>()?
+        .join(",");
+    let position = match text(pattern, "DayOfWeekIndex") {
+        None => 1,
+        Some(index) => WEEK_INDEXES.iter().find(|(_, name)| *name == index)?.0,
+    };
+    let month = match text(pattern, "Month") {
+        None => 0,
+        Some(month) => MONTHS.iter().position(|name| *name == month)? + 1,
+    };
+    let day_of_month = text(pattern, "DayOfMonth").unwrap_or_default();
+    let mut rule = match pattern.name() {
+        "DailyRecurrence" => "FREQ=DAILY".to_string(),
+        "WeeklyRecurrence" => format!("FREQ=WEEKLY;BYDAY={}", days),
+        "AbsoluteMonthlyRecurrence" => format!("FREQ=MONTHLY;BYMONTHDAY={}", day_of_month),
+        "RelativeMonthlyRecurrence" => format!("FREQ=MONTHLY;BYDAY={};BYSETPOS={}", days, position),
+        "AbsoluteYearlyRecurrence" => format!("FREQ=YEARLY;BYMONTH={};BYMONTHDAY={}", month, day_of_month),
+        "RelativeYearlyRecurrence" => format!("FREQ=YEARLY;BYMONTH={};BYDAY={};BYSETPOS={}", month, days, position),
+        _ => return None,
+    };
+    if let Some(interval) = text(pattern, "Interval").and_then(|interval| interval.parse::<u32>().ok()).filter(|interval| *interval > 1) {
+        rule.push_str(&format!(";INTERVAL={}", interval));
+    }
+    if let Some(first_day) = text(pattern, "FirstDayOfWeek") {
+        let code = WEEKDAYS.iter().find(|(_, name)| *name == first_day)?.0;
+        rule.push_str(&format!(";WKST={}", code));
+    }
+    // Dates may be followed by a time zone offset (e.g. `2021-12-31+01:00`)
+    let end_date = text(range, "EndDate").and_then(|date| NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok());
+    match (range.name(), end_date) {
+        ("NumberedRecurrence", _) => rule.push_str(&format!(";COUNT={}", text(range, "NumberOfOccurrences")?)),
+        // UNTIL has the same value type as DTSTART
+        ("EndDateRecurrence", Some(end_date)) if is_all_day => rule.push_str(&format!(";UNTIL={}", end_date.format("%Y%m%d"))),
+        ("EndDateRecurrence", Some(end_date)) => rule.push_str(&format!(";UNTIL={}", end_date.format("%Y%m%dT235959Z"))),
+        _ => (),
+    }
+    Some(rule)
+}
+
+/// The `t:Recurrence` element of an `RRULE`, or `None` if Exchange cannot describe it
+fn rrule_to_recurrence(rrule: &str, start: NaiveDate) -> Option<String> {
+    let parts: HashMap<String, &str> = rrule.split(';')
+        .filter_map(|part| part.split_once('='))
+        .map(|(name, value)| (name.to_ascii_uppercase(), value))
+        .collect();
+    const KNOWN_PARTS: [&str; 9] = ["FREQ", "INTERVAL", "COUNT", "UNTIL", "BYDAY", "BYMONTHDAY", "BYMONTH", "BYSETPOS", "WKST"];
+    if parts.keys().any(|name| !KNOWN_PARTS.contains(&name.as_str())) {
+        return None;
+    }
+    let day_name = |code: &str| WEEKDAYS.iter().find(|(day, _)| day.eq_ignore_ascii_case(code)).map(|(_, name)| *name);
+
+    let interval: u32 = parts.get("INTERVAL").map_or(Some(1), |interval| interval.parse().ok())?;
+    let mut position: Option<i32> = match parts.get("BYSETPOS") {
+        None => None,
+        Some(position) => Some(position.parse().ok()?),
+    };
+    let mut days = Vec::new();
+    for day in parts.get("BYDAY").into_iter().flat_map(|days| days.split(',')) {
+        // e.g. `2TU` or `-1FR`
+        let (ordinal, code) = day.split_at(day.len().checked_sub(2)?);
+        if !ordinal.is_empty() {
+            position = Some(ordinal.trim_start_matches('+').parse().ok()?);
+        }
+        days.push(day_name(code)?);
+    }
+    let index = match position {
+        None => None,
+        Some(position) => Some(WEEK_INDEXES.iter().find(|(n, _)| *n == position)?.1),
+    };
+    let month = match parts.get("BYMONTH") {
+        None => MONTHS[start.month0() as usize],
+        Some(month) => MONTHS.get(month.parse::<usize>().ok()?.checked_sub(1)?)?,
+    };
+    let day_of_month: u32 = parts.get("BYMONTHDAY").map_or(Some(start.day()), |day| day.parse().ok())?;
+
+    let pattern = match (parts.get("FREQ").copied()?, index, days.is_empty()) {
+        ("DAILY", None, true) => format!("<t:DailyRecurrence><t:Interval>{}</t:Interval></t:DailyRecurrence>", interval),
+        ("WEEKLY", None, _) => {
+            if days.is_empty() {
+                days.push(WEEKDAYS[start.weekday().num_days_from_monday() as usize].1);
+            }
+            let first_day = match parts.get("WKST") {
+                None => String::new(),
+                Some(first_day) => format!("<t:FirstDayOfWeek>{}</t:FirstDayOfWeek>", day_name(first_day)?),
+            };
+            format!("<t:WeeklyRecurrence><t:Interval>{}</t:Interval><t:DaysOfWeek>{}</t:DaysOfWeek>{}</t:WeeklyRecurrence>", interval, days.join(" "), first_day)
+        },
+        ("MONTHLY", None, true) => format!("<t:AbsoluteMonthlyRecurrence><t:Interval>{}</t:Interval><t:DayOfMonth>{}</t:DayOfMonth></t:AbsoluteMonthlyRecurrence>", interval, day_of_month),
+        ("MONTHLY", Some(index), false) => format!("<t:RelativeMonthlyRecurrence><t:Interval>{}</t:Interval><t:DaysOfWeek>{}</t:DaysOfWeek><t:DayOfWeekIndex>{}</t:DayOfWeekIndex></t:RelativeMonthlyRecurrence>",
+            interval, days.join(" "), index),
+        // Yearly patterns have no interval
+        ("YEARLY", None, true) if interval == 1 => format!("<t:AbsoluteYearlyRecurrence><t:DayOfMonth>{}</t:DayOfMonth><t:Month>{}</t:Month></t:AbsoluteYearlyRecurrence>", day_of_month, month),
+        ("YEARLY", Some(index), false) if interval == 1 => format!("<t:RelativeYearlyRecurrence><t:DaysOfWeek>{}</t:DaysOfWeek><t:DayOfWeekIndex>{}</t:DayOfWeekIndex><t:Month>{}</t:Month></t:RelativeYearlyRecurrence>",
+            days.join(" "), index, month),
+        _ => return None,
+    };
+
+    let start_date = start.format("%Y-%m-%d");
+    let range = match (parts.get("COUNT"), parts.get("UNTIL")) {
+        (None, None) => format!("<t:NoEndRecurrence><t:StartDate>{}</t:StartDate></t:NoEndRecurrence>", start_date),
+        (Some(count), None) => format!("<t:NumberedRecurrence><t:StartDate>{}</t:StartDate><t:NumberOfOccurrences>{}</t:NumberOfOccurrences></t:NumberedRecurrence>",
+            start_date, count.parse::<u32>().ok()?),
+        (None, Some(until)) => {
+            let end_date = crate::ical::parse_date_value(until)?.naive_utc().date();
+            format!("<t:EndDateRecurrence><t:StartDate>{}</t:StartDate><t:EndDate>{}</t:EndDate></t:EndDateRecurrence>", start_date, end_date.format("%Y-%m-%d"))
+        },
+        (Some(_), Some(_)) => return None,
+    };
+    Some(format!("<t:Recurrence>{}{}</t:Recurrence>", pattern, range))
+}
+
+/// Convert a `t:CalendarItem` element to an item, or return `None` if it is invalid
+fn calendar_item_to_item(calendar_item: &Element, url: Url) -> Option<Item> {
+    let id = find_elem(calendar_item, "ItemId")?;
+    let (id, change_key) = (id.attr("Id")?.to_string(), id.attr("ChangeKey")?.to_string());
+    let text = |name: &str| find_elem(calendar_item, name).map(|elem| elem.text()).filter(|text| !text.is_empty());
+    let is_all_day = text("IsAllDayEvent").as_deref() == Some("true");
+
+    let mut extra_parameters = Vec::new();
+    for (name, field) in [("DTSTART", "Start"), ("DTEND", "End")] {
+        let date = match parse_date(calendar_item, field) {
+            None => continue,
+            Some(date) => date,
+        };
+        let prop = match is_all_day {
+            true => {
+                let date = (date + Duration::hours(12)).naive_utc().date();
+                Property{ name: name.to_string(), params: Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])]), value: Some(date.format("%Y%m%d").to_string()) }
+            },
+            false => Property{ name: name.to_string(), params: None, value: Some(date.format("%Y%m%dT%H%M%SZ").to_string()) },
+        };
+        extra_parameters.push(prop);
+    }
+    for (name, value) in [("DESCRIPTION", text("Body")), ("LOCATION", text("Location"))] {
+        if let Some(value) = value {
+            extra_parameters.push(Property{ name: name.to_string(), params: None, value: Some(value) });
+        }
+    }
+    let categories: Vec<String> = find_elem(calendar_item, "Categories").into_iter()
+        .flat_map(|categories| categories.children().map(|category| category.text()))
+        .collect();
+    if !categories.is_empty() {
+        extra_parameters.push(Property{ name: "CATEGORIES".to_string(), params: None, value: Some(categories.join(",")) });
+    }
+    if let Some(rrule) = find_elem(calendar_item, "Recurrence").and_then(|recurrence| recurrence_to_rrule(recurrence, is_all_day)) {
+        extra_parameters.push(Property{ name: "RRULE".to_string(), params: None, value: Some(rrule) });
+    }
+    let free_busy_status = text("LegacyFreeBusyStatus");
+    let status = match (text("IsCancelled").as_deref(), free_busy_status.as_deref()) {
+        (Some("true"), _) => Some("CANCELLED"),
+        (_, Some("Tentative")) => Some("TENTATIVE"),
+        _ => None,
+    };
+    if let Some(status) = status {
+        extra_parameters.push(Property{ name: "STATUS".to_string(), params: None, value: Some(status.to_string()) });
+    }
+    if free_busy_status.as_deref() == Some("Free") {
+        extra_parameters.push(Property{ name: "TRANSP".to_string(), params: None, value: Some("TRANSPARENT".to_string()) });
+    }
+
+    let reminder = match text("ReminderIsSet").as_deref() {
+        Some("true") => text("ReminderMinutesBeforeStart").and_then(|minutes| minutes.parse::<i64>().ok()),
+        _ => None,
+    };
+    let subject = text("Subject").unwrap_or_default();
+    let mut new_event = Event::new_with_parameters(
+        subject.clone(),
+        text("UID").unwrap_or(id),
+        url,
+        SyncStatus::Synced(VersionTag::from(change_key)),
+        parse_date(calendar_item, "DateTimeCreated"),
+        parse_date(calendar_item, "LastModifiedTime").unwrap_or_else(Utc::now),
+        crate::ical::default_prod_id(),
+        extra_parameters,
+    );
+    if let Some(minutes) = reminder {
+        new_event.set_alarms(vec![Alarm::display(AlarmTrigger::before_start(Duration::minutes(minutes)), &subject)]);
+    }
+    Some(Item::Event(new_event))
+}
+
+/// The fields of an event that are synced, as (EWS field URI, `t:CalendarItem` child element) pairs, in the order of the EWS schema.
+///
+/// Fields that the event does not have have no element, so that they are deleted when an existing item is updated
+fn calendar_item_fields(event: &Event) -> Result<Vec<(&'static str, Option<String>)>, SendError> {
+    let format_date = |date: DateTime<Utc>| date.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let start = event.start()
+        .ok_or_else(|| format!("Event {} has no valid start date, and cannot be uploaded to Exchange", event.url()))?;
+    let end = event.end_or_default().and_then(|end| end.value.as_deref().and_then(crate::ical::parse_date_value))
+        .ok_or_else(|| format!("Event {} has no valid end date, and cannot be uploaded to Exchange", event.url()))?;
+    let is_all_day = event.value("DTSTART").is_some_and(|start| start.len() == 8);
+    let is = |name: &str, value: &str| event.value(name).is_some_and(|actual| actual.eq_ignore_ascii_case(value));
+
+    let recurrence = match event.value("RRULE") {
+        None => None,
+        Some(rrule) => Some(rrule_to_recurrence(rrule, start.naive_utc().date())
+            .ok_or_else(|| format!("The recurrence rule of event {} ({}) cannot be described by Exchange", event.url(), rrule))?),
+    };
+    let categories: String = event.extra_parameters().iter()
+        .filter(|prop| prop.name.eq_ignore_ascii_case("CATEGORIES"))
+        .filter_map(|prop| prop.value.as_deref())
+        .flat_map(|value| value.split(','))
+        .map(|category| format!("<t:String>{}</t:String>", xml_escape(category)))
+        .collect();
+    let free_busy_status = match (is("TRANSP", "TRANSPARENT"), is("STATUS", "TENTATIVE")) {
+        (true, _) => "Free",
+        (false, true) => "Tentative",
+        (false, false) => "Busy",
+    };
+    // Only alarms some time before the start of the event can be reminders
+    let reminder = event.alarms().iter().find_map(|alarm| match alarm.trigger() {
+        Some(AlarmTrigger::Relative{ offset, related_to_end: false }) if offset <= Duration::zero() => Some(-offset.num_minutes()),
+        _ => None,
+    });
+
+    let mut fields = vec![
+        ("item:Subject", Some(format!("<t:Subject>{}</t:Subject>", xml_escape(event.name())))),
+        ("item:Body", Some(format!(r#"<t:Body BodyType="Text">{}</t:Body>"#, xml_escape(event.value("DESCRIPTION").unwrap_or_default())))),
+        ("item:Categories", Some(categories).filter(|categories| !categories.is_empty()).map(|categories| format!("<t:Categories>{}</t:Categories>", categories))),
+        ("item:ReminderIsSet", Some(format!("<t:ReminderIsSet>{}</t:ReminderIsSet>", reminder.is_some()))),
+    ];
+    // Exchange always has a reminder time, even when reminders are not set
+    if let Some(minutes) = reminder {
+        fields.push(("item:ReminderMinutesBeforeStart", Some(format!("<t:ReminderMinutesBeforeStart>{}</t:ReminderMinutesBeforeStart>", minutes))));
+    }
+    fields.extend([
+        ("calendar:UID", Some(format!("<t:UID>{}</t:UID>", xml_escape(event.uid())))),
+        ("calendar:Start", Some(format!("<t:Start>{}</t:Start>", format_date(start)))),
+        ("calendar:End", Some(format!("<t:End>{}</t:End>", format_date(end)))),
+        ("calendar:IsAllDayEvent", Some(format!("<t:IsAllDayEvent>{}</t:IsAllDayEvent>", is_all_day))),
+        ("calendar:LegacyFreeBusyStatus", Some(format!("<t:LegacyFreeBusyStatus>{}</t:LegacyFreeBusyStatus>", free_busy_status))),
+        ("calendar:Location", event.value("LOCATION").map(|location| format!("<t:Location>{}</t:Location>", xml_escape(location)))),
+        ("calendar:Recurrence", recurrence),
+    ]);
+    Ok(fields)
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for EwsCalendar {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.create_event(&item).await.map_err(KFError::from)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.update_event(&item).await.map_err(KFError::from)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for EwsCalendar {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        Self {
+            name, resource, supported_components, color,
+            state: Mutex::new(None),
+            assigned_urls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_state().await?;
+        Ok(self.state.lock().unwrap().as_ref().map(|state| state.version_tags.clone()).unwrap_or_default())
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        // Items have to be downloaded to be filtered
+        let all_tags = self.get_item_version_tags().await?;
+        let urls: Vec<Url> = all_tags.keys().cloned().collect();
+        let items = self.get_events(&urls).await?;
+        Ok(items.into_iter().flatten()
+            .filter(|item| filter.matches(item))
+            .filter_map(|item| all_tags.get(item.url()).map(|tag| (item.url().clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        let items = self.get_events(std::slice::from_ref(url)).await?;
+        Ok(items.into_iter().next().flatten())
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        self.get_events(urls).await.map_err(KFError::from)
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.delete_event(item_url).await.map_err(KFError::from)
+    }
+
+    async fn move_item(&mut self, item_url: &Url, _destination: &Url) -> Result<SyncStatus, KFError> {
+        // Exchange gives moved items new IDs
+        Err(KFError::MoveNotSupported{ url: item_url.clone() })
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, _max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let mut results = Vec::new();
+        for item in items {
+            // Exchange servers throttle concurrent requests of the same user
+            let result = self.create_event(&item).await;
+            results.push(result);
+        }
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, _max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let mut results = Vec::new();
+        for item in items {
+            let result = self.update_event(&item).await;
+            results.push(result);
+        }
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], _max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let mut results = Vec::new();
+        for url in item_urls {
+            let result = self.delete_event(url).await;
+            results.push(result);
+        }
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    fn take_assigned_url(&mut self, url: &Url) -> Option<Url> {
+        self.assigned_urls.lock().unwrap().remove(url)
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        // This is the first request of a sync, that always downloads the latest changes
+        self.refresh().await?;
+        let state = self.state.lock().unwrap();
+        Ok(CalendarVersion{
+            ctag: Some(folder_version(&state.as_ref().map(|state| state.version_tags.clone()).unwrap_or_default())),
+            sync_token: state.as_ref().and_then(|state| state.sync_state.clone()),
+        })
+    }
+
+    fn resume_from(&mut self, version: &CalendarVersion, version_tags: HashMap<Url, VersionTag>) {
+        let mut state = self.state.lock().unwrap();
+        if let (None, Some(sync_state)) = (state.as_ref(), &version.sync_token) {
+            *state = Some(FolderState{ version_tags, sync_state: Some(sync_state.clone()) });
+        }
+    }
+
+    async fn update_color(&mut self, _color: Option<Color>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_description(&mut self, _description: Option<String>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_timezone(&mut self, _timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        let body = format!(r#"<m:UpdateFolder>
+                <m:FolderChanges><t:FolderChange>
+                    <t:FolderId Id="{}"/>
+                    <t:Updates><t:SetFolderField>
+                        <t:FieldURI FieldURI="folder:DisplayName"/>
+                        <t:CalendarFolder><t:DisplayName>{}</t:DisplayName></t:CalendarFolder>
+                    </t:SetFolderField></t:Updates>
+                </t:FolderChange></m:FolderChanges>
+            </m:UpdateFolder>"#, xml_escape(&self.id()), xml_escape(&name));
+        let reply = soap_request(&folder_endpoint(&self.resource), &body).await?;
+        for message in response_messages(&reply, self.resource.url()) {
+            message?;
+        }
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_conversion() {
+        let url: Url = "https://mail.example.com/EWS/Exchange.asmx/folders/AAMk%2Bf%2F1/AAMk%2Bi%2F1".parse().unwrap();
+        let element: Element = r#"<t:Task xmlns:t="http://schemas.microsoft.com/exchange/services/2006/types">
+            <t:ItemId Id="AAMk+i/1" ChangeKey="EwAAA"/>
+            <t:Subject>Water the plants &amp; the garden</t:Subject>
+            <t:Body BodyType="Text">The ones in the kitchen</t:Body>
+            <t:Importance>Low</t:Importance>
+            <t:ReminderDueBy>2021-03-01T09:00:00Z</t:ReminderDueBy>
+            <t:ReminderIsSet>true</t:ReminderIsSet>
+            <t:LastModifiedTime>2021-03-01T10:00:00Z</t:LastModifiedTime>
+            <t:DueDate>2021-03-02T00:00:00Z</t:DueDate>
+            <t:Status>Completed</t:Status>
+        </t:Task>"#.parse().unwrap();
+        let item = element_to_item(&element, url).unwrap();
+        let task = item.unwrap_task();
+        assert_eq!(task.uid(), "AAMk+i/1");
+        assert_eq!(task.name(), "Water the plants & the garden");
+        assert_eq!(task.sync_status(), &SyncStatus::Synced(VersionTag::from("EwAAA".to_string())));
+        assert!(task.completed());
+        assert!(task.extra_parameters().iter().any(|prop| prop.name == "PRIORITY" && prop.value.as_deref() == Some("9")));
+        assert!(task.extra_parameters().iter().any(|prop| prop.name == "DUE" && prop.value.as_deref() == Some("20210302")));
+        assert_eq!(task.alarms()[0].trigger(), Some(AlarmTrigger::Absolute("2021-03-01T09:00:00Z".parse().unwrap())));
+
+        let fields: HashMap<_, _> = task_fields(task).into_iter().collect();
+        assert_eq!(fields["item:Subject"].as_deref(), Some("<t:Subject>Water the plants &amp; the garden</t:Subject>"));
+        assert_eq!(fields["item:Importance"].as_deref(), Some("<t:Importance>Low</t:Importance>"));
+        assert_eq!(fields["item:ReminderDueBy"].as_deref(), Some("<t:ReminderDueBy>2021-03-01T09:00:00Z</t:ReminderDueBy>"));
+        assert_eq!(fields["task:DueDate"].as_deref(), Some("<t:DueDate>2021-03-02T00:00:00Z</t:DueDate>"));
+        assert_eq!(fields["task:Status"].as_deref(), Some("<t:Status>Completed</t:Status>"));
+
+        // Fields a task does not have are deleted
+        let task = Task::new("Call Bob".to_string(), false, &"https://mail.example.com/EWS/Exchange.asmx/folders/AAMk/".parse().unwrap());
+        let fields: HashMap<_, _> = task_fields(&task).into_iter().collect();
+        assert_eq!(fields["task:DueDate"], None);
+        assert_eq!(fields["item:ReminderDueBy"], None);
+        assert_eq!(fields["item:ReminderIsSet"].as_deref(), Some("<t:ReminderIsSet>false</t:ReminderIsSet>"));
+    }
+
+    #[test]
+    fn test_calendar_item_conversion() {
+        let url: Url = "https://mail.example.com/EWS/Exchange.asmx/folders/AAMk%2Bc%2F1/AAMk%2Be%2F1".parse().unwrap();
+        let element: Element = r#"<t:CalendarItem xmlns:t="http://schemas.microsoft.com/exchange/services/2006/types">
+            <t:ItemId Id="AAMk+e/1" ChangeKey="DwAAA"/>
+            <t:Subject>Team meeting</t:Subject>
+            <t:Body BodyType="Text">Weekly sync</t:Body>
+            <t:Categories><t:String>Work</t:String><t:String>Meetings</t:String></t:Categories>
+            <t:LastModifiedTime>2021-03-01T10:00:00Z</t:LastModifiedTime>
+            <t:ReminderIsSet>true</t:ReminderIsSet>
+            <t:ReminderMinutesBeforeStart>15</t:ReminderMinutesBeforeStart>
+            <t:UID>040000008200E001</t:UID>
+            <t:Start>2021-04-01T08:00:00Z</t:Start>
+            <t:End>2021-04-01T09:00:00Z</t:End>
+            <t:IsAllDayEvent>false</t:IsAllDayEvent>
+            <t:LegacyFreeBusyStatus>Tentative</t:LegacyFreeBusyStatus>
+            <t:Location>Room 4</t:Location>
+            <t:Recurrence>
+                <t:RelativeMonthlyRecurrence><t:Interval>2</t:Interval><t:DaysOfWeek>Thursday</t:DaysOfWeek><t:DayOfWeekIndex>First</t:DayOfWeekIndex></t:RelativeMonthlyRecurrence>
+                <t:EndDateRecurrence><t:StartDate>2021-04-01+02:00</t:StartDate><t:EndDate>2021-12-31+01:00</t:EndDate></t:EndDateRecurrence>
+            </t:Recurrence>
+        </t:CalendarItem>"#.parse().unwrap();
+        let item = calendar_item_to_item(&element, url).unwrap();
+        let event = item.unwrap_event();
+        assert_eq!(event.uid(), "040000008200E001");
+        assert_eq!(event.name(), "Team meeting");
+        assert_eq!(event.sync_status(), &SyncStatus::Synced(VersionTag::from("DwAAA".to_string())));
+        assert_eq!(event.value("DTSTART"), Some("20210401T080000Z"));
+        assert_eq!(event.value("LOCATION"), Some("Room 4"));
+        assert_eq!(event.value("CATEGORIES"), Some("Work,Meetings"));
+        assert_eq!(event.value("STATUS"), Some("TENTATIVE"));
+        assert_eq!(event.value("RRULE"), Some("FREQ=MONTHLY;BYDAY=TH;BYSETPOS=1;INTERVAL=2;UNTIL=20211231T235959Z"));
+        assert_eq!(event.alarms()[0].trigger(), Some(AlarmTrigger::before_start(Duration::minutes(15))));
+
+        let fields: HashMap<_, _> = calendar_item_fields(event).unwrap().into_iter().collect();
+        assert_eq!(fields["item:Categories"].as_deref(), Some("<t:Categories><t:String>Work</t:String><t:String>Meetings</t:String></t:Categories>"));
+        assert_eq!(fields["item:ReminderMinutesBeforeStart"].as_deref(), Some("<t:ReminderMinutesBeforeStart>15</t:ReminderMinutesBeforeStart>"));
+        assert_eq!(fields["calendar:UID"].as_deref(), Some("<t:UID>040000008200E001</t:UID>"));
+        assert_eq!(fields["calendar:Start"].as_deref(), Some("<t:Start>2021-04-01T08:00:00Z</t:Start>"));
+        assert_eq!(fields["calendar:LegacyFreeBusyStatus"].as_deref(), Some("<t:LegacyFreeBusyStatus>Tentative</t:LegacyFreeBusyStatus>"));
+        assert_eq!(fields["calendar:Recurrence"].as_deref(), Some("<t:Recurrence><t:RelativeMonthlyRecurrence><t:Interval>2</t:Interval><t:DaysOfWeek>Thursday</t:DaysOfWeek>\
+            <t:DayOfWeekIndex>First</t:DayOfWeekIndex></t:RelativeMonthlyRecurrence><t:EndDateRecurrence><t:StartDate>2021-04-01</t:StartDate>\
+            <t:EndDate>2021-12-31</t:EndDate></t:EndDateRecurrence></t:Recurrence>"));
+
+        // Whole-day events start at the midnight of the time zone they have been created in
+        let element: Element = r#"<t:CalendarItem xmlns:t="http://schemas.microsoft.com/exchange/services/2006/types">
+            <t:ItemId Id="AAMk+e/2" ChangeKey="DwAAB"/>
+            <t:Subject>Holidays</t:Subject>
+            <t:Start>2021-04-29T22:00:00Z</t:Start>
+            <t:End>2021-04-30T22:00:00Z</t:End>
+            <t:IsAllDayEvent>true</t:IsAllDayEvent>
+            <t:LegacyFreeBusyStatus>Free</t:LegacyFreeBusyStatus>
+        </t:CalendarItem>"#.parse().unwrap();
+        let item = calendar_item_to_item(&element, "https://mail.example.com/EWS/Exchange.asmx/folders/AAMk%2Bc%2F1/AAMk%2Be%2F2".parse().unwrap()).unwrap();
+        let event = item.unwrap_event();
+        assert_eq!(event.uid(), "AAMk+e/2");
+        assert_eq!(event.value("DTSTART"), Some("20210430"));
+        assert_eq!(event.value("DTEND"), Some("20210501"));
+        assert_eq!(event.value("TRANSP"), Some("TRANSPARENT"));
+
+        // Fields an event does not have are deleted
+        let fields: HashMap<_, _> = calendar_item_fields(event).unwrap().into_iter().collect();
+        assert_eq!(fields["calendar:IsAllDayEvent"].as_deref(), Some("<t:IsAllDayEvent>true</t:IsAllDayEvent>"));
+        assert_eq!(fields["calendar:Start"].as_deref(), Some("<t:Start>2021-04-30T00:00:00Z</t:Start>"));
+        assert_eq!(fields["calendar:LegacyFreeBusyStatus"].as_deref(), Some("<t:LegacyFreeBusyStatus>Free</t:LegacyFreeBusyStatus>"));
+        assert_eq!(fields["item:ReminderIsSet"].as_deref(), Some("<t:ReminderIsSet>false</t:ReminderIsSet>"));
+        assert_eq!(fields["calendar:Location"], None);
+        assert_eq!(fields["calendar:Recurrence"], None);
+        assert!(!fields.contains_key("item:ReminderMinutesBeforeStart"));
+
+        // Exchange cannot describe every recurrence
+        let mut event = event.clone();
+        event.set_property(Property{ name: "RRULE".to_string(), params: None, value: Some("FREQ=HOURLY;INTERVAL=2".to_string()) });
+        assert!(calendar_item_fields(&event).is_err());
+    }
+
+    #[test]
+    fn test_recurrences() {
+        let start = NaiveDate::from_ymd(2021, 4, 1);
+        for rrule in ["FREQ=DAILY;INTERVAL=3;COUNT=10", "FREQ=WEEKLY;BYDAY=MO,WE;WKST=SU", "FREQ=MONTHLY;BYMONTHDAY=15",
+                      "FREQ=YEARLY;BYMONTH=4;BYMONTHDAY=1", "FREQ=YEARLY;BYMONTH=11;BYDAY=TH;BYSETPOS=4"] {
+            let recurrence: Element = rrule_to_recurrence(rrule, start).unwrap()
+                .replace("<t:Recurrence>", r#"<t:Recurrence xmlns:t="http://schemas.microsoft.com/exchange/services/2006/types">"#)
+                .parse().unwrap();
+            assert_eq!(recurrence_to_rrule(&recurrence, false).as_deref(), Some(rrule));
+        }
+        // Ordinals can be given in BYDAY
+        let recurrence = rrule_to_recurrence("FREQ=MONTHLY;BYDAY=-1FR", start).unwrap();
+        assert!(recurrence.contains("<t:DaysOfWeek>Friday</t:DaysOfWeek><t:DayOfWeekIndex>Last</t:DayOfWeekIndex>"));
+        // Weekly rules default to the day of their start
+        let recurrence = rrule_to_recurrence("FREQ=WEEKLY", start).unwrap();
+        assert!(recurrence.contains("<t:DaysOfWeek>Thursday</t:DaysOfWeek>"));
+        assert!(rrule_to_recurrence("FREQ=MONTHLY;BYDAY=5SU", start).is_none());
+        assert!(rrule_to_recurrence("FREQ=YEARLY;INTERVAL=2", start).is_none());
+        assert!(rrule_to_recurrence("FREQ=DAILY;BYHOUR=9", start).is_none());
+    }
+}
@@ -0,0 +1,1114 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+use csscolorparser::Color;
+use futures_util::stream::{self, StreamExt};
+use ical::property::Property;
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use url::Url;
+
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::calendar::remote_calendar::SendError;
+use crate::item::Item;
+use crate::item::VersionTag;
+use crate::item::SyncStatus;
+use crate::task::{CompletionStatus, Task};
+use crate::event::Event;
+use crate::resource::Resource;
+use crate::provider::filter::SyncFilter;
+use crate::error::KFError;
+use crate::google::Page;
+use crate::rest::send_json;
+use crate::kv_store::checksum;
+
+/// A task, as described by the Google Tasks API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskResource {
+    id: String,
+    etag: String,
+    title: Option<String>,
+    notes: Option<String>,
+    status: Option<String>,
+    due: Option<DateTime<Utc>>,
+    completed: Option<DateTime<Utc>>,
+    updated: Option<DateTime<Utc>>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+/// The tasks of a list, as they have last been downloaded
+#[derive(Debug)]
+struct Listing {
+    items: HashMap<Url, Item>,
+    /// The latest modification date of these tasks, so that the next download only asks for the tasks that have changed since
+    updated_min: Option<DateTime<Utc>>,
+}
+
+
+
+/// A task list of a Google account, that is synced with the Google Tasks API.
+///
+/// It is created by a [`GoogleTasks`](crate::google::GoogleTasks) source. \
+/// Tasks are converted to iCal items: their titles, statuses, completion dates, notes (as `DESCRIPTION`) and due dates (as `DUE`) are synced.
+/// Other iCal properties (e.g. alarms) have no counterpart in Google Tasks, and are lost when tasks are downloaded. \
+/// Google chooses the IDs of new tasks: the URL of a task is its API URL, and its UID is its ID. A [`Provider`](crate::provider::Provider) moves the tasks it adds
+/// to these URLs (see [`DavCalendar::take_assigned_url`]).
+///
+/// The whole list is downloaded at the first sync. Later syncs only download the tasks that have changed since (using the `updatedMin` parameter of the API).
+#[derive(Debug)]
+pub struct GoogleTaskList {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+
+    listing: Mutex<Option<Listing>>,
+    assigned_urls: Mutex<HashMap<Url, Url>>,
+}
+
+impl GoogleTaskList {
+    /// The ID of this task list
+    pub fn id(&self) -> &str {
+        let mut segments = self.resource.url().path_segments().into_iter().flatten().rev().filter(|segment| !segment.is_empty());
+        // The URL ends with `/lists/{id}/tasks/`
+        segments.nth(1).unwrap_or_default()
+    }
+
+    /// The URL of the task that has a given ID
+    pub fn item_url(&self, id: &str) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(id);
+        }
+        url
+    }
+
+    /// The URL tasks are listed and created at (this is the URL of this calendar, without its trailing slash)
+    fn collection_url(&self) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty();
+        }
+        url
+    }
+
+    /// Download the tasks that have changed since the last download (or every task, the first time)
+    async fn refresh(&self) -> Result<(), SendError> {
+        let updated_min = self.listing.lock().unwrap().as_ref().map(|listing| listing.updated_min);
+        let mut tasks = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut url = self.collection_url();
+            url.query_pairs_mut()
+                .append_pair("maxResults", "100")
+                .append_pair("showCompleted", "true")
+                .append_pair("showHidden", "true")
+                .append_pair("showDeleted", "true");
+            if let Some(Some(updated_min)) = &updated_min {
+                url.query_pairs_mut().append_pair("updatedMin", &updated_min.to_rfc3339_opts(SecondsFormat::Millis, true));
+            }
+            if let Some(token) = &page_token {
+                url.query_pairs_mut().append_pair("pageToken", token);
+            }
+            let reply = send_json(&self.resource, Method::GET, url, None, None).await?;
+            let page: Page<TaskResource> = serde_json::from_str(&reply)?;
+            tasks.extend(page.items);
+            match page.next_page_token {
+                None => break,
+                token => page_token = token,
+            }
+        }
+
+        let mut listing = self.listing.lock().unwrap();
+        let listing = match (listing.as_mut(), updated_min) {
+            (Some(listing), Some(_)) => listing,
+            // This was a full download
+            _ => listing.insert(Listing{ items: HashMap::new(), updated_min: None }),
+        };
+        for task in tasks {
+            listing.updated_min = listing.updated_min.max(task.updated);
+            let url = self.item_url(&task.id);
+            match task.deleted {
+                true => { listing.items.remove(&url); },
+                false => { listing.items.insert(url.clone(), task_to_item(task, url)); },
+            }
+        }
+        Ok(())
+    }
+
+    /// Download the tasks, unless they have been downloaded already
+    async fn ensure_listing(&self) -> Result<(), SendError> {
+        if self.listing.lock().unwrap().is_none() {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    /// Update the downloaded tasks with a task that has just been uploaded, and return its sync status
+    fn store_uploaded(&self, reply: &str) -> Result<(Url, SyncStatus), SendError> {
+        let task: TaskResource = serde_json::from_str(reply)?;
+        let url = self.item_url(&task.id);
+        let sync_status = SyncStatus::Synced(VersionTag::from(task.etag.clone()));
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.items.insert(url.clone(), task_to_item(task, url.clone()));
+        }
+        Ok((url, sync_status))
+    }
+
+    async fn insert_task(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let task = google_task(item, self.resource.url())?;
+        let reply = send_json(&self.resource, Method::POST, self.collection_url(), Some(&task_to_json(task)), None).await?;
+        let (url, sync_status) = self.store_uploaded(&reply)?;
+        self.assigned_urls.lock().unwrap().insert(item.url().clone(), url);
+        Ok(sync_status)
+    }
+
+    async fn patch_task(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let task = google_task(item, self.resource.url())?;
+        let old_tag = match item.sync_status() {
+            SyncStatus::NotSynced => return Err("Cannot update an item that has not been synced already".into()),
+            SyncStatus::Synced(_) => return Err("Cannot update an item that has not changed".into()),
+            SyncStatus::LocallyModified(tag) => tag,
+            SyncStatus::LocallyDeleted(tag) => tag,
+        };
+        let reply = send_json(&self.resource, Method::PATCH, item.url().clone(), Some(&task_to_json(task)), Some(old_tag)).await?;
+        let (_, sync_status) = self.store_uploaded(&reply)?;
+        Ok(sync_status)
+    }
+
+    async fn delete_task(&self, item_url: &Url) -> Result<(), SendError> {
+        send_json(&self.resource, Method::DELETE, item_url.clone(), None, None).await?;
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.items.remove(item_url);
+        }
+        Ok(())
+    }
+
+    fn forbidden(&self) -> KFError {
+        KFError::Forbidden{ url: self.resource.url().clone() }
+    }
+}
+
+fn google_task<'a>(item: &'a Item, calendar_url: &Url) -> Result<&'a Task, SendError> {
+    match item {
+        Item::Task(task) => Ok(task),
+        Item::Event(_) | Item::Contact(_) => Err(format!("Item {} cannot be uploaded to {}: Google task lists only contain tasks", item.url(), calendar_url).into()),
+    }
+}
+
+/// Convert a task of the Google Tasks API to an item
+fn task_to_item(task: TaskResource, url: Url) -> Item {
+    let completion_status = match task.status.as_deref() {
+        Some("completed") => CompletionStatus::Completed(task.completed),
+        _ => CompletionStatus::Uncompleted,
+    };
+    let mut extra_parameters = Vec::new();
+    if let Some(notes) = task.notes.filter(|notes| !notes.is_empty()) {
+        extra_parameters.push(Property{ name: "DESCRIPTION".to_string(), params: None, value: Some(notes) });
+    }
+    if let Some(due) = task.due {
+        // Google Tasks only store the date of due dates
+        let params = Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])]);
+        extra_parameters.push(Property{ name: "DUE".to_string(), params, value: Some(due.format("%Y%m%d").to_string()) });
+    }
+
+    Item::Task(Task::new_with_parameters(
+        task.title.unwrap_or_default(),
+        task.id,
+        url,
+        completion_status,
+        SyncStatus::Synced(VersionTag::from(task.etag)),
+        None,
+        task.updated.unwrap_or_else(Utc::now),
+        crate::ical::default_prod_id(),
+        extra_parameters,
+    ))
+}
+
+/// The JSON body that sets the fields of a Google task to the ones of a task.
+///
+/// Fields that the task does not have are set to `null`, so that they are cleared when an existing Google task is updated
+fn task_to_json(task: &Task) -> serde_json::Value {
+    let property = |name: &str| task.extra_parameters().iter()
+        .find(|prop| prop.name.eq_ignore_ascii_case(name))
+        .and_then(|prop| prop.value.as_deref());
+    let due = property("DUE")
+        .and_then(crate::ical::parse_date_value)
+        .map(|due| due.format("%Y-%m-%dT00:00:00.000Z").to_string());
+
+    let mut json = serde_json::json!({
+        "title": task.name(),
+        "status": if task.completed() { "completed" } else { "needsAction" },
+        "notes": property("DESCRIPTION"),
+        "due": due,
+    });
+    match task.completion_status() {
+        CompletionStatus::Completed(Some(date)) => { json["completed"] = date.to_rfc3339_opts(SecondsFormat::Millis, true).into(); },
+        // Google sets the completion date itself
+        CompletionStatus::Completed(None) => (),
+        CompletionStatus::Uncompleted => { json["completed"] = serde_json::Value::Null; },
+    }
+    json
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for GoogleTaskList {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.insert_task(&item).await.map_err(KFError::from)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.patch_task(&item).await.map_err(KFError::from)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for GoogleTaskList {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        Self {
+            name, resource, supported_components, color,
+            listing: Mutex::new(None),
+            assigned_urls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| &listing.items)
+            .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| &listing.items)
+            .filter(|(_, item)| filter.matches(item))
+            .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        self.ensure_listing().await?;
+        Ok(self.listing.lock().unwrap().as_ref().and_then(|listing| listing.items.get(url).cloned()))
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(urls.iter()
+            .map(|url| listing.as_ref().and_then(|listing| listing.items.get(url).cloned()))
+            .collect())
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.delete_task(item_url).await.map_err(KFError::from)
+    }
+
+    async fn move_item(&mut self, item_url: &Url, _destination: &Url) -> Result<SyncStatus, KFError> {
+        // The destination URL cannot be chosen
+        Err(KFError::MoveNotSupported{ url: item_url.clone() })
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.insert_task(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.patch_task(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(item_urls.to_vec())
+            .map(|url| async move { this.delete_task(&url).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    fn take_assigned_url(&mut self, url: &Url) -> Option<Url> {
+        self.assigned_urls.lock().unwrap().remove(url)
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        // This is the first request of a sync, that always downloads the latest changes
+        self.refresh().await?;
+        let mut tags: Vec<_> = self.get_item_version_tags().await?.into_iter()
+            .map(|(url, tag)| format!("{} {}\n", url, tag.as_str()))
+            .collect();
+        tags.sort();
+        Ok(CalendarVersion{ ctag: Some(format!("{:x}", checksum(tags.concat().as_bytes()))), sync_token: None })
+    }
+
+    async fn update_color(&mut self, _color: Option<Color>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_description(&mut self, _description: Option<String>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_timezone(&mut self, _timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            // From `/lists/{id}/tasks/` to `/users/@me/lists/{id}`
+            let id = self.id().to_string();
+            segments.pop_if_empty().pop().pop().pop().extend(&["users", "@me", "lists", &id]);
+        }
+        send_json(&self.resource, Method::PATCH, url, Some(&serde_json::json!({ "title": name })), None).await
+            ?;
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+}
+
+
+/// The start or the end of an event, as described by the Google Calendar API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventDateTime {
+    /// The date of whole-day events
+    date: Option<NaiveDate>,
+    date_time: Option<DateTime<FixedOffset>>,
+    time_zone: Option<String>,
+}
+
+impl EventDateTime {
+    /// The iCal property (e.g. `DTSTART`) this describes.
+    ///
+    /// Times are returned in the time zone of the calendar: they keep their `TZID` when the event is in this time zone as well, and are converted to UTC otherwise
+    fn to_property(&self, name: &str, calendar_time_zone: Option<&str>) -> Option<Property> {
+        let (params, value) = match (&self.date, &self.date_time) {
+            (Some(date), _) => (Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])]), date.format("%Y%m%d").to_string()),
+            (None, Some(date_time)) => match self.time_zone.as_deref() {
+                Some(time_zone) if Some(time_zone) == calendar_time_zone => {
+                    (Some(vec![("TZID".to_string(), vec![time_zone.to_string()])]), date_time.format("%Y%m%dT%H%M%S").to_string())
+                },
+                _ => (None, date_time.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string()),
+            },
+            (None, None) => return None,
+        };
+        Some(Property{ name: name.to_string(), params, value: Some(value) })
+    }
+}
+
+/// The extended properties of an event
+#[derive(Debug, Default, Deserialize)]
+struct ExtendedProperties {
+    #[serde(default)]
+    private: BTreeMap<String, String>,
+}
+
+/// An event, as described by the Google Calendar API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventResource {
+    id: String,
+    etag: String,
+    /// Deleted events are only listed by incremental listings, with the `cancelled` status
+    status: Option<String>,
+    summary: Option<String>,
+    description: Option<String>,
+    location: Option<String>,
+    start: Option<EventDateTime>,
+    end: Option<EventDateTime>,
+    #[serde(default)]
+    recurrence: Vec<String>,
+    /// This is set on the exceptions of recurring events
+    recurring_event_id: Option<String>,
+    #[serde(rename = "iCalUID")]
+    ical_uid: Option<String>,
+    transparency: Option<String>,
+    created: Option<DateTime<Utc>>,
+    updated: Option<DateTime<Utc>>,
+    extended_properties: Option<ExtendedProperties>,
+}
+
+/// A page of a listing of events
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventPage {
+    #[serde(default = "Vec::new")]
+    items: Vec<EventResource>,
+    next_page_token: Option<String>,
+    /// The token that lists the changes made since this listing. It is only given in the last page
+    next_sync_token: Option<String>,
+    /// The time zone of the calendar
+    time_zone: Option<String>,
+}
+
+/// The events of a calendar, as they have last been listed
+#[derive(Debug, Default)]
+struct EventListing {
+    version_tags: HashMap<Url, VersionTag>,
+    /// The events that have been downloaded. Events that have been listed in a previous session (see [`DavCalendar::resume_from`]) are downloaded when they are requested
+    items: HashMap<Url, Item>,
+    /// The token that lists the changes made since this listing
+    sync_token: Option<String>,
+}
+
+/// The iCal properties that have a counterpart in the fields of Google events
+const MAPPED_PROPERTIES: [&str; 15] = ["DTSTART", "DTEND", "DURATION", "DESCRIPTION", "LOCATION", "RRULE", "EXRULE", "RDATE", "EXDATE",
+    "STATUS", "TRANSP", "SEQUENCE", "DTSTAMP", "CREATED", "LAST-MODIFIED"];
+/// The iCal properties that are stored in the `recurrence` field of Google events
+const RECURRENCE_PROPERTIES: [&str; 4] = ["RRULE", "EXRULE", "RDATE", "EXDATE"];
+/// The maximum lengths of the keys and values of extended properties
+const MAX_EXTENDED_KEY_LEN: usize = 44;
+const MAX_EXTENDED_VALUE_LEN: usize = 1024;
+
+
+
+/// A calendar of a Google account, that is synced with the Google Calendar API.
+///
+/// It is created by a [`GoogleCalendars`](crate::google::GoogleCalendars) source. \
+/// Events are converted to iCal items: their summaries, descriptions, locations, start and end dates, recurrence rules (and their exceptions dates),
+/// statuses and transparencies are synced. Times keep their `TZID` when they are in the time zone of the calendar, and are converted to UTC otherwise.
+/// The other iCal properties are stored in the private extended properties of Google events (one per property, as long as they are short enough to be stored there),
+/// so that they are kept when events are downloaded again. Reminders are not synced (Google keeps its own, and alarms of downloaded events are empty). \
+/// Modified instances of recurring events are separate events in Google Calendar, that have no counterpart in this crate: they are not synced. \
+/// Google chooses the IDs of new events: the URL of an event is its API URL. Its UID is its `iCalUID`, that is set when the event is uploaded.
+/// A [`Provider`](crate::provider::Provider) moves the events it adds to these URLs (see [`DavCalendar::take_assigned_url`]).
+///
+/// The whole calendar is listed at the first sync. Later syncs only list the changes made since (using the `syncToken` parameter of the API),
+/// even after the app is restarted (see [`DavCalendar::resume_from`]). When Google has expired a sync token, the whole calendar is listed again.
+#[derive(Debug)]
+pub struct GoogleCalendar {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+
+    listing: Mutex<Option<EventListing>>,
+    /// The time zone of this calendar, as given by the last listing
+    time_zone: Mutex<Option<String>>,
+    assigned_urls: Mutex<HashMap<Url, Url>>,
+}
+
+impl GoogleCalendar {
+    /// The ID of this calendar (e.g. `primary`, or an email address)
+    pub fn id(&self) -> String {
+        let mut segments = self.resource.url().path_segments().into_iter().flatten().rev().filter(|segment| !segment.is_empty());
+        // The URL ends with `/calendars/{id}/events/`
+        let id = segments.nth(1).unwrap_or_default();
+        percent_encoding::percent_decode_str(id).decode_utf8_lossy().into_owned()
+    }
+
+    /// The URL of the event that has a given ID
+    pub fn item_url(&self, id: &str) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(id);
+        }
+        url
+    }
+
+    /// The URL events are listed and created at (this is the URL of this calendar, without its trailing slash)
+    fn collection_url(&self) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty();
+        }
+        url
+    }
+
+    /// The URL of the calendar itself (i.e. `/calendars/{id}`)
+    fn metadata_url(&self) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().pop();
+        }
+        url
+    }
+
+    fn calendar_time_zone(&self) -> Option<String> {
+        self.time_zone.lock().unwrap().clone()
+    }
+
+    /// List the changes made since the last listing (or every event, the first time)
+    async fn refresh(&self) -> Result<(), SendError> {
+        let sync_token = self.listing.lock().unwrap().as_ref().and_then(|listing| listing.sync_token.clone());
+        let (events, next_sync_token, incremental) = match sync_token {
+            None => {
+                let (events, token) = self.fetch_events(None).await?;
+                (events, token, false)
+            },
+            Some(sync_token) => match self.fetch_events(Some(&sync_token)).await {
+                Err(err) if err.downcast_ref::<KFError>().is_some_and(|err| matches!(err, KFError::Http{ status: StatusCode::GONE, .. })) => {
+                    log::info!("The sync token of {} has expired, listing every event again", self.resource.url());
+                    let (events, token) = self.fetch_events(None).await?;
+                    (events, token, false)
+                },
+                result => {
+                    let (events, token) = result?;
+                    (events, token, true)
+                },
+            },
+        };
+
+        let time_zone = self.calendar_time_zone();
+        let mut listing = self.listing.lock().unwrap();
+        let listing = match (listing.as_mut(), incremental) {
+            (Some(listing), true) => listing,
+            // This was a full listing
+            _ => listing.insert(EventListing::default()),
+        };
+        for event in events {
+            if event.recurring_event_id.is_some() {
+                log::debug!("Ignoring event {}, which is an instance of a recurring event", event.id);
+                continue;
+            }
+            let url = self.item_url(&event.id);
+            match event.status.as_deref() {
+                Some("cancelled") => {
+                    listing.version_tags.remove(&url);
+                    listing.items.remove(&url);
+                },
+                _ => {
+                    listing.version_tags.insert(url.clone(), VersionTag::from(event.etag.clone()));
+                    listing.items.insert(url.clone(), event_to_item(event, url, time_zone.as_deref()));
+                },
+            }
+        }
+        listing.sync_token = next_sync_token;
+        Ok(())
+    }
+
+    /// List every event (or the changes made since a sync token), and return them with the token of the next changes
+    async fn fetch_events(&self, sync_token: Option<&str>) -> Result<(Vec<EventResource>, Option<String>), SendError> {
+        let mut events = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut url = self.collection_url();
+            url.query_pairs_mut().append_pair("maxResults", "250");
+            if let Some(token) = sync_token {
+                url.query_pairs_mut().append_pair("syncToken", token);
+            }
+            if let Some(token) = &page_token {
+                url.query_pairs_mut().append_pair("pageToken", token);
+            }
+            let reply = send_json(&self.resource, Method::GET, url, None, None).await?;
+            let page: EventPage = serde_json::from_str(&reply)?;
+            if page.time_zone.is_some() {
+                *self.time_zone.lock().unwrap() = page.time_zone;
+            }
+            events.extend(page.items);
+            match page.next_page_token {
+                None => return Ok((events, page.next_sync_token)),
+                token => page_token = token,
+            }
+        }
+    }
+
+    /// List the events, unless they have been listed already
+    async fn ensure_listing(&self) -> Result<(), SendError> {
+        if self.listing.lock().unwrap().is_none() {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    /// The event at a given URL, that is downloaded if it has been listed in a previous session
+    async fn fetch_item(&self, url: &Url) -> Result<Option<Item>, SendError> {
+        self.ensure_listing().await?;
+        {
+            let listing = self.listing.lock().unwrap();
+            let listing = match listing.as_ref() {
+                None => return Ok(None),
+                Some(listing) => listing,
+            };
+            if let Some(item) = listing.items.get(url) {
+                return Ok(Some(item.clone()));
+            }
+            if !listing.version_tags.contains_key(url) {
+                return Ok(None);
+            }
+        }
+
+        let reply = match send_json(&self.resource, Method::GET, url.clone(), None, None).await {
+            Err(err) if err.downcast_ref::<KFError>().is_some_and(|err| matches!(err, KFError::NotFound{ .. } | KFError::Http{ status: StatusCode::GONE, .. })) => return Ok(None),
+            result => result?,
+        };
+        let event: EventResource = serde_json::from_str(&reply)?;
+        if event.status.as_deref() == Some("cancelled") {
+            return Ok(None);
+        }
+        let tag = VersionTag::from(event.etag.clone());
+        let item = event_to_item(event, url.clone(), self.calendar_time_zone().as_deref());
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.version_tags.insert(url.clone(), tag);
+            listing.items.insert(url.clone(), item.clone());
+        }
+        Ok(Some(item))
+    }
+
+    /// Update the listed events with an event that has just been uploaded, and return its sync status
+    fn store_uploaded(&self, reply: &str) -> Result<(Url, SyncStatus), SendError> {
+        let event: EventResource = serde_json::from_str(reply)?;
+        let url = self.item_url(&event.id);
+        let tag = VersionTag::from(event.etag.clone());
+        let time_zone = self.calendar_time_zone();
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.version_tags.insert(url.clone(), tag.clone());
+            listing.items.insert(url.clone(), event_to_item(event, url.clone(), time_zone.as_deref()));
+        }
+        Ok((url, SyncStatus::Synced(tag)))
+    }
+
+    async fn insert_event(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let event = google_event(item, self.resource.url())?;
+        let mut json = event_to_json(event, self.calendar_time_zone().as_deref())?;
+        json["iCalUID"] = event.uid().into();
+        let reply = send_json(&self.resource, Method::POST, self.collection_url(), Some(&json), None).await?;
+        let (url, sync_status) = self.store_uploaded(&reply)?;
+        self.assigned_urls.lock().unwrap().insert(item.url().clone(), url);
+        Ok(sync_status)
+    }
+
+    async fn patch_event(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let event = google_event(item, self.resource.url())?;
+        let old_tag = match item.sync_status() {
+            SyncStatus::NotSynced => return Err("Cannot update an item that has not been synced already".into()),
+            SyncStatus::Synced(_) => return Err("Cannot update an item that has not changed".into()),
+            SyncStatus::LocallyModified(tag) => tag,
+            SyncStatus::LocallyDeleted(tag) => tag,
+        };
+        // A PATCH keeps the fields that are not mapped (e.g. reminders or attendees)
+        let json = event_to_json(event, self.calendar_time_zone().as_deref())?;
+        let reply = send_json(&self.resource, Method::PATCH, item.url().clone(), Some(&json), Some(old_tag)).await?;
+        let (_, sync_status) = self.store_uploaded(&reply)?;
+        Ok(sync_status)
+    }
+
+    async fn delete_event(&self, item_url: &Url) -> Result<(), SendError> {
+        match send_json(&self.resource, Method::DELETE, item_url.clone(), None, None).await {
+            // The event has already been deleted
+            Err(err) if err.downcast_ref::<KFError>().is_some_and(|err| matches!(err, KFError::Http{ status: StatusCode::GONE, .. })) => (),
+            result => { result?; },
+        }
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.version_tags.remove(item_url);
+            listing.items.remove(item_url);
+        }
+        Ok(())
+    }
+
+    async fn patch_metadata(&self, body: serde_json::Value) -> Result<(), KFError> {
+        send_json(&self.resource, Method::PATCH, self.metadata_url(), Some(&body), None).await?;
+        Ok(())
+    }
+
+    fn forbidden(&self) -> KFError {
+        KFError::Forbidden{ url: self.resource.url().clone() }
+    }
+}
+
+fn google_event<'a>(item: &'a Item, calendar_url: &Url) -> Result<&'a Event, SendError> {
+    match item {
+        Item::Event(event) => Ok(event),
+        Item::Task(_) | Item::Contact(_) => Err(format!("Item {} cannot be uploaded to {}: Google calendars only contain events", item.url(), calendar_url).into()),
+    }
+}
+
+/// Convert an event of the Google Calendar API to an item
+fn event_to_item(event: EventResource, url: Url, calendar_time_zone: Option<&str>) -> Item {
+    let mut extra_parameters = Vec::new();
+    for (name, date) in [("DTSTART", &event.start), ("DTEND", &event.end)] {
+        if let Some(prop) = date.as_ref().and_then(|date| date.to_property(name, calendar_time_zone)) {
+            extra_parameters.push(prop);
+        }
+    }
+    for (name, value) in [("DESCRIPTION", event.description), ("LOCATION", event.location)] {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            extra_parameters.push(Property{ name: name.to_string(), params: None, value: Some(value) });
+        }
+    }
+    extra_parameters.extend(event.recurrence.iter().filter_map(|line| crate::ical::parse_content_line(line)));
+    if event.status.as_deref() == Some("tentative") {
+        extra_parameters.push(Property{ name: "STATUS".to_string(), params: None, value: Some("TENTATIVE".to_string()) });
+    }
+    if event.transparency.as_deref() == Some("transparent") {
+        extra_parameters.push(Property{ name: "TRANSP".to_string(), params: None, value: Some("TRANSPARENT".to_string()) });
+    }
+    for (key, value) in event.extended_properties.unwrap_or_default().private {
+        // Other apps may have stored their own extended properties, that are not iCal properties
+        if !value.starts_with(':') && !value.starts_with(';') {
+            continue;
+        }
+        let name = key.split('~').next().unwrap_or_default();
+        if let Some(prop) = crate::ical::parse_content_line(&format!("{}{}", name, value)) {
+            extra_parameters.push(prop);
+        }
+    }
+
+    Item::Event(Event::new_with_parameters(
+        event.summary.unwrap_or_default(),
+        event.ical_uid.unwrap_or(event.id),
+        url,
+        SyncStatus::Synced(VersionTag::from(event.etag)),
+        event.created,
+        event.updated.unwrap_or_else(Utc::now),
+        crate::ical::default_prod_id(),
+        extra_parameters,
+    ))
+}
+
+/// The JSON description of a `DTSTART` or a `DTEND`.
+///
+/// Floating times are in the time zone of the calendar
+fn date_to_json(prop: &Property, calendar_time_zone: Option<&str>) -> Option<serde_json::Value> {
+    let value = prop.value.as_deref()?;
+    if value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some(serde_json::json!({ "date": date.format("%Y-%m-%d").to_string(), "dateTime": null, "timeZone": null }));
+    }
+    let date_time = NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S").ok()?;
+    let tzid = prop.params.iter().flatten()
+        .find(|(name, _)| name.eq_ignore_ascii_case("TZID"))
+        .and_then(|(_, values)| values.first());
+    let (date_time, time_zone) = match (value.ends_with('Z'), tzid) {
+        (true, _) => (date_time.format("%Y-%m-%dT%H:%M:%SZ").to_string(), "UTC"),
+        (false, Some(tzid)) => (date_time.format("%Y-%m-%dT%H:%M:%S").to_string(), tzid.as_str()),
+        (false, None) => (date_time.format("%Y-%m-%dT%H:%M:%S").to_string(), calendar_time_zone.unwrap_or("UTC")),
+    };
+    Some(serde_json::json!({ "date": null, "dateTime": date_time, "timeZone": time_zone }))
+}
+
+/// The JSON body that sets the fields of a Google event to the ones of an event.
+///
+/// Fields that the event does not have are set to `null`, so that they are cleared when an existing Google event is updated
+fn event_to_json(event: &Event, calendar_time_zone: Option<&str>) -> Result<serde_json::Value, SendError> {
+    let start = event.property("DTSTART")
+        .and_then(|start| date_to_json(start, calendar_time_zone))
+        .ok_or_else(|| format!("Event {} has no valid start date, and cannot be uploaded to Google Calendar", event.url()))?;
+    let end = event.end_or_default()
+        .and_then(|end| date_to_json(&end, calendar_time_zone))
+        .ok_or_else(|| format!("Event {} has no valid end date, and cannot be uploaded to Google Calendar", event.url()))?;
+    let is = |name: &str, value: &str| event.value(name).is_some_and(|actual| actual.eq_ignore_ascii_case(value));
+
+    let recurrence: Vec<String> = event.extra_parameters().iter()
+        .filter(|prop| RECURRENCE_PROPERTIES.contains(&prop.name.to_ascii_uppercase().as_str()))
+        .map(crate::ical::format_content_line)
+        .collect();
+    let mut private = serde_json::Map::new();
+    for prop in event.extra_parameters().iter().filter(|prop| !MAPPED_PROPERTIES.contains(&prop.name.to_ascii_uppercase().as_str())) {
+        let value = crate::ical::format_content_line(prop).split_off(prop.name.len());
+        let mut key = prop.name.clone();
+        let mut count = 1;
+        while private.contains_key(&key) {
+            count += 1;
+            key = format!("{}~{}", prop.name, count);
+        }
+        if key.len() > MAX_EXTENDED_KEY_LEN || value.len() > MAX_EXTENDED_VALUE_LEN {
+            log::warn!("Property {} of event {} is too long to be stored by Google Calendar, it will not be uploaded", prop.name, event.url());
+            continue;
+        }
+        private.insert(key, value.into());
+    }
+
+    Ok(serde_json::json!({
+        "summary": event.name(),
+        "description": event.value("DESCRIPTION"),
+        "location": event.value("LOCATION"),
+        "start": start,
+        "end": end,
+        "recurrence": recurrence,
+        "status": if is("STATUS", "TENTATIVE") { "tentative" } else { "confirmed" },
+        "transparency": if is("TRANSP", "TRANSPARENT") { "transparent" } else { "opaque" },
+        "extendedProperties": { "private": private },
+    }))
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for GoogleCalendar {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.insert_event(&item).await.map_err(KFError::from)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.patch_event(&item).await.map_err(KFError::from)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for GoogleCalendar {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        Self {
+            name, resource, supported_components, color,
+            listing: Mutex::new(None),
+            time_zone: Mutex::new(None),
+            assigned_urls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| listing.version_tags.clone())
+            .collect())
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| listing.version_tags.iter()
+                // Events that have not been downloaded are kept, the provider filters them once they are
+                .filter(move |(url, _)| listing.items.get(*url).is_none_or(|item| filter.matches(item)))
+                .map(|(url, tag)| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        self.fetch_item(url).await.map_err(KFError::from)
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        let mut items = Vec::with_capacity(urls.len());
+        for url in urls {
+            items.push(self.fetch_item(url).await?);
+        }
+        Ok(items)
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.delete_event(item_url).await.map_err(KFError::from)
+    }
+
+    async fn move_item(&mut self, item_url: &Url, _destination: &Url) -> Result<SyncStatus, KFError> {
+        // The destination URL cannot be chosen
+        Err(KFError::MoveNotSupported{ url: item_url.clone() })
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.insert_event(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.patch_event(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(item_urls.to_vec())
+            .map(|url| async move { this.delete_event(&url).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    fn take_assigned_url(&mut self, url: &Url) -> Option<Url> {
+        self.assigned_urls.lock().unwrap().remove(url)
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        // This is the first request of a sync, that always lists the latest changes
+        self.refresh().await?;
+        let listing = self.listing.lock().unwrap();
+        let mut tags: Vec<_> = listing.iter()
+            .flat_map(|listing| &listing.version_tags)
+            .map(|(url, tag)| format!("{} {}\n", url, tag.as_str()))
+            .collect();
+        tags.sort();
+        Ok(CalendarVersion{
+            ctag: Some(format!("{:x}", checksum(tags.concat().as_bytes()))),
+            sync_token: listing.as_ref().and_then(|listing| listing.sync_token.clone()),
+        })
+    }
+
+    fn resume_from(&mut self, version: &CalendarVersion, version_tags: HashMap<Url, VersionTag>) {
+        let mut listing = self.listing.lock().unwrap();
+        if let (None, Some(sync_token)) = (listing.as_ref(), &version.sync_token) {
+            *listing = Some(EventListing{ version_tags, items: HashMap::new(), sync_token: Some(sync_token.clone()) });
+        }
+    }
+
+    async fn update_color(&mut self, _color: Option<Color>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_description(&mut self, description: Option<String>) -> Result<(), KFError> {
+        self.patch_metadata(serde_json::json!({ "description": description })).await
+    }
+
+    async fn update_timezone(&mut self, timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        // Google calendars always have a time zone
+        let timezone = timezone.ok_or_else(|| self.forbidden())?;
+        self.patch_metadata(serde_json::json!({ "timeZone": timezone.tzid() })).await?;
+        *self.time_zone.lock().unwrap() = Some(timezone.tzid().to_string());
+        Ok(())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        self.patch_metadata(serde_json::json!({ "summary": name })).await?;
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_conversion() {
+        let url: Url = "https://tasks.googleapis.com/tasks/v1/lists/MDk3/tasks/T1".parse().unwrap();
+        let task: TaskResource = serde_json::from_str(r#"{
+            "kind": "tasks#task", "id": "T1", "etag": "\"LTk1\"", "title": "Water the plants",
+            "updated": "2021-03-01T10:00:00.000Z", "status": "completed", "completed": "2021-03-01T09:30:00.000Z",
+            "notes": "The ones in the kitchen", "due": "2021-03-02T00:00:00.000Z"
+        }"#).unwrap();
+        let item = task_to_item(task, url.clone());
+        let task = item.unwrap_task();
+        assert_eq!(task.uid(), "T1");
+        assert_eq!(task.url(), &url);
+        assert_eq!(task.sync_status(), &SyncStatus::Synced(VersionTag::from("\"LTk1\"".to_string())));
+        assert_eq!(task.completion_status().completion_date().map(|date| date.to_rfc3339()), Some("2021-03-01T09:30:00+00:00".to_string()));
+        assert!(task.extra_parameters().iter().any(|prop| prop.name == "DUE" && prop.value.as_deref() == Some("20210302")));
+
+        let json = task_to_json(task);
+        assert_eq!(json["title"], "Water the plants");
+        assert_eq!(json["status"], "completed");
+        assert_eq!(json["completed"], "2021-03-01T09:30:00.000Z");
+        assert_eq!(json["notes"], "The ones in the kitchen");
+        assert_eq!(json["due"], "2021-03-02T00:00:00.000Z");
+
+        // Fields a task does not have are cleared
+        let task = Task::new("Call Bob".to_string(), false, &"https://tasks.googleapis.com/tasks/v1/lists/MDk3/tasks/".parse().unwrap());
+        let json = task_to_json(&task);
+        assert_eq!(json["status"], "needsAction");
+        assert!(json["completed"].is_null() && json["notes"].is_null() && json["due"].is_null());
+    }
+
+    #[test]
+    fn test_event_conversion() {
+        let url: Url = "https://www.googleapis.com/calendar/v3/calendars/primary/events/E1".parse().unwrap();
+        let event: EventResource = serde_json::from_str(r#"{
+            "kind": "calendar#event", "id": "E1", "etag": "\"3181\"", "status": "tentative", "iCalUID": "uid-1@example.com",
+            "summary": "Team meeting", "location": "Room 4", "transparency": "transparent",
+            "created": "2021-03-01T09:00:00.000Z", "updated": "2021-03-01T10:00:00.000Z",
+            "start": { "dateTime": "2021-04-01T10:00:00+02:00", "timeZone": "Europe/Paris" },
+            "end": { "dateTime": "2021-04-01T11:00:00+02:00", "timeZone": "America/New_York" },
+            "recurrence": ["RRULE:FREQ=WEEKLY;COUNT=4", "EXDATE;TZID=Europe/Paris:20210408T100000"],
+            "extendedProperties": { "private": { "CATEGORIES": ":WORK", "CATEGORIES~2": ":MEETING", "appSetting": "on" } }
+        }"#).unwrap();
+        let item = event_to_item(event, url.clone(), Some("Europe/Paris"));
+        let event = item.unwrap_event();
+        assert_eq!(event.uid(), "uid-1@example.com");
+        assert_eq!(event.url(), &url);
+        assert_eq!(event.name(), "Team meeting");
+        assert_eq!(event.sync_status(), &SyncStatus::Synced(VersionTag::from("\"3181\"".to_string())));
+        let line = |name: &str| event.property(name).map(crate::ical::format_content_line);
+        // Times keep their time zone when it is the one of the calendar
+        assert_eq!(line("DTSTART").as_deref(), Some("DTSTART;TZID=Europe/Paris:20210401T100000"));
+        assert_eq!(line("DTEND").as_deref(), Some("DTEND:20210401T090000Z"));
+        assert_eq!(line("EXDATE").as_deref(), Some("EXDATE;TZID=Europe/Paris:20210408T100000"));
+        assert_eq!(event.value("STATUS"), Some("TENTATIVE"));
+        assert_eq!(event.value("TRANSP"), Some("TRANSPARENT"));
+        // Extended properties of other apps are not iCal properties
+        assert_eq!(event.extra_parameters().iter().filter(|prop| prop.name == "CATEGORIES").count(), 2);
+        assert!(event.property("appSetting").is_none());
+
+        let json = event_to_json(event, Some("Europe/Paris")).unwrap();
+        assert_eq!(json["summary"], "Team meeting");
+        assert_eq!(json["location"], "Room 4");
+        assert!(json["description"].is_null());
+        assert_eq!(json["start"], serde_json::json!({ "date": null, "dateTime": "2021-04-01T10:00:00", "timeZone": "Europe/Paris" }));
+        assert_eq!(json["end"], serde_json::json!({ "date": null, "dateTime": "2021-04-01T09:00:00Z", "timeZone": "UTC" }));
+        assert_eq!(json["recurrence"], serde_json::json!(["RRULE:FREQ=WEEKLY;COUNT=4", "EXDATE;TZID=Europe/Paris:20210408T100000"]));
+        assert_eq!(json["status"], "tentative");
+        assert_eq!(json["transparency"], "transparent");
+        assert_eq!(json["extendedProperties"]["private"], serde_json::json!({ "CATEGORIES": ":WORK", "CATEGORIES~2": ":MEETING" }));
+
+        // Whole-day events without an end last one day
+        let date = |name: &str, value: &str| Property{ name: name.to_string(), params: Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])]), value: Some(value.to_string()) };
+        let mut event = Event::new("Holidays".to_string(), date("DTSTART", "20210430"), date("DTEND", "20210501"), &"https://www.googleapis.com/calendar/v3/calendars/primary/events/".parse().unwrap());
+        event.remove_property("DTEND");
+        let json = event_to_json(&event, None).unwrap();
+        assert_eq!(json["start"]["date"], "2021-04-30");
+        assert_eq!(json["end"]["date"], "2021-05-01");
+        assert!(json["end"]["dateTime"].is_null());
+        assert_eq!(json["status"], "confirmed");
+        assert_eq!(json["recurrence"], serde_json::json!([]));
+
+        event.remove_property("DTSTART");
+        assert!(event_to_json(&event, None).is_err());
+    }
+}
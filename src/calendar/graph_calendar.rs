@@ -0,0 +1,1313 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Utc};
+use csscolorparser::Color;
+use futures_util::stream::{self, StreamExt};
+use ical::property::Property;
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::calendar::remote_calendar::SendError;
+use crate::alarm::{Alarm, AlarmTrigger};
+use crate::item::Item;
+use crate::item::VersionTag;
+use crate::item::SyncStatus;
+use crate::task::{CompletionStatus, Task};
+use crate::event::Event;
+use crate::resource::Resource;
+use crate::provider::filter::SyncFilter;
+use crate::error::KFError;
+use crate::graph::Page;
+use crate::rest::{send_json, send_json_with_headers};
+use crate::kv_store::checksum;
+
+/// A date and a time zone, as described by the Microsoft Graph API
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DateTimeTimeZone {
+    date_time: String,
+    time_zone: String,
+}
+
+impl DateTimeTimeZone {
+    fn new(date: &DateTime<Utc>) -> Self {
+        Self{ date_time: date.format("%Y-%m-%dT%H:%M:%S").to_string(), time_zone: "UTC".to_string() }
+    }
+
+    /// The date this describes.
+    ///
+    /// The dates of tasks are returned in UTC, unless the request asks for another time zone
+    fn to_utc(&self) -> Option<DateTime<Utc>> {
+        NaiveDateTime::parse_from_str(&self.date_time, "%Y-%m-%dT%H:%M:%S%.f").ok()
+            .map(|date| DateTime::from_utc(date, Utc))
+    }
+}
+
+/// The body of a task
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ItemBody {
+    content: Option<String>,
+}
+
+/// A task, as described by the Microsoft Graph API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TodoTask {
+    id: String,
+    #[serde(rename = "@odata.etag")]
+    etag: Option<String>,
+    /// Tasks that have been deleted are only listed by delta queries, with this annotation
+    #[serde(rename = "@removed")]
+    removed: Option<serde_json::Value>,
+    title: Option<String>,
+    status: Option<String>,
+    importance: Option<String>,
+    body: Option<ItemBody>,
+    due_date_time: Option<DateTimeTimeZone>,
+    completed_date_time: Option<DateTimeTimeZone>,
+    #[serde(default)]
+    is_reminder_on: bool,
+    reminder_date_time: Option<DateTimeTimeZone>,
+    created_date_time: Option<DateTime<Utc>>,
+    last_modified_date_time: Option<DateTime<Utc>>,
+}
+
+/// The tasks of a list, as they have last been downloaded
+#[derive(Debug)]
+struct Listing {
+    items: HashMap<Url, Item>,
+    /// The URL that returns the changes made since this download
+    delta_link: Option<Url>,
+}
+
+
+
+/// A Microsoft To Do task list, that is synced with the Microsoft Graph API.
+///
+/// It is created by a [`GraphTodo`](crate::graph::GraphTodo) source. \
+/// Tasks are converted to iCal items: their titles, statuses, completion dates, importances (as `PRIORITY`), bodies (as `DESCRIPTION`),
+/// due dates (as `DUE`) and reminders (as alarms) are synced.
+/// Other iCal properties have no counterpart in Microsoft To Do, and are lost when tasks are downloaded. \
+/// Microsoft Graph chooses the IDs of new tasks: the URL of a task is its API URL, and its UID is its ID. A [`Provider`](crate::provider::Provider) moves the tasks it adds
+/// to these URLs (see [`DavCalendar::take_assigned_url`]).
+///
+/// The whole list is downloaded at the first sync. Later syncs only download the changes made since (using delta queries).
+#[derive(Debug)]
+pub struct GraphTaskList {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+
+    listing: Mutex<Option<Listing>>,
+    assigned_urls: Mutex<HashMap<Url, Url>>,
+}
+
+impl GraphTaskList {
+    /// The ID of this task list
+    pub fn id(&self) -> &str {
+        let mut segments = self.resource.url().path_segments().into_iter().flatten().rev().filter(|segment| !segment.is_empty());
+        // The URL ends with `/lists/{id}/tasks/`
+        segments.nth(1).unwrap_or_default()
+    }
+
+    /// The URL of the task that has a given ID
+    pub fn item_url(&self, id: &str) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(id);
+        }
+        url
+    }
+
+    /// The URL tasks are created at (this is the URL of this calendar, without its trailing slash)
+    fn collection_url(&self) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty();
+        }
+        url
+    }
+
+    /// Download the changes made since the last download (or every task, the first time)
+    async fn refresh(&self) -> Result<(), SendError> {
+        let delta_link = self.listing.lock().unwrap().as_ref().and_then(|listing| listing.delta_link.clone());
+        let (tasks, new_delta_link) = match delta_link {
+            None => self.fetch_delta(None).await?,
+            Some(delta_link) => match self.fetch_delta(Some(delta_link)).await {
+                Err(err) if err.downcast_ref::<KFError>().is_some_and(|err| matches!(err, KFError::Http{ status: StatusCode::GONE, .. })) => {
+                    log::info!("The delta token of {} has expired, downloading every task again", self.resource.url());
+                    *self.listing.lock().unwrap() = None;
+                    self.fetch_delta(None).await?
+                },
+                result => result?,
+            },
+        };
+
+        let mut listing = self.listing.lock().unwrap();
+        let listing = listing.get_or_insert_with(|| Listing{ items: HashMap::new(), delta_link: None });
+        for task in tasks {
+            let url = self.item_url(&task.id);
+            match task.removed.is_some() {
+                true => { listing.items.remove(&url); },
+                false => { listing.items.insert(url.clone(), task_to_item(task, url)); },
+            }
+        }
+        listing.delta_link = new_delta_link;
+        Ok(())
+    }
+
+    /// Follow a delta query (or start a new one), and return the changes and the link to the next changes
+    async fn fetch_delta(&self, delta_link: Option<Url>) -> Result<(Vec<TodoTask>, Option<Url>), SendError> {
+        let mut url = delta_link.unwrap_or_else(|| {
+            let mut url = self.collection_url();
+            if let Ok(mut segments) = url.path_segments_mut() {
+                segments.push("delta");
+            }
+            url
+        });
+        let mut tasks = Vec::new();
+        loop {
+            let reply = send_json(&self.resource, Method::GET, url, None, None).await?;
+            let page: Page<TodoTask> = serde_json::from_str(&reply)?;
+            tasks.extend(page.value);
+            match page.next_link {
+                Some(next_link) => url = next_link,
+                None => return Ok((tasks, page.delta_link)),
+            }
+        }
+    }
+
+    /// Download the tasks, unless they have been downloaded already
+    async fn ensure_listing(&self) -> Result<(), SendError> {
+        if self.listing.lock().unwrap().is_none() {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    /// Update the downloaded tasks with a task that has just been uploaded, and return its sync status
+    fn store_uploaded(&self, reply: &str) -> Result<(Url, SyncStatus), SendError> {
+        let task: TodoTask = serde_json::from_str(reply)?;
+        let url = self.item_url(&task.id);
+        let etag = task.etag.clone().ok_or_else(|| format!("Task {} has no etag", url))?;
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.items.insert(url.clone(), task_to_item(task, url.clone()));
+        }
+        Ok((url, SyncStatus::Synced(VersionTag::from(etag))))
+    }
+
+    async fn insert_task(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let task = graph_task(item, self.resource.url())?;
+        let reply = send_json(&self.resource, Method::POST, self.collection_url(), Some(&task_to_json(task)), None).await?;
+        let (url, sync_status) = self.store_uploaded(&reply)?;
+        self.assigned_urls.lock().unwrap().insert(item.url().clone(), url);
+        Ok(sync_status)
+    }
+
+    async fn patch_task(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let task = graph_task(item, self.resource.url())?;
+        let old_tag = match item.sync_status() {
+            SyncStatus::NotSynced => return Err("Cannot update an item that has not been synced already".into()),
+            SyncStatus::Synced(_) => return Err("Cannot update an item that has not changed".into()),
+            SyncStatus::LocallyModified(tag) => tag,
+            SyncStatus::LocallyDeleted(tag) => tag,
+        };
+        let reply = send_json(&self.resource, Method::PATCH, item.url().clone(), Some(&task_to_json(task)), Some(old_tag)).await?;
+        let (_, sync_status) = self.store_uploaded(&reply)?;
+        Ok(sync_status)
+    }
+
+    async fn delete_task(&self, item_url: &Url) -> Result<(), SendError> {
+        send_json(&self.resource, Method::DELETE, item_url.clone(), None, None).await?;
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.items.remove(item_url);
+        }
+        Ok(())
+    }
+
+    fn forbidden(&self) -> KFError {
+        KFError::Forbidden{ url: self.resource.url().clone() }
+    }
+}
+
+fn graph_task<'a>(item: &'a Item, calendar_url: &Url) -> Result<&'a Task, SendError> {
+    match item {
+        Item::Task(task) => Ok(task),
+        Item::Event(_) | Item::Contact(_) => Err(format!("Item {} cannot be uploaded to {}: Microsoft To Do lists only contain tasks", item.url(), calendar_url).into()),
+    }
+}
+
+/// Convert a task of the Microsoft Graph API to an item
+fn task_to_item(task: TodoTask, url: Url) -> Item {
+    let completion_status = match task.status.as_deref() {
+        Some("completed") => CompletionStatus::Completed(task.completed_date_time.as_ref().and_then(DateTimeTimeZone::to_utc)),
+        _ => CompletionStatus::Uncompleted,
+    };
+    let mut extra_parameters = Vec::new();
+    let priority = match task.importance.as_deref() {
+        Some("high") => Some("1"),
+        Some("low") => Some("9"),
+        _ => None,
+    };
+    if let Some(priority) = priority {
+        extra_parameters.push(Property{ name: "PRIORITY".to_string(), params: None, value: Some(priority.to_string()) });
+    }
+    if let Some(content) = task.body.and_then(|body| body.content).filter(|content| !content.is_empty()) {
+        extra_parameters.push(Property{ name: "DESCRIPTION".to_string(), params: None, value: Some(content) });
+    }
+    if let Some(due) = task.due_date_time.as_ref().and_then(DateTimeTimeZone::to_utc) {
+        // Microsoft To Do only shows the date of due dates
+        let params = Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])]);
+        extra_parameters.push(Property{ name: "DUE".to_string(), params, value: Some(due.format("%Y%m%d").to_string()) });
+    }
+
+    let reminder = match task.is_reminder_on {
+        true => task.reminder_date_time.as_ref().and_then(DateTimeTimeZone::to_utc),
+        false => None,
+    };
+    let title = task.title.unwrap_or_default();
+    let mut new_task = Task::new_with_parameters(
+        title.clone(),
+        task.id,
+        url,
+        completion_status,
+        SyncStatus::Synced(VersionTag::from(task.etag.unwrap_or_default())),
+        task.created_date_time,
+        task.last_modified_date_time.unwrap_or_else(Utc::now),
+        crate::ical::default_prod_id(),
+        extra_parameters,
+    );
+    if let Some(reminder) = reminder {
+        new_task.set_alarms(vec![Alarm::display(AlarmTrigger::Absolute(reminder), &title)]);
+    }
+    Item::Task(new_task)
+}
+
+/// The JSON body that sets the fields of a Microsoft To Do task to the ones of a task.
+///
+/// Fields that the task does not have are set to `null`, so that they are cleared when an existing task is updated
+fn task_to_json(task: &Task) -> serde_json::Value {
+    let property = |name: &str| task.extra_parameters().iter()
+        .find(|prop| prop.name.eq_ignore_ascii_case(name))
+        .and_then(|prop| prop.value.as_deref());
+    let importance = match property("PRIORITY").and_then(|priority| priority.trim().parse::<u8>().ok()) {
+        Some(1..=4) => "high",
+        Some(6..=9) => "low",
+        _ => "normal",
+    };
+    let due = property("DUE")
+        .and_then(crate::ical::parse_date_value)
+        .map(|due| DateTimeTimeZone::new(&due));
+    // Only alarms at a given time can be reminders
+    let reminder = task.alarms().iter().find_map(|alarm| match alarm.trigger() {
+        Some(AlarmTrigger::Absolute(date)) => Some(DateTimeTimeZone::new(&date)),
+        _ => None,
+    });
+
+    let mut json = serde_json::json!({
+        "title": task.name(),
+        "status": if task.completed() { "completed" } else { "notStarted" },
+        "importance": importance,
+        "body": { "content": property("DESCRIPTION").unwrap_or_default(), "contentType": "text" },
+        "dueDateTime": due,
+        "isReminderOn": reminder.is_some(),
+        "reminderDateTime": reminder,
+    });
+    match task.completion_status() {
+        CompletionStatus::Completed(Some(date)) => { json["completedDateTime"] = serde_json::json!(DateTimeTimeZone::new(date)); },
+        // Microsoft Graph sets the completion date itself
+        CompletionStatus::Completed(None) => (),
+        CompletionStatus::Uncompleted => { json["completedDateTime"] = serde_json::Value::Null; },
+    }
+    json
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for GraphTaskList {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.insert_task(&item).await.map_err(KFError::from)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.patch_task(&item).await.map_err(KFError::from)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for GraphTaskList {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        Self {
+            name, resource, supported_components, color,
+            listing: Mutex::new(None),
+            assigned_urls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| &listing.items)
+            .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| &listing.items)
+            .filter(|(_, item)| filter.matches(item))
+            .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        self.ensure_listing().await?;
+        Ok(self.listing.lock().unwrap().as_ref().and_then(|listing| listing.items.get(url).cloned()))
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(urls.iter()
+            .map(|url| listing.as_ref().and_then(|listing| listing.items.get(url).cloned()))
+            .collect())
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.delete_task(item_url).await.map_err(KFError::from)
+    }
+
+    async fn move_item(&mut self, item_url: &Url, _destination: &Url) -> Result<SyncStatus, KFError> {
+        // The destination URL cannot be chosen
+        Err(KFError::MoveNotSupported{ url: item_url.clone() })
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.insert_task(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.patch_task(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(item_urls.to_vec())
+            .map(|url| async move { this.delete_task(&url).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    fn take_assigned_url(&mut self, url: &Url) -> Option<Url> {
+        self.assigned_urls.lock().unwrap().remove(url)
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        // This is the first request of a sync, that always downloads the latest changes
+        self.refresh().await?;
+        let mut tags: Vec<_> = self.get_item_version_tags().await?.into_iter()
+            .map(|(url, tag)| format!("{} {}\n", url, tag.as_str()))
+            .collect();
+        tags.sort();
+        Ok(CalendarVersion{ ctag: Some(format!("{:x}", checksum(tags.concat().as_bytes()))), sync_token: None })
+    }
+
+    async fn update_color(&mut self, _color: Option<Color>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_description(&mut self, _description: Option<String>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_timezone(&mut self, _timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        let mut url = self.collection_url();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            // From `/lists/{id}/tasks` to `/lists/{id}`
+            segments.pop();
+        }
+        send_json(&self.resource, Method::PATCH, url, Some(&serde_json::json!({ "displayName": name })), None).await
+            ?;
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+}
+
+
+/// The location of an event
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    display_name: Option<String>,
+}
+
+/// How an event recurs, as described by the Microsoft Graph API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecurrencePattern {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    interval: u32,
+    #[serde(default)]
+    days_of_week: Vec<String>,
+    #[serde(default)]
+    day_of_month: u32,
+    #[serde(default)]
+    month: u32,
+    index: Option<String>,
+    first_day_of_week: Option<String>,
+}
+
+/// When the recurrence of an event ends
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecurrenceRange {
+    #[serde(rename = "type")]
+    kind: String,
+    end_date: Option<NaiveDate>,
+    #[serde(default)]
+    number_of_occurrences: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct PatternedRecurrence {
+    pattern: RecurrencePattern,
+    range: RecurrenceRange,
+}
+
+/// An event of an Outlook calendar, as described by the Microsoft Graph API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphEvent {
+    id: String,
+    #[serde(rename = "@odata.etag")]
+    etag: Option<String>,
+    change_key: Option<String>,
+    /// Events that have been deleted are only listed by delta queries, with this annotation
+    #[serde(rename = "@removed")]
+    removed: Option<serde_json::Value>,
+    /// `singleInstance`, `seriesMaster`, or `occurrence` and `exception` for the instances of recurring events
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(rename = "iCalUId")]
+    ical_uid: Option<String>,
+    subject: Option<String>,
+    body: Option<ItemBody>,
+    location: Option<Location>,
+    start: Option<DateTimeTimeZone>,
+    end: Option<DateTimeTimeZone>,
+    #[serde(default)]
+    is_all_day: bool,
+    #[serde(default)]
+    is_cancelled: bool,
+    show_as: Option<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+    recurrence: Option<PatternedRecurrence>,
+    #[serde(default)]
+    is_reminder_on: bool,
+    reminder_minutes_before_start: Option<i64>,
+    created_date_time: Option<DateTime<Utc>>,
+    last_modified_date_time: Option<DateTime<Utc>>,
+}
+
+/// The events of a calendar, as they have last been listed
+#[derive(Debug, Default)]
+struct EventListing {
+    version_tags: HashMap<Url, VersionTag>,
+    /// The events that have been downloaded. Events that have been listed in a previous session (see [`DavCalendar::resume_from`]) are downloaded when they are requested
+    items: HashMap<Url, Item>,
+    /// The URL that returns the changes made since this listing
+    delta_link: Option<Url>,
+}
+
+/// Ask for plain text bodies, rather than HTML ones
+const PREFER_TEXT_BODY: (&str, &str) = ("Prefer", "outlook.body-content-type=\"text\"");
+/// The days of the week, as named by iCal and by the Microsoft Graph API
+const WEEKDAYS: [(&str, &str); 7] = [("MO", "monday"), ("TU", "tuesday"), ("WE", "wednesday"), ("TH", "thursday"), ("FR", "friday"), ("SA", "saturday"), ("SU", "sunday")];
+/// The positions of days in relative recurrences, as named by the Microsoft Graph API
+const WEEK_INDEXES: [(i32, &str); 5] = [(1, "first"), (2, "second"), (3, "third"), (4, "fourth"), (-1, "last")];
+
+
+
+/// An Outlook calendar of a Microsoft account, that is synced with the Microsoft Graph API.
+///
+/// It is created by a [`GraphCalendars`](crate::graph::GraphCalendars) source. \
+/// Events are converted to iCal items: their subjects, bodies (as `DESCRIPTION`), locations, start and end dates, categories, recurrences (as `RRULE`),
+/// free/busy statuses (as `TRANSP` and tentative `STATUS`), cancellations and reminders (as alarms) are synced. Times are converted to UTC when events are downloaded.
+/// Other iCal properties (e.g. `EXDATE`, or recurrence rules that Outlook cannot describe) have no counterpart in Outlook, and are lost when events are downloaded.
+/// Events whose recurrence rules cannot be described fail to be uploaded. \
+/// The occurrences and exceptions of recurring events are separate events in Outlook, that have no counterpart in this crate: they are not synced. \
+/// Microsoft Graph chooses the IDs of new events: the URL of an event is its API URL, and its UID is its `iCalUId`.
+/// A [`Provider`](crate::provider::Provider) moves the events it adds to these URLs (see [`DavCalendar::take_assigned_url`]).
+///
+/// The whole calendar is listed at the first sync. Later syncs only list the changes made since (using delta queries),
+/// even after the app is restarted, since the delta link is kept as the sync token of the calendar (see [`DavCalendar::resume_from`]).
+/// When Microsoft Graph has expired a delta link, the whole calendar is listed again.
+#[derive(Debug)]
+pub struct GraphCalendar {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+
+    listing: Mutex<Option<EventListing>>,
+    assigned_urls: Mutex<HashMap<Url, Url>>,
+}
+
+impl GraphCalendar {
+    /// The ID of this calendar
+    pub fn id(&self) -> String {
+        let mut segments = self.resource.url().path_segments().into_iter().flatten().rev().filter(|segment| !segment.is_empty());
+        // The URL ends with `/calendars/{id}/events/`
+        let id = segments.nth(1).unwrap_or_default();
+        percent_encoding::percent_decode_str(id).decode_utf8_lossy().into_owned()
+    }
+
+    /// The URL of the event that has a given ID
+    pub fn item_url(&self, id: &str) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(id);
+        }
+        url
+    }
+
+    /// The URL events are created at (this is the URL of this calendar, without its trailing slash)
+    fn collection_url(&self) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty();
+        }
+        url
+    }
+
+    /// List the changes made since the last listing (or every event, the first time)
+    async fn refresh(&self) -> Result<(), SendError> {
+        let delta_link = self.listing.lock().unwrap().as_ref().and_then(|listing| listing.delta_link.clone());
+        let (events, new_delta_link, incremental) = match delta_link {
+            None => {
+                let (events, delta_link) = self.fetch_delta(None).await?;
+                (events, delta_link, false)
+            },
+            Some(delta_link) => match self.fetch_delta(Some(delta_link)).await {
+                Err(err) if err.downcast_ref::<KFError>().is_some_and(|err| matches!(err, KFError::Http{ status: StatusCode::GONE, .. })) => {
+                    log::info!("The delta link of {} has expired, listing every event again", self.resource.url());
+                    let (events, delta_link) = self.fetch_delta(None).await?;
+                    (events, delta_link, false)
+                },
+                result => {
+                    let (events, delta_link) = result?;
+                    (events, delta_link, true)
+                },
+            },
+        };
+
+        let mut listing = self.listing.lock().unwrap();
+        let listing = match (listing.as_mut(), incremental) {
+            (Some(listing), true) => listing,
+            // This was a full listing
+            _ => listing.insert(EventListing::default()),
+        };
+        for event in events {
+            let url = self.item_url(&event.id);
+            if event.removed.is_some() {
+                listing.version_tags.remove(&url);
+                listing.items.remove(&url);
+                continue;
+            }
+            if matches!(event.kind.as_deref(), Some("occurrence") | Some("exception")) {
+                log::debug!("Ignoring event {}, which is an instance of a recurring event", event.id);
+                continue;
+            }
+            let item = event_to_item(event, url.clone());
+            if let Some(tag) = item.sync_status().version_tag() {
+                listing.version_tags.insert(url.clone(), tag.clone());
+            }
+            listing.items.insert(url, item);
+        }
+        listing.delta_link = new_delta_link;
+        Ok(())
+    }
+
+    /// Follow a delta query (or start a new one), and return the changes and the link to the next changes
+    async fn fetch_delta(&self, delta_link: Option<Url>) -> Result<(Vec<GraphEvent>, Option<Url>), SendError> {
+        let mut url = delta_link.unwrap_or_else(|| {
+            let mut url = self.collection_url();
+            if let Ok(mut segments) = url.path_segments_mut() {
+                segments.push("delta");
+            }
+            url
+        });
+        let mut events = Vec::new();
+        loop {
+            let reply = send_json_with_headers(&self.resource, Method::GET, url, None, None, &[PREFER_TEXT_BODY]).await?;
+            let page: Page<GraphEvent> = serde_json::from_str(&reply)?;
+            events.extend(page.value);
+            match page.next_link {
+                Some(next_link) => url = next_link,
+                None => return Ok((events, page.delta_link)),
+            }
+        }
+    }
+
+    /// List the events, unless they have been listed already
+    async fn ensure_listing(&self) -> Result<(), SendError> {
+        if self.listing.lock().unwrap().is_none() {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    /// The event at a given URL, that is downloaded if it has been listed in a previous session
+    async fn fetch_item(&self, url: &Url) -> Result<Option<Item>, SendError> {
+        self.ensure_listing().await?;
+        {
+            let listing = self.listing.lock().unwrap();
+            let listing = match listing.as_ref() {
+                None => return Ok(None),
+                Some(listing) => listing,
+            };
+            if let Some(item) = listing.items.get(url) {
+                return Ok(Some(item.clone()));
+            }
+            if !listing.version_tags.contains_key(url) {
+                return Ok(None);
+            }
+        }
+
+        let reply = match send_json_with_headers(&self.resource, Method::GET, url.clone(), None, None, &[PREFER_TEXT_BODY]).await {
+            Err(err) if err.downcast_ref::<KFError>().is_some_and(|err| matches!(err, KFError::NotFound{ .. })) => return Ok(None),
+            result => result?,
+        };
+        let event: GraphEvent = serde_json::from_str(&reply)?;
+        let item = event_to_item(event, url.clone());
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            if let Some(tag) = item.sync_status().version_tag() {
+                listing.version_tags.insert(url.clone(), tag.clone());
+            }
+            listing.items.insert(url.clone(), item.clone());
+        }
+        Ok(Some(item))
+    }
+
+    /// Update the listed events with an event that has just been uploaded, and return its sync status
+    fn store_uploaded(&self, reply: &str) -> Result<(Url, SyncStatus), SendError> {
+        let event: GraphEvent = serde_json::from_str(reply)?;
+        let url = self.item_url(&event.id);
+        let tag = event.etag.clone().or_else(|| event.change_key.clone()).ok_or_else(|| format!("Event {} has no etag", url))?;
+        let tag = VersionTag::from(tag);
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.version_tags.insert(url.clone(), tag.clone());
+            listing.items.insert(url.clone(), event_to_item(event, url.clone()));
+        }
+        Ok((url, SyncStatus::Synced(tag)))
+    }
+
+    async fn insert_event(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let event = graph_event(item, self.resource.url())?;
+        let reply = send_json_with_headers(&self.resource, Method::POST, self.collection_url(), Some(&event_to_json(event)?), None, &[PREFER_TEXT_BODY]).await?;
+        let (url, sync_status) = self.store_uploaded(&reply)?;
+        self.assigned_urls.lock().unwrap().insert(item.url().clone(), url);
+        Ok(sync_status)
+    }
+
+    async fn patch_event(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let event = graph_event(item, self.resource.url())?;
+        let old_tag = match item.sync_status() {
+            SyncStatus::NotSynced => return Err("Cannot update an item that has not been synced already".into()),
+            SyncStatus::Synced(_) => return Err("Cannot update an item that has not changed".into()),
+            SyncStatus::LocallyModified(tag) => tag,
+            SyncStatus::LocallyDeleted(tag) => tag,
+        };
+        // A PATCH keeps the fields that are not mapped (e.g. attendees)
+        let reply = send_json_with_headers(&self.resource, Method::PATCH, item.url().clone(), Some(&event_to_json(event)?), Some(old_tag), &[PREFER_TEXT_BODY]).await?;
+        let (_, sync_status) = self.store_uploaded(&reply)?;
+        Ok(sync_status)
+    }
+
+    async fn delete_event(&self, item_url: &Url) -> Result<(), SendError> {
+        send_json(&self.resource, Method::DELETE, item_url.clone(), None, None).await?;
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.version_tags.remove(item_url);
+            listing.items.remove(item_url);
+        }
+        Ok(())
+    }
+
+    fn forbidden(&self) -> KFError {
+        KFError::Forbidden{ url: self.resource.url().clone() }
+    }
+}
+
+fn graph_event<'a>(item: &'a Item, calendar_url: &Url) -> Result<&'a Event, SendError> {
+    match item {
+        Item::Event(event) => Ok(event),
+        Item::Task(_) | Item::Contact(_) => Err(format!("Item {} cannot be uploaded to {}: Outlook calendars only contain events", item.url(), calendar_url).into()),
+    }
+}
+
+/// The iCal property (e.g. `DTSTART`) of the start or the end of an event
+fn date_property(name: &str, date: &DateTimeTimeZone, is_all_day: bool) -> Option<Property> {
+    let date_time = NaiveDateTime::parse_from_str(&date.date_time, "%Y-%m-%dT%H:%M:%S%.f").ok()?;
+    let (params, value) = match (is_all_day, date.time_zone.as_str()) {
+        (true, _) => (Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])]), date_time.format("%Y%m%d").to_string()),
+        (false, "UTC") => (None, date_time.format("%Y%m%dT%H%M%SZ").to_string()),
+        (false, time_zone) => (Some(vec![("TZID".to_string(), vec![time_zone.to_string()])]), date_time.format("%Y%m%dT%H%M%S").to_string()),
+    };
+    Some(Property{ name: name.to_string(), params, value: Some(value) })
+}
+
+/// The JSON description of a `DTSTART` or a `DTEND`.
+///
+/// Floating times are in UTC
+fn date_to_json(prop: &Property) -> Option<serde_json::Value> {
+    let value = prop.value.as_deref()?;
+    if value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some(serde_json::json!({ "dateTime": date.format("%Y-%m-%dT00:00:00").to_string(), "timeZone": "UTC" }));
+    }
+    let date_time = NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S").ok()?;
+    let tzid = prop.params.iter().flatten()
+        .find(|(name, _)| name.eq_ignore_ascii_case("TZID"))
+        .and_then(|(_, values)| values.first())
+        .filter(|_| !value.ends_with('Z'));
+    Some(serde_json::json!({ "dateTime": date_time.format("%Y-%m-%dT%H:%M:%S").to_string(), "timeZone": tzid.map_or("UTC", String::as_str) }))
+}
+
+/// The `RRULE` of a recurrence, or `None` if this crate does not know its pattern
+fn recurrence_to_rrule(recurrence: &PatternedRecurrence, is_all_day: bool) -> Option<String> {
+    let pattern = &recurrence.pattern;
+    let days = pattern.days_of_week.iter()
+        .map(|day| WEEKDAYS.iter().find(|(_, name)| name.eq_ignore_ascii_case(day)).map(|(code, _)| *code))
+        .collect::<Option<Vec<_>>>()?
+        .join(",");
+    let position = match pattern.index.as_deref() {
+        None => 1,
+        Some(index) => WEEK_INDEXES.iter().find(|(_, name)| *name == index)?.0,
+    };
+    let mut rule = match pattern.kind.as_str() {
+        "daily" => "FREQ=DAILY".to_string(),
+        "weekly" => format!("FREQ=WEEKLY;BYDAY={}", days),
+        "absoluteMonthly" => format!("FREQ=MONTHLY;BYMONTHDAY={}", pattern.day_of_month),
+        "relativeMonthly" => format!("FREQ=MONTHLY;BYDAY={};BYSETPOS={}", days, position),
+        "absoluteYearly" => format!("FREQ=YEARLY;BYMONTH={};BYMONTHDAY={}", pattern.month, pattern.day_of_month),
+        "relativeYearly" => format!("FREQ=YEARLY;BYMONTH={};BYDAY={};BYSETPOS={}", pattern.month, days, position),
+        _ => return None,
+    };
+    if pattern.interval > 1 {
+        rule.push_str(&format!(";INTERVAL={}", pattern.interval));
+    }
+    if let (true, Some(first_day)) = (pattern.kind == "weekly", &pattern.first_day_of_week) {
+        let code = WEEKDAYS.iter().find(|(_, name)| name.eq_ignore_ascii_case(first_day))?.0;
+        rule.push_str(&format!(";WKST={}", code));
+    }
+    match (recurrence.range.kind.as_str(), &recurrence.range.end_date) {
+        ("numbered", _) => rule.push_str(&format!(";COUNT={}", recurrence.range.number_of_occurrences)),
+        // UNTIL has the same value type as DTSTART
+        ("endDate", Some(end_date)) if is_all_day => rule.push_str(&format!(";UNTIL={}", end_date.format("%Y%m%d"))),
+        ("endDate", Some(end_date)) => rule.push_str(&format!(";UNTIL={}", end_date.format("%Y%m%dT235959Z"))),
+        _ => (),
+    }
+    Some(rule)
+}
+
+/// The JSON description of the recurrence of an `RRULE`, or `None` if Outlook cannot describe it
+fn rrule_to_recurrence(rrule: &str, start: NaiveDate) -> Option<serde_json::Value> {
+    let parts: HashMap<String, &str> = rrule.split(';')
+        .filter_map(|part| part.split_once('='))
+        .map(|(name, value)| (name.to_ascii_uppercase(), value))
+        .collect();
+    const KNOWN_PARTS: [&str; 9] = ["FREQ", "INTERVAL", "COUNT", "UNTIL", "BYDAY", "BYMONTHDAY", "BYMONTH", "BYSETPOS", "WKST"];
+    if parts.keys().any(|name| !KNOWN_PARTS.contains(&name.as_str())) {
+        return None;
+    }
+    let day_name = |code: &str| WEEKDAYS.iter().find(|(day, _)| day.eq_ignore_ascii_case(code)).map(|(_, name)| *name);
+
+    let interval: u32 = parts.get("INTERVAL").map_or(Some(1), |interval| interval.parse().ok())?;
+    let mut position: Option<i32> = match parts.get("BYSETPOS") {
+        None => None,
+        Some(position) => Some(position.parse().ok()?),
+    };
+    let mut days = Vec::new();
+    for day in parts.get("BYDAY").into_iter().flat_map(|days| days.split(',')) {
+        // e.g. `2TU` or `-1FR`
+        let (ordinal, code) = day.split_at(day.len().checked_sub(2)?);
+        if !ordinal.is_empty() {
+            position = Some(ordinal.trim_start_matches('+').parse().ok()?);
+        }
+        days.push(day_name(code)?);
+    }
+    let index = match position {
+        None => None,
+        Some(position) => Some(WEEK_INDEXES.iter().find(|(n, _)| *n == position)?.1),
+    };
+    let month: u32 = parts.get("BYMONTH").map_or(Some(start.month()), |month| month.parse().ok())?;
+    let day_of_month: u32 = parts.get("BYMONTHDAY").map_or(Some(start.day()), |day| day.parse().ok())?;
+
+    let mut pattern = match (parts.get("FREQ").copied()?, index, days.is_empty()) {
+        ("DAILY", None, true) => serde_json::json!({ "type": "daily" }),
+        ("WEEKLY", None, _) => {
+            if days.is_empty() {
+                days.push(WEEKDAYS[start.weekday().num_days_from_monday() as usize].1);
+            }
+            serde_json::json!({ "type": "weekly", "daysOfWeek": days })
+        },
+        ("MONTHLY", None, true) => serde_json::json!({ "type": "absoluteMonthly", "dayOfMonth": day_of_month }),
+        ("MONTHLY", Some(index), false) => serde_json::json!({ "type": "relativeMonthly", "daysOfWeek": days, "index": index }),
+        ("YEARLY", None, true) => serde_json::json!({ "type": "absoluteYearly", "dayOfMonth": day_of_month, "month": month }),
+        ("YEARLY", Some(index), false) => serde_json::json!({ "type": "relativeYearly", "daysOfWeek": days, "index": index, "month": month }),
+        _ => return None,
+    };
+    pattern["interval"] = interval.into();
+    if let Some(first_day) = parts.get("WKST") {
+        pattern["firstDayOfWeek"] = day_name(first_day)?.into();
+    }
+
+    let start_date = start.format("%Y-%m-%d").to_string();
+    let range = match (parts.get("COUNT"), parts.get("UNTIL")) {
+        (None, None) => serde_json::json!({ "type": "noEnd", "startDate": start_date }),
+        (Some(count), None) => serde_json::json!({ "type": "numbered", "startDate": start_date, "numberOfOccurrences": count.parse::<u32>().ok()? }),
+        (None, Some(until)) => {
+            let end_date = crate::ical::parse_date_value(until)?.naive_utc().date();
+            serde_json::json!({ "type": "endDate", "startDate": start_date, "endDate": end_date.format("%Y-%m-%d").to_string() })
+        },
+        (Some(_), Some(_)) => return None,
+    };
+    Some(serde_json::json!({ "pattern": pattern, "range": range }))
+}
+
+/// Convert an event of the Microsoft Graph API to an item
+fn event_to_item(event: GraphEvent, url: Url) -> Item {
+    let mut extra_parameters = Vec::new();
+    for (name, date) in [("DTSTART", &event.start), ("DTEND", &event.end)] {
+        if let Some(prop) = date.as_ref().and_then(|date| date_property(name, date, event.is_all_day)) {
+            extra_parameters.push(prop);
+        }
+    }
+    let description = event.body.and_then(|body| body.content);
+    let location = event.location.and_then(|location| location.display_name);
+    for (name, value) in [("DESCRIPTION", description), ("LOCATION", location)] {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            extra_parameters.push(Property{ name: name.to_string(), params: None, value: Some(value) });
+        }
+    }
+    if !event.categories.is_empty() {
+        extra_parameters.push(Property{ name: "CATEGORIES".to_string(), params: None, value: Some(event.categories.join(",")) });
+    }
+    let is_all_day = event.is_all_day;
+    if let Some(rrule) = event.recurrence.as_ref().and_then(|recurrence| recurrence_to_rrule(recurrence, is_all_day)) {
+        extra_parameters.push(Property{ name: "RRULE".to_string(), params: None, value: Some(rrule) });
+    }
+    let status = match (event.is_cancelled, event.show_as.as_deref()) {
+        (true, _) => Some("CANCELLED"),
+        (false, Some("tentative")) => Some("TENTATIVE"),
+        _ => None,
+    };
+    if let Some(status) = status {
+        extra_parameters.push(Property{ name: "STATUS".to_string(), params: None, value: Some(status.to_string()) });
+    }
+    if event.show_as.as_deref() == Some("free") {
+        extra_parameters.push(Property{ name: "TRANSP".to_string(), params: None, value: Some("TRANSPARENT".to_string()) });
+    }
+
+    let reminder = match event.is_reminder_on {
+        true => event.reminder_minutes_before_start,
+        false => None,
+    };
+    let subject = event.subject.unwrap_or_default();
+    let mut new_event = Event::new_with_parameters(
+        subject.clone(),
+        event.ical_uid.unwrap_or(event.id),
+        url,
+        SyncStatus::Synced(VersionTag::from(event.etag.or(event.change_key).unwrap_or_default())),
+        event.created_date_time,
+        event.last_modified_date_time.unwrap_or_else(Utc::now),
+        crate::ical::default_prod_id(),
+        extra_parameters,
+    );
+    if let Some(minutes) = reminder {
+        new_event.set_alarms(vec![Alarm::display(AlarmTrigger::before_start(Duration::minutes(minutes)), &subject)]);
+    }
+    Item::Event(new_event)
+}
+
+/// The JSON body that sets the fields of an Outlook event to the ones of an event.
+///
+/// Fields that the event does not have are set to `null`, so that they are cleared when an existing event is updated
+fn event_to_json(event: &Event) -> Result<serde_json::Value, SendError> {
+    let start_prop = event.property("DTSTART");
+    let start = start_prop.and_then(date_to_json)
+        .ok_or_else(|| format!("Event {} has no valid start date, and cannot be uploaded to Outlook", event.url()))?;
+    let end = event.end_or_default().as_ref().and_then(date_to_json)
+        .ok_or_else(|| format!("Event {} has no valid end date, and cannot be uploaded to Outlook", event.url()))?;
+    let is_all_day = start_prop.and_then(|start| start.value.as_deref()).is_some_and(|start| start.len() == 8);
+    let is = |name: &str, value: &str| event.value(name).is_some_and(|actual| actual.eq_ignore_ascii_case(value));
+
+    let recurrence = match event.value("RRULE") {
+        None => None,
+        Some(rrule) => {
+            let start_date = event.start().map(|start| start.naive_utc().date())
+                .ok_or_else(|| format!("Event {} has no valid start date, and cannot be uploaded to Outlook", event.url()))?;
+            Some(rrule_to_recurrence(rrule, start_date)
+                .ok_or_else(|| format!("The recurrence rule of event {} ({}) cannot be described by Outlook", event.url(), rrule))?)
+        },
+    };
+    let categories: Vec<&str> = event.extra_parameters().iter()
+        .filter(|prop| prop.name.eq_ignore_ascii_case("CATEGORIES"))
+        .filter_map(|prop| prop.value.as_deref())
+        .flat_map(|value| value.split(','))
+        .collect();
+    let show_as = match (is("TRANSP", "TRANSPARENT"), is("STATUS", "TENTATIVE")) {
+        (true, _) => "free",
+        (false, true) => "tentative",
+        (false, false) => "busy",
+    };
+    // Only alarms some time before the start of the event can be reminders
+    let reminder = event.alarms().iter().find_map(|alarm| match alarm.trigger() {
+        Some(AlarmTrigger::Relative{ offset, related_to_end: false }) if offset <= Duration::zero() => Some(-offset.num_minutes()),
+        _ => None,
+    });
+
+    let mut json = serde_json::json!({
+        "subject": event.name(),
+        "body": { "content": event.value("DESCRIPTION").unwrap_or_default(), "contentType": "text" },
+        "location": { "displayName": event.value("LOCATION").unwrap_or_default() },
+        "start": start,
+        "end": end,
+        "isAllDay": is_all_day,
+        "showAs": show_as,
+        "categories": categories,
+        "recurrence": recurrence,
+        "isReminderOn": reminder.is_some(),
+    });
+    if let Some(minutes) = reminder {
+        json["reminderMinutesBeforeStart"] = minutes.into();
+    }
+    Ok(json)
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for GraphCalendar {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.insert_event(&item).await.map_err(KFError::from)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.patch_event(&item).await.map_err(KFError::from)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for GraphCalendar {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        Self {
+            name, resource, supported_components, color,
+            listing: Mutex::new(None),
+            assigned_urls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| listing.version_tags.clone())
+            .collect())
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| listing.version_tags.iter()
+                // Events that have not been downloaded are kept, the provider filters them once they are
+                .filter(move |(url, _)| listing.items.get(*url).is_none_or(|item| filter.matches(item)))
+                .map(|(url, tag)| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        self.fetch_item(url).await.map_err(KFError::from)
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        let mut items = Vec::with_capacity(urls.len());
+        for url in urls {
+            items.push(self.fetch_item(url).await?);
+        }
+        Ok(items)
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.delete_event(item_url).await.map_err(KFError::from)
+    }
+
+    async fn move_item(&mut self, item_url: &Url, _destination: &Url) -> Result<SyncStatus, KFError> {
+        // The destination URL cannot be chosen
+        Err(KFError::MoveNotSupported{ url: item_url.clone() })
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.insert_event(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.patch_event(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(item_urls.to_vec())
+            .map(|url| async move { this.delete_event(&url).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    fn take_assigned_url(&mut self, url: &Url) -> Option<Url> {
+        self.assigned_urls.lock().unwrap().remove(url)
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        // This is the first request of a sync, that always lists the latest changes
+        self.refresh().await?;
+        let listing = self.listing.lock().unwrap();
+        let mut tags: Vec<_> = listing.iter()
+            .flat_map(|listing| &listing.version_tags)
+            .map(|(url, tag)| format!("{} {}\n", url, tag.as_str()))
+            .collect();
+        tags.sort();
+        Ok(CalendarVersion{
+            ctag: Some(format!("{:x}", checksum(tags.concat().as_bytes()))),
+            sync_token: listing.as_ref().and_then(|listing| listing.delta_link.as_ref()).map(Url::to_string),
+        })
+    }
+
+    fn resume_from(&mut self, version: &CalendarVersion, version_tags: HashMap<Url, VersionTag>) {
+        let mut listing = self.listing.lock().unwrap();
+        let delta_link = version.sync_token.as_deref().and_then(|link| link.parse::<Url>().ok());
+        if let (None, Some(delta_link)) = (listing.as_ref(), delta_link) {
+            *listing = Some(EventListing{ version_tags, items: HashMap::new(), delta_link: Some(delta_link) });
+        }
+    }
+
+    async fn update_color(&mut self, _color: Option<Color>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_description(&mut self, _description: Option<String>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_timezone(&mut self, _timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        let mut url = self.collection_url();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            // From `/calendars/{id}/events` to `/calendars/{id}`
+            segments.pop();
+        }
+        send_json(&self.resource, Method::PATCH, url, Some(&serde_json::json!({ "name": name })), None).await?;
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_conversion() {
+        let url: Url = "https://graph.microsoft.com/v1.0/me/todo/lists/AAMk/tasks/AAMkT1".parse().unwrap();
+        let task: TodoTask = serde_json::from_str(r#"{
+            "@odata.etag": "W/\"j8+Rk\"", "id": "AAMkT1", "title": "Water the plants", "importance": "high",
+            "status": "completed", "completedDateTime": { "dateTime": "2021-03-01T00:00:00.0000000", "timeZone": "UTC" },
+            "body": { "content": "The ones in the kitchen", "contentType": "text" },
+            "dueDateTime": { "dateTime": "2021-03-02T00:00:00.0000000", "timeZone": "UTC" },
+            "isReminderOn": true, "reminderDateTime": { "dateTime": "2021-03-01T09:00:00.0000000", "timeZone": "UTC" },
+            "lastModifiedDateTime": "2021-03-01T10:00:00.1234567Z"
+        }"#).unwrap();
+        let item = task_to_item(task, url);
+        let task = item.unwrap_task();
+        assert_eq!(task.uid(), "AAMkT1");
+        assert_eq!(task.sync_status(), &SyncStatus::Synced(VersionTag::from("W/\"j8+Rk\"".to_string())));
+        assert!(task.completed());
+        assert!(task.extra_parameters().iter().any(|prop| prop.name == "PRIORITY" && prop.value.as_deref() == Some("1")));
+        assert!(task.extra_parameters().iter().any(|prop| prop.name == "DUE" && prop.value.as_deref() == Some("20210302")));
+        let reminder = "2021-03-01T09:00:00Z".parse().unwrap();
+        assert_eq!(task.alarms()[0].trigger(), Some(AlarmTrigger::Absolute(reminder)));
+
+        let json = task_to_json(task);
+        assert_eq!(json["title"], "Water the plants");
+        assert_eq!(json["importance"], "high");
+        assert_eq!(json["body"]["content"], "The ones in the kitchen");
+        assert_eq!(json["dueDateTime"]["dateTime"], "2021-03-02T00:00:00");
+        assert_eq!(json["completedDateTime"]["dateTime"], "2021-03-01T00:00:00");
+        assert_eq!(json["reminderDateTime"]["dateTime"], "2021-03-01T09:00:00");
+        assert_eq!(json["isReminderOn"], true);
+
+        // Fields a task does not have are cleared
+        let task = Task::new("Call Bob".to_string(), false, &"https://graph.microsoft.com/v1.0/me/todo/lists/AAMk/tasks/".parse().unwrap());
+        let json = task_to_json(&task);
+        assert_eq!(json["status"], "notStarted");
+        assert_eq!(json["importance"], "normal");
+        assert_eq!(json["isReminderOn"], false);
+        assert!(json["completedDateTime"].is_null() && json["dueDateTime"].is_null() && json["reminderDateTime"].is_null());
+    }
+
+    #[test]
+    fn test_event_conversion() {
+        let url: Url = "https://graph.microsoft.com/v1.0/me/calendars/AAMkC/events/AAMkE1".parse().unwrap();
+        let event: GraphEvent = serde_json::from_str(r#"{
+            "@odata.etag": "W/\"DwAAABYA\"", "id": "AAMkE1", "type": "seriesMaster", "iCalUId": "040000008200E001",
+            "subject": "Team meeting", "body": { "contentType": "text", "content": "Weekly sync" },
+            "location": { "displayName": "Room 4" }, "categories": ["Work", "Meetings"], "showAs": "tentative",
+            "start": { "dateTime": "2021-04-01T08:00:00.0000000", "timeZone": "UTC" },
+            "end": { "dateTime": "2021-04-01T09:00:00.0000000", "timeZone": "UTC" },
+            "recurrence": {
+                "pattern": { "type": "relativeMonthly", "interval": 2, "daysOfWeek": ["thursday"], "index": "first" },
+                "range": { "type": "endDate", "startDate": "2021-04-01", "endDate": "2021-12-31" }
+            },
+            "isReminderOn": true, "reminderMinutesBeforeStart": 15,
+            "lastModifiedDateTime": "2021-03-01T10:00:00.1234567Z"
+        }"#).unwrap();
+        let item = event_to_item(event, url.clone());
+        let event = item.unwrap_event();
+        assert_eq!(event.uid(), "040000008200E001");
+        assert_eq!(event.name(), "Team meeting");
+        assert_eq!(event.sync_status(), &SyncStatus::Synced(VersionTag::from("W/\"DwAAABYA\"".to_string())));
+        assert_eq!(event.value("DTSTART"), Some("20210401T080000Z"));
+        assert_eq!(event.value("DESCRIPTION"), Some("Weekly sync"));
+        assert_eq!(event.value("CATEGORIES"), Some("Work,Meetings"));
+        assert_eq!(event.value("STATUS"), Some("TENTATIVE"));
+        assert_eq!(event.value("RRULE"), Some("FREQ=MONTHLY;BYDAY=TH;BYSETPOS=1;INTERVAL=2;UNTIL=20211231T235959Z"));
+        assert_eq!(event.alarms()[0].trigger(), Some(AlarmTrigger::before_start(Duration::minutes(15))));
+
+        let json = event_to_json(event).unwrap();
+        assert_eq!(json["subject"], "Team meeting");
+        assert_eq!(json["location"]["displayName"], "Room 4");
+        assert_eq!(json["start"], serde_json::json!({ "dateTime": "2021-04-01T08:00:00", "timeZone": "UTC" }));
+        assert_eq!(json["isAllDay"], false);
+        assert_eq!(json["showAs"], "tentative");
+        assert_eq!(json["categories"], serde_json::json!(["Work", "Meetings"]));
+        assert_eq!(json["recurrence"]["pattern"], serde_json::json!({ "type": "relativeMonthly", "interval": 2, "daysOfWeek": ["thursday"], "index": "first" }));
+        assert_eq!(json["recurrence"]["range"], serde_json::json!({ "type": "endDate", "startDate": "2021-04-01", "endDate": "2021-12-31" }));
+        assert_eq!(json["reminderMinutesBeforeStart"], 15);
+
+        // Whole-day events without an end last one day
+        let date = |name: &str, value: &str| Property{ name: name.to_string(), params: Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])]), value: Some(value.to_string()) };
+        let mut event = Event::new("Holidays".to_string(), date("DTSTART", "20210430"), date("DTEND", "20210501"), &"https://graph.microsoft.com/v1.0/me/calendars/AAMkC/events/".parse().unwrap());
+        event.remove_property("DTEND");
+        let json = event_to_json(&event).unwrap();
+        assert_eq!(json["isAllDay"], true);
+        assert_eq!(json["end"]["dateTime"], "2021-05-01T00:00:00");
+        assert_eq!(json["showAs"], "busy");
+        assert!(json["recurrence"].is_null());
+
+        // Outlook cannot describe every recurrence
+        event.set_property(Property{ name: "RRULE".to_string(), params: None, value: Some("FREQ=HOURLY;INTERVAL=2".to_string()) });
+        assert!(event_to_json(&event).is_err());
+    }
+
+    #[test]
+    fn test_recurrences() {
+        let start = NaiveDate::from_ymd(2021, 4, 1);
+        for rrule in ["FREQ=DAILY;INTERVAL=3;COUNT=10", "FREQ=WEEKLY;BYDAY=MO,WE;WKST=SU", "FREQ=MONTHLY;BYMONTHDAY=15",
+                      "FREQ=YEARLY;BYMONTH=4;BYMONTHDAY=1", "FREQ=YEARLY;BYMONTH=11;BYDAY=TH;BYSETPOS=4"] {
+            let json = rrule_to_recurrence(rrule, start).unwrap();
+            let recurrence: PatternedRecurrence = serde_json::from_value(json).unwrap();
+            assert_eq!(recurrence_to_rrule(&recurrence, false).as_deref(), Some(rrule));
+        }
+        // Ordinals can be given in BYDAY
+        let json = rrule_to_recurrence("FREQ=MONTHLY;BYDAY=-1FR", start).unwrap();
+        assert_eq!(json["pattern"]["index"], "last");
+        // Weekly rules default to the day of their start
+        let json = rrule_to_recurrence("FREQ=WEEKLY", start).unwrap();
+        assert_eq!(json["pattern"]["daysOfWeek"], serde_json::json!(["thursday"]));
+        assert!(rrule_to_recurrence("FREQ=MONTHLY;BYDAY=5SU", start).is_none());
+        assert!(rrule_to_recurrence("FREQ=DAILY;BYHOUR=9", start).is_none());
+    }
+}
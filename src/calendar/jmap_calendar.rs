@@ -0,0 +1,1349 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use csscolorparser::Color;
+use futures_util::stream::{self, StreamExt};
+use ical::property::Property;
+use url::Url;
+
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::calendar::remote_calendar::SendError;
+use crate::alarm::{Alarm, AlarmAction, AlarmTrigger};
+use crate::item::Item;
+use crate::item::VersionTag;
+use crate::item::SyncStatus;
+use crate::task::{CompletionStatus, Task};
+use crate::event::Event;
+use crate::resource::Resource;
+use crate::provider::filter::SyncFilter;
+use crate::error::KFError;
+use crate::jmap::{method_result, set_error, JmapConnection, CALENDARS_CAPABILITY, TASKS_CAPABILITY};
+use crate::kv_store::checksum;
+
+/// The tasks of a list, as they have last been downloaded
+#[derive(Debug)]
+struct Listing {
+    items: HashMap<Url, Item>,
+    /// The state of the tasks of the account, that the server computes changes from
+    state: Option<String>,
+}
+
+
+
+/// A task list of a JMAP account, that is synced with [JMAP for Tasks](https://datatracker.ietf.org/doc/draft-ietf-jmap-tasks/).
+///
+/// It is created by a [`Jmap`](crate::jmap::Jmap) source. \
+/// Tasks are JSCalendar objects, that are converted to iCal items: their UIDs, titles, progresses, descriptions (as `DESCRIPTION`), due dates (as `DUE`),
+/// priorities (as `PRIORITY`), keywords (as `CATEGORIES`) and alerts (as alarms) are synced.
+/// Other iCal properties are lost when tasks are downloaded. \
+/// JMAP servers choose the IDs of new tasks: the URL of a task is built from its ID, and a [`Provider`](crate::provider::Provider) moves the tasks it adds
+/// to these URLs (see [`DavCalendar::take_assigned_url`]). JMAP objects have no version tags: the version tag of an item is a checksum of its JSCalendar object.
+///
+/// The whole list is downloaded at the first sync. Later syncs only download the changes made since (with `Task/changes`, that lists the changes of every list of the account).
+#[derive(Debug)]
+pub struct JmapTaskList {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+
+    connection: Arc<JmapConnection>,
+    listing: Mutex<Option<Listing>>,
+    assigned_urls: Mutex<HashMap<Url, Url>>,
+}
+
+impl JmapTaskList {
+    /// Share the connection (and the session) of a [`Jmap`](crate::jmap::Jmap) source
+    pub(crate) fn with_connection(mut self, connection: Arc<JmapConnection>) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    /// The JMAP ID of this task list
+    pub fn id(&self) -> String {
+        let segments = self.resource.url().path_segments().into_iter().flatten().rev().filter(|segment| !segment.is_empty());
+        // The URL ends with `/tasklists/{id}/`
+        segments.take(1).map(percent_decode).collect()
+    }
+
+    /// The URL of the task that has a given JMAP ID
+    pub fn item_url(&self, id: &str) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(id);
+        }
+        url
+    }
+
+    /// The JMAP ID of a task of this list
+    fn item_id(&self, url: &Url) -> Result<String, SendError> {
+        url.path_segments().and_then(|mut segments| segments.next_back())
+            .filter(|_| url.as_str().starts_with(self.resource.url().as_str()))
+            .map(percent_decode)
+            .ok_or_else(|| format!("Item {} does not belong to task list {}", url, self.resource.url()).into())
+    }
+
+    /// Download the changes made since the last download (or every task, the first time)
+    async fn refresh(&self) -> Result<(), SendError> {
+        let state = self.listing.lock().unwrap().as_ref().and_then(|listing| listing.state.clone());
+        if let Some(state) = state {
+            if self.fetch_changes(state).await? {
+                return Ok(());
+            }
+            log::info!("The server cannot list the changes made to {} any more, downloading every task again", self.resource.url());
+        }
+        self.fetch_all().await
+    }
+
+    async fn fetch_all(&self) -> Result<(), SendError> {
+        let responses = self.connection.call(vec![
+            ("Task/query", serde_json::json!({ "filter": { "inTaskList": self.id() } })),
+            ("Task/get", serde_json::json!({ "#ids": { "resultOf": "0", "name": "Task/query", "path": "/ids" } })),
+        ]).await?;
+        let mut responses = responses.into_iter();
+        method_result(responses.next().ok_or("Invalid reply to Task/query")?, self.resource.url())?;
+        let tasks = method_result(responses.next().ok_or("Invalid reply to Task/get")?, self.resource.url())?;
+
+        let mut items = HashMap::new();
+        for task in tasks["list"].as_array().into_iter().flatten() {
+            let url = self.item_url(task["id"].as_str().unwrap_or_default());
+            items.insert(url.clone(), task_to_item(task, url));
+        }
+        let state = tasks["state"].as_str().map(String::from);
+        *self.listing.lock().unwrap() = Some(Listing{ items, state });
+        Ok(())
+    }
+
+    /// Download the changes made since `state`, or return `false` if the server cannot tell them
+    async fn fetch_changes(&self, mut state: String) -> Result<bool, SendError> {
+        loop {
+            let responses = self.connection.call(vec![
+                ("Task/changes", serde_json::json!({ "sinceState": state })),
+                ("Task/get", serde_json::json!({ "#ids": { "resultOf": "0", "name": "Task/changes", "path": "/created" } })),
+                ("Task/get", serde_json::json!({ "#ids": { "resultOf": "0", "name": "Task/changes", "path": "/updated" } })),
+            ]).await?;
+            let mut responses = responses.into_iter();
+            let changes = responses.next().ok_or("Invalid reply to Task/changes")?;
+            if changes.0 == "error" && changes.1["type"] == "cannotCalculateChanges" {
+                return Ok(false);
+            }
+            let changes = method_result(changes, self.resource.url())?;
+            let created = method_result(responses.next().ok_or("Invalid reply to Task/get")?, self.resource.url())?;
+            let updated = method_result(responses.next().ok_or("Invalid reply to Task/get")?, self.resource.url())?;
+
+            let mut listing = self.listing.lock().unwrap();
+            let listing = listing.get_or_insert_with(|| Listing{ items: HashMap::new(), state: None });
+            for id in changes["destroyed"].as_array().into_iter().flatten() {
+                listing.items.remove(&self.item_url(id.as_str().unwrap_or_default()));
+            }
+            // The changes of every list of the account are returned
+            let tasks = created["list"].as_array().into_iter().flatten().chain(updated["list"].as_array().into_iter().flatten());
+            for task in tasks {
+                let url = self.item_url(task["id"].as_str().unwrap_or_default());
+                match task["taskListId"].as_str() == Some(&self.id()) {
+                    true => { listing.items.insert(url.clone(), task_to_item(task, url)); },
+                    false => { listing.items.remove(&url); },
+                }
+            }
+            state = changes["newState"].as_str().ok_or("Invalid reply to Task/changes")?.to_string();
+            listing.state = Some(state.clone());
+            if changes["hasMoreChanges"] != true {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Download the tasks, unless they have been downloaded already
+    async fn ensure_listing(&self) -> Result<(), SendError> {
+        if self.listing.lock().unwrap().is_none() {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    /// Update the downloaded tasks with a task that has just been uploaded (i.e. the reply to the `Task/get` that follows a `Task/set`), and return its URL and its sync status
+    fn store_uploaded(&self, reply: serde_json::Value) -> Result<(Url, SyncStatus), SendError> {
+        let task = reply["list"].get(0).ok_or_else(|| format!("Invalid reply to Task/get for {}", self.resource.url()))?;
+        let url = self.item_url(task["id"].as_str().unwrap_or_default());
+        let item = task_to_item(task, url.clone());
+        let sync_status = item.sync_status().clone();
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.items.insert(url.clone(), item);
+        }
+        Ok((url, sync_status))
+    }
+
+    async fn insert_task(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let task = jmap_task(item, self.resource.url())?;
+        let mut json = task_to_json(task);
+        json["taskListId"] = serde_json::json!(self.id());
+        json["uid"] = serde_json::json!(task.uid());
+        let responses = self.connection.call(vec![
+            ("Task/set", serde_json::json!({ "create": { "new": json } })),
+            ("Task/get", serde_json::json!({ "#ids": { "resultOf": "0", "name": "Task/set", "path": "/created/*/id" } })),
+        ]).await?;
+        let mut responses = responses.into_iter();
+        let set = method_result(responses.next().ok_or("Invalid reply to Task/set")?, self.resource.url())?;
+        if let Some(error) = set["notCreated"].get("new") {
+            return Err(set_error(error, item.url()));
+        }
+        let reply = method_result(responses.next().ok_or("Invalid reply to Task/get")?, self.resource.url())?;
+        let (url, sync_status) = self.store_uploaded(reply)?;
+        self.assigned_urls.lock().unwrap().insert(item.url().clone(), url);
+        Ok(sync_status)
+    }
+
+    async fn update_task(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let task = jmap_task(item, self.resource.url())?;
+        let old_tag = match item.sync_status() {
+            SyncStatus::NotSynced => return Err("Cannot update an item that has not been synced already".into()),
+            SyncStatus::Synced(_) => return Err("Cannot update an item that has not changed".into()),
+            SyncStatus::LocallyModified(tag) => tag,
+            SyncStatus::LocallyDeleted(tag) => tag,
+        };
+        // JMAP has no conditional updates, but the changes of the server have been downloaded at the start of this sync
+        let current_tag = self.listing.lock().unwrap().as_ref()
+            .and_then(|listing| listing.items.get(item.url()))
+            .and_then(|current| current.sync_status().version_tag().cloned());
+        if current_tag.is_some_and(|current_tag| &current_tag != old_tag) {
+            return Err(KFError::Conflict{ url: item.url().clone() }.into());
+        }
+
+        let id = self.item_id(item.url())?;
+        let responses = self.connection.call(vec![
+            ("Task/set", serde_json::json!({ "update": { &id: task_to_json(task) } })),
+            ("Task/get", serde_json::json!({ "ids": [&id] })),
+        ]).await?;
+        let mut responses = responses.into_iter();
+        let set = method_result(responses.next().ok_or("Invalid reply to Task/set")?, self.resource.url())?;
+        if let Some(error) = set["notUpdated"].get(&id) {
+            return Err(set_error(error, item.url()));
+        }
+        let reply = method_result(responses.next().ok_or("Invalid reply to Task/get")?, self.resource.url())?;
+        let (_, sync_status) = self.store_uploaded(reply)?;
+        Ok(sync_status)
+    }
+
+    async fn delete_task(&self, item_url: &Url) -> Result<(), SendError> {
+        let id = self.item_id(item_url)?;
+        let response = self.connection.call(vec![("Task/set", serde_json::json!({ "destroy": [&id] }))]).await?
+            .into_iter().next().ok_or("Invalid reply to Task/set")?;
+        let set = method_result(response, self.resource.url())?;
+        if let Some(error) = set["notDestroyed"].get(&id) {
+            return Err(set_error(error, item_url));
+        }
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.items.remove(item_url);
+        }
+        Ok(())
+    }
+
+    fn forbidden(&self) -> KFError {
+        KFError::Forbidden{ url: self.resource.url().clone() }
+    }
+}
+
+fn percent_decode(segment: &str) -> String {
+    percent_encoding::percent_decode_str(segment).decode_utf8_lossy().to_string()
+}
+
+fn jmap_task<'a>(item: &'a Item, calendar_url: &Url) -> Result<&'a Task, SendError> {
+    match item {
+        Item::Task(task) => Ok(task),
+        Item::Event(_) | Item::Contact(_) => Err(format!("Item {} cannot be uploaded to {}: JMAP task lists only contain tasks", item.url(), calendar_url).into()),
+    }
+}
+
+/// Convert a JSCalendar `UTCDateTime` (e.g. `2021-03-01T09:00:00Z`)
+fn parse_utc_date_time(value: &serde_json::Value) -> Option<DateTime<Utc>> {
+    value.as_str().and_then(|date| date.parse().ok())
+}
+
+/// Convert a JSCalendar `LocalDateTime` (e.g. `2021-03-01T09:00:00`) to an iCal `DATE-TIME` value (e.g. `20210301T090000`)
+fn local_to_ical(value: &str) -> String {
+    value.split('.').next().unwrap_or_default().replace(&['-', ':'][..], "")
+}
+
+/// Convert an iCal `DATE` or `DATE-TIME` value to a JSCalendar `LocalDateTime` (midnight, for dates)
+fn ical_to_local(value: &str) -> Option<String> {
+    let value = value.trim_end_matches('Z');
+    let (date, time) = value.split_once('T').unwrap_or((value, "000000"));
+    if date.len() != 8 || time.len() != 6 {
+        return None;
+    }
+    Some(format!("{}-{}-{}T{}:{}:{}", &date[0..4], &date[4..6], &date[6..8], &time[0..2], &time[2..4], &time[4..6]))
+}
+
+/// The iCal property (e.g. `DUE` or `DTSTART`) of a JSCalendar `LocalDateTime`, in a time zone (or floating, when it has none)
+fn local_date_property(name: &str, local: &str, time_zone: Option<&str>, show_without_time: bool) -> Property {
+    let (params, value) = match (show_without_time, time_zone) {
+        (true, _) => (Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])]), local_to_ical(local).chars().take(8).collect()),
+        (false, Some("Etc/UTC")) | (false, Some("UTC")) => (None, format!("{}Z", local_to_ical(local))),
+        (false, Some(time_zone)) => (Some(vec![("TZID".to_string(), vec![time_zone.to_string()])]), local_to_ical(local)),
+        // A floating time
+        (false, None) => (None, local_to_ical(local)),
+    };
+    Property{ name: name.to_string(), params, value: Some(value) }
+}
+
+/// The JSCalendar `LocalDateTime`, time zone and `showWithoutTime` of an iCal date property
+fn property_to_local(prop: &Property) -> (Option<String>, Option<String>, bool) {
+    let param = |name: &str| prop.params.iter().flatten().find(|(key, _)| key.eq_ignore_ascii_case(name)).and_then(|(_, values)| values.first());
+    let local = prop.value.as_deref().and_then(ical_to_local);
+    match (param("VALUE").map(String::as_str), param("TZID")) {
+        (Some("DATE"), _) => (local, None, true),
+        (_, Some(time_zone)) => (local, Some(time_zone.clone()), false),
+        _ if prop.value.as_deref().is_some_and(|value| value.ends_with('Z')) => (local, Some("Etc/UTC".to_string()), false),
+        _ => (local, None, false),
+    }
+}
+
+/// The `CATEGORIES` property of JSCalendar keywords, if there are any
+fn keywords_to_categories(keywords: &serde_json::Value) -> Option<Property> {
+    let keywords = keywords.as_object().filter(|keywords| !keywords.is_empty())?;
+    let categories: Vec<_> = keywords.keys().map(String::as_str).collect();
+    Some(Property{ name: "CATEGORIES".to_string(), params: None, value: Some(categories.join(",")) })
+}
+
+/// The JSCalendar keywords of the `CATEGORIES` of an item, or `null` if it has none
+fn categories_to_keywords(extra_parameters: &[Property]) -> serde_json::Value {
+    let keywords: serde_json::Map<_, _> = extra_parameters.iter()
+        .filter(|prop| prop.name.eq_ignore_ascii_case("CATEGORIES"))
+        .flat_map(|prop| prop.value.as_deref().unwrap_or_default().split(','))
+        .map(str::trim)
+        .filter(|category| !category.is_empty())
+        .map(|category| (category.to_string(), serde_json::Value::Bool(true)))
+        .collect();
+    if keywords.is_empty() { serde_json::Value::Null } else { keywords.into() }
+}
+
+/// Convert JSCalendar alerts to alarms, whose descriptions are `title`
+fn alerts_to_alarms(alerts: &serde_json::Value, title: &str) -> Vec<Alarm> {
+    alerts.as_object().into_iter().flat_map(|alerts| alerts.values())
+        .filter_map(|alert| {
+            let trigger = match alert["trigger"]["@type"].as_str() {
+                Some("AbsoluteTrigger") => AlarmTrigger::Absolute(parse_utc_date_time(&alert["trigger"]["when"])?),
+                Some("OffsetTrigger") => AlarmTrigger::Relative{
+                    offset: crate::ical::parse_duration(alert["trigger"]["offset"].as_str()?).ok()?,
+                    related_to_end: alert["trigger"]["relativeTo"] == "end",
+                },
+                _ => return None,
+            };
+            match alert["action"].as_str() {
+                Some("email") => Some(Alarm::new(AlarmAction::Email, trigger)),
+                _ => Some(Alarm::display(trigger, title)),
+            }
+        })
+        .collect()
+}
+
+/// Convert alarms to JSCalendar alerts, or `null` if there are none
+fn alarms_to_alerts(alarms: &[Alarm]) -> serde_json::Value {
+    let alerts: serde_json::Map<_, _> = alarms.iter()
+        .filter_map(|alarm| {
+            let trigger = match alarm.trigger()? {
+                AlarmTrigger::Absolute(date) => serde_json::json!({ "@type": "AbsoluteTrigger", "when": date.format("%Y-%m-%dT%H:%M:%SZ").to_string() }),
+                AlarmTrigger::Relative{ offset, related_to_end } => serde_json::json!({
+                    "@type": "OffsetTrigger",
+                    "offset": crate::alarm::format_duration(&offset),
+                    "relativeTo": if related_to_end { "end" } else { "start" },
+                }),
+            };
+            let action = match alarm.action() {
+                AlarmAction::Email => "email",
+                _ => "display",
+            };
+            Some(serde_json::json!({ "@type": "Alert", "trigger": trigger, "action": action }))
+        })
+        .enumerate()
+        .map(|(index, alert)| ((index + 1).to_string(), alert))
+        .collect();
+    if alerts.is_empty() { serde_json::Value::Null } else { alerts.into() }
+}
+
+/// Convert a JSCalendar task to an item
+fn task_to_item(task: &serde_json::Value, url: Url) -> Item {
+    let completion_status = match task["progress"].as_str() {
+        Some("completed") => CompletionStatus::Completed(parse_utc_date_time(&task["progressUpdated"])),
+        _ => CompletionStatus::Uncompleted,
+    };
+    let mut extra_parameters = Vec::new();
+    if let Some(description) = task["description"].as_str().filter(|description| !description.is_empty()) {
+        extra_parameters.push(Property{ name: "DESCRIPTION".to_string(), params: None, value: Some(description.to_string()) });
+    }
+    if let Some(due) = task["due"].as_str() {
+        extra_parameters.push(local_date_property("DUE", due, task["timeZone"].as_str(), task["showWithoutTime"] == true));
+    }
+    if let Some(priority) = task["priority"].as_u64().filter(|priority| *priority > 0) {
+        extra_parameters.push(Property{ name: "PRIORITY".to_string(), params: None, value: Some(priority.to_string()) });
+    }
+    extra_parameters.extend(keywords_to_categories(&task["keywords"]));
+
+    let title = task["title"].as_str().unwrap_or_default().to_string();
+    let alarms = alerts_to_alarms(&task["alerts"], &title);
+
+    let tag = format!("{:x}", checksum(task.to_string().as_bytes()));
+    let mut new_task = Task::new_with_parameters(
+        title,
+        task["uid"].as_str().or_else(|| task["id"].as_str()).unwrap_or_default().to_string(),
+        url,
+        completion_status,
+        SyncStatus::Synced(VersionTag::from(tag)),
+        parse_utc_date_time(&task["created"]),
+        parse_utc_date_time(&task["updated"]).unwrap_or_else(Utc::now),
+        crate::ical::default_prod_id(),
+        extra_parameters,
+    );
+    new_task.set_alarms(alarms);
+    Item::Task(new_task)
+}
+
+/// The JSCalendar properties of a task that are synced.
+///
+/// Properties that the task does not have are set to `null`, so that they are cleared when an existing task is updated
+fn task_to_json(task: &Task) -> serde_json::Value {
+    let property = |name: &str| task.extra_parameters().iter().find(|prop| prop.name.eq_ignore_ascii_case(name));
+    let value = |name: &str| property(name).and_then(|prop| prop.value.as_deref());
+
+    let (due, time_zone, show_without_time) = match property("DUE") {
+        None => (None, None, false),
+        Some(prop) => property_to_local(prop),
+    };
+    let priority = value("PRIORITY").and_then(|priority| priority.trim().parse::<u8>().ok()).unwrap_or(0);
+
+    let (progress, progress_updated) = match task.completion_status() {
+        CompletionStatus::Completed(date) => ("completed", date.map(|date| date.format("%Y-%m-%dT%H:%M:%SZ").to_string())),
+        CompletionStatus::Uncompleted => ("needs-action", None),
+    };
+    serde_json::json!({
+        "title": task.name(),
+        "description": value("DESCRIPTION").unwrap_or_default(),
+        "due": due,
+        "timeZone": time_zone,
+        "showWithoutTime": show_without_time,
+        "priority": priority,
+        "keywords": categories_to_keywords(task.extra_parameters()),
+        "alerts": alarms_to_alerts(task.alarms()),
+        "progress": progress,
+        "progressUpdated": progress_updated,
+    })
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for JmapTaskList {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.insert_task(&item).await.map_err(KFError::from)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.update_task(&item).await.map_err(KFError::from)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for JmapTaskList {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        // The session resource, i.e. the URL of this list without its `/tasklists/{id}/` suffix
+        let mut url = resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().pop().pop();
+        }
+        let connection = Arc::new(JmapConnection::new(resource.combine(url.path()), TASKS_CAPABILITY));
+        Self {
+            name, resource, supported_components, color, connection,
+            listing: Mutex::new(None),
+            assigned_urls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| &listing.items)
+            .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| &listing.items)
+            .filter(|(_, item)| filter.matches(item))
+            .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        self.ensure_listing().await?;
+        Ok(self.listing.lock().unwrap().as_ref().and_then(|listing| listing.items.get(url).cloned()))
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(urls.iter()
+            .map(|url| listing.as_ref().and_then(|listing| listing.items.get(url).cloned()))
+            .collect())
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.delete_task(item_url).await.map_err(KFError::from)
+    }
+
+    async fn move_item(&mut self, item_url: &Url, _destination: &Url) -> Result<SyncStatus, KFError> {
+        // The destination URL cannot be chosen
+        Err(KFError::MoveNotSupported{ url: item_url.clone() })
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.insert_task(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.update_task(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(item_urls.to_vec())
+            .map(|url| async move { this.delete_task(&url).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    fn take_assigned_url(&mut self, url: &Url) -> Option<Url> {
+        self.assigned_urls.lock().unwrap().remove(url)
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        // This is the first request of a sync, that always downloads the latest changes
+        self.refresh().await?;
+        let mut tags: Vec<_> = self.get_item_version_tags().await?.into_iter()
+            .map(|(url, tag)| format!("{} {}\n", url, tag.as_str()))
+            .collect();
+        tags.sort();
+        Ok(CalendarVersion{ ctag: Some(format!("{:x}", checksum(tags.concat().as_bytes()))), sync_token: None })
+    }
+
+    async fn update_color(&mut self, _color: Option<Color>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_description(&mut self, _description: Option<String>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_timezone(&mut self, _timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        let id = self.id();
+        let response = self.connection.call(vec![("TaskList/set", serde_json::json!({ "update": { &id: { "name": name } } }))]).await
+            ?
+            .into_iter().next().ok_or("Invalid reply to TaskList/set")?;
+        let set = method_result(response, self.resource.url())?;
+        if let Some(error) = set["notUpdated"].get(&id) {
+            return Err(set_error(error, self.resource.url()).into());
+        }
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+}
+
+
+/// The events of a calendar, as they have last been downloaded
+#[derive(Debug, Default)]
+struct EventListing {
+    version_tags: HashMap<Url, VersionTag>,
+    /// The events that have been downloaded. Events that have been listed in a previous session (see [`DavCalendar::resume_from`]) are downloaded when they are requested
+    items: HashMap<Url, Item>,
+    /// The state of the events of the account, that the server computes changes from
+    state: Option<String>,
+}
+
+/// The parts of `RRULE`s whose values are lists of numbers, and the JSCalendar properties they map to
+const NUMBER_PARTS: [(&str, &str); 7] = [("BYWEEKNO", "byWeekNo"), ("BYYEARDAY", "byYearDay"), ("BYMONTHDAY", "byMonthDay"),
+    ("BYHOUR", "byHour"), ("BYMINUTE", "byMinute"), ("BYSECOND", "bySecond"), ("BYSETPOS", "bySetPosition")];
+
+/// A calendar of a JMAP account, that is synced with [JMAP for Calendars](https://datatracker.ietf.org/doc/draft-ietf-jmap-calendars/).
+///
+/// It is created by a [`JmapCalendars`](crate::jmap::JmapCalendars) source. \
+/// Events are JSCalendar objects, that are converted to iCal items: their UIDs, titles, descriptions (as `DESCRIPTION`), starts and durations (as `DTSTART` and `DTEND`),
+/// locations, keywords (as `CATEGORIES`), recurrence rules (as `RRULE`), statuses, free/busy statuses (as `TRANSP`) and alerts (as alarms) are synced.
+/// Other iCal properties (e.g. `EXDATE`) are lost when events are downloaded, and the other JSCalendar properties (e.g. the overrides of recurring events) are kept when events are uploaded. \
+/// JMAP servers choose the IDs of new events: the URL of an event is built from its ID, and a [`Provider`](crate::provider::Provider) moves the events it adds
+/// to these URLs (see [`DavCalendar::take_assigned_url`]). The version tag of an item is a checksum of its JSCalendar object.
+///
+/// The whole calendar is downloaded at the first sync. Later syncs only download the changes made since (with `CalendarEvent/changes`, that lists the changes of every calendar of the account),
+/// even after the app is restarted, since the state of the events of the account is kept as the sync token of the calendar (see [`DavCalendar::resume_from`]).
+/// When the server cannot list the changes made since a state any more, the whole calendar is downloaded again.
+#[derive(Debug)]
+pub struct JmapCalendar {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+
+    connection: Arc<JmapConnection>,
+    listing: Mutex<Option<EventListing>>,
+    assigned_urls: Mutex<HashMap<Url, Url>>,
+}
+
+impl JmapCalendar {
+    /// Share the connection (and the session) of a [`JmapCalendars`](crate::jmap::JmapCalendars) source
+    pub(crate) fn with_connection(mut self, connection: Arc<JmapConnection>) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    /// The JMAP ID of this calendar
+    pub fn id(&self) -> String {
+        let segments = self.resource.url().path_segments().into_iter().flatten().rev().filter(|segment| !segment.is_empty());
+        // The URL ends with `/calendars/{id}/`
+        segments.take(1).map(percent_decode).collect()
+    }
+
+    /// The URL of the event that has a given JMAP ID
+    pub fn item_url(&self, id: &str) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(id);
+        }
+        url
+    }
+
+    /// The JMAP ID of an event of this calendar
+    fn item_id(&self, url: &Url) -> Result<String, SendError> {
+        url.path_segments().and_then(|mut segments| segments.next_back())
+            .filter(|_| url.as_str().starts_with(self.resource.url().as_str()))
+            .map(percent_decode)
+            .ok_or_else(|| format!("Item {} does not belong to calendar {}", url, self.resource.url()).into())
+    }
+
+    /// Download the changes made since the last download (or every event, the first time)
+    async fn refresh(&self) -> Result<(), SendError> {
+        let state = self.listing.lock().unwrap().as_ref().and_then(|listing| listing.state.clone());
+        if let Some(state) = state {
+            if self.fetch_changes(state).await? {
+                return Ok(());
+            }
+            log::info!("The server cannot list the changes made to {} any more, downloading every event again", self.resource.url());
+        }
+        self.fetch_all().await
+    }
+
+    async fn fetch_all(&self) -> Result<(), SendError> {
+        let responses = self.connection.call(vec![
+            ("CalendarEvent/query", serde_json::json!({ "filter": { "inCalendar": self.id() } })),
+            ("CalendarEvent/get", serde_json::json!({ "#ids": { "resultOf": "0", "name": "CalendarEvent/query", "path": "/ids" } })),
+        ]).await?;
+        let mut responses = responses.into_iter();
+        method_result(responses.next().ok_or("Invalid reply to CalendarEvent/query")?, self.resource.url())?;
+        let events = method_result(responses.next().ok_or("Invalid reply to CalendarEvent/get")?, self.resource.url())?;
+
+        let mut listing = EventListing{ state: events["state"].as_str().map(String::from), ..EventListing::default() };
+        for event in events["list"].as_array().into_iter().flatten() {
+            let url = self.item_url(event["id"].as_str().unwrap_or_default());
+            let item = event_to_item(event, url.clone());
+            if let Some(tag) = item.sync_status().version_tag() {
+                listing.version_tags.insert(url.clone(), tag.clone());
+            }
+            listing.items.insert(url, item);
+        }
+        *self.listing.lock().unwrap() = Some(listing);
+        Ok(())
+    }
+
+    /// Download the changes made since `state`, or return `false` if the server cannot tell them
+    async fn fetch_changes(&self, mut state: String) -> Result<bool, SendError> {
+        loop {
+            let responses = self.connection.call(vec![
+                ("CalendarEvent/changes", serde_json::json!({ "sinceState": state })),
+                ("CalendarEvent/get", serde_json::json!({ "#ids": { "resultOf": "0", "name": "CalendarEvent/changes", "path": "/created" } })),
+                ("CalendarEvent/get", serde_json::json!({ "#ids": { "resultOf": "0", "name": "CalendarEvent/changes", "path": "/updated" } })),
+            ]).await?;
+            let mut responses = responses.into_iter();
+            let changes = responses.next().ok_or("Invalid reply to CalendarEvent/changes")?;
+            if changes.0 == "error" && changes.1["type"] == "cannotCalculateChanges" {
+                return Ok(false);
+            }
+            let changes = method_result(changes, self.resource.url())?;
+            let created = method_result(responses.next().ok_or("Invalid reply to CalendarEvent/get")?, self.resource.url())?;
+            let updated = method_result(responses.next().ok_or("Invalid reply to CalendarEvent/get")?, self.resource.url())?;
+
+            let id = self.id();
+            let mut listing = self.listing.lock().unwrap();
+            let listing = listing.get_or_insert_with(EventListing::default);
+            for destroyed in changes["destroyed"].as_array().into_iter().flatten() {
+                let url = self.item_url(destroyed.as_str().unwrap_or_default());
+                listing.version_tags.remove(&url);
+                listing.items.remove(&url);
+            }
+            // The changes of every calendar of the account are returned
+            let events = created["list"].as_array().into_iter().flatten().chain(updated["list"].as_array().into_iter().flatten());
+            for event in events {
+                let url = self.item_url(event["id"].as_str().unwrap_or_default());
+                if event["calendarIds"][&id] != true {
+                    listing.version_tags.remove(&url);
+                    listing.items.remove(&url);
+                    continue;
+                }
+                let item = event_to_item(event, url.clone());
+                if let Some(tag) = item.sync_status().version_tag() {
+                    listing.version_tags.insert(url.clone(), tag.clone());
+                }
+                listing.items.insert(url, item);
+            }
+            state = changes["newState"].as_str().ok_or("Invalid reply to CalendarEvent/changes")?.to_string();
+            listing.state = Some(state.clone());
+            if changes["hasMoreChanges"] != true {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Download the events, unless they have been downloaded already
+    async fn ensure_listing(&self) -> Result<(), SendError> {
+        if self.listing.lock().unwrap().is_none() {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    /// The event at a given URL, that is downloaded if it has been listed in a previous session
+    async fn fetch_item(&self, url: &Url) -> Result<Option<Item>, SendError> {
+        self.ensure_listing().await?;
+        {
+            let listing = self.listing.lock().unwrap();
+            let listing = match listing.as_ref() {
+                None => return Ok(None),
+                Some(listing) => listing,
+            };
+            if let Some(item) = listing.items.get(url) {
+                return Ok(Some(item.clone()));
+            }
+            if !listing.version_tags.contains_key(url) {
+                return Ok(None);
+            }
+        }
+
+        let response = self.connection.call(vec![("CalendarEvent/get", serde_json::json!({ "ids": [self.item_id(url)?] }))]).await?
+            .into_iter().next().ok_or("Invalid reply to CalendarEvent/get")?;
+        let events = method_result(response, url)?;
+        let item = match events["list"].get(0) {
+            None => return Ok(None),
+            Some(event) => event_to_item(event, url.clone()),
+        };
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            if let Some(tag) = item.sync_status().version_tag() {
+                listing.version_tags.insert(url.clone(), tag.clone());
+            }
+            listing.items.insert(url.clone(), item.clone());
+        }
+        Ok(Some(item))
+    }
+
+    /// Update the downloaded events with an event that has just been uploaded (i.e. the reply to the `CalendarEvent/get` that follows a `CalendarEvent/set`), and return its URL and its sync status
+    fn store_uploaded(&self, reply: serde_json::Value) -> Result<(Url, SyncStatus), SendError> {
+        let event = reply["list"].get(0).ok_or_else(|| format!("Invalid reply to CalendarEvent/get for {}", self.resource.url()))?;
+        let url = self.item_url(event["id"].as_str().unwrap_or_default());
+        let item = event_to_item(event, url.clone());
+        let sync_status = item.sync_status().clone();
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            if let Some(tag) = sync_status.version_tag() {
+                listing.version_tags.insert(url.clone(), tag.clone());
+            }
+            listing.items.insert(url.clone(), item);
+        }
+        Ok((url, sync_status))
+    }
+
+    async fn insert_event(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let event = jmap_event(item, self.resource.url())?;
+        let mut json = event_to_json(event)?;
+        json["calendarIds"] = serde_json::json!({ self.id(): true });
+        json["uid"] = serde_json::json!(event.uid());
+        let responses = self.connection.call(vec![
+            ("CalendarEvent/set", serde_json::json!({ "create": { "new": json } })),
+            ("CalendarEvent/get", serde_json::json!({ "#ids": { "resultOf": "0", "name": "CalendarEvent/set", "path": "/created/*/id" } })),
+        ]).await?;
+        let mut responses = responses.into_iter();
+        let set = method_result(responses.next().ok_or("Invalid reply to CalendarEvent/set")?, self.resource.url())?;
+        if let Some(error) = set["notCreated"].get("new") {
+            return Err(set_error(error, item.url()));
+        }
+        let reply = method_result(responses.next().ok_or("Invalid reply to CalendarEvent/get")?, self.resource.url())?;
+        let (url, sync_status) = self.store_uploaded(reply)?;
+        self.assigned_urls.lock().unwrap().insert(item.url().clone(), url);
+        Ok(sync_status)
+    }
+
+    async fn update_event(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let event = jmap_event(item, self.resource.url())?;
+        let old_tag = match item.sync_status() {
+            SyncStatus::NotSynced => return Err("Cannot update an item that has not been synced already".into()),
+            SyncStatus::Synced(_) => return Err("Cannot update an item that has not changed".into()),
+            SyncStatus::LocallyModified(tag) => tag,
+            SyncStatus::LocallyDeleted(tag) => tag,
+        };
+        // JMAP has no conditional updates, but the changes of the server have been downloaded at the start of this sync
+        let current_tag = self.listing.lock().unwrap().as_ref()
+            .and_then(|listing| listing.version_tags.get(item.url()).cloned());
+        if current_tag.is_some_and(|current_tag| &current_tag != old_tag) {
+            return Err(KFError::Conflict{ url: item.url().clone() }.into());
+        }
+
+        let id = self.item_id(item.url())?;
+        let responses = self.connection.call(vec![
+            ("CalendarEvent/set", serde_json::json!({ "update": { &id: event_to_json(event)? } })),
+            ("CalendarEvent/get", serde_json::json!({ "ids": [&id] })),
+        ]).await?;
+        let mut responses = responses.into_iter();
+        let set = method_result(responses.next().ok_or("Invalid reply to CalendarEvent/set")?, self.resource.url())?;
+        if let Some(error) = set["notUpdated"].get(&id) {
+            return Err(set_error(error, item.url()));
+        }
+        let reply = method_result(responses.next().ok_or("Invalid reply to CalendarEvent/get")?, self.resource.url())?;
+        let (_, sync_status) = self.store_uploaded(reply)?;
+        Ok(sync_status)
+    }
+
+    async fn delete_event(&self, item_url: &Url) -> Result<(), SendError> {
+        let id = self.item_id(item_url)?;
+        let response = self.connection.call(vec![("CalendarEvent/set", serde_json::json!({ "destroy": [&id] }))]).await?
+            .into_iter().next().ok_or("Invalid reply to CalendarEvent/set")?;
+        let set = method_result(response, self.resource.url())?;
+        if let Some(error) = set["notDestroyed"].get(&id) {
+            return Err(set_error(error, item_url));
+        }
+        if let Some(listing) = self.listing.lock().unwrap().as_mut() {
+            listing.version_tags.remove(item_url);
+            listing.items.remove(item_url);
+        }
+        Ok(())
+    }
+
+    fn forbidden(&self) -> KFError {
+        KFError::Forbidden{ url: self.resource.url().clone() }
+    }
+}
+
+fn jmap_event<'a>(item: &'a Item, calendar_url: &Url) -> Result<&'a Event, SendError> {
+    match item {
+        Item::Event(event) => Ok(event),
+        Item::Task(_) | Item::Contact(_) => Err(format!("Item {} cannot be uploaded to {}: JMAP calendars only contain events", item.url(), calendar_url).into()),
+    }
+}
+
+/// Parse a JSCalendar `LocalDateTime`
+fn parse_local(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.split('.').next()?, "%Y-%m-%dT%H:%M:%S").ok()
+}
+
+/// The `RRULE` of a JSCalendar recurrence rule, or `None` if it is invalid
+///
+/// `utc` tells whether the rule ends at a UTC time (it does when its event starts at a time that is not floating):
+/// the offset of the time zone of the event is ignored, since iCal requires the `UNTIL`s of such events to be in UTC
+fn recurrence_rule_to_rrule(rule: &serde_json::Value, show_without_time: bool, utc: bool) -> Option<String> {
+    let mut parts = vec![format!("FREQ={}", rule["frequency"].as_str()?.to_ascii_uppercase())];
+    let numbers = |property: &str| rule[property].as_array().filter(|values| !values.is_empty())
+        .map(|values| values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(","));
+    if let Some(months) = rule["byMonth"].as_array().filter(|months| !months.is_empty()) {
+        parts.push(format!("BYMONTH={}", months.iter().filter_map(|month| month.as_str()).collect::<Vec<_>>().join(",")));
+    }
+    for (part, property) in &NUMBER_PARTS[..3] {
+        if let Some(values) = numbers(property) {
+            parts.push(format!("{}={}", part, values));
+        }
+    }
+    if let Some(days) = rule["byDay"].as_array().filter(|days| !days.is_empty()) {
+        let days = days.iter()
+            .map(|day| Some(format!("{}{}", day["nthOfPeriod"].as_i64().map(|n| n.to_string()).unwrap_or_default(), day["day"].as_str()?.to_ascii_uppercase())))
+            .collect::<Option<Vec<_>>>()?;
+        parts.push(format!("BYDAY={}", days.join(",")));
+    }
+    for (part, property) in &NUMBER_PARTS[3..] {
+        if let Some(values) = numbers(property) {
+            parts.push(format!("{}={}", part, values));
+        }
+    }
+    if let Some(interval) = rule["interval"].as_u64().filter(|interval| *interval > 1) {
+        parts.push(format!("INTERVAL={}", interval));
+    }
+    if let Some(first_day) = rule["firstDayOfWeek"].as_str() {
+        parts.push(format!("WKST={}", first_day.to_ascii_uppercase()));
+    }
+    if let Some(count) = rule["count"].as_u64() {
+        parts.push(format!("COUNT={}", count));
+    }
+    if let Some(until) = rule["until"].as_str() {
+        let until = local_to_ical(until);
+        parts.push(match (show_without_time, utc) {
+            (true, _) => format!("UNTIL={}", until.get(..8)?),
+            (false, true) => format!("UNTIL={}Z", until),
+            (false, false) => format!("UNTIL={}", until),
+        });
+    }
+    Some(parts.join(";"))
+}
+
+/// The JSCalendar recurrence rule of an `RRULE`, or `None` if it is invalid
+fn rrule_to_recurrence_rule(rrule: &str) -> Option<serde_json::Value> {
+    let mut rule = serde_json::json!({ "@type": "RecurrenceRule" });
+    for part in rrule.split(';') {
+        let (name, value) = part.split_once('=')?;
+        match name.to_ascii_uppercase().as_str() {
+            "FREQ" => rule["frequency"] = value.to_ascii_lowercase().into(),
+            "INTERVAL" => rule["interval"] = value.parse::<u64>().ok()?.into(),
+            "COUNT" => rule["count"] = value.parse::<u64>().ok()?.into(),
+            "UNTIL" => rule["until"] = ical_to_local(value)?.into(),
+            "WKST" => rule["firstDayOfWeek"] = value.to_ascii_lowercase().into(),
+            "BYMONTH" => rule["byMonth"] = value.split(',').collect::<Vec<_>>().into(),
+            "BYDAY" => {
+                let days = value.split(',')
+                    .map(|day| {
+                        // e.g. `2TU` or `-1FR`
+                        let (ordinal, code) = day.split_at(day.len().checked_sub(2)?);
+                        let mut n_day = serde_json::json!({ "@type": "NDay", "day": code.to_ascii_lowercase() });
+                        if !ordinal.is_empty() {
+                            n_day["nthOfPeriod"] = ordinal.trim_start_matches('+').parse::<i64>().ok()?.into();
+                        }
+                        Some(n_day)
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                rule["byDay"] = days.into();
+            },
+            name => {
+                let property = NUMBER_PARTS.iter().find(|(part, _)| *part == name)?.1;
+                rule[property] = value.split(',').map(|n| n.parse::<i64>().ok()).collect::<Option<Vec<_>>>()?.into();
+            },
+        }
+    }
+    rule.get("frequency")?;
+    Some(rule)
+}
+
+/// Convert a JSCalendar event to an item
+fn event_to_item(event: &serde_json::Value, url: Url) -> Item {
+    let mut extra_parameters = Vec::new();
+    let time_zone = event["timeZone"].as_str();
+    let show_without_time = event["showWithoutTime"] == true;
+    if let Some(start) = event["start"].as_str() {
+        extra_parameters.push(local_date_property("DTSTART", start, time_zone, show_without_time));
+        // JSCalendar events have a duration rather than an end
+        let duration = event["duration"].as_str().and_then(|duration| crate::ical::parse_duration(duration).ok());
+        if let (Some(start), Some(duration)) = (parse_local(start), duration) {
+            let end = (start + duration).format("%Y-%m-%dT%H:%M:%S").to_string();
+            extra_parameters.push(local_date_property("DTEND", &end, time_zone, show_without_time));
+        }
+    }
+    let location = event["locations"].as_object().into_iter().flat_map(|locations| locations.values())
+        .find_map(|location| location["name"].as_str());
+    for (name, value) in [("DESCRIPTION", event["description"].as_str()), ("LOCATION", location)] {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            extra_parameters.push(Property{ name: name.to_string(), params: None, value: Some(value.to_string()) });
+        }
+    }
+    extra_parameters.extend(keywords_to_categories(&event["keywords"]));
+    let utc = time_zone.is_some();
+    if let Some(rrule) = event["recurrenceRules"].get(0).and_then(|rule| recurrence_rule_to_rrule(rule, show_without_time, utc)) {
+        extra_parameters.push(Property{ name: "RRULE".to_string(), params: None, value: Some(rrule) });
+    }
+    let status = match event["status"].as_str() {
+        Some("cancelled") => Some("CANCELLED"),
+        Some("tentative") => Some("TENTATIVE"),
+        _ => None,
+    };
+    if let Some(status) = status {
+        extra_parameters.push(Property{ name: "STATUS".to_string(), params: None, value: Some(status.to_string()) });
+    }
+    if event["freeBusyStatus"] == "free" {
+        extra_parameters.push(Property{ name: "TRANSP".to_string(), params: None, value: Some("TRANSPARENT".to_string()) });
+    }
+
+    let title = event["title"].as_str().unwrap_or_default().to_string();
+    let alarms = alerts_to_alarms(&event["alerts"], &title);
+    let tag = format!("{:x}", checksum(event.to_string().as_bytes()));
+    let mut new_event = Event::new_with_parameters(
+        title,
+        event["uid"].as_str().or_else(|| event["id"].as_str()).unwrap_or_default().to_string(),
+        url,
+        SyncStatus::Synced(VersionTag::from(tag)),
+        parse_utc_date_time(&event["created"]),
+        parse_utc_date_time(&event["updated"]).unwrap_or_else(Utc::now),
+        crate::ical::default_prod_id(),
+        extra_parameters,
+    );
+    new_event.set_alarms(alarms);
+    Item::Event(new_event)
+}
+
+/// The JSCalendar properties of an event that are synced.
+///
+/// Properties that the event does not have are set to `null`, so that they are cleared when an existing event is updated
+fn event_to_json(event: &Event) -> Result<serde_json::Value, SendError> {
+    let (start, time_zone, show_without_time) = event.property("DTSTART").map(property_to_local).unwrap_or_default();
+    let start = start.ok_or_else(|| format!("Event {} has no valid start date, and cannot be uploaded to a JMAP server", event.url()))?;
+    let end = event.end_or_default().and_then(|end| end.value.as_deref().and_then(ical_to_local));
+    let duration = parse_local(&start).zip(end.as_deref().and_then(parse_local))
+        .map(|(start, end)| end - start)
+        .filter(|duration| *duration >= Duration::zero())
+        .map(|duration| crate::alarm::format_duration(&duration));
+    let is = |name: &str, value: &str| event.value(name).is_some_and(|actual| actual.eq_ignore_ascii_case(value));
+
+    let recurrence_rules = match event.value("RRULE") {
+        None => serde_json::Value::Null,
+        Some(rrule) => serde_json::json!([rrule_to_recurrence_rule(rrule)
+            .ok_or_else(|| format!("The recurrence rule of event {} ({}) is not valid", event.url(), rrule))?]),
+    };
+    let status = match (is("STATUS", "CANCELLED"), is("STATUS", "TENTATIVE")) {
+        (true, _) => "cancelled",
+        (false, true) => "tentative",
+        (false, false) => "confirmed",
+    };
+    let locations = match event.value("LOCATION") {
+        None => serde_json::Value::Null,
+        Some(location) => serde_json::json!({ "1": { "@type": "Location", "name": location } }),
+    };
+
+    Ok(serde_json::json!({
+        "@type": "Event",
+        "title": event.name(),
+        "description": event.value("DESCRIPTION").unwrap_or_default(),
+        "start": start,
+        "timeZone": time_zone,
+        "showWithoutTime": show_without_time,
+        "duration": duration,
+        "status": status,
+        "freeBusyStatus": if is("TRANSP", "TRANSPARENT") { "free" } else { "busy" },
+        "locations": locations,
+        "keywords": categories_to_keywords(event.extra_parameters()),
+        "recurrenceRules": recurrence_rules,
+        "alerts": alarms_to_alerts(event.alarms()),
+    }))
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for JmapCalendar {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.insert_event(&item).await.map_err(KFError::from)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.update_event(&item).await.map_err(KFError::from)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for JmapCalendar {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        // The session resource, i.e. the URL of this calendar without its `/calendars/{id}/` suffix
+        let mut url = resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().pop().pop();
+        }
+        let connection = Arc::new(JmapConnection::new(resource.combine(url.path()), CALENDARS_CAPABILITY));
+        Self {
+            name, resource, supported_components, color, connection,
+            listing: Mutex::new(None),
+            assigned_urls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| listing.version_tags.clone())
+            .collect())
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.ensure_listing().await?;
+        let listing = self.listing.lock().unwrap();
+        Ok(listing.iter()
+            .flat_map(|listing| listing.version_tags.iter()
+                // Events that have not been downloaded are kept, the provider filters them once they are
+                .filter(move |(url, _)| listing.items.get(*url).is_none_or(|item| filter.matches(item)))
+                .map(|(url, tag)| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        self.fetch_item(url).await.map_err(KFError::from)
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        let mut items = Vec::with_capacity(urls.len());
+        for url in urls {
+            items.push(self.fetch_item(url).await?);
+        }
+        Ok(items)
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.delete_event(item_url).await.map_err(KFError::from)
+    }
+
+    async fn move_item(&mut self, item_url: &Url, _destination: &Url) -> Result<SyncStatus, KFError> {
+        // The destination URL cannot be chosen
+        Err(KFError::MoveNotSupported{ url: item_url.clone() })
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.insert_event(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| async move { this.update_event(&item).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(item_urls.to_vec())
+            .map(|url| async move { this.delete_event(&url).await })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    fn take_assigned_url(&mut self, url: &Url) -> Option<Url> {
+        self.assigned_urls.lock().unwrap().remove(url)
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        // This is the first request of a sync, that always downloads the latest changes
+        self.refresh().await?;
+        let listing = self.listing.lock().unwrap();
+        let mut tags: Vec<_> = listing.iter()
+            .flat_map(|listing| &listing.version_tags)
+            .map(|(url, tag)| format!("{} {}\n", url, tag.as_str()))
+            .collect();
+        tags.sort();
+        Ok(CalendarVersion{
+            ctag: Some(format!("{:x}", checksum(tags.concat().as_bytes()))),
+            sync_token: listing.as_ref().and_then(|listing| listing.state.clone()),
+        })
+    }
+
+    fn resume_from(&mut self, version: &CalendarVersion, version_tags: HashMap<Url, VersionTag>) {
+        let mut listing = self.listing.lock().unwrap();
+        if let (None, Some(state)) = (listing.as_ref(), &version.sync_token) {
+            *listing = Some(EventListing{ version_tags, items: HashMap::new(), state: Some(state.clone()) });
+        }
+    }
+
+    async fn update_color(&mut self, _color: Option<Color>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_description(&mut self, _description: Option<String>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_timezone(&mut self, _timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        let id = self.id();
+        let response = self.connection.call(vec![("Calendar/set", serde_json::json!({ "update": { &id: { "name": name } } }))]).await
+            ?
+            .into_iter().next().ok_or("Invalid reply to Calendar/set")?;
+        let set = method_result(response, self.resource.url())?;
+        if let Some(error) = set["notUpdated"].get(&id) {
+            return Err(set_error(error, self.resource.url()).into());
+        }
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_conversion() {
+        let url: Url = "https://api.example.com/jmap/session/tasklists/L1/T1".parse().unwrap();
+        let task = serde_json::json!({
+            "@type": "Task", "id": "T1", "taskListId": "L1", "uid": "a8df6573-0474-496d-8496-033ad45d7fea",
+            "title": "Water the plants", "description": "The ones in the kitchen", "priority": 1,
+            "due": "2021-03-02T18:00:00", "timeZone": "Europe/Paris", "keywords": { "home": true },
+            "progress": "completed", "progressUpdated": "2021-03-01T10:00:00Z",
+            "alerts": { "a": { "@type": "Alert", "trigger": { "@type": "OffsetTrigger", "offset": "-PT15M", "relativeTo": "end" }, "action": "display" } },
+            "updated": "2021-03-01T10:00:00Z"
+        });
+        let item = task_to_item(&task, url);
+        let task = item.unwrap_task();
+        assert_eq!(task.uid(), "a8df6573-0474-496d-8496-033ad45d7fea");
+        assert_eq!(task.completion_status().completion_date(), Some(&"2021-03-01T10:00:00Z".parse().unwrap()));
+        let due = task.extra_parameters().iter().find(|prop| prop.name == "DUE").unwrap();
+        assert_eq!(due.value.as_deref(), Some("20210302T180000"));
+        assert_eq!(due.params, Some(vec![("TZID".to_string(), vec!["Europe/Paris".to_string()])]));
+        assert!(task.extra_parameters().iter().any(|prop| prop.name == "CATEGORIES" && prop.value.as_deref() == Some("home")));
+        assert_eq!(task.alarms()[0].trigger(), Some(AlarmTrigger::Relative{ offset: chrono::Duration::minutes(-15), related_to_end: true }));
+
+        let json = task_to_json(task);
+        assert_eq!(json["title"], "Water the plants");
+        assert_eq!(json["description"], "The ones in the kitchen");
+        assert_eq!(json["priority"], 1);
+        assert_eq!(json["due"], "2021-03-02T18:00:00");
+        assert_eq!(json["timeZone"], "Europe/Paris");
+        assert_eq!(json["keywords"], serde_json::json!({ "home": true }));
+        assert_eq!(json["progress"], "completed");
+        assert_eq!(json["progressUpdated"], "2021-03-01T10:00:00Z");
+        assert_eq!(json["alerts"]["1"]["trigger"]["offset"], "-PT15M");
+        assert_eq!(json["alerts"]["1"]["trigger"]["relativeTo"], "end");
+
+        // Properties a task does not have are cleared
+        let task = Task::new("Call Bob".to_string(), false, &"https://api.example.com/jmap/session/tasklists/L1/".parse().unwrap());
+        let json = task_to_json(&task);
+        assert_eq!(json["progress"], "needs-action");
+        assert_eq!(json["priority"], 0);
+        assert!(json["due"].is_null() && json["alerts"].is_null() && json["keywords"].is_null() && json["progressUpdated"].is_null());
+    }
+    #[test]
+    fn test_event_conversion() {
+        let url: Url = "https://api.example.com/jmap/session/calendars/C1/E1".parse().unwrap();
+        let event = serde_json::json!({
+            "@type": "Event", "id": "E1", "calendarIds": { "C1": true }, "uid": "0c7ea7c5-2c6b-4bb9-8cbe-31e6a4d7e7b2",
+            "title": "Team meeting", "description": "Weekly sync", "start": "2021-03-02T10:00:00", "timeZone": "Europe/Paris",
+            "duration": "PT1H30M", "status": "tentative", "freeBusyStatus": "free",
+            "locations": { "l1": { "@type": "Location", "name": "Room 4" } }, "keywords": { "work": true },
+            "recurrenceRules": [{ "@type": "RecurrenceRule", "frequency": "weekly", "byDay": [{ "@type": "NDay", "day": "tu" }], "count": 10 }],
+            "alerts": { "a": { "@type": "Alert", "trigger": { "@type": "OffsetTrigger", "offset": "-PT10M" }, "action": "display" } },
+            "updated": "2021-03-01T10:00:00Z"
+        });
+        let item = event_to_item(&event, url);
+        let event = match &item {
+            Item::Event(event) => event,
+            _ => panic!("Not an event"),
+        };
+        assert_eq!(event.uid(), "0c7ea7c5-2c6b-4bb9-8cbe-31e6a4d7e7b2");
+        assert_eq!(event.name(), "Team meeting");
+        let end = event.property("DTEND").unwrap();
+        assert_eq!(end.value.as_deref(), Some("20210302T113000"));
+        assert_eq!(end.params, Some(vec![("TZID".to_string(), vec!["Europe/Paris".to_string()])]));
+        assert_eq!(event.value("LOCATION"), Some("Room 4"));
+        assert_eq!(event.value("RRULE"), Some("FREQ=WEEKLY;BYDAY=TU;COUNT=10"));
+        assert_eq!(event.value("STATUS"), Some("TENTATIVE"));
+        assert_eq!(event.value("TRANSP"), Some("TRANSPARENT"));
+        assert_eq!(event.alarms()[0].trigger(), Some(AlarmTrigger::Relative{ offset: chrono::Duration::minutes(-10), related_to_end: false }));
+
+        let json = event_to_json(event).unwrap();
+        assert_eq!(json["title"], "Team meeting");
+        assert_eq!(json["description"], "Weekly sync");
+        assert_eq!(json["start"], "2021-03-02T10:00:00");
+        assert_eq!(json["timeZone"], "Europe/Paris");
+        assert_eq!(json["showWithoutTime"], false);
+        assert_eq!(json["duration"], "PT1H30M");
+        assert_eq!(json["status"], "tentative");
+        assert_eq!(json["freeBusyStatus"], "free");
+        assert_eq!(json["locations"]["1"]["name"], "Room 4");
+        assert_eq!(json["keywords"], serde_json::json!({ "work": true }));
+        assert_eq!(json["recurrenceRules"][0]["frequency"], "weekly");
+        assert_eq!(json["recurrenceRules"][0]["byDay"][0]["day"], "tu");
+        assert_eq!(json["alerts"]["1"]["trigger"]["offset"], "-PT10M");
+
+        // All-day events
+        let url: Url = "https://api.example.com/jmap/session/calendars/C1/E2".parse().unwrap();
+        let event = serde_json::json!({ "@type": "Event", "id": "E2", "title": "Holidays", "start": "2021-08-02T00:00:00", "showWithoutTime": true, "duration": "P5D" });
+        let item = event_to_item(&event, url);
+        let event = match &item {
+            Item::Event(event) => event,
+            _ => panic!("Not an event"),
+        };
+        assert_eq!(event.value("DTSTART"), Some("20210802"));
+        assert_eq!(event.value("DTEND"), Some("20210807"));
+        let json = event_to_json(event).unwrap();
+        assert_eq!(json["showWithoutTime"], true);
+        assert_eq!(json["duration"], "P5D");
+        assert!(json["timeZone"].is_null() && json["locations"].is_null() && json["recurrenceRules"].is_null());
+    }
+
+    #[test]
+    fn test_recurrence_rules() {
+        for rrule in ["FREQ=DAILY", "FREQ=MONTHLY;BYDAY=-1FR;INTERVAL=2", "FREQ=YEARLY;BYMONTH=3;BYMONTHDAY=15;WKST=SU;UNTIL=20301231T000000Z",
+                      "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=1"] {
+            let rule = rrule_to_recurrence_rule(rrule).unwrap();
+            assert_eq!(recurrence_rule_to_rrule(&rule, false, true).as_deref(), Some(rrule));
+        }
+        let rule = rrule_to_recurrence_rule("FREQ=WEEKLY;UNTIL=20301231").unwrap();
+        assert_eq!(rule["until"], "2030-12-31T00:00:00");
+        assert_eq!(recurrence_rule_to_rrule(&rule, true, false).as_deref(), Some("FREQ=WEEKLY;UNTIL=20301231"));
+
+        assert_eq!(rrule_to_recurrence_rule("FREQ=WEEKLY;RSCALE=HEBREW"), None);
+        assert_eq!(rrule_to_recurrence_rule("COUNT=3"), None);
+    }
+}
@@ -0,0 +1,430 @@
+//! The items of a [`CachedCalendar`](super::cached_calendar::CachedCalendar), that are only deserialized when they are accessed
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::ser::SerializeMap;
+use url::Url;
+
+use crate::item::{Item, SyncStatus};
+use super::date_index::DateIndex;
+
+/// What is known about an item without deserializing it completely.
+///
+/// This is enough to e.g. render a list of tasks, or a month view, without loading every description and every extra property
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemSummary {
+    pub uid: String,
+    pub name: String,
+    pub is_task: bool,
+    pub completed: bool,
+    pub sync_status: SyncStatus,
+    pub last_modified: Option<DateTime<Utc>>,
+    /// The `DTSTART` of the item, if any
+    pub start: Option<DateTime<Utc>>,
+    /// The `DUE` date of the item, if any
+    pub due: Option<DateTime<Utc>>,
+    /// Whether the item has a `RRULE`
+    #[serde(default)]
+    pub recurring: bool,
+    /// The `UNTIL` of its `RRULE`, if any
+    #[serde(default)]
+    pub recurrence_until: Option<DateTime<Utc>>,
+}
+
+impl ItemSummary {
+    pub fn from_item(item: &Item) -> Self {
+        match item {
+            Item::Task(task) => {
+                let value = |name: &str| task.extra_parameters().iter()
+                    .find(|prop| prop.name.eq_ignore_ascii_case(name))
+                    .and_then(|prop| prop.value.as_deref());
+                let date = |name: &str| value(name).and_then(crate::ical::parse_date_value);
+                let rrule = value("RRULE");
+                Self {
+                    uid: task.uid().to_string(),
+                    name: task.name().to_string(),
+                    is_task: true,
+                    completed: task.completed(),
+                    sync_status: task.sync_status().clone(),
+                    last_modified: Some(*task.last_modified()),
+                    start: date("DTSTART"),
+                    due: date("DUE"),
+                    recurring: rrule.is_some(),
+                    recurrence_until: rrule.and_then(|rrule| {
+                        rrule.split(';')
+                            .find_map(|part| part.strip_prefix("UNTIL="))
+                            .and_then(crate::ical::parse_date_value)
+                    }),
+                }
+            },
+            Item::Event(event) => Self {
+                uid: event.uid().to_string(),
+                name: event.name().to_string(),
+                is_task: false,
+                completed: false,
+                sync_status: event.sync_status().clone(),
+                last_modified: Some(*event.last_modified()),
+                start: event.start(),
+                due: event.end(),
+                recurring: event.value("RRULE").is_some(),
+                recurrence_until: event.value("RRULE").and_then(|rrule| {
+                    rrule.split(';')
+                        .find_map(|part| part.strip_prefix("UNTIL="))
+                        .and_then(crate::ical::parse_date_value)
+                }),
+            },
+            Item::Contact(contact) => Self {
+                uid: contact.uid().to_string(),
+                name: contact.name().to_string(),
+                is_task: false,
+                completed: false,
+                sync_status: contact.sync_status().clone(),
+                last_modified: Some(*contact.last_modified()),
+                start: None,
+                due: None,
+                recurring: false,
+                recurrence_until: None,
+            },
+        }
+    }
+
+    /// When the item starts, and when it ends (`None` for items that recur endlessly, or a given number of times).
+    ///
+    /// For recurring items, this spans every occurrence. Items that have no date return `None`
+    pub fn date_range(&self) -> Option<(DateTime<Utc>, Option<DateTime<Utc>>)> {
+        let first = self.start.or(self.due)?;
+        let last = self.start.max(self.due).unwrap_or(first);
+        if self.recurring {
+            // The last occurrence starts at `UNTIL` at the latest, and lasts as long as the first one
+            Some((first, self.recurrence_until.map(|until| until + (last - first))))
+        } else {
+            Some((first, Some(last)))
+        }
+    }
+
+    /// Whether this is a task that is due between two dates.
+    ///
+    /// Recurring tasks are due between two dates if this range overlaps the due dates of their recurrence set, even though none of their occurrences may be due in it
+    pub fn is_due_between(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> bool {
+        let first_due = match (self.is_task, self.due) {
+            (true, Some(due)) => due,
+            _ => return false,
+        };
+        let last_due = match self.recurring {
+            false => Some(first_due),
+            true => self.recurrence_until.map(|until| until + (first_due - self.start.unwrap_or(first_due))),
+        };
+        &first_due <= end && last_due.is_none_or(|last| &last >= start)
+    }
+}
+
+
+/// An item, and its serialized version as long as it has not been modified
+#[derive(Clone, Debug)]
+struct LazyItem {
+    summary: ItemSummary,
+    /// The serialized item. This is `None` once the item may have been modified
+    raw: Option<String>,
+    item: OnceCell<Item>,
+}
+
+impl LazyItem {
+    fn new(item: Item) -> Self {
+        Self { summary: ItemSummary::from_item(&item), raw: None, item: OnceCell::from(item) }
+    }
+
+    fn get(&self, url: &Url) -> Option<&Item> {
+        if let Some(item) = self.item.get() {
+            return Some(item);
+        }
+        match serde_json::from_str(self.raw.as_ref()?) {
+            Ok(item) => Some(self.item.get_or_init(|| item)),
+            Err(err) => {
+                log::error!("Unable to deserialize item {}: {}", url, err);
+                None
+            },
+        }
+    }
+
+    fn get_mut(&mut self, url: &Url) -> Option<&mut Item> {
+        self.get(url)?;
+        self.raw = None;
+        self.item.get_mut()
+    }
+
+    fn into_item(self, url: &Url) -> Option<Item> {
+        self.get(url)?;
+        self.item.into_inner()
+    }
+
+    fn summary(&self) -> Cow<'_, ItemSummary> {
+        match (&self.raw, self.item.get()) {
+            // The item may have changed since the summary has been computed
+            (None, Some(item)) => Cow::Owned(ItemSummary::from_item(item)),
+            _ => Cow::Borrowed(&self.summary),
+        }
+    }
+
+    fn raw(&self) -> Result<Cow<'_, str>, serde_json::Error> {
+        match (&self.raw, self.item.get()) {
+            (Some(raw), _) => Ok(Cow::Borrowed(raw)),
+            (None, Some(item)) => Ok(Cow::Owned(serde_json::to_string(item)?)),
+            (None, None) => unreachable!("An item is either serialized or deserialized"),
+        }
+    }
+}
+
+/// How items are serialized
+#[derive(Serialize, Deserialize)]
+struct StoredItem<'a> {
+    summary: Cow<'a, ItemSummary>,
+    data: Cow<'a, str>,
+}
+
+
+/// The items of a calendar, indexed by URL.
+///
+/// Items are stored as their [`ItemSummary`] and their serialized version, and they are only deserialized the first time they are accessed. \
+/// Items are also indexed by date (using their summaries), so that the items of a date range can be found without deserializing every item
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LazyItems {
+    entries: HashMap<Url, LazyItem>,
+    date_index: DateIndex,
+    /// Items that may have been modified since they have been indexed
+    modified: HashSet<Url>,
+}
+
+impl LazyItems {
+    pub(crate) fn contains_key(&self, url: &Url) -> bool {
+        self.entries.contains_key(url)
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &Url> {
+        self.entries.keys()
+    }
+
+    /// Returns an item, or `None` if it does not exist (or if it cannot be deserialized)
+    pub(crate) fn get(&self, url: &Url) -> Option<&Item> {
+        self.entries.get(url)?.get(url)
+    }
+
+    pub(crate) fn get_mut(&mut self, url: &Url) -> Option<&mut Item> {
+        let item = self.entries.get_mut(url)?.get_mut(url)?;
+        self.modified.insert(url.clone());
+        Some(item)
+    }
+
+    pub(crate) fn insert(&mut self, url: Url, item: Item) {
+        let entry = LazyItem::new(item);
+        self.date_index.insert(&url, &entry.summary);
+        self.modified.remove(&url);
+        self.entries.insert(url, entry);
+    }
+
+    pub(crate) fn remove(&mut self, url: &Url) -> Option<Item> {
+        self.date_index.remove(url);
+        self.modified.remove(url);
+        self.entries.remove(url)?.into_item(url)
+    }
+
+    /// Deserializes every item (items that cannot be deserialized are skipped)
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&Url, &Item)> {
+        self.entries.iter().filter_map(|(url, entry)| Some((url, entry.get(url)?)))
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (&Url, &mut Item)> {
+        self.modified.extend(self.entries.keys().cloned());
+        self.entries.iter_mut().filter_map(|(url, entry)| Some((url, entry.get_mut(url)?)))
+    }
+
+    /// The URLs of the items that may happen between two dates (see [`ItemSummary::date_range`]). This does not deserialize any item
+    pub(crate) fn urls_between(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> Vec<&Url> {
+        let overlaps = |summary: &ItemSummary| summary.date_range()
+            .is_some_and(|(first, last)| &first <= end && last.is_none_or(|last| &last >= start));
+
+        // Modified items may not be where they have been indexed
+        let mut urls: Vec<&Url> = self.date_index.between(start, end)
+            .filter(|url| !self.modified.contains(*url))
+            .filter(|url| self.entries.get(*url).is_some_and(|entry| overlaps(&entry.summary)))
+            .collect();
+        urls.extend(self.modified.iter()
+            .filter_map(|url| self.entries.get_key_value(url))
+            .filter(|(_, entry)| overlaps(&entry.summary()))
+            .map(|(url, _)| url));
+        urls
+    }
+
+    pub(crate) fn summary(&self, url: &Url) -> Option<Cow<'_, ItemSummary>> {
+        Some(self.entries.get(url)?.summary())
+    }
+
+    /// The summaries of every item. This does not deserialize any item
+    pub(crate) fn summaries(&self) -> impl Iterator<Item = (&Url, Cow<'_, ItemSummary>)> {
+        self.entries.iter().map(|(url, entry)| (url, entry.summary()))
+    }
+
+    /// The URLs of the items that cannot be deserialized. This deserializes every item
+    pub(crate) fn unreadable(&self) -> Vec<Url> {
+        self.entries.iter()
+            .filter(|(url, entry)| entry.get(url).is_none())
+            .map(|(url, _)| url.clone())
+            .collect()
+    }
+
+    /// Whether the summary that has been stored along with an item does not match its content
+    pub(crate) fn has_stale_summary(&self, url: &Url) -> bool {
+        self.entries.get(url).is_some_and(|entry| {
+            entry.raw.is_some() && entry.get(url).is_some_and(|item| ItemSummary::from_item(item) != entry.summary)
+        })
+    }
+
+    /// Compute the summary of an item again, from its content
+    pub(crate) fn refresh_summary(&mut self, url: &Url) {
+        if let Some(entry) = self.entries.get_mut(url) {
+            if let Some(summary) = entry.get(url).map(ItemSummary::from_item) {
+                self.date_index.insert(url, &summary);
+                entry.summary = summary;
+            }
+        }
+    }
+
+    /// Remove an item without deserializing it, and returns its serialized version
+    pub(crate) fn remove_raw(&mut self, url: &Url) -> Option<String> {
+        self.date_index.remove(url);
+        self.modified.remove(url);
+        let entry = self.entries.remove(url)?;
+        let raw = entry.raw().ok().map(Cow::into_owned);
+        raw
+    }
+
+    /// Whether an item has been deserialized already
+    #[cfg(test)]
+    fn is_loaded(&self, url: &Url) -> bool {
+        self.entries.get(url).is_some_and(|entry| entry.item.get().is_some())
+    }
+}
+
+impl Serialize for LazyItems {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (url, entry) in &self.entries {
+            let data = entry.raw().map_err(serde::ser::Error::custom)?;
+            map.serialize_entry(url, &StoredItem{ summary: entry.summary(), data })?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for LazyItems {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = HashMap::<Url, StoredItem<'static>>::deserialize(deserializer)?;
+        let mut date_index = DateIndex::default();
+        let entries = stored.into_iter()
+            .map(|(url, stored)| {
+                let entry = LazyItem { summary: stored.summary.into_owned(), raw: Some(stored.data.into_owned()), item: OnceCell::new() };
+                date_index.insert(&url, &entry.summary);
+                (url, entry)
+            })
+            .collect();
+        Ok(Self { entries, date_index, modified: HashSet::new() })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Task;
+
+    #[test]
+    fn test_lazy_items() {
+        let cal: Url = "https://some.server/cal/".parse().unwrap();
+        let mut items = LazyItems::default();
+        let mut urls = Vec::new();
+        for name in ["first", "second"] {
+            let task = Task::new(name.to_string(), false, &cal);
+            urls.push(task.url().clone());
+            items.insert(task.url().clone(), Item::Task(task));
+        }
+
+        let serialized = serde_json::to_string(&items).unwrap();
+        let mut items: LazyItems = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(items.summary(&urls[0]).unwrap().name, "first");
+        assert!(!items.is_loaded(&urls[0]));
+
+        assert_eq!(items.get(&urls[0]).unwrap().name(), "first");
+        assert!(items.is_loaded(&urls[0]));
+        assert!(!items.is_loaded(&urls[1]));
+
+        // Summaries follow the modifications of items
+        items.get_mut(&urls[1]).unwrap().unwrap_task_mut().set_name("renamed".to_string());
+        assert_eq!(items.summary(&urls[1]).unwrap().name, "renamed");
+        let items: LazyItems = serde_json::from_str(&serde_json::to_string(&items).unwrap()).unwrap();
+        assert_eq!(items.summary(&urls[1]).unwrap().name, "renamed");
+        assert_eq!(items.get(&urls[1]).unwrap().name(), "renamed");
+    }
+
+    fn task_with_dates(cal: &Url, dates: &[(&str, &str)]) -> Item {
+        let extra_parameters = dates.iter()
+            .map(|(name, value)| ical::property::Property{ name: name.to_string(), params: None, value: Some(value.to_string()) })
+            .collect();
+        let task = Task::new("A task".to_string(), false, cal);
+        Item::Task(Task::new_with_parameters(task.name().to_string(), task.uid().to_string(), task.url().clone(), task.completion_status().clone(),
+            SyncStatus::NotSynced, None, Utc::now(), task.ical_prod_id().to_string(), extra_parameters))
+    }
+
+    #[test]
+    fn test_date_index() {
+        let cal: Url = "https://some.server/cal/".parse().unwrap();
+        let date = |value: &str| crate::ical::parse_date_value(value).unwrap();
+        let mut items = LazyItems::default();
+        let mut insert = |item: Item| { let url = item.url().clone(); items.insert(url.clone(), item); url };
+        let single = insert(task_with_dates(&cal, &[("DTSTART", "20210310T090000Z"), ("DUE", "20210312T090000Z")]));
+        let bounded = insert(task_with_dates(&cal, &[("DTSTART", "20210101"), ("RRULE", "FREQ=MONTHLY;UNTIL=20210201T000000Z")]));
+        let endless = insert(task_with_dates(&cal, &[("DUE", "20210201"), ("RRULE", "FREQ=WEEKLY")]));
+        insert(task_with_dates(&cal, &[]));
+
+        let between = |items: &LazyItems, start: &str, end: &str| {
+            let mut urls: Vec<Url> = items.urls_between(&date(start), &date(end)).into_iter().cloned().collect();
+            urls.sort();
+            urls
+        };
+        let sorted = |mut urls: Vec<Url>| { urls.sort(); urls };
+        assert_eq!(between(&items, "20210311", "20210311"), sorted(vec![single.clone(), endless.clone()]));
+        assert_eq!(between(&items, "20210115", "20210120"), sorted(vec![bounded.clone()]));
+        assert_eq!(between(&items, "20200101", "20201231"), Vec::<Url>::new());
+
+        // The index survives serialization, and follows removals
+        let mut items: LazyItems = serde_json::from_str(&serde_json::to_string(&items).unwrap()).unwrap();
+        assert_eq!(between(&items, "20210311", "20210311"), sorted(vec![single.clone(), endless]));
+        assert!(!items.is_loaded(&single));
+        items.remove(&single);
+        assert_eq!(between(&items, "20210310", "20210312").len(), 1);
+    }
+
+    #[test]
+    fn test_due_dates() {
+        let cal: Url = "https://some.server/cal/".parse().unwrap();
+        let date = |value: &str| crate::ical::parse_date_value(value).unwrap();
+        let summary = |dates: &[(&str, &str)]| ItemSummary::from_item(&task_with_dates(&cal, dates));
+        let is_due_between = |summary: &ItemSummary, start: &str, end: &str| summary.is_due_between(&date(start), &date(end));
+
+        let single = summary(&[("DTSTART", "20210310T090000Z"), ("DUE", "20210312T090000Z")]);
+        assert!(is_due_between(&single, "20210312", "20210313"));
+        assert!(!is_due_between(&single, "20210310", "20210311"));
+
+        // The last occurrence is due one day after it starts
+        let bounded = summary(&[("DTSTART", "20210101"), ("DUE", "20210102"), ("RRULE", "FREQ=MONTHLY;UNTIL=20210201T000000Z")]);
+        assert!(is_due_between(&bounded, "20210202", "20210203"));
+        assert!(!is_due_between(&bounded, "20210204", "20210205"));
+        assert!(!is_due_between(&bounded, "20201201", "20201231"));
+        let endless = summary(&[("DUE", "20210201"), ("RRULE", "FREQ=WEEKLY")]);
+        assert!(is_due_between(&endless, "20300101", "20300102"));
+
+        assert!(!is_due_between(&summary(&[("DTSTART", "20210310")]), "20210101", "20211231"));
+    }
+}
@@ -1,7 +1,24 @@
 //! Various objects that implement Calendar-related traits
 
 pub mod cached_calendar;
+mod date_index;
+pub mod lazy_items;
 pub mod remote_calendar;
+pub mod sharing;
+pub mod subscribed_calendar;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod vdir_calendar;
+pub mod address_book;
+#[cfg(feature = "google")]
+pub mod google_calendar;
+#[cfg(feature = "graph")]
+pub mod graph_calendar;
+#[cfg(feature = "ews")]
+pub mod ews_calendar;
+#[cfg(feature = "jmap")]
+pub mod jmap_calendar;
+#[cfg(feature = "etebase")]
+pub mod etebase_calendar;
 
 use std::convert::TryFrom;
 use std::error::Error;
@@ -10,6 +27,9 @@ use serde::{Deserialize, Serialize};
 
 use bitflags::bitflags;
 
+use crate::alarm::Alarm;
+use crate::error::KFError;
+
 bitflags! {
     #[derive(Serialize, Deserialize)]
     pub struct SupportedComponents: u8 {
@@ -17,10 +37,67 @@ bitflags! {
         const EVENT = 1;
         /// A to-do item, such as a reminder
         const TODO = 2;
+        /// A contact (a vCard). Only address books contain contacts, and they contain nothing else
+        const CONTACT = 4;
     }
 }
 
+bitflags! {
+    /// Properties of a calendar that can be changed locally, and that are pushed to the server at the next sync
+    #[derive(Default, Serialize, Deserialize)]
+    pub struct CalendarProperties: u8 {
+        const COLOR = 1;
+        /// The position of the calendar in lists of calendars
+        const ORDER = 2;
+        /// The free-text description of the calendar
+        const DESCRIPTION = 4;
+        /// The default time zone of the calendar
+        const TIMEZONE = 8;
+        /// The display name of the calendar
+        const NAME = 16;
+        /// The alarms that are added to new items of the calendar
+        const DEFAULT_ALARMS = 32;
+    }
+}
+
+impl CalendarProperties {
+    /// Every single property that is set
+    pub fn iter_flags(&self) -> impl Iterator<Item = CalendarProperties> + '_ {
+        (0..u8::BITS)
+            .filter_map(|bit| CalendarProperties::from_bits(1 << bit))
+            .filter(move |flag| self.contains(*flag))
+    }
+
+    /// The name of a single property, e.g. to display it in logs
+    pub fn label(&self) -> &'static str {
+        match *self {
+            CalendarProperties::COLOR => "color",
+            CalendarProperties::ORDER => "order",
+            CalendarProperties::DESCRIPTION => "description",
+            CalendarProperties::TIMEZONE => "time zone",
+            CalendarProperties::NAME => "name",
+            CalendarProperties::DEFAULT_ALARMS => "default alarms",
+            _ => "properties",
+        }
+    }
+}
+
+/// The format of the `calendar-color` DAV property, i.e. `#RRGGBBAA`
+pub(crate) fn dav_color(color: &csscolorparser::Color) -> String {
+    let (r, g, b, a) = color.rgba_u8();
+    format!("#{:02X}{:02X}{:02X}{:02X}", r, g, b, a)
+}
+
 impl SupportedComponents {
+    /// The iCal names of these components, e.g. `"VEVENT, VTODO"`
+    pub fn ical_names(&self) -> String {
+        let mut names = Vec::new();
+        if self.contains(Self::EVENT) { names.push("VEVENT"); }
+        if self.contains(Self::TODO) { names.push("VTODO"); }
+        if self.contains(Self::CONTACT) { names.push("VCARD"); }
+        names.join(", ")
+    }
+
     pub fn to_xml_string(&self) -> String {
         format!(r#"
             <B:supported-calendar-component-set>
@@ -60,6 +137,210 @@ impl TryFrom<minidom::Element> for SupportedComponents {
 }
 
 
+bitflags! {
+    /// The WebDAV ACL privileges (see [RFC 3744](https://datatracker.ietf.org/doc/html/rfc3744#section-3)) the current user has on a calendar
+    #[derive(Serialize, Deserialize)]
+    pub struct Privileges: u16 {
+        /// Read the calendar and its items
+        const READ = 1;
+        /// Modify existing items
+        const WRITE_CONTENT = 2;
+        /// Modify the properties of the calendar (name, color...)
+        const WRITE_PROPERTIES = 4;
+        /// Create new items
+        const BIND = 8;
+        /// Delete items
+        const UNBIND = 16;
+        /// Modify the access rights of the calendar, i.e. share it
+        const WRITE_ACL = 32;
+    }
+}
+
+impl Privileges {
+    /// Returns whether existing items can be modified
+    pub fn can_write(&self) -> bool {
+        self.contains(Self::WRITE_CONTENT)
+    }
+    /// Returns whether new items can be created
+    pub fn can_create_items(&self) -> bool {
+        self.contains(Self::BIND)
+    }
+    /// Returns whether items can be deleted
+    pub fn can_delete_items(&self) -> bool {
+        self.contains(Self::UNBIND)
+    }
+    /// Returns whether the calendar can be shared with other users
+    pub fn can_share(&self) -> bool {
+        self.contains(Self::WRITE_ACL)
+    }
+    /// Returns whether nothing but reading is allowed
+    pub fn is_read_only(&self) -> bool {
+        !self.intersects(Self::WRITE_CONTENT | Self::BIND | Self::UNBIND)
+    }
+}
+
+impl TryFrom<minidom::Element> for Privileges {
+    type Error = Box<dyn Error>;
+
+    /// Create an instance from an XML <current-user-privilege-set> element
+    fn try_from(element: minidom::Element) -> Result<Self, Self::Error> {
+        if element.name() != "current-user-privilege-set" {
+            return Err("Element must be a <current-user-privilege-set>".into());
+        }
+
+        let mut flags = Self::empty();
+        for privilege in element.children().filter(|c| c.name() == "privilege") {
+            for p in privilege.children() {
+                match p.name() {
+                    "all" => flags.insert(Self::all()),
+                    "read" => flags.insert(Self::READ),
+                    "write" => flags.insert(Self::WRITE_CONTENT | Self::WRITE_PROPERTIES | Self::BIND | Self::UNBIND),
+                    "write-content" => flags.insert(Self::WRITE_CONTENT),
+                    "write-properties" => flags.insert(Self::WRITE_PROPERTIES),
+                    "bind" => flags.insert(Self::BIND),
+                    "unbind" => flags.insert(Self::UNBIND),
+                    "write-acl" | "share" => flags.insert(Self::WRITE_ACL),
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(flags)
+    }
+}
+
+impl Default for Privileges {
+    /// Servers that do not tell about privileges are assumed to allow everything
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+
+/// The version of a whole calendar, as reported by the server.
+///
+/// This changes whenever any item of the calendar changes, so that a sync can tell a calendar has not changed without comparing its items
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarVersion {
+    /// The `getctag` property (a non-standard extension that most servers support)
+    pub ctag: Option<String>,
+    /// The `sync-token` property (see [RFC 6578](https://datatracker.ietf.org/doc/html/rfc6578))
+    pub sync_token: Option<String>,
+}
+
+impl CalendarVersion {
+    /// Returns whether the server has told anything about this version. Unknown versions can never tell that a calendar has not changed
+    pub fn is_known(&self) -> bool {
+        self.ctag.is_some() || self.sync_token.is_some()
+    }
+
+    /// Returns whether a calendar has not changed between a `previous` version and this one.
+    ///
+    /// Ctags are compared when both versions have one, since some sync tokens change even when nothing else has (e.g. the ones of REST APIs, see [`DavCalendar::resume_from`](crate::traits::DavCalendar::resume_from))
+    pub fn is_unchanged_since(&self, previous: &CalendarVersion) -> bool {
+        match (&self.ctag, &previous.ctag) {
+            (Some(ctag), Some(previous_ctag)) => ctag == previous_ctag,
+            _ => self == previous,
+        }
+    }
+
+    /// Parse the reply to a `PROPFIND` of the `getctag` and `sync-token` properties
+    pub(crate) fn from_xml(element: &minidom::Element) -> Self {
+        let property = |name: &str| {
+            crate::utils::find_elem(element, name)
+                .map(|elem| elem.text().trim().to_string())
+                .filter(|text| !text.is_empty())
+        };
+        Self {
+            ctag: property("getctag"),
+            sync_token: property("sync-token"),
+        }
+    }
+}
+
+
+/// The default time zone of a calendar (its `calendar-timezone` property, see [RFC 4791](https://datatracker.ietf.org/doc/html/rfc4791#section-5.2.2)).
+///
+/// Floating and all-day items of this calendar should be interpreted in this time zone, rather than in the one of the device
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarTimezone {
+    tzid: String,
+    ical: String,
+}
+
+impl CalendarTimezone {
+    /// Parse an iCal `VCALENDAR` that contains a single `VTIMEZONE`
+    pub fn from_ical(ical: &str) -> Result<Self, KFError> {
+        let tzid = crate::ical::parse_timezone_id(ical)?;
+        Ok(Self { tzid, ical: ical.to_string() })
+    }
+
+    /// The identifier of the time zone (usually an IANA name, e.g. `Europe/Paris`)
+    pub fn tzid(&self) -> &str {
+        &self.tzid
+    }
+
+    /// The whole `VCALENDAR`, that describes the UTC offsets of this time zone
+    pub fn ical(&self) -> &str {
+        &self.ical
+    }
+}
+
+
+/// The alarms that are added to new items of a calendar, as published by Apple servers
+/// (see [draft-daboo-valarm-extensions](https://datatracker.ietf.org/doc/html/draft-daboo-valarm-extensions-04#section-9)).
+///
+/// They are attached to new local items if [`config::ATTACH_DEFAULT_ALARMS`](crate::config::ATTACH_DEFAULT_ALARMS) is set
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DefaultAlarms {
+    /// The alarm of events that have a time
+    pub event_date_time: Option<Alarm>,
+    /// The alarm of all-day events
+    pub event_date: Option<Alarm>,
+    /// The alarm of tasks whose start or due date has a time
+    pub task_date_time: Option<Alarm>,
+    /// The alarm of tasks whose start or due date is a whole day
+    pub task_date: Option<Alarm>,
+}
+
+impl DefaultAlarms {
+    /// The names of the CalDAV properties, in the order of [`Self::alarms`]
+    const DAV_PROPERTIES: [&'static str; 4] = ["default-alarm-vevent-datetime", "default-alarm-vevent-date", "default-alarm-vtodo-datetime", "default-alarm-vtodo-date"];
+
+    fn alarms(&self) -> [&Option<Alarm>; 4] {
+        [&self.event_date_time, &self.event_date, &self.task_date_time, &self.task_date]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alarms().iter().all(|alarm| alarm.is_none())
+    }
+
+    /// Parse the `<response>` of a calendar to a `PROPFIND`. Calendars that have no default alarm (or whose server does not support them) have `None`
+    pub(crate) fn from_xml(response: &minidom::Element, calendar_url: &url::Url) -> Option<Self> {
+        let [event_date_time, event_date, task_date_time, task_date] = Self::DAV_PROPERTIES.map(|property| {
+            let value = crate::utils::find_elem(response, property)?.text();
+            if value.trim().is_empty() {
+                return None;
+            }
+            Alarm::from_ical(&value)
+                .map_err(|err| log::warn!("Ignoring the invalid {} of calendar {}: {}", property, calendar_url, err))
+                .ok()
+        });
+        Some(Self{ event_date_time, event_date, task_date_time, task_date }).filter(|alarms| !alarms.is_empty())
+    }
+
+    /// The `<set>` and `<remove>` elements of a `PROPPATCH` that changes the default alarms of a calendar to these ones
+    pub(crate) fn to_property_update(&self) -> String {
+        Self::DAV_PROPERTIES.iter().zip(self.alarms())
+            .map(|(property, alarm)| match alarm {
+                Some(alarm) => format!("<d:set><d:prop><c:{0}>{1}</c:{0}></d:prop></d:set>", property, crate::utils::xml_escape(&alarm.to_ical())),
+                None => format!("<d:remove><d:prop><c:{}/></d:prop></d:remove>", property),
+            })
+            .collect()
+    }
+}
+
+
 /// Flags to tell which events should be retrieved
 pub enum SearchFilter {
     /// Return all items
@@ -77,3 +358,109 @@ impl Default for SearchFilter {
         SearchFilter::All
     }
 }
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_privileges_parsing() {
+        let xml = r#"<d:current-user-privilege-set xmlns:d="DAV:">
+                <d:privilege><d:read/></d:privilege>
+                <d:privilege><d:read-current-user-privilege-set/></d:privilege>
+                <d:privilege><d:write-properties/></d:privilege>
+            </d:current-user-privilege-set>"#;
+        let privileges = Privileges::try_from(xml.parse::<minidom::Element>().unwrap()).unwrap();
+        assert_eq!(privileges, Privileges::READ | Privileges::WRITE_PROPERTIES);
+        assert!(privileges.is_read_only());
+
+        let xml = r#"<d:current-user-privilege-set xmlns:d="DAV:">
+                <d:privilege><d:read/></d:privilege>
+                <d:privilege><d:write/></d:privilege>
+            </d:current-user-privilege-set>"#;
+        let privileges = Privileges::try_from(xml.parse::<minidom::Element>().unwrap()).unwrap();
+        assert!(privileges.can_write() && privileges.can_create_items() && privileges.can_delete_items());
+        assert!(!privileges.can_share());
+    }
+
+    #[test]
+    fn test_dav_color() {
+        assert_eq!(dav_color(&csscolorparser::parse("#ff8000").unwrap()), "#FF8000FF");
+        assert_eq!(dav_color(&csscolorparser::parse("#12345678").unwrap()), "#12345678");
+    }
+
+    #[test]
+    fn test_calendar_timezone_parsing() {
+        let ical = std::fs::read_to_string("tests/assets/timezone_europe_paris.ics").unwrap();
+        let timezone = CalendarTimezone::from_ical(&ical).unwrap();
+        assert_eq!(timezone.tzid(), "Europe/Paris");
+        assert_eq!(timezone.ical(), ical);
+
+        assert!(CalendarTimezone::from_ical("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nEND:VCALENDAR\r\n").is_err());
+    }
+
+    #[test]
+    fn test_default_alarms() {
+        let url: url::Url = "https://some.server/cal/".parse().unwrap();
+        let xml = r#"<d:response xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+                <d:propstat><d:prop>
+                    <c:default-alarm-vtodo-datetime>BEGIN:VALARM&#13;
+ACTION:DISPLAY&#13;
+TRIGGER:-PT15M&#13;
+END:VALARM&#13;
+</c:default-alarm-vtodo-datetime>
+                    <c:default-alarm-vtodo-date>not an alarm</c:default-alarm-vtodo-date>
+                    <c:default-alarm-vevent-date></c:default-alarm-vevent-date>
+                </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>
+            </d:response>"#;
+        let defaults = DefaultAlarms::from_xml(&xml.parse().unwrap(), &url).unwrap();
+        let alarm = Alarm::new(crate::alarm::AlarmAction::Display, crate::alarm::AlarmTrigger::before_start(chrono::Duration::minutes(15)));
+        assert_eq!(defaults, DefaultAlarms{ task_date_time: Some(alarm.clone()), ..DefaultAlarms::default() });
+        let update = defaults.to_property_update();
+        assert!(update.contains("<d:set><d:prop><c:default-alarm-vtodo-datetime>BEGIN:VALARM\r\nACTION:DISPLAY\r\n"));
+        assert!(update.contains("<d:remove><d:prop><c:default-alarm-vtodo-date/></d:prop></d:remove>"));
+
+        let xml = r#"<d:response xmlns:d="DAV:"><d:propstat><d:prop/></d:propstat></d:response>"#;
+        assert_eq!(DefaultAlarms::from_xml(&xml.parse().unwrap(), &url), None);
+
+        // Only tasks that have a date get the default alarm that matches it
+        let task_ics = |date: &str| format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Test//EN\r\nBEGIN:VTODO\r\nUID:some-uid\r\nDTSTAMP:20210101T100000Z\r\nSUMMARY:Task\r\n{}END:VTODO\r\nEND:VCALENDAR\r\n", date);
+        let mut task = crate::ical::parse(&task_ics("DUE:20211110T070000Z\r\n"), url.join("task.ics").unwrap(), crate::item::SyncStatus::NotSynced).unwrap();
+        assert!(task.unwrap_task_mut().attach_default_alarm(&defaults));
+        assert_eq!(task.unwrap_task().alarms(), &[alarm]);
+        // ...unless they already have one
+        assert!(!task.unwrap_task_mut().attach_default_alarm(&defaults));
+
+        let mut task = crate::ical::parse(&task_ics("DUE;VALUE=DATE:20211110\r\n"), url.join("task.ics").unwrap(), crate::item::SyncStatus::NotSynced).unwrap();
+        assert!(!task.unwrap_task_mut().attach_default_alarm(&defaults));
+        let mut task = crate::ical::parse(&task_ics(""), url.join("task.ics").unwrap(), crate::item::SyncStatus::NotSynced).unwrap();
+        assert!(!task.unwrap_task_mut().attach_default_alarm(&defaults));
+    }
+
+    #[test]
+    fn test_calendar_version_parsing() {
+        let xml = r#"<d:multistatus xmlns:d="DAV:" xmlns:cs="http://calendarserver.org/ns/">
+                <d:response>
+                    <d:href>/cal/</d:href>
+                    <d:propstat>
+                        <d:prop><cs:getctag>"1234"</cs:getctag></d:prop>
+                        <d:status>HTTP/1.1 200 OK</d:status>
+                    </d:propstat>
+                    <d:propstat>
+                        <d:prop><d:sync-token/></d:prop>
+                        <d:status>HTTP/1.1 404 Not Found</d:status>
+                    </d:propstat>
+                </d:response>
+            </d:multistatus>"#;
+        let version = CalendarVersion::from_xml(&xml.parse().unwrap());
+        assert_eq!(version, CalendarVersion{ ctag: Some("\"1234\"".to_string()), sync_token: None });
+        assert!(version.is_known());
+        assert!(!CalendarVersion::default().is_known());
+
+        let with_token = |token: &str| CalendarVersion{ sync_token: Some(token.to_string()), ..version.clone() };
+        assert!(with_token("2").is_unchanged_since(&with_token("1")));
+        assert!(!CalendarVersion{ ctag: None, sync_token: Some("2".to_string()) }.is_unchanged_since(&CalendarVersion{ ctag: None, sync_token: Some("1".to_string()) }));
+    }
+}
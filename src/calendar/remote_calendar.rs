@@ -3,18 +3,30 @@ use std::error::Error;
 use std::sync::Mutex;
 
 use async_trait::async_trait;
-use reqwest::{header::CONTENT_TYPE, header::CONTENT_LENGTH};
+use futures_util::stream::{self, StreamExt};
+use reqwest::{Method, Response, StatusCode, header::CONTENT_TYPE, header::CONTENT_LENGTH, header::ETAG};
 use csscolorparser::Color;
+use chrono::{DateTime, Utc};
 use url::Url;
 
 use crate::traits::BaseCalendar;
 use crate::traits::DavCalendar;
 use crate::calendar::SupportedComponents;
+use crate::calendar::Privileges;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::calendar::sharing::Sharing;
 use crate::item::Item;
 use crate::item::VersionTag;
 use crate::item::SyncStatus;
+use crate::occurrence::Occurrence;
+use crate::partial::{PartialItem, PartialRequest};
 use crate::resource::Resource;
-use crate::utils::find_elem;
+use crate::provider::filter::SyncFilter;
+use crate::error::KFError;
+use crate::utils::{find_elem, find_elems, same_resource};
+use crate::spans::{self, Instrument};
 
 static TASKS_BODY: &str = r#"
     <c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
@@ -29,6 +41,23 @@ static TASKS_BODY: &str = r#"
     </c:calendar-query>
 "#;
 
+static ETAG_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:">
+        <d:prop>
+            <d:getetag />
+        </d:prop>
+    </d:propfind>
+"#;
+
+pub(crate) static CALENDAR_VERSION_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:" xmlns:cs="http://calendarserver.org/ns/">
+        <d:prop>
+            <cs:getctag />
+            <d:sync-token />
+        </d:prop>
+    </d:propfind>
+"#;
+
 static MULTIGET_BODY_PREFIX: &str = r#"
     <c:calendar-multiget xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
         <d:prop>
@@ -39,6 +68,13 @@ static MULTIGET_BODY_SUFFIX: &str = r#"
     </c:calendar-multiget>
 "#;
 
+/// The error type of requests that may run concurrently (their futures must be `Send`, which `Box<dyn Error>` is not)
+pub(crate) type SendError = Box<dyn Error + Send + Sync>;
+
+pub(crate) fn sendable(err: Box<dyn Error>) -> SendError {
+    Box::new(KFError::from(err))
+}
+
 
 
 /// A CalDAV calendar created by a [`Client`](crate::client::Client).
@@ -48,144 +84,479 @@ pub struct RemoteCalendar {
     resource: Resource,
     supported_components: SupportedComponents,
     color: Option<Color>,
+    order: Option<u32>,
+    description: Option<String>,
+    timezone: Option<CalendarTimezone>,
+    default_alarms: Option<DefaultAlarms>,
+    privileges: Privileges,
+    owner: Option<Url>,
+    sharing: Option<Sharing>,
 
     cached_version_tags: Mutex<Option<HashMap<Url, VersionTag>>>,
 }
 
-#[async_trait]
-impl BaseCalendar for RemoteCalendar {
-    fn name(&self) -> &str { &self.name }
-    fn url(&self) -> &Url { &self.resource.url() }
-    fn supported_components(&self) -> crate::calendar::SupportedComponents {
-        self.supported_components
+impl RemoteCalendar {
+    /// Get a lightweight version of every item, that only contains the requested components and properties.
+    ///
+    /// This is much faster than downloading the whole items, e.g. to quickly display an agenda. Full items can be downloaded later on demand
+    pub async fn get_partial_items(&self, request: &PartialRequest) -> Result<Vec<PartialItem>, KFError> {
+        // Only list items that contain one of the requested components
+        let comp_filters: String = request.component_names()
+            .map(|name| format!("<c:comp-filter name=\"{}\" />", name))
+            .collect();
+        let filter = match request.component_names().count() {
+            1 => format!("<c:filter><c:comp-filter name=\"VCALENDAR\">{}</c:comp-filter></c:filter>", comp_filters),
+            _ => "<c:filter><c:comp-filter name=\"VCALENDAR\" /></c:filter>".to_string(),
+        };
+        let body = format!(r#"
+            <c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+                <d:prop>
+                    <d:getetag />
+                    {}
+                </d:prop>
+                {}
+            </c:calendar-query>
+            "#, request.to_calendar_data(), filter);
+
+        let responses = crate::client::sub_request_and_extract_elems(&self.resource, "REPORT", body, "response").await?;
+        let mut items = Vec::new();
+        for response in responses {
+            let item_url = match find_elem(&response, "href") {
+                None => {
+                    log::warn!("Unable to extract HREF");
+                    continue;
+                },
+                Some(href) => self.resource.combine(&href.text()).url().clone(),
+            };
+            let version_tag = match find_elem(&response, "getetag") {
+                None => {
+                    log::warn!("Unable to extract ETAG for item {}, ignoring it", item_url);
+                    continue;
+                },
+                Some(etag) => VersionTag::from(etag.text()),
+            };
+            let ical_data = match find_elem(&response, "calendar-data") {
+                None => {
+                    log::warn!("Missing calendar-data for item {}", item_url);
+                    continue;
+                },
+                Some(data) => data.text(),
+            };
+
+            match crate::ical::parse_partial(&ical_data, item_url, version_tag) {
+                Err(err) => log::warn!("{}, ignoring it", err),
+                Ok(item) => items.push(item),
+            }
+        }
+
+        Ok(items)
     }
-    fn color(&self) -> Option<&Color> {
-        self.color.as_ref()
+
+    /// Get the instances of the events that overlap a time range.
+    ///
+    /// Recurring events are expanded by the server (using the CalDAV `expand` element), so that every instance is returned as a distinct [`Occurrence`]
+    pub async fn get_occurrences(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<Occurrence>, KFError> {
+        let start = start.format("%Y%m%dT%H%M%SZ");
+        let end = end.format("%Y%m%dT%H%M%SZ");
+        let body = format!(r#"
+            <c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+                <d:prop>
+                    <d:getetag />
+                    <c:calendar-data>
+                        <c:expand start="{start}" end="{end}"/>
+                    </c:calendar-data>
+                </d:prop>
+                <c:filter>
+                    <c:comp-filter name="VCALENDAR">
+                        <c:comp-filter name="VEVENT">
+                            <c:time-range start="{start}" end="{end}"/>
+                        </c:comp-filter>
+                    </c:comp-filter>
+                </c:filter>
+            </c:calendar-query>
+            "#, start=start, end=end);
+
+        let responses = crate::client::sub_request_and_extract_elems(&self.resource, "REPORT", body, "response").await?;
+        let mut occurrences = Vec::new();
+        for response in responses {
+            let item_url = match find_elem(&response, "href") {
+                None => {
+                    log::warn!("Unable to extract HREF");
+                    continue;
+                },
+                Some(href) => self.resource.combine(&href.text()).url().clone(),
+            };
+            let ical_data = match find_elem(&response, "calendar-data") {
+                None => {
+                    log::warn!("Missing calendar-data for item {}", item_url);
+                    continue;
+                },
+                Some(data) => data.text(),
+            };
+            occurrences.extend(crate::ical::parse_occurrences(&ical_data, &item_url)?);
+        }
+
+        occurrences.sort_by_key(|occ| occ.start);
+        Ok(occurrences)
     }
 
-    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, Box<dyn Error>> {
-        let ical_text = crate::ical::build_from(&item)?;
+    /// Get the new version tag of an item that has just been written.
+    ///
+    /// Servers usually return it in the `ETag` header. Some of them do not (e.g. when they have altered the item), in which case we ask for it
+    async fn version_tag_after_write(&self, response: &Response, item_url: &Url) -> Result<VersionTag, KFError> {
+        if let Some(etag) = response.headers().get(ETAG) {
+            return Ok(VersionTag::from(etag.to_str()?.to_string()));
+        }
+
+        log::debug!("No ETag in the response headers for {}, asking for it", item_url);
+        let item = self.resource.combine(item_url.path());
+        let text = crate::client::sub_request(&item, "PROPFIND", ETAG_BODY.to_string(), 0).await?;
+        let root: minidom::Element = text.parse()?;
+        match find_elem(&root, "getetag") {
+            None => Err(format!("No ETag in the response for item {}", item_url).into()),
+            Some(etag) => Ok(VersionTag::from(etag.text())),
+        }
+    }
+
+    /// Extract the URLs and version tags from the `<response>` elements of a REPORT
+    fn parse_version_tags(&self, responses: &[minidom::Element]) -> HashMap<Url, VersionTag> {
+        let mut items = HashMap::new();
+        for response in responses {
+            let item_url = match find_elem(response, "href") {
+                None => {
+                    log::warn!("Unable to extract HREF");
+                    continue;
+                },
+                Some(href) => self.resource.combine(&href.text()).url().clone(),
+            };
+
+            let version_tag = match find_elem(response, "getetag") {
+                None => {
+                    log::warn!("Unable to extract ETAG for item {}, ignoring it", item_url);
+                    continue;
+                },
+                Some(etag) => VersionTag::from(etag.text()),
+            };
+
+            items.insert(item_url, version_tag);
+        }
+        items
+    }
 
-        let response = reqwest::Client::new()
-            .put(item.url().clone())
+    /// Upload an item that does not exist on the server yet. See [`BaseCalendar::add_item`]
+    async fn put_new_item(&self, item: &Item) -> Result<SyncStatus, SendError> {
+        let ical_text = crate::ical::build_from(item).map_err(sendable)?;
+
+        let request = self.resource.request(Method::PUT, item.url().clone())
             .header("If-None-Match", "*")
             .header(CONTENT_TYPE, "text/calendar")
             .header(CONTENT_LENGTH, ical_text.len())
-            .basic_auth(self.resource.username(), Some(self.resource.password()))
-            .body(ical_text)
-            .send()
-            .await?;
-
-        if response.status().is_success() == false {
-            return Err(format!("Unexpected HTTP status code {:?}", response.status()).into());
-        }
-
-        let reply_hdrs = response.headers();
-        match reply_hdrs.get("ETag") {
-            None => Err(format!("No ETag in these response headers: {:?} (request was {:?})", reply_hdrs, item.url()).into()),
-            Some(etag) => {
-                let vtag_str = etag.to_str()?;
-                let vtag = VersionTag::from(String::from(vtag_str));
-                Ok(SyncStatus::Synced(vtag))
-            }
+            .body(ical_text);
+        let response = self.resource.send(request).await.map_err(sendable)?;
+
+        if response.status() == StatusCode::INSUFFICIENT_STORAGE {
+            return Err(KFError::InsufficientStorage{ url: item.url().clone() }.into());
         }
+        if response.status() == StatusCode::FORBIDDEN {
+            return Err(KFError::Forbidden{ url: item.url().clone() }.into());
+        }
+        if !response.status().is_success() {
+            return Err(KFError::from_status(response.status(), item.url().clone()).into());
+        }
+
+        let vtag = self.version_tag_after_write(&response, item.url()).await?;
+        Ok(SyncStatus::Synced(vtag))
     }
 
-    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, Box<dyn Error>> {
+    /// Upload a new version of an item, unless it has changed on the server. See [`BaseCalendar::update_item`]
+    async fn put_changed_item(&self, item: &Item) -> Result<SyncStatus, SendError> {
         let old_etag = match item.sync_status() {
             SyncStatus::NotSynced => return Err("Cannot update an item that has not been synced already".into()),
             SyncStatus::Synced(_) => return Err("Cannot update an item that has not changed".into()),
             SyncStatus::LocallyModified(etag) => etag,
             SyncStatus::LocallyDeleted(etag) => etag,
         };
-        let ical_text = crate::ical::build_from(&item)?;
+        let ical_text = crate::ical::build_from(item).map_err(sendable)?;
 
-        let request = reqwest::Client::new()
-            .put(item.url().clone())
+        let request = self.resource.request(Method::PUT, item.url().clone())
             .header("If-Match", old_etag.as_str())
             .header(CONTENT_TYPE, "text/calendar")
             .header(CONTENT_LENGTH, ical_text.len())
-            .basic_auth(self.resource.username(), Some(self.resource.password()))
-            .body(ical_text)
-            .send()
-            .await?;
-
-        if request.status().is_success() == false {
-            return Err(format!("Unexpected HTTP status code {:?}", request.status()).into());
-        }
-
-        let reply_hdrs = request.headers();
-        match reply_hdrs.get("ETag") {
-            None => Err(format!("No ETag in these response headers: {:?} (request was {:?})", reply_hdrs, item.url()).into()),
-            Some(etag) => {
-                let vtag_str = etag.to_str()?;
-                let vtag = VersionTag::from(String::from(vtag_str));
-                Ok(SyncStatus::Synced(vtag))
+            .body(ical_text);
+        let response = self.resource.send(request).await.map_err(sendable)?;
+
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            // The version tags we know are outdated
+            *self.cached_version_tags.lock().unwrap() = None;
+            return Err(KFError::Conflict{ url: item.url().clone() }.into());
+        }
+        if response.status() == StatusCode::INSUFFICIENT_STORAGE {
+            return Err(KFError::InsufficientStorage{ url: item.url().clone() }.into());
+        }
+        if response.status() == StatusCode::FORBIDDEN {
+            return Err(KFError::Forbidden{ url: item.url().clone() }.into());
+        }
+        if !response.status().is_success() {
+            return Err(KFError::from_status(response.status(), item.url().clone()).into());
+        }
+
+        let vtag = self.version_tag_after_write(&response, item.url()).await?;
+        Ok(SyncStatus::Synced(vtag))
+    }
+
+    async fn delete_remote_item(&self, item_url: &Url) -> Result<(), SendError> {
+        let request = self.resource.request(Method::DELETE, item_url.clone());
+        let del_response = self.resource.send(request).await.map_err(sendable)?;
+
+        if del_response.status() == StatusCode::FORBIDDEN {
+            return Err(KFError::Forbidden{ url: item_url.clone() }.into());
+        }
+
+        if !del_response.status().is_success() {
+            return Err(KFError::from_status(del_response.status(), item_url.clone()).into());
+        }
+
+        Ok(())
+    }
+
+    /// Change properties of this calendar on the server. `update` is the content of a `<d:propertyupdate>` element, i.e. `<d:set>` and `<d:remove>` elements
+    async fn proppatch(&self, update: &str) -> Result<(), KFError> {
+        let url = self.resource.url();
+        let body = format!(r#"<?xml version="1.0" encoding="utf-8" ?>
+            <d:propertyupdate xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav" xmlns:a="http://apple.com/ns/ical/">
+                {}
+            </d:propertyupdate>
+            "#, update);
+        let request = self.resource.request(Method::from_bytes(b"PROPPATCH")?, url.clone())
+            .header(CONTENT_TYPE, "application/xml")
+            .body(body);
+        let response = self.resource.send(request).await?;
+
+        let status = response.status();
+        if status == StatusCode::FORBIDDEN {
+            return Err(KFError::Forbidden{ url: url.clone() });
+        }
+        if !status.is_success() {
+            return Err(KFError::from_status(status, url.clone()));
+        }
+
+        // Servers reply with the status of every property, unless they have been asked for a minimal reply
+        let text = response.text().await?;
+        if text.trim().is_empty() {
+            return Ok(());
+        }
+        let element: minidom::Element = text.parse()?;
+        for propstat in find_elems(&element, "propstat") {
+            let prop_status = match find_elem(propstat, "status") {
+                None => continue,
+                Some(prop_status) => prop_status.text(),
+            };
+            // e.g. "HTTP/1.1 200 OK"
+            match prop_status.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok()) {
+                Some(code) if (200..300).contains(&code) => (),
+                Some(403) => return Err(KFError::Forbidden{ url: url.clone() }),
+                _ => return Err(format!("The server has refused to change the properties of {}: {}", url, prop_status.trim()).into()),
             }
         }
+        Ok(())
+    }
+
+    /// Set the privileges the current user has on this calendar, as reported by the server
+    pub(crate) fn set_privileges(&mut self, privileges: Privileges) {
+        self.privileges = privileges;
+    }
+
+    /// Set the principal that owns this calendar, as reported by the server
+    pub(crate) fn set_owner(&mut self, owner: Option<Url>) {
+        self.owner = owner;
+    }
+
+    /// Set the sharing metadata of this calendar, as reported by the server
+    pub(crate) fn set_sharing(&mut self, sharing: Option<Sharing>) {
+        self.sharing = sharing;
+    }
+
+    /// Set the display order of this calendar, as reported by the server
+    pub(crate) fn set_order(&mut self, order: Option<u32>) {
+        self.order = order;
+    }
+
+    /// Set the description of this calendar, as reported by the server
+    pub(crate) fn set_description(&mut self, description: Option<String>) {
+        self.description = description;
+    }
+
+    /// Set the default time zone of this calendar, as reported by the server
+    pub(crate) fn set_timezone(&mut self, timezone: Option<CalendarTimezone>) {
+        self.timezone = timezone;
+    }
+
+    pub(crate) fn set_default_alarms(&mut self, default_alarms: Option<DefaultAlarms>) {
+        self.default_alarms = default_alarms;
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for RemoteCalendar {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { &self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+    fn order(&self) -> Option<u32> {
+        self.order
+    }
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+    fn timezone(&self) -> Option<&CalendarTimezone> {
+        self.timezone.as_ref()
+    }
+    fn default_alarms(&self) -> Option<&DefaultAlarms> {
+        self.default_alarms.as_ref()
+    }
+    fn privileges(&self) -> Privileges {
+        self.privileges
+    }
+    fn owner(&self) -> Option<&Url> {
+        self.owner.as_ref()
+    }
+    fn sharing(&self) -> Option<&Sharing> {
+        self.sharing.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        let span = spans::item("add", item.url(), Some(item.uid()));
+        self.put_new_item(&item).instrument(span).await.map_err(KFError::from)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        let span = spans::item("update", item.url(), Some(item.uid()));
+        self.put_changed_item(&item).instrument(span).await.map_err(KFError::from)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl DavCalendar for RemoteCalendar {
     fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
         Self {
             name, resource, supported_components, color,
+            order: None,
+            description: None,
+            timezone: None,
+            default_alarms: None,
+            privileges: Privileges::default(),
+            owner: None,
+            sharing: None,
             cached_version_tags: Mutex::new(None),
         }
     }
 
 
-    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, Box<dyn Error>> {
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
         if let Some(map) = &*self.cached_version_tags.lock().unwrap() {
             log::debug!("Version tags are already cached.");
             return Ok(map.clone());
         };
 
         let responses = crate::client::sub_request_and_extract_elems(&self.resource, "REPORT", TASKS_BODY.to_string(), "response").await?;
+        let items = self.parse_version_tags(&responses);
+
+        // Note: the mutex cannot be locked during this whole async function, but it can safely be re-entrant (this will just waste an unnecessary request)
+        *self.cached_version_tags.lock().unwrap() = Some(items.clone());
+        Ok(items)
+    }
 
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
         let mut items = HashMap::new();
-        for response in responses {
-            let item_url = crate::utils::find_elem(&response, "href")
-                .map(|elem| self.resource.combine(&elem.text()));
-            let item_url = match item_url {
-                None => {
-                    log::warn!("Unable to extract HREF");
-                    continue;
-                },
-                Some(resource) => {
-                    resource.url().clone()
-                },
-            };
+        // Like get_item_version_tags, only tasks are supported for now
+        let filter = SyncFilter { components: filter.components & SupportedComponents::TODO, ..filter.clone() };
+        for comp_filter in filter.to_comp_filters() {
+            let body = format!(r#"
+                <c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+                    <d:prop>
+                        <d:getetag />
+                    </d:prop>
+                    {}
+                </c:calendar-query>
+                "#, comp_filter);
+            let responses = crate::client::sub_request_and_extract_elems(&self.resource, "REPORT", body, "response").await?;
+            items.extend(self.parse_version_tags(&responses));
+        }
+        Ok(items)
+    }
 
-            let version_tag = match crate::utils::find_elem(&response, "getetag") {
-                None => {
-                    log::warn!("Unable to extract ETAG for item {}, ignoring it", item_url);
-                    continue;
-                },
-                Some(etag) => {
-                    VersionTag::from(etag.text())
-                }
-            };
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        let text = crate::client::sub_request(&self.resource, "PROPFIND", CALENDAR_VERSION_BODY.to_string(), 0).await?;
+        Ok(CalendarVersion::from_xml(&text.parse()?))
+    }
 
-            items.insert(item_url.clone(), version_tag);
-        }
+    async fn update_color(&mut self, color: Option<Color>) -> Result<(), KFError> {
+        let update = match &color {
+            Some(color) => format!("<d:set><d:prop><a:calendar-color>{}</a:calendar-color></d:prop></d:set>", crate::calendar::dav_color(color)),
+            None => "<d:remove><d:prop><a:calendar-color/></d:prop></d:remove>".to_string(),
+        };
+        self.proppatch(&update).await?;
+        self.color = color;
+        Ok(())
+    }
 
-        // Note: the mutex cannot be locked during this whole async function, but it can safely be re-entrant (this will just waste an unnecessary request)
-        *self.cached_version_tags.lock().unwrap() = Some(items.clone());
-        Ok(items)
+    async fn update_order(&mut self, order: Option<u32>) -> Result<(), KFError> {
+        let update = match order {
+            Some(order) => format!("<d:set><d:prop><a:calendar-order>{}</a:calendar-order></d:prop></d:set>", order),
+            None => "<d:remove><d:prop><a:calendar-order/></d:prop></d:remove>".to_string(),
+        };
+        self.proppatch(&update).await?;
+        self.order = order;
+        Ok(())
     }
 
-    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, Box<dyn Error>> {
-        let res = reqwest::Client::new()
-            .get(url.clone())
-            .header(CONTENT_TYPE, "text/calendar")
-            .basic_auth(self.resource.username(), Some(self.resource.password()))
-            .send()
-            .await?;
+    async fn update_description(&mut self, description: Option<String>) -> Result<(), KFError> {
+        let update = match &description {
+            Some(description) => format!("<d:set><d:prop><c:calendar-description>{}</c:calendar-description></d:prop></d:set>", crate::utils::xml_escape(description)),
+            None => "<d:remove><d:prop><c:calendar-description/></d:prop></d:remove>".to_string(),
+        };
+        self.proppatch(&update).await?;
+        self.description = description;
+        Ok(())
+    }
+
+    async fn update_timezone(&mut self, timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        let update = match &timezone {
+            Some(timezone) => format!("<d:set><d:prop><c:calendar-timezone>{}</c:calendar-timezone></d:prop></d:set>", crate::utils::xml_escape(timezone.ical())),
+            None => "<d:remove><d:prop><c:calendar-timezone/></d:prop></d:remove>".to_string(),
+        };
+        self.proppatch(&update).await?;
+        self.timezone = timezone;
+        Ok(())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        let update = format!("<d:set><d:prop><d:displayname>{}</d:displayname></d:prop></d:set>", crate::utils::xml_escape(&name));
+        self.proppatch(&update).await?;
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        let update = default_alarms.clone().unwrap_or_default().to_property_update();
+        self.proppatch(&update).await?;
+        self.default_alarms = default_alarms;
+        Ok(())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        let request = self.resource.request(Method::GET, url.clone())
+            .header(CONTENT_TYPE, "text/calendar");
+        let res = self.resource.send(request).await?;
 
         if res.status().is_success() == false {
-            return Err(format!("Unexpected HTTP status code {:?}", res.status()).into());
+            return Err(KFError::from_status(res.status(), url.clone()));
         }
 
         let text = res.text().await?;
@@ -201,11 +572,11 @@ impl DavCalendar for RemoteCalendar {
         Ok(Some(item))
     }
 
-    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, Box<dyn Error>> {
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
         // Build the request body
         let mut hrefs = String::new();
         for url in urls {
-            hrefs.push_str(&format!("        <d:href>{}</d:href>\n", url.path()));
+            hrefs.push_str(&format!("        <d:href>{}</d:href>\n", crate::utils::xml_escape(url.path())));
         }
         let body = format!("{}{}{}", MULTIGET_BODY_PREFIX, hrefs, MULTIGET_BODY_SUFFIX);
 
@@ -215,38 +586,96 @@ impl DavCalendar for RemoteCalendar {
         // This is supposed to be cached
         let version_tags = self.get_item_version_tags().await?;
 
-        // Parse the results
-        let mut results = Vec::new();
+        // Parse the results.
+        // They may come in any order, and their hrefs may be encoded differently than the requested URLs: items are matched by URL (never by UID, since servers can name them as they like)
+        let mut results = vec![None; urls.len()];
         for xml_reply in xml_replies {
             let href = find_elem(&xml_reply, "href").ok_or("Missing HREF")?.text();
-            let mut url = self.resource.url().clone();
-            url.set_path(&href);
+            let reply_url = self.resource.combine(&href).url().clone();
+            let index = match urls.iter().position(|url| same_resource(url, &reply_url)) {
+                Some(index) => index,
+                None => {
+                    log::warn!("The server has returned item {}, that has not been requested", reply_url);
+                    continue;
+                },
+            };
+            let url = &urls[index];
             let ical_data = find_elem(&xml_reply, "calendar-data").ok_or("Missing calendar-data")?.text();
 
-            let vt = match version_tags.get(&url) {
+            let vt = version_tags.get(url)
+                .or_else(|| version_tags.iter().find(|(listed_url, _)| same_resource(listed_url, url)).map(|(_, vt)| vt));
+            let vt = match vt {
                 None => return Err(format!("Inconsistent data: {} has no version tag", url).into()),
                 Some(vt) => vt,
             };
 
             let item = crate::ical::parse(&ical_data, url.clone(), SyncStatus::Synced(vt.clone()))?;
-            results.push(Some(item));
+            results[index] = Some(item);
         }
 
         Ok(results)
     }
 
-    async fn delete_item(&mut self, item_url: &Url) -> Result<(), Box<dyn Error>> {
-        let del_response = reqwest::Client::new()
-            .delete(item_url.clone())
-            .basic_auth(self.resource.username(), Some(self.resource.password()))
-            .send()
-            .await?;
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        let span = spans::item("delete", item_url, None);
+        self.delete_remote_item(item_url).instrument(span).await.map_err(KFError::from)
+    }
 
-        if del_response.status().is_success() == false {
-            return Err(format!("Unexpected HTTP status code {:?}", del_response.status()).into());
+    async fn move_item(&mut self, item_url: &Url, destination: &Url) -> Result<SyncStatus, KFError> {
+        let request = self.resource.request(Method::from_bytes(b"MOVE")?, item_url.clone())
+            .header("Destination", destination.as_str())
+            .header("Overwrite", "F");
+        let response = self.resource.send(request).await?;
+
+        match response.status() {
+            // Servers may refuse to move items across collections, or not support MOVE at all
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED | StatusCode::BAD_GATEWAY | StatusCode::FORBIDDEN => {
+                return Err(KFError::MoveNotSupported{ url: item_url.clone() });
+            },
+            status if !status.is_success() => {
+                return Err(KFError::from_status(status, item_url.clone()));
+            },
+            _ => (),
         }
+        *self.cached_version_tags.lock().unwrap() = None;
 
-        Ok(())
+        let vtag = self.version_tag_after_write(&response, destination).await?;
+        Ok(SyncStatus::Synced(vtag))
     }
-}
 
+    async fn add_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| {
+                let span = spans::item("add", item.url(), Some(item.uid()));
+                async move { this.put_new_item(&item).await }.instrument(span)
+            })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(items)
+            .map(|item| {
+                let span = spans::item("update", item.url(), Some(item.uid()));
+                async move { this.put_changed_item(&item).await }.instrument(span)
+            })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let this = &*self;
+        let results: Vec<_> = stream::iter(item_urls.to_vec())
+            .map(|url| {
+                let span = spans::item("delete", &url, None);
+                async move { this.delete_remote_item(&url).await }.instrument(span)
+            })
+            .buffered(max_concurrency.max(1))
+            .collect().await;
+        results.into_iter().map(|res| res.map_err(KFError::from)).collect()
+    }
+}
@@ -0,0 +1,214 @@
+//! Whether calendars are shared between users, as published by servers that support sharing (see [`Sharing`])
+
+use minidom::Element;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::utils::{find_elem, find_elems};
+
+/// Whether a calendar is shared, from the point of view of the current user
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShareStatus {
+    #[default]
+    NotShared,
+    /// The current user owns this calendar, and shares it with other users
+    SharedByMe,
+    /// Another user owns this calendar, and shares it with the current user
+    SharedWithMe,
+}
+
+/// What a sharee is allowed to do with a shared calendar
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShareAccess {
+    Read,
+    ReadWrite,
+}
+
+/// A user a calendar is shared with
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sharee {
+    /// The principal of this user, or a `mailto:` URL
+    pub href: Url,
+    pub display_name: Option<String>,
+    pub access: ShareAccess,
+    /// Whether this user has accepted the invitation, or `None` if the server does not tell
+    pub accepted: Option<bool>,
+}
+
+/// The sharing metadata of a calendar.
+///
+/// Servers publish it with non-standard properties (e.g. the ones of Nextcloud/sabre, or of Apple CalendarServer), and some of them do not publish it at all.
+/// The owner of a calendar is given by [`BaseCalendar::owner`](crate::traits::BaseCalendar::owner)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sharing {
+    pub status: ShareStatus,
+    /// The display name of the owner of the calendar (e.g. to label it "Shared by Alice"), if the server tells it
+    pub owner_display_name: Option<String>,
+    /// The users this calendar is shared with. Servers usually only tell this to the owner of the calendar
+    pub sharees: Vec<Sharee>,
+}
+
+impl Sharing {
+    /// Parse the `<response>` of a calendar to a `PROPFIND`.
+    ///
+    /// `owner` is the owner of the calendar, and `current_principal` the principal of the current user, that are compared when the server does not tell whether the calendar is shared
+    pub(crate) fn from_xml(response: &Element, owner: Option<&Url>, current_principal: Option<&Url>) -> Self {
+        let sharees = Self::parse_sharees(response, current_principal);
+
+        let has_resource_type = |name: &str| find_elem(response, "resourcetype")
+            .is_some_and(|types| types.children().any(|t| t.name() == name));
+        let share_access = find_elem(response, "share-access")
+            .and_then(|access| access.children().next())
+            .map(|access| access.name().to_string());
+        let owned_by_someone_else = match (owner, current_principal) {
+            (Some(owner), Some(me)) => !crate::utils::same_resource(owner, me),
+            _ => false,
+        };
+
+        let status = match share_access.as_deref() {
+            Some("shared-owner") => ShareStatus::SharedByMe,
+            Some("read") | Some("read-write") => ShareStatus::SharedWithMe,
+            _ if has_resource_type("shared-owner") => ShareStatus::SharedByMe,
+            _ if has_resource_type("shared") || owned_by_someone_else => ShareStatus::SharedWithMe,
+            _ if !sharees.is_empty() => ShareStatus::SharedByMe,
+            _ => ShareStatus::NotShared,
+        };
+
+        let owner_display_name = find_elem(response, "owner-displayname")
+            .map(|name| name.text())
+            .filter(|name| !name.is_empty());
+
+        Self { status, owner_display_name, sharees }
+    }
+
+    /// Sharees are listed in an `<invite>` property, either as `<user>`s (Nextcloud/sabre and Apple CalendarServer) or as `<sharee>`s (WebDAV resource sharing)
+    fn parse_sharees(response: &Element, current_principal: Option<&Url>) -> Vec<Sharee> {
+        // Servers reply with empty properties for the namespaces they do not support
+        let invite = match find_elems(response, "invite").into_iter().find(|invite| invite.children().next().is_some()) {
+            None => return Vec::new(),
+            Some(invite) => invite,
+        };
+
+        let mut sharees = Vec::new();
+        for user in invite.children().filter(|child| child.name() == "user" || child.name() == "sharee") {
+            let href = match find_elem(user, "href").and_then(|href| resolve_principal_href(&href.text(), current_principal)) {
+                None => continue,
+                Some(href) => href,
+            };
+            let display_name = find_elem(user, "common-name")
+                .or_else(|| find_elem(user, "displayname"))
+                .map(|name| name.text())
+                .filter(|name| !name.is_empty());
+            let access = match find_elem(user, "read-write") {
+                Some(_) => ShareAccess::ReadWrite,
+                None => ShareAccess::Read,
+            };
+            let accepted = if find_elem(user, "invite-accepted").is_some() {
+                Some(true)
+            } else if ["invite-declined", "invite-noresponse", "invite-invalid"].iter().any(|status| find_elem(user, status).is_some()) {
+                Some(false)
+            } else {
+                None
+            };
+            sharees.push(Sharee{ href, display_name, access, accepted });
+        }
+        sharees
+    }
+}
+
+/// Resolve the href of a principal.
+///
+/// Besides absolute URLs and paths, Nextcloud/sabre use paths relative to the root of the DAV server (e.g. `principals/users/alice`, sometimes prefixed by `principal:`).
+/// This root is found in the URL of the current principal
+pub(crate) fn resolve_principal_href(href: &str, current_principal: Option<&Url>) -> Option<Url> {
+    let href = href.trim();
+    let relative = href.strip_prefix("principal:").unwrap_or(href);
+    if relative == href {
+        if let Ok(url) = Url::parse(href) {
+            return Some(url);
+        }
+    }
+
+    let current_principal = current_principal?;
+    if relative.starts_with('/') {
+        return current_principal.join(relative).ok();
+    }
+    let principal_path = current_principal.path();
+    let root = match principal_path.find("/principals/") {
+        Some(index) => &principal_path[..=index],
+        None => "/",
+    };
+    current_principal.join(&format!("{}{}", root, relative)).ok()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sharing_parsing() {
+        let me: Url = "https://cloud.example.com/remote.php/dav/principals/users/bob/".parse().unwrap();
+        let alice: Url = "https://cloud.example.com/remote.php/dav/principals/users/alice/".parse().unwrap();
+
+        // A calendar that Bob shares (as Nextcloud publishes it)
+        let xml = r#"<d:response xmlns:d="DAV:" xmlns:oc="http://owncloud.org/ns" xmlns:cs="http://calendarserver.org/ns/">
+                <d:propstat><d:prop>
+                    <oc:invite>
+                        <oc:user>
+                            <d:href>principal:principals/users/alice</d:href>
+                            <oc:common-name>Alice</oc:common-name>
+                            <oc:invite-accepted/>
+                            <oc:access><oc:read-write/></oc:access>
+                        </oc:user>
+                        <oc:user>
+                            <d:href>mailto:carol@example.com</d:href>
+                            <oc:invite-noresponse/>
+                            <oc:access><oc:read/></oc:access>
+                        </oc:user>
+                    </oc:invite>
+                </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>
+                <d:propstat><d:prop><cs:invite/></d:prop><d:status>HTTP/1.1 404 Not Found</d:status></d:propstat>
+            </d:response>"#;
+        let sharing = Sharing::from_xml(&xml.parse().unwrap(), Some(&me), Some(&me));
+        assert_eq!(sharing.status, ShareStatus::SharedByMe);
+        assert_eq!(sharing.sharees, vec![
+            Sharee{ href: "https://cloud.example.com/remote.php/dav/principals/users/alice".parse().unwrap(), display_name: Some("Alice".to_string()), access: ShareAccess::ReadWrite, accepted: Some(true) },
+            Sharee{ href: "mailto:carol@example.com".parse().unwrap(), display_name: None, access: ShareAccess::Read, accepted: Some(false) },
+        ]);
+
+        // A calendar that Alice shares with Bob
+        let xml = r#"<d:response xmlns:d="DAV:" xmlns:nc="http://nextcloud.com/ns">
+                <d:propstat><d:prop>
+                    <nc:owner-displayname>Alice</nc:owner-displayname>
+                </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>
+            </d:response>"#;
+        let sharing = Sharing::from_xml(&xml.parse().unwrap(), Some(&alice), Some(&me));
+        assert_eq!(sharing.status, ShareStatus::SharedWithMe);
+        assert_eq!(sharing.owner_display_name.as_deref(), Some("Alice"));
+        assert!(sharing.sharees.is_empty());
+
+        // Servers that support WebDAV resource sharing tell it explicitly
+        let xml = r#"<d:response xmlns:d="DAV:">
+                <d:propstat><d:prop>
+                    <d:share-access><d:read/></d:share-access>
+                </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>
+            </d:response>"#;
+        assert_eq!(Sharing::from_xml(&xml.parse().unwrap(), None, Some(&me)).status, ShareStatus::SharedWithMe);
+
+        let xml = r#"<d:response xmlns:d="DAV:"><d:propstat><d:prop/></d:propstat></d:response>"#;
+        assert_eq!(Sharing::from_xml(&xml.parse().unwrap(), Some(&me), Some(&me)), Sharing::default());
+    }
+
+    #[test]
+    fn test_resolve_principal_href() {
+        let me: Url = "https://cloud.example.com/remote.php/dav/principals/users/bob/".parse().unwrap();
+        let resolve = |href: &str| resolve_principal_href(href, Some(&me)).unwrap().to_string();
+        assert_eq!(resolve("principals/users/alice"), "https://cloud.example.com/remote.php/dav/principals/users/alice");
+        assert_eq!(resolve("principal:principals/users/alice"), "https://cloud.example.com/remote.php/dav/principals/users/alice");
+        assert_eq!(resolve("/remote.php/dav/principals/users/alice/"), "https://cloud.example.com/remote.php/dav/principals/users/alice/");
+        assert_eq!(resolve("mailto:alice@example.com"), "mailto:alice@example.com");
+        assert_eq!(resolve_principal_href("principals/users/alice", None), None);
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use csscolorparser::Color;
+use reqwest::{Method, StatusCode, header::ACCEPT, header::ETAG, header::IF_MODIFIED_SINCE, header::IF_NONE_MATCH, header::LAST_MODIFIED};
+use url::Url;
+
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::Privileges;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::item::Item;
+use crate::item::VersionTag;
+use crate::item::SyncStatus;
+use crate::resource::Resource;
+use crate::provider::filter::SyncFilter;
+use crate::error::KFError;
+use crate::kv_store::checksum;
+
+/// A feed downloaded less than this ago is not downloaded again, so that a sync only downloads it once
+const FEED_MAX_AGE_SECS: i64 = 60;
+
+/// The content of a feed, as it has last been downloaded
+#[derive(Debug)]
+struct Feed {
+    version: CalendarVersion,
+    items: HashMap<Url, Item>,
+    downloaded_at: DateTime<Utc>,
+    /// The `ETag` header of the download, that is sent back to the server so that it only replies with an unchanged feed
+    etag: Option<String>,
+    /// The `Last-Modified` header of the download, for servers that do not send ETags
+    last_modified: Option<String>,
+}
+
+
+
+/// A read-only calendar, whose items are published as a single iCal file (e.g. a `webcal://` holiday feed).
+///
+/// It is created by a [`Subscriptions`](crate::subscription::Subscriptions) source. \
+/// The feed is downloaded again at every sync (or less often, see [`SubscribedCalendar::set_refresh_interval`]), and each of its components is exposed as a separate item, whose URL is built from its UID. \
+/// Downloads are conditional (with `If-None-Match` and `If-Modified-Since` headers), so that servers do not send feeds that have not changed. \
+/// Such a calendar cannot be changed, so that a local calendar synced with it is read-only.
+#[derive(Debug)]
+pub struct SubscribedCalendar {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+    refresh_interval: Duration,
+
+    feed: Mutex<Option<Feed>>,
+}
+
+impl SubscribedCalendar {
+    /// The URL of the item of this calendar that has a given UID
+    pub fn item_url(&self, uid: &str) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(&format!("{}.ics", uid));
+        }
+        url
+    }
+
+    /// Set how long a downloaded feed is used before it is downloaded again.
+    ///
+    /// Syncs that happen within this interval do not send any request. The default is zero, i.e. the feed is checked at every sync
+    pub fn set_refresh_interval(&mut self, interval: std::time::Duration) {
+        self.refresh_interval = Duration::from_std(interval).unwrap_or_else(|_| Duration::max_value());
+    }
+
+    /// Download the feed, unless it has been downloaded less than `max_age` ago
+    async fn refresh(&self, max_age: Duration) -> Result<(), KFError> {
+        let (is_fresh, etag, last_modified) = match self.feed.lock().unwrap().as_ref() {
+            None => (false, None, None),
+            Some(feed) => (Utc::now() - feed.downloaded_at < max_age, feed.etag.clone(), feed.last_modified.clone()),
+        };
+        if is_fresh {
+            return Ok(());
+        }
+
+        match self.download(etag, last_modified).await? {
+            Some(feed) => *self.feed.lock().unwrap() = Some(feed),
+            None => {
+                log::debug!("Feed {} has not changed", self.resource.url());
+                if let Some(feed) = self.feed.lock().unwrap().as_mut() {
+                    feed.downloaded_at = Utc::now();
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Download the feed, or return `None` if the server tells it has not changed since the download `etag` and `last_modified` come from
+    async fn download(&self, etag: Option<String>, last_modified: Option<String>) -> Result<Option<Feed>, KFError> {
+        let url = self.resource.url().clone();
+        let mut request = self.resource.request(Method::GET, url.clone())
+            .header(ACCEPT, "text/calendar");
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = self.resource.send(request).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(KFError::from_status(response.status(), url));
+        }
+        let header = |name| response.headers().get(name).and_then(|value| value.to_str().ok()).map(String::from);
+        let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+        let content = response.text().await?;
+
+        let mut items = HashMap::new();
+        let components = crate::ical::split_calendar(&content).map_err(|err| match err {
+            KFError::IcalParse{ line, reason, .. } => KFError::IcalParse{ url: Some(url.clone()), line, reason },
+            err => err,
+        })?;
+        for component in components {
+            let uid = match &component.uid {
+                None => {
+                    log::warn!("Ignoring a {} without a UID in {}", component.kind, url);
+                    continue;
+                },
+                Some(uid) => uid,
+            };
+            if component.kind != "VTODO" {
+                // Like for CalDAV calendars, only tasks are supported for now
+                log::debug!("Ignoring {} {} in {}", component.kind, uid, url);
+                continue;
+            }
+
+            let item_url = self.item_url(uid);
+            if items.contains_key(&item_url) {
+                log::warn!("Ignoring another {} with UID {} in {}", component.kind, uid, url);
+                continue;
+            }
+            let version_tag = VersionTag::from(format!("{:x}", checksum(component.ical.as_bytes())));
+            match crate::ical::parse(&component.ical, item_url.clone(), SyncStatus::Synced(version_tag)) {
+                Err(err) => log::warn!("Ignoring item {} in {}: {}", uid, url, err),
+                Ok(item) => { items.insert(item_url, item); },
+            }
+        }
+
+        Ok(Some(Feed {
+            version: CalendarVersion{ ctag: Some(format!("{:x}", checksum(content.as_bytes()))), sync_token: None },
+            items,
+            downloaded_at: Utc::now(),
+            etag,
+            last_modified,
+        }))
+    }
+
+    /// How long a feed is used by the requests of a sync, before it is downloaded again
+    fn max_age(&self) -> Duration {
+        self.refresh_interval.max(Duration::seconds(FEED_MAX_AGE_SECS))
+    }
+
+    fn forbidden(&self) -> KFError {
+        KFError::Forbidden{ url: self.resource.url().clone() }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for SubscribedCalendar {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+    fn privileges(&self) -> Privileges {
+        Privileges::READ
+    }
+
+    async fn add_item(&mut self, _item: Item) -> Result<SyncStatus, KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_item(&mut self, _item: Item) -> Result<SyncStatus, KFError> {
+        Err(self.forbidden())
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for SubscribedCalendar {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        Self {
+            name, resource, supported_components, color,
+            refresh_interval: Duration::zero(),
+            feed: Mutex::new(None),
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        self.refresh(self.max_age()).await?;
+        let feed = self.feed.lock().unwrap();
+        Ok(feed.iter()
+            .flat_map(|feed| &feed.items)
+            .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url.clone(), tag.clone())))
+            .collect())
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        let all_tags = self.get_item_version_tags().await?;
+        let feed = self.feed.lock().unwrap();
+        Ok(all_tags.into_iter()
+            .filter(|(url, _)| feed.as_ref().and_then(|feed| feed.items.get(url)).is_some_and(|item| filter.matches(item)))
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        self.refresh(self.max_age()).await?;
+        Ok(self.feed.lock().unwrap().as_ref().and_then(|feed| feed.items.get(url).cloned()))
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        self.refresh(self.max_age()).await?;
+        let feed = self.feed.lock().unwrap();
+        Ok(urls.iter()
+            .map(|url| feed.as_ref().and_then(|feed| feed.items.get(url).cloned()))
+            .collect())
+    }
+
+    async fn delete_item(&mut self, _item_url: &Url) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn move_item(&mut self, _item_url: &Url, _destination: &Url) -> Result<SyncStatus, KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, _max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        items.iter().map(|_| Err(self.forbidden())).collect()
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, _max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        items.iter().map(|_| Err(self.forbidden())).collect()
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], _max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        item_urls.iter().map(|_| Err(self.forbidden())).collect()
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        // This is the first request of a sync, that gets the latest version of the feed unless the refresh interval has not elapsed yet
+        self.refresh(self.refresh_interval).await?;
+        Ok(self.feed.lock().unwrap().as_ref().map(|feed| feed.version.clone()).unwrap_or_default())
+    }
+
+    async fn update_color(&mut self, _color: Option<Color>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_description(&mut self, _description: Option<String>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_timezone(&mut self, _timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        // Feeds have no name of their own, their names are chosen by the user
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+}
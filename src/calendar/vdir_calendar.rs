@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use csscolorparser::Color;
+use url::Url;
+
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::item::Item;
+use crate::item::VersionTag;
+use crate::item::SyncStatus;
+use crate::resource::Resource;
+use crate::provider::filter::SyncFilter;
+use crate::error::KFError;
+use crate::kv_store::checksum;
+use crate::cache::vdir::{write_atomically, COLOR_FILE, DISPLAYNAME_FILE};
+
+/// A calendar stored in a folder of a [`Vdir`](crate::vdir::Vdir), as one iCal file per item.
+///
+/// The version tag of an item is the checksum of its file, so that changes made by other apps (e.g. khal or todoman) are detected at the next sync. \
+/// Its display name and color are stored in the `displayname` and `color` files of the folder, like vdirsyncer does.
+/// Other calendar properties cannot be stored in a vdir, and cannot be changed.
+#[derive(Debug)]
+pub struct VdirCalendar {
+    name: String,
+    resource: Resource,
+    supported_components: SupportedComponents,
+    color: Option<Color>,
+
+    folder: PathBuf,
+}
+
+impl VdirCalendar {
+    /// Read the calendar of a folder, whose name and color are the ones of its metadata files (if any)
+    pub(crate) fn load(url: Url) -> Result<Self, KFError> {
+        let folder = url.to_file_path().map_err(|_| format!("{} is not a folder", url))?;
+        let name = std::fs::read_to_string(folder.join(DISPLAYNAME_FILE)).ok()
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .or_else(|| folder.file_name().and_then(OsStr::to_str).map(String::from))
+            .unwrap_or_default();
+        let color = std::fs::read_to_string(folder.join(COLOR_FILE)).ok()
+            .and_then(|color| csscolorparser::parse(color.trim()).ok());
+        let resource = Resource::new(url, String::new(), String::new());
+        Ok(Self::new(name, resource, SupportedComponents::TODO, color))
+    }
+
+    /// The folder that contains the files of this calendar
+    pub fn folder(&self) -> &std::path::Path {
+        &self.folder
+    }
+
+    /// The file of an item of this calendar
+    fn item_path(&self, url: &Url) -> Result<PathBuf, KFError> {
+        let path = url.to_file_path().map_err(|_| format!("Item {} is not a file", url))?;
+        if path.parent() != Some(self.folder.as_path()) || path.extension() != Some(OsStr::new("ics")) {
+            return Err(format!("Item {} is not an iCal file of calendar {} (the names of vdir items must end with .ics, see ItemUrls::FromUid)", url, self.resource.url()).into());
+        }
+        Ok(path)
+    }
+
+    /// The content and version tag of the file of an item, or `None` if it does not exist
+    fn read_item_file(&self, url: &Url) -> Result<Option<(String, VersionTag)>, KFError> {
+        match std::fs::read_to_string(self.item_path(url)?) {
+            Ok(content) => {
+                let version_tag = version_tag(&content);
+                Ok(Some((content, version_tag)))
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(format!("Unable to read item {}: {}", url, err).into()),
+        }
+    }
+
+    fn write_item_file(&self, item: &Item) -> Result<SyncStatus, KFError> {
+        let content = crate::ical::build_from(item)?;
+        write_atomically(&self.item_path(item.url())?, content.as_bytes())?;
+        Ok(SyncStatus::Synced(version_tag(&content)))
+    }
+
+    /// Read every item of this calendar
+    fn read_items(&self) -> Result<HashMap<Url, Item>, KFError> {
+        let mut items = HashMap::new();
+        for dir_entry in std::fs::read_dir(&self.folder)? {
+            let path = dir_entry?.path();
+            if path.extension() != Some(OsStr::new("ics")) || !path.is_file() {
+                continue;
+            }
+            let url = Url::from_file_path(&path).map_err(|_| format!("Invalid file name {:?}", path))?;
+            let content = std::fs::read_to_string(&path)?;
+            if !content.to_ascii_uppercase().contains("BEGIN:VTODO") {
+                // Like for CalDAV calendars, only tasks are supported for now
+                log::debug!("Ignoring {:?}, that contains no task", path);
+                continue;
+            }
+            match crate::ical::parse(&content, url.clone(), SyncStatus::Synced(version_tag(&content))) {
+                Err(err) => log::warn!("Ignoring invalid item {:?}: {}", path, err),
+                Ok(item) => { items.insert(url, item); },
+            }
+        }
+        Ok(items)
+    }
+
+    /// The non-async version of [`Self::add_item`]
+    pub fn add_item_sync(&mut self, item: &Item) -> Result<SyncStatus, KFError> {
+        if self.read_item_file(item.url())?.is_some() {
+            return Err(format!("Item {} cannot be added, it exists already", item.url()).into());
+        }
+        self.write_item_file(item)
+    }
+
+    /// The non-async version of [`Self::update_item`]
+    pub fn update_item_sync(&mut self, item: &Item) -> Result<SyncStatus, KFError> {
+        let old_tag = match item.sync_status() {
+            SyncStatus::NotSynced => return Err("Cannot update an item that has not been synced already".into()),
+            SyncStatus::Synced(_) => return Err("Cannot update an item that has not changed".into()),
+            SyncStatus::LocallyModified(tag) => tag,
+            SyncStatus::LocallyDeleted(tag) => tag,
+        };
+        match self.read_item_file(item.url())? {
+            Some((_, current_tag)) if &current_tag == old_tag => self.write_item_file(item),
+            // The file has been changed (or removed) by another app since it was last synced
+            _ => Err(KFError::Conflict{ url: item.url().clone() }),
+        }
+    }
+
+    /// The non-async version of [`Self::delete_item`]
+    pub fn delete_item_sync(&mut self, item_url: &Url) -> Result<(), KFError> {
+        std::fs::remove_file(self.item_path(item_url)?)
+            .map_err(|err| format!("Unable to delete item {}: {}", item_url, err).into())
+    }
+
+    fn forbidden(&self) -> KFError {
+        KFError::Forbidden{ url: self.resource.url().clone() }
+    }
+}
+
+fn version_tag(content: &str) -> VersionTag {
+    VersionTag::from(format!("{:x}", checksum(content.as_bytes())))
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl BaseCalendar for VdirCalendar {
+    fn name(&self) -> &str { &self.name }
+    fn url(&self) -> &Url { self.resource.url() }
+    fn supported_components(&self) -> crate::calendar::SupportedComponents {
+        self.supported_components
+    }
+    fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.add_item_sync(&item)
+    }
+
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError> {
+        self.update_item_sync(&item)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DavCalendar for VdirCalendar {
+    fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self {
+        let folder = resource.url().to_file_path().unwrap_or_default();
+        Self {
+            name, resource, supported_components, color,
+            folder,
+        }
+    }
+
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError> {
+        Ok(self.read_items()?.into_iter()
+            .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url, tag.clone())))
+            .collect())
+    }
+
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        Ok(self.read_items()?.into_iter()
+            .filter(|(_, item)| filter.matches(item))
+            .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url, tag.clone())))
+            .collect())
+    }
+
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError> {
+        match self.read_item_file(url)? {
+            None => Ok(None),
+            Some((content, version_tag)) => Ok(Some(crate::ical::parse(&content, url.clone(), SyncStatus::Synced(version_tag))?)),
+        }
+    }
+
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError> {
+        let mut items = Vec::new();
+        for url in urls {
+            items.push(self.get_item_by_url(url).await?);
+        }
+        Ok(items)
+    }
+
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        self.delete_item_sync(item_url)
+    }
+
+    async fn move_item(&mut self, item_url: &Url, destination: &Url) -> Result<SyncStatus, KFError> {
+        // Items can only be moved to another calendar of the same vdir
+        let destination_path = destination.to_file_path().ok()
+            .filter(|path| path.parent().and_then(|parent| parent.parent()) == self.folder.parent() && path.parent().is_some_and(|parent| parent.is_dir()))
+            .filter(|path| !path.exists())
+            .ok_or_else(|| KFError::MoveNotSupported{ url: item_url.clone() })?;
+        let content = std::fs::read_to_string(self.item_path(item_url)?)?;
+        std::fs::rename(self.item_path(item_url)?, &destination_path)?;
+        Ok(SyncStatus::Synced(version_tag(&content)))
+    }
+
+    async fn add_items(&mut self, items: Vec<Item>, _max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let mut results = Vec::new();
+        for item in items {
+            results.push(self.add_item_sync(&item));
+        }
+        results
+    }
+
+    async fn update_items(&mut self, items: Vec<Item>, _max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>> {
+        let mut results = Vec::new();
+        for item in items {
+            results.push(self.update_item_sync(&item));
+        }
+        results
+    }
+
+    async fn delete_items(&mut self, item_urls: &[Url], _max_concurrency: usize) -> Vec<Result<(), KFError>> {
+        let mut results = Vec::new();
+        for url in item_urls {
+            results.push(self.delete_item_sync(url));
+        }
+        results
+    }
+
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError> {
+        let mut tags: Vec<_> = self.get_item_version_tags().await?.into_iter()
+            .map(|(url, tag)| format!("{} {}\n", url, tag.as_str()))
+            .collect();
+        tags.sort();
+        Ok(CalendarVersion{ ctag: Some(format!("{:x}", checksum(tags.concat().as_bytes()))), sync_token: None })
+    }
+
+    async fn update_color(&mut self, color: Option<Color>) -> Result<(), KFError> {
+        match &color {
+            Some(color) => write_atomically(&self.folder.join(COLOR_FILE), color.to_hex_string().as_bytes())?,
+            None => { let _ = std::fs::remove_file(self.folder.join(COLOR_FILE)); },
+        }
+        self.color = color;
+        Ok(())
+    }
+
+    async fn update_order(&mut self, _order: Option<u32>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_description(&mut self, _description: Option<String>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_timezone(&mut self, _timezone: Option<CalendarTimezone>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+
+    async fn update_name(&mut self, name: String) -> Result<(), KFError> {
+        write_atomically(&self.folder.join(DISPLAYNAME_FILE), name.as_bytes())?;
+        self.name = name;
+        Ok(())
+    }
+
+    async fn update_default_alarms(&mut self, _default_alarms: Option<DefaultAlarms>) -> Result<(), KFError> {
+        Err(self.forbidden())
+    }
+}
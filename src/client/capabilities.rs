@@ -0,0 +1,115 @@
+//! Detection of the optional features a CalDAV server supports
+
+use std::collections::HashSet;
+
+use minidom::Element;
+
+/// The features a server advertises, either in the `DAV` header of its replies to `OPTIONS` requests, or in the `supported-report-set` of its calendar home set.
+///
+/// These are fetched once per [`Client`](crate::client::Client), see [`Client::server_capabilities`](crate::client::Client::server_capabilities)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerCapabilities {
+    /// The compliance classes from the `DAV` header (e.g. `1`, `access-control`, `calendar-access`), lowercased
+    pub dav_classes: HashSet<String>,
+    /// The names of the REPORTs the server supports (e.g. `sync-collection`), or `None` if the server did not tell
+    pub supported_reports: Option<HashSet<String>>,
+}
+
+impl ServerCapabilities {
+    /// Build an instance from the value of a `DAV` header
+    pub fn from_dav_header(value: &str) -> Self {
+        let dav_classes = value
+            .split(',')
+            .map(|class| class.trim().to_lowercase())
+            .filter(|class| !class.is_empty())
+            .collect();
+
+        Self {
+            dav_classes,
+            supported_reports: None,
+        }
+    }
+
+    /// Read the REPORTs listed in a `<supported-report-set>` element
+    pub fn set_supported_reports(&mut self, supported_report_set: &Element) {
+        let reports = supported_report_set.children()
+            .filter(|child| child.name() == "supported-report")
+            .flat_map(|supported| supported.children().filter(|child| child.name() == "report"))
+            .flat_map(|report| report.children().map(|name| name.name().to_string()))
+            .collect();
+        self.supported_reports = Some(reports);
+    }
+
+    /// Returns whether the server advertises a given compliance class in its `DAV` header
+    pub fn has_dav_class(&self, class: &str) -> bool {
+        self.dav_classes.contains(&class.to_lowercase())
+    }
+
+    /// Returns whether a REPORT is supported. Unless the server tells otherwise, only the REPORTs that are mandatory for CalDAV servers are assumed to be supported
+    pub fn supports_report(&self, report: &str) -> bool {
+        match &self.supported_reports {
+            Some(reports) => reports.contains(report),
+            None => matches!(report, "calendar-query" | "calendar-multiget"),
+        }
+    }
+
+    /// Whether the server is a CalDAV server at all ([RFC 4791](https://datatracker.ietf.org/doc/html/rfc4791))
+    pub fn supports_calendar_access(&self) -> bool {
+        self.has_dav_class("calendar-access")
+    }
+
+    /// Whether the server implements scheduling of invitations by itself ([RFC 6638](https://datatracker.ietf.org/doc/html/rfc6638))
+    pub fn supports_calendar_auto_schedule(&self) -> bool {
+        self.has_dav_class("calendar-auto-schedule")
+    }
+
+    /// Whether collections can be created with an extended MKCOL ([RFC 5689](https://datatracker.ietf.org/doc/html/rfc5689))
+    pub fn supports_extended_mkcol(&self) -> bool {
+        self.has_dav_class("extended-mkcol")
+    }
+
+    /// Whether the server supports the WebDAV access control protocol ([RFC 3744](https://datatracker.ietf.org/doc/html/rfc3744))
+    pub fn supports_access_control(&self) -> bool {
+        self.has_dav_class("access-control")
+    }
+
+    /// Whether changes can be listed incrementally with a `sync-collection` REPORT ([RFC 6578](https://datatracker.ietf.org/doc/html/rfc6578))
+    pub fn supports_sync_collection(&self) -> bool {
+        self.supports_report("sync-collection")
+    }
+
+    /// Whether several items can be downloaded in a single `calendar-multiget` REPORT
+    pub fn supports_multiget(&self) -> bool {
+        self.supports_report("calendar-multiget")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_parsing() {
+        let mut caps = ServerCapabilities::from_dav_header("1, 3, extended-mkcol, access-control, calendarserver-principal-property-search, calendar-access, Calendar-Auto-Schedule");
+        assert!(caps.supports_calendar_access());
+        assert!(caps.supports_calendar_auto_schedule());
+        assert!(caps.supports_extended_mkcol());
+        assert!(caps.has_dav_class("1"));
+        assert!(!caps.has_dav_class("2"));
+
+        // Mandatory REPORTs are assumed until the server tells otherwise
+        assert!(caps.supports_multiget());
+        assert!(!caps.supports_sync_collection());
+
+        let report_set: Element = r#"<d:supported-report-set xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+                <d:supported-report><d:report><d:expand-property/></d:report></d:supported-report>
+                <d:supported-report><d:report><d:sync-collection/></d:report></d:supported-report>
+                <d:supported-report><d:report><c:calendar-query/></d:report></d:supported-report>
+            </d:supported-report-set>"#.parse().unwrap();
+        caps.set_supported_reports(&report_set);
+        assert!(caps.supports_sync_collection());
+        assert!(caps.supports_report("calendar-query"));
+        assert!(!caps.supports_multiget());
+    }
+}
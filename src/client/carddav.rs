@@ -0,0 +1,191 @@
+//! CardDAV address books (see [`AddressBooks`])
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use reqwest::{Method, StatusCode};
+use reqwest::header::CONTENT_TYPE;
+use csscolorparser::Color;
+use url::Url;
+
+use crate::resource::Resource;
+use crate::calendar::address_book::AddressBook;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarTimezone;
+use crate::client::{sub_request_and_extract_elem, sub_request_and_extract_elems, Client, ServerCapabilities, DAVCLIENT_BODY};
+use crate::error::KFError;
+use crate::traits::CalDavSource;
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::utils::{find_elem, xml_escape};
+
+static ADDRESSBOOK_HOMESET_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:" xmlns:card="urn:ietf:params:xml:ns:carddav" >
+      <d:prop>
+        <card:addressbook-home-set />
+      </d:prop>
+    </d:propfind>
+"#;
+
+static ADDRESSBOOKS_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:" xmlns:card="urn:ietf:params:xml:ns:carddav" >
+       <d:prop>
+         <d:displayname />
+         <d:resourcetype />
+         <card:addressbook-description />
+       </d:prop>
+    </d:propfind>
+"#;
+
+
+
+/// The CardDAV address books of an account, whose items are [`Contact`](crate::contact::Contact)s.
+///
+/// This source can be used in a [`Provider`](crate::provider::Provider) (see [`CardDavProvider`](crate::CardDavProvider)), to sync contacts into a local cache
+/// just like a [`Client`] syncs calendars. It is usually created from a `Client` (see [`Client::address_books`]), so that it shares its network settings. \
+/// Address books are found in the `addressbook-home-set` of the current user (see [RFC 6352](https://datatracker.ietf.org/doc/html/rfc6352#section-7.1.1)).
+#[derive(Debug)]
+pub struct AddressBooks {
+    resource: Resource,
+    home_set: Mutex<Option<Resource>>,
+}
+
+impl Client {
+    /// The CardDAV address books of the same account, on the same server
+    pub fn address_books(&self) -> AddressBooks {
+        AddressBooks::from_resource(self.resource.clone())
+    }
+}
+
+impl AddressBooks {
+    /// Create a source with the default network settings. This does not start a connection
+    pub fn new<S: AsRef<str>, T: ToString, U: ToString>(url: S, username: T, password: U) -> Result<Self, KFError> {
+        let url = Url::parse(url.as_ref())?;
+        Ok(Self::from_resource(Resource::new(url, username.to_string(), password.to_string())))
+    }
+
+    fn from_resource(resource: Resource) -> Self {
+        Self {
+            resource,
+            home_set: Mutex::new(None),
+        }
+    }
+
+    /// Return the address book home set, or fetch it from server if not known yet
+    async fn get_home_set(&self) -> Result<Resource, KFError> {
+        if let Some(home_set) = &*self.home_set.lock().unwrap() {
+            return Ok(home_set.clone());
+        }
+
+        let href = sub_request_and_extract_elem(&self.resource, DAVCLIENT_BODY.into(), &["current-user-principal", "href"]).await?;
+        let principal = self.resource.combine(&href);
+        let href = sub_request_and_extract_elem(&principal, ADDRESSBOOK_HOMESET_BODY.into(), &["addressbook-home-set", "href"]).await?;
+        let home_set = self.resource.combine(&href);
+        log::debug!("Address book home set URL is {:?}", home_set.url().path());
+
+        *self.home_set.lock().unwrap() = Some(home_set.clone());
+        Ok(home_set)
+    }
+
+    async fn fetch_address_books(&self) -> Result<HashMap<Url, Arc<Mutex<AddressBook>>>, KFError> {
+        let home_set = self.get_home_set().await?;
+        let reps = sub_request_and_extract_elems(&home_set, "PROPFIND", ADDRESSBOOKS_BODY.to_string(), "response").await?;
+
+        let mut calendars = HashMap::new();
+        for rep in reps {
+            // The home set itself, and the other collections it may contain, are not address books
+            let is_address_book = find_elem(&rep, "resourcetype")
+                .map(|types| types.children().any(|child| child.name() == "addressbook"))
+                .unwrap_or(false);
+            if !is_address_book {
+                continue;
+            }
+            let href = match find_elem(&rep, "href") {
+                None => continue,
+                Some(href) => href.text(),
+            };
+            let name = find_elem(&rep, "displayname").map(|e| e.text()).unwrap_or("<no name>".to_string());
+            let description = find_elem(&rep, "addressbook-description")
+                .map(|description| description.text())
+                .filter(|description| !description.is_empty());
+
+            let mut address_book = AddressBook::new(name, self.resource.combine(&href), SupportedComponents::CONTACT, None);
+            address_book.set_description(description);
+            log::info!("Found address book {}", address_book.name());
+            calendars.insert(address_book.url().clone(), Arc::new(Mutex::new(address_book)));
+        }
+        Ok(calendars)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<AddressBook> for AddressBooks {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<AddressBook>>>, KFError> {
+        // Like the calendars of a Client, address books are listed again at every sync, so that they do not keep outdated version tags
+        self.fetch_address_books().await
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<AddressBook>>> {
+        self.get_calendars().await.ok()?.get(url).cloned()
+    }
+
+    /// Create an address book (with an extended `MKCOL`, see [RFC 5689](https://datatracker.ietf.org/doc/html/rfc5689)). Address books have no color: `color` is ignored
+    async fn create_calendar(&mut self, url: Url, name: String, supported_components: SupportedComponents, _color: Option<Color>) -> Result<Arc<Mutex<AddressBook>>, KFError> {
+        if supported_components != SupportedComponents::CONTACT {
+            return Err(format!("Address book {} can only contain contacts", url).into());
+        }
+        if self.get_calendars().await?.contains_key(&url) {
+            return Err("This address book already exists".into());
+        }
+
+        let body = format!(r#"<?xml version="1.0" encoding="utf-8" ?>
+            <d:mkcol xmlns:d="DAV:" xmlns:card="urn:ietf:params:xml:ns:carddav">
+                <d:set>
+                    <d:prop>
+                        <d:resourcetype><d:collection/><card:addressbook/></d:resourcetype>
+                        <d:displayname>{}</d:displayname>
+                    </d:prop>
+                </d:set>
+            </d:mkcol>
+            "#, xml_escape(&name));
+        let request = self.resource.request(Method::from_bytes(b"MKCOL")?, url.clone())
+            .header(CONTENT_TYPE, "application/xml")
+            .body(body);
+        let response = self.resource.send(request).await?;
+        if response.status() != StatusCode::CREATED {
+            return Err(KFError::from_status(response.status(), url));
+        }
+
+        Ok(Arc::new(Mutex::new(AddressBook::new(name, self.resource.combine(url.path()), SupportedComponents::CONTACT, None))))
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<AddressBook>>, KFError> {
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        let home_set = self.get_home_set().await?;
+        let request = home_set.request(Method::OPTIONS, home_set.url().clone());
+        let response = home_set.send(request).await?;
+        if !response.status().is_success() {
+            return Err(KFError::from_status(response.status(), home_set.url().clone()));
+        }
+        let dav_header = response.headers().get_all("DAV").iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(ServerCapabilities::from_dav_header(&dav_header))
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        // Every change is sent to the server right away
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        // Every change is sent to the server right away
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+//! Custom headers added to outgoing requests
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use reqwest::Method;
+use reqwest::header::HeaderMap;
+use url::Url;
+
+/// A callback that is able to add (or override) headers of every outgoing request.
+///
+/// It is given the method and the URL of the request, which makes it possible to add headers only to some requests
+pub type HeaderHook = Arc<dyn Fn(&Method, &Url, &mut HeaderMap) + Send + Sync>;
+
+/// The header hooks that have been registered to a client
+#[derive(Clone, Default)]
+pub(crate) struct HeaderHooks {
+    hooks: Vec<HeaderHook>,
+}
+
+impl HeaderHooks {
+    pub(crate) fn push(&mut self, hook: HeaderHook) {
+        self.hooks.push(hook);
+    }
+
+    /// Compute the extra headers for a request
+    pub(crate) fn headers_for(&self, method: &Method, url: &Url) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for hook in &self.hooks {
+            hook(method, url, &mut headers);
+        }
+        headers
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
+
+impl Debug for HeaderHooks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{} header hooks>", self.hooks.len())
+    }
+}
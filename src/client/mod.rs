@@ -0,0 +1,614 @@
+//! This module provides a client to connect to a CalDAV server
+
+use std::convert::TryFrom;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use reqwest::{Method, StatusCode};
+use reqwest::header::CONTENT_TYPE;
+use minidom::Element;
+use url::Url;
+use csscolorparser::Color;
+use chrono::{DateTime, Utc};
+
+use crate::resource::Resource;
+use crate::utils::{find_elem, find_elems};
+use crate::calendar::remote_calendar::RemoteCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::calendar::sharing::{resolve_principal_href, Sharing};
+use crate::freebusy::FreeBusy;
+use crate::error::KFError;
+use crate::traits::CalDavSource;
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+
+pub mod builder;
+pub use builder::ClientBuilder;
+pub mod tls;
+pub use tls::{ClientIdentity, TlsSettings};
+pub(crate) mod transport;
+pub mod retry;
+pub use retry::RetryPolicy;
+pub mod rate_limit;
+pub use rate_limit::RateLimit;
+pub mod headers;
+pub use headers::HeaderHook;
+pub mod observer;
+pub use observer::{NetworkObserver, RequestInfo, ResponseInfo};
+pub mod pool;
+pub use pool::{HttpVersion, PoolSettings};
+pub mod redirect;
+pub use redirect::RedirectPolicy;
+pub mod capabilities;
+pub use capabilities::ServerCapabilities;
+pub mod carddav;
+pub use carddav::AddressBooks;
+#[cfg(feature = "push_notifications")]
+pub mod push;
+
+
+pub(crate) static DAVCLIENT_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:">
+       <d:prop>
+           <d:current-user-principal />
+       </d:prop>
+    </d:propfind>
+"#;
+
+static HOMESET_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav" >
+      <d:self/>
+      <d:prop>
+        <c:calendar-home-set />
+      </d:prop>
+    </d:propfind>
+"#;
+
+static DELEGATIONS_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:" xmlns:cs="http://calendarserver.org/ns/">
+       <d:prop>
+           <cs:calendar-proxy-read-for />
+           <cs:calendar-proxy-write-for />
+       </d:prop>
+    </d:propfind>
+"#;
+
+static QUOTA_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:">
+       <d:prop>
+           <d:quota-available-bytes />
+           <d:quota-used-bytes />
+       </d:prop>
+    </d:propfind>
+"#;
+
+static REPORTS_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:">
+       <d:prop>
+           <d:supported-report-set />
+       </d:prop>
+    </d:propfind>
+"#;
+
+static CAL_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav" >
+       <d:prop>
+         <d:displayname />
+         <E:calendar-color xmlns:E="http://apple.com/ns/ical/"/>
+         <E:calendar-order xmlns:E="http://apple.com/ns/ical/"/>
+         <c:calendar-description />
+         <c:calendar-timezone />
+         <c:default-alarm-vevent-datetime />
+         <c:default-alarm-vevent-date />
+         <c:default-alarm-vtodo-datetime />
+         <c:default-alarm-vtodo-date />
+         <d:resourcetype />
+         <c:supported-calendar-component-set />
+         <d:current-user-privilege-set />
+         <d:owner />
+         <d:share-access />
+         <d:invite />
+         <oc:invite xmlns:oc="http://owncloud.org/ns"/>
+         <oc:owner-principal xmlns:oc="http://owncloud.org/ns"/>
+         <nc:owner-displayname xmlns:nc="http://nextcloud.com/ns"/>
+         <cs:invite xmlns:cs="http://calendarserver.org/ns/"/>
+       </d:prop>
+    </d:propfind>
+"#;
+
+
+
+pub(crate) async fn sub_request(resource: &Resource, method: &str, body: String, depth: u32) -> Result<String, KFError> {
+    let method = method.parse()
+        .expect("invalid method name");
+
+    let request = resource.request(method, resource.url().clone())
+        .header("Depth", depth)
+        .header(CONTENT_TYPE, "application/xml")
+        .body(body);
+    let res = resource.send(request).await?;
+
+    if res.status().is_success() == false {
+        return Err(KFError::from_status(res.status(), resource.url().clone()));
+    }
+
+    let text = res.text().await?;
+    Ok(text)
+}
+
+pub(crate) async fn sub_request_and_extract_elem(resource: &Resource, body: String, items: &[&str]) -> Result<String, KFError> {
+    let text = sub_request(resource, "PROPFIND", body, 0).await?;
+
+    let mut current_element: &Element = &text.parse()?;
+    for item in items {
+        current_element = match find_elem(&current_element, item) {
+            Some(elem) => elem,
+            None => return Err(format!("missing element {}", item).into()),
+        }
+    }
+    Ok(current_element.text())
+}
+
+pub(crate) async fn sub_request_and_extract_elems(resource: &Resource, method: &str, body: String, item: &str) -> Result<Vec<Element>, KFError> {
+    let text = sub_request(resource, method, body, 1).await?;
+
+    let element: &Element = &text.parse()?;
+    Ok(find_elems(&element, item)
+        .iter()
+        .map(|elem| (*elem).clone())
+        .collect()
+    )
+}
+
+
+/// A CalDAV data source that fetches its data from a CalDAV server
+#[derive(Debug)]
+pub struct Client {
+    resource: Resource,
+
+    /// The interior mutable part of a Client.
+    /// This data may be retrieved once and then cached
+    cached_replies: Mutex<CachedReplies>,
+
+    /// Principals (e.g. delegated mailboxes or group calendars) whose calendars are listed as well as the current user's
+    other_principals: Vec<Url>,
+    /// Calendar home sets whose calendars are listed as well as the current user's
+    other_calendar_home_sets: Vec<Url>,
+}
+
+
+/// The storage space of a collection, as reported by the server
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quota {
+    /// The remaining space (in bytes), or `None` if the server does not tell
+    pub available_bytes: Option<u64>,
+    /// The used space (in bytes), or `None` if the server does not tell
+    pub used_bytes: Option<u64>,
+}
+
+impl Quota {
+    /// Returns whether the server reports there is no space left
+    pub fn is_full(&self) -> bool {
+        self.available_bytes == Some(0)
+    }
+}
+
+
+#[derive(Debug, Default)]
+struct CachedReplies {
+    principal: Option<Resource>,
+    calendar_home_set: Option<Resource>,
+    calendars: Option<HashMap<Url, Arc<Mutex<RemoteCalendar>>>>,
+    capabilities: Option<ServerCapabilities>,
+}
+
+impl Client {
+    /// Create a client with the default settings. This does not start a connection
+    ///
+    /// See [`Client::builder`] to customize its network settings
+    pub fn new<S: AsRef<str>, T: ToString, U: ToString>(url: S, username: T, password: U) -> Result<Self, KFError> {
+        Self::builder(url, username, password).build()
+    }
+
+    /// Start building a client with custom network settings
+    pub fn builder<S: AsRef<str>, T: ToString, U: ToString>(url: S, username: T, password: U) -> ClientBuilder {
+        ClientBuilder::new(url, username, password)
+    }
+
+    pub(crate) fn new_from_resource(resource: Resource) -> Self {
+        Self{
+            resource,
+            cached_replies: Mutex::new(CachedReplies::default()),
+            other_principals: Vec::new(),
+            other_calendar_home_sets: Vec::new(),
+        }
+    }
+
+    /// Also list the calendars of another principal (on the same server), e.g. a delegated mailbox or a group calendar.
+    ///
+    /// See also [`Client::delegated_principals`]
+    pub fn add_principal(&mut self, principal_url: Url) {
+        self.other_principals.push(principal_url);
+    }
+
+    /// Also list the calendars of another calendar home set (on the same server)
+    pub fn add_calendar_home_set(&mut self, home_set_url: Url) {
+        self.other_calendar_home_sets.push(home_set_url);
+    }
+
+    /// Ask the server which principals have delegated the access to their calendars to the current user.
+    ///
+    /// This uses the `calendar-proxy-read-for` and `calendar-proxy-write-for` properties, that are supported by most servers (e.g. Nextcloud, SOGo, DAViCal)
+    pub async fn delegated_principals(&self) -> Result<Vec<Url>, KFError> {
+        let principal = self.get_principal().await?;
+        let text = sub_request(&principal, "PROPFIND", DELEGATIONS_BODY.to_string(), 0).await?;
+        let root: Element = text.parse()?;
+
+        let mut principals = Vec::new();
+        for prop_name in &["calendar-proxy-read-for", "calendar-proxy-write-for"] {
+            if let Some(prop) = find_elem(&root, prop_name) {
+                for href in find_elems(prop, "href") {
+                    let url = self.resource.combine(&href.text()).url().clone();
+                    if !principals.contains(&url) {
+                        principals.push(url);
+                    }
+                }
+            }
+        }
+        Ok(principals)
+    }
+
+    /// Return the Principal URL, or fetch it from server if not known yet
+    async fn get_principal(&self) -> Result<Resource, KFError> {
+        if let Some(p) = &self.cached_replies.lock().unwrap().principal {
+            return Ok(p.clone());
+        }
+
+        let href = sub_request_and_extract_elem(&self.resource, DAVCLIENT_BODY.into(), &["current-user-principal", "href"]).await?;
+        let principal_url = self.resource.combine(&href);
+        self.cached_replies.lock().unwrap().principal = Some(principal_url.clone());
+        log::debug!("Principal URL is {}", href);
+
+        return Ok(principal_url);
+    }
+
+    /// Return the Homeset URL, or fetch it from server if not known yet
+    async fn get_cal_home_set(&self) -> Result<Resource, KFError> {
+        if let Some(h) = &self.cached_replies.lock().unwrap().calendar_home_set {
+            return Ok(h.clone());
+        }
+        let principal_url = self.get_principal().await?;
+
+        let chs_url = self.fetch_cal_home_set(&principal_url).await?;
+        self.cached_replies.lock().unwrap().calendar_home_set = Some(chs_url.clone());
+        log::debug!("Calendar home set URL is {:?}", chs_url.url().path());
+
+        Ok(chs_url)
+    }
+
+    /// Fetch the Homeset URL of a principal
+    async fn fetch_cal_home_set(&self, principal: &Resource) -> Result<Resource, KFError> {
+        let href = sub_request_and_extract_elem(principal, HOMESET_BODY.into(), &["calendar-home-set", "href"]).await?;
+        Ok(self.resource.combine(&href))
+    }
+
+    /// Return the features the server supports, or fetch them if not known yet
+    ///
+    /// The `DAV` header is read from an `OPTIONS` request to the calendar home set, and the supported REPORTs are read from one of the calendars
+    pub async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        if let Some(caps) = &self.cached_replies.lock().unwrap().capabilities {
+            return Ok(caps.clone());
+        }
+
+        let cal_home_set = self.get_cal_home_set().await?;
+        let request = cal_home_set.request(Method::OPTIONS, cal_home_set.url().clone());
+        let response = cal_home_set.send(request).await?;
+        if !response.status().is_success() {
+            return Err(KFError::from_status(response.status(), cal_home_set.url().clone()));
+        }
+        let dav_header = response.headers().get_all("DAV").iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut caps = ServerCapabilities::from_dav_header(&dav_header);
+
+        // Supported REPORTs depend on the kind of collection. Calendars are the ones we care about
+        let some_calendar = self.get_calendars().await?.keys().next()
+            .map(|url| self.resource.combine(url.path()))
+            .unwrap_or(cal_home_set);
+        match sub_request(&some_calendar, "PROPFIND", REPORTS_BODY.to_string(), 0).await {
+            Err(err) => log::warn!("Unable to get the supported REPORTs: {}", err),
+            Ok(text) => {
+                let root: Element = text.parse()?;
+                if let Some(report_set) = find_elem(&root, "supported-report-set") {
+                    caps.set_supported_reports(report_set);
+                }
+            },
+        }
+
+        log::debug!("Server capabilities are {:?}", caps);
+        self.cached_replies.lock().unwrap().capabilities = Some(caps.clone());
+        Ok(caps)
+    }
+
+    /// Ask the server for the free/busy periods of a calendar between `start` and `end` (using a CalDAV `free-busy-query` REPORT)
+    ///
+    /// This does not require the calendar items to be synced (nor even to be readable)
+    pub async fn free_busy(&self, calendar_url: &Url, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<FreeBusy, KFError> {
+        let body = format!(r#"
+            <c:free-busy-query xmlns:c="urn:ietf:params:xml:ns:caldav">
+                <c:time-range start="{}" end="{}"/>
+            </c:free-busy-query>
+            "#,
+            start.format("%Y%m%dT%H%M%SZ"),
+            end.format("%Y%m%dT%H%M%SZ"),
+        );
+
+        let calendar = self.resource.combine(calendar_url.path());
+        let text = sub_request(&calendar, "REPORT", body, 1).await?;
+        crate::ical::parse_free_busy(&text)
+    }
+
+    /// Ask the server how much storage space is used and available for a calendar (see [RFC 4331](https://datatracker.ietf.org/doc/html/rfc4331))
+    pub async fn get_quota(&self, calendar_url: &Url) -> Result<Quota, KFError> {
+        let calendar = self.resource.combine(calendar_url.path());
+        let text = sub_request(&calendar, "PROPFIND", QUOTA_BODY.to_string(), 0).await?;
+        let root: Element = text.parse()?;
+
+        let parse_bytes = |name: &str| {
+            find_elem(&root, name)
+                .map(|elem| elem.text())
+                .and_then(|text| text.trim().parse::<u64>().ok())
+        };
+
+        Ok(Quota {
+            available_bytes: parse_bytes("quota-available-bytes"),
+            used_bytes: parse_bytes("quota-used-bytes"),
+        })
+    }
+
+    async fn populate_calendars(&self) -> Result<(), KFError> {
+        let cal_home_set = self.get_cal_home_set().await?;
+        let principal = self.get_principal().await?;
+
+        let mut calendars = HashMap::new();
+        self.list_calendars(&cal_home_set, Some(principal.url()), &mut calendars).await?;
+
+        for other_principal in &self.other_principals {
+            let other_principal = self.resource.combine(other_principal.path());
+            let home_set = match self.fetch_cal_home_set(&other_principal).await {
+                Err(err) => {
+                    log::warn!("Unable to get the calendar home set of principal {}: {}", other_principal.url(), err);
+                    continue;
+                },
+                Ok(home_set) => home_set,
+            };
+            if let Err(err) = self.list_calendars(&home_set, Some(other_principal.url()), &mut calendars).await {
+                log::warn!("Unable to list the calendars of principal {}: {}", other_principal.url(), err);
+            }
+        }
+        for other_home_set in &self.other_calendar_home_sets {
+            let other_home_set = self.resource.combine(other_home_set.path());
+            if let Err(err) = self.list_calendars(&other_home_set, None, &mut calendars).await {
+                log::warn!("Unable to list the calendars of {}: {}", other_home_set.url(), err);
+            }
+        }
+
+        let mut replies = self.cached_replies.lock().unwrap();
+        replies.calendars = Some(calendars);
+        Ok(())
+    }
+
+    /// List the calendars of a calendar home set, that belong to `default_owner` unless the server tells otherwise
+    async fn list_calendars(&self, cal_home_set: &Resource, default_owner: Option<&Url>, calendars: &mut HashMap<Url, Arc<Mutex<RemoteCalendar>>>) -> Result<(), KFError> {
+        let reps = sub_request_and_extract_elems(cal_home_set, "PROPFIND", CAL_BODY.to_string(), "response").await?;
+        let current_principal = self.cached_replies.lock().unwrap().principal.as_ref().map(|principal| principal.url().clone());
+        for rep in reps {
+            let display_name = find_elem(&rep, "displayname").map(|e| e.text()).unwrap_or("<no name>".to_string());
+            log::debug!("Considering calendar {}", display_name);
+
+            // We filter out non-calendar items
+            let resource_types = match find_elem(&rep, "resourcetype") {
+                None => continue,
+                Some(rt) => rt,
+            };
+            let mut found_calendar_type = false;
+            for resource_type in resource_types.children() {
+                if resource_type.name() == "calendar" {
+                    found_calendar_type = true;
+                    break;
+                }
+            }
+            if found_calendar_type == false {
+                continue;
+            }
+
+            // We filter out the root calendar collection, that has an empty supported-calendar-component-set
+            let el_supported_comps = match find_elem(&rep, "supported-calendar-component-set") {
+                None => continue,
+                Some(comps) => comps,
+            };
+            if el_supported_comps.children().count() == 0 {
+                continue;
+            }
+
+            let calendar_href = match find_elem(&rep, "href") {
+                None => {
+                    log::warn!("Calendar {} has no URL! Ignoring it.", display_name);
+                    continue;
+                },
+                Some(h) => h.text(),
+            };
+
+            let this_calendar_url = self.resource.combine(&calendar_href);
+
+            let supported_components = match crate::calendar::SupportedComponents::try_from(el_supported_comps.clone()) {
+                Err(err) => {
+                    log::warn!("Calendar {} has invalid supported components ({})! Ignoring it.", display_name, err);
+                    continue;
+                },
+                Ok(sc) => sc,
+            };
+
+            let this_calendar_color = find_elem(&rep, "calendar-color")
+                .and_then(|col| {
+                    col.texts().next()
+                        .and_then(|t| csscolorparser::parse(t).ok())
+                });
+
+            // Servers that do not support ACLs return an empty (404) property. In this case, we assume we can do anything
+            let this_calendar_privileges = find_elem(&rep, "current-user-privilege-set")
+                .and_then(|el| crate::calendar::Privileges::try_from(el.clone()).ok())
+                .filter(|privileges| !privileges.is_empty())
+                .unwrap_or_default();
+
+            let this_calendar_owner = find_elem(&rep, "owner")
+                .and_then(|owner| find_elem(owner, "href"))
+                .map(|href| self.resource.combine(&href.text()).url().clone())
+                .or_else(|| find_elem(&rep, "owner-principal")
+                    .and_then(|owner| resolve_principal_href(&owner.text(), current_principal.as_ref())))
+                .or_else(|| default_owner.cloned());
+            let this_calendar_sharing = Sharing::from_xml(&rep, this_calendar_owner.as_ref(), current_principal.as_ref());
+
+            let this_calendar_order = find_elem(&rep, "calendar-order")
+                .and_then(|order| order.text().trim().parse().ok());
+
+            let this_calendar_description = find_elem(&rep, "calendar-description")
+                .map(|description| description.text())
+                .filter(|description| !description.is_empty());
+
+            let this_calendar_timezone = find_elem(&rep, "calendar-timezone")
+                .map(|timezone| timezone.text())
+                .filter(|timezone| !timezone.trim().is_empty())
+                .and_then(|timezone| match CalendarTimezone::from_ical(&timezone) {
+                    Ok(timezone) => Some(timezone),
+                    Err(err) => {
+                        log::warn!("Ignoring the invalid time zone of calendar {}: {}", this_calendar_url.url(), err);
+                        None
+                    },
+                });
+
+            let this_calendar_default_alarms = DefaultAlarms::from_xml(&rep, this_calendar_url.url());
+
+            let mut this_calendar = RemoteCalendar::new(display_name, this_calendar_url, supported_components, this_calendar_color);
+            this_calendar.set_order(this_calendar_order);
+            this_calendar.set_description(this_calendar_description);
+            this_calendar.set_timezone(this_calendar_timezone);
+            this_calendar.set_default_alarms(this_calendar_default_alarms);
+            this_calendar.set_privileges(this_calendar_privileges);
+            this_calendar.set_owner(this_calendar_owner);
+            this_calendar.set_sharing(Some(this_calendar_sharing));
+            log::info!("Found calendar {}", this_calendar.name());
+            calendars.insert(this_calendar.url().clone(), Arc::new(Mutex::new(this_calendar)));
+        }
+
+        Ok(())
+    }
+
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<RemoteCalendar> for Client {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<RemoteCalendar>>>, KFError> {
+        self.populate_calendars().await?;
+
+        match &self.cached_replies.lock().unwrap().calendars {
+            Some(cals) => {
+                return Ok(cals.clone())
+            },
+            None => return Err("No calendars available".into())
+        };
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<RemoteCalendar>>> {
+        if let Err(err) = self.populate_calendars().await {
+            log::warn!("Unable to fetch calendars: {}", err);
+            return None;
+        }
+
+        self.cached_replies.lock().unwrap()
+            .calendars
+            .as_ref()
+            .and_then(|cals| cals.get(url))
+            .map(|cal| cal.clone())
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        Client::server_capabilities(self).await
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        // Every change is sent to the server right away
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        // Every change is sent to the server right away
+        Ok(())
+    }
+
+    async fn create_calendar(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>) -> Result<Arc<Mutex<RemoteCalendar>>, KFError> {
+        self.create_calendar_with_timezone(url, name, supported_components, color, None).await
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<RemoteCalendar>>, KFError> {
+        self.populate_calendars().await?;
+
+        match self.cached_replies.lock().unwrap().calendars.as_ref() {
+            None => return Err("No calendars have been fetched".into()),
+            Some(cals) => {
+                if cals.contains_key(&url) {
+                    return Err("This calendar already exists".into());
+                }
+            },
+        }
+
+        let creation_body = calendar_body(name, supported_components, color, timezone);
+
+        let request = self.resource.request(Method::from_bytes(b"MKCALENDAR").unwrap(), url.clone())
+            .header(CONTENT_TYPE, "application/xml")
+            .body(creation_body);
+        let response = self.resource.send(request).await?;
+
+        let status = response.status();
+        if status != StatusCode::CREATED {
+            return Err(KFError::from_status(status, url.clone()));
+        }
+
+        self.get_calendar(&url).await.ok_or(format!("Unable to insert calendar {:?}", url).into())
+    }
+}
+
+fn calendar_body(name: String, supported_components: SupportedComponents, color: Option<Color>, timezone: Option<CalendarTimezone>) -> String {
+    let color_property = match color {
+        None => "".to_string(),
+        Some(color) => format!("<D:calendar-color xmlns:D=\"http://apple.com/ns/ical/\">{}</D:calendar-color>", crate::calendar::dav_color(&color)),
+    };
+    let timezone_property = match timezone {
+        None => "".to_string(),
+        Some(timezone) => format!("<B:calendar-timezone>{}</B:calendar-timezone>", crate::utils::xml_escape(timezone.ical())),
+    };
+
+    // This is taken from https://tools.ietf.org/html/rfc4791#page-24
+    format!(r#"<?xml version="1.0" encoding="utf-8" ?>
+        <B:mkcalendar xmlns:B="urn:ietf:params:xml:ns:caldav">
+            <A:set xmlns:A="DAV:">
+                <A:prop>
+                    <A:displayname>{}</A:displayname>
+                    {}
+                    {}
+                    {}
+                </A:prop>
+            </A:set>
+        </B:mkcalendar>
+        "#,
+        crate::utils::xml_escape(&name),
+        color_property,
+        timezone_property,
+        supported_components.to_xml_string(),
+    )
+}
@@ -0,0 +1,130 @@
+//! Observation of the network activity of a client
+//!
+//! This can be used to display what the client is doing, or to produce diagnostic logs when a server behaves unexpectedly.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE};
+use url::Url;
+
+/// A request that is about to be sent
+#[derive(Clone, Debug)]
+pub struct RequestInfo {
+    /// An identifier, that is shared with the matching [`ResponseInfo`]
+    pub id: u64,
+    pub method: Method,
+    pub url: Url,
+    /// The request headers, with credentials redacted
+    pub headers: HeaderMap,
+    /// The request body, if the observer wants bodies (see [`NetworkObserver::wants_bodies`])
+    pub body: Option<String>,
+}
+
+/// A response that has been received
+#[derive(Clone, Debug)]
+pub struct ResponseInfo {
+    /// The identifier of the matching [`RequestInfo`]
+    pub id: u64,
+    pub method: Method,
+    pub url: Url,
+    pub status: StatusCode,
+    /// The response headers, with cookies redacted
+    pub headers: HeaderMap,
+    /// The response body, if the observer wants bodies (see [`NetworkObserver::wants_bodies`])
+    pub body: Option<String>,
+    /// How long it took to get the response
+    pub elapsed: Duration,
+}
+
+/// Something that is notified of every request a [`Client`](crate::client::Client) (and its calendars) sends, see [`ClientBuilder::observer`](crate::client::ClientBuilder::observer).
+///
+/// Retries and redirections are reported as distinct requests
+pub trait NetworkObserver: Send + Sync {
+    /// Called before a request is sent
+    fn on_request(&self, _request: &RequestInfo) {}
+
+    /// Called when a response has been received
+    fn on_response(&self, _response: &ResponseInfo) {}
+
+    /// Whether request and response bodies should be captured.
+    ///
+    /// This has a cost, since responses have to be completely read before being handed to the caller. Defaults to `false`
+    fn wants_bodies(&self) -> bool {
+        false
+    }
+}
+
+/// The observers that have been registered to a client
+#[derive(Clone, Default)]
+pub(crate) struct Observers {
+    observers: Vec<Arc<dyn NetworkObserver>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Observers {
+    pub(crate) fn push(&mut self, observer: Arc<dyn NetworkObserver>) {
+        self.observers.push(observer);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    pub(crate) fn want_bodies(&self) -> bool {
+        self.observers.iter().any(|obs| obs.wants_bodies())
+    }
+
+    pub(crate) fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub(crate) fn on_request(&self, request: &RequestInfo) {
+        for observer in &self.observers {
+            observer.on_request(request);
+        }
+    }
+
+    pub(crate) fn on_response(&self, response: &ResponseInfo) {
+        for observer in &self.observers {
+            observer.on_response(response);
+        }
+    }
+}
+
+impl std::fmt::Debug for Observers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{} network observers>", self.observers.len())
+    }
+}
+
+/// Copy headers, hiding the ones that contain credentials
+pub(crate) fn redact(headers: &HeaderMap) -> HeaderMap {
+    let mut redacted = headers.clone();
+    for name in [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, SET_COOKIE] {
+        if redacted.contains_key(&name) {
+            redacted.insert(name, HeaderValue::from_static("<redacted>"));
+        }
+    }
+    redacted
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redaction() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic am9objpzZWNyZXQ="));
+        headers.insert("Depth", HeaderValue::from_static("1"));
+
+        let redacted = redact(&headers);
+        assert_eq!(redacted.get(AUTHORIZATION).unwrap(), "<redacted>");
+        assert_eq!(redacted.get("Depth").unwrap(), "1");
+        assert!(redacted.get(COOKIE).is_none());
+    }
+}
@@ -0,0 +1,56 @@
+//! Connection reuse settings
+//!
+//! Syncing thousands of items means thousands of requests to the same server. Keeping connections open (and multiplexing them with HTTP/2) avoids a TCP and TLS handshake for each of them.
+
+use std::time::Duration;
+
+/// Which HTTP versions can be used to talk to the server
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// Negotiate the version with the server (HTTP/2 is used if the server offers it during the TLS handshake)
+    Auto,
+    /// Only use HTTP/1.1, for servers or proxies that mishandle HTTP/2
+    Http1Only,
+    /// Use HTTP/2 without negotiating it first. Only use this if you know the server supports it (e.g. on plain `http` connections to a local server)
+    Http2PriorKnowledge,
+}
+
+/// How connections to the server are kept and reused
+#[derive(Clone, Debug)]
+pub struct PoolSettings {
+    /// Maximum number of idle connections kept open to the server
+    pub max_idle_per_host: usize,
+    /// How long idle connections are kept open (`None` to keep them forever)
+    pub idle_timeout: Option<Duration>,
+    /// Interval of TCP keep-alive probes (`None` to disable them)
+    pub tcp_keepalive: Option<Duration>,
+    /// Which HTTP versions can be used
+    pub http_version: HttpVersion,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: usize::MAX,
+            idle_timeout: Some(Duration::from_secs(90)),
+            tcp_keepalive: None,
+            http_version: HttpVersion::Auto,
+        }
+    }
+}
+
+impl PoolSettings {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn apply_to(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        builder = builder
+            .pool_max_idle_per_host(self.max_idle_per_host)
+            .pool_idle_timeout(self.idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive);
+
+        match self.http_version {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1Only => builder.http1_only(),
+            HttpVersion::Http2PriorKnowledge => builder.http2_prior_knowledge(),
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Push notifications of calendar changes
+//!
+//! This implements the client side of [WebDAV Push](https://github.com/bitfireAT/webdav-push/), that Nextcloud also supports.
+//! Servers that support it expose a `topic` for every calendar. Applications subscribe to a calendar by giving the server a Web Push resource they own,
+//! and then forward the messages they receive on that resource to a [`PushListener`], that turns them into "calendar X changed" events.
+//! This makes it possible to trigger a sync only when (and where) something has changed, instead of polling the server.
+//!
+//! This module is only available with the `push_notifications` feature.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use chrono::{DateTime, Utc};
+use minidom::Element;
+use reqwest::{Method, StatusCode};
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use url::Url;
+
+use crate::client::Client;
+use crate::error::KFError;
+use crate::utils::find_elem;
+
+static TOPIC_BODY: &str = r#"
+    <d:propfind xmlns:d="DAV:" xmlns:p="https://bitfire.at/webdav-push">
+       <d:prop>
+           <p:topic />
+           <p:push-transports />
+       </d:prop>
+    </d:propfind>
+"#;
+
+/// A subscription to the push notifications of a calendar
+#[derive(Clone, Debug, PartialEq)]
+pub struct PushSubscription {
+    /// The calendar this subscription is about
+    pub calendar_url: Url,
+    /// The URL of the subscription on the server, that is used to unsubscribe
+    pub registration_url: Url,
+    /// When the server will drop this subscription, if it told us
+    pub expires: Option<DateTime<Utc>>,
+}
+
+/// An event produced by a [`PushListener`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum PushEvent {
+    /// Something has changed in this calendar, so it should be synced
+    CalendarChanged(Url),
+}
+
+/// Turns the push messages an application receives into [`PushEvent`]s
+#[derive(Debug)]
+pub struct PushListener {
+    topics: HashMap<String, Url>,
+    sender: UnboundedSender<PushEvent>,
+}
+
+impl PushListener {
+    /// Create a listener, and the stream of events it will produce
+    pub fn new() -> (Self, UnboundedReceiver<PushEvent>) {
+        let (sender, receiver) = unbounded_channel();
+        let listener = Self {
+            topics: HashMap::new(),
+            sender,
+        };
+        (listener, receiver)
+    }
+
+    /// Start listening to the changes of a calendar (see [`Client::push_topic`] to get its topic)
+    pub fn watch(&mut self, topic: String, calendar_url: Url) {
+        self.topics.insert(topic, calendar_url);
+    }
+
+    /// Stop listening to the changes of a calendar
+    pub fn unwatch(&mut self, calendar_url: &Url) {
+        self.topics.retain(|_, url| url != calendar_url);
+    }
+
+    /// Handle the body of a push message the application has received.
+    ///
+    /// This returns the URL of the changed calendar (if it is watched), and sends the matching event to the stream
+    pub fn handle_message(&self, body: &str) -> Result<Option<Url>, Box<dyn Error>> {
+        let root: Element = body.parse()?;
+        if root.name() != "push-message" {
+            return Err(format!("Unexpected push message <{}>", root.name()).into());
+        }
+        let topic = find_elem(&root, "topic").ok_or("Missing topic in push message")?.text();
+
+        match self.topics.get(topic.trim()) {
+            None => {
+                log::debug!("Ignoring push message for unknown topic {}", topic);
+                Ok(None)
+            },
+            Some(calendar_url) => {
+                log::debug!("Calendar {} has changed", calendar_url);
+                // The receiver may have been dropped, this is not our problem
+                let _ = self.sender.send(PushEvent::CalendarChanged(calendar_url.clone()));
+                Ok(Some(calendar_url.clone()))
+            },
+        }
+    }
+}
+
+impl Client {
+    /// Return the push topic of a calendar, or `None` if the server does not support push notifications for this calendar
+    pub async fn push_topic(&self, calendar_url: &Url) -> Result<Option<String>, Box<dyn Error>> {
+        let calendar = self.resource.combine(calendar_url.path());
+        let text = crate::client::sub_request(&calendar, "PROPFIND", TOPIC_BODY.to_string(), 0).await?;
+        let root: Element = text.parse()?;
+
+        let supports_web_push = find_elem(&root, "push-transports")
+            .map(|transports| find_elem(transports, "web-push").is_some())
+            .unwrap_or(false);
+        if !supports_web_push {
+            return Ok(None);
+        }
+
+        Ok(find_elem(&root, "topic")
+            .map(|topic| topic.text().trim().to_string())
+            .filter(|topic| !topic.is_empty()))
+    }
+
+    /// Ask the server to send a message to `push_resource` (a Web Push endpoint owned by the application) whenever the calendar changes
+    pub async fn push_subscribe(&self, calendar_url: &Url, push_resource: &Url, expires: Option<DateTime<Utc>>) -> Result<PushSubscription, Box<dyn Error>> {
+        let expires_xml = expires
+            .map(|date| format!("<p:expires>{}</p:expires>", date.format("%a, %d %b %Y %H:%M:%S GMT")))
+            .unwrap_or_default();
+        let body = format!(r#"
+            <p:push-register xmlns:p="https://bitfire.at/webdav-push">
+                <p:subscription>
+                    <p:web-push-subscription>
+                        <p:push-resource>{}</p:push-resource>
+                    </p:web-push-subscription>
+                </p:subscription>
+                {}
+            </p:push-register>
+            "#, push_resource, expires_xml);
+
+        let request = self.resource.request(Method::POST, calendar_url.clone())
+            .header(CONTENT_TYPE, "application/xml")
+            .body(body);
+        let response = self.resource.send(request).await?;
+
+        match response.status() {
+            StatusCode::CREATED | StatusCode::NO_CONTENT => (),
+            status => return Err(KFError::from_status(status, calendar_url.clone()).into()),
+        }
+
+        let location = response.headers().get(LOCATION)
+            .ok_or("The server did not return the URL of the push subscription")?
+            .to_str()?;
+        let registration_url = calendar_url.join(location)?;
+
+        let expires = response.headers().get("Expires")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .map(|date| date.with_timezone(&Utc))
+            .or(expires);
+
+        Ok(PushSubscription {
+            calendar_url: calendar_url.clone(),
+            registration_url,
+            expires,
+        })
+    }
+
+    /// Cancel a subscription made with [`Client::push_subscribe`]
+    pub async fn push_unsubscribe(&self, subscription: &PushSubscription) -> Result<(), Box<dyn Error>> {
+        let request = self.resource.request(Method::DELETE, subscription.registration_url.clone());
+        let response = self.resource.send(request).await?;
+
+        if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+            return Err(KFError::from_status(response.status(), subscription.registration_url.clone()).into());
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_messages() {
+        let calendar_url: Url = "https://my.server.com/calendars/john/work/".parse().unwrap();
+        let (mut listener, mut events) = PushListener::new();
+        listener.watch("O7M1nQ7cKkKTKsoS_j6Z3w".to_string(), calendar_url.clone());
+
+        let message = r#"<P:push-message xmlns:D="DAV:" xmlns:P="https://bitfire.at/webdav-push">
+                <P:topic>O7M1nQ7cKkKTKsoS_j6Z3w</P:topic>
+            </P:push-message>"#;
+        assert_eq!(listener.handle_message(message).unwrap(), Some(calendar_url.clone()));
+        assert_eq!(events.try_recv().unwrap(), PushEvent::CalendarChanged(calendar_url.clone()));
+
+        let unknown = r#"<P:push-message xmlns:P="https://bitfire.at/webdav-push"><P:topic>other</P:topic></P:push-message>"#;
+        assert_eq!(listener.handle_message(unknown).unwrap(), None);
+        assert!(events.try_recv().is_err());
+
+        listener.unwatch(&calendar_url);
+        assert_eq!(listener.handle_message(message).unwrap(), None);
+        assert!(listener.handle_message("<not-a-push-message/>").is_err());
+    }
+}
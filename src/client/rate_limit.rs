@@ -0,0 +1,122 @@
+//! Client-side throttling of requests
+//!
+//! Some providers (e.g. Google or iCloud) temporarily block clients that send too many requests, which can easily happen during large initial syncs.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::time::Instant;
+
+/// Limits how many requests a [`Client`](crate::client::Client) (and its calendars) can send
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    /// Maximum sustained number of requests per second
+    pub requests_per_second: f64,
+    /// How many requests can be sent at once after an idle period, before `requests_per_second` applies
+    pub burst: u32,
+    /// Maximum number of requests that can be in flight at the same time (`None` for no limit)
+    pub max_concurrent_requests: Option<usize>,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 10.0,
+            burst: 10,
+            max_concurrent_requests: Some(4),
+        }
+    }
+}
+
+
+/// A token bucket, that enforces a [`RateLimit`]
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+    concurrency: Option<Semaphore>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        let bucket = Bucket { tokens: f64::from(limit.burst), last_refill: Instant::now() };
+        let concurrency = limit.max_concurrent_requests.map(|n| Semaphore::new(n.max(1)));
+        Self { limit, bucket: Mutex::new(bucket), concurrency }
+    }
+
+    /// Wait until a request can be sent.
+    ///
+    /// The returned permit (if any) must be kept as long as the request is in flight
+    pub(crate) async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let permit = match &self.concurrency {
+            None => None,
+            Some(semaphore) => semaphore.acquire().await.ok(),
+        };
+
+        loop {
+            let wait = self.bucket.lock().unwrap().take_token(&self.limit, Instant::now());
+            match wait {
+                None => break,
+                Some(delay) => {
+                    log::debug!("Rate limit reached, waiting for {:?}", delay);
+                    crate::time::sleep(delay).await;
+                },
+            }
+        }
+
+        permit
+    }
+}
+
+impl Bucket {
+    /// Consume a token if there is one, or return how long to wait for the next one
+    fn take_token(&mut self, limit: &RateLimit, now: Instant) -> Option<Duration> {
+        let rate = limit.requests_per_second.max(f64::MIN_POSITIVE);
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(limit.burst.max(1)));
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limit = RateLimit { requests_per_second: 2.0, burst: 3, max_concurrent_requests: None };
+        let start = Instant::now();
+        let mut bucket = Bucket { tokens: 3.0, last_refill: start };
+
+        // The burst is available at once...
+        assert_eq!(bucket.take_token(&limit, start), None);
+        assert_eq!(bucket.take_token(&limit, start), None);
+        assert_eq!(bucket.take_token(&limit, start), None);
+        // ...then we have to wait for a refill
+        assert_eq!(bucket.take_token(&limit, start), Some(Duration::from_millis(500)));
+        assert_eq!(bucket.take_token(&limit, start + Duration::from_millis(500)), None);
+
+        // An idle bucket never holds more than the burst
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(bucket.take_token(&limit, later), None);
+        }
+        assert!(bucket.take_token(&limit, later).is_some());
+    }
+}
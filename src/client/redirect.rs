@@ -0,0 +1,94 @@
+//! How HTTP redirections are followed
+//!
+//! Redirections are not handled by `reqwest` itself, because it would turn a redirected `PROPFIND` or `REPORT` into a `GET`, and would silently drop the credentials when changing hosts.
+
+use url::Url;
+
+/// Tells which redirections a [`Client`](crate::client::Client) follows
+#[derive(Clone, Debug)]
+pub struct RedirectPolicy {
+    /// Maximum number of redirections followed for a single request. `0` means "never follow redirections"
+    pub max_redirects: usize,
+    /// Whether redirections from `http` to `https` on the same host are followed
+    pub allow_https_upgrade: bool,
+    /// Whether redirections to another host (or port, or scheme) are followed
+    pub allow_cross_origin: bool,
+    /// Whether the credentials are sent to the target of a cross-origin redirection. This only makes sense when `allow_cross_origin` is set
+    pub send_credentials_cross_origin: bool,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self {
+            max_redirects: 10,
+            allow_https_upgrade: true,
+            allow_cross_origin: false,
+            send_credentials_cross_origin: false,
+        }
+    }
+}
+
+impl RedirectPolicy {
+    /// A policy that never follows any redirection
+    pub fn none() -> Self {
+        Self {
+            max_redirects: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Whether the redirection from `from` to `to` may be followed
+    pub(crate) fn allows(&self, from: &Url, to: &Url) -> bool {
+        if from.scheme() == "https" && to.scheme() != "https" {
+            // Never downgrade to an insecure connection
+            return false;
+        }
+        if is_same_origin(from, to) || (self.allow_https_upgrade && is_https_upgrade(from, to)) {
+            return true;
+        }
+        self.allow_cross_origin
+    }
+
+    /// Whether the credentials can be sent when following the redirection from `from` to `to`
+    pub(crate) fn keeps_credentials(&self, from: &Url, to: &Url) -> bool {
+        is_same_origin(from, to)
+            || is_https_upgrade(from, to)
+            || self.send_credentials_cross_origin
+    }
+}
+
+fn is_same_origin(a: &Url, b: &Url) -> bool {
+    a.origin() == b.origin()
+}
+
+fn is_https_upgrade(from: &Url, to: &Url) -> bool {
+    from.scheme() == "http"
+        && to.scheme() == "https"
+        && from.host() == to.host()
+        && (from.port().is_none() || from.port() == Some(80))
+        && (to.port().is_none() || to.port() == Some(443))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redirect_policy() {
+        let url = |s: &str| Url::parse(s).unwrap();
+        let policy = RedirectPolicy::default();
+
+        assert!(policy.allows(&url("https://a.com/dav/"), &url("https://a.com/remote.php/dav/")));
+        assert!(policy.allows(&url("http://a.com/dav/"), &url("https://a.com/dav/")));
+        assert!(!policy.allows(&url("https://a.com/dav/"), &url("http://a.com/dav/")));
+        assert!(!policy.allows(&url("https://a.com/dav/"), &url("https://b.com/dav/")));
+        assert!(!policy.allows(&url("https://a.com/dav/"), &url("https://a.com:8443/dav/")));
+        assert!(policy.keeps_credentials(&url("http://a.com/dav/"), &url("https://a.com/dav/")));
+
+        let permissive = RedirectPolicy { allow_cross_origin: true, ..RedirectPolicy::default() };
+        assert!(permissive.allows(&url("https://a.com/dav/"), &url("https://b.com/dav/")));
+        assert!(!permissive.allows(&url("https://a.com/dav/"), &url("http://b.com/dav/")));
+        assert!(!permissive.keeps_credentials(&url("https://a.com/dav/"), &url("https://b.com/dav/")));
+    }
+}
@@ -0,0 +1,213 @@
+//! Automatic retries of failed requests
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::{Method, Request, Response, StatusCode};
+use reqwest::header::{IF_MATCH, IF_NONE_MATCH, RETRY_AFTER};
+
+use crate::time::SystemTime;
+
+/// Tells how (and whether) requests that failed because of a transient error should be retried
+///
+/// Delays grow exponentially: the _n_-th retry waits for `base_delay * 2^(n-1)` (capped to `max_delay`).
+/// This policy applies to every request issued by a [`Client`](crate::client::Client) and its calendars.
+///
+/// Only requests that can safely be sent twice are retried: idempotent methods (`GET`, `PUT`, `DELETE`, `PROPFIND`, `REPORT`...) and conditional requests (with `If-Match` or `If-None-Match`),
+/// that the server rejects if a previous attempt has actually been applied. Other requests (e.g. `POST`, that some servers use to create items) are only retried if `retry_non_idempotent` is set.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts for a single request (including the first one). `1` means "never retry"
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub base_delay: Duration,
+    /// Upper bound of the delay between two attempts
+    pub max_delay: Duration,
+    /// Whether a random part (up to 50%) should be removed from every delay, so that many clients do not retry all at the same time
+    pub jitter: bool,
+    /// HTTP status codes that are considered transient, and that should be retried
+    pub retryable_statuses: Vec<StatusCode>,
+    /// Whether timeouts and connection errors should be retried
+    pub retry_on_network_errors: bool,
+    /// Whether the `Retry-After` header sent by the server (usually with 429 or 503 replies) should be honored (still capped to `max_delay`)
+    pub honor_retry_after: bool,
+    /// Whether requests that are neither idempotent nor conditional (e.g. `POST`) should be retried as well.
+    /// A failed request may have been applied by the server anyway, so retrying it may e.g. create the same item twice. Defaults to `false`
+    pub retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            retryable_statuses: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retry_on_network_errors: true,
+            honor_retry_after: true,
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries anything
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Whether `request` may be sent again if it fails
+    pub(crate) fn allows_retries_of(&self, request: &Request) -> bool {
+        self.retry_non_idempotent
+            || is_idempotent(request.method())
+            || request.headers().contains_key(IF_MATCH)
+            || request.headers().contains_key(IF_NONE_MATCH)
+    }
+
+    pub(crate) fn should_retry_status(&self, attempt: u32, status: StatusCode) -> bool {
+        attempt < self.max_attempts && self.retryable_statuses.contains(&status)
+    }
+
+    pub(crate) fn should_retry_error(&self, attempt: u32, err: &reqwest::Error) -> bool {
+        attempt < self.max_attempts && self.retry_on_network_errors && is_network_error(err)
+    }
+
+    /// The delay to wait for after the `attempt`-th attempt failed
+    pub(crate) fn delay(&self, attempt: u32, response: Option<&Response>) -> Duration {
+        if self.honor_retry_after {
+            if let Some(server_delay) = response.and_then(retry_after) {
+                return server_delay.min(self.max_delay);
+            }
+        }
+
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self.base_delay
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        if self.jitter {
+            // No need for a proper RNG here, we only want retries of different clients to be spread
+            let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+            let ratio = 0.5 + f64::from(nanos % 1000) / 2000.0;
+            delay.mul_f64(ratio)
+        } else {
+            delay
+        }
+    }
+}
+
+/// Whether sending a request with this method several times has the same effect as sending it once
+fn is_idempotent(method: &Method) -> bool {
+    matches!(method.as_str(), "GET" | "HEAD" | "OPTIONS" | "PUT" | "DELETE" | "PROPFIND" | "REPORT")
+}
+
+/// Whether a request failed because the server could not be reached (rather than e.g. because its response was invalid)
+#[cfg(not(target_arch = "wasm32"))]
+fn is_network_error(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.is_connect()
+}
+
+/// Browsers do not tell why a request failed, so every failure to send a request is considered a network error
+#[cfg(target_arch = "wasm32")]
+fn is_network_error(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.is_request()
+}
+
+/// Parse the `Retry-After` header of a response
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, Utc::now())
+}
+
+/// `Retry-After` is either a number of seconds, or an HTTP date
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    let delay = date.with_timezone(&Utc) - now;
+    Some(delay.to_std().unwrap_or_else(|_| Duration::from_secs(0)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_exponential_delays() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            jitter: false,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.delay(1, None), Duration::from_secs(1));
+        assert_eq!(policy.delay(2, None), Duration::from_secs(2));
+        assert_eq!(policy.delay(3, None), Duration::from_secs(4));
+        assert_eq!(policy.delay(5, None), Duration::from_secs(10));
+        assert_eq!(policy.delay(100, None), Duration::from_secs(10));
+
+        let jittered = RetryPolicy { jitter: true, ..policy };
+        let delay = jittered.delay(2, None);
+        assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_retry_after_parsing() {
+        let now = Utc.ymd(2021, 3, 21).and_hms(10, 0, 0);
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Sun, 21 Mar 2021 10:01:30 GMT", now), Some(Duration::from_secs(90)));
+        assert_eq!(parse_retry_after("Sun, 21 Mar 2021 09:00:00 GMT", now), Some(Duration::from_secs(0)));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_retryable() {
+        let policy = RetryPolicy::default();
+        assert!(policy.should_retry_status(1, StatusCode::SERVICE_UNAVAILABLE));
+        assert!(policy.should_retry_status(2, StatusCode::BAD_GATEWAY));
+        assert!(!policy.should_retry_status(3, StatusCode::BAD_GATEWAY));
+        assert!(!policy.should_retry_status(1, StatusCode::NOT_FOUND));
+        assert!(!RetryPolicy::none().should_retry_status(1, StatusCode::SERVICE_UNAVAILABLE));
+    }
+
+    #[test]
+    fn test_only_idempotent_requests_are_retried() {
+        let url: url::Url = "https://some.server/cal/".parse().unwrap();
+        let request = |method: &str, header: Option<reqwest::header::HeaderName>| {
+            let mut request = Request::new(Method::from_bytes(method.as_bytes()).unwrap(), url.clone());
+            if let Some(header) = header {
+                request.headers_mut().insert(header, "\"etag\"".parse().unwrap());
+            }
+            request
+        };
+
+        let policy = RetryPolicy::default();
+        for method in ["GET", "PUT", "DELETE", "PROPFIND", "REPORT"] {
+            assert!(policy.allows_retries_of(&request(method, None)), "{}", method);
+        }
+        assert!(!policy.allows_retries_of(&request("POST", None)));
+        assert!(!policy.allows_retries_of(&request("PATCH", None)));
+        assert!(!policy.allows_retries_of(&request("MKCALENDAR", None)));
+        assert!(policy.allows_retries_of(&request("PATCH", Some(IF_MATCH))));
+        assert!(policy.allows_retries_of(&request("POST", Some(IF_NONE_MATCH))));
+
+        let opt_in = RetryPolicy { retry_non_idempotent: true, ..RetryPolicy::default() };
+        assert!(opt_in.allows_retries_of(&request("POST", None)));
+    }
+}
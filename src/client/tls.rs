@@ -0,0 +1,133 @@
+//! TLS settings of a [`Client`](crate::client::Client)
+
+use std::error::Error;
+use std::fmt::{Debug, Formatter};
+use std::path::Path;
+
+/// A TLS client certificate (and its private key), used to authenticate against servers that require mutual TLS.
+///
+/// This is typically needed for servers that are behind a mTLS reverse proxy.
+#[derive(Clone)]
+pub struct ClientIdentity {
+    pub(crate) pkcs12_der: Vec<u8>,
+    // Browsers ask the user for client certificates themselves
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub(crate) password: String,
+}
+
+impl ClientIdentity {
+    /// Create an identity from a DER-formatted PKCS #12 archive, that contains both the certificate (chain) and its private key
+    pub fn from_pkcs12_der(der: Vec<u8>, password: &str) -> Self {
+        Self { pkcs12_der: der, password: password.to_string() }
+    }
+
+    /// Create an identity from a PKCS #12 file (usually with a `.p12` or `.pfx` extension)
+    pub fn from_pkcs12_file(path: &Path, password: &str) -> Result<Self, Box<dyn Error>> {
+        let der = std::fs::read(path)
+            .map_err(|err| format!("Unable to read client certificate {:?}: {}", path, err))?;
+        Ok(Self::from_pkcs12_der(der, password))
+    }
+}
+
+impl Debug for ClientIdentity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Let's not leak the private key (nor its password) into logs
+        f.debug_struct("ClientIdentity")
+            .field("pkcs12_der", &format!("<{} bytes>", self.pkcs12_der.len()))
+            .finish()
+    }
+}
+
+
+/// An X.509 certificate, either PEM- or DER-encoded
+#[derive(Clone, Debug)]
+pub enum Certificate {
+    Pem(Vec<u8>),
+    Der(Vec<u8>),
+}
+
+impl Certificate {
+    /// Read a certificate file. Files that start with a `-----BEGIN` line are considered PEM-encoded, other ones DER-encoded
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read(path)
+            .map_err(|err| format!("Unable to read certificate {:?}: {}", path, err))?;
+        if content.starts_with(b"-----BEGIN") {
+            Ok(Self::Pem(content))
+        } else {
+            Ok(Self::Der(content))
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn to_reqwest(&self) -> Result<reqwest::Certificate, Box<dyn Error>> {
+        let cert = match self {
+            Self::Pem(pem) => reqwest::Certificate::from_pem(pem),
+            Self::Der(der) => reqwest::Certificate::from_der(der),
+        };
+        cert.map_err(|err| format!("Invalid certificate: {}", err).into())
+    }
+}
+
+
+/// Custom TLS settings, e.g. for self-hosted servers that use self-signed certificates or a private certificate authority
+///
+/// By default, the system trust store is used, and invalid certificates are rejected.
+#[derive(Clone, Debug, Default)]
+pub struct TlsSettings {
+    pub(crate) root_certificates: Vec<Certificate>,
+    pub(crate) pinned_certificates: Vec<Certificate>,
+    pub(crate) danger_accept_invalid_certs: bool,
+}
+
+impl TlsSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust an additional root certificate (e.g. the certificate of a private CA), on top of the system trust store
+    pub fn add_root_certificate(mut self, cert: Certificate) -> Self {
+        self.root_certificates.push(cert);
+        self
+    }
+
+    /// Pin a certificate.
+    ///
+    /// As soon as at least one certificate is pinned, the system trust store is no longer used, and only the pinned certificates
+    /// (and the ones added by [`Self::add_root_certificate`]) are trusted.
+    /// A self-signed server certificate can be pinned directly.
+    pub fn pin_certificate(mut self, cert: Certificate) -> Self {
+        self.pinned_certificates.push(cert);
+        self
+    }
+
+    /// Accept any server certificate, even expired, self-signed or issued for another host.
+    ///
+    /// # Warning
+    ///
+    /// This makes the connection vulnerable to man-in-the-middle attacks, and should only be used as a last resort (or for tests).
+    /// Prefer [`Self::add_root_certificate`] or [`Self::pin_certificate`] whenever possible.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.danger_accept_invalid_certs = accept;
+        self
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn is_default(&self) -> bool {
+        self.root_certificates.is_empty() && self.pinned_certificates.is_empty() && !self.danger_accept_invalid_certs
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn apply_to(&self, mut builder: reqwest::ClientBuilder) -> Result<reqwest::ClientBuilder, Box<dyn Error>> {
+        for cert in self.root_certificates.iter().chain(self.pinned_certificates.iter()) {
+            builder = builder.add_root_certificate(cert.to_reqwest()?);
+        }
+        if !self.pinned_certificates.is_empty() {
+            builder = builder.tls_built_in_root_certs(false);
+        }
+        if self.danger_accept_invalid_certs {
+            log::warn!("TLS certificate validation is disabled. The connection to the server is not secure");
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        Ok(builder)
+    }
+}
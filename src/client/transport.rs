@@ -0,0 +1,327 @@
+//! The HTTP layer that is shared by a [`Client`](crate::client::Client) and every calendar it creates
+
+use std::error::Error;
+use std::sync::Arc;
+
+use reqwest::{Method, Request, RequestBuilder, Response, StatusCode};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, LOCATION};
+use url::Url;
+
+use crate::client::builder::ClientSettings;
+use crate::client::retry::RetryPolicy;
+use crate::client::redirect::RedirectPolicy;
+use crate::client::rate_limit::RateLimiter;
+use crate::client::headers::HeaderHooks;
+use crate::client::observer::{redact, Observers, RequestInfo, ResponseInfo};
+use crate::spans::{self, Instrument};
+use crate::metrics::{Metrics, RequestMetrics};
+use crate::time::Instant;
+
+/// The HTTP transport used to reach a CalDAV server.
+///
+/// It wraps a configured `reqwest::Client`, that is cheap to clone and that keeps its connection pool across clones.
+/// Every [`Resource`](crate::resource::Resource) carries one, so that calendars and items reached from a [`Client`](crate::client::Client) use the same settings.
+#[derive(Clone, Debug)]
+pub struct Transport {
+    http: reqwest::Client,
+    retry_policy: RetryPolicy,
+    redirect_policy: RedirectPolicy,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    header_hooks: HeaderHooks,
+    observers: Observers,
+    metrics: Metrics,
+    prefer_minimal: bool,
+}
+
+impl Transport {
+    /// Build a transport from the given settings
+    pub(crate) fn new(settings: &ClientSettings) -> Result<Self, Box<dyn Error>> {
+        let mut builder = configure_connections(reqwest::Client::builder(), settings)?;
+
+        let mut default_headers = HeaderMap::new();
+        for (name, value) in &settings.default_headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|err| format!("Invalid header name {:?}: {}", name, err))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|err| format!("Invalid value for header {}: {}", name, err))?;
+            default_headers.insert(name, value);
+        }
+        builder = builder.default_headers(default_headers);
+
+        Ok(Self {
+            http: builder.build()?,
+            retry_policy: settings.retry_policy.clone(),
+            redirect_policy: settings.redirect_policy.clone(),
+            rate_limiter: settings.rate_limit.clone().map(|limit| Arc::new(RateLimiter::new(limit))),
+            header_hooks: settings.header_hooks.clone(),
+            observers: settings.observers.clone(),
+            metrics: settings.metrics.clone(),
+            prefer_minimal: settings.prefer_minimal,
+        })
+    }
+
+    /// Start building a request
+    pub(crate) fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let mut extra_headers = HeaderMap::new();
+        if self.prefer_minimal && (method == Method::PUT || method.as_str() == "PROPPATCH") {
+            extra_headers.insert("Prefer", HeaderValue::from_static("return=minimal"));
+            extra_headers.insert("Brief", HeaderValue::from_static("t"));
+        }
+        if !self.header_hooks.is_empty() {
+            extra_headers.extend(self.header_hooks.headers_for(&method, &url));
+        }
+
+        self.http.request(method, url)
+            .headers(extra_headers)
+    }
+
+    /// Send a request, and retry it in case of transient errors (see [`RetryPolicy`])
+    pub(crate) async fn send(&self, request: RequestBuilder) -> Result<Response, Box<dyn Error>> {
+        // Requests that the server may have applied although they failed (e.g. POST) must not be sent twice
+        let retryable = request.try_clone()
+            .and_then(|req| req.build().ok())
+            .is_some_and(|req| self.retry_policy.allows_retries_of(&req));
+        let mut attempt = 1;
+        loop {
+            let permit = match &self.rate_limiter {
+                None => None,
+                Some(limiter) => limiter.acquire().await,
+            };
+
+            let this_attempt = match request.try_clone() {
+                Some(req) => req,
+                // Requests with streamed bodies cannot be retried
+                None => return Ok(request.send().await?),
+            };
+
+            let (delay, reason) = match self.execute(this_attempt).await {
+                Ok(response) => {
+                    if !retryable || !self.retry_policy.should_retry_status(attempt, response.status()) {
+                        return Ok(response);
+                    }
+                    (self.retry_policy.delay(attempt, Some(&response)), format!("HTTP status {}", response.status()))
+                },
+                Err(err) => {
+                    if !retryable || !self.retry_policy.should_retry_error(attempt, &err) {
+                        return Err(err.into());
+                    }
+                    (self.retry_policy.delay(attempt, None), err.to_string())
+                },
+            };
+
+            // Do not prevent other requests from being sent while we are waiting
+            drop(permit);
+            log::warn!("Request failed ({}), retrying in {:?} (attempt {}/{})", reason, delay, attempt + 1, self.retry_policy.max_attempts);
+            crate::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Send a request, following the redirections that are allowed by the [`RedirectPolicy`]
+    async fn execute(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+        let mut request = request.build()?;
+        let mut redirects = 0;
+        loop {
+            let next_request = request.try_clone();
+            let response = self.execute_once(request).await?;
+
+            let status = response.status();
+            match status {
+                StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => (),
+                _ => return Ok(response),
+            }
+
+            let target = response.headers().get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| response.url().join(location).ok());
+            let (target, next_request) = match (target, next_request) {
+                (Some(t), Some(r)) => (t, r),
+                _ => return Ok(response),
+            };
+
+            if redirects >= self.redirect_policy.max_redirects {
+                log::warn!("Too many redirections, not following {} to {}", response.url(), target);
+                return Ok(response);
+            }
+            if !self.redirect_policy.allows(response.url(), &target) {
+                log::warn!("Not following redirection from {} to {}, as forbidden by the redirect policy", response.url(), target);
+                return Ok(response);
+            }
+
+            log::debug!("Following redirection ({}) from {} to {}", status, response.url(), target);
+            request = redirected(next_request, status, response.url(), target, &self.redirect_policy);
+            redirects += 1;
+        }
+    }
+
+    /// Send a single request
+    async fn execute_once(&self, request: Request) -> Result<Response, reqwest::Error> {
+        let span = spans::request(request.method(), request.url());
+        let method = request.method().clone();
+        let bytes_sent = request.body().and_then(|body| body.as_bytes()).map_or(0, |bytes| bytes.len() as u64);
+        let start = Instant::now();
+        let result = self.execute_observed(request).instrument(span.clone()).await;
+        if let Ok(response) = &result {
+            spans::record_status(&span, response.status());
+        }
+        if let Some(sink) = self.metrics.sink() {
+            sink.request(&RequestMetrics {
+                method,
+                status: result.as_ref().ok().map(|response| response.status()),
+                elapsed: start.elapsed(),
+                bytes_sent,
+                bytes_received: result.as_ref().ok().and_then(|response| response.content_length()),
+            });
+        }
+        result
+    }
+
+    /// Send a single request, and notify the observers (if any)
+    async fn execute_observed(&self, request: Request) -> Result<Response, reqwest::Error> {
+        if self.observers.is_empty() {
+            return self.http.execute(request).await;
+        }
+
+        let with_bodies = self.observers.want_bodies();
+        let request_info = RequestInfo {
+            id: self.observers.next_id(),
+            method: request.method().clone(),
+            url: request.url().clone(),
+            headers: redact(request.headers()),
+            body: request.body()
+                .filter(|_| with_bodies)
+                .and_then(|body| body.as_bytes())
+                .map(|bytes| String::from_utf8_lossy(bytes).into_owned()),
+        };
+        self.observers.on_request(&request_info);
+
+        let start = Instant::now();
+        let response = self.http.execute(request).await?;
+        let mut response_info = ResponseInfo {
+            id: request_info.id,
+            method: request_info.method,
+            url: response.url().clone(),
+            status: response.status(),
+            headers: redact(response.headers()),
+            body: None,
+            elapsed: start.elapsed(),
+        };
+
+        let response = if with_bodies {
+            read_body(response, &mut response_info, start).await?
+        } else {
+            response
+        };
+
+        self.observers.on_response(&response_info);
+        Ok(response)
+    }
+}
+
+/// Read the body of a response for the observers. The body has to be read, then put back into a new response
+#[cfg(not(target_arch = "wasm32"))]
+async fn read_body(response: Response, info: &mut ResponseInfo, start: Instant) -> Result<Response, reqwest::Error> {
+    use reqwest::ResponseBuilderExt;
+    use reqwest::header::{CONTENT_ENCODING, CONTENT_LENGTH};
+
+    let version = response.version();
+    let mut headers = response.headers().clone();
+    let bytes = response.bytes().await?;
+    info.body = Some(String::from_utf8_lossy(&bytes).into_owned());
+    info.elapsed = start.elapsed();
+
+    // The body is already decompressed
+    headers.remove(CONTENT_ENCODING);
+    headers.remove(CONTENT_LENGTH);
+    let mut builder = http::Response::builder()
+        .status(info.status)
+        .version(version)
+        .url(info.url.clone());
+    if let Some(builder_headers) = builder.headers_mut() {
+        *builder_headers = headers;
+    }
+    Ok(Response::from(builder.body(bytes).expect("A response built from a valid response should be valid")))
+}
+
+/// Responses cannot be rebuilt in browsers, so observers do not get their bodies there
+#[cfg(target_arch = "wasm32")]
+async fn read_body(response: Response, _info: &mut ResponseInfo, _start: Instant) -> Result<Response, reqwest::Error> {
+    Ok(response)
+}
+
+/// Apply the settings of the connections themselves (TLS, proxies, timeouts, connection pool...)
+#[cfg(not(target_arch = "wasm32"))]
+fn configure_connections(mut builder: reqwest::ClientBuilder, settings: &ClientSettings) -> Result<reqwest::ClientBuilder, Box<dyn Error>> {
+    builder = builder
+        // Redirections are handled by `Transport::execute`
+        .redirect(reqwest::redirect::Policy::none())
+        .gzip(settings.compression)
+        .brotli(settings.compression);
+
+    if let Some(identity) = &settings.identity {
+        let identity = reqwest::Identity::from_pkcs12_der(&identity.pkcs12_der, &identity.password)
+            .map_err(|err| format!("Invalid client certificate: {}", err))?;
+        builder = builder.identity(identity);
+    }
+    builder = settings.tls.apply_to(builder)?;
+
+    if let Some(user_agent) = &settings.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+
+    if let Some(timeout) = settings.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(timeout) = settings.request_timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder = settings.pool.apply_to(builder);
+
+    if settings.proxies.is_empty() && !settings.use_system_proxies {
+        builder = builder.no_proxy();
+    }
+    for proxy_url in &settings.proxies {
+        match proxy_url.scheme() {
+            "http" | "https" | "socks5" | "socks5h" => (),
+            other => return Err(format!("Unsupported proxy scheme {:?} in {}", other, proxy_url).into()),
+        }
+        let proxy = reqwest::Proxy::all(proxy_url.clone())
+            .map_err(|err| format!("Invalid proxy {}: {}", proxy_url, err))?;
+        builder = builder.proxy(proxy);
+    }
+    Ok(builder)
+}
+
+/// Browsers manage connections by themselves: settings that would be silently ignored are rejected
+#[cfg(target_arch = "wasm32")]
+fn configure_connections(builder: reqwest::ClientBuilder, settings: &ClientSettings) -> Result<reqwest::ClientBuilder, Box<dyn Error>> {
+    if settings.identity.is_some() || !settings.tls.is_default() {
+        return Err("Client certificates and custom TLS settings are not supported when compiling to wasm32".into());
+    }
+    if !settings.proxies.is_empty() || settings.connect_timeout.is_some() || settings.request_timeout.is_some() {
+        return Err("Proxies and timeouts are not supported when compiling to wasm32".into());
+    }
+    Ok(builder)
+}
+
+/// Build the request to send to the target of a redirection
+fn redirected(mut request: Request, status: StatusCode, from: &Url, to: Url, policy: &RedirectPolicy) -> Request {
+    if !policy.keeps_credentials(from, &to) {
+        request.headers_mut().remove(AUTHORIZATION);
+    }
+    if status == StatusCode::SEE_OTHER {
+        // 303 means "get the result from there"
+        *request.method_mut() = Method::GET;
+        *request.body_mut() = None;
+    }
+    *request.url_mut() = to;
+    request
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new(&ClientSettings::default())
+            .expect("The default HTTP settings should be valid")
+    }
+}
@@ -3,6 +3,8 @@
 use std::sync::{Arc, Mutex};
 use once_cell::sync::Lazy;
 
+use crate::uid::{ItemUrls, UidGenerator, UuidV4};
+
 /// Part of the ProdID string that describes the organization (example of a ProdID string: `-//ABC Corporation//My Product//EN`).
 /// Feel free to override it when initing this library.
 pub static ORG_NAME: Lazy<Arc<Mutex<String>>> = Lazy::new(|| Arc::new(Mutex::new("My organization".to_string())));
@@ -10,3 +12,15 @@ pub static ORG_NAME: Lazy<Arc<Mutex<String>>> = Lazy::new(|| Arc::new(Mutex::new
 /// Part of the ProdID string that describes the product name (example of a ProdID string: `-//ABC Corporation//My Product//EN`).
 /// Feel free to override it when initing this library.
 pub static PRODUCT_NAME: Lazy<Arc<Mutex<String>>> = Lazy::new(|| Arc::new(Mutex::new("KitchenFridge".to_string())));
+
+/// How the UIDs of new items are generated (see [`crate::uid`] for available generators).
+/// Feel free to override it when initing this library.
+pub static UID_GENERATOR: Lazy<Arc<Mutex<Box<dyn UidGenerator>>>> = Lazy::new(|| Arc::new(Mutex::new(Box::new(UuidV4))));
+
+/// How the URLs of new items are derived from their UIDs. Servers that impose their own naming may require [`ItemUrls::FromUid`].
+/// Feel free to override it when initing this library.
+pub static ITEM_URLS: Lazy<Arc<Mutex<ItemUrls>>> = Lazy::new(|| Arc::new(Mutex::new(ItemUrls::default())));
+
+/// Whether new local tasks that have no alarm get the default alarm of their calendar (see [`crate::calendar::DefaultAlarms`]).
+/// This is disabled by default, since apps that do not display alarms would otherwise add alarms their users do not know about.
+pub static ATTACH_DEFAULT_ALARMS: Lazy<Arc<Mutex<bool>>> = Lazy::new(|| Arc::new(Mutex::new(false)));
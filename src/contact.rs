@@ -0,0 +1,197 @@
+//! Contacts (vCard items, in CardDAV address books)
+
+use std::collections::BTreeSet;
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use ical::property::Property;
+use url::Url;
+
+use crate::Item;
+use crate::item::{FieldChange, ItemField, SyncStatus};
+use crate::calendar::SupportedComponents;
+use crate::calendar::cached_calendar::CachedCalendar;
+use crate::traits::BaseCalendar;
+use crate::task::{content_property_values, same_content_properties};
+use crate::uid;
+
+/// The vCard version of the contacts this crate creates
+pub const DEFAULT_VCARD_VERSION: &str = "4.0";
+
+/// A contact, i.e. a vCard (in its 3.0 or 4.0 version) of an address book
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Contact {
+    /// The contact URL. It is unrelated to its UID, since servers may name their items as they like
+    url: Url,
+    /// Persistent, globally unique identifier of the contact (the vCard `UID`)
+    uid: String,
+
+    /// The sync status of this item
+    sync_status: SyncStatus,
+    /// The last time this contact was modified (the vCard `REV`)
+    last_modified: DateTime<Utc>,
+
+    /// The formatted name of the contact (the vCard `FN`)
+    name: String,
+
+    /// The vCard `VERSION`, i.e. `3.0` or `4.0`
+    version: String,
+    /// The vCard `PRODID`
+    prod_id: String,
+
+    /// The other properties of the vCard (e.g. `N`, `EMAIL` or `TEL`), as they have been parsed.
+    /// They are needed to serialize this item into an equivalent vCard
+    extra_parameters: Vec<Property>,
+}
+
+impl Contact {
+    /// Create a brand new contact that is not on a server yet.
+    /// This will pick a new (random) UID.
+    pub fn new(name: String, parent_calendar_url: &Url) -> Self {
+        let new_uid = uid::new_uid();
+        let new_url = uid::new_item_url(parent_calendar_url, &new_uid);
+        Self::new_with_parameters(name, new_uid, new_url, SyncStatus::NotSynced, Utc::now(),
+            DEFAULT_VCARD_VERSION.to_string(), crate::ical::default_prod_id(), Vec::new())
+    }
+
+    /// Create a new Contact instance, that may be synced on the server already
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_parameters(name: String, uid: String, new_url: Url,
+                               sync_status: SyncStatus, last_modified: DateTime<Utc>,
+                               version: String, prod_id: String, extra_parameters: Vec<Property>,
+                            ) -> Self
+    {
+        Self {
+            url: new_url,
+            uid,
+            name,
+            sync_status,
+            last_modified,
+            version,
+            prod_id,
+            extra_parameters,
+        }
+    }
+
+    pub fn url(&self) -> &Url       { &self.url         }
+    pub fn uid(&self) -> &str       { &self.uid         }
+    pub fn name(&self) -> &str      { &self.name        }
+    pub fn version(&self) -> &str   { &self.version     }
+    pub fn sync_status(&self) -> &SyncStatus      { &self.sync_status  }
+    pub fn last_modified(&self) -> &DateTime<Utc> { &self.last_modified }
+    pub fn extra_parameters(&self) -> &[Property] { &self.extra_parameters }
+    /// The `PRODID` of the vCard. This is named like its iCal counterpart, see [`Item::ical_prod_id`]
+    pub fn ical_prod_id(&self) -> &str            { &self.prod_id }
+    /// vCards have no creation date
+    pub fn creation_date(&self) -> Option<&DateTime<Utc>> { None }
+
+    /// The email addresses of this contact (its `EMAIL` properties)
+    pub fn emails(&self) -> Vec<&str> {
+        self.values("EMAIL")
+    }
+
+    /// The phone numbers of this contact (its `TEL` properties)
+    pub fn phone_numbers(&self) -> Vec<&str> {
+        self.values("TEL")
+    }
+
+    fn values(&self, name: &str) -> Vec<&str> {
+        self.extra_parameters.iter()
+            .filter(|prop| prop.name.eq_ignore_ascii_case(name))
+            .filter_map(|prop| prop.value.as_deref())
+            .collect()
+    }
+
+    #[cfg(any(test, feature = "integration_tests"))]
+    pub fn has_same_observable_content_as(&self, other: &Contact) -> bool {
+           self.url == other.url
+        && self.uid == other.uid
+        && self.name == other.name
+        // sync status must be the same variant, but we ignore its embedded version tag
+        && std::mem::discriminant(&self.sync_status) == std::mem::discriminant(&other.sync_status)
+    }
+
+    /// Returns whether both contacts have the same content, ignoring where they are stored and their sync metadata (see [`Task::content_eq`](crate::Task::content_eq))
+    pub fn content_eq(&self, other: &Contact) -> bool {
+           self.uid == other.uid
+        && self.name == other.name
+        && same_content_properties(&self.extra_parameters, &other.extra_parameters)
+    }
+
+    /// The fields that differ between this contact and `other` (see [`Task::diff`](crate::Task::diff))
+    pub fn diff(&self, other: &Contact) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        FieldChange::push_if_different(&mut changes, ItemField::Uid, Some(self.uid.clone()), Some(other.uid.clone()));
+        FieldChange::push_if_different(&mut changes, ItemField::Name, Some(self.name.clone()), Some(other.name.clone()));
+
+        let mut old_values = content_property_values(&self.extra_parameters);
+        let mut new_values = content_property_values(&other.extra_parameters);
+        let names: BTreeSet<String> = old_values.keys().chain(new_values.keys()).cloned().collect();
+        for name in names {
+            let (old, new) = (old_values.remove(&name), new_values.remove(&name));
+            FieldChange::push_if_different(&mut changes, ItemField::Property(name), old, new);
+        }
+        changes
+    }
+
+    /// A copy of this contact, that can be added to the address book at `calendar_url` as a new contact. \
+    /// The copy has a new UID and URL, and it has never been synced
+    pub fn duplicate(&self, calendar_url: &Url) -> Contact {
+        let new_uid = uid::new_uid();
+        let new_url = uid::new_item_url(calendar_url, &new_uid);
+        Self::new_with_parameters(self.name.clone(), new_uid, new_url, SyncStatus::NotSynced, Utc::now(),
+            self.version.clone(), self.prod_id.clone(), self.extra_parameters.clone())
+    }
+
+    /// Add a copy of this contact (see [`Contact::duplicate`]) to `calendar`. It will be uploaded at the next sync. \
+    /// Returns the URL of the copy
+    pub fn duplicate_into(&self, calendar: &mut CachedCalendar) -> Result<Url, Box<dyn Error>> {
+        if !calendar.supported_components().contains(SupportedComponents::CONTACT) {
+            return Err(format!("Calendar {} is not an address book", calendar.url()).into());
+        }
+        let copy = self.duplicate(calendar.url());
+        let url = copy.url().clone();
+        calendar.add_item_sync(Item::Contact(copy))?;
+        Ok(url)
+    }
+
+    /// A copy of this contact at another URL, with the same UID and sync status
+    pub(crate) fn moved_to(&self, new_url: Url) -> Contact {
+        Contact { url: new_url, ..self.clone() }
+    }
+
+    pub fn set_sync_status(&mut self, new_status: SyncStatus) {
+        self.sync_status = new_status;
+    }
+
+    fn update_sync_status(&mut self) {
+        match &self.sync_status {
+            SyncStatus::NotSynced => (),
+            SyncStatus::LocallyModified(_) => (),
+            SyncStatus::Synced(prev_vt) => {
+                self.sync_status = SyncStatus::LocallyModified(prev_vt.clone());
+            }
+            SyncStatus::LocallyDeleted(_) => {
+                log::warn!("Trying to update an item that has previously been deleted. These changes will probably be ignored at next sync.");
+            },
+        }
+    }
+
+    /// Rename a contact.
+    /// This updates its "last modified" field
+    pub fn set_name(&mut self, new_name: String) {
+        self.update_sync_status();
+        self.last_modified = Utc::now();
+        self.name = new_name;
+    }
+
+    /// Replace every property that has a given name (e.g. `EMAIL`) by new values.
+    /// This updates its "last modified" field
+    pub fn set_values(&mut self, name: &str, values: &[&str]) {
+        self.update_sync_status();
+        self.last_modified = Utc::now();
+        self.extra_parameters.retain(|prop| !prop.name.eq_ignore_ascii_case(name));
+        self.extra_parameters.extend(values.iter().map(|value| Property{ name: name.to_uppercase(), params: None, value: Some(value.to_string()) }));
+    }
+}
@@ -0,0 +1,197 @@
+//! Errors that callers may want to handle specifically
+//!
+//! The providers and the calendars of this crate return a [`KFError`], whose variants tell apart the failures callers may want to handle
+//! (e.g. `matches!(err, KFError::Conflict{ .. })`). Other failures are wrapped into [`KFError::Other`]
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+use reqwest::StatusCode;
+use url::Url;
+
+/// The ways requests to a server, changes to a calendar, or accesses to the cache can fail
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum KFError {
+    /// The server replied with an unexpected HTTP status code
+    Http { status: StatusCode, url: Url },
+    /// The server refused the credentials (i.e. it replied with `401 Unauthorized`)
+    Unauthorized { url: Url },
+    /// The server does not know this resource (i.e. it replied with `404 Not Found`)
+    NotFound { url: Url },
+    /// An item could not be updated on the server, because it has been modified there since it was last synced
+    /// (i.e. the server replied with `412 Precondition Failed` to a conditional request)
+    Conflict { url: Url },
+    /// The server refused a change because the current user is not allowed to make it (i.e. the server replied with `403 Forbidden`)
+    Forbidden { url: Url },
+    /// The server refused to store an item because the user has no storage space left (i.e. the server replied with `507 Insufficient Storage`)
+    ///
+    /// See [`Client::get_quota`](crate::client::Client::get_quota) to check the available space beforehand
+    InsufficientStorage { url: Url },
+    /// The server cannot move an item to another calendar (e.g. it does not support `MOVE` requests, or not between these calendars)
+    MoveNotSupported { url: Url },
+    /// A local change has been refused because the calendar is read-only (see [`BaseCalendar::is_read_only`](crate::traits::BaseCalendar::is_read_only))
+    ReadOnlyCalendar { calendar: Url },
+    /// An item has been refused because its calendar does not support its kind of components (e.g. a task in a calendar that only supports events, see [`BaseCalendar::supported_components`](crate::traits::BaseCalendar::supported_components))
+    UnsupportedComponent { calendar: Url, item: Url },
+    /// An iCal file (e.g. an item downloaded from the server) is invalid. `line` is the line of the file where parsing failed, when it is known
+    IcalParse { url: Option<Url>, line: Option<usize>, reason: String },
+    /// The cache could not be read from or written to its storage (e.g. the disk is full, or the permissions are wrong)
+    Storage { source: std::io::Error },
+    /// The cache is being read or written by another process (e.g. a daemon syncing the same cache folder), and did not become available in time
+    CacheInUse { path: PathBuf },
+    /// Any other failure (e.g. a network error, or a malformed reply from the server)
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl KFError {
+    /// The error for an unsuccessful HTTP status code
+    pub(crate) fn from_status(status: StatusCode, url: Url) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthorized{ url },
+            StatusCode::NOT_FOUND => Self::NotFound{ url },
+            StatusCode::FORBIDDEN => Self::Forbidden{ url },
+            StatusCode::PRECONDITION_FAILED => Self::Conflict{ url },
+            StatusCode::INSUFFICIENT_STORAGE => Self::InsufficientStorage{ url },
+            _ => Self::Http{ status, url },
+        }
+    }
+
+    pub(crate) fn ical_parse<S: ToString>(url: &Url, reason: S) -> Self {
+        Self::IcalParse{ url: Some(url.clone()), line: None, reason: reason.to_string() }
+    }
+
+    /// The error for a file the `ical` crate could not parse, with the line it failed at when it reports one
+    pub(crate) fn from_parser(url: Option<&Url>, err: ical::parser::ParserError) -> Self {
+        use ical::property::PropertyError;
+
+        let line = match &err {
+            ical::parser::ParserError::PropertyError(err) => match err {
+                PropertyError::MissingName{ line }
+                | PropertyError::MissingClosingQuote{ line }
+                | PropertyError::MissingDelimiter{ line, .. }
+                | PropertyError::MissingContentAfter{ line, .. }
+                | PropertyError::MissingParamKey{ line } => Some(*line),
+            },
+            _ => None,
+        };
+        Self::IcalParse{ url: url.cloned(), line, reason: err.to_string() }
+    }
+
+    /// Wrap the I/O errors of a [`CacheStorage`](crate::cache::storage::CacheStorage) into [`KFError::Storage`]
+    pub(crate) fn from_storage(err: Box<dyn Error>) -> Self {
+        match err.downcast::<std::io::Error>() {
+            Ok(source) => Self::Storage{ source: *source },
+            Err(err) => Self::from(err),
+        }
+    }
+}
+
+impl Display for KFError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http{ status, url } => write!(f, "Unexpected HTTP status code {:?} for {}", status, url),
+            Self::Unauthorized{ url } => write!(f, "The server refused the credentials for {}", url),
+            Self::NotFound{ url } => write!(f, "{} does not exist on the server", url),
+            Self::Conflict{ url } => write!(f, "Item {} has been modified on the server since it was last synced", url),
+            Self::Forbidden{ url } => write!(f, "The server does not allow this change on {}", url),
+            Self::InsufficientStorage{ url } => write!(f, "The server is full, and cannot store item {}", url),
+            Self::MoveNotSupported{ url } => write!(f, "The server is unable to move item {}", url),
+            Self::ReadOnlyCalendar{ calendar } => write!(f, "Calendar {} is read-only", calendar),
+            Self::UnsupportedComponent{ calendar, item } => write!(f, "Calendar {} does not support the kind of item {}", calendar, item),
+            Self::IcalParse{ url, line, reason } => {
+                write!(f, "Unable to parse iCal data")?;
+                if let Some(url) = url {
+                    write!(f, " for item {}", url)?;
+                }
+                if let Some(line) = line {
+                    write!(f, " at line {}", line)?;
+                }
+                write!(f, ": {}", reason)
+            },
+            Self::Storage{ source } => write!(f, "Unable to access the cache storage: {}", source),
+            Self::CacheInUse{ path } => write!(f, "The cache at {:?} is in use by another process", path),
+            Self::Other(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for KFError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Storage{ source } => Some(source),
+            Self::Other(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+/// Errors that were already boxed (e.g. by a function that returns a `Box<dyn Error>`) are unboxed if they are a `KFError`
+///
+/// Other errors are kept as their message, because a `Box<dyn Error>` is not `Send`
+impl From<Box<dyn Error>> for KFError {
+    fn from(err: Box<dyn Error>) -> Self {
+        match err.downcast::<KFError>() {
+            Ok(err) => *err,
+            Err(err) => Self::Other(err.to_string().into()),
+        }
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for KFError {
+    fn from(err: Box<dyn Error + Send + Sync>) -> Self {
+        match err.downcast::<KFError>() {
+            Ok(err) => *err,
+            Err(err) => Self::Other(err),
+        }
+    }
+}
+
+macro_rules! other_error_from {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for KFError {
+                fn from(err: $error) -> Self {
+                    Self::Other(err.into())
+                }
+            }
+        )*
+    };
+}
+
+other_error_from!(
+    String, &str,
+    std::io::Error, url::ParseError, chrono::ParseError, serde_json::Error, minidom::Error,
+    reqwest::Error, reqwest::header::ToStrError, http::method::InvalidMethod
+);
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kf_errors() {
+        let url: Url = "https://some.server/cal/item.ics".parse().unwrap();
+        assert!(matches!(KFError::from_status(StatusCode::UNAUTHORIZED, url.clone()), KFError::Unauthorized{ .. }));
+        assert!(matches!(KFError::from_status(StatusCode::PRECONDITION_FAILED, url.clone()), KFError::Conflict{ .. }));
+        assert!(matches!(KFError::from_status(StatusCode::FORBIDDEN, url.clone()), KFError::Forbidden{ .. }));
+        assert!(matches!(KFError::from_status(StatusCode::BAD_GATEWAY, url.clone()), KFError::Http{ status: StatusCode::BAD_GATEWAY, .. }));
+
+        let err = crate::ical::parse("not iCal", url.clone(), crate::item::SyncStatus::NotSynced).unwrap_err();
+        assert!(matches!(err, KFError::IcalParse{ url: Some(_), .. }));
+        let err = crate::ical::parse("BEGIN:VCALENDAR\nVERSION:2.0\nBEGIN:VTODO\nSUMMARY\nEND:VTODO\nEND:VCALENDAR\n", url.clone(), crate::item::SyncStatus::NotSynced).unwrap_err();
+        assert!(matches!(err, KFError::IcalParse{ line: Some(4), .. }), "{}", err);
+
+        let io_error: Box<dyn Error> = Box::new(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied"));
+        let err = KFError::from_storage(io_error);
+        assert!(matches!(err, KFError::Storage{ source } if source.kind() == std::io::ErrorKind::PermissionDenied));
+        let other_error: Box<dyn Error> = "some error".into();
+        assert!(matches!(KFError::from_storage(other_error), KFError::Other(_)));
+
+        // Errors that went through a `Box<dyn Error>` can still be told apart
+        let boxed: Box<dyn Error> = Box::new(KFError::Conflict{ url });
+        assert!(matches!(KFError::from(boxed), KFError::Conflict{ .. }));
+    }
+}
@@ -0,0 +1,195 @@
+//! This module provides a source of calendars that are synced with an [Etebase](https://www.etebase.com/) server (e.g. EteSync), whose data is end-to-end encrypted (see [`Etebase`])
+
+use std::error::Error;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use csscolorparser::Color;
+use url::Url;
+
+use crate::resource::Resource;
+use crate::calendar::etebase_calendar::EtebaseCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarTimezone;
+use crate::client::ServerCapabilities;
+use crate::traits::CalDavSource;
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::error::KFError;
+
+/// The type of the collections of events, as used by EteSync apps
+pub const EVENTS_TYPE: &str = "etebase.vevent";
+/// The type of the collections of tasks, as used by EteSync apps
+pub const TASKS_TYPE: &str = "etebase.vtodo";
+
+/// A collection of an Etebase account, as decrypted by an [`EtebaseAccount`]
+#[derive(Clone, Debug)]
+pub struct EtebaseCollection {
+    /// The Etebase UID of the collection
+    pub uid: String,
+    /// The type of the collection, e.g. [`TASKS_TYPE`]
+    pub collection_type: String,
+    pub name: String,
+    /// The color of the collection, e.g. `#RRGGBB`
+    pub color: Option<String>,
+}
+
+/// An item of a collection, as decrypted by an [`EtebaseAccount`]
+#[derive(Clone, Debug)]
+pub struct EtebaseItem {
+    /// The Etebase UID of the item. It is chosen by Etebase, and is unrelated to the UID of the iCal item
+    pub uid: String,
+    /// The etag of the item, that changes every time the item is changed
+    pub etag: String,
+    /// The iCal file of the item (empty for deleted items)
+    pub content: String,
+    pub deleted: bool,
+}
+
+/// Changes made to the items of a collection
+#[derive(Clone, Debug)]
+pub struct EtebaseChanges {
+    pub items: Vec<EtebaseItem>,
+    /// The sync token to ask for the next changes with
+    pub stoken: Option<String>,
+    /// Whether every change has been returned, or whether more of them must be fetched from `stoken`
+    pub done: bool,
+}
+
+/// An Etebase account that is logged in, that encrypts and decrypts the data of its collections.
+///
+/// Etebase data is encrypted on the client with libsodium, so that this crate leaves the cryptography and the network protocol to the [`etebase`](https://docs.rs/etebase) crate:
+/// apps implement this trait with an `etebase::Account` (its collection and item managers map one-to-one to these functions), and hand it to an [`Etebase`] source. \
+/// Since the `etebase` crate is blocking, implementations are expected to run its calls with e.g. `tokio::task::spawn_blocking`.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait EtebaseAccount: Debug + Send + Sync {
+    /// List the collections of the account (including the ones shared with it)
+    async fn list_collections(&self) -> Result<Vec<EtebaseCollection>, Box<dyn Error + Send + Sync>>;
+
+    /// Create a collection
+    async fn create_collection(&self, collection_type: &str, name: &str) -> Result<EtebaseCollection, Box<dyn Error + Send + Sync>>;
+
+    /// List the items of a collection that have changed since `stoken`, or every item when `stoken` is `None` (deleted items may be omitted then)
+    async fn fetch_changes(&self, collection_uid: &str, stoken: Option<&str>) -> Result<EtebaseChanges, Box<dyn Error + Send + Sync>>;
+
+    /// Create an item whose content is an iCal file
+    async fn create_item(&self, collection_uid: &str, content: &str) -> Result<EtebaseItem, Box<dyn Error + Send + Sync>>;
+
+    /// Change the content of an item, or delete it, in a transaction that only succeeds if the item still has the etag `item.etag`.
+    ///
+    /// Failed transactions should be reported as [`KFError::Conflict`](crate::error::KFError::Conflict)s, so that the conflict is resolved like any other one
+    async fn update_item(&self, collection_uid: &str, item: &EtebaseItem) -> Result<EtebaseItem, Box<dyn Error + Send + Sync>>;
+}
+
+/// The calendars of an Etebase account, whose items are end-to-end encrypted.
+///
+/// This source can be used instead of a [`Client`](crate::Client) in a [`Provider`](crate::provider::Provider) (see [`EtebaseProvider`](crate::EtebaseProvider)),
+/// so that the decrypted items are synced into a local cache just like CalDAV ones. See [`EtebaseAccount`] for how the account is logged in. \
+/// The URLs of the collections and items of this source are built from the URL of the server and their Etebase UIDs: they are only used to tell them apart, and cannot be requested.
+#[derive(Debug)]
+pub struct Etebase {
+    url: Url,
+    account: Arc<dyn EtebaseAccount>,
+    calendars: Mutex<Option<HashMap<Url, Arc<Mutex<EtebaseCalendar>>>>>,
+}
+
+impl Etebase {
+    /// Sync the collections of `account`, that is logged in the Etebase server at `url`
+    pub fn new<S: AsRef<str>>(url: S, account: Arc<dyn EtebaseAccount>) -> Result<Self, KFError> {
+        Ok(Self {
+            url: Url::parse(url.as_ref())?,
+            account,
+            calendars: Mutex::new(None),
+        })
+    }
+
+    /// The URL of the calendar of the collection that has a given Etebase UID
+    pub fn collection_url(&self, uid: &str) -> Url {
+        let mut url = self.url.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().extend(&["collections", uid, ""]);
+        }
+        url
+    }
+
+    /// Create a new collection, of events or of tasks.
+    ///
+    /// Etebase chooses the UIDs of collections, so that they cannot be created by [`CalDavSource::create_calendar`]. Apps should create them with this function instead,
+    /// and the next sync of a [`Provider`](crate::provider::Provider) will create their local counterparts
+    pub async fn create_collection(&self, name: &str, supported_components: SupportedComponents) -> Result<Arc<Mutex<EtebaseCalendar>>, KFError> {
+        let collection_type = match supported_components {
+            SupportedComponents::TODO => TASKS_TYPE,
+            SupportedComponents::EVENT => EVENTS_TYPE,
+            _ => return Err(format!("Etebase collections contain either events or tasks, not {}", supported_components.ical_names()).into()),
+        };
+        let collection = self.account.create_collection(collection_type, name).await?;
+        let calendar = Arc::new(Mutex::new(self.calendar(collection)));
+
+        let mut calendars = self.calendars.lock().unwrap();
+        if let Some(calendars) = calendars.as_mut() {
+            let url = calendar.lock().unwrap().url().clone();
+            calendars.insert(url, calendar.clone());
+        }
+        Ok(calendar)
+    }
+
+    fn calendar(&self, collection: EtebaseCollection) -> EtebaseCalendar {
+        let supported_components = match collection.collection_type.as_str() {
+            EVENTS_TYPE => SupportedComponents::EVENT,
+            _ => SupportedComponents::TODO,
+        };
+        let color = collection.color.as_deref().and_then(|color| csscolorparser::parse(color).ok());
+        let resource = Resource::new(self.collection_url(&collection.uid), String::new(), String::new());
+        EtebaseCalendar::new(collection.name, resource, supported_components, color)
+            .with_account(self.account.clone(), collection.uid)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<EtebaseCalendar> for Etebase {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<EtebaseCalendar>>>, KFError> {
+        if let Some(calendars) = &*self.calendars.lock().unwrap() {
+            return Ok(calendars.clone());
+        }
+        let collections = self.account.list_collections().await?;
+        let calendars: HashMap<_, _> = collections.into_iter()
+            // Other apps store other kinds of data (e.g. contacts) in the same account
+            .filter(|collection| collection.collection_type == EVENTS_TYPE || collection.collection_type == TASKS_TYPE)
+            .map(|collection| {
+                let calendar = self.calendar(collection);
+                (calendar.url().clone(), Arc::new(Mutex::new(calendar)))
+            })
+            .collect();
+        *self.calendars.lock().unwrap() = Some(calendars.clone());
+        Ok(calendars)
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<EtebaseCalendar>>> {
+        self.get_calendars().await.ok()?.get(url).cloned()
+    }
+
+    async fn create_calendar(&mut self, url: Url, _name: String, _supported_components: SupportedComponents, _color: Option<Color>) -> Result<Arc<Mutex<EtebaseCalendar>>, KFError> {
+        Err(format!("Calendar {} cannot be created: Etebase chooses the UIDs of collections (see Etebase::create_collection)", url).into())
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<EtebaseCalendar>>, KFError> {
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        // This is not a CalDAV server
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        Ok(())
+    }
+}
@@ -1,58 +1,247 @@
 //! Calendar events (iCal `VEVENT` items)
 
+use std::collections::BTreeSet;
+use std::error::Error;
+
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+#[cfg(any(feature = "google", feature = "graph", feature = "ews", feature = "jmap"))]
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use ical::property::Property;
 use url::Url;
 
-use crate::item::SyncStatus;
+use crate::Item;
+use crate::item::{FieldChange, ItemField, SyncStatus};
+use crate::calendar::SupportedComponents;
+use crate::calendar::cached_calendar::CachedCalendar;
+use crate::alarm::Alarm;
+use crate::traits::BaseCalendar;
+use crate::task::{content_property_values, same_content_properties};
+use crate::uid;
 
-/// TODO: implement `Event` one day.
-/// This crate currently only supports tasks, not calendar events.
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// A calendar event
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Event {
+    /// The event URL. It is unrelated to its UID, since servers may name their items as they like
+    url: Url,
+    /// Persistent, globally unique identifier for the calendar component
     uid: String,
-    name: String,
+
+    /// The sync status of this item
     sync_status: SyncStatus,
+    /// The time this item was created, if known
+    creation_date: Option<DateTime<Utc>>,
+    /// The last time this item was modified
+    last_modified: DateTime<Utc>,
+
+    /// The display name of the event (its `SUMMARY`)
+    name: String,
+
+    /// The PRODID, as defined in iCal files
+    ical_prod_id: String,
+
+    /// The other properties of the event (e.g. `DTSTART`, `DTEND`, `LOCATION` or `RRULE`), as they have been parsed.
+    /// They are needed to serialize this item into an equivalent iCal file
+    extra_parameters: Vec<Property>,
+
+    /// The `VALARM` sub-components of this event
+    #[serde(default)]
+    alarms: Vec<Alarm>,
 }
 
 impl Event {
-    pub fn new() -> Self {
-        unimplemented!();
+    /// Create a brand new event that is not on a server yet, from its `DTSTART` and `DTEND` properties.
+    /// This will pick a new (random) UID.
+    pub fn new(name: String, start: Property, end: Property, parent_calendar_url: &Url) -> Self {
+        let new_uid = uid::new_uid();
+        let new_url = uid::new_item_url(parent_calendar_url, &new_uid);
+        Self::new_with_parameters(name, new_uid, new_url, SyncStatus::NotSynced, Some(Utc::now()), Utc::now(),
+            crate::ical::default_prod_id(), vec![start, end])
     }
 
-    pub fn url(&self) -> &Url {
-        unimplemented!();
+    /// Create a new Event instance, that may be synced on the server already
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_parameters(name: String, uid: String, new_url: Url,
+                               sync_status: SyncStatus, creation_date: Option<DateTime<Utc>>, last_modified: DateTime<Utc>,
+                               ical_prod_id: String, extra_parameters: Vec<Property>,
+                            ) -> Self
+    {
+        Self {
+            url: new_url,
+            uid,
+            name,
+            sync_status,
+            creation_date,
+            last_modified,
+            ical_prod_id,
+            extra_parameters,
+            alarms: Vec::new(),
+        }
     }
 
-    pub fn uid(&self) -> &str {
-        &self.uid
+    pub fn url(&self) -> &Url       { &self.url         }
+    pub fn uid(&self) -> &str       { &self.uid         }
+    pub fn name(&self) -> &str      { &self.name        }
+    pub fn ical_prod_id(&self) -> &str            { &self.ical_prod_id }
+    pub fn sync_status(&self) -> &SyncStatus      { &self.sync_status  }
+    pub fn last_modified(&self) -> &DateTime<Utc> { &self.last_modified }
+    pub fn creation_date(&self) -> Option<&DateTime<Utc>> { self.creation_date.as_ref() }
+    pub fn extra_parameters(&self) -> &[Property] { &self.extra_parameters }
+    pub fn alarms(&self) -> &[Alarm]              { &self.alarms }
+
+    /// The first property of this event that has a given name (e.g. `DTSTART` or `LOCATION`)
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.extra_parameters.iter().find(|prop| prop.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The value of the first property of this event that has a given name
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.property(name).and_then(|prop| prop.value.as_deref())
+    }
+
+    /// When this event starts (its `DTSTART`). Whole-day events start at midnight UTC
+    pub fn start(&self) -> Option<DateTime<Utc>> {
+        self.value("DTSTART").and_then(crate::ical::parse_date_value)
     }
 
-    pub fn name(&self) -> &str {
-        &self.name
+    /// When this event ends (its `DTEND`), if it is set
+    pub fn end(&self) -> Option<DateTime<Utc>> {
+        self.value("DTEND").and_then(crate::ical::parse_date_value)
     }
 
-    pub fn ical_prod_id(&self) -> &str {
-        unimplemented!()
+    /// The `DTEND` of this event, that is computed from its `DURATION` (or from the default durations of RFC 5545) when it has none.
+    /// This is useful for APIs that require an end date
+    #[cfg(any(feature = "google", feature = "graph", feature = "ews", feature = "jmap"))]
+    pub(crate) fn end_or_default(&self) -> Option<Property> {
+        if let Some(end) = self.property("DTEND") {
+            return Some(end.clone());
+        }
+        let start = self.property("DTSTART")?;
+        let value = start.value.as_deref()?;
+        let duration = self.value("DURATION").and_then(|duration| crate::ical::parse_duration(duration).ok());
+        let end = match value.len() {
+            8 => {
+                let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+                // Whole-day events last one day
+                (date + duration.unwrap_or_else(|| Duration::days(1))).format("%Y%m%d").to_string()
+            },
+            _ => {
+                let date_time = NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S").ok()?;
+                let suffix = if value.ends_with('Z') { "Z" } else { "" };
+                format!("{}{}", (date_time + duration.unwrap_or_else(Duration::zero)).format("%Y%m%dT%H%M%S"), suffix)
+            },
+        };
+        Some(Property{ name: "DTEND".to_string(), params: start.params.clone(), value: Some(end) })
     }
 
-    pub fn creation_date(&self) -> Option<&DateTime<Utc>> {
-        unimplemented!()
+    #[cfg(any(test, feature = "integration_tests"))]
+    pub fn has_same_observable_content_as(&self, other: &Event) -> bool {
+           self.url == other.url
+        && self.uid == other.uid
+        && self.name == other.name
+        // sync status must be the same variant, but we ignore its embedded version tag
+        && std::mem::discriminant(&self.sync_status) == std::mem::discriminant(&other.sync_status)
     }
 
-    pub fn last_modified(&self) -> &DateTime<Utc> {
-        unimplemented!()
+    /// Returns whether both events have the same content, ignoring their sync metadata. See [`Task::content_eq`](crate::Task::content_eq)
+    pub fn content_eq(&self, other: &Event) -> bool {
+           self.uid == other.uid
+        && self.name == other.name
+        && same_content_properties(&self.extra_parameters, &other.extra_parameters)
+        && self.alarms == other.alarms
     }
 
-    pub fn sync_status(&self) -> &SyncStatus {
-        &self.sync_status
+    /// The fields that differ between this event and `other`. See [`Task::diff`](crate::Task::diff)
+    pub fn diff(&self, other: &Event) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        FieldChange::push_if_different(&mut changes, ItemField::Uid, Some(self.uid.clone()), Some(other.uid.clone()));
+        FieldChange::push_if_different(&mut changes, ItemField::Name, Some(self.name.clone()), Some(other.name.clone()));
+
+        let mut old_values = content_property_values(&self.extra_parameters);
+        let mut new_values = content_property_values(&other.extra_parameters);
+        let names: BTreeSet<String> = old_values.keys().chain(new_values.keys()).cloned().collect();
+        for name in names {
+            let (old, new) = (old_values.remove(&name), new_values.remove(&name));
+            FieldChange::push_if_different(&mut changes, ItemField::Property(name), old, new);
+        }
+        changes
     }
+
+    /// A copy of this event, that can be added to the calendar at `calendar_url` as a new event. \
+    /// The copy has a new UID and URL, it has never been synced, and its `SEQUENCE` is reset
+    pub fn duplicate(&self, calendar_url: &Url) -> Event {
+        let extra_parameters = self.extra_parameters.iter()
+            .filter(|prop| !prop.name.eq_ignore_ascii_case("SEQUENCE"))
+            .cloned()
+            .collect();
+        let new_uid = uid::new_uid();
+        let new_url = uid::new_item_url(calendar_url, &new_uid);
+        let mut copy = Self::new_with_parameters(self.name.clone(), new_uid, new_url, SyncStatus::NotSynced, Some(Utc::now()), Utc::now(),
+            self.ical_prod_id.clone(), extra_parameters);
+        copy.alarms = self.alarms.clone();
+        copy
+    }
+
+    /// Add a copy of this event (see [`Event::duplicate`]) to `calendar`. It will be uploaded at the next sync. \
+    /// Returns the URL of the copy
+    pub fn duplicate_into(&self, calendar: &mut CachedCalendar) -> Result<Url, Box<dyn Error>> {
+        if !calendar.supported_components().contains(SupportedComponents::EVENT) {
+            return Err(format!("Calendar {} does not support events", calendar.url()).into());
+        }
+        let copy = self.duplicate(calendar.url());
+        let url = copy.url().clone();
+        calendar.add_item_sync(Item::Event(copy))?;
+        Ok(url)
+    }
+
+    /// A copy of this event at another URL (e.g. in another calendar), with the same UID, alarms and iCal data
+    pub(crate) fn moved_to(&self, new_url: Url) -> Event {
+        Event { url: new_url, ..self.clone() }
+    }
+
+    pub(crate) fn set_alarms(&mut self, alarms: Vec<Alarm>) {
+        self.alarms = alarms;
+    }
+
     pub fn set_sync_status(&mut self, new_status: SyncStatus) {
         self.sync_status = new_status;
     }
 
-    #[cfg(any(test, feature = "integration_tests"))]
-    pub fn has_same_observable_content_as(&self, _other: &Event) -> bool {
-        unimplemented!();
+    fn update_sync_status(&mut self) {
+        match &self.sync_status {
+            SyncStatus::NotSynced => (),
+            SyncStatus::LocallyModified(_) => (),
+            SyncStatus::Synced(prev_vt) => {
+                self.sync_status = SyncStatus::LocallyModified(prev_vt.clone());
+            }
+            SyncStatus::LocallyDeleted(_) => {
+                log::warn!("Trying to update an item that has previously been deleted. These changes will probably be ignored at next sync.");
+            },
+        }
+    }
+
+    /// Rename an event.
+    /// This updates its "last modified" field
+    pub fn set_name(&mut self, new_name: String) {
+        self.update_sync_status();
+        self.last_modified = Utc::now();
+        self.name = new_name;
+    }
+
+    /// Replace the properties that have the same name as `property` (e.g. `DTSTART` or `LOCATION`) by `property`.
+    /// This updates its "last modified" field
+    pub fn set_property(&mut self, property: Property) {
+        self.update_sync_status();
+        self.last_modified = Utc::now();
+        self.extra_parameters.retain(|prop| !prop.name.eq_ignore_ascii_case(&property.name));
+        self.extra_parameters.push(property);
+    }
+
+    /// Remove the properties that have a given name.
+    /// This updates its "last modified" field
+    pub fn remove_property(&mut self, name: &str) {
+        self.update_sync_status();
+        self.last_modified = Utc::now();
+        self.extra_parameters.retain(|prop| !prop.name.eq_ignore_ascii_case(name));
     }
 }
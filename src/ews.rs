@@ -0,0 +1,358 @@
+//! This module provides sources that are synced with an Exchange server through [Exchange Web Services](https://learn.microsoft.com/en-us/exchange/client-developer/web-service-reference/ews-reference-for-exchange):
+//! the task folders of a mailbox (see [`Ews`]), and its calendar folders of appointments and meetings (see [`EwsCalendars`])
+//!
+//! Only the Tasks and Calendar folders of the mailbox, and the subfolders of them, are supported.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use csscolorparser::Color;
+use minidom::Element;
+use reqwest::Method;
+use reqwest::header::CONTENT_TYPE;
+use url::Url;
+
+use crate::resource::Resource;
+use crate::calendar::ews_calendar::{EwsCalendar, EwsTaskFolder};
+use crate::calendar::remote_calendar::SendError;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarTimezone;
+use crate::client::ServerCapabilities;
+use crate::error::KFError;
+use crate::traits::CalDavSource;
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::utils::{find_elem, find_elems, xml_escape};
+
+/// The version of the EWS schema that requests are written in
+const REQUEST_SERVER_VERSION: &str = "Exchange2013";
+
+/// The task folders of an Exchange mailbox.
+///
+/// This source can be used instead of a [`Client`](crate::Client) in a [`Provider`](crate::provider::Provider) (see [`EwsProvider`](crate::EwsProvider)),
+/// so that on-premises Exchange servers, that have no CalDAV access, can be synced. \
+/// Each task folder is an [`EwsTaskFolder`]: see its documentation for how tasks are mapped to iCal items.
+///
+/// Every request is sent to the EWS endpoint of the server (usually `https://<server>/EWS/Exchange.asmx`), with basic authentication. \
+/// The URLs of the folders and items of this source are built from this endpoint and their EWS IDs: they are only used to tell them apart, and cannot be requested.
+#[derive(Debug)]
+pub struct Ews {
+    resource: Resource,
+    calendars: Mutex<Option<HashMap<Url, Arc<Mutex<EwsTaskFolder>>>>>,
+}
+
+impl Ews {
+    /// Use the EWS endpoint at `url`
+    pub fn new<S: AsRef<str>, T: ToString, U: ToString>(url: S, username: T, password: U) -> Result<Self, KFError> {
+        let url = Url::parse(url.as_ref())?;
+        Ok(Self {
+            resource: Resource::new(url, username.to_string(), password.to_string()),
+            calendars: Mutex::new(None),
+        })
+    }
+
+    /// The URL of the calendar of the task folder that has a given EWS ID
+    pub fn task_folder_url(&self, id: &str) -> Url {
+        folder_url(self.resource.url(), id)
+    }
+
+    /// Create a new task folder, in the default Tasks folder.
+    ///
+    /// Exchange chooses the IDs of folders, so that they cannot be created by [`CalDavSource::create_calendar`]. Apps should create them with this function instead,
+    /// and the next sync of a [`Provider`](crate::provider::Provider) will create their local counterparts
+    pub async fn create_task_folder(&self, name: &str) -> Result<Arc<Mutex<EwsTaskFolder>>, KFError> {
+        let id = create_folder(&self.resource, "tasks", "TasksFolder", name).await?;
+        let calendar = Arc::new(Mutex::new(self.task_folder(&id, name.to_string())));
+
+        let mut calendars = self.calendars.lock().unwrap();
+        if let Some(calendars) = calendars.as_mut() {
+            let url = calendar.lock().unwrap().url().clone();
+            calendars.insert(url, calendar.clone());
+        }
+        Ok(calendar)
+    }
+
+    fn task_folder(&self, id: &str, name: String) -> EwsTaskFolder {
+        let resource = self.resource.combine(self.task_folder_url(id).path());
+        EwsTaskFolder::new(name, resource, SupportedComponents::TODO, None)
+    }
+
+    async fn fetch_task_folders(&self) -> Result<HashMap<Url, Arc<Mutex<EwsTaskFolder>>>, KFError> {
+        let mut calendars = HashMap::new();
+        for (id, name) in fetch_folders(&self.resource, "tasks", "TasksFolder").await? {
+            let calendar = self.task_folder(&id, name);
+            calendars.insert(calendar.url().clone(), Arc::new(Mutex::new(calendar)));
+        }
+        Ok(calendars)
+    }
+}
+
+/// The calendar folders of an Exchange mailbox.
+///
+/// This source can be used instead of a [`Client`](crate::Client) in a [`Provider`](crate::provider::Provider) (see [`EwsCalendarProvider`](crate::EwsCalendarProvider)),
+/// so that the calendar items (i.e. appointments and meetings) of on-premises Exchange servers, that have no CalDAV access, can be synced. \
+/// Each calendar folder is an [`EwsCalendar`]: see its documentation for how calendar items are mapped to iCal events.
+///
+/// Requests are sent like the ones of an [`Ews`] source, and the URLs of folders and items are built the same way.
+#[derive(Debug)]
+pub struct EwsCalendars {
+    resource: Resource,
+    calendars: Mutex<Option<HashMap<Url, Arc<Mutex<EwsCalendar>>>>>,
+}
+
+impl EwsCalendars {
+    /// Use the EWS endpoint at `url`
+    pub fn new<S: AsRef<str>, T: ToString, U: ToString>(url: S, username: T, password: U) -> Result<Self, KFError> {
+        let url = Url::parse(url.as_ref())?;
+        Ok(Self {
+            resource: Resource::new(url, username.to_string(), password.to_string()),
+            calendars: Mutex::new(None),
+        })
+    }
+
+    /// The URL of the calendar of the calendar folder that has a given EWS ID
+    pub fn calendar_folder_url(&self, id: &str) -> Url {
+        folder_url(self.resource.url(), id)
+    }
+
+    /// Create a new calendar folder, in the default Calendar folder.
+    ///
+    /// Like task folders, calendar folders cannot be created by [`CalDavSource::create_calendar`] (see [`Ews::create_task_folder`])
+    pub async fn create_calendar_folder(&self, name: &str) -> Result<Arc<Mutex<EwsCalendar>>, KFError> {
+        let id = create_folder(&self.resource, "calendar", "CalendarFolder", name).await?;
+        let calendar = Arc::new(Mutex::new(self.calendar_folder(&id, name.to_string())));
+
+        let mut calendars = self.calendars.lock().unwrap();
+        if let Some(calendars) = calendars.as_mut() {
+            let url = calendar.lock().unwrap().url().clone();
+            calendars.insert(url, calendar.clone());
+        }
+        Ok(calendar)
+    }
+
+    fn calendar_folder(&self, id: &str, name: String) -> EwsCalendar {
+        let resource = self.resource.combine(self.calendar_folder_url(id).path());
+        EwsCalendar::new(name, resource, SupportedComponents::EVENT, None)
+    }
+
+    async fn fetch_calendar_folders(&self) -> Result<HashMap<Url, Arc<Mutex<EwsCalendar>>>, KFError> {
+        let mut calendars = HashMap::new();
+        for (id, name) in fetch_folders(&self.resource, "calendar", "CalendarFolder").await? {
+            let calendar = self.calendar_folder(&id, name);
+            calendars.insert(calendar.url().clone(), Arc::new(Mutex::new(calendar)));
+        }
+        Ok(calendars)
+    }
+}
+
+/// The URL of the folder that has a given EWS ID, from the URL of an EWS endpoint
+fn folder_url(endpoint: &Url, id: &str) -> Url {
+    let mut url = endpoint.clone();
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments.pop_if_empty().extend(&["folders", id, ""]);
+    }
+    url
+}
+
+/// Create a folder (as a `t:{folder_element}` element) in a distinguished folder (e.g. `tasks`), and return its EWS ID
+async fn create_folder(resource: &Resource, parent_id: &str, folder_element: &str, name: &str) -> Result<String, KFError> {
+    let body = format!(r#"<m:CreateFolder>
+            <m:ParentFolderId><t:DistinguishedFolderId Id="{0}"/></m:ParentFolderId>
+            <m:Folders><t:{1}><t:DisplayName>{2}</t:DisplayName></t:{1}></m:Folders>
+        </m:CreateFolder>"#, parent_id, folder_element, xml_escape(name));
+    let reply = soap_request(resource, &body).await?;
+    let message = response_messages(&reply, resource.url()).into_iter().next()
+        .ok_or("Invalid reply to CreateFolder")?
+        ?;
+    let id = find_elem(message, "FolderId").and_then(|id| id.attr("Id"))
+        .ok_or("Invalid reply to CreateFolder")?;
+    Ok(id.to_string())
+}
+
+/// The IDs and names of a distinguished folder (e.g. `tasks`) and of its subfolders that are `t:{folder_element}` elements
+async fn fetch_folders(resource: &Resource, distinguished_id: &str, folder_element: &str) -> Result<Vec<(String, String)>, KFError> {
+    // FindFolder does not return the folder it starts from
+    let body = format!(r#"<m:GetFolder>
+            <m:FolderShape><t:BaseShape>Default</t:BaseShape></m:FolderShape>
+            <m:FolderIds><t:DistinguishedFolderId Id="{}"/></m:FolderIds>
+        </m:GetFolder>"#, distinguished_id);
+    let default_folder = soap_request(resource, &body).await?;
+    let body = format!(r#"<m:FindFolder Traversal="Deep">
+            <m:FolderShape><t:BaseShape>Default</t:BaseShape></m:FolderShape>
+            <m:ParentFolderIds><t:DistinguishedFolderId Id="{}"/></m:ParentFolderIds>
+        </m:FindFolder>"#, distinguished_id);
+    let subfolders = soap_request(resource, &body).await?;
+
+    let mut folders = Vec::new();
+    for reply in [&default_folder, &subfolders] {
+        for message in response_messages(reply, resource.url()) {
+            for folder in find_elems(message?, folder_element) {
+                let id = match find_elem(folder, "FolderId").and_then(|id| id.attr("Id")) {
+                    None => continue,
+                    Some(id) => id,
+                };
+                let name = find_elem(folder, "DisplayName").map(|name| name.text()).unwrap_or_default();
+                folders.push((id.to_string(), name));
+            }
+        }
+    }
+    Ok(folders)
+}
+
+/// Send an EWS request (i.e. the content of a SOAP body) to the endpoint of `resource`, and return the SOAP envelope of its reply
+pub(crate) async fn soap_request(resource: &Resource, body: &str) -> Result<Element, SendError> {
+    let envelope = format!(r#"<?xml version="1.0" encoding="utf-8"?>
+<soap:Envelope xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/" xmlns:t="http://schemas.microsoft.com/exchange/services/2006/types" xmlns:m="http://schemas.microsoft.com/exchange/services/2006/messages">
+    <soap:Header><t:RequestServerVersion Version="{}"/></soap:Header>
+    <soap:Body>{}</soap:Body>
+</soap:Envelope>"#, REQUEST_SERVER_VERSION, body);
+
+    let url = resource.url().clone();
+    let request = resource.request(Method::POST, url.clone())
+        .header(CONTENT_TYPE, "text/xml; charset=utf-8")
+        .body(envelope);
+    let response = resource.send(request).await.map_err(crate::calendar::remote_calendar::sendable)?;
+    let status = response.status();
+    let text = response.text().await?;
+    // SOAP faults are sent with a 500 status code
+    if !status.is_success() && !text.contains("Fault") {
+        return Err(Box::new(KFError::from_status(status, url)));
+    }
+    let root: Element = text.parse().map_err(|err| format!("Invalid EWS reply from {}: {}", url, err))?;
+    if let Some(fault) = find_elem(&root, "Fault") {
+        let reason = find_elem(fault, "faultstring").map(|reason| reason.text()).unwrap_or_default();
+        return Err(format!("EWS request to {} failed: {}", url, reason).into());
+    }
+    Ok(root)
+}
+
+/// The response messages of an EWS reply (one per item or folder of the request, in the same order), or the errors they report
+///
+/// `url` is the URL errors are about
+pub(crate) fn response_messages<'a>(reply: &'a Element, url: &Url) -> Vec<Result<&'a Element, SendError>> {
+    let messages = match find_elem(reply, "ResponseMessages") {
+        None => return Vec::new(),
+        Some(messages) => messages,
+    };
+    messages.children()
+        .map(|message| match message.attr("ResponseClass") {
+            Some("Error") => Err(response_error(message, url)),
+            _ => Ok(message),
+        })
+        .collect()
+}
+
+fn response_error(message: &Element, url: &Url) -> SendError {
+    let code = find_elem(message, "ResponseCode").map(|code| code.text()).unwrap_or_default();
+    match code.as_str() {
+        "ErrorIrresolvableConflict" => Box::new(KFError::Conflict{ url: url.clone() }),
+        "ErrorItemNotFound" | "ErrorFolderNotFound" => Box::new(KFError::NotFound{ url: url.clone() }),
+        "ErrorAccessDenied" => Box::new(KFError::Forbidden{ url: url.clone() }),
+        _ => {
+            let text = find_elem(message, "MessageText").map(|text| text.text()).unwrap_or_default();
+            format!("EWS error {} for {}: {}", code, url, text).into()
+        },
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<EwsTaskFolder> for Ews {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<EwsTaskFolder>>>, KFError> {
+        if let Some(calendars) = &*self.calendars.lock().unwrap() {
+            return Ok(calendars.clone());
+        }
+        let calendars = self.fetch_task_folders().await?;
+        *self.calendars.lock().unwrap() = Some(calendars.clone());
+        Ok(calendars)
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<EwsTaskFolder>>> {
+        self.get_calendars().await.ok()?.get(url).cloned()
+    }
+
+    async fn create_calendar(&mut self, url: Url, _name: String, _supported_components: SupportedComponents, _color: Option<Color>) -> Result<Arc<Mutex<EwsTaskFolder>>, KFError> {
+        Err(format!("Calendar {} cannot be created: Exchange chooses the IDs of folders (see Ews::create_task_folder)", url).into())
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<EwsTaskFolder>>, KFError> {
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        // This is not a CalDAV server
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        Ok(())
+    }
+}
+
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<EwsCalendar> for EwsCalendars {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<EwsCalendar>>>, KFError> {
+        if let Some(calendars) = &*self.calendars.lock().unwrap() {
+            return Ok(calendars.clone());
+        }
+        let calendars = self.fetch_calendar_folders().await?;
+        *self.calendars.lock().unwrap() = Some(calendars.clone());
+        Ok(calendars)
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<EwsCalendar>>> {
+        self.get_calendars().await.ok()?.get(url).cloned()
+    }
+
+    async fn create_calendar(&mut self, url: Url, _name: String, _supported_components: SupportedComponents, _color: Option<Color>) -> Result<Arc<Mutex<EwsCalendar>>, KFError> {
+        Err(format!("Calendar {} cannot be created: Exchange chooses the IDs of folders (see EwsCalendars::create_calendar_folder)", url).into())
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<EwsCalendar>>, KFError> {
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        // This is not a CalDAV server
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        Ok(())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_messages() {
+        let url: Url = "https://mail.example.com/EWS/Exchange.asmx".parse().unwrap();
+        let reply: Element = r#"<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body>
+            <m:GetItemResponse xmlns:m="http://schemas.microsoft.com/exchange/services/2006/messages"><m:ResponseMessages>
+                <m:GetItemResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode></m:GetItemResponseMessage>
+                <m:GetItemResponseMessage ResponseClass="Error"><m:ResponseCode>ErrorItemNotFound</m:ResponseCode></m:GetItemResponseMessage>
+                <m:GetItemResponseMessage ResponseClass="Error"><m:ResponseCode>ErrorIrresolvableConflict</m:ResponseCode></m:GetItemResponseMessage>
+            </m:ResponseMessages></m:GetItemResponse>
+        </s:Body></s:Envelope>"#.parse().unwrap();
+        let messages = response_messages(&reply, &url);
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].is_ok());
+        assert!(matches!(messages[1].as_ref().unwrap_err().downcast_ref::<KFError>(), Some(KFError::NotFound{ .. })));
+        assert!(matches!(messages[2].as_ref().unwrap_err().downcast_ref::<KFError>(), Some(KFError::Conflict{ .. })));
+    }
+}
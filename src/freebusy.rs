@@ -0,0 +1,61 @@
+//! Free/busy information (iCal `VFREEBUSY` components), as returned by a CalDAV `free-busy-query` REPORT
+
+use chrono::{DateTime, Utc};
+
+/// The kind of a busy period (the `FBTYPE` parameter of a `FREEBUSY` property)
+#[derive(Clone, Debug, PartialEq)]
+pub enum FreeBusyType {
+    Free,
+    Busy,
+    BusyUnavailable,
+    BusyTentative,
+    /// Any other (e.g. experimental) value
+    Other(String),
+}
+
+impl FreeBusyType {
+    pub(crate) fn from_fbtype(value: &str) -> Self {
+        match value.to_ascii_uppercase().as_str() {
+            "FREE" => Self::Free,
+            "BUSY" => Self::Busy,
+            "BUSY-UNAVAILABLE" => Self::BusyUnavailable,
+            "BUSY-TENTATIVE" => Self::BusyTentative,
+            _ => Self::Other(value.to_string()),
+        }
+    }
+}
+
+impl Default for FreeBusyType {
+    /// RFC5545 states the default value is `BUSY`
+    fn default() -> Self {
+        Self::Busy
+    }
+}
+
+/// A time interval
+#[derive(Clone, Debug, PartialEq)]
+pub struct FreeBusyPeriod {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub fb_type: FreeBusyType,
+}
+
+/// The availability of a calendar over a given time range
+#[derive(Clone, Debug, PartialEq)]
+pub struct FreeBusy {
+    /// Start of the range the server has computed the availability for, if it told us
+    pub start: Option<DateTime<Utc>>,
+    /// End of the range the server has computed the availability for, if it told us
+    pub end: Option<DateTime<Utc>>,
+    /// The periods of time that are not free. Everything else in the range is free.
+    pub periods: Vec<FreeBusyPeriod>,
+}
+
+impl FreeBusy {
+    /// Returns whether the given time is in a busy period (of any kind)
+    pub fn is_busy_at(&self, time: &DateTime<Utc>) -> bool {
+        self.periods.iter()
+            .filter(|p| p.fb_type != FreeBusyType::Free)
+            .any(|p| &p.start <= time && time < &p.end)
+    }
+}
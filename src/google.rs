@@ -0,0 +1,361 @@
+//! This module provides sources that are synced with the REST APIs of Google accounts:
+//! task lists with the [Google Tasks API](https://developers.google.com/tasks) (see [`GoogleTasks`]),
+//! and calendars of events with the [Google Calendar API](https://developers.google.com/calendar/api) (see [`GoogleCalendars`])
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use csscolorparser::Color;
+use reqwest::Method;
+use serde::Deserialize;
+use url::Url;
+
+use crate::resource::{AccessToken, Resource};
+use crate::calendar::google_calendar::{GoogleCalendar, GoogleTaskList};
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarTimezone;
+use crate::client::ServerCapabilities;
+use crate::client::transport::Transport;
+use crate::rest::send_json;
+use crate::traits::CalDavSource;
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::error::KFError;
+
+/// The root of the Google Tasks API
+pub const GOOGLE_TASKS_API: &str = "https://tasks.googleapis.com/tasks/v1/";
+
+/// The root of the Google Calendar API
+pub const GOOGLE_CALENDAR_API: &str = "https://www.googleapis.com/calendar/v3/";
+
+/// A task list, as described by the Google Tasks API
+#[derive(Debug, Deserialize)]
+struct TaskListResource {
+    id: String,
+    title: Option<String>,
+}
+
+/// A calendar of the calendar list of an account, as described by the Google Calendar API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CalendarListEntry {
+    id: String,
+    summary: Option<String>,
+    /// The name the user has given to this calendar, if it is not theirs
+    summary_override: Option<String>,
+    background_color: Option<String>,
+    access_role: Option<String>,
+}
+
+/// A calendar, as described by the Google Calendar API
+#[derive(Debug, Deserialize)]
+struct CalendarResource {
+    id: String,
+    summary: Option<String>,
+}
+
+/// A page of a listing of the Google Tasks API (or of the calendar list of the Google Calendar API)
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Page<T> {
+    #[serde(default = "Vec::new")]
+    pub items: Vec<T>,
+    pub next_page_token: Option<String>,
+}
+
+/// The task lists of a Google account.
+///
+/// This source can be used instead of a [`Client`](crate::Client) in a [`Provider`](crate::provider::Provider) (see [`GoogleTasksProvider`](crate::GoogleTasksProvider)),
+/// so that Google accounts are synced through their REST API, rather than through Google's CalDAV endpoint (which does not serve tasks). \
+/// Each task list is a [`GoogleTaskList`]: see its documentation for how tasks are mapped to iCal items.
+///
+/// This crate does not implement OAuth2 flows: apps obtain an access token (with the `https://www.googleapis.com/auth/tasks` scope) themselves,
+/// and replace it in the [`AccessToken`] when it expires.
+#[derive(Debug)]
+pub struct GoogleTasks {
+    resource: Resource,
+    calendars: Mutex<Option<HashMap<Url, Arc<Mutex<GoogleTaskList>>>>>,
+}
+
+impl GoogleTasks {
+    pub fn new(access_token: AccessToken) -> Self {
+        // This is a valid URL
+        Self::with_base_url(GOOGLE_TASKS_API.parse().unwrap(), access_token)
+    }
+
+    /// Use another root than [`GOOGLE_TASKS_API`] (e.g. a test server)
+    pub fn with_base_url(base_url: Url, access_token: AccessToken) -> Self {
+        Self {
+            resource: Resource::new_with_access_token(base_url, access_token, Transport::default()),
+            calendars: Mutex::new(None),
+        }
+    }
+
+    /// The URL of the calendar of the task list that has a given ID
+    pub fn task_list_url(&self, id: &str) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().extend(&["lists", id, "tasks", ""]);
+        }
+        url
+    }
+
+    /// Create a new task list.
+    ///
+    /// Google chooses the IDs of task lists, so that they cannot be created by [`CalDavSource::create_calendar`]. Apps should create them with this function instead,
+    /// and the next sync of a [`Provider`](crate::provider::Provider) will create their local counterparts
+    pub async fn create_task_list(&self, title: &str) -> Result<Arc<Mutex<GoogleTaskList>>, KFError> {
+        let url = self.api_url(&["users", "@me", "lists"]);
+        let body = serde_json::json!({ "title": title });
+        let reply = send_json(&self.resource, Method::POST, url, Some(&body), None).await?;
+        let list: TaskListResource = serde_json::from_str(&reply)?;
+        let calendar = Arc::new(Mutex::new(self.task_list(list)));
+
+        let mut calendars = self.calendars.lock().unwrap();
+        if let Some(calendars) = calendars.as_mut() {
+            let url = calendar.lock().unwrap().url().clone();
+            calendars.insert(url, calendar.clone());
+        }
+        Ok(calendar)
+    }
+
+    fn api_url(&self, segments: &[&str]) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(segments);
+        }
+        url
+    }
+
+    fn task_list(&self, list: TaskListResource) -> GoogleTaskList {
+        let url = self.task_list_url(&list.id);
+        let resource = Resource::new_with_access_token(url, self.access_token(), Transport::default());
+        GoogleTaskList::new(list.title.unwrap_or_default(), resource, SupportedComponents::TODO, None)
+    }
+
+    fn access_token(&self) -> AccessToken {
+        // Resources of this source are always built with a token
+        self.resource.access_token().cloned().unwrap_or_default()
+    }
+
+    async fn fetch_task_lists(&self) -> Result<HashMap<Url, Arc<Mutex<GoogleTaskList>>>, KFError> {
+        let mut calendars = HashMap::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut url = self.api_url(&["users", "@me", "lists"]);
+            url.query_pairs_mut().append_pair("maxResults", "100");
+            if let Some(token) = &page_token {
+                url.query_pairs_mut().append_pair("pageToken", token);
+            }
+            let reply = send_json(&self.resource, Method::GET, url, None, None).await?;
+            let page: Page<TaskListResource> = serde_json::from_str(&reply)?;
+            for list in page.items {
+                let calendar = self.task_list(list);
+                calendars.insert(calendar.url().clone(), Arc::new(Mutex::new(calendar)));
+            }
+            match page.next_page_token {
+                None => break,
+                token => page_token = token,
+            }
+        }
+        Ok(calendars)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<GoogleTaskList> for GoogleTasks {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<GoogleTaskList>>>, KFError> {
+        if let Some(calendars) = &*self.calendars.lock().unwrap() {
+            return Ok(calendars.clone());
+        }
+        let calendars = self.fetch_task_lists().await?;
+        *self.calendars.lock().unwrap() = Some(calendars.clone());
+        Ok(calendars)
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<GoogleTaskList>>> {
+        self.get_calendars().await.ok()?.get(url).cloned()
+    }
+
+    async fn create_calendar(&mut self, url: Url, _name: String, _supported_components: SupportedComponents, _color: Option<Color>) -> Result<Arc<Mutex<GoogleTaskList>>, KFError> {
+        Err(format!("Calendar {} cannot be created: Google chooses the URLs of task lists (see GoogleTasks::create_task_list)", url).into())
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<GoogleTaskList>>, KFError> {
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        // This is not a CalDAV server
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        Ok(())
+    }
+}
+
+
+/// The calendars of a Google account.
+///
+/// This source can be used instead of a [`Client`](crate::Client) in a [`Provider`](crate::provider::Provider) (see [`GoogleCalendarProvider`](crate::GoogleCalendarProvider)),
+/// so that the events of Google accounts are synced through their REST API, rather than through Google's CalDAV endpoint. \
+/// Each calendar of the calendar list of the account is a [`GoogleCalendar`]: see its documentation for how events are mapped to iCal items.
+/// Calendars that only share their free/busy information are not listed.
+///
+/// This crate does not implement OAuth2 flows: apps obtain an access token (with the `https://www.googleapis.com/auth/calendar` scope) themselves,
+/// and replace it in the [`AccessToken`] when it expires.
+#[derive(Debug)]
+pub struct GoogleCalendars {
+    resource: Resource,
+    calendars: Mutex<Option<HashMap<Url, Arc<Mutex<GoogleCalendar>>>>>,
+}
+
+impl GoogleCalendars {
+    pub fn new(access_token: AccessToken) -> Self {
+        // This is a valid URL
+        Self::with_base_url(GOOGLE_CALENDAR_API.parse().unwrap(), access_token)
+    }
+
+    /// Use another root than [`GOOGLE_CALENDAR_API`] (e.g. a test server)
+    pub fn with_base_url(base_url: Url, access_token: AccessToken) -> Self {
+        Self {
+            resource: Resource::new_with_access_token(base_url, access_token, Transport::default()),
+            calendars: Mutex::new(None),
+        }
+    }
+
+    /// The URL of the calendar that has a given ID (e.g. `primary`)
+    pub fn calendar_url(&self, id: &str) -> Url {
+        self.api_url(&["calendars", id, "events", ""])
+    }
+
+    /// Create a new (secondary) calendar.
+    ///
+    /// Google chooses the IDs of calendars, so that they cannot be created by [`CalDavSource::create_calendar`]. Apps should create them with this function instead,
+    /// and the next sync of a [`Provider`](crate::provider::Provider) will create their local counterparts
+    pub async fn create_google_calendar(&self, summary: &str) -> Result<Arc<Mutex<GoogleCalendar>>, KFError> {
+        let url = self.api_url(&["calendars"]);
+        let body = serde_json::json!({ "summary": summary });
+        let reply = send_json(&self.resource, Method::POST, url, Some(&body), None).await?;
+        let created: CalendarResource = serde_json::from_str(&reply)?;
+        let calendar = Arc::new(Mutex::new(self.calendar(&created.id, created.summary.unwrap_or_default(), None)));
+
+        let mut calendars = self.calendars.lock().unwrap();
+        if let Some(calendars) = calendars.as_mut() {
+            let url = calendar.lock().unwrap().url().clone();
+            calendars.insert(url, calendar.clone());
+        }
+        Ok(calendar)
+    }
+
+    fn api_url(&self, segments: &[&str]) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(segments);
+        }
+        url
+    }
+
+    fn calendar(&self, id: &str, name: String, color: Option<Color>) -> GoogleCalendar {
+        let url = self.calendar_url(id);
+        let resource = Resource::new_with_access_token(url, self.access_token(), Transport::default());
+        GoogleCalendar::new(name, resource, SupportedComponents::EVENT, color)
+    }
+
+    fn access_token(&self) -> AccessToken {
+        // Resources of this source are always built with a token
+        self.resource.access_token().cloned().unwrap_or_default()
+    }
+
+    async fn fetch_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<GoogleCalendar>>>, KFError> {
+        let mut calendars = HashMap::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut url = self.api_url(&["users", "me", "calendarList"]);
+            url.query_pairs_mut().append_pair("maxResults", "250");
+            if let Some(token) = &page_token {
+                url.query_pairs_mut().append_pair("pageToken", token);
+            }
+            let reply = send_json(&self.resource, Method::GET, url, None, None).await?;
+            let page: Page<CalendarListEntry> = serde_json::from_str(&reply)?;
+            for entry in page.items {
+                if entry.access_role.as_deref() == Some("freeBusyReader") {
+                    continue;
+                }
+                let name = entry.summary_override.or(entry.summary).unwrap_or_default();
+                let color = entry.background_color.and_then(|color| csscolorparser::parse(&color).ok());
+                let calendar = self.calendar(&entry.id, name, color);
+                calendars.insert(calendar.url().clone(), Arc::new(Mutex::new(calendar)));
+            }
+            match page.next_page_token {
+                None => break,
+                token => page_token = token,
+            }
+        }
+        Ok(calendars)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<GoogleCalendar> for GoogleCalendars {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<GoogleCalendar>>>, KFError> {
+        if let Some(calendars) = &*self.calendars.lock().unwrap() {
+            return Ok(calendars.clone());
+        }
+        let calendars = self.fetch_calendars().await?;
+        *self.calendars.lock().unwrap() = Some(calendars.clone());
+        Ok(calendars)
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<GoogleCalendar>>> {
+        self.get_calendars().await.ok()?.get(url).cloned()
+    }
+
+    async fn create_calendar(&mut self, url: Url, _name: String, _supported_components: SupportedComponents, _color: Option<Color>) -> Result<Arc<Mutex<GoogleCalendar>>, KFError> {
+        Err(format!("Calendar {} cannot be created: Google chooses the URLs of calendars (see GoogleCalendars::create_google_calendar)", url).into())
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<GoogleCalendar>>, KFError> {
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        // This is not a CalDAV server
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls() {
+        let source = GoogleTasks::new(AccessToken::new("token".to_string()));
+        assert_eq!(source.task_list_url("MDk3").as_str(), "https://tasks.googleapis.com/tasks/v1/lists/MDk3/tasks/");
+        assert_eq!(source.api_url(&["users", "@me", "lists"]).as_str(), "https://tasks.googleapis.com/tasks/v1/users/@me/lists");
+
+        let source = GoogleCalendars::new(AccessToken::new("token".to_string()));
+        let url = source.calendar_url("alice@example.com");
+        assert_eq!(url.as_str(), "https://www.googleapis.com/calendar/v3/calendars/alice@example.com/events/");
+        let calendar = source.calendar("team#holidays@group.v.calendar.google.com", "Holidays".to_string(), None);
+        assert_eq!(calendar.id(), "team#holidays@group.v.calendar.google.com");
+    }
+}
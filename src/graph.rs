@@ -0,0 +1,306 @@
+//! This module provides sources that are synced with the Microsoft Graph API:
+//! task lists of [Microsoft To Do](https://learn.microsoft.com/en-us/graph/todo-concept-overview) (see [`GraphTodo`]),
+//! and [Outlook calendars](https://learn.microsoft.com/en-us/graph/outlook-calendar-concept-overview) of events (see [`GraphCalendars`])
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use csscolorparser::Color;
+use reqwest::Method;
+use serde::Deserialize;
+use url::Url;
+
+use crate::resource::{AccessToken, Resource};
+use crate::calendar::graph_calendar::{GraphCalendar, GraphTaskList};
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarTimezone;
+use crate::client::ServerCapabilities;
+use crate::client::transport::Transport;
+use crate::rest::send_json;
+use crate::traits::CalDavSource;
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+use crate::error::KFError;
+
+/// The root of the Microsoft Graph API
+pub const GRAPH_API: &str = "https://graph.microsoft.com/v1.0/";
+
+/// A task list, as described by the Microsoft Graph API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TodoTaskList {
+    id: String,
+    display_name: Option<String>,
+}
+
+/// An Outlook calendar, as described by the Microsoft Graph API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphCalendarResource {
+    id: String,
+    name: Option<String>,
+    hex_color: Option<String>,
+}
+
+/// A page of a listing of the Microsoft Graph API
+#[derive(Debug, Deserialize)]
+pub(crate) struct Page<T> {
+    #[serde(default = "Vec::new")]
+    pub value: Vec<T>,
+    #[serde(rename = "@odata.nextLink")]
+    pub next_link: Option<Url>,
+    #[serde(rename = "@odata.deltaLink")]
+    pub delta_link: Option<Url>,
+}
+
+/// The Microsoft To Do task lists of a Microsoft account (e.g. an Outlook.com or a Microsoft 365 account).
+///
+/// This source can be used instead of a [`Client`](crate::Client) in a [`Provider`](crate::provider::Provider) (see [`GraphTodoProvider`](crate::GraphTodoProvider)),
+/// so that Microsoft accounts, that usually have no CalDAV access, can be synced through the Microsoft Graph API. \
+/// Each task list is a [`GraphTaskList`]: see its documentation for how tasks are mapped to iCal items.
+///
+/// This crate does not implement OAuth2 flows: apps obtain an access token (with the `Tasks.ReadWrite` permission) themselves,
+/// and replace it in the [`AccessToken`] when it expires.
+#[derive(Debug)]
+pub struct GraphTodo {
+    resource: Resource,
+    calendars: Mutex<Option<HashMap<Url, Arc<Mutex<GraphTaskList>>>>>,
+}
+
+impl GraphTodo {
+    pub fn new(access_token: AccessToken) -> Self {
+        // This is a valid URL
+        Self::with_base_url(GRAPH_API.parse().unwrap(), access_token)
+    }
+
+    /// Use another root than [`GRAPH_API`] (e.g. a national cloud, or a test server)
+    pub fn with_base_url(base_url: Url, access_token: AccessToken) -> Self {
+        Self {
+            resource: Resource::new_with_access_token(base_url, access_token, Transport::default()),
+            calendars: Mutex::new(None),
+        }
+    }
+
+    /// The URL of the calendar of the task list that has a given ID
+    pub fn task_list_url(&self, id: &str) -> Url {
+        self.api_url(&["me", "todo", "lists", id, "tasks", ""])
+    }
+
+    /// Create a new task list.
+    ///
+    /// Microsoft Graph chooses the IDs of task lists, so that they cannot be created by [`CalDavSource::create_calendar`]. Apps should create them with this function instead,
+    /// and the next sync of a [`Provider`](crate::provider::Provider) will create their local counterparts
+    pub async fn create_task_list(&self, name: &str) -> Result<Arc<Mutex<GraphTaskList>>, KFError> {
+        let url = self.api_url(&["me", "todo", "lists"]);
+        let body = serde_json::json!({ "displayName": name });
+        let reply = send_json(&self.resource, Method::POST, url, Some(&body), None).await?;
+        let list: TodoTaskList = serde_json::from_str(&reply)?;
+        let calendar = Arc::new(Mutex::new(self.task_list(list)));
+
+        let mut calendars = self.calendars.lock().unwrap();
+        if let Some(calendars) = calendars.as_mut() {
+            let url = calendar.lock().unwrap().url().clone();
+            calendars.insert(url, calendar.clone());
+        }
+        Ok(calendar)
+    }
+
+    fn api_url(&self, segments: &[&str]) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(segments);
+        }
+        url
+    }
+
+    fn task_list(&self, list: TodoTaskList) -> GraphTaskList {
+        let url = self.task_list_url(&list.id);
+        let resource = Resource::new_with_access_token(url, self.access_token(), Transport::default());
+        GraphTaskList::new(list.display_name.unwrap_or_default(), resource, SupportedComponents::TODO, None)
+    }
+
+    fn access_token(&self) -> AccessToken {
+        // Resources of this source are always built with a token
+        self.resource.access_token().cloned().unwrap_or_default()
+    }
+
+    async fn fetch_task_lists(&self) -> Result<HashMap<Url, Arc<Mutex<GraphTaskList>>>, KFError> {
+        let mut calendars = HashMap::new();
+        let mut url = Some(self.api_url(&["me", "todo", "lists"]));
+        while let Some(page_url) = url {
+            let reply = send_json(&self.resource, Method::GET, page_url, None, None).await?;
+            let page: Page<TodoTaskList> = serde_json::from_str(&reply)?;
+            for list in page.value {
+                let calendar = self.task_list(list);
+                calendars.insert(calendar.url().clone(), Arc::new(Mutex::new(calendar)));
+            }
+            url = page.next_link;
+        }
+        Ok(calendars)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<GraphTaskList> for GraphTodo {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<GraphTaskList>>>, KFError> {
+        if let Some(calendars) = &*self.calendars.lock().unwrap() {
+            return Ok(calendars.clone());
+        }
+        let calendars = self.fetch_task_lists().await?;
+        *self.calendars.lock().unwrap() = Some(calendars.clone());
+        Ok(calendars)
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<GraphTaskList>>> {
+        self.get_calendars().await.ok()?.get(url).cloned()
+    }
+
+    async fn create_calendar(&mut self, url: Url, _name: String, _supported_components: SupportedComponents, _color: Option<Color>) -> Result<Arc<Mutex<GraphTaskList>>, KFError> {
+        Err(format!("Calendar {} cannot be created: Microsoft Graph chooses the URLs of task lists (see GraphTodo::create_task_list)", url).into())
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<GraphTaskList>>, KFError> {
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        // This is not a CalDAV server
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        Ok(())
+    }
+}
+
+/// The Outlook calendars of a Microsoft account (e.g. an Outlook.com or a Microsoft 365 account).
+///
+/// This source can be used instead of a [`Client`](crate::Client) in a [`Provider`](crate::provider::Provider) (see [`GraphCalendarProvider`](crate::GraphCalendarProvider)),
+/// so that the events of Microsoft accounts, that usually have no CalDAV access, can be synced through the Microsoft Graph API. \
+/// Each calendar is a [`GraphCalendar`]: see its documentation for how events are mapped to iCal items.
+///
+/// This crate does not implement OAuth2 flows: apps obtain an access token (with the `Calendars.ReadWrite` permission) themselves,
+/// and replace it in the [`AccessToken`] when it expires.
+#[derive(Debug)]
+pub struct GraphCalendars {
+    resource: Resource,
+    calendars: Mutex<Option<HashMap<Url, Arc<Mutex<GraphCalendar>>>>>,
+}
+
+impl GraphCalendars {
+    pub fn new(access_token: AccessToken) -> Self {
+        // This is a valid URL
+        Self::with_base_url(GRAPH_API.parse().unwrap(), access_token)
+    }
+
+    /// Use another root than [`GRAPH_API`] (e.g. a national cloud, or a test server)
+    pub fn with_base_url(base_url: Url, access_token: AccessToken) -> Self {
+        Self {
+            resource: Resource::new_with_access_token(base_url, access_token, Transport::default()),
+            calendars: Mutex::new(None),
+        }
+    }
+
+    /// The URL of the calendar that has a given ID
+    pub fn calendar_url(&self, id: &str) -> Url {
+        self.api_url(&["me", "calendars", id, "events", ""])
+    }
+
+    /// Create a new calendar.
+    ///
+    /// Microsoft Graph chooses the IDs of calendars, so that they cannot be created by [`CalDavSource::create_calendar`]. Apps should create them with this function instead,
+    /// and the next sync of a [`Provider`](crate::provider::Provider) will create their local counterparts
+    pub async fn create_outlook_calendar(&self, name: &str) -> Result<Arc<Mutex<GraphCalendar>>, KFError> {
+        let url = self.api_url(&["me", "calendars"]);
+        let body = serde_json::json!({ "name": name });
+        let reply = send_json(&self.resource, Method::POST, url, Some(&body), None).await?;
+        let created: GraphCalendarResource = serde_json::from_str(&reply)?;
+        let calendar = Arc::new(Mutex::new(self.calendar(created)));
+
+        let mut calendars = self.calendars.lock().unwrap();
+        if let Some(calendars) = calendars.as_mut() {
+            let url = calendar.lock().unwrap().url().clone();
+            calendars.insert(url, calendar.clone());
+        }
+        Ok(calendar)
+    }
+
+    fn api_url(&self, segments: &[&str]) -> Url {
+        let mut url = self.resource.url().clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(segments);
+        }
+        url
+    }
+
+    fn calendar(&self, calendar: GraphCalendarResource) -> GraphCalendar {
+        let url = self.calendar_url(&calendar.id);
+        let resource = Resource::new_with_access_token(url, self.access_token(), Transport::default());
+        let color = calendar.hex_color.filter(|color| !color.is_empty()).and_then(|color| csscolorparser::parse(&color).ok());
+        GraphCalendar::new(calendar.name.unwrap_or_default(), resource, SupportedComponents::EVENT, color)
+    }
+
+    fn access_token(&self) -> AccessToken {
+        // Resources of this source are always built with a token
+        self.resource.access_token().cloned().unwrap_or_default()
+    }
+
+    async fn fetch_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<GraphCalendar>>>, KFError> {
+        let mut calendars = HashMap::new();
+        let mut url = Some(self.api_url(&["me", "calendars"]));
+        while let Some(page_url) = url {
+            let reply = send_json(&self.resource, Method::GET, page_url, None, None).await?;
+            let page: Page<GraphCalendarResource> = serde_json::from_str(&reply)?;
+            for calendar in page.value {
+                let calendar = self.calendar(calendar);
+                calendars.insert(calendar.url().clone(), Arc::new(Mutex::new(calendar)));
+            }
+            url = page.next_link;
+        }
+        Ok(calendars)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<GraphCalendar> for GraphCalendars {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<GraphCalendar>>>, KFError> {
+        if let Some(calendars) = &*self.calendars.lock().unwrap() {
+            return Ok(calendars.clone());
+        }
+        let calendars = self.fetch_calendars().await?;
+        *self.calendars.lock().unwrap() = Some(calendars.clone());
+        Ok(calendars)
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<GraphCalendar>>> {
+        self.get_calendars().await.ok()?.get(url).cloned()
+    }
+
+    async fn create_calendar(&mut self, url: Url, _name: String, _supported_components: SupportedComponents, _color: Option<Color>) -> Result<Arc<Mutex<GraphCalendar>>, KFError> {
+        Err(format!("Calendar {} cannot be created: Microsoft Graph chooses the URLs of calendars (see GraphCalendars::create_outlook_calendar)", url).into())
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<GraphCalendar>>, KFError> {
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        // This is not a CalDAV server
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        Ok(())
+    }
+}
@@ -3,26 +3,45 @@
 use std::error::Error;
 
 use chrono::{DateTime, Utc};
-use ics::properties::{Completed, Created, LastModified, PercentComplete, Status, Summary};
-use ics::{ICalendar, ToDo};
+use ics::properties::{Action, Completed, Created, LastModified, PercentComplete, Status, Summary, Trigger};
+use ics::{Event as IcsEvent, ICalendar, ToDo};
+use ics::Alarm as IcsAlarm;
 use ics::components::Parameter as IcsParameter;
 use ics::components::Property as IcsProperty;
 use ical::property::Property as IcalProperty;
 
 use crate::Task;
+use crate::Event;
 use crate::item::Item;
 use crate::task::CompletionStatus;
+use crate::alarm::Alarm;
+use crate::scheduling::Method;
 
 
-/// Create an iCal item from a `crate::item::Item`
+/// Create an iCal item from a `crate::item::Item` (or a vCard, for contacts)
 pub fn build_from(item: &Item) -> Result<String, Box<dyn Error>> {
     match item {
         Item::Task(t) => build_from_task(t),
-        _ => unimplemented!(),
+        Item::Event(e) => build_from_event(e),
+        Item::Contact(c) => Ok(crate::vcard::build_from_contact(c)),
     }
 }
 
+/// Create an iCal file from a task.
+///
+/// Tasks that have been parsed from an iCal file (see [`Task::raw_ics`]) are serialized as this file, where only the properties that have changed are rewritten
 pub fn build_from_task(task: &Task) -> Result<String, Box<dyn Error>> {
+    if let Some(raw_ics) = task.raw_ics() {
+        match super::patch::patch_raw_ics(task, raw_ics) {
+            Ok(ics) => return Ok(ics),
+            Err(err) => log::warn!("Unable to reuse the original iCal data of task {}, it will be generated from scratch: {}", task.url(), err),
+        }
+    }
+    build_fresh_from_task(task)
+}
+
+/// Create an iCal file from a task, regardless of the file it may have been parsed from
+pub(crate) fn build_fresh_from_task(task: &Task) -> Result<String, Box<dyn Error>> {
     let s_last_modified = format_date_time(task.last_modified());
 
     let mut todo = ToDo::new(
@@ -55,12 +74,63 @@ pub fn build_from_task(task: &Task) -> Result<String, Box<dyn Error>> {
         todo.push(ics_property);
     }
 
+    for alarm in task.alarms() {
+        todo.add_alarm(alarm_to_ics(alarm));
+    }
+
     let mut calendar = ICalendar::new("2.0", task.ical_prod_id());
     calendar.add_todo(todo);
 
     Ok(calendar.to_string())
 }
 
+/// Create an iCal file from an event
+pub fn build_from_event(event: &Event) -> Result<String, Box<dyn Error>> {
+    let s_last_modified = format_date_time(event.last_modified());
+
+    let mut ics_event = IcsEvent::new(event.uid(), s_last_modified.clone());
+    if let Some(dt) = event.creation_date() {
+        ics_event.push(Created::new(format_date_time(dt)));
+    }
+    ics_event.push(LastModified::new(s_last_modified));
+    if !event.name().is_empty() {
+        ics_event.push(Summary::new(event.name()));
+    }
+
+    for ical_property in event.extra_parameters() {
+        ics_event.push(ical_to_ics_property(ical_property.clone()));
+    }
+
+    for alarm in event.alarms() {
+        ics_event.add_alarm(alarm_to_ics(alarm));
+    }
+
+    let mut calendar = ICalendar::new("2.0", event.ical_prod_id());
+    calendar.add_event(ics_event);
+
+    Ok(calendar.to_string())
+}
+
+/// Create an iCal file for a scheduling message (see the [`crate::scheduling`] module), that contains one `VEVENT` per list of properties.
+///
+/// The properties are written as they are, except their `DTSTAMP` that is set to now
+pub(crate) fn build_scheduling_message(method: &Method, events: Vec<Vec<IcalProperty>>) -> String {
+    let mut calendar = ICalendar::new("2.0", super::default_prod_id());
+    calendar.push(IcsProperty::new("METHOD", method.as_ical().to_string()));
+    let dtstamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    for properties in events {
+        let uid = properties.iter().find(|prop| prop.name.eq_ignore_ascii_case("UID")).and_then(|prop| prop.value.clone()).unwrap_or_default();
+        let mut event = IcsEvent::new(uid, dtstamp.clone());
+        for prop in properties {
+            if !prop.name.eq_ignore_ascii_case("UID") && !prop.name.eq_ignore_ascii_case("DTSTAMP") {
+                event.push(ical_to_ics_property(prop));
+            }
+        }
+        calendar.add_event(event);
+    }
+    calendar.to_string()
+}
+
 fn format_date_time(dt: &DateTime<Utc>) -> String {
     dt.format("%Y%m%dT%H%M%S").to_string()
 }
@@ -73,18 +143,42 @@ fn ical_to_ics_property(prop: IcalProperty) -> IcsProperty<'static> {
     };
     prop.params.map(|v| {
         for (key, vec_values) in v {
-            let values = vec_values.join(";");
+            let values = super::format_param_values(&vec_values);
             ics_prop.add(IcsParameter::new(key, values));
         }
     });
     ics_prop
 }
 
+/// `ACTION` and `TRIGGER` are the first properties of `VALARM`s built by the `ics` crate, the other ones are kept in their original order
+fn alarm_to_ics(alarm: &Alarm) -> IcsAlarm<'static> {
+    let find = |name: &str| alarm.properties().iter().find(|prop| prop.name.eq_ignore_ascii_case(name));
+
+    let mut action = Action::new(alarm.action().as_ical().to_string());
+    let mut trigger = Trigger::new(find("TRIGGER").and_then(|prop| prop.value.clone()).unwrap_or_default());
+    for (key, values) in find("TRIGGER").and_then(|prop| prop.params.clone()).unwrap_or_default() {
+        trigger.add(IcsParameter::new(key, super::format_param_values(&values)));
+    }
+    for (key, values) in find("ACTION").and_then(|prop| prop.params.clone()).unwrap_or_default() {
+        action.add(IcsParameter::new(key, super::format_param_values(&values)));
+    }
+
+    let mut ics_alarm = IcsAlarm::new(action, trigger);
+    for prop in alarm.properties() {
+        if !prop.name.eq_ignore_ascii_case("ACTION") && !prop.name.eq_ignore_ascii_case("TRIGGER") {
+            ics_alarm.push(ical_to_ics_property(prop.clone()));
+        }
+    }
+    ics_alarm
+}
+
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::Task;
+    use crate::alarm::{AlarmTrigger, Attachment};
+    use crate::item::SyncStatus;
     use crate::config::{ORG_NAME, PRODUCT_NAME};
 
     #[test]
@@ -129,6 +223,23 @@ mod tests {
         assert_eq!(ical, expected_ical);
     }
 
+    #[test]
+    fn test_ical_with_email_alarm() {
+        let cal_url = "http://my.calend.ar/id".parse().unwrap();
+        let mut task = Task::new(String::from("Renew the passport"), false, &cal_url);
+        let mut alarm = Alarm::email(AlarmTrigger::before_start(chrono::Duration::days(7)), "Passport", "The passport expires next week", &["john@example.com"]);
+        alarm.add_attachment(Attachment::uri("https://example.com/form.pdf", Some("application/pdf")));
+        let mut properties = alarm.properties().to_vec();
+        properties[4].params = Some(vec![("CN".to_string(), vec!["Doe, John".to_string()])]);
+        task.add_alarm(Alarm::from_properties(properties));
+        let item = Item::Task(task);
+
+        let ical = build_from(&item).unwrap();
+        assert!(ical.contains("ATTENDEE;CN=\"Doe, John\":mailto:john@example.com\r\n"));
+        let parsed = crate::ical::parse(&ical, item.url().clone(), SyncStatus::NotSynced).unwrap();
+        assert_eq!(parsed.unwrap_task().alarms(), item.unwrap_task().alarms());
+    }
+
     fn build_task(completed: bool) -> (String, String, String) {
         let cal_url = "http://my.calend.ar/id".parse().unwrap();
         let now = Utc::now();
@@ -143,8 +254,21 @@ mod tests {
     }
 
     #[test]
-    #[ignore]
     fn test_ical_from_event() {
-        unimplemented!();
+        let cal_url = "http://my.calend.ar/id".parse().unwrap();
+        let date = |name: &str, value: &str| IcalProperty{ name: name.to_string(), params: Some(vec![("TZID".to_string(), vec!["Europe/Paris".to_string()])]), value: Some(value.to_string()) };
+        let mut event = Event::new("Dentist".to_string(), date("DTSTART", "20210401T100000"), date("DTEND", "20210401T110000"), &cal_url);
+        event.set_property(IcalProperty{ name: "LOCATION".to_string(), params: None, value: Some("Main street".to_string()) });
+
+        let ical = build_from_event(&event).unwrap();
+        assert!(ical.contains("BEGIN:VEVENT\r\n"));
+        assert!(ical.contains("SUMMARY:Dentist\r\n"));
+        assert!(ical.contains("DTSTART;TZID=Europe/Paris:20210401T100000\r\n"));
+
+        let parsed = crate::ical::parse(&ical, event.url().clone(), SyncStatus::NotSynced).unwrap();
+        let parsed = parsed.unwrap_event();
+        assert!(parsed.content_eq(&event));
+        assert_eq!(parsed.value("LOCATION"), Some("Main street"));
+        assert_eq!(parsed.start().map(|start| start.to_rfc3339()), Some("2021-04-01T10:00:00+00:00".to_string()));
     }
 }
@@ -4,8 +4,18 @@
 
 mod parser;
 pub use parser::parse;
+pub use parser::parse_free_busy;
+pub use parser::parse_occurrences;
+pub use parser::parse_partial;
+pub use parser::parse_scheduling_messages;
+pub(crate) use parser::parse_date_value;
+pub(crate) use parser::parse_timezone_id;
+pub(crate) use parser::split_calendar;
+pub(crate) use parser::{parse_alarm, parse_duration};
 mod builder;
 pub use builder::build_from;
+pub(crate) use builder::build_scheduling_message;
+mod patch;
 
 use crate::config::{ORG_NAME, PRODUCT_NAME};
 
@@ -13,6 +23,35 @@ pub fn default_prod_id() -> String {
     format!("-//{}//{}//EN", ORG_NAME.lock().unwrap(), PRODUCT_NAME.lock().unwrap())
 }
 
+/// Format the values of a property parameter, e.g. `CN="Doe, John"`. Values that contain a separator are quoted, so that they are parsed back as a single value
+pub(crate) fn format_param_values(values: &[String]) -> String {
+    values.iter()
+        .map(|value| match value.contains(&[',', ';', ':'][..]) {
+            true => format!("\"{}\"", value),
+            false => value.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Format a property as a single (unfolded) iCal content line, e.g. `DTSTART;TZID=Europe/Paris:20210401T100000`
+#[cfg(feature = "google")]
+pub(crate) fn format_content_line(prop: &ical::property::Property) -> String {
+    let mut line = prop.name.clone();
+    for (name, values) in prop.params.iter().flatten() {
+        line.push_str(&format!(";{}={}", name, format_param_values(values)));
+    }
+    line.push(':');
+    line.push_str(prop.value.as_deref().unwrap_or_default());
+    line
+}
+
+/// Parse a single iCal content line (see [`format_content_line`])
+#[cfg(feature = "google")]
+pub(crate) fn parse_content_line(line: &str) -> Option<ical::property::Property> {
+    ical::PropertyParser::from_reader(line.as_bytes()).next()?.ok()
+}
+
 
 
 #[cfg(test)]
@@ -22,6 +61,16 @@ mod tests {
     use std::collections::HashSet;
     use crate::item::SyncStatus;
 
+    #[test]
+    #[cfg(feature = "google")]
+    fn test_content_lines() {
+        let prop = parse_content_line("EXDATE;TZID=Europe/Paris:20210408T100000,20210415T100000").unwrap();
+        assert_eq!(prop.name, "EXDATE");
+        assert_eq!(prop.params, Some(vec![("TZID".to_string(), vec!["Europe/Paris".to_string()])]));
+        assert_eq!(format_content_line(&prop), "EXDATE;TZID=Europe/Paris:20210408T100000,20210415T100000");
+        assert!(parse_content_line("not a property").is_none());
+    }
+
     #[test]
     fn test_ical_round_trip_serde() {
         let ical_with_unknown_fields = std::fs::read_to_string("tests/assets/ical_with_unknown_fields.ics").unwrap();
@@ -1,9 +1,8 @@
 //! A module to parse ICal files
 
-use std::error::Error;
 
 use ical::parser::ical::component::{IcalCalendar, IcalEvent, IcalTodo};
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use url::Url;
 
 use crate::Item;
@@ -11,15 +10,27 @@ use crate::item::SyncStatus;
 use crate::Task;
 use crate::task::CompletionStatus;
 use crate::Event;
+use crate::freebusy::{FreeBusy, FreeBusyPeriod, FreeBusyType};
+use crate::occurrence::Occurrence;
+use crate::scheduling::{Meeting, Method, Participant, SchedulingMessage};
+use crate::partial::PartialItem;
+use crate::item::VersionTag;
+use crate::error::KFError;
+use crate::alarm::Alarm;
 
 
 /// Parse an iCal file into the internal representation [`crate::Item`]
-pub fn parse(content: &str, item_url: Url, sync_status: SyncStatus) -> Result<Item, Box<dyn Error>> {
+///
+/// vCard files are parsed as contacts (see [`crate::vcard::parse`])
+pub fn parse(content: &str, item_url: Url, sync_status: SyncStatus) -> Result<Item, KFError> {
+    if crate::vcard::is_vcard(content) {
+        return Ok(Item::Contact(crate::vcard::parse(content, item_url, sync_status)?));
+    }
     let mut reader = ical::IcalParser::new(content.as_bytes());
     let parsed_item = match reader.next() {
-        None => return Err(format!("Invalid iCal data to parse for item {}", item_url).into()),
+        None => return Err(KFError::ical_parse(&item_url, "no calendar found")),
         Some(item) => match item {
-            Err(err) => return Err(format!("Unable to parse iCal data for item {}: {}", item_url, err).into()),
+            Err(err) => return Err(KFError::from_parser(Some(&item_url), err)),
             Ok(item) => item,
         }
     };
@@ -29,8 +40,39 @@ pub fn parse(content: &str, item_url: Url, sync_status: SyncStatus) -> Result<It
         .unwrap_or_else(|| super::default_prod_id());
 
     let item = match assert_single_type(&parsed_item)? {
-        CurrentType::Event(_) => {
-            Item::Event(Event::new())
+        CurrentType::Event(event) => {
+            let mut name = None;
+            let mut uid = None;
+            let mut last_modified = None;
+            let mut creation_date = None;
+            let mut extra_parameters = Vec::new();
+
+            for prop in &event.properties {
+                match prop.name.as_str() {
+                    "SUMMARY" => { name = prop.value.clone() },
+                    "UID" => { uid = prop.value.clone() },
+                    // See the same properties of tasks below
+                    "DTSTAMP" | "LAST-MODIFIED" => { last_modified = parse_date_time_from_property(&prop.value) },
+                    "CREATED" => { creation_date = parse_date_time_from_property(&prop.value) },
+                    _ => {
+                        // This field is not supported. Let's store it anyway, so that we are able to re-create an identical iCal file
+                        extra_parameters.push(prop.clone());
+                    }
+                }
+            }
+            let uid = match uid {
+                Some(uid) => uid,
+                None => return Err(KFError::ical_parse(&item_url, "missing UID")),
+            };
+            let last_modified = match last_modified {
+                Some(dt) => dt,
+                None => return Err(KFError::ical_parse(&item_url, "missing DTSTAMP, but this is required by RFC5545")),
+            };
+
+            // Unlike tasks, events do not need a SUMMARY (e.g. blocked slots)
+            let mut event_item = Event::new_with_parameters(name.unwrap_or_default(), uid, item_url, sync_status, creation_date, last_modified, ical_prod_id, extra_parameters);
+            event_item.set_alarms(event.alarms.iter().map(|alarm| Alarm::from_properties(alarm.properties.clone())).collect());
+            Item::Event(event_item)
         },
 
         CurrentType::Todo(todo) => {
@@ -89,15 +131,15 @@ pub fn parse(content: &str, item_url: Url, sync_status: SyncStatus) -> Result<It
             }
             let name = match name {
                 Some(name) => name,
-                None => return Err(format!("Missing name for item {}", item_url).into()),
+                None => return Err(KFError::ical_parse(&item_url, "missing name")),
             };
             let uid = match uid {
                 Some(uid) => uid,
-                None => return Err(format!("Missing UID for item {}", item_url).into()),
+                None => return Err(KFError::ical_parse(&item_url, "missing UID")),
             };
             let last_modified = match last_modified {
                 Some(dt) => dt,
-                None => return Err(format!("Missing DTSTAMP for item {}, but this is required by RFC5545", item_url).into()),
+                None => return Err(KFError::ical_parse(&item_url, "missing DTSTAMP, but this is required by RFC5545")),
             };
             let completion_status = match completed {
                 false => {
@@ -109,7 +151,10 @@ pub fn parse(content: &str, item_url: Url, sync_status: SyncStatus) -> Result<It
                 true => CompletionStatus::Completed(completion_date),
             };
 
-            Item::Task(Task::new_with_parameters(name, uid, item_url, completion_status, sync_status, creation_date, last_modified, ical_prod_id, extra_parameters))
+            let mut task = Task::new_with_parameters(name, uid, item_url, completion_status, sync_status, creation_date, last_modified, ical_prod_id, extra_parameters);
+            task.set_raw_ics(Some(content.to_string()));
+            task.set_alarms(todo.alarms.iter().map(|alarm| Alarm::from_properties(alarm.properties.clone())).collect());
+            Item::Task(task)
         },
     };
 
@@ -122,6 +167,408 @@ pub fn parse(content: &str, item_url: Url, sync_status: SyncStatus) -> Result<It
     Ok(item)
 }
 
+/// Parse the `VFREEBUSY` component of an iCal file (e.g. the reply to a CalDAV `free-busy-query` REPORT)
+pub fn parse_free_busy(content: &str) -> Result<FreeBusy, KFError> {
+    let mut reader = ical::IcalParser::new(content.as_bytes());
+    let calendar = match reader.next() {
+        None => return Err(KFError::IcalParse{ url: None, line: None, reason: "no calendar found".to_string() }),
+        Some(Err(err)) => return Err(KFError::from_parser(None, err)),
+        Some(Ok(calendar)) => calendar,
+    };
+
+    let mut result = FreeBusy { start: None, end: None, periods: Vec::new() };
+    for vfreebusy in &calendar.free_busys {
+        for prop in &vfreebusy.properties {
+            match prop.name.as_str() {
+                "DTSTART" => result.start = parse_date_time_from_property(&prop.value),
+                "DTEND" => result.end = parse_date_time_from_property(&prop.value),
+                "FREEBUSY" => {
+                    let fb_type = find_param(prop, "FBTYPE")
+                        .map(FreeBusyType::from_fbtype)
+                        .unwrap_or_default();
+                    // A FREEBUSY property can contain several comma-separated periods
+                    for period in prop.value.as_deref().unwrap_or("").split(',').filter(|p| !p.is_empty()) {
+                        let (start, end) = parse_period(period)?;
+                        result.periods.push(FreeBusyPeriod{ start, end, fb_type: fb_type.clone() });
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parse the `TZID` of the `VTIMEZONE` of an iCal file (e.g. the `calendar-timezone` property of a calendar)
+pub(crate) fn parse_timezone_id(content: &str) -> Result<String, KFError> {
+    let mut reader = ical::IcalParser::new(content.as_bytes());
+    let calendar = match reader.next() {
+        None => return Err(KFError::IcalParse{ url: None, line: None, reason: "no calendar found".to_string() }),
+        Some(Err(err)) => return Err(KFError::from_parser(None, err)),
+        Some(Ok(calendar)) => calendar,
+    };
+
+    calendar.timezones.iter()
+        .flat_map(|timezone| &timezone.properties)
+        .find(|prop| prop.name == "TZID")
+        .and_then(|prop| prop.value.clone())
+        .ok_or_else(|| KFError::IcalParse{ url: None, line: None, reason: "no VTIMEZONE with a TZID found".to_string() })
+}
+
+/// Parse a `VALARM` component that is not part of an item (e.g. a default alarm of a calendar)
+pub(crate) fn parse_alarm(content: &str) -> Result<Alarm, KFError> {
+    let lines: Vec<&str> = content.trim().lines().map(str::trim_end).filter(|line| !line.is_empty()).collect();
+    let wrapped = format!("BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\n{}\r\nEND:VTODO\r\nEND:VCALENDAR\r\n", lines.join("\r\n"));
+    let mut reader = ical::IcalParser::new(wrapped.as_bytes());
+    let calendar = match reader.next() {
+        None => return Err(KFError::IcalParse{ url: None, line: None, reason: "no alarm found".to_string() }),
+        Some(Err(err)) => return Err(KFError::from_parser(None, err)),
+        Some(Ok(calendar)) => calendar,
+    };
+
+    calendar.todos.into_iter()
+        .flat_map(|todo| todo.alarms)
+        .next()
+        .map(|alarm| Alarm::from_properties(alarm.properties))
+        .ok_or_else(|| KFError::IcalParse{ url: None, line: None, reason: "no VALARM found".to_string() })
+}
+
+/// A component of an iCal file that contains several of them, as a self-contained iCal file (see [`split_calendar`])
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SplitComponent {
+    /// The name of the component (e.g. `VTODO` or `VEVENT`)
+    pub kind: String,
+    pub uid: Option<String>,
+    pub ical: String,
+}
+
+/// Split an iCal file that contains many components (e.g. a subscribed calendar) into one iCal file per component.
+///
+/// Every file keeps the properties of the original `VCALENDAR`, and all of its `VTIMEZONE`s
+pub(crate) fn split_calendar(content: &str) -> Result<Vec<SplitComponent>, KFError> {
+    let mut header = Vec::new();
+    let mut timezones = Vec::new();
+    let mut components = Vec::new();
+    let mut current: Option<(String, Vec<&str>)> = None;
+    let mut depth = 0;
+
+    for (line_number, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let unexpected = |reason: String| KFError::IcalParse{ url: None, line: Some(line_number + 1), reason };
+        let upper = line.to_ascii_uppercase();
+        if let Some(kind) = upper.strip_prefix("BEGIN:") {
+            depth += 1;
+            match depth {
+                1 if kind.trim() != "VCALENDAR" => return Err(unexpected(format!("unexpected {}", line))),
+                1 => continue,
+                2 => current = Some((kind.trim().to_string(), Vec::new())),
+                _ => (),
+            }
+        }
+
+        match (depth, &mut current) {
+            (0, _) => return Err(unexpected(format!("unexpected content outside of a VCALENDAR: {}", line))),
+            (1, _) if upper.starts_with("END:") => (),
+            (1, _) => header.push(line),
+            (_, Some((_, lines))) => lines.push(line),
+            (_, None) => (),
+        }
+
+        if upper.starts_with("END:") {
+            if depth == 2 {
+                if let Some((kind, lines)) = current.take() {
+                    match kind.as_str() {
+                        "VTIMEZONE" => timezones.extend(lines),
+                        _ => components.push((kind, lines)),
+                    }
+                }
+            }
+            depth -= 1;
+        }
+    }
+    if depth != 0 {
+        return Err(KFError::IcalParse{ url: None, line: None, reason: "unterminated VCALENDAR".to_string() });
+    }
+
+    Ok(components.into_iter()
+        .map(|(kind, lines)| {
+            let uid = find_unfolded_value(&lines, "UID");
+            let mut ical = String::from("BEGIN:VCALENDAR\r\n");
+            for line in header.iter().chain(&timezones).chain(&lines) {
+                ical.push_str(line);
+                ical.push_str("\r\n");
+            }
+            ical.push_str("END:VCALENDAR\r\n");
+            SplitComponent{ kind, uid, ical }
+        })
+        .collect())
+}
+
+/// The value of the first property `name` of a component, whose lines may be folded
+fn find_unfolded_value(lines: &[&str], name: &str) -> Option<String> {
+    let mut value: Option<String> = None;
+    for line in lines.iter().skip(1) {
+        if let Some(continuation) = line.strip_prefix(|c| c == ' ' || c == '\t') {
+            if let Some(value) = value.as_mut() {
+                value.push_str(continuation);
+            }
+            continue;
+        }
+        if value.is_some() {
+            break;
+        }
+        // Only the properties of the component itself are considered, not the ones of its sub-components (e.g. VALARMs)
+        if line.to_ascii_uppercase().starts_with("BEGIN:") {
+            return None;
+        }
+        let (prop_name, prop_value) = match line.find(':') {
+            None => continue,
+            Some(index) => (&line[..index], &line[index + 1..]),
+        };
+        let prop_name = prop_name.split(';').next().unwrap_or_default();
+        if prop_name.eq_ignore_ascii_case(name) {
+            value = Some(prop_value.to_string());
+        }
+    }
+    value
+}
+
+/// Parse the `VEVENT` instances of an iCal file (e.g. the calendar-data of a CalDAV REPORT that has been expanded by the server)
+pub fn parse_occurrences(content: &str, item_url: &Url) -> Result<Vec<Occurrence>, KFError> {
+    let mut reader = ical::IcalParser::new(content.as_bytes());
+    let calendar = match reader.next() {
+        None => return Err(KFError::ical_parse(item_url, "no calendar found")),
+        Some(Err(err)) => return Err(KFError::from_parser(Some(item_url), err)),
+        Some(Ok(calendar)) => calendar,
+    };
+
+    let mut occurrences = Vec::new();
+    for event in &calendar.events {
+        let mut uid = None;
+        let mut summary = None;
+        let mut recurrence_id = None;
+        let mut start = None;
+        let mut end = None;
+        let mut duration = None;
+        let mut all_day = false;
+        for prop in &event.properties {
+            match prop.name.as_str() {
+                "UID" => uid = prop.value.clone(),
+                "SUMMARY" => summary = prop.value.clone(),
+                "RECURRENCE-ID" => recurrence_id = parse_date_or_date_time(prop),
+                "DTSTART" => {
+                    start = parse_date_or_date_time(prop);
+                    all_day = is_date_value(prop);
+                },
+                "DTEND" => end = parse_date_or_date_time(prop),
+                "DURATION" => duration = prop.value.as_deref().map(parse_duration).transpose()?,
+                _ => (),
+            }
+        }
+
+        let uid = uid.ok_or_else(|| format!("Missing UID for an event of item {}", item_url))?;
+        let start = start.ok_or_else(|| format!("Missing DTSTART for event {}", uid))?;
+        let end = end.or_else(|| duration.map(|d| start + d));
+        occurrences.push(Occurrence {
+            item_url: item_url.clone(),
+            uid,
+            summary,
+            recurrence_id,
+            start,
+            end,
+            all_day,
+        });
+    }
+
+    Ok(occurrences)
+}
+
+/// Parse an iCal file that has a `METHOD` into scheduling messages (see [`SchedulingMessage::parse`])
+pub fn parse_scheduling_messages(content: &str) -> Result<Vec<SchedulingMessage>, KFError> {
+    let mut reader = ical::IcalParser::new(content.as_bytes());
+    let calendar = match reader.next() {
+        None => return Err(KFError::IcalParse{ url: None, line: None, reason: "no calendar found".to_string() }),
+        Some(Err(err)) => return Err(KFError::from_parser(None, err)),
+        Some(Ok(calendar)) => calendar,
+    };
+    let method = calendar.properties.iter()
+        .find(|prop| prop.name.eq_ignore_ascii_case("METHOD"))
+        .and_then(|prop| prop.value.as_deref())
+        .map(Method::from_ical)
+        .ok_or_else(|| KFError::IcalParse{ url: None, line: None, reason: "no METHOD found, this is not a scheduling message".to_string() })?;
+    if !matches!(method, Method::Request | Method::Cancel | Method::Counter | Method::DeclineCounter) {
+        return Ok(vec![SchedulingMessage::Unsupported(method)]);
+    }
+
+    let mut meetings: Vec<Meeting> = Vec::new();
+    let mut instances = Vec::new();
+    for event in &calendar.events {
+        let meeting = parse_meeting(event)?;
+        match meeting.recurrence_id {
+            None => meetings.push(meeting),
+            Some(_) => instances.push(meeting),
+        }
+    }
+    for instance in instances {
+        match meetings.iter_mut().find(|meeting| meeting.uid == instance.uid && meeting.recurrence_id.is_none()) {
+            Some(meeting) => meeting.overridden_instances.push(instance),
+            None => meetings.push(instance),
+        }
+    }
+    if meetings.is_empty() {
+        return Err(KFError::IcalParse{ url: None, line: None, reason: "no VEVENT found".to_string() });
+    }
+
+    Ok(meetings.into_iter()
+        .map(|meeting| match method {
+            Method::Cancel => SchedulingMessage::Cancellation(meeting),
+            Method::Counter => SchedulingMessage::Counter(meeting),
+            Method::DeclineCounter => SchedulingMessage::DeclineCounter(meeting),
+            _ if meeting.sequence == 0 => SchedulingMessage::Invitation(meeting),
+            _ => SchedulingMessage::Update(meeting),
+        })
+        .collect())
+}
+
+fn parse_meeting(event: &IcalEvent) -> Result<Meeting, KFError> {
+    let mut meeting = Meeting {
+        uid: String::new(), sequence: 0, recurrence_id: None,
+        summary: None, description: None, location: None,
+        start: None, end: None, all_day: false,
+        organizer: None, attendees: Vec::new(),
+        overridden_instances: Vec::new(),
+        properties: event.properties.clone(),
+    };
+    let mut duration = None;
+    for prop in &event.properties {
+        match prop.name.as_str() {
+            "UID" => meeting.uid = prop.value.clone().unwrap_or_default(),
+            "SEQUENCE" => meeting.sequence = prop.value.as_deref().and_then(|value| value.parse().ok()).unwrap_or(0),
+            "RECURRENCE-ID" => meeting.recurrence_id = parse_date_or_date_time(prop),
+            "SUMMARY" => meeting.summary = prop.value.clone(),
+            "DESCRIPTION" => meeting.description = prop.value.clone(),
+            "LOCATION" => meeting.location = prop.value.clone(),
+            "DTSTART" => {
+                meeting.start = parse_date_or_date_time(prop);
+                meeting.all_day = is_date_value(prop);
+            },
+            "DTEND" => meeting.end = parse_date_or_date_time(prop),
+            "DURATION" => duration = prop.value.as_deref().map(parse_duration).transpose()?,
+            "ORGANIZER" => meeting.organizer = Participant::from_property(prop),
+            "ATTENDEE" => meeting.attendees.extend(Participant::from_property(prop)),
+            _ => (),
+        }
+    }
+    if meeting.uid.is_empty() {
+        return Err(KFError::IcalParse{ url: None, line: None, reason: "missing UID for a meeting".to_string() });
+    }
+    if let (None, Some(start), Some(duration)) = (meeting.end, meeting.start, duration) {
+        meeting.end = Some(start + duration);
+    }
+    Ok(meeting)
+}
+
+fn is_date_value(prop: &ical::property::Property) -> bool {
+    find_param(prop, "VALUE").map(|v| v.eq_ignore_ascii_case("DATE")).unwrap_or(false)
+        || prop.value.as_ref().map(|v| v.len() == 8).unwrap_or(false)
+}
+
+/// Parse a `DATE-TIME` value, or a `DATE` value (as midnight UTC)
+fn parse_date_or_date_time(prop: &ical::property::Property) -> Option<DateTime<Utc>> {
+    if is_date_value(prop) {
+        return prop.value.as_deref().and_then(parse_date_value);
+    }
+    parse_date_time_from_property(&prop.value)
+}
+
+/// Parse a `DATE-TIME` or `DATE` value (as midnight UTC)
+pub(crate) fn parse_date_value(value: &str) -> Option<DateTime<Utc>> {
+    if value.len() == 8 {
+        return chrono::NaiveDate::parse_from_str(value, "%Y%m%d").ok()
+            .map(|date| Utc.from_utc_datetime(&date.and_hms(0, 0, 0)));
+    }
+    parse_date_time(value).ok()
+}
+
+/// Parse the first event or task of an iCal file that only contains some properties (see [`crate::partial`])
+pub fn parse_partial(content: &str, item_url: Url, version_tag: VersionTag) -> Result<PartialItem, KFError> {
+    let mut reader = ical::IcalParser::new(content.as_bytes());
+    let calendar = match reader.next() {
+        None => return Err(KFError::ical_parse(&item_url, "no calendar found")),
+        Some(Err(err)) => return Err(KFError::from_parser(Some(&item_url), err)),
+        Some(Ok(calendar)) => calendar,
+    };
+
+    let (component, properties) = if let Some(event) = calendar.events.into_iter().next() {
+        ("VEVENT", event.properties)
+    } else if let Some(todo) = calendar.todos.into_iter().next() {
+        ("VTODO", todo.properties)
+    } else {
+        return Err(KFError::ical_parse(&item_url, "no event nor task found"));
+    };
+
+    Ok(PartialItem::new(item_url, version_tag, component.to_string(), properties))
+}
+
+/// Parse a RFC5545 period, either `start/end` or `start/duration`
+fn parse_period(period: &str) -> Result<(DateTime<Utc>, DateTime<Utc>), KFError> {
+    let (start, end_or_duration) = period.split_once('/')
+        .ok_or_else(|| format!("Invalid period {:?}", period))?;
+    let start = parse_date_time(start)?;
+    let end = if end_or_duration.starts_with('P') || end_or_duration.starts_with("+P") {
+        start + parse_duration(end_or_duration)?
+    } else {
+        parse_date_time(end_or_duration)?
+    };
+    Ok((start, end))
+}
+
+/// Parse a RFC5545 duration (e.g. `PT1H30M`, `-P1D` or `P2W`)
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, KFError> {
+    let invalid = || format!("Invalid duration {:?}", value);
+
+    let (negative, rest) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    let mut in_time_part = false;
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => in_time_part = true,
+            unit => {
+                let n: i64 = number.parse().map_err(|_| invalid())?;
+                number.clear();
+                total = total + match (unit, in_time_part) {
+                    ('W', false) => Duration::weeks(n),
+                    ('D', false) => Duration::days(n),
+                    ('H', true) => Duration::hours(n),
+                    ('M', true) => Duration::minutes(n),
+                    ('S', true) => Duration::seconds(n),
+                    _ => return Err(invalid().into()),
+                };
+            },
+        }
+    }
+    if !number.is_empty() {
+        return Err(invalid().into());
+    }
+
+    Ok(if negative { -total } else { total })
+}
+
+fn find_param<'a>(prop: &'a ical::property::Property, name: &str) -> Option<&'a str> {
+    prop.params.as_ref()?
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .and_then(|(_, values)| values.first())
+        .map(|v| v.as_str())
+}
+
 fn parse_date_time(dt: &str) -> Result<DateTime<Utc>, chrono::format::ParseError> {
                     Utc.datetime_from_str(dt, "%Y%m%dT%H%M%SZ")
     .or_else(|_err| Utc.datetime_from_str(dt, "%Y%m%dT%H%M%S") )
@@ -155,7 +602,7 @@ enum CurrentType<'a> {
     Todo(&'a IcalTodo),
 }
 
-fn assert_single_type<'a>(item: &'a IcalCalendar) -> Result<CurrentType<'a>, Box<dyn Error>> {
+fn assert_single_type<'a>(item: &'a IcalCalendar) -> Result<CurrentType<'a>, KFError> {
     let n_events = item.events.len();
     let n_todos = item.todos.len();
     let n_journals = item.journals.len();
@@ -245,6 +692,19 @@ DTSTAMP:20210321T001600
 SUMMARY:Buy a gift for Mom
 END:VTODO
 END:VCALENDAR
+"#;
+
+    const EXAMPLE_FREEBUSY: &str = r#"BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//Example Corp.//CalDAV Server//EN
+BEGIN:VFREEBUSY
+DTSTAMP:20050125T090000Z
+DTSTART:20060104T140000Z
+DTEND:20060105T220000Z
+FREEBUSY:20060104T140000Z/PT1H
+FREEBUSY;FBTYPE=BUSY-TENTATIVE:20060104T160000Z/20060104T173000Z,20060105T090000Z/PT1H
+END:VFREEBUSY
+END:VCALENDAR
 "#;
 
     use super::*;
@@ -294,6 +754,94 @@ END:VCALENDAR
         assert_eq!(task.completion_status(), &CompletionStatus::Completed(None));
     }
 
+    #[test]
+    fn test_free_busy_parsing() {
+        let fb = parse_free_busy(EXAMPLE_FREEBUSY).unwrap();
+        assert_eq!(fb.start, Some(Utc.ymd(2006, 1, 4).and_hms(14, 0, 0)));
+        assert_eq!(fb.end, Some(Utc.ymd(2006, 1, 5).and_hms(22, 0, 0)));
+        assert_eq!(fb.periods, vec![
+            FreeBusyPeriod{ start: Utc.ymd(2006, 1, 4).and_hms(14, 0, 0), end: Utc.ymd(2006, 1, 4).and_hms(15, 0, 0), fb_type: FreeBusyType::Busy },
+            FreeBusyPeriod{ start: Utc.ymd(2006, 1, 4).and_hms(16, 0, 0), end: Utc.ymd(2006, 1, 4).and_hms(17, 30, 0), fb_type: FreeBusyType::BusyTentative },
+            FreeBusyPeriod{ start: Utc.ymd(2006, 1, 5).and_hms(9, 0, 0), end: Utc.ymd(2006, 1, 5).and_hms(10, 0, 0), fb_type: FreeBusyType::BusyTentative },
+        ]);
+        assert!(fb.is_busy_at(&Utc.ymd(2006, 1, 4).and_hms(16, 10, 0)));
+        assert!(!fb.is_busy_at(&Utc.ymd(2006, 1, 4).and_hms(15, 0, 0)));
+    }
+
+    #[test]
+    fn test_occurrences_parsing() {
+        let content = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//Example Corp.//CalDAV Server//EN\r
+BEGIN:VEVENT\r
+UID:abcd2\r
+DTSTAMP:20060206T001121Z\r
+DTSTART:20060103T170000Z\r
+DURATION:PT1H\r
+RECURRENCE-ID:20060103T170000Z\r
+SUMMARY:Event #2\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:abcd2\r
+DTSTAMP:20060206T001121Z\r
+DTSTART:20060104T140000Z\r
+DTEND:20060104T150000Z\r
+RECURRENCE-ID:20060104T140000Z\r
+SUMMARY:Event #2 bis\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:abcd3\r
+DTSTAMP:20060206T001121Z\r
+DTSTART;VALUE=DATE:20060105\r
+SUMMARY:Holiday\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let url: Url = "http://some.id/for/testing".parse().unwrap();
+        let occurrences = parse_occurrences(content, &url).unwrap();
+        assert_eq!(occurrences.len(), 3);
+
+        assert_eq!(occurrences[0].uid, "abcd2");
+        assert_eq!(occurrences[0].recurrence_id, Some(Utc.ymd(2006, 1, 3).and_hms(17, 0, 0)));
+        assert_eq!(occurrences[0].end, Some(Utc.ymd(2006, 1, 3).and_hms(18, 0, 0)));
+        assert_eq!(occurrences[1].summary.as_deref(), Some("Event #2 bis"));
+        assert_eq!(occurrences[1].end, Some(Utc.ymd(2006, 1, 4).and_hms(15, 0, 0)));
+        assert!(occurrences[2].all_day);
+        assert_eq!(occurrences[2].start, Utc.ymd(2006, 1, 5).and_hms(0, 0, 0));
+        assert_eq!(occurrences[2].recurrence_id, None);
+    }
+
+    #[test]
+    fn test_partial_parsing() {
+        let content = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:abcd3\r
+SUMMARY:Holiday\r
+DTSTART;VALUE=DATE:20060105\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let url: Url = "http://some.id/for/testing".parse().unwrap();
+        let partial = parse_partial(content, url.clone(), VersionTag::from("etag".to_string())).unwrap();
+        assert!(partial.is_event());
+        assert_eq!(partial.url(), &url);
+        assert_eq!(partial.uid(), Some("abcd3"));
+        assert_eq!(partial.summary(), Some("Holiday"));
+        assert_eq!(partial.date("DTSTART"), Some(Utc.ymd(2006, 1, 5).and_hms(0, 0, 0)));
+        assert_eq!(partial.property("DESCRIPTION"), None);
+    }
+
+    #[test]
+    fn test_duration_parsing() {
+        assert_eq!(parse_duration("PT1H30M").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("-PT15M").unwrap(), Duration::minutes(-15));
+        assert_eq!(parse_duration("P1DT12H").unwrap(), Duration::hours(36));
+        assert_eq!(parse_duration("P2W").unwrap(), Duration::weeks(2));
+        assert!(parse_duration("1H").is_err());
+        assert!(parse_duration("PT5").is_err());
+    }
+
     #[test]
     fn test_multiple_items_in_ical() {
         let version_tag = VersionTag::from(String::from("test-tag"));
@@ -303,4 +851,28 @@ END:VCALENDAR
         let item = parse(EXAMPLE_MULTIPLE_ICAL, item_url.clone(), sync_status.clone());
         assert!(item.is_err());
     }
+
+    #[test]
+    fn test_split_calendar() {
+        let feed = std::fs::read_to_string("tests/assets/subscription.ics").unwrap();
+        let components = split_calendar(&feed).unwrap();
+
+        let kinds: Vec<_> = components.iter().map(|comp| comp.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["VTODO", "VEVENT", "VTODO"]);
+        let uids: Vec<_> = components.iter().map(|comp| comp.uid.as_deref().unwrap()).collect();
+        assert_eq!(uids, vec!["chore-take-out-the-trash@example.com", "bin-collection@example.com", "chore-water-the-plants@example.com"]);
+
+        // Every component is a valid iCal file on its own
+        let item_url: Url = "http://some.id/for/testing".parse().unwrap();
+        let item = parse(&components[0].ical, item_url.clone(), SyncStatus::NotSynced).unwrap();
+        assert_eq!(item.unwrap_task().name(), "Take out the trash");
+        assert!(components[0].ical.contains("TZID:Europe/Paris"));
+        assert!(components[0].ical.contains("X-WR-CALNAME:Chores"));
+        let item = parse(&components[2].ical, item_url, SyncStatus::NotSynced).unwrap();
+        assert!(item.unwrap_task().completed());
+        assert!(!components[2].ical.contains("Take out the trash"));
+
+        assert!(split_calendar("BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nUID:1\r\n").is_err());
+        assert!(split_calendar("BEGIN:VTODO\r\nEND:VTODO\r\n").is_err());
+    }
 }
@@ -0,0 +1,209 @@
+//! Minimal edits of the iCal files tasks have been parsed from, see [`patch_raw_ics`]
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use crate::Task;
+use super::builder::build_fresh_from_task;
+
+/// A content line of an iCal file
+struct ContentLine<'a> {
+    /// The line as it appears in the file, including its folds and its line ending
+    text: &'a str,
+    /// The line once unfolded
+    unfolded: String,
+}
+
+impl ContentLine<'_> {
+    /// The name of the property (or `BEGIN`/`END` for component delimiters)
+    fn name(&self) -> String {
+        self.unfolded.split([';', ':']).next().unwrap_or_default().to_ascii_uppercase()
+    }
+
+    fn value(&self) -> &str {
+        self.unfolded.split_once(':').map(|(_, value)| value).unwrap_or_default()
+    }
+}
+
+/// Split an iCal file into its content lines (see RFC5545, section 3.1)
+fn content_lines(ics: &str) -> Vec<ContentLine<'_>> {
+    let mut lines: Vec<ContentLine> = Vec::new();
+    let mut start = 0;
+    for physical in ics.split_inclusive('\n') {
+        let end = start + physical.len();
+        let content = physical.trim_end_matches(['\r', '\n']);
+        match (content.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(line)) => {
+                line.text = &ics[end - physical.len() - line.text.len()..end];
+                line.unfolded.push_str(continuation);
+            },
+            _ => lines.push(ContentLine{ text: physical, unfolded: content.to_string() }),
+        }
+        start = end;
+    }
+    lines
+}
+
+/// The lines of the properties of the `VTODO` of an iCal file (excluding its sub-components), grouped by property name
+fn todo_properties<'a>(lines: &'a [ContentLine<'a>]) -> HashMap<String, Vec<&'a ContentLine<'a>>> {
+    let mut properties: HashMap<String, Vec<&ContentLine>> = HashMap::new();
+    let mut components = Vec::new();
+    for line in lines {
+        match line.name().as_str() {
+            "BEGIN" => components.push(line.value().to_ascii_uppercase()),
+            "END" => { components.pop(); },
+            name if components == ["VCALENDAR", "VTODO"] => properties.entry(name.to_string()).or_default().push(line),
+            _ => (),
+        }
+    }
+    properties
+}
+
+/// The indices of the lines of the alarms of the `VTODO` of an iCal file, including their `BEGIN` and `END` lines
+fn todo_alarm_lines(lines: &[ContentLine]) -> HashSet<usize> {
+    let mut indices = HashSet::new();
+    let mut components = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        if line.name() == "BEGIN" {
+            components.push(line.value().to_ascii_uppercase());
+        }
+        if components.len() >= 3 && components[..3] == ["VCALENDAR", "VTODO", "VALARM"] {
+            indices.insert(index);
+        }
+        if line.name() == "END" {
+            components.pop();
+        }
+    }
+    indices
+}
+
+/// Serialize a task as the iCal file it has been parsed from, where only the properties that have changed since then are rewritten.
+///
+/// This keeps everything this crate does not understand (e.g. time zones, formatting) exactly as it was.
+/// Alarms are kept as well, unless they have changed, in which case they are all rewritten
+pub(crate) fn patch_raw_ics(task: &Task, raw_ics: &str) -> Result<String, Box<dyn Error>> {
+    let original = super::parse(raw_ics, task.url().clone(), task.sync_status().clone())?;
+    let alarms_changed = original.unwrap_task().alarms() != task.alarms();
+    let original_ics = build_fresh_from_task(original.unwrap_task())?;
+    let current_ics = build_fresh_from_task(task)?;
+    if original_ics == current_ics {
+        return Ok(raw_ics.to_string());
+    }
+
+    let original_lines = content_lines(&original_ics);
+    let current_lines = content_lines(&current_ics);
+    let original_properties = todo_properties(&original_lines);
+    let current_properties = todo_properties(&current_lines);
+    let unfolded = |lines: Option<&Vec<&ContentLine>>| -> Vec<String> {
+        lines.map(|lines| lines.iter().map(|line| line.unfolded.clone()).collect()).unwrap_or_default()
+    };
+    let changed: HashSet<&String> = original_properties.keys().chain(current_properties.keys())
+        .filter(|name| unfolded(original_properties.get(*name)) != unfolded(current_properties.get(*name)))
+        .collect();
+
+    let raw_lines = content_lines(raw_ics);
+    let raw_properties = todo_properties(&raw_lines);
+
+    let mut patched = String::with_capacity(raw_ics.len());
+    let mut written = HashSet::new();
+    let mut write_current = |patched: &mut String, name: &String| {
+        if written.insert(name.clone()) {
+            for line in current_properties.get(name).into_iter().flatten() {
+                patched.push_str(line.text);
+            }
+        }
+    };
+    // Properties that do not exist in the original file are added after the other properties, before sub-components (e.g. alarms)
+    let mut added: Vec<&String> = changed.iter()
+        .filter(|name| !raw_properties.contains_key(**name))
+        .copied()
+        .collect();
+    added.sort();
+    let raw_alarms = todo_alarm_lines(&raw_lines);
+    let current_alarms = todo_alarm_lines(&current_lines);
+
+    let mut components = Vec::new();
+    for (index, line) in raw_lines.iter().enumerate() {
+        if alarms_changed && raw_alarms.contains(&index) {
+            continue;
+        }
+        let name = line.name();
+        let in_todo = components == ["VCALENDAR", "VTODO"];
+        match name.as_str() {
+            "BEGIN" | "END" if in_todo => {
+                for name in added.drain(..) {
+                    write_current(&mut patched, name);
+                }
+                if alarms_changed && name == "END" {
+                    for (_, alarm_line) in current_lines.iter().enumerate().filter(|(index, _)| current_alarms.contains(index)) {
+                        patched.push_str(alarm_line.text);
+                    }
+                }
+            },
+            _ if in_todo && changed.contains(&name) => {
+                // The first line of a changed property is replaced by its new lines, and the other ones are removed
+                write_current(&mut patched, &name);
+                continue;
+            },
+            _ => (),
+        }
+        match name.as_str() {
+            "BEGIN" => components.push(line.value().to_ascii_uppercase()),
+            "END" => { components.pop(); },
+            _ => (),
+        }
+        patched.push_str(line.text);
+    }
+    Ok(patched)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::item::SyncStatus;
+    use crate::task::CompletionStatus;
+
+    #[test]
+    fn test_patch_raw_ics() {
+        let raw_ics = "BEGIN:VCALENDAR\r\n\
+            VERSION:2.0\r\n\
+            PRODID:-//Some server//EN\r\n\
+            BEGIN:VTODO\r\n\
+            UID:some-uid\r\n\
+            DTSTAMP:20210101T100000Z\r\n\
+            SUMMARY:Buy milk\r\n\
+            X-UNKNOWN;X-PARAM=\"quoted\":folded\r\n  \"value\"\r\n\
+            STATUS:NEEDS-ACTION\r\n\
+            BEGIN:VALARM\r\n\
+            ACTION:DISPLAY\r\n\
+            TRIGGER:-PT15M\r\n\
+            END:VALARM\r\n\
+            END:VTODO\r\n\
+            END:VCALENDAR\r\n";
+        let url = "https://some.server/cal/task.ics".parse().unwrap();
+        let mut item = crate::ical::parse(raw_ics, url, SyncStatus::NotSynced).unwrap();
+        assert_eq!(crate::ical::build_from(&item).unwrap(), raw_ics);
+
+        item.unwrap_task_mut().set_completion_status(CompletionStatus::Completed(None));
+        let patched = crate::ical::build_from(&item).unwrap();
+        assert!(patched.contains("STATUS:COMPLETED\r\n"));
+        assert!(patched.contains("PERCENT-COMPLETE:100\r\n"));
+        assert!(!patched.contains("NEEDS-ACTION"));
+        // Everything else is kept as it was
+        assert!(patched.contains("X-UNKNOWN;X-PARAM=\"quoted\":folded\r\n  \"value\"\r\n"));
+        assert!(patched.contains("BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:-PT15M\r\nEND:VALARM\r\n"));
+        assert!(patched.contains("SUMMARY:Buy milk\r\n"));
+        assert!(patched.contains("LAST-MODIFIED:"));
+        assert!(patched.find("PERCENT-COMPLETE").unwrap() < patched.find("BEGIN:VALARM").unwrap());
+        assert!(patched.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Some server//EN\r\n"));
+
+        // Alarms are rewritten once they change
+        let alarm = crate::alarm::Alarm::display(crate::alarm::AlarmTrigger::before_start(chrono::Duration::hours(1)), "Milk");
+        item.unwrap_task_mut().add_alarm(alarm);
+        let patched = crate::ical::build_from(&item).unwrap();
+        assert!(patched.contains("BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:-PT15M\r\nEND:VALARM\r\nBEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:-PT1H\r\nDESCRIPTION:Milk\r\nEND:VALARM\r\nEND:VTODO\r\n"));
+        assert!(patched.contains("X-UNKNOWN;X-PARAM=\"quoted\":folded\r\n  \"value\"\r\n"));
+        let reparsed = crate::ical::parse(&patched, item.url().clone(), SyncStatus::NotSynced).unwrap();
+        assert_eq!(reparsed.unwrap_task().alarms(), item.unwrap_task().alarms());
+    }
+}
@@ -1,24 +1,34 @@
-//! CalDAV items (todo, events, journals...)
+//! CalDAV items (todo, events, journals...), and CardDAV contacts
 // TODO: move Event and Task to nest them in crate::items::calendar::Calendar?
 
+use std::error::Error;
+
 use serde::{Deserialize, Serialize};
 use url::Url;
 use chrono::{DateTime, Utc};
 
+use crate::calendar::cached_calendar::CachedCalendar;
+use crate::calendar::SupportedComponents;
+
 
+// Tasks are larger than the other items. Boxing them would not be worth the churn
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Item {
     Event(crate::event::Event),
     Task(crate::task::Task),
+    /// A contact of an address book (see [`crate::calendar::address_book::AddressBook`])
+    Contact(crate::contact::Contact),
 }
 
-/// Returns `task.$property_name` or `event.$property_name`, depending on whether self is a Task or an Event
+/// Returns `task.$property_name`, `event.$property_name` or `contact.$property_name`, depending on whether self is a Task, an Event or a Contact
 macro_rules! synthetise_common_getter {
     ($property_name:ident, $return_type:ty) => {
         pub fn $property_name(&self) -> $return_type {
             match self {
                 Item::Event(e) => e.$property_name(),
                 Item::Task(t) => t.$property_name(),
+                Item::Contact(c) => c.$property_name(),
             }
         }
     }
@@ -37,6 +47,7 @@ impl Item {
         match self {
             Item::Event(e) => e.set_sync_status(new_status),
             Item::Task(t) => t.set_sync_status(new_status),
+            Item::Contact(c) => c.set_sync_status(new_status),
         }
     }
 
@@ -54,6 +65,19 @@ impl Item {
         }
     }
 
+    pub fn is_contact(&self) -> bool {
+        matches!(self, Item::Contact(_))
+    }
+
+    /// The kind of calendar component this item is (e.g. `VTODO` for tasks), that calendars must support to contain it
+    pub fn component(&self) -> SupportedComponents {
+        match self {
+            Item::Event(_) => SupportedComponents::EVENT,
+            Item::Task(_) => SupportedComponents::TODO,
+            Item::Contact(_) => SupportedComponents::CONTACT,
+        }
+    }
+
     /// Returns a mutable reference to the inner Task
     ///
     /// # Panics
@@ -76,11 +100,98 @@ impl Item {
         }
     }
 
+    /// Returns a reference to the inner Event
+    ///
+    /// # Panics
+    /// Panics if the inner item is not an Event
+    pub fn unwrap_event(&self) -> &crate::event::Event {
+        match self {
+            Item::Event(e) => e,
+            _ => panic!("Not an event"),
+        }
+    }
+
+    pub fn unwrap_event_mut(&mut self) -> &mut crate::event::Event {
+        match self {
+            Item::Event(e) => e,
+            _ => panic!("Not an event"),
+        }
+    }
+
+    /// Returns a mutable reference to the inner Contact
+    ///
+    /// # Panics
+    /// Panics if the inner item is not a Contact
+    pub fn unwrap_contact_mut(&mut self) -> &mut crate::contact::Contact {
+        match self {
+            Item::Contact(c) => c,
+            _ => panic!("Not a contact"),
+        }
+    }
+
+    /// Returns a reference to the inner Contact
+    ///
+    /// # Panics
+    /// Panics if the inner item is not a Contact
+    pub fn unwrap_contact(&self) -> &crate::contact::Contact {
+        match self {
+            Item::Contact(c) => c,
+            _ => panic!("Not a contact"),
+        }
+    }
+
+    /// Returns whether both items have the same content, ignoring their sync metadata. See [`Task::content_eq`](crate::Task::content_eq)
+    pub fn content_eq(&self, other: &Item) -> bool {
+        match (self, other) {
+            (Item::Event(s), Item::Event(o)) => s.content_eq(o),
+            (Item::Task(s),  Item::Task(o))  => s.content_eq(o),
+            (Item::Contact(s), Item::Contact(o)) => s.content_eq(o),
+            _ => false,
+        }
+    }
+
+    /// The fields that differ between this item and `other`, with their values in both items (see [`Task::diff`](crate::Task::diff)). \
+    /// This is empty if both items have the same content (see [`Item::content_eq`])
+    pub fn diff(&self, other: &Item) -> Vec<FieldChange> {
+        match (self, other) {
+            (Item::Event(s), Item::Event(o)) => s.diff(o),
+            (Item::Task(s),  Item::Task(o))  => s.diff(o),
+            (Item::Contact(s), Item::Contact(o)) => s.diff(o),
+            _ => {
+                let mut changes = Vec::new();
+                FieldChange::push_if_different(&mut changes, ItemField::Kind, Some(self.ical_component().to_string()), Some(other.ical_component().to_string()));
+                FieldChange::push_if_different(&mut changes, ItemField::Uid, Some(self.uid().to_string()), Some(other.uid().to_string()));
+                FieldChange::push_if_different(&mut changes, ItemField::Name, Some(self.name().to_string()), Some(other.name().to_string()));
+                changes
+            },
+        }
+    }
+
+    /// Add a copy of this item to `calendar`, with a new UID and URL (see [`Task::duplicate_into`](crate::Task::duplicate_into)). \
+    /// Returns the URL of the copy
+    pub fn duplicate_into(&self, calendar: &mut CachedCalendar) -> Result<Url, Box<dyn Error>> {
+        match self {
+            Item::Event(e) => e.duplicate_into(calendar),
+            Item::Task(t) => t.duplicate_into(calendar),
+            Item::Contact(c) => c.duplicate_into(calendar),
+        }
+    }
+
+    /// The name of the iCal component of this item (e.g. `VTODO`), or `VCARD` for contacts
+    fn ical_component(&self) -> &'static str {
+        match self {
+            Item::Event(_) => "VEVENT",
+            Item::Task(_) => "VTODO",
+            Item::Contact(_) => "VCARD",
+        }
+    }
+
     #[cfg(any(test, feature = "integration_tests"))]
     pub fn has_same_observable_content_as(&self, other: &Item) -> bool {
         match (self, other) {
             (Item::Event(s), Item::Event(o)) => s.has_same_observable_content_as(o),
             (Item::Task(s),  Item::Task(o))  => s.has_same_observable_content_as(o),
+            (Item::Contact(s), Item::Contact(o)) => s.has_same_observable_content_as(o),
             _ => false,
         }
     }
@@ -89,6 +200,42 @@ impl Item {
 
 
 
+/// A field of an item, see [`FieldChange`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ItemField {
+    /// Whether this is a task, an event or a contact (`VTODO`, `VEVENT` or `VCARD`)
+    Kind,
+    Uid,
+    /// The `SUMMARY` of the item
+    Name,
+    /// The iCal `STATUS` of a task, i.e. `COMPLETED` or `NEEDS-ACTION`
+    Status,
+    /// The `COMPLETED` date of a task, as an RFC 3339 string
+    CompletionDate,
+    /// Any other iCal property (e.g. `DESCRIPTION` or `DUE`), by its uppercase name
+    Property(String),
+}
+
+/// A field that differs between two versions of an item, see [`Item::diff`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: ItemField,
+    /// The value of the field in the first item, or `None` if it is not set there
+    pub old: Option<String>,
+    /// The value of the field in the other item, or `None` if it is not set there
+    pub new: Option<String>,
+}
+
+impl FieldChange {
+    pub(crate) fn push_if_different(changes: &mut Vec<FieldChange>, field: ItemField, old: Option<String>, new: Option<String>) {
+        if old != new {
+            changes.push(FieldChange{ field, old, new });
+        }
+    }
+}
+
+
+
 /// A VersionTag is basically a CalDAV `ctag` or `etag`. Whenever it changes, this means the data has changed.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct VersionTag {
@@ -131,9 +278,94 @@ pub enum SyncStatus {
     LocallyDeleted(VersionTag),
 }
 impl SyncStatus {
+    /// The version tag this item has been synced with, if any
+    pub fn version_tag(&self) -> Option<&VersionTag> {
+        match self {
+            SyncStatus::NotSynced => None,
+            SyncStatus::Synced(tag) | SyncStatus::LocallyModified(tag) | SyncStatus::LocallyDeleted(tag) => Some(tag),
+        }
+    }
+
+    /// What this status is, regardless of its version tag
+    pub fn kind(&self) -> SyncStatusKind {
+        match self {
+            SyncStatus::NotSynced => SyncStatusKind::NotSynced,
+            SyncStatus::Synced(_) => SyncStatusKind::Synced,
+            SyncStatus::LocallyModified(_) => SyncStatusKind::LocallyModified,
+            SyncStatus::LocallyDeleted(_) => SyncStatusKind::LocallyDeleted,
+        }
+    }
+
     /// Generate a random SyncStatus::Synced
     #[cfg(feature = "local_calendar_mocks_remote_calendars")]
     pub fn random_synced() -> Self {
         Self::Synced(VersionTag::random())
     }
 }
+
+/// The variants of [`SyncStatus`], without their version tags. This is used to select items by status
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SyncStatusKind {
+    NotSynced,
+    Synced,
+    LocallyModified,
+    LocallyDeleted,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ical::property::Property;
+    use crate::task::{CompletionStatus, Task};
+
+    fn task(name: &str, completion_status: CompletionStatus, extra_parameters: Vec<Property>) -> Item {
+        Item::Task(Task::new_with_parameters(
+            name.to_string(), "uid".to_string(), "https://some.server/cal/task.ics".parse().unwrap(), completion_status,
+            SyncStatus::NotSynced, None, Utc::now(), "prod id".to_string(), extra_parameters))
+    }
+
+    fn property(name: &str, value: &str) -> Property {
+        Property{ name: name.to_string(), params: None, value: Some(value.to_string()) }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = task("Buy milk", CompletionStatus::Uncompleted, vec![property("DESCRIPTION", "Skimmed"), property("SEQUENCE", "1")]);
+        assert!(old.diff(&old.clone()).is_empty());
+
+        let completion_date = Utc::now();
+        let new = task("Buy soy milk", CompletionStatus::Completed(Some(completion_date)), vec![
+            property("SEQUENCE", "2"), property("CATEGORIES", "home"), property("categories", "groceries"),
+        ]);
+        let change = |field, old: Option<&str>, new: Option<&str>| FieldChange{ field, old: old.map(String::from), new: new.map(String::from) };
+        assert_eq!(old.diff(&new), vec![
+            change(ItemField::Name, Some("Buy milk"), Some("Buy soy milk")),
+            change(ItemField::Status, Some("NEEDS-ACTION"), Some("COMPLETED")),
+            change(ItemField::CompletionDate, None, Some(&completion_date.to_rfc3339())),
+            change(ItemField::Property("CATEGORIES".to_string()), None, Some("home, groceries")),
+            change(ItemField::Property("DESCRIPTION".to_string()), Some("Skimmed"), None),
+        ]);
+    }
+    #[test]
+    fn test_duplicate_into() {
+        use crate::calendar::SupportedComponents;
+        use crate::traits::CompleteCalendar;
+
+        let original = task("Buy milk", CompletionStatus::Uncompleted, vec![property("DESCRIPTION", "Skimmed"), property("SEQUENCE", "4")]);
+        let cal_url: Url = "https://some.server/other-cal/".parse().unwrap();
+        let mut cal = CachedCalendar::new("Other".to_string(), cal_url.clone(), SupportedComponents::TODO, None);
+        let copy_url = original.duplicate_into(&mut cal).unwrap();
+
+        let copy = cal.get_item_by_url_sync(&copy_url).unwrap();
+        assert!(copy_url.as_str().starts_with(cal_url.as_str()));
+        assert_ne!(copy.uid(), original.uid());
+        assert_eq!(copy.sync_status(), &SyncStatus::NotSynced);
+        assert_eq!(copy.unwrap_task().extra_parameters().len(), 1);
+        assert_eq!(original.diff(copy), vec![FieldChange{ field: ItemField::Uid, old: Some("uid".to_string()), new: Some(copy.uid().to_string()) }]);
+
+        let mut event_cal = CachedCalendar::new("Events".to_string(), cal_url, SupportedComponents::EVENT, None);
+        assert!(original.duplicate_into(&mut event_cal).is_err());
+        assert!(event_cal.get_items_sync().unwrap().is_empty());
+    }
+}
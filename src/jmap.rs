@@ -0,0 +1,415 @@
+//! This module provides sources that are synced with a [JMAP](https://jmap.io/) server:
+//! the task lists of JMAP for Tasks (the [`TASKS_CAPABILITY`], see [`Jmap`]), and the calendars of JMAP for Calendars (the [`CALENDARS_CAPABILITY`], see [`JmapCalendars`])
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use csscolorparser::Color;
+use reqwest::Method;
+use reqwest::header::ACCEPT;
+use serde::Deserialize;
+use url::Url;
+
+use crate::resource::{AccessToken, Resource};
+use crate::calendar::jmap_calendar::{JmapCalendar, JmapTaskList};
+use crate::calendar::remote_calendar::SendError;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarTimezone;
+use crate::client::ServerCapabilities;
+use crate::client::transport::Transport;
+use crate::error::KFError;
+use crate::rest::send_json;
+use crate::traits::CalDavSource;
+use crate::traits::BaseCalendar;
+use crate::traits::DavCalendar;
+
+/// The capability of the JMAP core protocol
+const CORE_CAPABILITY: &str = "urn:ietf:params:jmap:core";
+/// The capability of JMAP for Tasks
+pub const TASKS_CAPABILITY: &str = "urn:ietf:params:jmap:tasks";
+/// The capability of JMAP for Calendars
+pub const CALENDARS_CAPABILITY: &str = "urn:ietf:params:jmap:calendars";
+
+/// The JMAP session of an account, as returned by the session resource of the server
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Session {
+    api_url: String,
+    event_source_url: Option<String>,
+    #[serde(default)]
+    primary_accounts: HashMap<String, String>,
+}
+
+/// A task list, as described by JMAP for Tasks, or a calendar, as described by JMAP for Calendars
+#[derive(Debug, Deserialize)]
+struct JmapList {
+    id: String,
+    name: Option<String>,
+    color: Option<String>,
+}
+
+/// A connection to a JMAP server, that is shared by a source and its calendars
+#[derive(Debug)]
+pub(crate) struct JmapConnection {
+    /// The session resource of the server
+    resource: Resource,
+    /// The capability of the objects that are synced (e.g. [`TASKS_CAPABILITY`])
+    capability: &'static str,
+    session: Mutex<Option<Session>>,
+}
+
+impl JmapConnection {
+    pub(crate) fn new(resource: Resource, capability: &'static str) -> Self {
+        Self { resource, capability, session: Mutex::new(None) }
+    }
+
+    /// The session resource of the server
+    pub(crate) fn url(&self) -> &Url {
+        self.resource.url()
+    }
+
+    /// Fetch the session of the account, unless it has been fetched already
+    async fn session(&self) -> Result<Session, SendError> {
+        if let Some(session) = &*self.session.lock().unwrap() {
+            return Ok(session.clone());
+        }
+        let reply = send_json(&self.resource, Method::GET, self.resource.url().clone(), None, None).await?;
+        let session: Session = serde_json::from_str(&reply)?;
+        if !session.primary_accounts.contains_key(self.capability) {
+            return Err(format!("The JMAP server at {} does not support {}", self.resource.url(), self.capability).into());
+        }
+        *self.session.lock().unwrap() = Some(session.clone());
+        Ok(session)
+    }
+
+    /// Send method calls (their names, and their arguments but the account ID, that is added) in a single request, and return their responses (their names and their arguments).
+    ///
+    /// Calls are given the IDs `"0"`, `"1"`, etc., that later calls can reference (see [RFC 8620](https://www.rfc-editor.org/rfc/rfc8620#section-3.7)).
+    /// A method that fails returns an `"error"` response: see [`method_result`]
+    pub(crate) async fn call(&self, method_calls: Vec<(&str, serde_json::Value)>) -> Result<Vec<(String, serde_json::Value)>, SendError> {
+        let session = self.session().await?;
+        let api_url = self.resource.url().join(&session.api_url)?;
+        let account_id = &session.primary_accounts[self.capability];
+
+        let method_calls: Vec<_> = method_calls.into_iter().enumerate()
+            .map(|(index, (name, mut arguments))| {
+                arguments["accountId"] = serde_json::json!(account_id);
+                serde_json::json!([name, arguments, index.to_string()])
+            })
+            .collect();
+        let body = serde_json::json!({ "using": [CORE_CAPABILITY, self.capability], "methodCalls": method_calls });
+        let reply = send_json(&self.resource, Method::POST, api_url.clone(), Some(&body), None).await?;
+        let reply: serde_json::Value = serde_json::from_str(&reply)?;
+        let responses = reply["methodResponses"].as_array()
+            .ok_or_else(|| format!("Invalid JMAP reply from {}", api_url))?;
+        Ok(responses.iter()
+            .map(|response| (response[0].as_str().unwrap_or_default().to_string(), response[1].clone()))
+            .collect())
+    }
+
+    /// Wait until the server pushes a change of the calendars (or task lists) or items of the account
+    async fn wait_for_changes(&self) -> Result<(), SendError> {
+        let session = self.session().await?;
+        let template = session.event_source_url
+            .ok_or_else(|| format!("The JMAP server at {} does not push changes", self.resource.url()))?;
+        let types = match self.capability {
+            CALENDARS_CAPABILITY => "Calendar,CalendarEvent",
+            _ => "TaskList,Task",
+        };
+        // The server closes the connection after its first change
+        let url = template.replace("{types}", types).replace("{closeafter}", "state").replace("{ping}", "0");
+        let url = self.resource.url().join(&url)?;
+        let request = self.resource.request(Method::GET, url.clone()).header(ACCEPT, "text/event-stream");
+        let response = self.resource.send(request).await.map_err(crate::calendar::remote_calendar::sendable)?;
+        if !response.status().is_success() {
+            return Err(Box::new(KFError::from_status(response.status(), url)));
+        }
+        response.text().await?;
+        Ok(())
+    }
+}
+
+/// The arguments of a method response, or the error it reports.
+///
+/// `url` is the URL errors are about
+pub(crate) fn method_result(response: (String, serde_json::Value), url: &Url) -> Result<serde_json::Value, SendError> {
+    match response.0.as_str() {
+        "error" => Err(set_error(&response.1, url)),
+        _ => Ok(response.1),
+    }
+}
+
+/// Convert a method error or a `SetError` (that describe why an object could not be created, updated or destroyed) to an error
+pub(crate) fn set_error(error: &serde_json::Value, url: &Url) -> SendError {
+    match error["type"].as_str().unwrap_or_default() {
+        "notFound" => Box::new(KFError::NotFound{ url: url.clone() }),
+        "forbidden" | "accountReadOnly" => Box::new(KFError::Forbidden{ url: url.clone() }),
+        error_type => format!("JMAP error {} for {}: {}", error_type, url, error["description"].as_str().unwrap_or_default()).into(),
+    }
+}
+
+/// The task lists of a JMAP account (e.g. a Fastmail or a Stalwart account).
+///
+/// This source can be used instead of a [`Client`](crate::Client) in a [`Provider`](crate::provider::Provider) (see [`JmapProvider`](crate::JmapProvider)),
+/// so that servers that speak JMAP rather than CalDAV can be synced. \
+/// Each task list is a [`JmapTaskList`]: see its documentation for how tasks are mapped to iCal items.
+///
+/// The URL of this source is the session resource of the server (e.g. `https://api.fastmail.com/jmap/session`), that tells where the API is. \
+/// The URLs of the task lists and tasks of this source are built from this URL and their JMAP IDs: they are only used to tell them apart, and cannot be requested.
+#[derive(Debug)]
+pub struct Jmap {
+    connection: Arc<JmapConnection>,
+    calendars: Mutex<Option<HashMap<Url, Arc<Mutex<JmapTaskList>>>>>,
+}
+
+impl Jmap {
+    /// Use the JMAP session resource at `url`, with basic authentication
+    pub fn new<S: AsRef<str>, T: ToString, U: ToString>(url: S, username: T, password: U) -> Result<Self, KFError> {
+        let url = Url::parse(url.as_ref())?;
+        Ok(Self::with_resource(Resource::new(url, username.to_string(), password.to_string())))
+    }
+
+    /// Use the JMAP session resource at `url`, with an OAuth2 access token (or an API token, that Fastmail sends as one)
+    pub fn with_access_token(url: Url, access_token: AccessToken) -> Self {
+        Self::with_resource(Resource::new_with_access_token(url, access_token, Transport::default()))
+    }
+
+    fn with_resource(resource: Resource) -> Self {
+        Self {
+            connection: Arc::new(JmapConnection::new(resource, TASKS_CAPABILITY)),
+            calendars: Mutex::new(None),
+        }
+    }
+
+    /// The URL of the calendar of the task list that has a given ID
+    pub fn task_list_url(&self, id: &str) -> Url {
+        let mut url = self.connection.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().extend(&["tasklists", id, ""]);
+        }
+        url
+    }
+
+    /// Create a new task list.
+    ///
+    /// JMAP servers choose the IDs of task lists, so that they cannot be created by [`CalDavSource::create_calendar`]. Apps should create them with this function instead,
+    /// and the next sync of a [`Provider`](crate::provider::Provider) will create their local counterparts
+    pub async fn create_task_list(&self, name: &str) -> Result<Arc<Mutex<JmapTaskList>>, KFError> {
+        let arguments = serde_json::json!({ "create": { "new": { "name": name } } });
+        let response = self.connection.call(vec![("TaskList/set", arguments)]).await
+            ?
+            .into_iter().next().ok_or("Invalid reply to TaskList/set")?;
+        let set = method_result(response, self.connection.url())?;
+        if let Some(error) = set["notCreated"].get("new") {
+            return Err(set_error(error, self.connection.url()).into());
+        }
+        let id = set["created"]["new"]["id"].as_str().ok_or("Invalid reply to TaskList/set")?;
+        let calendar = Arc::new(Mutex::new(self.task_list(JmapList{ id: id.to_string(), name: Some(name.to_string()), color: None })));
+
+        let mut calendars = self.calendars.lock().unwrap();
+        if let Some(calendars) = calendars.as_mut() {
+            let url = calendar.lock().unwrap().url().clone();
+            calendars.insert(url, calendar.clone());
+        }
+        Ok(calendar)
+    }
+
+    /// Wait until the server pushes a change of the task lists or tasks of the account (through its event source), e.g. to sync only when something has changed.
+    ///
+    /// This returns an error if the server does not push changes
+    pub async fn wait_for_changes(&self) -> Result<(), KFError> {
+        self.connection.wait_for_changes().await.map_err(KFError::from)
+    }
+
+    fn task_list(&self, list: JmapList) -> JmapTaskList {
+        let resource = self.connection.resource.combine(self.task_list_url(&list.id).path());
+        let color = list.color.and_then(|color| color.parse().ok());
+        JmapTaskList::new(list.name.unwrap_or_default(), resource, SupportedComponents::TODO, color)
+            .with_connection(self.connection.clone())
+    }
+
+    async fn fetch_task_lists(&self) -> Result<HashMap<Url, Arc<Mutex<JmapTaskList>>>, KFError> {
+        let response = self.connection.call(vec![("TaskList/get", serde_json::json!({ "ids": null }))]).await
+            ?
+            .into_iter().next().ok_or("Invalid reply to TaskList/get")?;
+        let lists = method_result(response, self.connection.url())?;
+        let lists: Vec<JmapList> = serde_json::from_value(lists["list"].clone())?;
+
+        let mut calendars = HashMap::new();
+        for list in lists {
+            let calendar = self.task_list(list);
+            calendars.insert(calendar.url().clone(), Arc::new(Mutex::new(calendar)));
+        }
+        Ok(calendars)
+    }
+}
+
+/// The calendars of a JMAP account (e.g. a Fastmail or a Stalwart account).
+///
+/// This source can be used instead of a [`Client`](crate::Client) in a [`Provider`](crate::provider::Provider) (see [`JmapCalendarProvider`](crate::JmapCalendarProvider)),
+/// so that the events of servers that speak JMAP rather than CalDAV can be synced. \
+/// Each calendar is a [`JmapCalendar`]: see its documentation for how events are mapped to iCal items.
+///
+/// Like the URL of a [`Jmap`] source, the URL of this source is the session resource of the server,
+/// and the URLs of the calendars and events of this source are built from this URL and their JMAP IDs.
+#[derive(Debug)]
+pub struct JmapCalendars {
+    connection: Arc<JmapConnection>,
+    calendars: Mutex<Option<HashMap<Url, Arc<Mutex<JmapCalendar>>>>>,
+}
+
+impl JmapCalendars {
+    /// Use the JMAP session resource at `url`, with basic authentication
+    pub fn new<S: AsRef<str>, T: ToString, U: ToString>(url: S, username: T, password: U) -> Result<Self, KFError> {
+        let url = Url::parse(url.as_ref())?;
+        Ok(Self::with_resource(Resource::new(url, username.to_string(), password.to_string())))
+    }
+
+    /// Use the JMAP session resource at `url`, with an OAuth2 access token (or an API token, that Fastmail sends as one)
+    pub fn with_access_token(url: Url, access_token: AccessToken) -> Self {
+        Self::with_resource(Resource::new_with_access_token(url, access_token, Transport::default()))
+    }
+
+    fn with_resource(resource: Resource) -> Self {
+        Self {
+            connection: Arc::new(JmapConnection::new(resource, CALENDARS_CAPABILITY)),
+            calendars: Mutex::new(None),
+        }
+    }
+
+    /// The URL of the calendar that has a given ID
+    pub fn calendar_url(&self, id: &str) -> Url {
+        let mut url = self.connection.url().clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().extend(&["calendars", id, ""]);
+        }
+        url
+    }
+
+    /// Create a new calendar.
+    ///
+    /// Like task lists, calendars cannot be created by [`CalDavSource::create_calendar`] (see [`Jmap::create_task_list`])
+    pub async fn create_jmap_calendar(&self, name: &str) -> Result<Arc<Mutex<JmapCalendar>>, KFError> {
+        let arguments = serde_json::json!({ "create": { "new": { "name": name } } });
+        let response = self.connection.call(vec![("Calendar/set", arguments)]).await
+            ?
+            .into_iter().next().ok_or("Invalid reply to Calendar/set")?;
+        let set = method_result(response, self.connection.url())?;
+        if let Some(error) = set["notCreated"].get("new") {
+            return Err(set_error(error, self.connection.url()).into());
+        }
+        let id = set["created"]["new"]["id"].as_str().ok_or("Invalid reply to Calendar/set")?;
+        let calendar = Arc::new(Mutex::new(self.calendar(JmapList{ id: id.to_string(), name: Some(name.to_string()), color: None })));
+
+        let mut calendars = self.calendars.lock().unwrap();
+        if let Some(calendars) = calendars.as_mut() {
+            let url = calendar.lock().unwrap().url().clone();
+            calendars.insert(url, calendar.clone());
+        }
+        Ok(calendar)
+    }
+
+    /// Wait until the server pushes a change of the calendars or events of the account (see [`Jmap::wait_for_changes`])
+    pub async fn wait_for_changes(&self) -> Result<(), KFError> {
+        self.connection.wait_for_changes().await.map_err(KFError::from)
+    }
+
+    fn calendar(&self, calendar: JmapList) -> JmapCalendar {
+        let resource = self.connection.resource.combine(self.calendar_url(&calendar.id).path());
+        let color = calendar.color.and_then(|color| color.parse().ok());
+        JmapCalendar::new(calendar.name.unwrap_or_default(), resource, SupportedComponents::EVENT, color)
+            .with_connection(self.connection.clone())
+    }
+
+    async fn fetch_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<JmapCalendar>>>, KFError> {
+        let response = self.connection.call(vec![("Calendar/get", serde_json::json!({ "ids": null }))]).await
+            ?
+            .into_iter().next().ok_or("Invalid reply to Calendar/get")?;
+        let calendars = method_result(response, self.connection.url())?;
+        let calendars: Vec<JmapList> = serde_json::from_value(calendars["list"].clone())?;
+
+        let mut result = HashMap::new();
+        for calendar in calendars {
+            let calendar = self.calendar(calendar);
+            result.insert(calendar.url().clone(), Arc::new(Mutex::new(calendar)));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<JmapTaskList> for Jmap {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<JmapTaskList>>>, KFError> {
+        if let Some(calendars) = &*self.calendars.lock().unwrap() {
+            return Ok(calendars.clone());
+        }
+        let calendars = self.fetch_task_lists().await?;
+        *self.calendars.lock().unwrap() = Some(calendars.clone());
+        Ok(calendars)
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<JmapTaskList>>> {
+        self.get_calendars().await.ok()?.get(url).cloned()
+    }
+
+    async fn create_calendar(&mut self, url: Url, _name: String, _supported_components: SupportedComponents, _color: Option<Color>) -> Result<Arc<Mutex<JmapTaskList>>, KFError> {
+        Err(format!("Calendar {} cannot be created: JMAP servers choose the IDs of task lists (see Jmap::create_task_list)", url).into())
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<JmapTaskList>>, KFError> {
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        // This is not a CalDAV server
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        Ok(())
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<JmapCalendar> for JmapCalendars {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<JmapCalendar>>>, KFError> {
+        if let Some(calendars) = &*self.calendars.lock().unwrap() {
+            return Ok(calendars.clone());
+        }
+        let calendars = self.fetch_calendars().await?;
+        *self.calendars.lock().unwrap() = Some(calendars.clone());
+        Ok(calendars)
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<JmapCalendar>>> {
+        self.get_calendars().await.ok()?.get(url).cloned()
+    }
+
+    async fn create_calendar(&mut self, url: Url, _name: String, _supported_components: SupportedComponents, _color: Option<Color>) -> Result<Arc<Mutex<JmapCalendar>>, KFError> {
+        Err(format!("Calendar {} cannot be created: JMAP servers choose the IDs of calendars (see JmapCalendars::create_jmap_calendar)", url).into())
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<JmapCalendar>>, KFError> {
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        // This is not a CalDAV server
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+//! A key-value store that lives in a single file, see [`KvStore`]
+//!
+//! It is used as an alternative backend of the [`Cache`](crate::cache::Cache) (see [`KvStorage`](crate::cache::storage::KvStorage)),
+//! for apps that prefer a single file to a folder of JSON files.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use redb::{Database, ReadableTable, TableDefinition, TableError};
+
+/// The only table of the database
+const TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("kitchen-fridge");
+
+/// A key, and its new value (or `None` to delete it)
+type Op = (String, Option<Vec<u8>>);
+/// A key, and its value
+type Entry = (String, Vec<u8>);
+
+/// Several writes that are committed all at once, see [`KvStore::commit`]
+#[derive(Clone, Debug, Default)]
+pub struct WriteBatch {
+    ops: Vec<Op>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, key: String, value: Vec<u8>) {
+        self.ops.push((key, Some(value)));
+    }
+
+    pub fn delete(&mut self, key: String) {
+        self.ops.push((key, None));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// A key-value store backed by a [redb](https://docs.rs/redb) database.
+///
+/// Writes are grouped into batches, that are committed (and flushed to the disk) in a single transaction.
+/// A transaction that has not been completely written (e.g. because the app has crashed) is rolled back by redb the next time the file is opened,
+/// so that the store is always in the state of its last successful commit. \
+/// redb locks the file as long as it is open, so it is only opened during each operation, and other processes can use it in-between
+/// (callers are expected to prevent concurrent accesses, see [`KvStorage`](crate::cache::storage::KvStorage))
+#[derive(Debug)]
+pub struct KvStore {
+    path: PathBuf,
+}
+
+impl KvStore {
+    /// Open the store at `path`, or create an empty one if this file does not exist
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let store = Self { path: path.to_path_buf() };
+        // Make sure this is a valid database
+        store.database()?;
+        Ok(store)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn database(&self) -> Result<Database, redb::Error> {
+        Ok(Database::create(&self.path)?)
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let database = self.database()?;
+        let read = database.begin_read()?;
+        let table = match read.open_table(TABLE) {
+            Ok(table) => table,
+            // Nothing has been written yet
+            Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let value = table.get(key)?.map(|value| value.value().to_vec());
+        Ok(value)
+    }
+
+    /// The entries of this store, ordered by key
+    pub fn entries(&self) -> Result<Vec<Entry>, Box<dyn Error>> {
+        let database = self.database()?;
+        let read = database.begin_read()?;
+        let table = match read.open_table(TABLE) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut entries = Vec::new();
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            entries.push((key.value().to_string(), value.value().to_vec()));
+        }
+        Ok(entries)
+    }
+
+    /// Write a batch to the disk, in a single transaction.
+    ///
+    /// Writes that would not change anything (e.g. writing the value a key already has) are skipped, so that nothing is written if nothing has changed
+    pub fn commit(&mut self, batch: WriteBatch) -> Result<(), Box<dyn Error>> {
+        let database = self.database()?;
+        let write = database.begin_write()?;
+        let mut changed = false;
+        {
+            let mut table = write.open_table(TABLE)?;
+            for (key, value) in batch.ops {
+                let current = table.get(key.as_str())?.map(|current| current.value().to_vec());
+                if current == value {
+                    continue;
+                }
+                changed = true;
+                match value {
+                    Some(value) => { table.insert(key.as_str(), value.as_slice())?; },
+                    None => { table.remove(key.as_str())?; },
+                }
+            }
+        }
+        match changed {
+            true => write.commit()?,
+            false => write.abort()?,
+        }
+        Ok(())
+    }
+
+    /// Rewrite the file, so that it does not contain free space anymore
+    pub fn compact(&mut self) -> Result<(), Box<dyn Error>> {
+        self.database()?.compact()?;
+        Ok(())
+    }
+}
+
+/// FNV-1a, which is enough to detect changes (e.g. to compute a version tag from a content)
+pub(crate) fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kv_store() {
+        let path = PathBuf::from("test_cache/kv_store/store.kv");
+        let _ = std::fs::remove_file(&path);
+
+        let mut store = KvStore::open(&path).unwrap();
+        assert_eq!(store.get("a").unwrap(), None);
+        assert!(store.entries().unwrap().is_empty());
+
+        let mut batch = WriteBatch::new();
+        batch.put("a".to_string(), b"first".to_vec());
+        batch.put("b".to_string(), b"second".to_vec());
+        store.commit(batch).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.delete("a".to_string());
+        batch.put("c".to_string(), b"third".to_vec());
+        store.commit(batch).unwrap();
+
+        // Writing values that are already known is a no-op
+        let modified = std::fs::read(&path).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put("b".to_string(), b"second".to_vec());
+        batch.delete("a".to_string());
+        store.commit(batch).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), modified);
+
+        let mut store = KvStore::open(&path).unwrap();
+        assert_eq!(store.entries().unwrap(), vec![("b".to_string(), b"second".to_vec()), ("c".to_string(), b"third".to_vec())]);
+        assert_eq!(store.get("c").unwrap(), Some(b"third".to_vec()));
+
+        store.compact().unwrap();
+        let store = KvStore::open(&path).unwrap();
+        assert_eq!(store.get("b").unwrap(), Some(b"second".to_vec()));
+        assert_eq!(store.get("a").unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_store() {
+        let path = PathBuf::from("test_cache/kv_store/invalid.kv");
+        let _ = std::fs::remove_file(&path);
+
+        // Files that are not redb databases are not overwritten
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"garbage that is long enough to look like a header, but is not one at all").unwrap();
+        assert!(KvStore::open(&path).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"garbage that is long enough to look like a header, but is not one at all");
+    }
+}
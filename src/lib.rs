@@ -10,12 +10,21 @@
 //! It provides a CalDAV client in the [`client`] module, that can be used as a stand-alone module.
 //!
 //! Because the connection to the server may be slow, this crate also provides a local cache for CalDAV data in the [`cache`] module.
-//! This way, user-frendly apps are able to quicky display cached data on startup.
+//! This way, user-frendly apps are able to quicky display cached data on startup. \
+//! Reactive apps can also be notified of every change of the cache (see [`cache::Cache::subscribe`]), so that they only refresh what has changed after a sync.
 //!
 //! These two "data sources" (actual client and local cache) can be used together in a [`CalDavProvider`](CalDavProvider). \
 //! A `CalDavProvider` abstracts these two sources by merging them together into one virtual source. \
 //! It also handles synchronisation between the local cache and the server, and robustly recovers from any network error (so that it never corrupts the local or remote source).
 //!
+//! Calendars that are only published as iCal files (e.g. `webcal://` feeds) can be subscribed to with the [`subscription`] module, and synced into the same kind of local cache with a [`SubscriptionProvider`]. \
+//! Calendars can also be read from (and written to) a local folder of iCal files (see the [`vdir`] module), e.g. to work fully offline, or next to tools such as khal, with a [`VdirProvider`].
+//!
+//! Contacts of CardDAV address books (see the [`contact`] and [`vcard`] modules) are synced the same way, with a [`CardDavProvider`] built from [`Client::address_books`].
+//!
+//! Apps that remind their users of their tasks can have the alarms of the cached items fired at the right moment by an [`alarms::Scheduler`].
+//! Mail clients can parse the meeting invitations attached to emails with the [`scheduling`] module.
+//!
 //! Note that many methods are defined in common traits (see [`crate::traits`]).
 //!
 //! ## Examples
@@ -23,11 +32,32 @@
 //! See example usage in the `examples/` folder, that you can run using `cargo run --example <example-name>`. \
 //! You can also have a look at [`Voilà`](https://github.com/daladim/voila-tasks), a GUI app that uses `kitchen-fridge` under the hood.
 //!
+//! ## Without an async runtime
+//!
+//! Apps that do not run an async runtime can use the blocking wrappers of the [`blocking`] module.
+//!
 //! ## Configuration options
 //!
 //! Have a look at the [`config`] module to see what default options can be overridden.
+//!
+//! The network settings of a [`Client`] (e.g. TLS client certificates) can be set with a [`client::ClientBuilder`]. \
+//! Since every calendar of a `Client` shares its HTTP settings, a `CalDavProvider` built around such a client uses them for the whole sync.
+//!
+//! ## Optional features
+//!
+//! * `push_notifications` enables the [`client::push`] module, to be notified of changes instead of polling the server
+//! * `tracing` emits [`tracing`](https://docs.rs/tracing) spans for every sync, calendar, item operation and HTTP request, with their URLs, item UIDs and HTTP statuses.
+//!   The messages of a sync are then emitted as `tracing` events within these spans (they are still forwarded to `log` when no `tracing` subscriber is set)
+//! * `google` enables the [`google`] module, to sync the task lists of Google accounts through the Google Tasks API (with a [`GoogleTasksProvider`]), and their calendars through the Google Calendar API (with a [`GoogleCalendarProvider`]), rather than through CalDAV
+//! * `graph` enables the [`graph`] module, to sync the Microsoft To Do task lists of Microsoft accounts (e.g. Outlook.com or Microsoft 365) through the Microsoft Graph API (with a [`GraphTodoProvider`]), and their Outlook calendars (with a [`GraphCalendarProvider`])
+//! * `ews` enables the [`ews`] module, to sync the task folders of on-premises Exchange servers that have no CalDAV access, through Exchange Web Services (with an [`EwsProvider`]), and their calendar folders (with an [`EwsCalendarProvider`])
+//! * `jmap` enables the [`jmap`] module, to sync the task lists of JMAP servers (e.g. Fastmail or Stalwart) with JMAP for Tasks (with a [`JmapProvider`]) and their calendars with JMAP for Calendars (with a [`JmapCalendarProvider`]), and to be notified of their changes
+//! * `etebase` enables the [`etebase`] module, to sync the end-to-end encrypted calendars of Etebase servers (e.g. EteSync) with an [`EtebaseProvider`]. The encryption itself is left to the `etebase` crate (see [`etebase::EtebaseAccount`])
+//! * `desktop_notifications` enables the [`alarms::notifications`] module, to show desktop notifications (with buttons to dismiss or snooze them) with `notify-rust` for the alarms fired by an [`alarms::Scheduler`]
 
 #![doc(html_logo_url = "https://raw.githubusercontent.com/daladim/kitchen-fridge/master/resources/kitchen-fridge.svg")]
+// `KFError` carries the URLs of the items and calendars that failed, which makes it larger than what clippy expects from an error type
+#![allow(clippy::result_large_err)]
 
 pub mod traits;
 
@@ -38,19 +68,102 @@ pub mod task;
 pub use task::Task;
 pub mod event;
 pub use event::Event;
+pub mod contact;
+pub use contact::Contact;
+pub mod vcard;
+pub mod alarm;
+pub mod alarms;
+pub mod freebusy;
+pub mod occurrence;
+pub mod recurrence;
+pub mod scheduling;
+pub mod partial;
 pub mod provider;
 pub mod mock_behaviour;
 
 pub mod client;
 pub use client::Client;
+pub mod subscription;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod vdir;
+#[cfg(feature = "google")]
+pub mod google;
+#[cfg(feature = "graph")]
+pub mod graph;
+#[cfg(feature = "ews")]
+pub mod ews;
+#[cfg(feature = "jmap")]
+pub mod jmap;
+#[cfg(feature = "etebase")]
+pub mod etebase;
 pub mod cache;
 pub use cache::Cache;
+pub mod kv_store;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod blocking;
 pub mod ical;
 
 pub mod config;
+pub mod error;
 pub mod utils;
 pub mod resource;
+pub mod uid;
+pub mod metrics;
+mod spans;
+mod time;
+#[cfg(any(feature = "google", feature = "graph", feature = "jmap"))]
+mod rest;
 
 /// Unless you want another kind of Provider to write integration tests, you'll probably want this kind of Provider. \
 /// See alse the [`Provider` documentation](crate::provider::Provider)
 pub type CalDavProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, Client, calendar::remote_calendar::RemoteCalendar>;
+
+/// A Provider that syncs the CardDAV address books of an account (see [`client::AddressBooks`]) into a local cache
+pub type CardDavProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, client::AddressBooks, calendar::address_book::AddressBook>;
+
+/// A Provider that syncs read-only calendars published as iCal files (see [`subscription::Subscriptions`]) into a local cache
+pub type SubscriptionProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, subscription::Subscriptions, calendar::subscribed_calendar::SubscribedCalendar>;
+
+/// A Provider that syncs the calendars of a local vdir (see [`vdir::Vdir`]) into a local cache
+#[cfg(not(target_arch = "wasm32"))]
+pub type VdirProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, vdir::Vdir, calendar::vdir_calendar::VdirCalendar>;
+
+/// A Provider that syncs the task lists of a Google account (see [`google::GoogleTasks`]) into a local cache
+#[cfg(feature = "google")]
+pub type GoogleTasksProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, google::GoogleTasks, calendar::google_calendar::GoogleTaskList>;
+
+/// A Provider that syncs the calendars of a Google account (see [`google::GoogleCalendars`]) into a local cache
+#[cfg(feature = "google")]
+pub type GoogleCalendarProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, google::GoogleCalendars, calendar::google_calendar::GoogleCalendar>;
+
+/// A Provider that syncs the Microsoft To Do task lists of a Microsoft account (see [`graph::GraphTodo`]) into a local cache
+#[cfg(feature = "graph")]
+pub type GraphTodoProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, graph::GraphTodo, calendar::graph_calendar::GraphTaskList>;
+
+/// A Provider that syncs the Outlook calendars of a Microsoft account (see [`graph::GraphCalendars`]) into a local cache
+#[cfg(feature = "graph")]
+pub type GraphCalendarProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, graph::GraphCalendars, calendar::graph_calendar::GraphCalendar>;
+
+/// A Provider that syncs the task folders of an Exchange mailbox (see [`ews::Ews`]) into a local cache
+#[cfg(feature = "ews")]
+pub type EwsProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, ews::Ews, calendar::ews_calendar::EwsTaskFolder>;
+
+/// A Provider that syncs the calendar folders of an Exchange mailbox (see [`ews::EwsCalendars`]) into a local cache
+#[cfg(feature = "ews")]
+pub type EwsCalendarProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, ews::EwsCalendars, calendar::ews_calendar::EwsCalendar>;
+
+/// A Provider that syncs the task lists of a JMAP account (see [`jmap::Jmap`]) into a local cache
+#[cfg(feature = "jmap")]
+pub type JmapProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, jmap::Jmap, calendar::jmap_calendar::JmapTaskList>;
+
+/// A Provider that syncs the calendars of a JMAP account (see [`jmap::JmapCalendars`]) into a local cache
+#[cfg(feature = "jmap")]
+pub type JmapCalendarProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, jmap::JmapCalendars, calendar::jmap_calendar::JmapCalendar>;
+
+/// A Provider that syncs the collections of an Etebase account (see [`etebase::Etebase`]) into a local cache
+#[cfg(feature = "etebase")]
+pub type EtebaseProvider = provider::Provider<cache::Cache, calendar::cached_calendar::CachedCalendar, etebase::Etebase, calendar::etebase_calendar::EtebaseCalendar>;
+
+/// Several [`CalDavProvider`]s synced together, usually one per account. \
+/// See also the [`MultiProvider` documentation](crate::provider::multi::MultiProvider)
+pub type CalDavMultiProvider = provider::multi::MultiProvider<cache::Cache, calendar::cached_calendar::CachedCalendar, Client, calendar::remote_calendar::RemoteCalendar>;
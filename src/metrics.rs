@@ -0,0 +1,80 @@
+//! Figures about what clients and providers do, e.g. to export them to Prometheus (see [`MetricsSink`])
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode};
+use url::Url;
+
+/// An HTTP request that has been sent, see [`MetricsSink::request`]
+#[derive(Clone, Debug)]
+pub struct RequestMetrics {
+    pub method: Method,
+    /// The status of the response, or `None` if no response has been received (e.g. because of a timeout)
+    pub status: Option<StatusCode>,
+    /// How long it took to get the response
+    pub elapsed: Duration,
+    /// The size of the request body
+    pub bytes_sent: u64,
+    /// The size of the response body, if the server has told it
+    pub bytes_received: Option<u64>,
+}
+
+/// How an item has been synced, see [`MetricsSink::item_synced`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ItemTransfer {
+    Downloaded,
+    Uploaded,
+    /// The item has been deleted locally, because it had been deleted from the server
+    DeletedLocally,
+    /// The item has been deleted from the server, because it had been deleted locally
+    DeletedRemotely,
+}
+
+/// Something that is told about every request of a [`Client`](crate::client::Client) (see [`ClientBuilder::metrics_sink`](crate::client::ClientBuilder::metrics_sink)),
+/// and about every sync of a [`Provider`](crate::provider::Provider) (see [`Provider::set_metrics_sink`](crate::provider::Provider::set_metrics_sink)).
+///
+/// Implementations usually increment counters, and should return quickly since they are called during syncs.
+/// Every method does nothing by default
+pub trait MetricsSink: Send + Sync {
+    /// Called once for every HTTP request. Retries and redirections are reported as distinct requests
+    fn request(&self, _request: &RequestMetrics) {}
+
+    /// Called when an item has been transferred during a sync
+    fn item_synced(&self, _calendar: &Url, _transfer: ItemTransfer) {}
+
+    /// Called when an item has been modified both locally and on the server
+    fn conflict(&self, _calendar: &Url) {}
+
+    /// Called for every error (or warning) of a sync
+    fn sync_error(&self, _calendar: Option<&Url>) {}
+
+    /// Called at the end of every sync
+    fn sync_finished(&self, _duration: Duration, _success: bool) {}
+}
+
+/// The sink a client or a provider reports into, if any
+#[derive(Clone, Default)]
+pub(crate) struct Metrics {
+    sink: Option<Arc<dyn MetricsSink>>,
+}
+
+impl Metrics {
+    pub(crate) fn new(sink: Option<Arc<dyn MetricsSink>>) -> Self {
+        Self { sink }
+    }
+
+    pub(crate) fn sink(&self) -> Option<&dyn MetricsSink> {
+        self.sink.as_deref()
+    }
+}
+
+impl Debug for Metrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.sink {
+            None => write!(f, "<no metrics sink>"),
+            Some(_) => write!(f, "<metrics sink>"),
+        }
+    }
+}
@@ -0,0 +1,25 @@
+//! Single instances of (possibly recurring) events, as expanded by a CalDAV server
+
+use chrono::{DateTime, Utc};
+use url::Url;
+
+/// One instance of an event.
+///
+/// For recurring events, the server returns one occurrence per instance within the requested time range (see [`RemoteCalendar::get_occurrences`](crate::calendar::remote_calendar::RemoteCalendar::get_occurrences)),
+/// so that clients do not have to evaluate recurrence rules themselves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Occurrence {
+    /// The URL of the item this occurrence is an instance of
+    pub item_url: Url,
+    /// The UID of the event
+    pub uid: String,
+    /// The summary of this instance (that may differ from the other instances, for overridden instances)
+    pub summary: Option<String>,
+    /// The `RECURRENCE-ID` of this instance, or `None` for non-recurring events
+    pub recurrence_id: Option<DateTime<Utc>>,
+    pub start: DateTime<Utc>,
+    /// The end of this instance, or `None` if the event has neither an end nor a duration
+    pub end: Option<DateTime<Utc>>,
+    /// Whether this is an all-day event (i.e. `start` and `end` have no time part)
+    pub all_day: bool,
+}
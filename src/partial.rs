@@ -0,0 +1,126 @@
+//! Lightweight versions of items, that only contain some of their properties
+//!
+//! Downloading only a few properties (e.g. the summary and dates of events) is much faster than downloading whole items,
+//! which is useful to quickly display an agenda. Full items can then be fetched on demand (e.g. with [`DavCalendar::get_item_by_url`](crate::traits::DavCalendar::get_item_by_url)).
+
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use ical::property::Property;
+use url::Url;
+
+use crate::item::VersionTag;
+
+/// Tells which components and properties should be retrieved (using the CalDAV `calendar-data` element)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PartialRequest {
+    /// Component names (e.g. `VEVENT`) and the names of their properties (e.g. `SUMMARY`)
+    components: Vec<(String, Vec<String>)>,
+}
+
+impl PartialRequest {
+    /// An empty request. Add components to it with [`Self::component`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A request for the properties that are usually displayed in agenda views (summary, dates, status)
+    pub fn agenda() -> Self {
+        Self::new()
+            .component("VEVENT", &["SUMMARY", "DTSTART", "DTEND", "DURATION", "RRULE", "STATUS"])
+            .component("VTODO", &["SUMMARY", "DTSTART", "DUE", "STATUS", "COMPLETED"])
+    }
+
+    /// Also retrieve some properties of a kind of component. The `UID` property is always retrieved
+    pub fn component(mut self, name: &str, properties: &[&str]) -> Self {
+        let mut properties: Vec<String> = properties.iter().map(|p| p.to_uppercase()).collect();
+        if !properties.iter().any(|p| p == "UID") {
+            properties.push("UID".to_string());
+        }
+        self.components.push((name.to_uppercase(), properties));
+        self
+    }
+
+    /// The names of the requested components
+    pub fn component_names(&self) -> impl Iterator<Item = &str> {
+        self.components.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// The `<calendar-data>` element of a REPORT that retrieves only the requested components and properties
+    pub(crate) fn to_calendar_data(&self) -> String {
+        let mut comps = String::new();
+        for (name, properties) in &self.components {
+            comps.push_str(&format!("<c:comp name=\"{}\">", name));
+            for property in properties {
+                comps.push_str(&format!("<c:prop name=\"{}\"/>", property));
+            }
+            comps.push_str("</c:comp>");
+        }
+
+        format!("<c:calendar-data><c:comp name=\"VCALENDAR\"><c:prop name=\"VERSION\"/>{}</c:comp></c:calendar-data>", comps)
+    }
+}
+
+
+/// An item, of which only some properties are known
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PartialItem {
+    url: Url,
+    version_tag: VersionTag,
+    /// The kind of component (e.g. `VEVENT` or `VTODO`)
+    component: String,
+    properties: Vec<Property>,
+}
+
+impl PartialItem {
+    pub fn new(url: Url, version_tag: VersionTag, component: String, properties: Vec<Property>) -> Self {
+        Self { url, version_tag, component, properties }
+    }
+
+    pub fn url(&self) -> &Url                 { &self.url }
+    pub fn version_tag(&self) -> &VersionTag  { &self.version_tag }
+    pub fn component(&self) -> &str           { &self.component }
+    pub fn properties(&self) -> &[Property]   { &self.properties }
+
+    pub fn is_event(&self) -> bool {
+        self.component == "VEVENT"
+    }
+
+    pub fn is_task(&self) -> bool {
+        self.component == "VTODO"
+    }
+
+    /// The value of a property, if it has been retrieved
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties.iter()
+            .find(|prop| prop.name.eq_ignore_ascii_case(name))
+            .and_then(|prop| prop.value.as_deref())
+    }
+
+    pub fn uid(&self) -> Option<&str> {
+        self.property("UID")
+    }
+
+    pub fn summary(&self) -> Option<&str> {
+        self.property("SUMMARY")
+    }
+
+    /// The value of a date property (e.g. `DTSTART`). Dates without a time part are considered to be at midnight UTC
+    pub fn date(&self, name: &str) -> Option<DateTime<Utc>> {
+        crate::ical::parse_date_value(self.property(name)?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calendar_data() {
+        let request = PartialRequest::new().component("vevent", &["summary", "DTSTART"]);
+        assert_eq!(
+            request.to_calendar_data(),
+            r#"<c:calendar-data><c:comp name="VCALENDAR"><c:prop name="VERSION"/><c:comp name="VEVENT"><c:prop name="SUMMARY"/><c:prop name="DTSTART"/><c:prop name="UID"/></c:comp></c:comp></c:calendar-data>"#
+        );
+    }
+}
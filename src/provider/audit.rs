@@ -0,0 +1,241 @@
+//! A persistent record of what syncs have done to the items of a calendar, see [`CompleteCalendar::audit_log`](crate::traits::CompleteCalendar::audit_log)
+//!
+//! This is mostly useful to find out why an item has changed or disappeared
+
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::item::{FieldChange, VersionTag};
+use super::pending::PendingChangeKind;
+
+/// How many entries an audit log keeps, unless configured otherwise (see [`AuditLog::set_max_entries`])
+const DEFAULT_MAX_ENTRIES: usize = 10_000;
+
+/// What a sync has done to an item
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditOperation {
+    Addition,
+    Modification,
+    Deletion,
+}
+
+impl From<PendingChangeKind> for AuditOperation {
+    fn from(kind: PendingChangeKind) -> Self {
+        match kind {
+            PendingChangeKind::Addition => Self::Addition,
+            PendingChangeKind::Modification => Self::Modification,
+            PendingChangeKind::Deletion => Self::Deletion,
+        }
+    }
+}
+
+/// Which side of the sync an operation has been applied to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditDirection {
+    /// A local change has been pushed to the server
+    Upload,
+    /// A change from the server has been applied locally
+    Download,
+}
+
+/// How an operation has ended
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AuditOutcome {
+    Done,
+    /// The server has refused the operation because the item has been changed there in the meantime
+    Conflict,
+    /// The operation has not been attempted (e.g. because of insufficient privileges). It will be retried at the next sync
+    Skipped,
+    Failed(String),
+}
+
+/// A single operation of a sync
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    pub calendar: Url,
+    pub item: Url,
+    pub operation: AuditOperation,
+    pub direction: AuditDirection,
+    /// The version tag (i.e. the ETag) the local item had been synced with before the operation, if any
+    pub old_etag: Option<VersionTag>,
+    /// The version tag of the item after the operation, if it still exists and has been synced
+    pub new_etag: Option<VersionTag>,
+    pub outcome: AuditOutcome,
+    /// The fields that a downloaded modification has changed in the local item. This is empty for other operations
+    #[serde(default)]
+    pub changes: Vec<FieldChange>,
+}
+
+impl Display for AuditEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let tag = |tag: &Option<VersionTag>| tag.as_ref().map(|tag| tag.as_str().to_string()).unwrap_or_else(|| "-".to_string());
+        write!(f, "{} {:?} {:?} {} ({} -> {}): {:?}",
+            self.at.to_rfc3339(), self.direction, self.operation, self.item,
+            tag(&self.old_etag), tag(&self.new_etag), self.outcome)
+    }
+}
+
+/// Criteria to select entries of an [`AuditLog`]. Every criterion that is set must match
+#[derive(Clone, Debug, Default)]
+pub struct AuditQuery {
+    item: Option<Url>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    operation: Option<AuditOperation>,
+    direction: Option<AuditDirection>,
+    failures_only: bool,
+}
+
+impl AuditQuery {
+    /// A query that matches every entry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only select the entries about an item
+    pub fn set_item(&mut self, item: Option<Url>) {
+        self.item = item;
+    }
+
+    /// Only select the entries that have been recorded during a given time range
+    pub fn set_time_range(&mut self, since: Option<DateTime<Utc>>, until: Option<DateTime<Utc>>) {
+        self.since = since;
+        self.until = until;
+    }
+
+    pub fn set_operation(&mut self, operation: Option<AuditOperation>) {
+        self.operation = operation;
+    }
+
+    pub fn set_direction(&mut self, direction: Option<AuditDirection>) {
+        self.direction = direction;
+    }
+
+    /// Only select the operations that have not been done (i.e. that have failed, been skipped, or run into a conflict)
+    pub fn set_failures_only(&mut self, failures_only: bool) {
+        self.failures_only = failures_only;
+    }
+
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.item.as_ref().is_none_or(|item| item == &entry.item)
+            && self.since.is_none_or(|since| entry.at >= since)
+            && self.until.is_none_or(|until| entry.at < until)
+            && self.operation.is_none_or(|operation| operation == entry.operation)
+            && self.direction.is_none_or(|direction| direction == entry.direction)
+            && (!self.failures_only || entry.outcome != AuditOutcome::Done)
+    }
+}
+
+/// The operations that syncs have performed on the items of a calendar, the oldest first.
+///
+/// Entries are only ever appended. When the log is full, the oldest ones are dropped
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditLog {
+    entries: VecDeque<AuditEntry>,
+    #[serde(default = "default_max_entries")]
+    max_entries: usize,
+}
+
+fn default_max_entries() -> usize {
+    DEFAULT_MAX_ENTRIES
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self { entries: VecDeque::new(), max_entries: DEFAULT_MAX_ENTRIES }
+    }
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how many entries are kept. Defaults to 10000
+    pub fn set_max_entries(&mut self, max_entries: usize) {
+        self.max_entries = max_entries;
+        self.truncate();
+    }
+
+    pub(crate) fn record(&mut self, entry: AuditEntry) {
+        self.entries.push_back(entry);
+        self.truncate();
+    }
+
+    fn truncate(&mut self) {
+        while self.entries.len() > self.max_entries {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every entry, the oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &AuditEntry> {
+        self.entries.iter()
+    }
+
+    /// The entries that match a query, the oldest first
+    pub fn query<'a>(&'a self, query: &'a AuditQuery) -> impl Iterator<Item = &'a AuditEntry> {
+        self.entries.iter().filter(move |entry| query.matches(entry))
+    }
+
+    /// Everything that has been done to an item, the oldest first
+    pub fn item_history<'a>(&'a self, item: &'a Url) -> impl Iterator<Item = &'a AuditEntry> {
+        self.entries.iter().filter(move |entry| &entry.item == item)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(item: &str, operation: AuditOperation, outcome: AuditOutcome) -> AuditEntry {
+        AuditEntry {
+            at: Utc::now(),
+            calendar: "https://some.server/cal/".parse().unwrap(),
+            item: item.parse().unwrap(),
+            operation,
+            direction: AuditDirection::Upload,
+            old_etag: None,
+            new_etag: Some(VersionTag::from(String::from("etag"))),
+            outcome,
+            changes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let first: Url = "https://some.server/cal/first.ics".parse().unwrap();
+        let mut log = AuditLog::new();
+        log.record(entry(first.as_str(), AuditOperation::Addition, AuditOutcome::Done));
+        log.record(entry("https://some.server/cal/second.ics", AuditOperation::Addition, AuditOutcome::Failed("Oops".to_string())));
+        log.record(entry(first.as_str(), AuditOperation::Modification, AuditOutcome::Skipped));
+        assert_eq!(log.item_history(&first).count(), 2);
+
+        let mut query = AuditQuery::new();
+        query.set_failures_only(true);
+        assert_eq!(log.query(&query).count(), 2);
+        query.set_operation(Some(AuditOperation::Addition));
+        assert_eq!(log.query(&query).map(|entry| entry.item.as_str()).collect::<Vec<_>>(), vec!["https://some.server/cal/second.ics"]);
+        query.set_time_range(Some(Utc::now() + chrono::Duration::hours(1)), None);
+        assert_eq!(log.query(&query).count(), 0);
+
+        // The oldest entries are dropped first
+        log.set_max_entries(1);
+        assert_eq!(log.len(), 1);
+        assert_eq!(log.entries().next().unwrap().operation, AuditOperation::Modification);
+    }
+}
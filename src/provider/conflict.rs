@@ -0,0 +1,181 @@
+//! How conflicts between local and remote changes are resolved during a sync
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use url::Url;
+
+use crate::Item;
+use crate::item::{FieldChange, VersionTag};
+
+/// An item that has been changed both locally and on the server since the last sync
+#[derive(Debug)]
+pub struct Conflict<'a> {
+    /// The URL of the conflicting item
+    pub url: &'a Url,
+    /// The local version, or `None` if the item has been locally deleted
+    pub local: Option<&'a Item>,
+    /// The remote version, or `None` if the item has been deleted from the server
+    pub remote: Option<&'a Item>,
+}
+
+impl Conflict<'_> {
+    /// The fields that would change if the remote version replaced the local one, e.g. to show them to the user.
+    /// This is empty if one side has been deleted
+    pub fn changes(&self) -> Vec<FieldChange> {
+        match (self.local, self.remote) {
+            (Some(local), Some(remote)) => local.diff(remote),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The outcome of a [`Conflict`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// The server version replaces the local one (or the local item is deleted, if it has been deleted from the server)
+    KeepRemote,
+    /// The local version is pushed to the server (or the remote item is deleted, if it has been locally deleted)
+    KeepLocal,
+    /// The server version replaces the local one, and the local version is uploaded as a new item (a "conflicted copy", see [`conflicted_copy_name`]).
+    /// When one side has been deleted, this keeps the side that has been modified
+    KeepBoth,
+}
+
+/// A user-provided function that resolves conflicts, e.g. by asking the user
+pub type ConflictResolver = Arc<dyn Fn(&Conflict) -> Resolution + Send + Sync>;
+
+/// The strategy used to resolve conflicts, see [`Provider::set_conflict_resolution`](crate::provider::Provider::set_conflict_resolution)
+#[derive(Clone, Default)]
+pub enum ConflictResolution {
+    /// The server always wins. This is the default
+    #[default]
+    ServerWins,
+    /// The local version always wins
+    LocalWins,
+    /// The most recently modified version wins. Modifications win over deletions
+    NewestWins,
+    /// No version is discarded: the local version is kept as a conflicted copy, see [`Resolution::KeepBoth`]
+    KeepBoth,
+    /// A function is called for every conflict. It receives both versions
+    Custom(ConflictResolver),
+}
+
+impl Debug for ConflictResolution {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ServerWins => write!(f, "ServerWins"),
+            Self::LocalWins => write!(f, "LocalWins"),
+            Self::NewestWins => write!(f, "NewestWins"),
+            Self::KeepBoth => write!(f, "KeepBoth"),
+            Self::Custom(_) => write!(f, "Custom(<resolver>)"),
+        }
+    }
+}
+
+impl ConflictResolution {
+    /// Whether the remote version of conflicting items must be downloaded to resolve a conflict
+    pub(crate) fn needs_remote_version(&self) -> bool {
+        matches!(self, Self::NewestWins | Self::Custom(_))
+    }
+
+    pub(crate) fn resolve(&self, conflict: &Conflict) -> Resolution {
+        match self {
+            Self::ServerWins => Resolution::KeepRemote,
+            Self::LocalWins => Resolution::KeepLocal,
+            Self::KeepBoth => Resolution::KeepBoth,
+            Self::NewestWins => match (conflict.local, conflict.remote) {
+                (Some(local), Some(remote)) => {
+                    if local.last_modified() > remote.last_modified() {
+                        Resolution::KeepLocal
+                    } else {
+                        Resolution::KeepRemote
+                    }
+                },
+                (None, _) => Resolution::KeepRemote,
+                (_, None) => Resolution::KeepLocal,
+            },
+            Self::Custom(resolver) => resolver(conflict),
+        }
+    }
+}
+
+/// What has happened on both sides of a conflict
+pub(crate) enum ConflictKind {
+    /// Modified on both sides. The remote version has this version tag
+    BothModified(VersionTag),
+    /// Locally deleted, and remotely modified (the remote version has this version tag)
+    LocallyDeleted(VersionTag),
+    /// Locally modified, and deleted from the server
+    RemotelyDeleted,
+}
+
+/// The name of the copy of a conflicting item, e.g. `Buy milk (conflicted copy 2024-05-01)`
+pub fn conflicted_copy_name(name: &str, date: &DateTime<Utc>) -> String {
+    format!("{} (conflicted copy {})", name, date.format("%Y-%m-%d"))
+}
+
+/// Make a copy of an item, that can be uploaded as a new item (new URL and UID, and a name that tells it is a conflicted copy)
+pub(crate) fn copy_as_new_item(item: &Item, calendar_url: &Url) -> Option<Item> {
+    match item {
+        Item::Task(task) => {
+            let mut copy = task.duplicate(calendar_url);
+            copy.set_name(conflicted_copy_name(task.name(), &Utc::now()));
+            Some(Item::Task(copy))
+        },
+        Item::Contact(contact) => {
+            let mut copy = contact.duplicate(calendar_url);
+            copy.set_name(conflicted_copy_name(contact.name(), &Utc::now()));
+            Some(Item::Contact(copy))
+        },
+        Item::Event(event) => {
+            let mut copy = event.duplicate(calendar_url);
+            copy.set_name(conflicted_copy_name(event.name(), &Utc::now()));
+            Some(Item::Event(copy))
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use crate::item::SyncStatus;
+    use crate::task::CompletionStatus;
+
+    fn task_modified_at(url: &Url, last_modified: chrono::DateTime<Utc>) -> Item {
+        Item::Task(crate::Task::new_with_parameters(
+            "a task".to_string(), "uid".to_string(), url.clone(), CompletionStatus::Uncompleted,
+            SyncStatus::NotSynced, None, last_modified, "prod id".to_string(), Vec::new()))
+    }
+
+    #[test]
+    fn test_newest_wins() {
+        let url: Url = "https://some.server/cal/item.ics".parse().unwrap();
+        let older = task_modified_at(&url, Utc::now() - Duration::hours(1));
+        let newer = task_modified_at(&url, Utc::now());
+
+        let strategy = ConflictResolution::NewestWins;
+        assert_eq!(strategy.resolve(&Conflict{ url: &url, local: Some(&newer), remote: Some(&older) }), Resolution::KeepLocal);
+        assert_eq!(strategy.resolve(&Conflict{ url: &url, local: Some(&older), remote: Some(&newer) }), Resolution::KeepRemote);
+        assert_eq!(strategy.resolve(&Conflict{ url: &url, local: None, remote: Some(&older) }), Resolution::KeepRemote);
+        assert_eq!(strategy.resolve(&Conflict{ url: &url, local: Some(&older), remote: None }), Resolution::KeepLocal);
+
+        let custom = ConflictResolution::Custom(Arc::new(|_| Resolution::KeepBoth));
+        assert_eq!(custom.resolve(&Conflict{ url: &url, local: Some(&older), remote: None }), Resolution::KeepBoth);
+    }
+
+    #[test]
+    fn test_copy_as_new_item() {
+        let cal_url: Url = "https://some.server/cal/".parse().unwrap();
+        let original = task_modified_at(&cal_url.join("item.ics").unwrap(), Utc::now());
+        let copy = copy_as_new_item(&original, &cal_url).unwrap();
+        assert_ne!(copy.url(), original.url());
+        assert_ne!(copy.uid(), original.uid());
+        assert!(copy.name().starts_with("a task (conflicted copy "));
+        assert_eq!(conflicted_copy_name("Buy milk", &Utc.ymd(2024, 5, 1).and_hms(10, 0, 0)), "Buy milk (conflicted copy 2024-05-01)");
+        assert_eq!(copy.sync_status(), &SyncStatus::NotSynced);
+    }
+}
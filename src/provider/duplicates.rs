@@ -0,0 +1,142 @@
+//! Detection of duplicate items, see [`Provider::find_duplicates`](crate::provider::Provider::find_duplicates)
+
+use std::collections::HashMap;
+
+use url::Url;
+
+use crate::Item;
+use crate::item::SyncStatus;
+
+/// What makes two items duplicates of each other.
+///
+/// Items are duplicates when they have different UIDs, but every selected criterion has the same value
+#[derive(Clone, Debug, PartialEq)]
+pub struct DuplicateCriteria {
+    /// Compare the names (i.e. the `SUMMARY`) of items, ignoring case and surrounding whitespace
+    pub name: bool,
+    /// Compare whether items are completed (completion dates are ignored)
+    pub completion: bool,
+    /// Names of other iCal properties that must have the same values (e.g. `DTSTART`, `DUE` or `DESCRIPTION`). Missing properties match each other
+    pub properties: Vec<String>,
+}
+
+impl Default for DuplicateCriteria {
+    fn default() -> Self {
+        Self {
+            name: true,
+            completion: false,
+            properties: vec!["DTSTART".to_string(), "DTEND".to_string(), "DUE".to_string()],
+        }
+    }
+}
+
+/// Items of a calendar that are duplicates of each other
+#[derive(Clone, Debug, PartialEq)]
+pub struct DuplicateGroup {
+    pub calendar: Url,
+    /// The item that would be kept by [`Provider::merge_duplicates`](crate::provider::Provider::merge_duplicates), i.e. the most recently modified one
+    pub keep: Url,
+    /// The items that would be deleted
+    pub duplicates: Vec<Url>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct DuplicateKey {
+    name: Option<String>,
+    completed: Option<bool>,
+    properties: Vec<Option<String>>,
+}
+
+impl DuplicateCriteria {
+    /// The values this item is compared on, or `None` for items that are never considered as duplicates
+    fn key(&self, item: &Item) -> Option<DuplicateKey> {
+        let task = match item {
+            Item::Task(task) => task,
+            // Events and contacts have no completion
+            Item::Event(_) | Item::Contact(_) => return None,
+        };
+        if let SyncStatus::LocallyDeleted(_) = task.sync_status() {
+            return None;
+        }
+
+        Some(DuplicateKey {
+            name: self.name.then(|| task.name().trim().to_lowercase()),
+            completed: self.completion.then(|| task.completed()),
+            properties: self.properties.iter()
+                .map(|prop_name| task.extra_parameters().iter()
+                    .find(|prop| prop.name.eq_ignore_ascii_case(prop_name))
+                    .and_then(|prop| prop.value.clone()))
+                .collect(),
+        })
+    }
+
+    /// Group the items of a calendar that are duplicates of each other
+    pub(crate) fn find_duplicates<'a, I: IntoIterator<Item = &'a Item>>(&self, calendar: &Url, items: I) -> Vec<DuplicateGroup> {
+        let mut candidates: HashMap<DuplicateKey, Vec<&Item>> = HashMap::new();
+        for item in items {
+            if let Some(key) = self.key(item) {
+                candidates.entry(key).or_default().push(item);
+            }
+        }
+
+        let mut groups = Vec::new();
+        for (_, mut same_items) in candidates {
+            // Several versions of the same item are not duplicates
+            same_items.sort_by(|a, b| a.uid().cmp(b.uid()));
+            same_items.dedup_by(|a, b| a.uid() == b.uid());
+            if same_items.len() < 2 {
+                continue;
+            }
+
+            same_items.sort_by(|a, b| b.last_modified().cmp(a.last_modified()).then_with(|| a.url().cmp(b.url())));
+            groups.push(DuplicateGroup {
+                calendar: calendar.clone(),
+                keep: same_items[0].url().clone(),
+                duplicates: same_items[1..].iter().map(|item| item.url().clone()).collect(),
+            });
+        }
+        groups.sort_by(|a, b| a.keep.cmp(&b.keep));
+        groups
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use ical::property::Property;
+    use crate::task::CompletionStatus;
+
+    fn task(name: &str, uid: &str, due: Option<&str>, age_hours: i64) -> Item {
+        let url: Url = format!("https://some.server/cal/{}.ics", uid).parse().unwrap();
+        let extra_parameters = due.into_iter()
+            .map(|due| Property{ name: "DUE".to_string(), params: None, value: Some(due.to_string()) })
+            .collect();
+        Item::Task(crate::Task::new_with_parameters(
+            name.to_string(), uid.to_string(), url, CompletionStatus::Uncompleted, SyncStatus::NotSynced,
+            None, Utc::now() - Duration::hours(age_hours), "prod id".to_string(), extra_parameters))
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let cal: Url = "https://some.server/cal/".parse().unwrap();
+        let items = vec![
+            task("Buy milk", "a", Some("20211201T100000Z"), 3),
+            task(" buy Milk", "b", Some("20211201T100000Z"), 1),
+            task("Buy milk", "c", Some("20211202T100000Z"), 2),
+            task("Buy milk", "d", None, 2),
+            task("Walk the dog", "e", None, 2),
+        ];
+
+        let groups = DuplicateCriteria::default().find_duplicates(&cal, &items);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].keep, *items[1].url());
+        assert_eq!(groups[0].duplicates, vec![items[0].url().clone()]);
+
+        let by_name = DuplicateCriteria{ properties: Vec::new(), ..DuplicateCriteria::default() };
+        let groups = by_name.find_duplicates(&cal, &items);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].duplicates.len(), 3);
+    }
+}
@@ -0,0 +1,145 @@
+//! Restrictions of what is synced, see [`Provider::set_sync_filter`](crate::provider::Provider::set_sync_filter)
+
+use chrono::{DateTime, Utc};
+
+use crate::Item;
+use crate::calendar::SupportedComponents;
+
+/// Restricts a sync to some kinds of items, and to a time window.
+///
+/// The filter is applied symmetrically: items that are outside of it are neither downloaded, nor considered deleted.
+/// Hence, restricting a filter never deletes any data, neither locally nor on the server.
+///
+/// Items that are already known locally keep being synced, even if the server version has moved out of the time window
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyncFilter {
+    /// The kinds of items to sync
+    pub components: SupportedComponents,
+    /// Ignore items that ended before this date
+    pub since: Option<DateTime<Utc>>,
+    /// Ignore items that start after this date
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Default for SyncFilter {
+    fn default() -> Self {
+        Self { components: SupportedComponents::all(), since: None, until: None }
+    }
+}
+
+impl SyncFilter {
+    /// A filter that lets everything through
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only sync tasks
+    pub fn tasks_only() -> Self {
+        Self { components: SupportedComponents::TODO, ..Self::default() }
+    }
+
+    /// Only sync events
+    pub fn events_only() -> Self {
+        Self { components: SupportedComponents::EVENT, ..Self::default() }
+    }
+
+    /// Ignore items that ended before `since`
+    pub fn since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Ignore items that start after `until`
+    pub fn until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Whether this filter lets everything through
+    pub fn is_unrestricted(&self) -> bool {
+        self.components.contains(SupportedComponents::all()) && self.since.is_none() && self.until.is_none()
+    }
+
+    /// Whether a calendar that supports these components may contain items that are not filtered out
+    pub fn matches_calendar(&self, supported_components: SupportedComponents) -> bool {
+        self.components.intersects(supported_components)
+    }
+
+    /// Whether this kind of item is synced
+    pub fn matches_kind(&self, item: &Item) -> bool {
+        match item {
+            Item::Task(_) => self.components.contains(SupportedComponents::TODO),
+            Item::Event(_) => self.components.contains(SupportedComponents::EVENT),
+            Item::Contact(_) => self.components.contains(SupportedComponents::CONTACT),
+        }
+    }
+
+    /// Whether an item is synced.
+    ///
+    /// Like CalDAV servers do, items that have no date always match the time window
+    pub fn matches(&self, item: &Item) -> bool {
+        if !self.matches_kind(item) {
+            return false;
+        }
+        let dates: Vec<DateTime<Utc>> = match item {
+            Item::Task(task) => {
+                let completion_date = match task.completion_status() {
+                    crate::task::CompletionStatus::Completed(date) => *date,
+                    crate::task::CompletionStatus::Uncompleted => None,
+                };
+                task.creation_date().cloned().into_iter().chain(completion_date).collect()
+            },
+            Item::Event(event) => event.start().into_iter().chain(event.end()).collect(),
+            Item::Contact(_) => Vec::new(),
+        };
+        if dates.is_empty() {
+            return true;
+        }
+        dates.iter().any(|date| self.contains(date))
+    }
+
+    /// Whether a date is within the time window
+    pub fn contains(&self, date: &DateTime<Utc>) -> bool {
+        self.since.is_none_or(|since| date >= &since) && self.until.is_none_or(|until| date <= &until)
+    }
+
+    /// The CalDAV `<comp-filter>` elements (one per component, since they cannot be combined in a single query)
+    pub(crate) fn to_comp_filters(&self) -> Vec<String> {
+        let time_range = match (self.since, self.until) {
+            (None, None) => String::new(),
+            (since, until) => format!("<c:time-range{}{}/>",
+                since.map(|d| format!(" start=\"{}\"", d.format("%Y%m%dT%H%M%SZ"))).unwrap_or_default(),
+                until.map(|d| format!(" end=\"{}\"", d.format("%Y%m%dT%H%M%SZ"))).unwrap_or_default(),
+            ),
+        };
+        let mut filters = Vec::new();
+        for (flag, name) in [(SupportedComponents::EVENT, "VEVENT"), (SupportedComponents::TODO, "VTODO")] {
+            if self.components.contains(flag) {
+                filters.push(format!(
+                    "<c:filter><c:comp-filter name=\"VCALENDAR\"><c:comp-filter name=\"{}\">{}</c:comp-filter></c:comp-filter></c:filter>",
+                    name, time_range));
+            }
+        }
+        filters
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_comp_filters() {
+        let filter = SyncFilter::tasks_only().since(Utc.ymd(2021, 3, 1).and_hms(0, 0, 0));
+        assert_eq!(
+            filter.to_comp_filters(),
+            vec![r#"<c:filter><c:comp-filter name="VCALENDAR"><c:comp-filter name="VTODO"><c:time-range start="20210301T000000Z"/></c:comp-filter></c:comp-filter></c:filter>"#.to_string()]
+        );
+        assert_eq!(SyncFilter::new().to_comp_filters().len(), 2);
+        assert!(SyncFilter::new().is_unrestricted());
+        assert!(!filter.is_unrestricted());
+        assert!(!filter.matches_calendar(SupportedComponents::EVENT));
+    }
+}
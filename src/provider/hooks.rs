@@ -0,0 +1,73 @@
+//! Callbacks that transform items during syncs, see [`Provider::add_before_upload_hook`](crate::provider::Provider::add_before_upload_hook)
+//!
+//! Hooks can e.g. encrypt or decrypt some fields, strip attachments, or enforce naming conventions.
+//! A hook receives an item, and returns the item to use instead (possibly modified), or `None` to veto it.
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use futures_util::future::BoxFuture;
+
+use crate::Item;
+
+/// An async callback that transforms an item, or vetoes it (by returning `None`).
+///
+/// Hooks must not change the URL of items
+pub type ItemHook = Arc<dyn Fn(Item) -> BoxFuture<'static, Option<Item>> + Send + Sync>;
+
+/// The hooks registered on a [`Provider`](crate::provider::Provider)
+#[derive(Clone, Default)]
+pub(crate) struct SyncHooks {
+    pub(crate) before_upload: Vec<ItemHook>,
+    pub(crate) after_download: Vec<ItemHook>,
+}
+
+impl Debug for SyncHooks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SyncHooks({} before upload, {} after download)", self.before_upload.len(), self.after_download.len())
+    }
+}
+
+impl SyncHooks {
+    /// Run the hooks one after the other, until one of them vetoes the item
+    pub(crate) async fn run(hooks: &[ItemHook], item: Item) -> Option<Item> {
+        let url = item.url().clone();
+        let mut item = item;
+        for hook in hooks {
+            item = hook(item).await?;
+            if item.url() != &url {
+                log::warn!("A sync hook has changed the URL of {}. This is not supported, the item is ignored", url);
+                return None;
+            }
+        }
+        Some(item)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    #[tokio::test]
+    async fn test_run_hooks() {
+        let cal: Url = "https://some.server/cal/".parse().unwrap();
+        let task = Item::Task(crate::Task::new("a task".to_string(), false, &cal));
+
+        let rename: ItemHook = Arc::new(|item| Box::pin(async move {
+            match item {
+                Item::Task(mut task) => {
+                    task.set_name(task.name().to_uppercase());
+                    Some(Item::Task(task))
+                },
+                other => Some(other),
+            }
+        }));
+        let veto: ItemHook = Arc::new(|_| Box::pin(async { None }));
+
+        let renamed = SyncHooks::run(std::slice::from_ref(&rename), task.clone()).await.unwrap();
+        assert_eq!(renamed.name(), "A TASK");
+        assert!(SyncHooks::run(&[rename, veto], task).await.is_none());
+    }
+}
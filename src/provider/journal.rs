@@ -0,0 +1,107 @@
+//! Pending operations of a sync, so that an interrupted sync can be resumed
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Journals older than this are not resumed: the server has probably changed too much since they have been written
+const MAX_JOURNAL_AGE_HOURS: i64 = 24;
+
+/// The operations that a sync of a calendar still has to perform.
+///
+/// It is stored in the local calendar before these operations start, and every operation is removed from it as soon as it has been done.
+/// If the sync is interrupted (e.g. the application has been killed, or the network dropped), the next sync resumes the remaining operations instead of comparing both calendars again
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SyncJournal {
+    pub created_at: DateTime<Utc>,
+    /// Items deleted locally, to delete from the server
+    pub local_del: HashSet<Url>,
+    /// Items deleted from the server, to delete locally
+    pub remote_del: HashSet<Url>,
+    /// Items created on the server, to download
+    pub remote_additions: HashSet<Url>,
+    /// Items changed on the server, to download
+    pub remote_changes: HashSet<Url>,
+    /// Items created locally, to upload
+    pub local_additions: HashSet<Url>,
+    /// Items changed locally, to upload
+    pub local_changes: HashSet<Url>,
+    /// Local changes the server has refused because it has a newer version, that will be downloaded instead
+    #[serde(default)]
+    pub late_conflicts: HashSet<Url>,
+}
+
+impl SyncJournal {
+    /// An empty journal
+    pub fn new() -> Self {
+        Self {
+            created_at: Utc::now(),
+            local_del: HashSet::new(),
+            remote_del: HashSet::new(),
+            remote_additions: HashSet::new(),
+            remote_changes: HashSet::new(),
+            local_additions: HashSet::new(),
+            local_changes: HashSet::new(),
+            late_conflicts: HashSet::new(),
+        }
+    }
+
+    /// The number of remaining operations
+    pub fn len(&self) -> usize {
+        self.local_del.len() + self.remote_del.len()
+            + self.remote_additions.len() + self.remote_changes.len()
+            + self.local_additions.len() + self.local_changes.len()
+            + self.late_conflicts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether this journal is too old to be resumed
+    pub fn is_expired(&self) -> bool {
+        Utc::now() - self.created_at > Duration::hours(MAX_JOURNAL_AGE_HOURS)
+    }
+
+    /// Remove the operation on an item, once it has been performed (or attempted)
+    pub(crate) fn mark_done(&mut self, url: &Url) {
+        for pending in [
+            &mut self.local_del, &mut self.remote_del,
+            &mut self.remote_additions, &mut self.remote_changes,
+            &mut self.local_additions, &mut self.local_changes,
+            &mut self.late_conflicts,
+        ] {
+            pending.remove(url);
+        }
+    }
+}
+
+impl Default for SyncJournal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal() {
+        let url: Url = "https://some.server/cal/item.ics".parse().unwrap();
+        let mut journal = SyncJournal::new();
+        journal.remote_additions.insert(url.clone());
+        journal.local_changes.insert("https://some.server/cal/other.ics".parse().unwrap());
+        assert_eq!(journal.len(), 2);
+        assert!(!journal.is_expired());
+
+        journal.mark_done(&url);
+        assert_eq!(journal.len(), 1);
+
+        journal.created_at = Utc::now() - Duration::days(2);
+        assert!(journal.is_expired());
+    }
+}
@@ -0,0 +1,174 @@
+//! Property-level three-way merge of items that have been modified both locally and on the server
+
+use std::collections::BTreeMap;
+
+use ical::property::Property;
+
+use crate::Item;
+use crate::item::SyncStatus;
+use crate::task::Task;
+
+/// Merge one field. Returns `None` if both sides have changed this field in different ways
+fn merge_field<T: Clone, F: Fn(&T, &T) -> bool>(base: &T, local: &T, remote: &T, eq: F) -> Option<T> {
+    if eq(local, remote) || eq(local, base) {
+        Some(remote.clone())
+    } else if eq(remote, base) {
+        Some(local.clone())
+    } else {
+        None
+    }
+}
+
+fn property_eq(a: &Property, b: &Property) -> bool {
+    a.name == b.name && a.value == b.value && a.params == b.params
+}
+
+fn properties_eq(a: &[Property], b: &[Property]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(pa, pb)| property_eq(pa, pb))
+}
+
+/// Group properties by name, so that each kind of property (e.g. all the `CATEGORIES` lines) is merged as a single field
+fn by_name(properties: &[Property]) -> BTreeMap<String, Vec<Property>> {
+    let mut map: BTreeMap<String, Vec<Property>> = BTreeMap::new();
+    for prop in properties {
+        map.entry(prop.name.to_uppercase()).or_default().push(prop.clone());
+    }
+    map
+}
+
+fn merge_properties(base: &[Property], local: &[Property], remote: &[Property]) -> Option<Vec<Property>> {
+    let base = by_name(base);
+    let local = by_name(local);
+    let remote = by_name(remote);
+    let empty = Vec::new();
+
+    // Keep the order of the remote version, then append properties that only exist locally
+    let mut names: Vec<&String> = remote.keys().collect();
+    names.extend(local.keys().filter(|name| !remote.contains_key(*name)));
+    names.extend(base.keys().filter(|name| !remote.contains_key(*name) && !local.contains_key(*name)));
+
+    let mut merged = Vec::new();
+    for name in names {
+        let props = merge_field(
+            base.get(name).unwrap_or(&empty),
+            local.get(name).unwrap_or(&empty),
+            remote.get(name).unwrap_or(&empty),
+            |a, b| properties_eq(a, b),
+        )?;
+        merged.extend(props);
+    }
+    Some(merged)
+}
+
+fn merge_tasks(base: &Task, local: &Task, remote: &Task) -> Option<Task> {
+    let name = merge_field(&base.name().to_string(), &local.name().to_string(), &remote.name().to_string(), |a, b| a == b)?;
+    let completion_status = merge_field(base.completion_status(), local.completion_status(), remote.completion_status(), |a, b| a == b)?;
+    let extra_parameters = merge_properties(base.extra_parameters(), local.extra_parameters(), remote.extra_parameters())?;
+    // Alarms (including whether they have been dismissed or snoozed) are merged as a whole
+    let alarms = merge_field(&base.alarms().to_vec(), &local.alarms().to_vec(), &remote.alarms().to_vec(), |a, b| a == b)?;
+
+    let mut merged = Task::new_with_parameters(
+        name,
+        remote.uid().to_string(),
+        remote.url().clone(),
+        completion_status,
+        remote.sync_status().clone(),
+        remote.creation_date().or_else(|| local.creation_date()).cloned(),
+        std::cmp::max(*local.last_modified(), *remote.last_modified()),
+        remote.ical_prod_id().to_string(),
+        extra_parameters,
+    );
+    merged.set_alarms(alarms);
+    // The merged task is serialized as a patched version of the server one
+    merged.set_raw_ics(remote.raw_ics().map(str::to_string));
+    Some(merged)
+}
+
+/// Merge the changes made locally and on the server since `base`, which is the version both sides had after the last sync.
+///
+/// Returns `None` when a property has been changed on both sides (in which case the configured [`ConflictResolution`](super::conflict::ConflictResolution) should be used).
+/// The merged item carries the sync status of `remote`
+pub(crate) fn three_way_merge(base: &Item, local: &Item, remote: &Item) -> Option<Item> {
+    match (base, local, remote) {
+        (Item::Task(base), Item::Task(local), Item::Task(remote)) => {
+            merge_tasks(base, local, remote).map(Item::Task)
+        },
+        // Only tasks are merged, the other items rely on the conflict resolution
+        _ => None,
+    }
+}
+
+/// Turn a merged item into the local version that will be pushed to the server
+pub(crate) fn as_local_change(mut merged: Item) -> Item {
+    if let SyncStatus::Synced(tag) = merged.sync_status() {
+        let tag = tag.clone();
+        merged.set_sync_status(SyncStatus::LocallyModified(tag));
+    }
+    merged
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use url::Url;
+    use crate::item::VersionTag;
+    use crate::task::CompletionStatus;
+
+    fn task(name: &str, completed: bool, location: Option<&str>) -> Item {
+        let url: Url = "https://some.server/cal/item.ics".parse().unwrap();
+        let extra = location.map(|loc| vec![Property{ name: "LOCATION".to_string(), params: None, value: Some(loc.to_string()) }]).unwrap_or_default();
+        let completion = if completed { CompletionStatus::Completed(None) } else { CompletionStatus::Uncompleted };
+        Item::Task(Task::new_with_parameters(
+            name.to_string(), "uid".to_string(), url, completion,
+            SyncStatus::Synced(VersionTag::from("remote".to_string())), None, Utc::now(), "prod id".to_string(), extra))
+    }
+
+    #[test]
+    fn test_merge_distinct_properties() {
+        let base = task("name", false, None);
+        let local = task("name", true, None);
+        let remote = task("name", false, Some("office"));
+
+        let merged = three_way_merge(&base, &local, &remote).unwrap();
+        assert!(merged.unwrap_task().completed());
+        assert_eq!(merged.unwrap_task().extra_parameters()[0].value.as_deref(), Some("office"));
+        assert!(!merged.content_eq(&remote));
+    }
+
+    #[test]
+    fn test_merge_collision() {
+        let base = task("name", false, None);
+        let local = task("local name", false, None);
+        let remote = task("remote name", false, None);
+        assert!(three_way_merge(&base, &local, &remote).is_none());
+
+        // Identical changes on both sides are not a collision
+        let local = task("same name", false, None);
+        let remote = task("same name", false, None);
+        let merged = three_way_merge(&base, &local, &remote).unwrap();
+        assert!(merged.content_eq(&remote));
+    }
+
+    #[test]
+    fn test_content_eq() {
+        let item = task("name", false, Some("office"));
+        let mut synced_elsewhere = task("name", false, Some("office"));
+        synced_elsewhere.set_sync_status(SyncStatus::LocallyModified(VersionTag::from("other".to_string())));
+        assert!(item.content_eq(&synced_elsewhere));
+
+        // Revisions do not change the content
+        let mut revised = synced_elsewhere.unwrap_task().clone();
+        let mut extra = revised.extra_parameters().to_vec();
+        extra.push(Property{ name: "SEQUENCE".to_string(), params: None, value: Some("3".to_string()) });
+        revised = Task::new_with_parameters(revised.name().to_string(), revised.uid().to_string(), revised.url().clone(), revised.completion_status().clone(),
+            revised.sync_status().clone(), None, Utc::now(), "another prod id".to_string(), extra);
+        assert!(item.content_eq(&Item::Task(revised)));
+
+        assert!(!item.content_eq(&task("other name", false, Some("office"))));
+        assert!(!item.content_eq(&task("name", true, Some("office"))));
+        assert!(!item.content_eq(&task("name", false, Some("home"))));
+        assert!(!item.content_eq(&task("name", false, None)));
+    }
+}
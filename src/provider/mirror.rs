@@ -0,0 +1,357 @@
+//! Syncs between two servers, see [`Mirror`]
+//!
+//! This is useful to migrate from a server to another one, or to continuously mirror calendars between them.
+
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::traits::{CalDavSource, DavCalendar};
+use crate::item::{SyncStatus, VersionTag};
+use crate::Item;
+use crate::error::KFError;
+use super::conflict::{self, Conflict, ConflictResolution, Resolution};
+use super::moved_item;
+use super::sync_progress::{ProgressEvent, SyncProgress};
+use super::sync_result::SyncResult;
+
+/// An item that exists on both servers, as it was after the last sync
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct MirroredItem {
+    source_calendar: Url,
+    source_tag: VersionTag,
+    target_url: Url,
+    target_tag: VersionTag,
+}
+
+/// What a [`Mirror`] knows about previous syncs.
+///
+/// Servers do not keep track of what has already been synced. This state must be kept between syncs (e.g. serialized to a file), otherwise changes and deletions cannot be told apart from new items
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MirrorState {
+    /// Source calendar URL -> target calendar URL
+    calendars: HashMap<Url, Url>,
+    /// Source item URL -> what is known about it
+    items: HashMap<Url, MirroredItem>,
+}
+
+impl MirrorState {
+    /// The target calendar each source calendar is mirrored to
+    pub fn calendars(&self) -> &HashMap<Url, Url> {
+        &self.calendars
+    }
+}
+
+/// Syncs calendars between two servers, using the same conflict resolution as a [`Provider`](crate::provider::Provider).
+///
+/// Every source calendar is mirrored to a target calendar, either set with [`Self::map_calendar`], or created under [`Self::set_target_calendar_home`].
+/// Changes are synced both ways, unless [`Self::set_one_way`] is used.
+/// In the [`SyncResult`], changes applied to the target are reported as remote changes, and changes applied to the source as local changes
+pub struct Mirror<A, U, B, V>
+where
+    A: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+    B: CalDavSource<V>,
+    V: DavCalendar + Sync + Send,
+{
+    source: A,
+    target: B,
+    state: MirrorState,
+    target_calendar_home: Option<Url>,
+    conflict_resolution: ConflictResolution,
+    one_way: bool,
+
+    phantom_u: PhantomData<U>,
+    phantom_v: PhantomData<V>,
+}
+
+impl<A, U, B, V> Mirror<A, U, B, V>
+where
+    A: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+    B: CalDavSource<V>,
+    V: DavCalendar + Sync + Send,
+{
+    /// Create a mirror that has never been synced
+    pub fn new(source: A, target: B) -> Self {
+        Self::with_state(source, target, MirrorState::default())
+    }
+
+    /// Create a mirror that resumes from the state of a previous one (see [`Self::state`])
+    pub fn with_state(source: A, target: B, state: MirrorState) -> Self {
+        Self { source, target, state,
+            target_calendar_home: None,
+            conflict_resolution: ConflictResolution::default(),
+            one_way: false,
+            phantom_u: PhantomData, phantom_v: PhantomData,
+        }
+    }
+
+    /// The state that must be kept until the next sync
+    pub fn state(&self) -> &MirrorState { &self.state }
+    pub fn source(&self) -> &A { &self.source }
+    pub fn target(&self) -> &B { &self.target }
+
+    /// Mirror a source calendar to a given target calendar (that is created if needed)
+    pub fn map_calendar(&mut self, source_calendar: Url, target_calendar: Url) {
+        self.state.calendars.insert(source_calendar, target_calendar);
+    }
+
+    /// Create the calendars that have not been mapped (see [`Self::map_calendar`]) in this collection of the target server.
+    /// Without it, unmapped calendars are not synced
+    pub fn set_target_calendar_home(&mut self, home: Url) {
+        self.target_calendar_home = Some(home);
+    }
+
+    /// Set how conflicts are resolved. The source plays the role of the server, e.g. [`ConflictResolution::ServerWins`] (the default) keeps the source version
+    pub fn set_conflict_resolution(&mut self, strategy: ConflictResolution) {
+        self.conflict_resolution = strategy;
+    }
+
+    /// Only sync from the source to the target (e.g. for a migration). Changes made on the target are overwritten, and its other items are left untouched
+    pub fn set_one_way(&mut self, one_way: bool) {
+        self.one_way = one_way;
+    }
+
+    /// Sync every source calendar with its target calendar
+    pub async fn sync(&mut self) -> SyncResult {
+        let mut progress = SyncProgress::new();
+        progress.info("Starting a mirror sync.");
+        if let Err(err) = self.run_sync(&mut progress).await {
+            progress.error(&format!("Sync terminated because of an error: {}", err));
+        }
+        progress.set_current_calendar(None);
+        let result = progress.result();
+        progress.info(&result.to_string());
+        result
+    }
+
+    async fn run_sync(&mut self, progress: &mut SyncProgress) -> Result<(), KFError> {
+        let source_cals = self.source.get_calendars().await?;
+        for (source_url, source_cal) in source_cals {
+            let target_cal = match self.get_or_create_target_calendar(&source_url, &source_cal).await {
+                Ok(Some(cal)) => cal,
+                Ok(None) => {
+                    progress.debug(&format!("Calendar {} is not mapped to any target calendar", source_url));
+                    continue;
+                },
+                Err(err) => {
+                    progress.warn(&format!("Unable to get or create the target calendar of {} ({}). Skipping this time", source_url, err));
+                    continue;
+                },
+            };
+
+            progress.set_current_calendar(Some(source_url.clone()));
+            if let Err(err) = self.sync_calendar_pair(&source_url, source_cal, target_cal, progress).await {
+                progress.warn(&format!("Unable to sync calendar {}: {}, skipping this time.", source_url, err));
+            }
+            progress.set_current_calendar(None);
+        }
+        Ok(())
+    }
+
+    async fn get_or_create_target_calendar(&mut self, source_url: &Url, source_cal: &Arc<Mutex<U>>) -> Result<Option<Arc<Mutex<V>>>, KFError> {
+        let target_url = match (self.state.calendars.get(source_url), &self.target_calendar_home) {
+            (Some(url), _) => url.clone(),
+            (None, Some(home)) => home.join(&format!("{}/", last_segment(source_url)))?,
+            (None, None) => return Ok(None),
+        };
+
+        if let Some(cal) = self.target.get_calendar(&target_url).await {
+            self.state.calendars.insert(source_url.clone(), target_url);
+            return Ok(Some(cal));
+        }
+        let (name, supported_components, color, timezone) = {
+            let source_cal = source_cal.lock().unwrap();
+            (source_cal.name().to_string(), source_cal.supported_components(), source_cal.color().cloned(), source_cal.timezone().cloned())
+        };
+        let cal = self.target.create_calendar_with_timezone(target_url.clone(), name, supported_components, color, timezone).await?;
+        self.state.calendars.insert(source_url.clone(), target_url);
+        Ok(Some(cal))
+    }
+
+    // Calendars are locked during the whole sync of a calendar
+    #[allow(clippy::await_holding_lock)]
+    async fn sync_calendar_pair(&mut self, source_url: &Url, source_cal: Arc<Mutex<U>>, target_cal: Arc<Mutex<V>>, progress: &mut SyncProgress) -> Result<(), KFError> {
+        let mut source_cal = source_cal.lock().unwrap();
+        let mut target_cal = target_cal.lock().unwrap();
+        let target_cal_url = target_cal.url().clone();
+        progress.info(&format!("Mirroring calendar {} to {}", source_cal.name(), target_cal_url));
+        progress.event(ProgressEvent::CalendarStarted{ calendar: source_url.clone(), name: source_cal.name().to_string(), items_to_sync: 0 });
+
+        let source_tags = source_cal.get_item_version_tags().await?;
+        let target_tags = target_cal.get_item_version_tags().await?;
+
+        let known: Vec<(Url, MirroredItem)> = self.state.items.iter()
+            .filter(|(_, mirrored)| &mirrored.source_calendar == source_url)
+            .map(|(url, mirrored)| (url.clone(), mirrored.clone()))
+            .collect();
+        // Target items that were known before this sync, even if they have just been deleted
+        let known_targets: HashSet<Url> = known.iter().map(|(_, mirrored)| mirrored.target_url.clone()).collect();
+
+        // Items that have already been mirrored
+        for (url, mirrored) in known {
+            let source_tag = source_tags.get(&url);
+            let target_tag = target_tags.get(&mirrored.target_url);
+            let source_changed = source_tag.is_some_and(|tag| tag != &mirrored.source_tag);
+            let target_changed = target_tag.is_some_and(|tag| tag != &mirrored.target_tag) && !self.one_way;
+
+            let outcome = match (source_tag, target_tag) {
+                (None, None) => Ok(None),
+                (Some(_), None) if source_changed || self.one_way => {
+                    // Modifications win over deletions
+                    self.copy_to_target(&url, &mut *source_cal, &mut *target_cal, &mirrored.target_url, None, progress).await.map(Some)
+                },
+                (Some(_), None) => {
+                    progress.debug(&format!("> {} has been deleted from the target, deleting it from the source", url));
+                    delete_item(&mut *source_cal, &url, progress, false).await.map(|_| None)
+                },
+                (None, Some(_)) if target_changed => {
+                    self.copy_to_source(&mirrored.target_url, &mut *target_cal, &mut *source_cal, &url, None, progress).await.map(Some)
+                },
+                (None, Some(_)) => {
+                    progress.debug(&format!("> {} has been deleted from the source, deleting it from the target", url));
+                    delete_item(&mut *target_cal, &mirrored.target_url, progress, true).await.map(|_| None)
+                },
+                (Some(source_tag), Some(target_tag)) => match (source_changed, target_changed) {
+                    (false, false) => Ok(Some(mirrored.clone())),
+                    (true, false) => self.copy_to_target(&url, &mut *source_cal, &mut *target_cal, &mirrored.target_url, Some(target_tag), progress).await.map(Some),
+                    (false, true) => self.copy_to_source(&mirrored.target_url, &mut *target_cal, &mut *source_cal, &url, Some(source_tag), progress).await.map(Some),
+                    (true, true) => self.resolve_conflict(&url, &mut *source_cal, &mut *target_cal, &mirrored, source_tag, target_tag, progress).await.map(Some),
+                },
+            };
+
+            match outcome {
+                Ok(Some(mirrored)) => { self.state.items.insert(url, mirrored); },
+                Ok(None) => { self.state.items.remove(&url); },
+                Err(err) => progress.item_error(&url, &format!("Unable to mirror item {}: {}", url, err)),
+            }
+        }
+
+        // New items
+        let new_in_source: Vec<Url> = source_tags.keys()
+            .filter(|url| !self.state.items.contains_key(*url))
+            .cloned()
+            .collect();
+        for url in new_in_source {
+            let target_url = target_cal_url.join(last_segment(&url))?;
+            match self.copy_to_target(&url, &mut *source_cal, &mut *target_cal, &target_url, None, progress).await {
+                Ok(mirrored) => { self.state.items.insert(url, mirrored); },
+                Err(err) => progress.item_error(&url, &format!("Unable to copy new item {} to the target: {}", url, err)),
+            }
+        }
+
+        if !self.one_way {
+            let new_in_target: Vec<Url> = target_tags.keys()
+                .filter(|url| !known_targets.contains(*url) && !self.state.items.values().any(|mirrored| &mirrored.target_url == *url))
+                .cloned()
+                .collect();
+            for target_url in new_in_target {
+                let url = source_url.join(last_segment(&target_url))?;
+                match self.copy_to_source(&target_url, &mut *target_cal, &mut *source_cal, &url, None, progress).await {
+                    Ok(mirrored) => { self.state.items.insert(url, mirrored); },
+                    Err(err) => progress.item_error(&target_url, &format!("Unable to copy new item {} to the source: {}", target_url, err)),
+                }
+            }
+        }
+
+        progress.event(ProgressEvent::CalendarFinished{ calendar: source_url.clone(), items_done: progress.counter() });
+        Ok(())
+    }
+
+    /// Copy a source item to the target (creating it if `target_tag` is `None`), and return its new state
+    async fn copy_to_target(&self, url: &Url, source_cal: &mut U, target_cal: &mut V, target_url: &Url, target_tag: Option<&VersionTag>, progress: &mut SyncProgress) -> Result<MirroredItem, KFError> {
+        progress.debug(&format!("> Copying {} to the target", url));
+        let item = source_cal.get_item_by_url(url).await?.ok_or("the item has vanished from the source")?;
+        let source_tag = synced_tag(item.sync_status())?;
+        let bytes = progress.item_size(&item);
+        let new_target_tag = write_item(&item, target_cal, target_url, target_tag).await?;
+        progress.event(ProgressEvent::ItemUploaded{ calendar: target_cal.url().clone(), item: target_url.clone(), new: target_tag.is_none(), bytes });
+        Ok(MirroredItem{ source_calendar: source_cal.url().clone(), source_tag, target_url: target_url.clone(), target_tag: new_target_tag })
+    }
+
+    /// Copy a target item to the source (creating it if `source_tag` is `None`), and return its new state
+    async fn copy_to_source(&self, target_url: &Url, target_cal: &mut V, source_cal: &mut U, url: &Url, source_tag: Option<&VersionTag>, progress: &mut SyncProgress) -> Result<MirroredItem, KFError> {
+        progress.debug(&format!("> Copying {} to the source", target_url));
+        let item = target_cal.get_item_by_url(target_url).await?.ok_or("the item has vanished from the target")?;
+        let target_tag = synced_tag(item.sync_status())?;
+        let bytes = progress.item_size(&item);
+        let new_source_tag = write_item(&item, source_cal, url, source_tag).await?;
+        progress.event(ProgressEvent::ItemDownloaded{ calendar: source_cal.url().clone(), item: url.clone(), new: source_tag.is_none(), bytes });
+        Ok(MirroredItem{ source_calendar: source_cal.url().clone(), source_tag: new_source_tag, target_url: target_url.clone(), target_tag })
+    }
+
+    /// Resolve an item that has been changed on both servers, and return its new state
+    #[allow(clippy::too_many_arguments)]
+    async fn resolve_conflict(&self, url: &Url, source_cal: &mut U, target_cal: &mut V, mirrored: &MirroredItem, source_tag: &VersionTag, target_tag: &VersionTag, progress: &mut SyncProgress) -> Result<MirroredItem, KFError> {
+        progress.event(ProgressEvent::Conflict{ calendar: source_cal.url().clone(), item: url.clone() });
+        let source_item = source_cal.get_item_by_url(url).await?;
+        let target_item = target_cal.get_item_by_url(&mirrored.target_url).await?;
+        let resolution = self.conflict_resolution.resolve(&Conflict{ url, local: target_item.as_ref(), remote: source_item.as_ref() });
+        progress.debug(&format!("*   Conflict on {} resolved as {:?}", url, resolution));
+
+        match resolution {
+            Resolution::KeepLocal => self.copy_to_source(&mirrored.target_url, target_cal, source_cal, url, Some(source_tag), progress).await,
+            Resolution::KeepRemote => self.copy_to_target(url, source_cal, target_cal, &mirrored.target_url, Some(target_tag), progress).await,
+            Resolution::KeepBoth => {
+                // The target version is kept as a new item in the source, that will be mirrored at the next sync
+                if let Some(copy) = target_item.as_ref().and_then(|item| conflict::copy_as_new_item(item, source_cal.url())) {
+                    source_cal.add_item(copy).await?;
+                }
+                self.copy_to_target(url, source_cal, target_cal, &mirrored.target_url, Some(target_tag), progress).await
+            },
+        }
+    }
+}
+
+/// Create (if `dest_tag` is `None`) or overwrite an item at `dest_url`, and return its new version tag
+async fn write_item<C: DavCalendar>(item: &Item, dest_cal: &mut C, dest_url: &Url, dest_tag: Option<&VersionTag>) -> Result<VersionTag, KFError> {
+    let mut copy = moved_item(item, dest_url).ok_or("this kind of item is not supported yet")?;
+    let new_status = match dest_tag {
+        None => {
+            copy.set_sync_status(SyncStatus::NotSynced);
+            dest_cal.add_item(copy).await?
+        },
+        Some(tag) => {
+            copy.set_sync_status(SyncStatus::LocallyModified(tag.clone()));
+            dest_cal.update_item(copy).await?
+        },
+    };
+    synced_tag(&new_status)
+}
+
+async fn delete_item<C: DavCalendar>(cal: &mut C, url: &Url, progress: &mut SyncProgress, from_target: bool) -> Result<(), KFError> {
+    cal.delete_item(url).await?;
+    progress.event(ProgressEvent::ItemDeleted{ calendar: cal.url().clone(), item: url.clone(), remote: from_target });
+    Ok(())
+}
+
+fn synced_tag(sync_status: &SyncStatus) -> Result<VersionTag, KFError> {
+    match sync_status {
+        SyncStatus::Synced(tag) => Ok(tag.clone()),
+        _ => Err("the server has not returned a version tag".into()),
+    }
+}
+
+/// The last non-empty segment of the path of a URL, e.g. `item.ics` or `calendar`
+fn last_segment(url: &Url) -> &str {
+    url.path_segments()
+        .and_then(|segments| segments.rev().find(|s| !s.is_empty()))
+        .unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_segment() {
+        assert_eq!(last_segment(&"https://some.server/cal/item.ics".parse().unwrap()), "item.ics");
+        assert_eq!(last_segment(&"https://some.server/calendars/work/".parse().unwrap()), "work");
+        assert_eq!(last_segment(&"https://some.server/".parse().unwrap()), "");
+    }
+}
@@ -2,22 +2,56 @@
 //!
 //! It is also responsible for syncing them together
 
-use std::error::Error;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
 use std::sync::{Arc, Mutex};
 use std::fmt::{Display, Formatter};
+use std::time::Duration;
 
 use url::Url;
+use chrono::Utc;
 use itertools::Itertools;
+use futures_util::stream::{self, StreamExt};
 
 use crate::traits::{BaseCalendar, CalDavSource, DavCalendar};
 use crate::traits::CompleteCalendar;
-use crate::item::SyncStatus;
+use crate::item::{FieldChange, SyncStatus, VersionTag};
+use crate::error::KFError;
+use crate::client::ServerCapabilities;
+use crate::calendar::Privileges;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarProperties;
+use crate::Item;
+use crate::spans::{self, Instrument};
+use crate::metrics::{Metrics, MetricsSink};
 
+pub mod conflict;
+mod merge;
+pub mod plan;
+pub mod filter;
+pub mod duplicates;
+pub mod search;
+pub mod scheduler;
+pub mod journal;
+pub mod audit;
+pub mod pending;
+pub mod hooks;
+pub mod mirror;
+pub mod multi;
+use filter::SyncFilter;
+use duplicates::{DuplicateCriteria, DuplicateGroup};
+use search::{SearchMatch, SearchOptions};
+use journal::SyncJournal;
+use audit::{AuditDirection, AuditEntry, AuditOperation, AuditOutcome};
+use pending::{PendingChange, PendingChangeKind};
+use hooks::{ItemHook, SyncHooks};
+use plan::{CalendarPlan, Differences, SyncAction, SyncPlan};
+use conflict::{Conflict, ConflictKind, ConflictResolution, Resolution};
 pub mod sync_progress;
+pub mod sync_result;
+use sync_result::{ChangeStatus, SyncResult};
 use sync_progress::SyncProgress;
-use sync_progress::{FeedbackSender, SyncEvent};
+use sync_progress::{FeedbackSender, ProgressEvent, ProgressSender, SyncEvent};
 
 /// How many items will be batched in a single HTTP request when downloading from the server
 #[cfg(not(test))]
@@ -26,8 +60,12 @@ const DOWNLOAD_BATCH_SIZE: usize = 30;
 #[cfg(test)]
 const DOWNLOAD_BATCH_SIZE: usize = 3;
 
-// I am too lazy to actually make `fetch_and_apply` generic over an async closure.
-// Let's work around by passing an enum, so that `fetch_and_apply` will know what to do
+/// See [`Provider::set_max_concurrent_transfers`]
+const DEFAULT_MAX_CONCURRENT_TRANSFERS: usize = 4;
+
+// I am too lazy to actually make `apply_batch` generic over an async closure.
+// Let's work around by passing an enum, so that `apply_batch` will know what to do
+#[derive(Clone, Copy)]
 enum BatchDownloadType {
     RemoteAdditions,
     RemoteChanges,
@@ -60,6 +98,23 @@ where
     /// The local cache
     local: L,
 
+    /// The maximum duration of a whole sync
+    sync_deadline: Option<Duration>,
+    /// How conflicts are resolved
+    conflict_resolution: ConflictResolution,
+    /// Whether conflicting items are merged property by property before using `conflict_resolution`
+    three_way_merge: bool,
+    /// Which items are synced
+    sync_filter: SyncFilter,
+    /// How many items (or batches of items) are transferred at the same time
+    max_concurrent_transfers: usize,
+    /// Callbacks that transform items before they are uploaded, and after they are downloaded
+    hooks: SyncHooks,
+    /// Privileges that are not used, even if the server grants them: either because the calendar has been configured as read-only, or because the server refused them
+    revoked_privileges: HashMap<Url, Privileges>,
+    /// Where figures about syncs are reported
+    metrics: Metrics,
+
     phantom_t: PhantomData<T>,
     phantom_u: PhantomData<U>,
 }
@@ -74,13 +129,91 @@ where
     /// Create a provider.
     ///
     /// `remote` is usually a [`Client`](crate::client::Client), `local` is usually a [`Cache`](crate::cache::Cache).
-    /// However, both can be interchangeable. The only difference is that `remote` always wins in case of a sync conflict
+    /// However, both can be interchangeable. The only difference is that `remote` wins in case of a sync conflict (unless another [`ConflictResolution`] is set)
     pub fn new(remote: R, local: L) -> Self {
         Self { remote, local,
+            sync_deadline: None,
+            conflict_resolution: ConflictResolution::default(),
+            three_way_merge: true,
+            sync_filter: SyncFilter::default(),
+            max_concurrent_transfers: DEFAULT_MAX_CONCURRENT_TRANSFERS,
+            hooks: SyncHooks::default(),
+            revoked_privileges: HashMap::new(),
+            metrics: Metrics::default(),
             phantom_t: PhantomData, phantom_u: PhantomData,
         }
     }
 
+    /// Set the maximum duration of a whole sync (or `None`, the default, for no limit).
+    ///
+    /// A sync that exceeds it is aborted and reported as failed. As for any other failed sync, no data is corrupted, and the next sync will pick up where this one stopped. \
+    /// This is a safety net against stalled servers, see also [`ClientBuilder::request_timeout`](crate::client::ClientBuilder::request_timeout) to bound every single request.
+    pub fn set_sync_deadline(&mut self, deadline: Option<Duration>) {
+        self.sync_deadline = deadline;
+    }
+
+    /// Set how items that have been changed both locally and on the server are handled. Defaults to [`ConflictResolution::ServerWins`]
+    pub fn set_conflict_resolution(&mut self, strategy: ConflictResolution) {
+        self.conflict_resolution = strategy;
+    }
+
+    /// Set whether items that have been changed both locally and on the server are merged property by property. Defaults to `true`.
+    ///
+    /// Changes to different properties (e.g. the server changed the location, and the local item has been marked as completed) are all kept.
+    /// The [`ConflictResolution`] is only used when the same property has been changed on both sides, or when the version of the item at the last sync is unknown
+    pub fn set_three_way_merge(&mut self, enabled: bool) {
+        self.three_way_merge = enabled;
+    }
+
+    /// Restrict the sync to some kinds of items, or to a time window (see [`SyncFilter`]). By default, everything is synced.
+    ///
+    /// Calendars that cannot contain any item that matches the filter are not synced at all
+    pub fn set_sync_filter(&mut self, filter: SyncFilter) {
+        self.sync_filter = filter;
+    }
+
+    /// Set how many requests that download, upload or delete items may be in flight at the same time during a sync. Defaults to 4.
+    ///
+    /// Higher values make the first sync of large calendars much faster, at the cost of a higher load on the server. `1` transfers items one at a time
+    pub fn set_max_concurrent_transfers(&mut self, max_concurrent_transfers: usize) {
+        self.max_concurrent_transfers = max_concurrent_transfers.max(1);
+    }
+
+    /// Register a hook that is called before an item is uploaded to the server (hooks are called in the order they have been added).
+    ///
+    /// The hook returns the version of the item to upload (the local item itself is not modified), or `None` to keep the item from being uploaded.
+    /// Vetoed items are reported as skipped, and will be proposed to the hook again at the next sync
+    pub fn add_before_upload_hook(&mut self, hook: ItemHook) {
+        self.hooks.before_upload.push(hook);
+    }
+
+    /// Register a hook that is called after an item has been downloaded from the server, before it is stored locally (hooks are called in the order they have been added).
+    ///
+    /// The hook returns the version of the item to store, or `None` not to store it at all
+    pub fn add_after_download_hook(&mut self, hook: ItemHook) {
+        self.hooks.after_download.push(hook);
+    }
+
+    /// Treat a calendar as read-only, even if the server allows to modify it (or stop doing so, with `false`).
+    ///
+    /// Local changes to a read-only calendar are not pushed to the server: they are kept locally, and are pushed once the calendar is writable again.
+    /// Calendars are also considered read-only when the server refuses changes (`403 Forbidden`), until this is called with `false`.
+    /// The privileges of the local calendars reflect this after the next sync, so that they refuse local changes (see [`BaseCalendar::is_read_only`]).
+    /// Use [`CompleteCalendar::set_read_only`] to mark a local calendar as read-only right away
+    pub fn set_calendar_read_only(&mut self, calendar: Url, read_only: bool) {
+        match read_only {
+            true => { self.revoked_privileges.insert(calendar, Privileges::WRITE_CONTENT | Privileges::BIND | Privileges::UNBIND); },
+            false => { self.revoked_privileges.remove(&calendar); },
+        }
+    }
+
+    /// Report what every sync does (transferred items, conflicts, errors, durations) to a [`MetricsSink`], or stop doing so with `None`.
+    ///
+    /// The HTTP requests of a sync are reported by the client, see [`ClientBuilder::metrics_sink`](crate::client::ClientBuilder::metrics_sink)
+    pub fn set_metrics_sink(&mut self, sink: Option<Arc<dyn MetricsSink>>) {
+        self.metrics = Metrics::new(sink);
+    }
+
     /// Returns the data source described as `local`
     pub fn local(&self)  -> &L { &self.local }
     /// Returns the data source described as `local`
@@ -95,41 +228,83 @@ where
     /// Performs a synchronisation between `local` and `remote`, and provide feeedback to the user about the progress.
     ///
     /// This bidirectional sync applies additions/deletions made on a source to the other source.
-    /// In case of conflicts (the same item has been modified on both ends since the last sync, `remote` always wins).
+    /// In case of conflicts (the same item has been modified on both ends since the last sync), changes to distinct properties are merged,
+    /// otherwise `remote` wins (see [`Provider::set_three_way_merge`] and [`Provider::set_conflict_resolution`]).
     ///
-    /// It returns whether the sync was totally successful (details about errors are logged using the `log::*` macros).
+    /// It returns a summary of what has been done, that tells whether the sync was totally successful (details about errors are also logged using the `log::*` macros).
     /// In case errors happened, the sync might have been partially executed but your data will never be correupted (either locally nor in the server).
     /// Simply run this function again, it will re-start a sync, picking up where it failed.
-    pub async fn sync_with_feedback(&mut self, feedback_sender: FeedbackSender) -> bool {
+    pub async fn sync_with_feedback(&mut self, feedback_sender: FeedbackSender) -> SyncResult {
         let mut progress = SyncProgress::new_with_feedback_channel(feedback_sender);
         self.run_sync(&mut progress).await
     }
 
+    /// Performs a synchronisation between `local` and `remote`, and reports every step of it (see [`ProgressEvent`]).
+    ///
+    /// See [`Self::sync_with_feedback`]
+    pub async fn sync_with_progress(&mut self, progress_sender: ProgressSender) -> SyncResult {
+        let mut progress = SyncProgress::new_with_progress_channel(progress_sender);
+        self.run_sync(&mut progress).await
+    }
+
     /// Performs a synchronisation between `local` and `remote`, without giving any feedback.
     ///
     /// See [`Self::sync_with_feedback`]
-    pub async fn sync(&mut self) -> bool {
+    pub async fn sync(&mut self) -> SyncResult {
         let mut progress = SyncProgress::new();
         self.run_sync(&mut progress).await
     }
 
-    async fn run_sync(&mut self, progress: &mut SyncProgress) -> bool {
-        if let Err(err) = self.run_sync_inner(progress).await {
+    async fn run_sync(&mut self, progress: &mut SyncProgress) -> SyncResult {
+        progress.set_metrics(self.metrics.clone());
+        let span = spans::sync();
+        let result = match self.sync_deadline {
+            None => self.run_sync_inner(progress).instrument(span).await,
+            Some(deadline) => {
+                match crate::time::timeout(deadline, self.run_sync_inner(progress).instrument(span)).await {
+                    Some(result) => result,
+                    None => Err(format!("the sync deadline ({:?}) has been exceeded", deadline).into()),
+                }
+            },
+        };
+        if let Err(err) = result {
             progress.error(&format!("Sync terminated because of an error: {}", err));
         }
+        progress.set_current_calendar(None);
         progress.feedback(SyncEvent::Finished{ success: progress.is_success() });
-        progress.is_success()
+        let result = progress.result();
+        progress.info(&result.to_string());
+        if let Some(sink) = self.metrics.sink() {
+            sink.sync_finished(result.duration, result.is_success());
+        }
+        result
     }
 
-    async fn run_sync_inner(&mut self, progress: &mut SyncProgress) -> Result<(), Box<dyn Error>> {
+    async fn run_sync_inner(&mut self, progress: &mut SyncProgress) -> Result<(), KFError> {
         progress.info("Starting a sync.");
         progress.feedback(SyncEvent::Started);
 
         let mut handled_calendars = HashSet::new();
 
+        let capabilities = match self.remote.server_capabilities().await {
+            Ok(caps) => caps,
+            Err(err) => {
+                progress.warn(&format!("Unable to detect the server capabilities ({}). Assuming a baseline CalDAV server", err));
+                ServerCapabilities::default()
+            },
+        };
+        if !capabilities.supports_multiget() {
+            progress.info("The server does not support multiget REPORTs, items will be downloaded one by one");
+        }
+
         // Sync every remote calendar
         let cals_remote = self.remote.get_calendars().await?;
         for (cal_url, cal_remote) in cals_remote {
+            if !self.sync_filter.matches_calendar(cal_remote.lock().unwrap().supported_components()) {
+                progress.debug(&format!("Calendar {} is filtered out", cal_url));
+                handled_calendars.insert(cal_url);
+                continue;
+            }
             let counterpart = match self.get_or_insert_local_counterpart_calendar(&cal_url, cal_remote.clone()).await {
                 Err(err) => {
                     progress.warn(&format!("Unable to get or insert local counterpart calendar for {} ({}). Skipping this time", cal_url, err));
@@ -138,7 +313,7 @@ where
                 Ok(arc) => arc,
             };
 
-            if let Err(err) = Self::sync_calendar_pair(counterpart, cal_remote, progress).await {
+            if let Err(err) = self.sync_calendar_pair(counterpart, cal_remote, &capabilities, progress).await {
                 progress.warn(&format!("Unable to sync calendar {}: {}, skipping this time.", cal_url, err));
                 continue;
             }
@@ -151,6 +326,10 @@ where
             if handled_calendars.contains(&cal_url) {
                 continue;
             }
+            if !self.sync_filter.matches_calendar(cal_local.lock().unwrap().supported_components()) {
+                progress.debug(&format!("Calendar {} is filtered out", cal_url));
+                continue;
+            }
 
             let counterpart = match self.get_or_insert_remote_counterpart_calendar(&cal_url, cal_local.clone()).await {
                 Err(err) => {
@@ -160,7 +339,7 @@ where
                 Ok(arc) => arc,
             };
 
-            if let Err(err) = Self::sync_calendar_pair(cal_local, counterpart, progress).await {
+            if let Err(err) = self.sync_calendar_pair(cal_local, counterpart, &capabilities, progress).await {
                 progress.warn(&format!("Unable to sync calendar {}: {}, skipping this time.", cal_url, err));
                 continue;
             }
@@ -172,18 +351,385 @@ where
     }
 
 
-    async fn get_or_insert_local_counterpart_calendar(&mut self, cal_url: &Url, needle: Arc<Mutex<U>>) -> Result<Arc<Mutex<T>>, Box<dyn Error>> {
+    /// The local changes that the next sync will push to the server, in the order they will be replayed.
+    ///
+    /// This only reads the local source, so this can be used while offline, e.g. to show the user what has not been synced yet.
+    /// Outcomes of the changes are then reported in [`SyncResult::change_outcomes`]
+    pub async fn pending_changes(&self) -> Result<Vec<PendingChange>, KFError> {
+        let mut changes = Vec::new();
+        for cal in self.local.get_calendars().await?.values() {
+            changes.extend(cal.lock().unwrap().pending_changes());
+        }
+        changes.sort_by_key(|change| change.queued_at);
+        Ok(changes)
+    }
+
+    /// Compare `local` and `remote` as [`Self::sync`] would, but without changing anything.
+    ///
+    /// This returns what a sync would do, which is useful to show a preview to the user, or to safely test a server
+    // Calendars are locked while being compared, just like during a sync
+    #[allow(clippy::await_holding_lock)]
+    pub async fn plan_sync(&self) -> Result<SyncPlan, KFError> {
+        let mut progress = SyncProgress::new();
+        let mut plan = SyncPlan::default();
+
+        let cals_remote = self.remote.get_calendars().await?;
+        let cals_local = self.local.get_calendars().await?;
+
+        let filter = &self.sync_filter;
+        for (cal_url, cal_remote) in &cals_remote {
+            let cal_remote = cal_remote.lock().unwrap();
+            if !filter.matches_calendar(cal_remote.supported_components()) {
+                continue;
+            }
+            let calendar_plan = match cals_local.get(cal_url) {
+                None => {
+                    let remote_items = match filter.is_unrestricted() {
+                        true => cal_remote.get_item_version_tags().await?,
+                        false => cal_remote.get_filtered_item_version_tags(filter).await?,
+                    };
+                    CalendarPlan {
+                        url: cal_url.clone(),
+                        name: cal_remote.name().to_string(),
+                        create_locally: true,
+                        create_remotely: false,
+                        actions: remote_items.into_keys().map(SyncAction::Download).collect(),
+                    }
+                },
+                Some(cal_local) => {
+                    let cal_local = cal_local.lock().unwrap();
+                    let remote_items = Self::remote_items_in_scope(&*cal_local, &*cal_remote, filter).await?;
+                    let differences = Self::find_differences(&*cal_local, remote_items, filter, &mut progress).await?;
+                    CalendarPlan {
+                        url: cal_url.clone(),
+                        name: cal_local.name().to_string(),
+                        create_locally: false,
+                        create_remotely: false,
+                        actions: differences.into_actions(),
+                    }
+                },
+            };
+            plan.calendars.push(calendar_plan);
+        }
+
+        for (cal_url, cal_local) in &cals_local {
+            if cals_remote.contains_key(cal_url) {
+                continue;
+            }
+            let cal_local = cal_local.lock().unwrap();
+            if !filter.matches_calendar(cal_local.supported_components()) {
+                continue;
+            }
+            let differences = Self::find_differences(&*cal_local, HashMap::new(), filter, &mut progress).await?;
+            plan.calendars.push(CalendarPlan {
+                url: cal_url.clone(),
+                name: cal_local.name().to_string(),
+                create_locally: false,
+                create_remotely: true,
+                actions: differences.into_actions(),
+            });
+        }
+
+        Ok(plan)
+    }
+
+    /// Rename a calendar, both locally and on the server.
+    ///
+    /// Calendars are identified by their URLs, that do not change, so that nothing has to be migrated. \
+    /// If the server cannot be reached, the new name is kept locally, and pushed at the next sync.
+    /// If the server does not allow renaming this calendar, the local calendar keeps the name of the server
+    #[allow(clippy::await_holding_lock)]
+    pub async fn rename_calendar(&mut self, calendar: &Url, new_name: String) -> Result<(), KFError> {
+        let cal_local = self.local.get_calendar(calendar).await
+            .ok_or_else(|| format!("There is no local calendar {}", calendar))?;
+        cal_local.lock().unwrap().set_name(new_name.clone());
+
+        let result = match self.remote.get_calendar(calendar).await {
+            // It will be created with this name at the next sync
+            None => Ok(()),
+            Some(cal_remote) => {
+                let result = cal_remote.lock().unwrap().update_name(new_name).await;
+                let mut cal_local = cal_local.lock().unwrap();
+                match result {
+                    Ok(()) => cal_local.clear_modified_properties(CalendarProperties::NAME),
+                    Err(KFError::Forbidden{ .. }) => {
+                        Self::mirror_calendar_property(&mut cal_local, &cal_remote.lock().unwrap(), CalendarProperties::NAME);
+                    },
+                    Err(_) => (),
+                }
+                result
+            },
+        };
+
+        self.local.save().await?;
+        result
+    }
+
+    /// Move an item to another calendar, both locally and on the server. The item keeps its UID, and its new URL is returned.
+    ///
+    /// The server is asked to move the item. If it cannot, the item is created in the target calendar, then deleted from its former calendar. \
+    /// Items that have not been synced yet are only moved locally, and will be uploaded to their new calendar at the next sync
+    #[allow(clippy::await_holding_lock)]
+    pub async fn move_item(&mut self, item_url: &Url, target_calendar: &Url) -> Result<Url, KFError> {
+        let mut source = None;
+        for (cal_url, cal) in self.local.get_calendars().await? {
+            if cal.lock().unwrap().get_item_by_url(item_url).await.is_some() {
+                source = Some((cal_url, cal));
+                break;
+            }
+        }
+        let (source_calendar, source_local) = source.ok_or_else(|| format!("Item {} is not in any local calendar", item_url))?;
+        if &source_calendar == target_calendar {
+            return Ok(item_url.clone());
+        }
+        let target_local = self.local.get_calendar(target_calendar).await
+            .ok_or_else(|| format!("There is no local calendar {}", target_calendar))?;
+        for (cal_url, cal) in [(&source_calendar, &source_local), (target_calendar, &target_local)] {
+            if cal.lock().unwrap().is_read_only() {
+                return Err(KFError::ReadOnlyCalendar{ calendar: cal_url.clone() });
+            }
+        }
+
+        let file_name = item_url.path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| format!("Unable to get the file name of item {}", item_url))?;
+        let new_url = target_calendar.join(file_name)?;
+        if target_local.lock().unwrap().get_item_by_url(&new_url).await.is_some() {
+            return Err(format!("Calendar {} already contains an item at {}", target_calendar, new_url).into());
+        }
+
+        let item = source_local.lock().unwrap().get_item_by_url(item_url).await.cloned()
+            .ok_or_else(|| format!("Item {} has vanished", item_url))?;
+        if !target_local.lock().unwrap().supports_item(&item) {
+            return Err(KFError::UnsupportedComponent{ calendar: target_calendar.clone(), item: item_url.clone() });
+        }
+        let mut moved = moved_item(&item, &new_url)
+            .ok_or_else(|| format!("Unable to move {}: moving events is not supported yet", item_url))?;
+
+        let new_sync_status = match item.sync_status() {
+            SyncStatus::NotSynced => SyncStatus::NotSynced,
+            SyncStatus::LocallyDeleted(_) => return Err(format!("Item {} has been deleted", item_url).into()),
+            SyncStatus::Synced(_) | SyncStatus::LocallyModified(_) => self.move_remote_item(&item, &moved, &source_calendar, target_calendar).await?,
+        };
+        moved.set_sync_status(new_sync_status);
+
+        target_local.lock().unwrap().add_item(moved).await?;
+        let deletion = source_local.lock().unwrap().immediately_delete_item(item_url).await;
+        if let Err(err) = deletion {
+            // Do not leave the item in both calendars, the next sync would upload it twice
+            if let Err(rollback_err) = target_local.lock().unwrap().immediately_delete_item(&new_url).await {
+                log::error!("Unable to remove the copy {} of item {}: {}", new_url, item_url, rollback_err);
+            }
+            return Err(err);
+        }
+
+        self.local.save().await?;
+        Ok(new_url)
+    }
+
+    /// Move a local item to another URL of the same calendar, e.g. the one the server has chosen for it
+    async fn move_local_item(cal_local: &mut T, url: &Url, new_url: &Url) -> Result<(), KFError> {
+        let item = cal_local.get_item_by_url(url).await.cloned()
+            .ok_or_else(|| format!("Item {} has vanished", url))?;
+        let moved = moved_item(&item, new_url)
+            .ok_or_else(|| format!("Unable to move {}: moving events is not supported yet", url))?;
+        cal_local.immediately_delete_item(url).await?;
+        cal_local.add_item(moved).await?;
+        Ok(())
+    }
+
+    /// Move an item on the server, and return the new sync status of the moved local item
+    #[allow(clippy::await_holding_lock)]
+    async fn move_remote_item(&self, item: &Item, moved: &Item, source_calendar: &Url, target_calendar: &Url) -> Result<SyncStatus, KFError> {
+        let source_remote = self.remote.get_calendar(source_calendar).await
+            .ok_or_else(|| format!("There is no remote calendar {}", source_calendar))?;
+        let target_remote = self.remote.get_calendar(target_calendar).await
+            .ok_or_else(|| format!("There is no remote calendar {} (it will be created at the next sync)", target_calendar))?;
+
+        let result = source_remote.lock().unwrap().move_item(item.url(), moved.url()).await;
+        match result {
+            Ok(SyncStatus::Synced(new_tag)) => Ok(match item.sync_status() {
+                // The local changes still have to be pushed
+                SyncStatus::LocallyModified(_) => SyncStatus::LocallyModified(new_tag),
+                _ => SyncStatus::Synced(new_tag),
+            }),
+            Ok(other) => Err(format!("Unexpected sync status {:?} after moving {}", other, item.url()).into()),
+            Err(KFError::MoveNotSupported{ .. }) => {
+                log::debug!("The server cannot move {}, creating a copy and deleting the original instead", item.url());
+                let new_sync_status = target_remote.lock().unwrap().add_item(moved.clone()).await?;
+                let deletion = source_remote.lock().unwrap().delete_item(item.url()).await;
+                if let Err(err) = deletion {
+                    // Do not leave two copies of the item on the server
+                    if let Err(cleanup_err) = target_remote.lock().unwrap().delete_item(moved.url()).await {
+                        log::error!("Unable to delete the copy {} of item {}: {}", moved.url(), item.url(), cleanup_err);
+                    }
+                    return Err(format!("Unable to delete {} from its former calendar: {}", item.url(), err).into());
+                }
+                Ok(new_sync_status)
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Download again an item whose content has been evicted from the local cache (see [`EvictionPolicy`](crate::cache::eviction::EvictionPolicy)), so that it can be read or modified
+    #[allow(clippy::await_holding_lock)]
+    pub async fn fetch_evicted_item(&mut self, item_url: &Url) -> Result<(), KFError> {
+        let mut source = None;
+        for (cal_url, cal) in self.local.get_calendars().await? {
+            if cal.lock().unwrap().evicted_version_tags().contains_key(item_url) {
+                source = Some((cal_url, cal));
+                break;
+            }
+        }
+        let (cal_url, cal_local) = source.ok_or_else(|| format!("Item {} has not been evicted from any local calendar", item_url))?;
+        let cal_remote = self.remote.get_calendar(&cal_url).await
+            .ok_or_else(|| format!("There is no remote calendar {}", cal_url))?;
+
+        let item = cal_remote.lock().unwrap().get_item_by_url(item_url).await?
+            .ok_or_else(|| format!("Item {} does not exist on the server anymore", item_url))?;
+        let item = SyncHooks::run(&self.hooks.after_download, item).await
+            .ok_or_else(|| format!("Item {} has been vetoed by a hook", item_url))?;
+        cal_local.lock().unwrap().update_item(item).await?;
+        Ok(())
+    }
+
+    /// Find the local items that are duplicates of each other (e.g. after an import, or after a server has misbehaved), without changing anything.
+    ///
+    /// Only items of the same calendar are compared. See also [`Self::merge_duplicates`]
+    pub async fn find_duplicates(&self, criteria: &DuplicateCriteria) -> Result<Vec<DuplicateGroup>, KFError> {
+        let mut groups = Vec::new();
+        for (cal_url, cal) in self.local.get_calendars().await? {
+            let cal = cal.lock().unwrap();
+            groups.extend(criteria.find_duplicates(&cal_url, cal.iter_items().map(|(_, item)| item)));
+        }
+        Ok(groups)
+    }
+
+    /// Keep a single item of every group of duplicates, and delete the other ones.
+    ///
+    /// Duplicates are marked for deletion, and will be deleted from the server at the next sync. Returns the number of deleted items
+    #[allow(clippy::await_holding_lock)]
+    pub async fn merge_duplicates(&mut self, groups: &[DuplicateGroup]) -> Result<usize, KFError> {
+        let mut deleted = 0;
+        for group in groups {
+            let cal = self.local.get_calendar(&group.calendar).await
+                .ok_or_else(|| format!("There is no local calendar {}", group.calendar))?;
+            let mut cal = cal.lock().unwrap();
+            if cal.get_item_by_url(&group.keep).await.is_none() {
+                return Err(format!("Item {} has vanished, its duplicates are not deleted", group.keep).into());
+            }
+            for url in &group.duplicates {
+                cal.mark_for_deletion(url).await?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Find the local items that contain every word of a query in their summaries, descriptions, locations or categories.
+    ///
+    /// Case and diacritics are ignored (e.g. "creme" matches "Crème"). Matches are sorted by calendar, then by name
+    pub async fn search(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchMatch>, KFError> {
+        let mut matches = Vec::new();
+        for (cal_url, cal) in self.local.get_calendars().await? {
+            let cal = cal.lock().unwrap();
+            matches.extend(options.search(query, &cal_url, cal.iter_items().map(|(_, item)| item)));
+        }
+        matches.sort_by(|a, b| a.calendar.cmp(&b.calendar).then_with(|| a.name.cmp(&b.name)));
+        Ok(matches)
+    }
+
+    async fn get_or_insert_local_counterpart_calendar(&mut self, cal_url: &Url, needle: Arc<Mutex<U>>) -> Result<Arc<Mutex<T>>, KFError> {
         get_or_insert_counterpart_calendar("local", &mut self.local, cal_url, needle).await
     }
-    async fn get_or_insert_remote_counterpart_calendar(&mut self, cal_url: &Url, needle: Arc<Mutex<T>>) -> Result<Arc<Mutex<U>>, Box<dyn Error>> {
+    async fn get_or_insert_remote_counterpart_calendar(&mut self, cal_url: &Url, needle: Arc<Mutex<T>>) -> Result<Arc<Mutex<U>>, KFError> {
         get_or_insert_counterpart_calendar("remote", &mut self.remote, cal_url, needle).await
     }
 
 
-    async fn sync_calendar_pair(cal_local: Arc<Mutex<T>>, cal_remote: Arc<Mutex<U>>, progress: &mut SyncProgress) -> Result<(), Box<dyn Error>> {
+    /// Push the properties of a calendar (e.g. its color) that have been changed locally, and mirror the other ones from the server
+    #[allow(clippy::await_holding_lock)]
+    async fn sync_calendar_properties(cal_local: &Mutex<T>, cal_remote: &Mutex<U>, progress: &mut SyncProgress) {
         let mut cal_remote = cal_remote.lock().unwrap();
         let mut cal_local = cal_local.lock().unwrap();
-        let cal_name = cal_local.name().to_string();
+
+        for property in CalendarProperties::all().iter_flags() {
+            if !cal_local.modified_properties().contains(property) {
+                Self::mirror_calendar_property(&mut cal_local, &cal_remote, property);
+                continue;
+            }
+
+            progress.debug(&format!("> Pushing the {} of calendar {} to the server", property.label(), cal_local.name()));
+            let result = match property {
+                CalendarProperties::COLOR => cal_remote.update_color(cal_local.color().cloned()).await,
+                CalendarProperties::ORDER => cal_remote.update_order(cal_local.order()).await,
+                CalendarProperties::DESCRIPTION => cal_remote.update_description(cal_local.description().map(String::from)).await,
+                CalendarProperties::TIMEZONE => cal_remote.update_timezone(cal_local.timezone().cloned()).await,
+                CalendarProperties::NAME => cal_remote.update_name(cal_local.name().to_string()).await,
+                CalendarProperties::DEFAULT_ALARMS => cal_remote.update_default_alarms(cal_local.default_alarms().cloned()).await,
+                _ => Err(format!("Unknown calendar property {:?}", property).into()),
+            };
+            match result {
+                Ok(()) => cal_local.clear_modified_properties(property),
+                Err(KFError::Forbidden{ .. }) => {
+                    progress.warn(&format!("The server does not allow changing the {} of calendar {}. Using the one of the server", property.label(), cal_local.name()));
+                    Self::mirror_calendar_property(&mut cal_local, &cal_remote, property);
+                },
+                Err(err) => progress.warn(&format!("Unable to change the {} of calendar {}: {}. This will be retried at the next sync", property.label(), cal_local.name(), err)),
+            }
+        }
+    }
+
+    /// Replace a property of a local calendar by the one of its remote counterpart
+    fn mirror_calendar_property(cal_local: &mut T, cal_remote: &U, property: CalendarProperties) {
+        match property {
+            CalendarProperties::COLOR if cal_local.color() != cal_remote.color() => cal_local.set_color(cal_remote.color().cloned()),
+            CalendarProperties::ORDER if cal_local.order() != cal_remote.order() => cal_local.set_order(cal_remote.order()),
+            CalendarProperties::DESCRIPTION if cal_local.description() != cal_remote.description() => cal_local.set_description(cal_remote.description().map(String::from)),
+            CalendarProperties::TIMEZONE if cal_local.timezone() != cal_remote.timezone() => cal_local.set_timezone(cal_remote.timezone().cloned()),
+            CalendarProperties::NAME if cal_local.name() != cal_remote.name() => cal_local.set_name(cal_remote.name().to_string()),
+            CalendarProperties::DEFAULT_ALARMS if cal_local.default_alarms() != cal_remote.default_alarms() => cal_local.set_default_alarms(cal_remote.default_alarms().cloned()),
+            _ => (),
+        }
+        cal_local.clear_modified_properties(property);
+    }
+
+    /// Sync a calendar, and remember in the local calendar how it went
+    async fn sync_calendar_pair(&mut self, cal_local: Arc<Mutex<T>>, cal_remote: Arc<Mutex<U>>, capabilities: &ServerCapabilities, progress: &mut SyncProgress) -> Result<(), KFError> {
+        let errors_before = progress.error_count();
+        let span = {
+            let cal = cal_local.lock().unwrap();
+            spans::calendar(cal.url(), cal.name())
+        };
+        let result = self.sync_calendar_pair_inner(cal_local.clone(), cal_remote, capabilities, progress).instrument(span).await;
+        let errors = (progress.error_count() - errors_before) as usize + usize::from(result.is_err());
+        cal_local.lock().unwrap().record_sync(Utc::now(), errors);
+        result
+    }
+
+    async fn sync_calendar_pair_inner(&mut self, cal_local: Arc<Mutex<T>>, cal_remote: Arc<Mutex<U>>, capabilities: &ServerCapabilities, progress: &mut SyncProgress) -> Result<(), KFError> {
+        // Calendars are only locked during each step, so that the local source can be saved in-between
+        let (cal_name, cal_url) = {
+            let cal_remote = cal_remote.lock().unwrap();
+            let mut cal_local = cal_local.lock().unwrap();
+
+            // Keep the local calendar aware of what the user is allowed to do
+            let revoked = self.revoked_privileges.get(cal_local.url()).copied().unwrap_or_else(Privileges::empty);
+            let privileges = cal_remote.privileges() & !revoked;
+            if cal_local.privileges() != privileges {
+                cal_local.set_privileges(privileges);
+            }
+            if cal_local.owner() != cal_remote.owner() {
+                cal_local.set_owner(cal_remote.owner().cloned());
+            }
+            if cal_local.sharing() != cal_remote.sharing() {
+                cal_local.set_sharing(cal_remote.sharing().cloned());
+            }
+            (cal_local.name().to_string(), cal_local.url().clone())
+        };
+        progress.set_current_calendar(Some(cal_url.clone()));
+        Self::sync_calendar_properties(&cal_local, &cal_remote, progress).await;
 
         progress.info(&format!("Syncing calendar {}", cal_name));
         progress.reset_counter();
@@ -193,25 +739,680 @@ where
             details: "started".to_string()
         });
 
-        // Step 1 - find the differences
+        let errors_before = progress.error_count();
+        let resumable = cal_local.lock().unwrap().sync_journal().is_some_and(|journal| !journal.is_expired());
+        // Filtered syncs only know part of the calendar, they cannot tell whether it has changed since the last sync
+        let remote_version = match resumable || !self.sync_filter.is_unrestricted() {
+            true => None,
+            false => {
+                Self::resume_remote(&cal_local, &cal_remote).await;
+                Self::remote_version(&cal_remote, progress).await
+            },
+        };
+        let unchanged = remote_version.is_some() && {
+            let cal_local = cal_local.lock().unwrap();
+            cal_local.synced_remote_version().zip(remote_version.as_ref()).is_some_and(|(synced, remote)| remote.is_unchanged_since(synced))
+                && cal_local.pending_changes().is_empty()
+        };
+        if resumable {
+            progress.info(&format!("Resuming the interrupted sync of calendar {}", cal_name));
+        } else if unchanged {
+            progress.debug(&format!("Calendar {} has not changed since the last sync", cal_name));
+        } else {
+            self.prepare_sync_journal(&cal_local, &cal_remote, progress).await?;
+        }
+
+        let items_to_sync = cal_local.lock().unwrap().sync_journal().map(|journal| journal.len()).unwrap_or(0);
+        progress.event(ProgressEvent::CalendarStarted{
+            calendar: cal_url.clone(),
+            name: cal_name.clone(),
+            items_to_sync,
+        });
+
+        // Step 2 - commit changes
+        // The progress is saved after each step, so that an interrupted sync can be resumed
+        if items_to_sync > 0 {
+            self.checkpoint(&cal_url, progress).await;
+            progress.trace("Committing changes...");
+
+            let mut refused = self.push_local_deletions(&cal_local, &cal_remote, progress).await;
+            self.checkpoint(&cal_url, progress).await;
+
+            self.apply_remote_deletions(&cal_local, progress).await;
+            self.checkpoint(&cal_url, progress).await;
+
+            self.download_pending(BatchDownloadType::RemoteAdditions, |journal| &journal.remote_additions, &cal_local, &cal_remote, capabilities, progress).await;
+            self.checkpoint(&cal_url, progress).await;
+
+            self.download_pending(BatchDownloadType::RemoteChanges, |journal| &journal.remote_changes, &cal_local, &cal_remote, capabilities, progress).await;
+            self.checkpoint(&cal_url, progress).await;
+
+            refused |= self.push_local_changes(&cal_local, &cal_remote, progress).await;
+            if !refused.is_empty() {
+                *self.revoked_privileges.entry(cal_url.clone()).or_insert_with(Privileges::empty) |= refused;
+                let mut cal_local = cal_local.lock().unwrap();
+                let privileges = cal_local.privileges() & !refused;
+                cal_local.set_privileges(privileges);
+            }
+            self.checkpoint(&cal_url, progress).await;
+
+            self.download_pending(BatchDownloadType::RemoteChanges, |journal| &journal.late_conflicts, &cal_local, &cal_remote, capabilities, progress).await;
+        }
+
+        {
+            let mut cal_local = cal_local.lock().unwrap();
+            Self::record_base_versions(&mut *cal_local, progress).await;
+            *cal_local.sync_journal_mut() = None;
+            // The next sync can skip this calendar if it has not changed in the meantime, unless this one has not completely succeeded
+            let complete = progress.error_count() == errors_before;
+            cal_local.set_synced_remote_version(remote_version.filter(|_| complete));
+        }
+        if items_to_sync > 0 || resumable {
+            self.checkpoint(&cal_url, progress).await;
+        }
+
+        progress.event(ProgressEvent::CalendarFinished{ calendar: cal_url, items_done: progress.counter() });
+        progress.set_current_calendar(None);
+        Ok(())
+    }
+
+    /// Tell a remote calendar what it looked like at the end of the last complete sync, so that it only lists the changes made since (see [`DavCalendar::resume_from`])
+    // The local calendar is locked while its items are read, just like during a sync
+    #[allow(clippy::await_holding_lock)]
+    async fn resume_remote(cal_local: &Mutex<T>, cal_remote: &Mutex<U>) {
+        let (version, version_tags) = {
+            let cal_local = cal_local.lock().unwrap();
+            let version = match cal_local.synced_remote_version() {
+                None => return,
+                Some(version) => version.clone(),
+            };
+            let mut version_tags = cal_local.evicted_version_tags();
+            match cal_local.get_items().await {
+                Err(_) => return,
+                Ok(items) => version_tags.extend(items.into_iter()
+                    .filter_map(|(url, item)| item.sync_status().version_tag().map(|tag| (url, tag.clone())))),
+            }
+            (version, version_tags)
+        };
+        cal_remote.lock().unwrap().resume_from(&version, version_tags);
+    }
+
+    /// The current version of a remote calendar, or `None` if the server does not tell
+    // The remote calendar is locked while being queried, just like during a sync
+    #[allow(clippy::await_holding_lock)]
+    async fn remote_version(cal_remote: &Mutex<U>, progress: &mut SyncProgress) -> Option<CalendarVersion> {
+        let version = cal_remote.lock().unwrap().get_calendar_version().await;
+        match version {
+            Err(err) => {
+                progress.debug(&format!("Unable to get the version of the calendar: {}. Its items will be compared", err));
+                None
+            },
+            Ok(version) => Some(version).filter(CalendarVersion::is_known),
+        }
+    }
+
+    /// Save the calendar that is being synced (and its journal), so that the sync can be resumed from here in case it is interrupted
+    async fn checkpoint(&self, cal_url: &Url, progress: &mut SyncProgress) {
+        if let Err(err) = self.local.save_calendar(cal_url).await {
+            progress.warn(&format!("Unable to save the progress of the sync: {}. An interrupted sync would start over.", err));
+        }
+    }
+
+    /// Step 1 - find the differences, resolve the conflicts, and store the operations to perform into the journal of the local calendar
+    // Calendars are locked while being compared, just like during a sync
+    #[allow(clippy::await_holding_lock)]
+    async fn prepare_sync_journal(&self, cal_local: &Mutex<T>, cal_remote: &Mutex<U>, progress: &mut SyncProgress) -> Result<(), KFError> {
+        let conflict_resolution = &self.conflict_resolution;
+        let three_way_merge = self.three_way_merge;
+        let filter = &self.sync_filter;
+        let cal_remote = cal_remote.lock().unwrap();
+        let mut cal_local = cal_local.lock().unwrap();
+        let cal_name = cal_local.name().to_string();
+        let cal_url = cal_local.url().clone();
+        let mut privileges = cal_local.privileges();
+        if cal_local.is_read_only() {
+            // Changes made before the calendar has been marked as read-only are kept locally as well
+            privileges.remove(Privileges::WRITE_CONTENT | Privileges::BIND | Privileges::UNBIND);
+        }
+
         progress.debug("Finding the differences to sync...");
+        let Differences {
+            mut local_del, mut remote_del,
+            mut local_changes, mut remote_changes,
+            mut local_additions, remote_additions,
+            conflicts,
+        } = Self::find_differences(&*cal_local, Self::remote_items_in_scope(&*cal_local, &*cal_remote, filter).await?, filter, progress).await?;
+
+        // Resolve the conflicts
+        for (url, kind) in conflicts {
+            progress.event(ProgressEvent::Conflict{ calendar: cal_url.clone(), item: url.clone() });
+            let try_merge = three_way_merge
+                && matches!(kind, ConflictKind::BothModified(_))
+                && cal_local.base_version(&url).is_some();
+            let remote_item = match kind {
+                ConflictKind::RemotelyDeleted => None,
+                _ if !try_merge && !conflict_resolution.needs_remote_version() => None,
+                _ => match cal_remote.get_item_by_url(&url).await {
+                    Ok(None) => None,
+                    Ok(Some(item)) => SyncHooks::run(&self.hooks.after_download, item).await,
+                    Err(err) if try_merge => {
+                        progress.warn(&format!("Unable to download the remote version of {} ({}). This conflict will be resolved at the next sync.", url, err));
+                        continue;
+                    },
+                    Err(err) => {
+                        progress.warn(&format!("Unable to download the remote version of {} ({}). Using the remote version.", url, err));
+                        remote_changes.insert(url);
+                        continue;
+                    },
+                },
+            };
+
+            // Both sides may have made the same changes
+            if let (ConflictKind::BothModified(_), Some(remote)) = (&kind, &remote_item) {
+                if let Some(local) = cal_local.get_item_by_url_mut(&url).await {
+                    if local.content_eq(remote) {
+                        progress.debug(&format!("*   {} has been changed the same way in both sources", url));
+                        *local = remote.clone();
+                        continue;
+                    }
+                }
+            }
+
+            if let (true, Some(remote)) = (try_merge, &remote_item) {
+                let merged = match (cal_local.base_version(&url), cal_local.get_item_by_url(&url).await) {
+                    (Some(base), Some(local)) => merge::three_way_merge(base, local, remote),
+                    _ => None,
+                };
+                match merged {
+                    None => progress.debug(&format!("*   {} has the same properties changed in both sources, it cannot be merged", url)),
+                    Some(merged) => {
+                        progress.debug(&format!("*   Conflict on {} resolved by merging both versions", url));
+                        let must_upload = !merged.content_eq(remote);
+                        if let Some(item) = cal_local.get_item_by_url_mut(&url).await {
+                            if must_upload {
+                                *item = merge::as_local_change(merged);
+                                local_changes.insert(url);
+                            } else {
+                                *item = merged;
+                            }
+                        }
+                        continue;
+                    },
+                }
+            }
+
+            let resolution = {
+                let local_item = match kind {
+                    ConflictKind::LocallyDeleted(_) => None,
+                    _ => cal_local.get_item_by_url(&url).await,
+                };
+                conflict_resolution.resolve(&Conflict{ url: &url, local: local_item, remote: remote_item.as_ref() })
+            };
+            progress.debug(&format!("*   Conflict on {} resolved as {:?}", url, resolution));
+
+            match (resolution, kind) {
+                (Resolution::KeepRemote, ConflictKind::RemotelyDeleted) => {
+                    remote_del.insert(url);
+                },
+                (Resolution::KeepRemote, _) | (Resolution::KeepBoth, ConflictKind::LocallyDeleted(_)) => {
+                    remote_changes.insert(url);
+                },
+                (Resolution::KeepLocal, ConflictKind::BothModified(remote_tag)) => {
+                    // Overwrite the remote version
+                    if let Some(item) = cal_local.get_item_by_url_mut(&url).await {
+                        item.set_sync_status(SyncStatus::LocallyModified(remote_tag));
+                        local_changes.insert(url);
+                    }
+                },
+                (Resolution::KeepLocal, ConflictKind::LocallyDeleted(remote_tag)) => {
+                    if let Some(item) = cal_local.get_item_by_url_mut(&url).await {
+                        item.set_sync_status(SyncStatus::LocallyDeleted(remote_tag));
+                        local_del.insert(url);
+                    }
+                },
+                (_, ConflictKind::RemotelyDeleted) => {
+                    // Re-create the item on the server
+                    if let Some(item) = cal_local.get_item_by_url_mut(&url).await {
+                        item.set_sync_status(SyncStatus::NotSynced);
+                        local_additions.insert(url);
+                    }
+                },
+                (Resolution::KeepBoth, ConflictKind::BothModified(_)) => {
+                    let copy = cal_local.get_item_by_url(&url).await
+                        .and_then(|item| conflict::copy_as_new_item(item, cal_local.url()));
+                    match copy {
+                        None => progress.warn(&format!("Unable to make a copy of {}. Using the remote version.", url)),
+                        Some(copy) => {
+                            let copy_url = copy.url().clone();
+                            match cal_local.add_item(copy).await {
+                                Err(err) => progress.error(&format!("Unable to add a copy of {}: {}", url, err)),
+                                Ok(_) => {
+                                    progress.debug(&format!("*   The local version of {} is kept as {}", url, copy_url));
+                                    local_additions.insert(copy_url);
+                                },
+                            }
+                        },
+                    }
+                    remote_changes.insert(url);
+                },
+            }
+        }
+
+        // Local changes the user is not allowed to push are kept locally, in case the privileges change later
+        if !privileges.can_delete_items() && !local_del.is_empty() {
+            progress.info(&format!("Not allowed to delete items from calendar {}. Keeping {} local deletion(s) pending", cal_name, local_del.len()));
+            Self::skip_all(&mut cal_local, &mut local_del, PendingChangeKind::Deletion, progress).await;
+        }
+        if !privileges.can_create_items() && !local_additions.is_empty() {
+            progress.info(&format!("Not allowed to add items to calendar {}. Keeping {} local addition(s) pending", cal_name, local_additions.len()));
+            Self::skip_all(&mut cal_local, &mut local_additions, PendingChangeKind::Addition, progress).await;
+        }
+        if !privileges.can_write() && !local_changes.is_empty() {
+            progress.info(&format!("Not allowed to modify items of calendar {}. Keeping {} local change(s) pending", cal_name, local_changes.len()));
+            Self::skip_all(&mut cal_local, &mut local_changes, PendingChangeKind::Modification, progress).await;
+        }
+
+        // Servers reject items their calendar does not support (e.g. tasks in a calendar that only supports events), usually with confusing errors
+        let mut unsupported = HashSet::new();
+        for url in &local_additions {
+            if let Some(item) = cal_local.get_item_by_url(url).await {
+                if !cal_remote.supports_item(item) {
+                    unsupported.insert(url.clone());
+                }
+            }
+        }
+        if !unsupported.is_empty() {
+            progress.warn(&format!("Calendar {} only supports {} items. Keeping {} local addition(s) pending", cal_name, cal_remote.supported_components().ical_names(), unsupported.len()));
+            local_additions.retain(|url| !unsupported.contains(url));
+            Self::skip_all(&mut cal_local, &mut unsupported, PendingChangeKind::Addition, progress).await;
+        }
+
+
+        *cal_local.sync_journal_mut() = Some(SyncJournal {
+            local_del, remote_del,
+            remote_additions, remote_changes,
+            local_additions, local_changes,
+            ..SyncJournal::new()
+        });
+        Ok(())
+    }
+
+    /// Remove an operation from the journal of the local calendar, once it has been attempted
+    fn mark_done(cal_local: &mut T, url: &Url) {
+        if let Some(journal) = cal_local.sync_journal_mut() {
+            journal.mark_done(url);
+        }
+    }
+
+    /// The pending operations of a step of the sync.
+    /// Local changes are replayed in the order they have been made
+    fn pending(cal_local: &T, step: fn(&SyncJournal) -> &HashSet<Url>) -> Vec<Url> {
+        let urls = match cal_local.sync_journal() {
+            None => return Vec::new(),
+            Some(journal) => step(journal),
+        };
+        let mut ordered: Vec<Url> = cal_local.pending_changes().into_iter()
+            .map(|change| change.item)
+            .filter(|url| urls.contains(url))
+            .collect();
+        let known: HashSet<Url> = ordered.iter().cloned().collect();
+        ordered.extend(urls.iter().filter(|url| !known.contains(*url)).cloned());
+        ordered
+    }
+
+    /// Push local deletions, and return the privileges the server has refused
+    #[allow(clippy::await_holding_lock)]
+    async fn push_local_deletions(&self, cal_local: &Mutex<T>, cal_remote: &Mutex<U>, progress: &mut SyncProgress) -> Privileges {
+        let max_concurrency = self.max_concurrent_transfers;
+        let mut cal_remote = cal_remote.lock().unwrap();
+        let mut cal_local = cal_local.lock().unwrap();
+        let cal_name = cal_local.name().to_string();
+        let cal_url = cal_local.url().clone();
+
+        let mut refused = Privileges::empty();
+        let local_del = Self::pending(&cal_local, |journal| &journal.local_del);
+        for batch in local_del.chunks(max_concurrency) {
+            if !refused.is_empty() {
+                for url in batch {
+                    Self::skip(&mut cal_local, url, PendingChangeKind::Deletion, progress).await;
+                    Self::mark_done(&mut cal_local, url);
+                }
+                continue;
+            }
+            let mut old_etags = HashMap::new();
+            for url_del in batch {
+                progress.debug(&format!("> Pushing local deletion {} to the server", url_del));
+                old_etags.insert(url_del, Self::version_tag(&cal_local, url_del).await);
+                progress.increment_counter(1);
+                progress.feedback(SyncEvent::InProgress{
+                    calendar: cal_name.clone(),
+                    items_done_already: progress.counter(),
+                    details: Self::item_name(&cal_local, url_del).await,
+                });
+            }
+
+            let results = cal_remote.delete_items(batch, max_concurrency).await;
+            for (url_del, result) in batch.iter().zip(results) {
+                match result {
+                    Err(KFError::Forbidden{ .. }) => {
+                        if refused.is_empty() {
+                            progress.warn(&format!("The server does not allow deleting items from calendar {}. Local deletions are kept pending", cal_name));
+                            refused = Privileges::UNBIND;
+                        }
+                        Self::skip(&mut cal_local, url_del, PendingChangeKind::Deletion, progress).await;
+                    },
+                    Err(err) => {
+                        progress.item_warn(url_del, &format!("Unable to delete remote item {}: {}", url_del, err));
+                        progress.change_outcome(url_del, PendingChangeKind::Deletion, ChangeStatus::Failed(err.to_string()));
+                        let old_etag = old_etags.remove(url_del).flatten();
+                        Self::audit(&mut cal_local, url_del, AuditOperation::Deletion, AuditDirection::Upload, old_etag, AuditOutcome::Failed(err.to_string())).await;
+                    },
+                    Ok(()) => {
+                        progress.event(ProgressEvent::ItemDeleted{ calendar: cal_url.clone(), item: url_del.clone(), remote: true });
+                        progress.change_outcome(url_del, PendingChangeKind::Deletion, ChangeStatus::Pushed);
+                        // Change the local copy from "marked to deletion" to "actually deleted"
+                        if let Err(err) = cal_local.immediately_delete_item(url_del).await {
+                            progress.item_error(url_del, &format!("Unable to permanently delete local item {}: {}", url_del, err));
+                        }
+                        let old_etag = old_etags.remove(url_del).flatten();
+                        Self::audit(&mut cal_local, url_del, AuditOperation::Deletion, AuditDirection::Upload, old_etag, AuditOutcome::Done).await;
+                    },
+                }
+                Self::mark_done(&mut cal_local, url_del);
+            }
+        }
+        refused
+    }
+
+    #[allow(clippy::await_holding_lock)]
+    async fn apply_remote_deletions(&self, cal_local: &Mutex<T>, progress: &mut SyncProgress) {
+        let mut cal_local = cal_local.lock().unwrap();
+        let cal_name = cal_local.name().to_string();
+        let cal_url = cal_local.url().clone();
+
+        for url_del in Self::pending(&cal_local, |journal| &journal.remote_del) {
+            progress.debug(&format!("> Applying remote deletion {} locally", url_del));
+            progress.increment_counter(1);
+            progress.feedback(SyncEvent::InProgress{
+                calendar: cal_name.clone(),
+                items_done_already: progress.counter(),
+                details: Self::item_name(&cal_local, &url_del).await,
+            });
+            Self::mark_done(&mut cal_local, &url_del);
+            let old_etag = Self::version_tag(&cal_local, &url_del).await;
+            let outcome = match cal_local.immediately_delete_item(&url_del).await {
+                Err(err) => {
+                    progress.item_warn(&url_del, &format!("Unable to delete local item {}: {}", url_del, err));
+                    AuditOutcome::Failed(err.to_string())
+                },
+                Ok(()) => {
+                    progress.event(ProgressEvent::ItemDeleted{ calendar: cal_url.clone(), item: url_del.clone(), remote: false });
+                    AuditOutcome::Done
+                },
+            };
+            Self::audit(&mut cal_local, &url_del, AuditOperation::Deletion, AuditDirection::Download, old_etag, outcome).await;
+        }
+    }
+
+    #[allow(clippy::await_holding_lock)]
+    async fn download_pending(
+        &self,
+        batch_type: BatchDownloadType,
+        step: fn(&SyncJournal) -> &HashSet<Url>,
+        cal_local: &Mutex<T>,
+        cal_remote: &Mutex<U>,
+        capabilities: &ServerCapabilities,
+        progress: &mut SyncProgress,
+    ) {
+        let cal_remote = cal_remote.lock().unwrap();
+        let mut cal_local = cal_local.lock().unwrap();
+        let urls = Self::pending(&cal_local, step).into_iter().collect();
+        self.download_and_apply(batch_type, urls, &mut *cal_local, &*cal_remote, capabilities, progress).await;
+    }
+
+    /// Push local additions, then local changes, and return the privileges the server has refused.
+    /// Conflicts that are detected by the server are stored into the journal, so that they are downloaded afterwards
+    #[allow(clippy::await_holding_lock)]
+    async fn push_local_changes(&self, cal_local: &Mutex<T>, cal_remote: &Mutex<U>, progress: &mut SyncProgress) -> Privileges {
+        let conflict_resolution = &self.conflict_resolution;
+        let max_concurrency = self.max_concurrent_transfers;
+        let mut cal_remote = cal_remote.lock().unwrap();
+        let mut cal_local = cal_local.lock().unwrap();
+        let cal_name = cal_local.name().to_string();
+        let cal_url = cal_local.url().clone();
+
+        // Items are uploaded by batches, so that we can stop as soon as the server is full
+        let mut server_is_full = false;
+        let mut refused = Privileges::empty();
+        let local_additions = Self::pending(&cal_local, |journal| &journal.local_additions);
+        for batch in local_additions.chunks(max_concurrency) {
+            if server_is_full || refused.contains(Privileges::BIND) {
+                for url in batch {
+                    Self::skip(&mut cal_local, url, PendingChangeKind::Addition, progress).await;
+                    Self::mark_done(&mut cal_local, url);
+                }
+                continue;
+            }
+            let mut items = Vec::with_capacity(batch.len());
+            for url_add in batch {
+                progress.debug(&format!("> Pushing local addition {} to the server", url_add));
+                progress.increment_counter(1);
+                progress.feedback(SyncEvent::InProgress{
+                    calendar: cal_name.clone(),
+                    items_done_already: progress.counter(),
+                    details: Self::item_name(&cal_local, url_add).await,
+                });
+                match cal_local.get_item_by_url(url_add).await {
+                    None => {
+                        progress.error(&format!("Inconsistency: created item {} has been marked for upload but is locally missing", url_add));
+                        progress.change_outcome(url_add, PendingChangeKind::Addition, ChangeStatus::Failed("locally missing".to_string()));
+                    },
+                    Some(item) => items.push(item.clone()),
+                }
+            }
+
+            let items = self.before_upload(items, PendingChangeKind::Addition, &mut cal_local, progress).await;
+            let urls: Vec<Url> = items.iter().map(|item| item.url().clone()).collect();
+            let results = cal_remote.add_items(items, max_concurrency).await;
+            for url in batch {
+                Self::mark_done(&mut cal_local, url);
+            }
+            for (url_add, result) in urls.into_iter().zip(results) {
+                match result {
+                    Err(err @ KFError::InsufficientStorage{ .. }) => {
+                        if server_is_full {
+                            Self::skip(&mut cal_local, &url_add, PendingChangeKind::Addition, progress).await;
+                            continue;
+                        }
+                        progress.error(&format!("The server has no storage space left for calendar {}. Local additions and changes will not be pushed until some space is freed.", cal_name));
+                        server_is_full = true;
+                        progress.change_outcome(&url_add, PendingChangeKind::Addition, ChangeStatus::Failed(err.to_string()));
+                        Self::audit(&mut cal_local, &url_add, AuditOperation::Addition, AuditDirection::Upload, None, AuditOutcome::Failed(err.to_string())).await;
+                    },
+                    Err(KFError::Forbidden{ .. }) => {
+                        if !refused.contains(Privileges::BIND) {
+                            progress.warn(&format!("The server does not allow adding items to calendar {}. Local additions are kept locally", cal_name));
+                            refused |= Privileges::BIND;
+                        }
+                        Self::skip(&mut cal_local, &url_add, PendingChangeKind::Addition, progress).await;
+                    },
+                    Err(err) => {
+                        progress.item_error(&url_add, &format!("Unable to add item {} to remote calendar: {}", url_add, err));
+                        progress.change_outcome(&url_add, PendingChangeKind::Addition, ChangeStatus::Failed(err.to_string()));
+                        Self::audit(&mut cal_local, &url_add, AuditOperation::Addition, AuditDirection::Upload, None, AuditOutcome::Failed(err.to_string())).await;
+                    },
+                    Ok(new_ss) => {
+                        progress.change_outcome(&url_add, PendingChangeKind::Addition, ChangeStatus::Pushed);
+                        let url_add = match cal_remote.take_assigned_url(&url_add) {
+                            None => url_add,
+                            Some(assigned_url) => match Self::move_local_item(&mut cal_local, &url_add, &assigned_url).await {
+                                Ok(()) => assigned_url,
+                                Err(err) => {
+                                    progress.item_error(&url_add, &format!("Unable to move item {} to {}, where the server has stored it: {}", url_add, assigned_url, err));
+                                    url_add
+                                },
+                            },
+                        };
+                        if let Some(item) = cal_local.get_item_by_url_mut(&url_add).await {
+                            // Update local sync status
+                            item.set_sync_status(new_ss);
+                            let bytes = progress.item_size(item);
+                            progress.event(ProgressEvent::ItemUploaded{ calendar: cal_url.clone(), item: url_add.clone(), new: true, bytes });
+                        }
+                        Self::audit(&mut cal_local, &url_add, AuditOperation::Addition, AuditDirection::Upload, None, AuditOutcome::Done).await;
+                    },
+                }
+            }
+        }
+
+        let local_changes = Self::pending(&cal_local, |journal| &journal.local_changes);
+        for batch in local_changes.chunks(max_concurrency) {
+            if server_is_full || refused.contains(Privileges::WRITE_CONTENT) {
+                for url in batch {
+                    Self::skip(&mut cal_local, url, PendingChangeKind::Modification, progress).await;
+                    Self::mark_done(&mut cal_local, url);
+                }
+                continue;
+            }
+            let mut items = Vec::with_capacity(batch.len());
+            for url_change in batch {
+                progress.debug(&format!("> Pushing local change {} to the server", url_change));
+                progress.increment_counter(1);
+                progress.feedback(SyncEvent::InProgress{
+                    calendar: cal_name.clone(),
+                    items_done_already: progress.counter(),
+                    details: Self::item_name(&cal_local, url_change).await,
+                });
+                match cal_local.get_item_by_url(url_change).await {
+                    None => {
+                        progress.error(&format!("Inconsistency: modified item {} has been marked for upload but is locally missing", url_change));
+                        progress.change_outcome(url_change, PendingChangeKind::Modification, ChangeStatus::Failed("locally missing".to_string()));
+                    },
+                    Some(item) => items.push(item.clone()),
+                }
+            }
+
+            let items = self.before_upload(items, PendingChangeKind::Modification, &mut cal_local, progress).await;
+            let urls: Vec<Url> = items.iter().map(|item| item.url().clone()).collect();
+            let mut old_etags: HashMap<Url, VersionTag> = items.iter()
+                .filter_map(|item| Some((item.url().clone(), item.sync_status().version_tag()?.clone())))
+                .collect();
+            let results = cal_remote.update_items(items, max_concurrency).await;
+            for url in batch {
+                Self::mark_done(&mut cal_local, url);
+            }
+            for (url_change, result) in urls.into_iter().zip(results) {
+                match result {
+                    Err(KFError::Conflict{ .. }) => {
+                        // The item has been modified on the server since we've listed the remote items
+                        progress.change_outcome(&url_change, PendingChangeKind::Modification, ChangeStatus::Conflict);
+                        let old_etag = old_etags.remove(&url_change);
+                        Self::audit(&mut cal_local, &url_change, AuditOperation::Modification, AuditDirection::Upload, old_etag, AuditOutcome::Conflict).await;
+                        match conflict_resolution {
+                            ConflictResolution::ServerWins => {
+                                progress.event(ProgressEvent::Conflict{ calendar: cal_url.clone(), item: url_change.clone() });
+                                progress.info(&format!("Conflict: task {} has been modified in both sources. Using the remote version.", url_change));
+                                if let Some(journal) = cal_local.sync_journal_mut() {
+                                    journal.late_conflicts.insert(url_change.clone());
+                                }
+                            },
+                            _ => progress.info(&format!("Conflict: task {} has been modified on the server during the sync. It will be resolved at the next sync.", url_change)),
+                        }
+                    },
+                    Err(err @ KFError::InsufficientStorage{ .. }) => {
+                        if server_is_full {
+                            Self::skip(&mut cal_local, &url_change, PendingChangeKind::Modification, progress).await;
+                            continue;
+                        }
+                        progress.error(&format!("The server has no storage space left for calendar {}. Local changes will not be pushed until some space is freed.", cal_name));
+                        server_is_full = true;
+                        progress.change_outcome(&url_change, PendingChangeKind::Modification, ChangeStatus::Failed(err.to_string()));
+                        let old_etag = old_etags.remove(&url_change);
+                        Self::audit(&mut cal_local, &url_change, AuditOperation::Modification, AuditDirection::Upload, old_etag, AuditOutcome::Failed(err.to_string())).await;
+                    },
+                    Err(KFError::Forbidden{ .. }) => {
+                        if !refused.contains(Privileges::WRITE_CONTENT) {
+                            progress.warn(&format!("The server does not allow modifying items of calendar {}. Local changes are kept locally", cal_name));
+                            refused |= Privileges::WRITE_CONTENT;
+                        }
+                        Self::skip(&mut cal_local, &url_change, PendingChangeKind::Modification, progress).await;
+                    },
+                    Err(err) => {
+                        progress.item_error(&url_change, &format!("Unable to update item {} in remote calendar: {}", url_change, err));
+                        progress.change_outcome(&url_change, PendingChangeKind::Modification, ChangeStatus::Failed(err.to_string()));
+                        let old_etag = old_etags.remove(&url_change);
+                        Self::audit(&mut cal_local, &url_change, AuditOperation::Modification, AuditDirection::Upload, old_etag, AuditOutcome::Failed(err.to_string())).await;
+                    },
+                    Ok(new_ss) => {
+                        progress.change_outcome(&url_change, PendingChangeKind::Modification, ChangeStatus::Pushed);
+                        if let Some(item) = cal_local.get_item_by_url_mut(&url_change).await {
+                            // Update local sync status
+                            item.set_sync_status(new_ss);
+                            let bytes = progress.item_size(item);
+                            progress.event(ProgressEvent::ItemUploaded{ calendar: cal_url.clone(), item: url_change.clone(), new: false, bytes });
+                        }
+                        let old_etag = old_etags.remove(&url_change);
+                        Self::audit(&mut cal_local, &url_change, AuditOperation::Modification, AuditDirection::Upload, old_etag, AuditOutcome::Done).await;
+                    },
+                }
+            }
+        }
+        refused
+    }
+
+    /// The version tags of the remote items that are in the scope of the filter, i.e. the ones that match it, and the ones that are already known locally.
+    ///
+    /// Items out of this scope are ignored, so that they are neither downloaded, nor considered as deleted from the server
+    async fn remote_items_in_scope(cal_local: &T, cal_remote: &U, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError> {
+        let all_items = cal_remote.get_item_version_tags().await?;
+        if filter.is_unrestricted() {
+            return Ok(all_items);
+        }
+
+        let matching_items = cal_remote.get_filtered_item_version_tags(filter).await?;
+        let evicted = cal_local.evicted_version_tags();
+        let mut in_scope = HashMap::new();
+        for (url, tag) in all_items {
+            let known_locally = evicted.contains_key(&url) || cal_local.get_item_by_url(&url).await
+                .is_some_and(|item| filter.matches_kind(item));
+            if known_locally || matching_items.contains_key(&url) {
+                in_scope.insert(url, tag);
+            }
+        }
+        Ok(in_scope)
+    }
+
+    /// Compare both calendars, without changing anything
+    async fn find_differences(cal_local: &T, remote_items: HashMap<Url, VersionTag>, filter: &SyncFilter, progress: &mut SyncProgress) -> Result<Differences, KFError> {
         let mut local_del = HashSet::new();
         let mut remote_del = HashSet::new();
         let mut local_changes = HashSet::new();
         let mut remote_changes = HashSet::new();
         let mut local_additions = HashSet::new();
         let mut remote_additions = HashSet::new();
+        let mut conflicts = Vec::new();
 
-        let remote_items = cal_remote.get_item_version_tags().await?;
         progress.feedback(SyncEvent::InProgress{
-            calendar: cal_name.clone(),
+            calendar: cal_local.name().to_string(),
             items_done_already: 0,
             details: format!("{} remote items", remote_items.len()),
         });
 
-        let mut local_items_to_handle = cal_local.get_item_urls().await?;
+        let mut local_items_to_handle: HashSet<Url> = cal_local.iter_items()
+            .filter(|(_, item)| filter.matches_kind(item))
+            .map(|(url, _)| url.clone())
+            .collect();
+        let mut evicted = cal_local.evicted_version_tags();
         for (url, remote_tag) in remote_items {
             progress.trace(&format!("***** Considering remote item {}...", url));
+            if let Some(evicted_tag) = evicted.remove(&url) {
+                // The content of this item has been evicted from the local calendar. It is only downloaded again if it has changed
+                if evicted_tag != remote_tag {
+                    progress.debug(&format!("*   {} is a remote change of an evicted item", url));
+                    remote_changes.insert(url);
+                }
+                continue;
+            }
             match cal_local.get_item_by_url(&url).await {
                 None => {
                     // This was created on the remote
@@ -241,9 +1442,8 @@ where
                                 progress.debug(&format!("*   {} is a local change", url));
                                 local_changes.insert(url);
                             } else {
-                                progress.info(&format!("Conflict: task {} has been modified in both sources. Using the remote version.", url));
-                                progress.debug(&format!("*   {} is considered a remote change", url));
-                                remote_changes.insert(url);
+                                progress.info(&format!("Conflict: task {} has been modified in both sources.", url));
+                                conflicts.push((url, ConflictKind::BothModified(remote_tag)));
                             }
                         },
                         SyncStatus::LocallyDeleted(local_tag) => {
@@ -252,9 +1452,8 @@ where
                                 progress.debug(&format!("*   {} is a local deletion", url));
                                 local_del.insert(url);
                             } else {
-                                progress.info(&format!("Conflict: task {} has been locally deleted and remotely modified. Reverting to the remote version.", url));
-                                progress.debug(&format!("*   {} is a considered a remote change", url));
-                                remote_changes.insert(url);
+                                progress.info(&format!("Conflict: task {} has been locally deleted and remotely modified.", url));
+                                conflicts.push((url, ConflictKind::LocallyDeleted(remote_tag)));
                             }
                         },
                     }
@@ -262,6 +1461,11 @@ where
             }
         }
 
+        for url in evicted.into_keys() {
+            progress.debug(&format!("#   {} is a deletion from the server of an evicted item", url));
+            remote_del.insert(url);
+        }
+
         // Also iterate on the local tasks that are not on the remote
         for url in local_items_to_handle {
             progress.trace(&format!("##### Considering local item {}...", url));
@@ -290,118 +1494,96 @@ where
                     remote_del.insert(url);
                 },
                 SyncStatus::LocallyModified(_) => {
-                    progress.info(&format!("Conflict: item {} has been deleted from the server and locally modified. Deleting the local copy", url));
-                    remote_del.insert(url);
+                    progress.info(&format!("Conflict: item {} has been deleted from the server and locally modified.", url));
+                    conflicts.push((url, ConflictKind::RemotelyDeleted));
                 },
             }
         }
 
+        Ok(Differences {
+            local_del, remote_del,
+            local_changes, remote_changes,
+            local_additions, remote_additions,
+            conflicts,
+        })
+    }
 
-        // Step 2 - commit changes
-        progress.trace("Committing changes...");
-        for url_del in local_del {
-            progress.debug(&format!("> Pushing local deletion {} to the server", url_del));
-            progress.increment_counter(1);
-            progress.feedback(SyncEvent::InProgress{
-                calendar: cal_name.clone(),
-                items_done_already: progress.counter(),
-                details: Self::item_name(&cal_local, &url_del).await,
-            });
-
-            match cal_remote.delete_item(&url_del).await {
-                Err(err) => {
-                    progress.warn(&format!("Unable to delete remote item {}: {}", url_del, err));
-                },
-                Ok(()) => {
-                    // Change the local copy from "marked to deletion" to "actually deleted"
-                    if let Err(err) = cal_local.immediately_delete_item(&url_del).await {
-                        progress.error(&format!("Unable to permanently delete local item {}: {}", url_del, err));
-                    }
+    /// Run the hooks on items that are about to be uploaded. Items that are vetoed are skipped
+    async fn before_upload(&self, items: Vec<Item>, kind: PendingChangeKind, cal_local: &mut T, progress: &mut SyncProgress) -> Vec<Item> {
+        if self.hooks.before_upload.is_empty() {
+            return items;
+        }
+        let mut kept = Vec::with_capacity(items.len());
+        for item in items {
+            let url = item.url().clone();
+            match SyncHooks::run(&self.hooks.before_upload, item).await {
+                None => {
+                    progress.debug(&format!("*   {} has been vetoed by a hook, it is not pushed", url));
+                    Self::skip(cal_local, &url, kind, progress).await;
                 },
+                Some(item) => kept.push(item),
             }
         }
+        kept
+    }
 
-        for url_del in remote_del {
-            progress.debug(&format!("> Applying remote deletion {} locally", url_del));
-            progress.increment_counter(1);
-            progress.feedback(SyncEvent::InProgress{
-                calendar: cal_name.clone(),
-                items_done_already: progress.counter(),
-                details: Self::item_name(&cal_local, &url_del).await,
-            });
-            if let Err(err) = cal_local.immediately_delete_item(&url_del).await {
-                progress.warn(&format!("Unable to delete local item {}: {}", url_del, err));
-            }
+    /// Do not push some local changes, and report them as skipped
+    async fn skip_all(cal_local: &mut T, urls: &mut HashSet<Url>, kind: PendingChangeKind, progress: &mut SyncProgress) {
+        for url in urls.drain() {
+            Self::skip(cal_local, &url, kind, progress).await;
         }
+    }
 
-        Self::apply_remote_additions(
-            remote_additions,
-            &mut *cal_local,
-            &mut *cal_remote,
-            progress,
-            &cal_name
-        ).await;
+    /// Do not push a local change, and report it as skipped
+    async fn skip(cal_local: &mut T, url: &Url, kind: PendingChangeKind, progress: &mut SyncProgress) {
+        progress.event(ProgressEvent::Skipped{ calendar: cal_local.url().clone(), item: url.clone() });
+        progress.change_outcome(url, kind, ChangeStatus::Skipped);
+        let old_etag = Self::version_tag(cal_local, url).await;
+        Self::audit(cal_local, url, kind.into(), AuditDirection::Upload, old_etag, AuditOutcome::Skipped).await;
+    }
+
+    /// Record an operation of the sync into the audit log of the local calendar.
+    /// `old_etag` is the version tag the local item had before the operation, its new version tag is read from the local calendar
+    async fn audit(cal_local: &mut T, url: &Url, operation: AuditOperation, direction: AuditDirection, old_etag: Option<VersionTag>, outcome: AuditOutcome) {
+        Self::audit_changes(cal_local, url, operation, direction, old_etag, outcome, Vec::new()).await
+    }
 
-        Self::apply_remote_changes(
-            remote_changes,
-            &mut *cal_local,
-            &mut *cal_remote,
-            progress,
-            &cal_name
-        ).await;
+    /// Same as [`Self::audit`], for an operation that has changed these fields of the local item
+    async fn audit_changes(cal_local: &mut T, url: &Url, operation: AuditOperation, direction: AuditDirection, old_etag: Option<VersionTag>, outcome: AuditOutcome, changes: Vec<FieldChange>) {
+        let new_etag = Self::version_tag(cal_local, url).await;
+        let calendar = cal_local.url().clone();
+        cal_local.audit_log_mut().record(AuditEntry{
+            at: Utc::now(), calendar, item: url.clone(),
+            operation, direction, old_etag, new_etag, outcome, changes,
+        });
+    }
 
+    /// The version tag a local item has been synced with, if any
+    async fn version_tag(cal_local: &T, url: &Url) -> Option<VersionTag> {
+        cal_local.get_item_by_url(url).await.and_then(|item| item.sync_status().version_tag().cloned())
+    }
 
-        for url_add in local_additions {
-            progress.debug(&format!("> Pushing local addition {} to the server", url_add));
-            progress.increment_counter(1);
-            progress.feedback(SyncEvent::InProgress{
-                calendar: cal_name.clone(),
-                items_done_already: progress.counter(),
-                details: Self::item_name(&cal_local, &url_add).await,
-            });
-            match cal_local.get_item_by_url_mut(&url_add).await {
-                None => {
-                    progress.error(&format!("Inconsistency: created item {} has been marked for upload but is locally missing", url_add));
-                    continue;
-                },
-                Some(item) => {
-                    match cal_remote.add_item(item.clone()).await {
-                        Err(err) => progress.error(&format!("Unable to add item {} to remote calendar: {}", url_add, err)),
-                        Ok(new_ss) => {
-                            // Update local sync status
-                            item.set_sync_status(new_ss);
-                        },
-                    }
-                },
-            };
-        }
+    /// Remember the current version of every synced item, so that it can be used as the base of a future merge
+    async fn record_base_versions(cal_local: &mut T, progress: &mut SyncProgress) {
+        let to_record: Vec<Item> = match cal_local.get_items().await {
+            Err(err) => {
+                progress.warn(&format!("Unable to list the local items: {}", err));
+                return;
+            },
+            Ok(items) => items.values()
+                .filter(|item| match (item.sync_status(), cal_local.base_version(item.url()).map(|base| base.sync_status())) {
+                    (SyncStatus::Synced(tag), Some(SyncStatus::Synced(base_tag))) => tag != base_tag,
+                    (SyncStatus::Synced(_), _) => true,
+                    _ => false,
+                })
+                .map(|item| (*item).clone())
+                .collect(),
+        };
 
-        for url_change in local_changes {
-            progress.debug(&format!("> Pushing local change {} to the server", url_change));
-            progress.increment_counter(1);
-            progress.feedback(SyncEvent::InProgress{
-                calendar: cal_name.clone(),
-                items_done_already: progress.counter(),
-                details: Self::item_name(&cal_local, &url_change).await,
-            });
-            match cal_local.get_item_by_url_mut(&url_change).await {
-                None => {
-                    progress.error(&format!("Inconsistency: modified item {} has been marked for upload but is locally missing", url_change));
-                    continue;
-                },
-                Some(item) => {
-                    match cal_remote.update_item(item.clone()).await {
-                        Err(err) => progress.error(&format!("Unable to update item {} in remote calendar: {}", url_change, err)),
-                        Ok(new_ss) => {
-                            // Update local sync status
-                            item.set_sync_status(new_ss);
-                        },
-                    };
-                }
-            };
+        for item in to_record {
+            let url = item.url().clone();
+            cal_local.set_base_version(&url, Some(item));
         }
-
-        Ok(())
     }
 
 
@@ -409,44 +1591,70 @@ where
         cal.get_item_by_url(url).await.map(|item| item.name()).unwrap_or_default().to_string()
     }
 
-    async fn apply_remote_additions(
-        mut remote_additions: HashSet<Url>,
+    /// Download items by batches, several batches at the same time (see [`Self::set_max_concurrent_transfers`]), and apply them locally
+    async fn download_and_apply(
+        &self,
+        batch_type: BatchDownloadType,
+        urls: HashSet<Url>,
         cal_local: &mut T,
-        cal_remote: &mut U,
+        cal_remote: &U,
+        capabilities: &ServerCapabilities,
         progress: &mut SyncProgress,
-        cal_name: &str
     ) {
-        for batch in remote_additions.drain().chunks(DOWNLOAD_BATCH_SIZE).into_iter() {
-            Self::fetch_batch_and_apply(BatchDownloadType::RemoteAdditions, batch, cal_local, cal_remote, progress, cal_name).await;
+        let batches: Vec<Vec<Url>> = urls.into_iter()
+            .chunks(DOWNLOAD_BATCH_SIZE).into_iter()
+            .map(|batch| batch.collect())
+            .collect();
+
+        let mut downloads = stream::iter(batches)
+            .map(|batch| async move {
+                let fetched = if capabilities.supports_multiget() {
+                    cal_remote.get_items_by_url(&batch).await
+                } else {
+                    Self::fetch_one_by_one(&batch, cal_remote).await
+                };
+                (batch, fetched)
+            })
+            .buffer_unordered(self.max_concurrent_transfers);
+
+        while let Some((batch, fetched)) = downloads.next().await {
+            Self::apply_batch(batch_type, batch, fetched, &self.hooks.after_download, cal_local, progress).await;
         }
     }
 
-    async fn apply_remote_changes(
-        mut remote_changes: HashSet<Url>,
-        cal_local: &mut T,
-        cal_remote: &mut U,
-        progress: &mut SyncProgress,
-        cal_name: &str
-    ) {
-        for batch in remote_changes.drain().chunks(DOWNLOAD_BATCH_SIZE).into_iter() {
-            Self::fetch_batch_and_apply(BatchDownloadType::RemoteChanges, batch, cal_local, cal_remote, progress, cal_name).await;
+    /// Download items with one request each, for servers that do not support multiget REPORTs
+    async fn fetch_one_by_one(urls: &[Url], cal_remote: &U) -> Result<Vec<Option<Item>>, KFError> {
+        let mut items = Vec::with_capacity(urls.len());
+        for url in urls {
+            items.push(cal_remote.get_item_by_url(url).await?);
         }
+        Ok(items)
     }
 
-    async fn fetch_batch_and_apply<I: Iterator<Item = Url>>(
+    async fn apply_batch(
         batch_type: BatchDownloadType,
-        remote_additions: I,
+        batch: Vec<Url>,
+        fetched: Result<Vec<Option<Item>>, KFError>,
+        hooks: &[ItemHook],
         cal_local: &mut T,
-        cal_remote: &mut U,
         progress: &mut SyncProgress,
-        cal_name: &str
     ) {
-        progress.debug(&format!("> Applying a batch of {} locally", batch_type) /* too bad Chunks does not implement ExactSizeIterator, that could provide useful debug info. See https://github.com/rust-itertools/itertools/issues/171 */);
+        progress.debug(&format!("> Applying a batch of {} {} locally", batch.len(), batch_type));
+        for url in &batch {
+            Self::mark_done(cal_local, url);
+        }
 
-        let list_of_additions: Vec<Url> = remote_additions.map(|url| url.clone()).collect();
-        match cal_remote.get_items_by_url(&list_of_additions).await {
+        let operation = match batch_type {
+            BatchDownloadType::RemoteAdditions => AuditOperation::Addition,
+            BatchDownloadType::RemoteChanges => AuditOperation::Modification,
+        };
+        match fetched {
             Err(err) => {
-                progress.warn(&format!("Unable to get the batch of {} {:?}: {}. Skipping them.", batch_type, list_of_additions, err));
+                progress.warn(&format!("Unable to get the batch of {} {:?}: {}. Skipping them.", batch_type, batch, err));
+                for url in &batch {
+                    let old_etag = Self::version_tag(cal_local, url).await;
+                    Self::audit(cal_local, url, operation, AuditDirection::Download, old_etag, AuditOutcome::Failed(err.to_string())).await;
+                }
             },
             Ok(items) => {
                 for item in items {
@@ -456,25 +1664,53 @@ where
                             continue;
                         },
                         Some(new_item) => {
+                            let bytes = progress.item_size(&new_item);
+                            let item_url = new_item.url().clone();
+                            let new_item = match SyncHooks::run(hooks, new_item).await {
+                                None => {
+                                    progress.debug(&format!("*   {} has been vetoed by a hook, it is not stored locally", item_url));
+                                    continue;
+                                },
+                                Some(item) => item,
+                            };
+                            let old_etag = Self::version_tag(cal_local, &item_url).await;
+                            let changes = match cal_local.get_item_by_url(&item_url).await {
+                                Some(old_item) => old_item.diff(&new_item),
+                                None => Vec::new(),
+                            };
                             let local_update_result = match batch_type {
-                                BatchDownloadType::RemoteAdditions => cal_local.add_item(new_item.clone()).await,
-                                BatchDownloadType::RemoteChanges => cal_local.update_item(new_item.clone()).await,
+                                BatchDownloadType::RemoteAdditions => cal_local.add_item(new_item).await,
+                                BatchDownloadType::RemoteChanges => cal_local.update_item(new_item).await,
                             };
-                            if let Err(err) = local_update_result {
-                                progress.error(&format!("Not able to add item {} to local calendar: {}", new_item.url(), err));
-                            }
+                            let outcome = match local_update_result {
+                                Err(err) => {
+                                    progress.item_error(&item_url, &format!("Not able to add item {} to local calendar: {}", item_url, err));
+                                    AuditOutcome::Failed(err.to_string())
+                                },
+                                Ok(_) => {
+                                    progress.event(ProgressEvent::ItemDownloaded{
+                                        calendar: cal_local.url().clone(),
+                                        item: item_url.clone(),
+                                        new: matches!(batch_type, BatchDownloadType::RemoteAdditions),
+                                        bytes,
+                                    });
+                                    AuditOutcome::Done
+                                },
+                            };
+                            let changes = if outcome == AuditOutcome::Done { changes } else { Vec::new() };
+                            Self::audit_changes(cal_local, &item_url, operation, AuditDirection::Download, old_etag, outcome, changes).await;
                         },
                     }
                 }
 
                 // Notifying every item at the same time would not make sense. Let's notify only one of them
-                let one_item_name = match list_of_additions.get(0) {
-                    Some(url) => Self::item_name(&cal_local, &url).await,
+                let one_item_name = match batch.first() {
+                    Some(url) => Self::item_name(cal_local, url).await,
                     None => String::from("<unable to get the name of the first batched item>"),
                 };
-                progress.increment_counter(list_of_additions.len());
+                progress.increment_counter(batch.len());
                 progress.feedback(SyncEvent::InProgress{
-                    calendar: cal_name.to_string(),
+                    calendar: cal_local.name().to_string(),
                     items_done_already: progress.counter(),
                     details: one_item_name,
                 });
@@ -485,7 +1721,7 @@ where
 
 
 async fn get_or_insert_counterpart_calendar<H, N, I>(haystack_descr: &str, haystack: &mut H, cal_url: &Url, needle: Arc<Mutex<N>>)
-    -> Result<Arc<Mutex<I>>, Box<dyn Error>>
+    -> Result<Arc<Mutex<I>>, KFError>
 where
     H: CalDavSource<I>,
     I: BaseCalendar,
@@ -502,14 +1738,25 @@ where
         let name = src.name().to_string();
         let supported_comps = src.supported_components();
         let color = src.color();
-        if let Err(err) = haystack.create_calendar(
+        let timezone = src.timezone();
+        if let Err(err) = haystack.create_calendar_with_timezone(
             cal_url.clone(),
             name,
             supported_comps,
             color.cloned(),
+            timezone.cloned(),
         ).await{
             return Err(err);
         }
     }
 }
 
+
+/// A copy of an item at another URL, with the same UID
+fn moved_item(item: &Item, new_url: &Url) -> Option<Item> {
+    match item {
+        Item::Task(task) => Some(Item::Task(task.moved_to(new_url.clone()))),
+        Item::Contact(contact) => Some(Item::Contact(contact.moved_to(new_url.clone()))),
+        Item::Event(event) => Some(Item::Event(event.moved_to(new_url.clone()))),
+    }
+}
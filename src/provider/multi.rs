@@ -0,0 +1,180 @@
+//! Several accounts synced together, see [`MultiProvider`]
+
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+
+use futures_util::future::join_all;
+use url::Url;
+
+use crate::traits::{CalDavSource, CompleteCalendar, DavCalendar};
+use crate::error::KFError;
+use super::Provider;
+use super::sync_progress::{progress_channel, ProgressEvent};
+use super::sync_result::{SyncError, SyncResult};
+
+/// A [`ProgressEvent`] of one of the accounts of a [`MultiProvider`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountEvent {
+    pub account: String,
+    pub event: ProgressEvent,
+}
+
+/// See [`MultiProvider::sync_all_with_progress`]
+pub type AccountProgressSender = tokio::sync::mpsc::UnboundedSender<AccountEvent>;
+
+/// A local calendar, and the account it belongs to
+pub struct AccountCalendar<T> {
+    pub account: String,
+    pub url: Url,
+    pub calendar: Arc<Mutex<T>>,
+}
+
+/// What happened to an account during a [`MultiProvider::sync_all`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountSyncResult {
+    pub account: String,
+    pub result: SyncResult,
+}
+
+/// What happened during a [`MultiProvider::sync_all`], account by account (in the order they have been added)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MultiSyncResult {
+    pub accounts: Vec<AccountSyncResult>,
+}
+
+impl MultiSyncResult {
+    /// Whether every account has been successfully synced
+    pub fn is_success(&self) -> bool {
+        self.accounts.iter().all(|account| account.result.is_success())
+    }
+
+    /// The result of an account
+    pub fn account(&self, name: &str) -> Option<&SyncResult> {
+        self.accounts.iter().find(|account| account.account == name).map(|account| &account.result)
+    }
+
+    /// All errors, with the account they happened in
+    pub fn all_errors(&self) -> impl Iterator<Item = (&str, &SyncError)> {
+        self.accounts.iter().flat_map(|account| account.result.all_errors().map(move |err| (account.account.as_str(), err)))
+    }
+}
+
+impl Display for MultiSyncResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, account) in self.accounts.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}: {}", account.account, account.result)?;
+        }
+        Ok(())
+    }
+}
+
+/// Several [`Provider`]s (usually one per account), that are synced together.
+///
+/// Accounts are identified by a name, that is chosen by the application
+pub struct MultiProvider<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    accounts: Vec<(String, Provider<L, T, R, U>)>,
+}
+
+impl<L, T, R, U> Default for MultiProvider<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    fn default() -> Self {
+        Self { accounts: Vec::new() }
+    }
+}
+
+impl<L, T, R, U> MultiProvider<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    /// Create a provider without any account
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an account. This fails if there is already an account with the same name
+    pub fn add_account(&mut self, name: String, provider: Provider<L, T, R, U>) -> Result<(), KFError> {
+        if self.account(&name).is_some() {
+            return Err(format!("There is already an account named {}", name).into());
+        }
+        self.accounts.push((name, provider));
+        Ok(())
+    }
+
+    /// Remove an account, and return its provider
+    pub fn remove_account(&mut self, name: &str) -> Option<Provider<L, T, R, U>> {
+        let index = self.accounts.iter().position(|(account, _)| account == name)?;
+        Some(self.accounts.remove(index).1)
+    }
+
+    pub fn account(&self, name: &str) -> Option<&Provider<L, T, R, U>> {
+        self.accounts.iter().find(|(account, _)| account == name).map(|(_, provider)| provider)
+    }
+
+    pub fn account_mut(&mut self, name: &str) -> Option<&mut Provider<L, T, R, U>> {
+        self.accounts.iter_mut().find(|(account, _)| account == name).map(|(_, provider)| provider)
+    }
+
+    /// The names of the accounts, in the order they have been added
+    pub fn account_names(&self) -> impl Iterator<Item = &str> {
+        self.accounts.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Sync every account. Accounts are synced at the same time, and a failing account does not prevent the other ones from being synced
+    pub async fn sync_all(&mut self) -> MultiSyncResult {
+        let syncs = self.accounts.iter_mut().map(|(name, provider)| async move {
+            AccountSyncResult{ account: name.clone(), result: provider.sync().await }
+        });
+        MultiSyncResult{ accounts: join_all(syncs).await }
+    }
+
+    /// Same as [`Self::sync_all`], but the [`ProgressEvent`]s of every account are sent to a single channel, tagged with the name of their account
+    pub async fn sync_all_with_progress(&mut self, progress_sender: AccountProgressSender) -> MultiSyncResult {
+        let syncs = self.accounts.iter_mut().map(|(name, provider)| {
+            let progress_sender = progress_sender.clone();
+            async move {
+                let (sender, mut receiver) = progress_channel();
+                let forward = async {
+                    while let Some(event) = receiver.recv().await {
+                        // The receiver may have been dropped, which is not an error for the sync itself
+                        let _ = progress_sender.send(AccountEvent{ account: name.clone(), event });
+                    }
+                };
+                let (result, _) = tokio::join!(provider.sync_with_progress(sender), forward);
+                AccountSyncResult{ account: name.clone(), result }
+            }
+        });
+        MultiSyncResult{ accounts: join_all(syncs).await }
+    }
+
+    /// The local calendars of every account.
+    ///
+    /// Accounts whose calendars cannot be listed are reported as errors, with their name
+    pub async fn calendars(&self) -> Result<Vec<AccountCalendar<T>>, KFError> {
+        let mut calendars = Vec::new();
+        for (name, provider) in &self.accounts {
+            let cals = provider.local().get_calendars().await
+                .map_err(|err| format!("Unable to list the calendars of account {}: {}", name, err))?;
+            for (url, calendar) in cals {
+                calendars.push(AccountCalendar{ account: name.clone(), url, calendar });
+            }
+        }
+        Ok(calendars)
+    }
+}
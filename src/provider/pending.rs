@@ -0,0 +1,39 @@
+//! Local changes that have not been pushed to the server yet, see [`Provider::pending_changes`](crate::provider::Provider::pending_changes)
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::item::SyncStatus;
+
+/// What kind of local change is waiting to be pushed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PendingChangeKind {
+    Addition,
+    Modification,
+    Deletion,
+}
+
+impl PendingChangeKind {
+    /// The change that an item with this sync status is waiting for, if any
+    pub fn from_sync_status(sync_status: &SyncStatus) -> Option<Self> {
+        match sync_status {
+            SyncStatus::NotSynced => Some(Self::Addition),
+            SyncStatus::LocallyModified(_) => Some(Self::Modification),
+            SyncStatus::LocallyDeleted(_) => Some(Self::Deletion),
+            SyncStatus::Synced(_) => None,
+        }
+    }
+}
+
+/// A local change that will be pushed at the next sync.
+///
+/// Several changes made to the same item are merged: only the first time it has been changed is kept
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingChange {
+    pub calendar: Url,
+    pub item: Url,
+    pub kind: PendingChangeKind,
+    /// When the item has been changed
+    pub queued_at: DateTime<Utc>,
+}
@@ -0,0 +1,95 @@
+//! Previews of what a sync would do, see [`Provider::plan_sync`](crate::provider::Provider::plan_sync)
+
+use std::collections::HashSet;
+
+use url::Url;
+
+use super::conflict::ConflictKind;
+
+/// Something a sync would do to an item
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SyncAction {
+    /// An item created on the server would be downloaded
+    Download(Url),
+    /// An item changed on the server would be downloaded
+    DownloadChange(Url),
+    /// An item created locally would be uploaded
+    Upload(Url),
+    /// An item changed locally would be uploaded
+    UploadChange(Url),
+    /// An item deleted locally would be deleted from the server
+    DeleteRemote(Url),
+    /// An item deleted from the server would be deleted locally
+    DeleteLocal(Url),
+    /// An item has been changed (or deleted) on both sides. It would be resolved according to the [`ConflictResolution`](super::conflict::ConflictResolution) of the provider
+    Conflict(Url),
+}
+
+impl SyncAction {
+    /// The item this action applies to
+    pub fn url(&self) -> &Url {
+        match self {
+            Self::Download(url) | Self::DownloadChange(url) |
+            Self::Upload(url) | Self::UploadChange(url) |
+            Self::DeleteRemote(url) | Self::DeleteLocal(url) |
+            Self::Conflict(url) => url,
+        }
+    }
+}
+
+/// What a sync would do to a calendar
+#[derive(Clone, Debug, PartialEq)]
+pub struct CalendarPlan {
+    pub url: Url,
+    pub name: String,
+    /// Whether this calendar only exists on the server, and would be created locally
+    pub create_locally: bool,
+    /// Whether this calendar only exists locally, and would be created on the server
+    pub create_remotely: bool,
+    pub actions: Vec<SyncAction>,
+}
+
+/// What a sync would do
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncPlan {
+    pub calendars: Vec<CalendarPlan>,
+}
+
+impl SyncPlan {
+    /// Whether a sync would not change anything
+    pub fn is_empty(&self) -> bool {
+        self.calendars.iter().all(|cal| !cal.create_locally && !cal.create_remotely && cal.actions.is_empty())
+    }
+
+    /// All the actions, for every calendar
+    pub fn actions(&self) -> impl Iterator<Item = &SyncAction> {
+        self.calendars.iter().flat_map(|cal| cal.actions.iter())
+    }
+}
+
+
+/// The differences between a local calendar and its remote counterpart
+#[derive(Default)]
+pub(crate) struct Differences {
+    pub local_del: HashSet<Url>,
+    pub remote_del: HashSet<Url>,
+    pub local_changes: HashSet<Url>,
+    pub remote_changes: HashSet<Url>,
+    pub local_additions: HashSet<Url>,
+    pub remote_additions: HashSet<Url>,
+    pub conflicts: Vec<(Url, ConflictKind)>,
+}
+
+impl Differences {
+    pub(crate) fn into_actions(self) -> Vec<SyncAction> {
+        let mut actions = Vec::new();
+        actions.extend(self.remote_additions.into_iter().map(SyncAction::Download));
+        actions.extend(self.remote_changes.into_iter().map(SyncAction::DownloadChange));
+        actions.extend(self.local_additions.into_iter().map(SyncAction::Upload));
+        actions.extend(self.local_changes.into_iter().map(SyncAction::UploadChange));
+        actions.extend(self.local_del.into_iter().map(SyncAction::DeleteRemote));
+        actions.extend(self.remote_del.into_iter().map(SyncAction::DeleteLocal));
+        actions.extend(self.conflicts.into_iter().map(|(url, _)| SyncAction::Conflict(url)));
+        actions
+    }
+}
@@ -0,0 +1,262 @@
+//! Periodic syncs in the background
+//!
+//! Applications usually want to sync every few minutes, to retry (but not too often) when the server is unreachable, and to let the user pause syncs or trigger one immediately.
+//! A [`SyncScheduler`] does all of this.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::traits::{CalDavSource, CompleteCalendar, DavCalendar};
+use crate::time::Instant;
+use super::Provider;
+use super::sync_result::SyncResult;
+
+/// When a [`SyncScheduler`] runs syncs
+#[derive(Clone, Debug)]
+pub struct ScheduleSettings {
+    /// The delay between two syncs
+    pub interval: Duration,
+    /// The maximum random delay that is added to every interval, so that many clients do not sync all at the same time
+    pub jitter: Duration,
+    /// The delay before retrying after a failed sync. It doubles after every consecutive failure
+    pub retry_delay: Duration,
+    /// Upper bound of the delay before retrying after failed syncs
+    pub max_retry_delay: Duration,
+    /// Whether a sync should be run as soon as the scheduler starts
+    pub sync_on_start: bool,
+}
+
+impl Default for ScheduleSettings {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15 * 60),
+            jitter: Duration::from_secs(60),
+            retry_delay: Duration::from_secs(30),
+            max_retry_delay: Duration::from_secs(60 * 60),
+            sync_on_start: true,
+        }
+    }
+}
+
+impl ScheduleSettings {
+    /// The delay before the next sync, after `consecutive_failures` failed syncs
+    pub(crate) fn next_delay(&self, consecutive_failures: u32) -> Duration {
+        let delay = match consecutive_failures {
+            0 => self.interval,
+            n => {
+                let exponent = (n - 1).min(31);
+                self.retry_delay
+                    .checked_mul(1 << exponent)
+                    .unwrap_or(self.max_retry_delay)
+                    .min(self.max_retry_delay)
+            },
+        };
+
+        let jitter_nanos = self.jitter.as_nanos() as u64;
+        if jitter_nanos == 0 {
+            return delay;
+        }
+        // No need for a proper RNG here, we only want syncs of different clients to be spread
+        let random = RandomState::new().build_hasher().finish();
+        delay + Duration::from_nanos(random % jitter_nanos)
+    }
+}
+
+/// Something that happened in a [`SyncScheduler`]
+#[derive(Clone, Debug)]
+pub enum SchedulerEvent {
+    SyncStarted,
+    SyncFinished(SyncResult),
+    Paused,
+    Resumed,
+    /// The scheduler has stopped, and will not run any sync anymore
+    Stopped,
+}
+
+#[derive(Debug)]
+enum Command {
+    Pause,
+    Resume,
+    SyncNow,
+    Stop,
+}
+
+/// Controls a running [`SyncScheduler`].
+///
+/// The scheduler stops when every handle has been dropped
+#[derive(Clone, Debug)]
+pub struct SchedulerHandle {
+    commands: UnboundedSender<Command>,
+}
+
+impl SchedulerHandle {
+    /// Stop running periodic syncs (a sync that is in progress is not interrupted)
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    /// Resume periodic syncs after [`Self::pause`]
+    pub fn resume(&self) {
+        self.send(Command::Resume);
+    }
+
+    /// Run a sync as soon as possible, even if the scheduler is paused
+    pub fn sync_now(&self) {
+        self.send(Command::SyncNow);
+    }
+
+    /// Stop the scheduler for good
+    pub fn stop(&self) {
+        self.send(Command::Stop);
+    }
+
+    fn send(&self, command: Command) {
+        // The scheduler may have stopped already, in which case there is nothing to do
+        let _ = self.commands.send(command);
+    }
+}
+
+/// Runs [`Provider::sync`] periodically.
+///
+/// The provider is shared behind an async mutex, so that the application can still use it between syncs.
+/// The scheduler does nothing until [`Self::run`] is awaited (e.g. in a dedicated task)
+pub struct SyncScheduler<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    provider: Arc<tokio::sync::Mutex<Provider<L, T, R, U>>>,
+    settings: ScheduleSettings,
+    commands: UnboundedReceiver<Command>,
+    events: UnboundedSender<SchedulerEvent>,
+}
+
+impl<L, T, R, U> SyncScheduler<L, T, R, U>
+where
+    L: CalDavSource<T>,
+    T: CompleteCalendar + Sync + Send,
+    R: CalDavSource<U>,
+    U: DavCalendar + Sync + Send,
+{
+    /// Create a scheduler, a handle to control it, and a receiver of its events
+    pub fn new(provider: Arc<tokio::sync::Mutex<Provider<L, T, R, U>>>, settings: ScheduleSettings) -> (Self, SchedulerHandle, UnboundedReceiver<SchedulerEvent>) {
+        let (command_sender, commands) = unbounded_channel();
+        let (events, event_receiver) = unbounded_channel();
+        let scheduler = Self { provider, settings, commands, events };
+        (scheduler, SchedulerHandle{ commands: command_sender }, event_receiver)
+    }
+
+    /// Run syncs until the scheduler is stopped
+    pub async fn run(mut self) {
+        let mut paused = false;
+        let mut consecutive_failures = 0;
+        let mut next_sync = match self.settings.sync_on_start {
+            true => Instant::now(),
+            false => Instant::now() + self.settings.next_delay(0),
+        };
+
+        loop {
+            let command = if paused {
+                self.commands.recv().await
+            } else {
+                tokio::select! {
+                    command = self.commands.recv() => command,
+                    _ = crate::time::sleep_until(next_sync) => Some(Command::SyncNow),
+                }
+            };
+
+            match command {
+                None | Some(Command::Stop) => {
+                    self.notify(SchedulerEvent::Stopped);
+                    return;
+                },
+                Some(Command::Pause) => {
+                    if !paused {
+                        paused = true;
+                        self.notify(SchedulerEvent::Paused);
+                    }
+                },
+                Some(Command::Resume) => {
+                    if paused {
+                        paused = false;
+                        self.notify(SchedulerEvent::Resumed);
+                    }
+                },
+                Some(Command::SyncNow) => {
+                    self.notify(SchedulerEvent::SyncStarted);
+                    let result = self.provider.lock().await.sync().await;
+                    consecutive_failures = match result.is_success() {
+                        true => 0,
+                        false => consecutive_failures + 1,
+                    };
+                    next_sync = Instant::now() + self.settings.next_delay(consecutive_failures);
+                    log::debug!("Next sync in {:?}", next_sync - Instant::now());
+                    self.notify(SchedulerEvent::SyncFinished(result));
+                },
+            }
+        }
+    }
+
+    fn notify(&self, event: SchedulerEvent) {
+        // Nobody may be listening, which is fine
+        let _ = self.events.send(event);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_delay() {
+        let settings = ScheduleSettings {
+            interval: Duration::from_secs(600),
+            jitter: Duration::from_secs(0),
+            retry_delay: Duration::from_secs(10),
+            max_retry_delay: Duration::from_secs(100),
+            sync_on_start: true,
+        };
+        assert_eq!(settings.next_delay(0), Duration::from_secs(600));
+        assert_eq!(settings.next_delay(1), Duration::from_secs(10));
+        assert_eq!(settings.next_delay(3), Duration::from_secs(40));
+        assert_eq!(settings.next_delay(50), Duration::from_secs(100));
+
+        let jittered = ScheduleSettings { jitter: Duration::from_secs(5), ..settings };
+        let delay = jittered.next_delay(0);
+        assert!(delay >= Duration::from_secs(600) && delay < Duration::from_secs(605));
+    }
+
+    #[cfg(feature = "local_calendar_mocks_remote_calendars")]
+    #[tokio::test]
+    async fn test_scheduler_commands() {
+        use std::path::Path;
+        use crate::cache::Cache;
+
+        let provider = Provider::new(Cache::new(Path::new("test_cache/scheduler_remote")), Cache::new(Path::new("test_cache/scheduler_local")));
+        let settings = ScheduleSettings { interval: Duration::from_secs(3600), jitter: Duration::from_secs(0), ..ScheduleSettings::default() };
+        let (scheduler, handle, mut events) = SyncScheduler::new(Arc::new(tokio::sync::Mutex::new(provider)), settings);
+
+        let controller = async move {
+            assert!(matches!(events.recv().await, Some(SchedulerEvent::SyncStarted)));
+            assert!(matches!(events.recv().await, Some(SchedulerEvent::SyncFinished(result)) if result.is_success()));
+
+            handle.pause();
+            assert!(matches!(events.recv().await, Some(SchedulerEvent::Paused)));
+            handle.sync_now();
+            assert!(matches!(events.recv().await, Some(SchedulerEvent::SyncStarted)));
+            assert!(matches!(events.recv().await, Some(SchedulerEvent::SyncFinished(_))));
+            handle.resume();
+            assert!(matches!(events.recv().await, Some(SchedulerEvent::Resumed)));
+            handle.stop();
+            assert!(matches!(events.recv().await, Some(SchedulerEvent::Stopped)));
+        };
+        tokio::join!(scheduler.run(), controller);
+    }
+}
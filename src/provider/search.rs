@@ -0,0 +1,200 @@
+//! Full-text search of the local items, see [`Provider::search`](crate::provider::Provider::search)
+
+use chrono::{DateTime, Utc};
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use url::Url;
+
+use crate::Item;
+use crate::item::SyncStatus;
+use crate::calendar::SupportedComponents;
+use crate::calendar::lazy_items::ItemSummary;
+
+/// The properties of an item a search looks into
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SearchField {
+    /// The name of the item, i.e. its `SUMMARY`
+    Summary,
+    Description,
+    Location,
+    Categories,
+}
+
+impl SearchField {
+    const ALL: [SearchField; 4] = [Self::Summary, Self::Description, Self::Location, Self::Categories];
+
+    fn property_name(&self) -> &'static str {
+        match self {
+            Self::Summary => "SUMMARY",
+            Self::Description => "DESCRIPTION",
+            Self::Location => "LOCATION",
+            Self::Categories => "CATEGORIES",
+        }
+    }
+}
+
+/// Restricts a search to some kinds of items, and to a time window
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchOptions {
+    /// The kinds of items to search
+    pub components: SupportedComponents,
+    /// Ignore items that ended before this date
+    pub since: Option<DateTime<Utc>>,
+    /// Ignore items that start after this date
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self { components: SupportedComponents::all(), since: None, until: None }
+    }
+}
+
+/// An item that matches a search
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchMatch {
+    pub calendar: Url,
+    pub item: Url,
+    pub name: String,
+    /// The fields the query has been found in
+    pub fields: Vec<SearchField>,
+}
+
+impl SearchOptions {
+    /// Search everything
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only search tasks
+    pub fn tasks_only() -> Self {
+        Self { components: SupportedComponents::TODO, ..Self::default() }
+    }
+
+    /// Only search events
+    pub fn events_only() -> Self {
+        Self { components: SupportedComponents::EVENT, ..Self::default() }
+    }
+
+    /// Ignore items that ended before `since`
+    pub fn since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Ignore items that start after `until`
+    pub fn until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Whether an item is within the time window. When a time window is set, items that have no date never match it
+    fn matches_dates(&self, item: &Item) -> bool {
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        match ItemSummary::from_item(item).date_range() {
+            None => false,
+            Some((first, last)) => self.until.is_none_or(|until| first <= until)
+                && self.since.is_none_or(|since| last.is_none_or(|last| last >= since)),
+        }
+    }
+
+    /// The items of a calendar that contain every word of a query, ignoring case and diacritics
+    pub(crate) fn search<'a, I: IntoIterator<Item = &'a Item>>(&self, query: &str, calendar: &Url, items: I) -> Vec<SearchMatch> {
+        let words: Vec<String> = normalize(query).split_whitespace().map(String::from).collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matches = Vec::new();
+        for item in items {
+            let task = match item {
+                Item::Task(task) if self.components.contains(SupportedComponents::TODO) => task,
+                // Only tasks are searched
+                _ => continue,
+            };
+            if matches!(task.sync_status(), SyncStatus::LocallyDeleted(_)) || !self.matches_dates(item) {
+                continue;
+            }
+
+            let texts: Vec<(SearchField, String)> = SearchField::ALL.iter()
+                .flat_map(|field| {
+                    let values: Vec<String> = match field {
+                        SearchField::Summary => vec![task.name().to_string()],
+                        _ => task.extra_parameters().iter()
+                            .filter(|prop| prop.name.eq_ignore_ascii_case(field.property_name()))
+                            .filter_map(|prop| prop.value.clone())
+                            .collect(),
+                    };
+                    values.into_iter().map(move |value| (*field, normalize(&value)))
+                })
+                .collect();
+            if !words.iter().all(|word| texts.iter().any(|(_, text)| text.contains(word.as_str()))) {
+                continue;
+            }
+
+            let mut fields: Vec<SearchField> = texts.iter()
+                .filter(|(_, text)| words.iter().any(|word| text.contains(word.as_str())))
+                .map(|(field, _)| *field)
+                .collect();
+            fields.dedup();
+            matches.push(SearchMatch{ calendar: calendar.clone(), item: task.url().clone(), name: task.name().to_string(), fields });
+        }
+        matches
+    }
+}
+
+/// Lowercase a text, and remove its diacritics (e.g. "Crème Brûlée" becomes "creme brulee")
+fn normalize(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Task;
+
+    fn task(name: &str, properties: &[(&str, &str)]) -> Item {
+        let cal: Url = "https://some.server/cal/".parse().unwrap();
+        let extra_parameters = properties.iter()
+            .map(|(name, value)| ical::property::Property{ name: name.to_string(), params: None, value: Some(value.to_string()) })
+            .collect();
+        let task = Task::new(name.to_string(), false, &cal);
+        Item::Task(Task::new_with_parameters(task.name().to_string(), task.uid().to_string(), task.url().clone(), task.completion_status().clone(),
+            SyncStatus::NotSynced, None, Utc::now(), task.ical_prod_id().to_string(), extra_parameters))
+    }
+
+    #[test]
+    fn test_search() {
+        let cal: Url = "https://some.server/cal/".parse().unwrap();
+        let items = vec![
+            task("Bake a Crème Brûlée", &[("DESCRIPTION", "For the party")]),
+            task("Buy eggs", &[("LOCATION", "Supermarket"), ("CATEGORIES", "Shopping,Party")]),
+            task("Call Bob", &[("DUE", "20210301T090000Z")]),
+        ];
+        let names = |matches: Vec<SearchMatch>| -> Vec<String> {
+            let mut names: Vec<String> = matches.into_iter().map(|m| m.name).collect();
+            names.sort();
+            names
+        };
+        let options = SearchOptions::new();
+
+        assert_eq!(names(options.search("creme BRULEE", &cal, &items)), vec!["Bake a Crème Brûlée"]);
+        assert_eq!(names(options.search("party", &cal, &items)), vec!["Bake a Crème Brûlée", "Buy eggs"]);
+        assert_eq!(names(options.search("party supermarket", &cal, &items)), vec!["Buy eggs"]);
+        assert!(options.search("party bob", &cal, &items).is_empty());
+        assert!(options.search("  ", &cal, &items).is_empty());
+        assert_eq!(options.search("eggs shopping", &cal, &items)[0].fields, vec![SearchField::Summary, SearchField::Categories]);
+
+        // Items that have no date never match a time window
+        let date = |value: &str| crate::ical::parse_date_value(value).unwrap();
+        let options = SearchOptions::new().since(date("20210201")).until(date("20210401"));
+        assert_eq!(names(options.search("a", &cal, &items)), vec!["Call Bob"]);
+        assert!(SearchOptions::events_only().search("bob", &cal, &items).is_empty());
+    }
+}
@@ -2,6 +2,24 @@
 
 use std::fmt::{Display, Error, Formatter};
 
+use url::Url;
+
+use crate::Item;
+use crate::metrics::{ItemTransfer, Metrics};
+use crate::time::Instant;
+use super::pending::PendingChangeKind;
+use super::sync_result::{ChangeOutcome, ChangeStatus, SyncResult};
+
+/// Log a message. With the `tracing` feature, this is a `tracing` event, so that it is attached to the spans of the sync
+macro_rules! emit {
+    ($level:ident, $text:expr) => {{
+        #[cfg(feature = "tracing")]
+        tracing::$level!("{}", $text);
+        #[cfg(not(feature = "tracing"))]
+        log::$level!("{}", $text);
+    }};
+}
+
 /// An event that happens during a sync
 #[derive(Clone, Debug)]
 pub enum SyncEvent {
@@ -50,18 +68,66 @@ pub fn feedback_channel() -> (FeedbackSender, FeedbackReceiver) {
 
 
 
+/// A detailed event that happens during a sync, see [`progress_channel`].
+///
+/// Unlike [`SyncEvent`]s (of which only the latest is kept), every single event is delivered, so that a GUI can show an accurate progress bar
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProgressEvent {
+    /// The differences of a calendar have been found, and `items_to_sync` items are about to be transferred or deleted
+    CalendarStarted{ calendar: Url, name: String, items_to_sync: usize },
+    /// An item has been downloaded from the server. `new` tells whether it did not exist locally. `bytes` is the size of its iCal representation
+    ItemDownloaded{ calendar: Url, item: Url, new: bool, bytes: usize },
+    /// An item has been uploaded to the server. `new` tells whether it did not exist on the server. `bytes` is the size of its iCal representation
+    ItemUploaded{ calendar: Url, item: Url, new: bool, bytes: usize },
+    /// An item has been deleted from the server (if `remote` is `true`), or locally
+    ItemDeleted{ calendar: Url, item: Url, remote: bool },
+    /// An item has been modified on both sides
+    Conflict{ calendar: Url, item: Url },
+    /// A local change has not been pushed to the server (e.g. because of insufficient privileges). It will be retried at the next sync
+    Skipped{ calendar: Url, item: Url },
+    /// The sync of a calendar is over
+    CalendarFinished{ calendar: Url, items_done: usize },
+    /// An error happened, possibly about a single item. The sync goes on, but it will not be reported as successful
+    Error{ calendar: Option<Url>, item: Option<Url>, message: String },
+}
+
+/// See [`progress_channel`]
+pub type ProgressSender = tokio::sync::mpsc::UnboundedSender<ProgressEvent>;
+/// See [`progress_channel`]
+pub type ProgressReceiver = tokio::sync::mpsc::UnboundedReceiver<ProgressEvent>;
+
+/// Create a channel that receives every [`ProgressEvent`] of a sync (see [`Provider::sync_with_progress`](crate::provider::Provider::sync_with_progress))
+pub fn progress_channel() -> (ProgressSender, ProgressReceiver) {
+    tokio::sync::mpsc::unbounded_channel()
+}
+
+
+
+
 /// A structure that tracks the progression and the errors that happen during a sync
 pub struct SyncProgress {
     n_errors: u32,
     feedback_channel: Option<FeedbackSender>,
+    progress_channel: Option<ProgressSender>,
+    current_calendar: Option<Url>,
     counter: usize,
+    started: Instant,
+    calendar_started: Instant,
+    result: SyncResult,
+    metrics: Metrics,
 }
 impl SyncProgress {
     pub fn new() -> Self {
-        Self { n_errors: 0, feedback_channel: None, counter: 0 }
+        Self {
+            n_errors: 0, feedback_channel: None, progress_channel: None, current_calendar: None, counter: 0,
+            started: Instant::now(), calendar_started: Instant::now(), result: SyncResult::default(), metrics: Metrics::default(),
+        }
     }
     pub fn new_with_feedback_channel(channel: FeedbackSender) -> Self {
-        Self { n_errors: 0, feedback_channel: Some(channel), counter: 0 }
+        Self { feedback_channel: Some(channel), ..Self::new() }
+    }
+    pub fn new_with_progress_channel(channel: ProgressSender) -> Self {
+        Self { progress_channel: Some(channel), ..Self::new() }
     }
 
     /// Reset the user-info counter
@@ -85,27 +151,53 @@ impl SyncProgress {
         self.n_errors == 0
     }
 
+    /// How many errors (and warnings) have happened so far
+    pub fn error_count(&self) -> u32 {
+        self.n_errors
+    }
+
     /// Log an error
     pub fn error(&mut self, text: &str) {
-        log::error!("{}", text);
+        emit!(error, text);
         self.n_errors += 1;
+        self.report_error(text);
     }
     /// Log a warning
     pub fn warn(&mut self, text: &str) {
-        log::warn!("{}", text);
+        emit!(warn, text);
+        self.n_errors += 1;
+        self.report_error(text);
+    }
+    /// Log an error about a single item
+    pub fn item_error(&mut self, item: &Url, text: &str) {
+        emit!(error, text);
         self.n_errors += 1;
+        self.report_item_error(Some(item), text);
+    }
+    /// Log a warning about a single item
+    pub fn item_warn(&mut self, item: &Url, text: &str) {
+        emit!(warn, text);
+        self.n_errors += 1;
+        self.report_item_error(Some(item), text);
+    }
+    fn report_error(&mut self, text: &str) {
+        self.report_item_error(None, text);
+    }
+    fn report_item_error(&mut self, item: Option<&Url>, text: &str) {
+        let calendar = self.current_calendar.clone();
+        self.event(ProgressEvent::Error{ calendar, item: item.cloned(), message: text.to_string() });
     }
     /// Log an info
     pub fn info(&mut self, text: &str) {
-        log::info!("{}", text);
+        emit!(info, text);
     }
     /// Log a debug message
     pub fn debug(&mut self, text: &str) {
-        log::debug!("{}", text);
+        emit!(debug, text);
     }
     /// Log a trace message
     pub fn trace(&mut self, text: &str) {
-        log::trace!("{}", text);
+        emit!(trace, text);
     }
     /// Send an event as a feedback to the listener (if any).
     pub fn feedback(&mut self, event: SyncEvent) {
@@ -115,4 +207,59 @@ impl SyncProgress {
                 sender.send(event)
             });
     }
+
+    /// Send a detailed event to the listener (if any), and take it into account in the [`SyncResult`]
+    pub fn event(&mut self, event: ProgressEvent) {
+        self.result.record(&event, self.current_calendar.is_some());
+        if let Some(sink) = self.metrics.sink() {
+            match &event {
+                ProgressEvent::ItemDownloaded{ calendar, .. } => sink.item_synced(calendar, ItemTransfer::Downloaded),
+                ProgressEvent::ItemUploaded{ calendar, .. } => sink.item_synced(calendar, ItemTransfer::Uploaded),
+                ProgressEvent::ItemDeleted{ calendar, remote: true, .. } => sink.item_synced(calendar, ItemTransfer::DeletedRemotely),
+                ProgressEvent::ItemDeleted{ calendar, remote: false, .. } => sink.item_synced(calendar, ItemTransfer::DeletedLocally),
+                ProgressEvent::Conflict{ calendar, .. } => sink.conflict(calendar),
+                ProgressEvent::Error{ calendar, .. } => sink.sync_error(calendar.as_ref()),
+                _ => (),
+            }
+        }
+        if let Some(sender) = &self.progress_channel {
+            // The receiver may have been dropped, which is not an error for the sync itself
+            let _ = sender.send(event);
+        }
+    }
+    /// Record what happened to a local change that has been replayed
+    pub fn change_outcome(&mut self, item: &Url, kind: PendingChangeKind, status: ChangeStatus) {
+        if self.current_calendar.is_some() {
+            self.result.record_change(ChangeOutcome{ item: item.clone(), kind, status });
+        }
+    }
+    /// Report the events of this sync to a [`MetricsSink`](crate::metrics::MetricsSink)
+    pub(crate) fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = metrics;
+    }
+    /// Set the calendar that is currently being synced. Errors are reported as related to this calendar
+    pub fn set_current_calendar(&mut self, calendar: Option<Url>) {
+        if self.current_calendar.is_some() {
+            self.result.finish_calendar(self.calendar_started.elapsed());
+        }
+        if let Some(url) = &calendar {
+            self.calendar_started = Instant::now();
+            self.result.start_calendar(url.clone());
+        }
+        self.current_calendar = calendar;
+    }
+    /// The calendar that is currently being synced, if any
+    pub fn current_calendar(&self) -> Option<&Url> {
+        self.current_calendar.as_ref()
+    }
+    /// The size of the iCal representation of an item
+    pub fn item_size(&self, item: &Item) -> usize {
+        crate::ical::build_from(item).map(|ics| ics.len()).unwrap_or(0)
+    }
+    /// What has happened so far
+    pub fn result(&self) -> SyncResult {
+        let mut result = self.result.clone();
+        result.duration = self.started.elapsed();
+        result
+    }
 }
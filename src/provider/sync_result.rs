@@ -0,0 +1,230 @@
+//! Summaries of syncs
+
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use url::Url;
+
+use super::pending::PendingChangeKind;
+use super::sync_progress::ProgressEvent;
+
+/// How many items have been added, updated and deleted in a source
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChangeCounts {
+    pub added: usize,
+    pub updated: usize,
+    pub deleted: usize,
+}
+
+impl ChangeCounts {
+    pub fn total(&self) -> usize {
+        self.added + self.updated + self.deleted
+    }
+}
+
+/// An error that happened during a sync
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyncError {
+    /// The item this error is about, if it is about a single item
+    pub item: Option<Url>,
+    pub message: String,
+}
+
+/// What happened to a local change that has been replayed during a sync
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChangeStatus {
+    /// The change has been applied on the server
+    Pushed,
+    /// The change has not been pushed (e.g. because of insufficient privileges). It will be retried at the next sync
+    Skipped,
+    /// The item has been changed on the server as well, see [`ConflictResolution`](crate::provider::conflict::ConflictResolution)
+    Conflict,
+    /// The change could not be pushed. It will be retried at the next sync
+    Failed(String),
+}
+
+/// The outcome of a local change, see [`CalendarSyncResult::changes`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeOutcome {
+    pub item: Url,
+    pub kind: PendingChangeKind,
+    pub status: ChangeStatus,
+}
+
+/// What happened to a calendar during a sync
+#[derive(Clone, Debug, PartialEq)]
+pub struct CalendarSyncResult {
+    pub url: Url,
+    pub name: String,
+    /// Changes applied to the local source (i.e. downloaded from the server)
+    pub local: ChangeCounts,
+    /// Changes applied to the server
+    pub remote: ChangeCounts,
+    /// Items that have been modified on both sides
+    pub conflicts: usize,
+    /// Local changes that have not been pushed (e.g. because of insufficient privileges, or a full server). They will be retried at the next sync
+    pub skipped: usize,
+    pub errors: Vec<SyncError>,
+    /// The outcomes of the local changes, in the order they have been replayed
+    pub changes: Vec<ChangeOutcome>,
+    pub bytes_downloaded: usize,
+    pub bytes_uploaded: usize,
+    pub duration: Duration,
+}
+
+/// What happened during a sync, see [`Provider::sync`](crate::provider::Provider::sync)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncResult {
+    pub calendars: Vec<CalendarSyncResult>,
+    /// The errors that are not related to a specific calendar
+    pub errors: Vec<SyncError>,
+    pub duration: Duration,
+}
+
+impl SyncResult {
+    /// Whether the sync was totally successful.
+    ///
+    /// In case errors happened, the sync might have been partially executed, but data is not corrupted. The next sync will pick up where this one failed
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty() && self.calendars.iter().all(|cal| cal.errors.is_empty())
+    }
+
+    /// All errors, including the ones related to a calendar
+    pub fn all_errors(&self) -> impl Iterator<Item = &SyncError> {
+        self.errors.iter().chain(self.calendars.iter().flat_map(|cal| cal.errors.iter()))
+    }
+
+    /// The changes applied to the local source, for all calendars
+    pub fn local_changes(&self) -> ChangeCounts {
+        self.calendars.iter().fold(ChangeCounts::default(), |acc, cal| add_counts(acc, cal.local))
+    }
+
+    /// The changes applied to the server, for all calendars
+    pub fn remote_changes(&self) -> ChangeCounts {
+        self.calendars.iter().fold(ChangeCounts::default(), |acc, cal| add_counts(acc, cal.remote))
+    }
+
+    /// The total number of conflicts, for all calendars
+    pub fn conflicts(&self) -> usize {
+        self.calendars.iter().map(|cal| cal.conflicts).sum()
+    }
+
+    /// The outcomes of the local changes, for all calendars
+    pub fn change_outcomes(&self) -> impl Iterator<Item = &ChangeOutcome> {
+        self.calendars.iter().flat_map(|cal| cal.changes.iter())
+    }
+
+    /// Start gathering the results of a calendar
+    pub(crate) fn start_calendar(&mut self, url: Url) {
+        self.calendars.push(CalendarSyncResult{
+            url,
+            name: String::new(),
+            local: ChangeCounts::default(),
+            remote: ChangeCounts::default(),
+            conflicts: 0,
+            skipped: 0,
+            errors: Vec::new(),
+            changes: Vec::new(),
+            bytes_downloaded: 0,
+            bytes_uploaded: 0,
+            duration: Duration::default(),
+        });
+    }
+
+    pub(crate) fn finish_calendar(&mut self, duration: Duration) {
+        if let Some(cal) = self.calendars.last_mut() {
+            cal.duration = duration;
+        }
+    }
+
+    pub(crate) fn record_change(&mut self, outcome: ChangeOutcome) {
+        if let Some(cal) = self.calendars.last_mut() {
+            cal.changes.push(outcome);
+        }
+    }
+
+    /// Update the results with an event. `in_calendar` tells whether the event happened while a calendar was being synced
+    pub(crate) fn record(&mut self, event: &ProgressEvent, in_calendar: bool) {
+        if let ProgressEvent::Error{ item, message, .. } = event {
+            let error = SyncError{ item: item.clone(), message: message.clone() };
+            match self.calendars.last_mut() {
+                Some(cal) if in_calendar => cal.errors.push(error),
+                _ => self.errors.push(error),
+            }
+            return;
+        }
+
+        let cal = match self.calendars.last_mut() {
+            Some(cal) if in_calendar => cal,
+            _ => return,
+        };
+        match event {
+            ProgressEvent::CalendarStarted{ name, .. } => cal.name = name.clone(),
+            ProgressEvent::ItemDownloaded{ new, bytes, .. } => {
+                if *new { cal.local.added += 1; } else { cal.local.updated += 1; }
+                cal.bytes_downloaded += bytes;
+            },
+            ProgressEvent::ItemUploaded{ new, bytes, .. } => {
+                if *new { cal.remote.added += 1; } else { cal.remote.updated += 1; }
+                cal.bytes_uploaded += bytes;
+            },
+            ProgressEvent::ItemDeleted{ remote: true, .. } => cal.remote.deleted += 1,
+            ProgressEvent::ItemDeleted{ remote: false, .. } => cal.local.deleted += 1,
+            ProgressEvent::Conflict{ .. } => cal.conflicts += 1,
+            ProgressEvent::Skipped{ .. } => cal.skipped += 1,
+            ProgressEvent::CalendarFinished{ .. } | ProgressEvent::Error{ .. } => (),
+        }
+    }
+}
+
+fn add_counts(a: ChangeCounts, b: ChangeCounts) -> ChangeCounts {
+    ChangeCounts {
+        added: a.added + b.added,
+        updated: a.updated + b.updated,
+        deleted: a.deleted + b.deleted,
+    }
+}
+
+impl Display for SyncResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let local = self.local_changes();
+        let remote = self.remote_changes();
+        write!(f, "Sync {} in {:.1?}: {} local change(s) (+{} ~{} -{}), {} remote change(s) (+{} ~{} -{}), {} conflict(s), {} error(s)",
+            if self.is_success() { "succeeded" } else { "failed" },
+            self.duration,
+            local.total(), local.added, local.updated, local.deleted,
+            remote.total(), remote.added, remote.updated, remote.deleted,
+            self.conflicts(),
+            self.all_errors().count(),
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_events() {
+        let cal: Url = "https://some.server/cal/".parse().unwrap();
+        let item = cal.join("item.ics").unwrap();
+
+        let mut result = SyncResult::default();
+        result.record(&ProgressEvent::Error{ calendar: None, item: None, message: "no calendar".to_string() }, false);
+        result.start_calendar(cal.clone());
+        result.record(&ProgressEvent::ItemDownloaded{ calendar: cal.clone(), item: item.clone(), new: true, bytes: 100 }, true);
+        result.record(&ProgressEvent::ItemUploaded{ calendar: cal.clone(), item: item.clone(), new: false, bytes: 50 }, true);
+        result.record(&ProgressEvent::ItemDeleted{ calendar: cal.clone(), item: item.clone(), remote: true }, true);
+        result.record(&ProgressEvent::Conflict{ calendar: cal.clone(), item: item.clone() }, true);
+        result.record(&ProgressEvent::Error{ calendar: Some(cal), item: Some(item.clone()), message: "oops".to_string() }, true);
+
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.calendars[0].errors[0].item, Some(item));
+        assert_eq!(result.local_changes(), ChangeCounts{ added: 1, updated: 0, deleted: 0 });
+        assert_eq!(result.remote_changes(), ChangeCounts{ added: 0, updated: 1, deleted: 1 });
+        assert_eq!(result.calendars[0].bytes_downloaded, 100);
+        assert_eq!(result.conflicts(), 1);
+        assert!(!result.is_success());
+    }
+}
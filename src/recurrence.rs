@@ -0,0 +1,187 @@
+//! Expansion of recurrence rules (iCal `RRULE` properties), e.g. to know when the next instance of a recurring task is due
+//!
+//! Only the most common rules are supported: their frequency, `INTERVAL`, `COUNT` and `UNTIL`.
+//! Their `BYxxx` parts (e.g. `BYDAY=MO,WE`) are not evaluated, so that such rules only yield the instances that fall on the same weekday (or day of the month) as the first one.
+
+use std::convert::TryFrom;
+use std::error::Error;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use ical::property::Property;
+
+/// The `FREQ` of a recurrence rule
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frequency {
+    Secondly,
+    Minutely,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A recurrence rule (an iCal `RRULE`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub frequency: Frequency,
+    /// Every how many periods the item recurs (e.g. every 2 weeks)
+    pub interval: u32,
+    /// How many instances there are (including the first one), if the rule is bounded this way
+    pub count: Option<u32>,
+    /// The last date an instance can start at, if the rule is bounded this way
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl RecurrenceRule {
+    /// Parse the value of a `RRULE` property, e.g. `FREQ=WEEKLY;INTERVAL=2;COUNT=10`
+    pub fn parse(rrule: &str) -> Result<Self, Box<dyn Error>> {
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut count = None;
+        let mut until = None;
+        for part in rrule.split(';') {
+            let (name, value) = part.split_once('=').ok_or_else(|| format!("Invalid recurrence rule {:?}", rrule))?;
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => frequency = Some(match value.to_ascii_uppercase().as_str() {
+                    "SECONDLY" => Frequency::Secondly,
+                    "MINUTELY" => Frequency::Minutely,
+                    "HOURLY" => Frequency::Hourly,
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return Err(format!("Unknown recurrence frequency {:?}", value).into()),
+                }),
+                "INTERVAL" => interval = value.parse().ok().filter(|interval| *interval > 0).ok_or_else(|| format!("Invalid recurrence interval {:?}", value))?,
+                "COUNT" => count = Some(value.parse().map_err(|_| format!("Invalid recurrence count {:?}", value))?),
+                "UNTIL" => until = Some(crate::ical::parse_date_value(value).ok_or_else(|| format!("Invalid recurrence end {:?}", value))?),
+                _ => log::debug!("Recurrence rule part {} is not supported, it is ignored", part),
+            }
+        }
+        let frequency = frequency.ok_or_else(|| format!("Recurrence rule {:?} has no frequency", rrule))?;
+        Ok(Self{ frequency, interval, count, until })
+    }
+
+    /// The start dates of the instances of an item that starts at `start`, in chronological order (the first one being `start` itself).
+    ///
+    /// This iterator is endless for rules that have neither a `COUNT` nor an `UNTIL`
+    pub fn instances(&self, start: DateTime<Utc>) -> Instances {
+        Instances{ rule: self.clone(), start, period: 0, yielded: 0 }
+    }
+
+    fn nth_period(&self, start: DateTime<Utc>, n: i64) -> Option<DateTime<Utc>> {
+        let months = match self.frequency {
+            Frequency::Secondly => return start.checked_add_signed(Duration::seconds(n)),
+            Frequency::Minutely => return start.checked_add_signed(Duration::minutes(n)),
+            Frequency::Hourly => return start.checked_add_signed(Duration::hours(n)),
+            Frequency::Daily => return start.checked_add_signed(Duration::days(n)),
+            Frequency::Weekly => return start.checked_add_signed(Duration::weeks(n)),
+            Frequency::Monthly => n,
+            Frequency::Yearly => n * 12,
+        };
+        let month_index = i64::from(start.year()) * 12 + i64::from(start.month0()) + months;
+        let year = i32::try_from(month_index.div_euclid(12)).ok()?;
+        let month = month_index.rem_euclid(12) as u32 + 1;
+        let date = NaiveDate::from_ymd_opt(year, month, start.day())?;
+        Some(Utc.from_utc_datetime(&date.and_time(start.time())))
+    }
+}
+
+/// See [`RecurrenceRule::instances`]
+#[derive(Clone, Debug)]
+pub struct Instances {
+    rule: RecurrenceRule,
+    start: DateTime<Utc>,
+    period: i64,
+    yielded: u32,
+}
+
+impl Iterator for Instances {
+    type Item = DateTime<Utc>;
+
+    fn next(&mut self) -> Option<DateTime<Utc>> {
+        loop {
+            if self.rule.count.is_some_and(|count| self.yielded >= count) {
+                return None;
+            }
+            let instance = self.rule.nth_period(self.start, self.period * i64::from(self.rule.interval));
+            self.period += 1;
+            match instance {
+                // Monthly and yearly rules skip the periods that have no such day (e.g. February 30th), but there cannot be that many of them
+                None if self.period > i64::from(u16::MAX) => return None,
+                None => continue,
+                Some(instance) if self.rule.until.is_some_and(|until| instance > until) => return None,
+                Some(instance) => {
+                    self.yielded += 1;
+                    return Some(instance);
+                },
+            }
+        }
+    }
+}
+
+/// When the instances of a recurring item start, given its `DTSTART` (or the date the recurrence is relative to) and its properties (its `RRULE` and `EXDATE`s).
+///
+/// Items that have no (valid) `RRULE` have a single instance
+pub fn instances(start: DateTime<Utc>, properties: &[Property]) -> Box<dyn Iterator<Item = DateTime<Utc>> + Send> {
+    let value = |name: &str| properties.iter().find(|prop| prop.name.eq_ignore_ascii_case(name)).and_then(|prop| prop.value.as_deref());
+    let rule = match value("RRULE").map(RecurrenceRule::parse) {
+        None => return Box::new(std::iter::once(start)),
+        Some(Err(err)) => {
+            log::warn!("Unable to expand a recurrence: {}", err);
+            return Box::new(std::iter::once(start));
+        },
+        Some(Ok(rule)) => rule,
+    };
+    let excluded: Vec<DateTime<Utc>> = properties.iter()
+        .filter(|prop| prop.name.eq_ignore_ascii_case("EXDATE"))
+        .filter_map(|prop| prop.value.as_deref())
+        .flat_map(|value| value.split(','))
+        .filter_map(crate::ical::parse_date_value)
+        .collect();
+    Box::new(rule.instances(start).filter(move |instance| !excluded.contains(instance)))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_recurrence_rules() {
+        let rule = RecurrenceRule::parse("FREQ=WEEKLY;INTERVAL=2;COUNT=3").unwrap();
+        assert_eq!(rule, RecurrenceRule{ frequency: Frequency::Weekly, interval: 2, count: Some(3), until: None });
+        let instances: Vec<_> = rule.instances(date("2021-03-01T09:00:00Z")).collect();
+        assert_eq!(instances, vec![date("2021-03-01T09:00:00Z"), date("2021-03-15T09:00:00Z"), date("2021-03-29T09:00:00Z")]);
+
+        // Months that have no 31st day are skipped
+        let rule = RecurrenceRule::parse("FREQ=MONTHLY;UNTIL=20210601T000000Z").unwrap();
+        let instances: Vec<_> = rule.instances(date("2021-01-31T10:00:00Z")).collect();
+        assert_eq!(instances, vec![date("2021-01-31T10:00:00Z"), date("2021-03-31T10:00:00Z"), date("2021-05-31T10:00:00Z")]);
+
+        let rule = RecurrenceRule::parse("FREQ=YEARLY").unwrap();
+        assert_eq!(rule.instances(date("2020-02-29T00:00:00Z")).nth(1), Some(date("2024-02-29T00:00:00Z")));
+
+        assert!(RecurrenceRule::parse("INTERVAL=2").is_err());
+        assert!(RecurrenceRule::parse("FREQ=DAILY;INTERVAL=0").is_err());
+    }
+
+    #[test]
+    fn test_instances_with_exdates() {
+        let properties = vec![
+            Property{ name: "RRULE".to_string(), params: None, value: Some("FREQ=DAILY;COUNT=4".to_string()) },
+            Property{ name: "EXDATE".to_string(), params: None, value: Some("20210302T090000Z,20210303T090000Z".to_string()) },
+        ];
+        let expanded: Vec<_> = instances(date("2021-03-01T09:00:00Z"), &properties).collect();
+        assert_eq!(expanded, vec![date("2021-03-01T09:00:00Z"), date("2021-03-04T09:00:00Z")]);
+
+        let expanded: Vec<_> = instances(date("2021-03-01T09:00:00Z"), &[]).collect();
+        assert_eq!(expanded, vec![date("2021-03-01T09:00:00Z")]);
+    }
+}
@@ -1,21 +1,67 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use reqwest::{Method, RequestBuilder, Response};
 use url::Url;
 
-/// Just a wrapper around a URL and credentials
+use crate::client::transport::Transport;
+
+/// An OAuth2 access token, that is sent as a `Bearer` token by the resources that share it.
+///
+/// This crate does not implement OAuth2 flows: apps obtain tokens themselves, and replace them (see [`Self::set`]) when they expire
+#[derive(Clone, Default)]
+pub struct AccessToken(Arc<Mutex<String>>);
+
+impl AccessToken {
+    pub fn new(token: String) -> Self {
+        Self(Arc::new(Mutex::new(token)))
+    }
+
+    /// Replace the token, e.g. after it has been refreshed. Every resource that shares this token uses the new one
+    pub fn set(&self, token: String) {
+        *self.0.lock().unwrap() = token;
+    }
+
+    fn get(&self) -> String {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl std::fmt::Debug for AccessToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AccessToken(<hidden>)")
+    }
+}
+
+/// Just a wrapper around a URL and credentials (and the HTTP transport that can reach this URL)
 #[derive(Clone, Debug)]
 pub struct Resource {
     url: Url,
     username: String,
     password: String,
+    access_token: Option<AccessToken>,
+    transport: Transport,
 }
 
 impl Resource {
     pub fn new(url: Url, username: String, password: String) -> Self {
-        Self { url, username, password }
+        Self::new_with_transport(url, username, password, Transport::default())
+    }
+
+    pub(crate) fn new_with_transport(url: Url, username: String, password: String, transport: Transport) -> Self {
+        Self { url, username, password, access_token: None, transport }
+    }
+
+    /// A resource that authenticates with an OAuth2 access token, rather than with a username and a password
+    #[cfg_attr(not(any(feature = "google", feature = "graph", feature = "jmap")), allow(dead_code))]
+    pub(crate) fn new_with_access_token(url: Url, access_token: AccessToken, transport: Transport) -> Self {
+        Self { url, username: String::new(), password: String::new(), access_token: Some(access_token), transport }
     }
 
     pub fn url(&self) -> &Url { &self.url }
     pub fn username(&self) -> &String { &self.username }
     pub fn password(&self) -> &String { &self.password }
+    pub fn access_token(&self) -> Option<&AccessToken> { self.access_token.as_ref() }
 
     /// Build a new Resource by keeping the same credentials, scheme and server from `base` but changing the path part
     pub fn combine(&self, new_path: &str) -> Resource {
@@ -23,4 +69,23 @@ impl Resource {
         built.url.set_path(&new_path);
         built
     }
+
+    /// Start building an authenticated request to `url`, using the same transport and credentials as this resource
+    ///
+    /// Resources without credentials (e.g. public feeds) send anonymous requests
+    pub(crate) fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let request = self.transport.request(method, url);
+        if let Some(token) = &self.access_token {
+            return request.bearer_auth(token.get());
+        }
+        match self.username.is_empty() && self.password.is_empty() {
+            true => request,
+            false => request.basic_auth(self.username(), Some(self.password())),
+        }
+    }
+
+    /// Send a request built by [`Self::request`], retrying it in case of transient errors
+    pub(crate) async fn send(&self, request: RequestBuilder) -> Result<Response, Box<dyn Error>> {
+        self.transport.send(request).await
+    }
 }
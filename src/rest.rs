@@ -0,0 +1,37 @@
+//! Helpers for the sources that sync with REST APIs rather than with CalDAV
+
+use reqwest::{Method, StatusCode};
+use reqwest::header::{CONTENT_TYPE, IF_MATCH};
+use url::Url;
+
+use crate::resource::Resource;
+use crate::calendar::remote_calendar::SendError;
+use crate::item::VersionTag;
+use crate::error::KFError;
+
+/// Send a request to a REST API, with a JSON body (if any), and return the body of its reply
+///
+/// A `412 Precondition Failed` reply (to a request that is sent with an `If-Match` version tag) is a [`KFError::Conflict`]
+pub(crate) async fn send_json(resource: &Resource, method: Method, url: Url, body: Option<&serde_json::Value>, if_match: Option<&VersionTag>) -> Result<String, SendError> {
+    send_json_with_headers(resource, method, url, body, if_match, &[]).await
+}
+
+/// Like [`send_json`], with extra headers (e.g. the `Prefer` header of Microsoft Graph)
+pub(crate) async fn send_json_with_headers(resource: &Resource, method: Method, url: Url, body: Option<&serde_json::Value>, if_match: Option<&VersionTag>, headers: &[(&str, &str)]) -> Result<String, SendError> {
+    let mut request = resource.request(method, url.clone());
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    if let Some(body) = body {
+        request = request.header(CONTENT_TYPE, "application/json").body(body.to_string());
+    }
+    if let Some(tag) = if_match {
+        request = request.header(IF_MATCH, tag.as_str());
+    }
+    let response = resource.send(request).await.map_err(crate::calendar::remote_calendar::sendable)?;
+    match response.status() {
+        StatusCode::PRECONDITION_FAILED => Err(Box::new(KFError::Conflict{ url })),
+        status if !status.is_success() => Err(Box::new(KFError::from_status(status, url))),
+        _ => Ok(response.text().await?),
+    }
+}
@@ -0,0 +1,659 @@
+//! Scheduling messages (iTIP, see [RFC 5546](https://datatracker.ietf.org/doc/html/rfc5546)), e.g. the meeting invitations that are attached to emails as `.ics` files
+//!
+//! Mail clients can parse such attachments with [`SchedulingMessage::parse`], and get structured meeting requests back.
+//! Attendees can reply to them ([`Meeting::respond`]) or propose another time ([`Meeting::counter`]), and organizers can accept or decline such proposals ([`Meeting::check_counter`]).
+
+
+use chrono::{DateTime, Utc};
+use ical::property::Property;
+
+use crate::item::{FieldChange, ItemField};
+use crate::error::KFError;
+
+/// The `METHOD` of an iCal file, that tells what a scheduling message is for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Method {
+    Publish,
+    Request,
+    Reply,
+    Add,
+    Cancel,
+    Refresh,
+    Counter,
+    DeclineCounter,
+    /// Any other (e.g. experimental) method
+    Other(String),
+}
+
+impl Method {
+    pub(crate) fn from_ical(value: &str) -> Self {
+        match value.to_ascii_uppercase().as_str() {
+            "PUBLISH" => Self::Publish,
+            "REQUEST" => Self::Request,
+            "REPLY" => Self::Reply,
+            "ADD" => Self::Add,
+            "CANCEL" => Self::Cancel,
+            "REFRESH" => Self::Refresh,
+            "COUNTER" => Self::Counter,
+            "DECLINECOUNTER" => Self::DeclineCounter,
+            _ => Self::Other(value.to_string()),
+        }
+    }
+
+    /// The value of the iCal `METHOD` property
+    pub fn as_ical(&self) -> &str {
+        match self {
+            Self::Publish => "PUBLISH",
+            Self::Request => "REQUEST",
+            Self::Reply => "REPLY",
+            Self::Add => "ADD",
+            Self::Cancel => "CANCEL",
+            Self::Refresh => "REFRESH",
+            Self::Counter => "COUNTER",
+            Self::DeclineCounter => "DECLINECOUNTER",
+            Self::Other(method) => method,
+        }
+    }
+}
+
+/// Whether an attendee takes part in a meeting (its `PARTSTAT` parameter)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParticipationStatus {
+    NeedsAction,
+    Accepted,
+    Declined,
+    Tentative,
+    Delegated,
+    /// Any other (e.g. experimental) value
+    Other(String),
+}
+
+impl ParticipationStatus {
+    pub(crate) fn from_ical(value: &str) -> Self {
+        match value.to_ascii_uppercase().as_str() {
+            "NEEDS-ACTION" => Self::NeedsAction,
+            "ACCEPTED" => Self::Accepted,
+            "DECLINED" => Self::Declined,
+            "TENTATIVE" => Self::Tentative,
+            "DELEGATED" => Self::Delegated,
+            _ => Self::Other(value.to_string()),
+        }
+    }
+
+    /// The value of the iCal `PARTSTAT` parameter
+    pub fn as_ical(&self) -> &str {
+        match self {
+            Self::NeedsAction => "NEEDS-ACTION",
+            Self::Accepted => "ACCEPTED",
+            Self::Declined => "DECLINED",
+            Self::Tentative => "TENTATIVE",
+            Self::Delegated => "DELEGATED",
+            Self::Other(status) => status,
+        }
+    }
+}
+
+impl Default for ParticipationStatus {
+    /// RFC5545 states the default value is `NEEDS-ACTION`
+    fn default() -> Self {
+        Self::NeedsAction
+    }
+}
+
+/// The organizer or an attendee of a meeting (an `ORGANIZER` or `ATTENDEE` property)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Participant {
+    /// The calendar address of the participant, usually a `mailto:` URI
+    pub address: String,
+    /// The name of the participant (its `CN`)
+    pub name: Option<String>,
+    /// e.g. `REQ-PARTICIPANT` or `CHAIR` (its `ROLE`)
+    pub role: Option<String>,
+    pub participation_status: ParticipationStatus,
+    /// Whether the organizer expects a reply from this participant (its `RSVP`)
+    pub rsvp: bool,
+}
+
+impl Participant {
+    pub(crate) fn from_property(prop: &Property) -> Option<Self> {
+        let param = |name: &str| prop.params.iter().flatten()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .and_then(|(_, values)| values.first());
+        Some(Self{
+            address: prop.value.clone()?,
+            name: param("CN").cloned(),
+            role: param("ROLE").cloned(),
+            participation_status: param("PARTSTAT").map(|status| ParticipationStatus::from_ical(status.as_str())).unwrap_or_default(),
+            rsvp: param("RSVP").is_some_and(|rsvp| rsvp.eq_ignore_ascii_case("TRUE")),
+        })
+    }
+
+    /// The email address of the participant, if its address is a `mailto:` URI
+    pub fn email(&self) -> Option<&str> {
+        self.address.get(..7)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("mailto:"))
+            .map(|_| &self.address[7..])
+    }
+
+    /// Whether this participant has a given calendar address. Addresses are compared case-insensitively, and `mailto:` can be omitted
+    pub fn has_address(&self, address: &str) -> bool {
+        let address = address.get(..7)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("mailto:"))
+            .map_or(address, |_| &address[7..]);
+        self.email().unwrap_or(&self.address).eq_ignore_ascii_case(address)
+    }
+}
+
+/// A meeting (a `VEVENT`), as described by a scheduling message
+#[derive(Clone, Debug)]
+pub struct Meeting {
+    pub uid: String,
+    /// The revision of the meeting: the organizer increments it every time they reschedule the meeting
+    pub sequence: u32,
+    /// For overridden (or cancelled) instances of recurring meetings, the start of the instance this is about
+    pub recurrence_id: Option<DateTime<Utc>>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    /// When the meeting starts. This may be missing from cancellations
+    pub start: Option<DateTime<Utc>>,
+    /// When the meeting ends, if it has an end or a duration
+    pub end: Option<DateTime<Utc>>,
+    /// Whether this is an all-day meeting (i.e. `start` and `end` have no time part)
+    pub all_day: bool,
+    pub organizer: Option<Participant>,
+    pub attendees: Vec<Participant>,
+    /// The instances of a recurring meeting that differ from the others (e.g. that have been moved), that are described in the same message
+    pub overridden_instances: Vec<Meeting>,
+    /// Every property of the `VEVENT`, as it has been parsed
+    pub properties: Vec<Property>,
+}
+
+impl PartialEq for Meeting {
+    fn eq(&self, other: &Self) -> bool {
+        self.uid == other.uid
+            && self.sequence == other.sequence
+            && self.recurrence_id == other.recurrence_id
+            && self.overridden_instances == other.overridden_instances
+            && self.properties.len() == other.properties.len()
+            && self.properties.iter().zip(&other.properties).all(|(a, b)| a.name.eq_ignore_ascii_case(&b.name) && a.params == b.params && a.value == b.value)
+    }
+}
+
+impl Meeting {
+    /// The attendee that has a given calendar address (e.g. the user this message has been sent to), if any (see [`Participant::has_address`])
+    pub fn attendee(&self, address: &str) -> Option<&Participant> {
+        self.attendees.iter().find(|attendee| attendee.has_address(address))
+    }
+
+    /// The reply of the attendee that has a given calendar address, to tell the organizer whether they take part in this meeting (or in this instance of it, for overridden instances).
+    ///
+    /// The reply only mentions this attendee, as iTIP requires. This is what "Accept", "Tentative" and "Decline" buttons send.
+    /// Note that [`Event`](crate::Event)s cannot be replied to, since they are not supported yet: replies are built from the meeting of the invitation instead
+    pub fn respond(&self, attendee: &str, status: ParticipationStatus) -> Result<SchedulingReply, KFError> {
+        if !matches!(status, ParticipationStatus::Accepted | ParticipationStatus::Declined | ParticipationStatus::Tentative) {
+            return Err(format!("Invalid reply {}, attendees can only accept, decline or tentatively accept a meeting", status.as_ical()).into());
+        }
+        let organizer = self.organizer.as_ref().ok_or_else(|| format!("Meeting {} has no organizer to reply to", self.uid))?;
+
+        const ECHOED: [&str; 8] = ["UID", "SEQUENCE", "RECURRENCE-ID", "ORGANIZER", "SUMMARY", "DTSTART", "DTEND", "DURATION"];
+        let mut properties: Vec<Property> = self.properties.iter()
+            .filter(|prop| ECHOED.iter().any(|name| prop.name.eq_ignore_ascii_case(name)))
+            .cloned()
+            .collect();
+        let mut replying = self.attendee_property(attendee)?;
+        let mut params: Vec<(String, Vec<String>)> = replying.params.take().unwrap_or_default().into_iter()
+            .filter(|(param, _)| !param.eq_ignore_ascii_case("PARTSTAT") && !param.eq_ignore_ascii_case("RSVP"))
+            .collect();
+        params.push(("PARTSTAT".to_string(), vec![status.as_ical().to_string()]));
+        replying.params = Some(params);
+        let address = replying.value.clone().unwrap_or_default();
+        properties.push(replying);
+
+        Ok(SchedulingReply {
+            attendee: address,
+            organizer: organizer.address.clone(),
+            participation_status: status,
+            ics: crate::ical::build_scheduling_message(&Method::Reply, vec![properties]),
+        })
+    }
+
+    /// The counter-proposal of the attendee that has a given calendar address: a new time for this meeting (or for this instance of it, for overridden instances), with an optional message to the organizer.
+    ///
+    /// The proposal describes the whole meeting with its new time, but only mentions this attendee. It keeps the `SEQUENCE` of the meeting, so that the organizer can tell whether it is about its latest version
+    pub fn counter(&self, attendee: &str, start: DateTime<Utc>, end: Option<DateTime<Utc>>, comment: Option<&str>) -> Result<OutgoingMessage, KFError> {
+        let organizer = self.organizer.as_ref().ok_or_else(|| format!("Meeting {} has no organizer to send a proposal to", self.uid))?;
+        let proposer = self.attendee_property(attendee)?;
+
+        const REPLACED: [&str; 6] = ["DTSTART", "DTEND", "DURATION", "ATTENDEE", "COMMENT", "DTSTAMP"];
+        let mut properties: Vec<Property> = self.properties.iter()
+            .filter(|prop| !REPLACED.iter().any(|name| prop.name.eq_ignore_ascii_case(name)))
+            .cloned()
+            .collect();
+        properties.push(date_property("DTSTART", &start, self.all_day));
+        properties.extend(end.map(|end| date_property("DTEND", &end, self.all_day)));
+        properties.push(proposer);
+        properties.extend(comment.map(|comment| Property{ name: "COMMENT".to_string(), params: None, value: Some(comment.to_string()) }));
+
+        Ok(OutgoingMessage {
+            method: Method::Counter,
+            recipients: vec![organizer.address.clone()],
+            ics: crate::ical::build_scheduling_message(&Method::Counter, vec![properties]),
+        })
+    }
+
+    /// Check a counter-proposal an attendee has sent about this meeting (the organizer's version of it), and tell what it would change.
+    ///
+    /// Proposals about an instance of a recurring meeting are compared to this instance. Proposals made for an older version of this meeting (whose `SEQUENCE` is lower) are flagged as [`CounterProposal::outdated`]
+    pub fn check_counter(&self, counter: &Meeting) -> Result<CounterProposal, KFError> {
+        let original = self.countered(&counter.uid, counter.recurrence_id)?;
+        if counter.sequence > original.sequence {
+            return Err(format!("The proposal is about version {} of meeting {}, which has not been sent yet (its latest version is {})", counter.sequence, self.uid, original.sequence).into());
+        }
+        let proposer = counter.attendees.iter()
+            .find(|attendee| original.attendee(&attendee.address).is_some())
+            .ok_or_else(|| format!("The proposal about meeting {} has not been made by one of its attendees", self.uid))?;
+        let start = counter.start.ok_or_else(|| format!("The proposal about meeting {} has no start", self.uid))?;
+
+        let value = |meeting: &Meeting, name: &str| meeting.properties.iter()
+            .find(|prop| prop.name.eq_ignore_ascii_case(name))
+            .and_then(|prop| prop.value.clone());
+        let mut changes = Vec::new();
+        FieldChange::push_if_different(&mut changes, ItemField::Name, value(original, "SUMMARY"), value(counter, "SUMMARY"));
+        // Dates are compared once parsed, since they may be written differently (e.g. a proposal is in UTC, while the meeting has a TZID, or a DURATION instead of a DTEND).
+        // A proposal without an end keeps the duration of the meeting (see `accept_counter`)
+        let date_value = |date: Option<DateTime<Utc>>| date.and_then(|date| date_property("", &date, original.all_day).value);
+        let end = counter.end.or_else(|| original.end.zip(original.start).map(|(end, original_start)| start + (end - original_start)));
+        FieldChange::push_if_different(&mut changes, ItemField::Property("DTSTART".to_string()), date_value(original.start), date_value(Some(start)));
+        FieldChange::push_if_different(&mut changes, ItemField::Property("DTEND".to_string()), date_value(original.end), date_value(end));
+        for name in ["LOCATION", "DESCRIPTION"] {
+            FieldChange::push_if_different(&mut changes, ItemField::Property(name.to_string()), value(original, name), value(counter, name));
+        }
+
+        Ok(CounterProposal {
+            uid: self.uid.clone(),
+            recurrence_id: counter.recurrence_id,
+            proposer: proposer.clone(),
+            start,
+            end: counter.end,
+            comment: value(counter, "COMMENT"),
+            outdated: counter.sequence < original.sequence,
+            changes,
+        })
+    }
+
+    /// Reschedule this meeting (the organizer's version of it) as an attendee has proposed, and tell every attendee about it.
+    ///
+    /// This is a new version of the meeting (its `SEQUENCE` is incremented), or of the instance the proposal is about, and attendees are asked to reply again
+    pub fn accept_counter(&self, proposal: &CounterProposal) -> Result<OutgoingMessage, KFError> {
+        let original = self.countered(&proposal.uid, proposal.recurrence_id)?;
+        const REPLACED: [&str; 6] = ["DTSTART", "DTEND", "DURATION", "SEQUENCE", "DTSTAMP", "RECURRENCE-ID"];
+        const RECURRENCE: [&str; 3] = ["RRULE", "RDATE", "EXDATE"];
+        let mut properties = Vec::new();
+        for prop in &original.properties {
+            if REPLACED.iter().any(|name| prop.name.eq_ignore_ascii_case(name)) {
+                continue;
+            }
+            // An instance that is rescheduled on its own does not recur
+            if proposal.recurrence_id.is_some() && RECURRENCE.iter().any(|name| prop.name.eq_ignore_ascii_case(name)) {
+                continue;
+            }
+            let mut prop = prop.clone();
+            if prop.name.eq_ignore_ascii_case("ATTENDEE") {
+                let mut params: Vec<(String, Vec<String>)> = prop.params.take().unwrap_or_default().into_iter()
+                    .filter(|(param, _)| !param.eq_ignore_ascii_case("PARTSTAT") && !param.eq_ignore_ascii_case("RSVP"))
+                    .collect();
+                params.push(("PARTSTAT".to_string(), vec!["NEEDS-ACTION".to_string()]));
+                params.push(("RSVP".to_string(), vec!["TRUE".to_string()]));
+                prop.params = Some(params);
+            }
+            properties.push(prop);
+        }
+        properties.extend(proposal.recurrence_id.map(|instance| date_property("RECURRENCE-ID", &instance, original.all_day)));
+        properties.push(Property{ name: "SEQUENCE".to_string(), params: None, value: Some((original.sequence + 1).to_string()) });
+        properties.push(date_property("DTSTART", &proposal.start, original.all_day));
+        match (proposal.end, original.start, original.end) {
+            (Some(end), _, _) => properties.push(date_property("DTEND", &end, original.all_day)),
+            // The meeting keeps its length
+            (None, Some(start), Some(end)) => properties.push(date_property("DTEND", &(proposal.start + (end - start)), original.all_day)),
+            (None, _, _) => (),
+        }
+
+        let organizer = original.organizer.as_ref().map(|organizer| organizer.address.as_str());
+        Ok(OutgoingMessage {
+            method: Method::Request,
+            recipients: original.attendees.iter()
+                .map(|attendee| attendee.address.clone())
+                .filter(|address| Some(address.as_str()) != organizer)
+                .collect(),
+            ics: crate::ical::build_scheduling_message(&Method::Request, vec![properties]),
+        })
+    }
+
+    /// Tell the attendee that has made a proposal that this meeting (the organizer's version of it) is not changed, with an optional message
+    pub fn decline_counter(&self, proposal: &CounterProposal, comment: Option<&str>) -> Result<OutgoingMessage, KFError> {
+        let original = self.countered(&proposal.uid, proposal.recurrence_id)?;
+        const ECHOED: [&str; 3] = ["UID", "SEQUENCE", "ORGANIZER"];
+        let mut properties: Vec<Property> = original.properties.iter()
+            .filter(|prop| ECHOED.iter().any(|name| prop.name.eq_ignore_ascii_case(name)))
+            .cloned()
+            .collect();
+        properties.extend(proposal.recurrence_id.map(|instance| date_property("RECURRENCE-ID", &instance, original.all_day)));
+        properties.push(original.attendee_property(&proposal.proposer.address)?);
+        properties.extend(comment.map(|comment| Property{ name: "COMMENT".to_string(), params: None, value: Some(comment.to_string()) }));
+
+        Ok(OutgoingMessage {
+            method: Method::DeclineCounter,
+            recipients: vec![proposal.proposer.address.clone()],
+            ics: crate::ical::build_scheduling_message(&Method::DeclineCounter, vec![properties]),
+        })
+    }
+
+    /// The meeting (or the overridden instance of it) a proposal is about
+    fn countered(&self, uid: &str, recurrence_id: Option<DateTime<Utc>>) -> Result<&Meeting, KFError> {
+        if uid != self.uid {
+            return Err(format!("The proposal is about meeting {}, not {}", uid, self.uid).into());
+        }
+        Ok(match recurrence_id {
+            Some(instance) if self.recurrence_id != Some(instance) => self.overridden_instances.iter()
+                .find(|overridden| overridden.recurrence_id == Some(instance))
+                .unwrap_or(self),
+            _ => self,
+        })
+    }
+
+    /// The `ATTENDEE` property of an attendee
+    fn attendee_property(&self, address: &str) -> Result<Property, KFError> {
+        self.properties.iter()
+            .filter(|prop| prop.name.eq_ignore_ascii_case("ATTENDEE"))
+            .find(|prop| Participant::from_property(prop).is_some_and(|participant| participant.has_address(address)))
+            .cloned()
+            .ok_or_else(|| format!("{} is not invited to meeting {}", address, self.uid).into())
+    }
+}
+
+/// A `DTSTART`-like property
+fn date_property(name: &str, date: &DateTime<Utc>, all_day: bool) -> Property {
+    match all_day {
+        true => Property{ name: name.to_string(), params: Some(vec![("VALUE".to_string(), vec!["DATE".to_string()])]), value: Some(date.format("%Y%m%d").to_string()) },
+        false => Property{ name: name.to_string(), params: None, value: Some(date.format("%Y%m%dT%H%M%SZ").to_string()) },
+    }
+}
+
+/// Changes an attendee proposes to a meeting (see [`Meeting::check_counter`]), that the organizer can accept ([`Meeting::accept_counter`]) or decline ([`Meeting::decline_counter`])
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CounterProposal {
+    /// The UID of the meeting
+    pub uid: String,
+    /// The instance of a recurring meeting the proposal is about, if it is not about the whole meeting
+    pub recurrence_id: Option<DateTime<Utc>>,
+    /// The attendee that has made the proposal
+    pub proposer: Participant,
+    /// The proposed start
+    pub start: DateTime<Utc>,
+    /// The proposed end, if the proposal tells it
+    pub end: Option<DateTime<Utc>>,
+    /// The message of the attendee to the organizer (its `COMMENT`)
+    pub comment: Option<String>,
+    /// Whether the proposal has been made for an older version of the meeting (e.g. it has been rescheduled since). Such proposals are usually declined
+    pub outdated: bool,
+    /// What the proposal would change in the meeting
+    pub changes: Vec<FieldChange>,
+}
+
+/// A scheduling message to send, e.g. a counter-proposal (see [`Meeting::counter`])
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutgoingMessage {
+    pub method: Method,
+    /// The calendar addresses of the participants this message should be sent to
+    pub recipients: Vec<String>,
+    /// The message itself, as an iCal file
+    pub ics: String,
+}
+
+impl OutgoingMessage {
+    /// The MIME type of [`Self::ics`], e.g. for an email attachment, or for the `Content-Type` of a `POST` to a CalDAV scheduling outbox
+    pub fn content_type(&self) -> String {
+        format!("text/calendar; charset=utf-8; method={}", self.method.as_ical())
+    }
+}
+
+/// The reply of an attendee to an invitation (a `REPLY`), see [`Meeting::respond`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchedulingReply {
+    /// The calendar address of the attendee that replies
+    pub attendee: String,
+    /// The calendar address of the organizer, that the reply should be sent to
+    pub organizer: String,
+    pub participation_status: ParticipationStatus,
+    /// The reply itself, as an iCal file
+    pub ics: String,
+}
+
+impl SchedulingReply {
+    /// The MIME type of [`Self::ics`], e.g. for an email attachment, or for the `Content-Type` of a `POST` to a CalDAV scheduling outbox
+    pub const CONTENT_TYPE: &'static str = "text/calendar; charset=utf-8; method=REPLY";
+}
+
+/// What a scheduling message is about
+#[derive(Clone, Debug, PartialEq)]
+pub enum SchedulingMessage {
+    /// An invitation to a meeting (a `REQUEST` whose `SEQUENCE` is 0)
+    Invitation(Meeting),
+    /// A new version of a meeting, e.g. because it has been rescheduled (a `REQUEST` whose `SEQUENCE` has been incremented).
+    /// Recipients that do not know about this meeting yet should handle it like an invitation
+    Update(Meeting),
+    /// The meeting, or the instance of it given by its `recurrence_id`, has been cancelled (a `CANCEL`)
+    Cancellation(Meeting),
+    /// An attendee proposes changes to the meeting, usually a new time (a `COUNTER`). See [`Meeting::check_counter`]
+    Counter(Meeting),
+    /// The organizer has declined the changes an attendee has proposed (a `DECLINECOUNTER`)
+    DeclineCounter(Meeting),
+    /// A message with another method, that is not supported
+    Unsupported(Method),
+}
+
+impl SchedulingMessage {
+    /// Parse an iCal file that has a `METHOD` (e.g. an `.ics` attachment of an email).
+    ///
+    /// A file can describe several meetings, hence several messages. Overridden instances of a recurring meeting are part of its message (see [`Meeting::overridden_instances`]),
+    /// unless the file only describes such instances (e.g. a cancellation of some instances), in which case each of them has its own message
+    pub fn parse(ics: &str) -> Result<Vec<Self>, KFError> {
+        crate::ical::parse_scheduling_messages(ics)
+    }
+
+    /// The meeting this message is about, if its method is supported
+    pub fn meeting(&self) -> Option<&Meeting> {
+        match self {
+            Self::Invitation(meeting) | Self::Update(meeting) | Self::Cancellation(meeting)
+                | Self::Counter(meeting) | Self::DeclineCounter(meeting) => Some(meeting),
+            Self::Unsupported(_) => None,
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    const INVITATION: &str = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Example Corp.//CalDAV Client//EN\r\nMETHOD:REQUEST\r\n\
+        BEGIN:VEVENT\r\nUID:standup@example.com\r\nDTSTAMP:20211102T080000Z\r\nSEQUENCE:0\r\nSUMMARY:Stand-up\r\nLOCATION:Room 2\r\n\
+        DTSTART:20211108T090000Z\r\nDURATION:PT15M\r\nRRULE:FREQ=DAILY;COUNT=5\r\n\
+        ORGANIZER;CN=\"Doe, Jane\":mailto:jane@example.com\r\n\
+        ATTENDEE;CN=John Smith;ROLE=REQ-PARTICIPANT;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:john@example.com\r\n\
+        ATTENDEE;PARTSTAT=ACCEPTED:mailto:jane@example.com\r\nEND:VEVENT\r\n\
+        BEGIN:VEVENT\r\nUID:standup@example.com\r\nDTSTAMP:20211102T080000Z\r\nRECURRENCE-ID:20211110T090000Z\r\nSUMMARY:Stand-up\r\n\
+        DTSTART:20211110T100000Z\r\nDTEND:20211110T101500Z\r\nORGANIZER:mailto:jane@example.com\r\nEND:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    #[test]
+    fn test_parse_invitation() {
+        let messages = SchedulingMessage::parse(INVITATION).unwrap();
+        assert_eq!(messages.len(), 1);
+        let meeting = match &messages[0] {
+            SchedulingMessage::Invitation(meeting) => meeting,
+            other => panic!("Unexpected message {:?}", other),
+        };
+        assert_eq!(meeting.uid, "standup@example.com");
+        assert_eq!(meeting.summary.as_deref(), Some("Stand-up"));
+        assert_eq!(meeting.location.as_deref(), Some("Room 2"));
+        assert_eq!((meeting.start, meeting.end), (Some(date("2021-11-08T09:00:00Z")), Some(date("2021-11-08T09:15:00Z"))));
+        let organizer = meeting.organizer.as_ref().unwrap();
+        assert_eq!((organizer.email(), organizer.name.as_deref()), (Some("jane@example.com"), Some("Doe, Jane")));
+
+        let john = meeting.attendee("JOHN@example.com").unwrap();
+        assert_eq!(john.name.as_deref(), Some("John Smith"));
+        assert_eq!(john.participation_status, ParticipationStatus::NeedsAction);
+        assert!(john.rsvp);
+        assert_eq!(meeting.attendee("mailto:jane@example.com").unwrap().participation_status, ParticipationStatus::Accepted);
+        assert!(meeting.attendee("someone@example.com").is_none());
+
+        assert_eq!(meeting.overridden_instances.len(), 1);
+        assert_eq!(meeting.overridden_instances[0].recurrence_id, Some(date("2021-11-10T09:00:00Z")));
+        assert_eq!(meeting.overridden_instances[0].start, Some(date("2021-11-10T10:00:00Z")));
+    }
+
+    #[test]
+    fn test_parse_updates_and_cancellations() {
+        let update = INVITATION.replace("SEQUENCE:0", "SEQUENCE:2");
+        assert!(matches!(&SchedulingMessage::parse(&update).unwrap()[..], [SchedulingMessage::Update(meeting)] if meeting.sequence == 2));
+
+        let cancellation = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Example Corp.//CalDAV Client//EN\r\nMETHOD:CANCEL\r\n\
+            BEGIN:VEVENT\r\nUID:standup@example.com\r\nDTSTAMP:20211109T080000Z\r\nSEQUENCE:3\r\nRECURRENCE-ID:20211111T090000Z\r\n\
+            ORGANIZER:mailto:jane@example.com\r\nATTENDEE:mailto:john@example.com\r\nSTATUS:CANCELLED\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nUID:standup@example.com\r\nDTSTAMP:20211109T080000Z\r\nSEQUENCE:3\r\nRECURRENCE-ID:20211112T090000Z\r\n\
+            ORGANIZER:mailto:jane@example.com\r\nATTENDEE:mailto:john@example.com\r\nSTATUS:CANCELLED\r\nEND:VEVENT\r\n\
+            END:VCALENDAR\r\n";
+        let messages = SchedulingMessage::parse(cancellation).unwrap();
+        let cancelled: Vec<_> = messages.iter().map(|message| match message {
+            SchedulingMessage::Cancellation(meeting) => meeting.recurrence_id,
+            other => panic!("Unexpected message {:?}", other),
+        }).collect();
+        assert_eq!(cancelled, vec![Some(date("2021-11-11T09:00:00Z")), Some(date("2021-11-12T09:00:00Z"))]);
+        assert_eq!(messages[0].meeting().unwrap().start, None);
+
+        let published = INVITATION.replace("METHOD:REQUEST", "METHOD:PUBLISH");
+        assert_eq!(SchedulingMessage::parse(&published).unwrap(), vec![SchedulingMessage::Unsupported(Method::Publish)]);
+        assert!(SchedulingMessage::parse(&INVITATION.replace("METHOD:REQUEST\r\n", "")).is_err());
+    }
+
+    #[test]
+    fn test_respond() {
+        let messages = SchedulingMessage::parse(&INVITATION.replace("SEQUENCE:0", "SEQUENCE:1")).unwrap();
+        let meeting = messages[0].meeting().unwrap();
+        let reply = meeting.respond("john@example.com", ParticipationStatus::Accepted).unwrap();
+        assert_eq!(reply.organizer, "mailto:jane@example.com");
+        assert_eq!(reply.attendee, "mailto:john@example.com");
+        let unfolded = reply.ics.replace("\r\n ", "");
+        assert!(unfolded.contains("METHOD:REPLY\r\n"));
+        assert!(unfolded.contains("ATTENDEE;CN=John Smith;PARTSTAT=ACCEPTED;ROLE=REQ-PARTICIPANT:mailto:john@example.com\r\n"));
+        assert!(unfolded.contains("ORGANIZER;CN=\"Doe, Jane\":mailto:jane@example.com\r\n"));
+        assert_eq!(unfolded.matches("ATTENDEE").count(), 1);
+        assert!(!unfolded.contains("RRULE"));
+
+        // The reply is understood by the organizer
+        let parsed = crate::ical::parse_scheduling_messages(&reply.ics.replace("METHOD:REPLY", "METHOD:REQUEST")).unwrap();
+        let replied = parsed[0].meeting().unwrap();
+        assert_eq!((replied.uid.as_str(), replied.sequence), ("standup@example.com", 1));
+        assert_eq!(replied.attendees.len(), 1);
+        assert_eq!(replied.attendee("john@example.com").unwrap().participation_status, ParticipationStatus::Accepted);
+        assert!(!replied.attendees[0].rsvp);
+
+        // Instances can be replied to on their own
+        let mut instance = meeting.overridden_instances[0].clone();
+        let john = meeting.properties.iter().find(|prop| prop.name == "ATTENDEE").unwrap();
+        instance.properties.push(john.clone());
+        instance.attendees.extend(Participant::from_property(john));
+        let reply = instance.respond("mailto:John@Example.com", ParticipationStatus::Declined).unwrap();
+        assert!(reply.ics.contains("RECURRENCE-ID:20211110T090000Z\r\n") && reply.ics.contains("PARTSTAT=DECLINED"));
+
+        assert!(meeting.respond("someone@example.com", ParticipationStatus::Accepted).is_err());
+        assert!(meeting.respond("john@example.com", ParticipationStatus::NeedsAction).is_err());
+    }
+
+    #[test]
+    fn test_counter_proposals() {
+        let request = INVITATION.replace("SEQUENCE:0", "SEQUENCE:1");
+        let meeting = SchedulingMessage::parse(&request).unwrap()[0].meeting().unwrap().clone();
+
+        // John proposes to meet an hour later
+        let counter = meeting.counter("john@example.com", date("2021-11-08T10:00:00Z"), Some(date("2021-11-08T10:15:00Z")), Some("I have another meeting")).unwrap();
+        assert_eq!(counter.recipients, vec!["mailto:jane@example.com"]);
+        assert_eq!(counter.content_type(), "text/calendar; charset=utf-8; method=COUNTER");
+        let received = match &SchedulingMessage::parse(&counter.ics).unwrap()[..] {
+            [SchedulingMessage::Counter(received)] => received.clone(),
+            other => panic!("Unexpected messages {:?}", other),
+        };
+        assert_eq!(received.sequence, 1);
+
+        // Jane sees what John proposes
+        let proposal = meeting.check_counter(&received).unwrap();
+        assert_eq!(proposal.proposer.email(), Some("john@example.com"));
+        assert_eq!((proposal.start, proposal.end), (date("2021-11-08T10:00:00Z"), Some(date("2021-11-08T10:15:00Z"))));
+        assert_eq!(proposal.comment.as_deref(), Some("I have another meeting"));
+        assert!(!proposal.outdated);
+        let changed: Vec<_> = proposal.changes.iter().map(|change| change.field.clone()).collect();
+        assert_eq!(changed, vec![ItemField::Property("DTSTART".to_string()), ItemField::Property("DTEND".to_string())]);
+        assert_eq!(proposal.changes[1].old.as_deref(), Some("20211108T091500Z"));
+
+        // ...and accepts it: this is a new version of the meeting, that attendees should accept again
+        let update = meeting.accept_counter(&proposal).unwrap();
+        assert_eq!(update.recipients, vec!["mailto:john@example.com"]);
+        let updated = match &SchedulingMessage::parse(&update.ics).unwrap()[..] {
+            [SchedulingMessage::Update(updated)] => updated.clone(),
+            other => panic!("Unexpected messages {:?}", other),
+        };
+        assert_eq!(updated.sequence, 2);
+        assert_eq!((updated.start, updated.end), (Some(date("2021-11-08T10:00:00Z")), Some(date("2021-11-08T10:15:00Z"))));
+        assert!(updated.attendees.iter().all(|attendee| attendee.participation_status == ParticipationStatus::NeedsAction && attendee.rsvp));
+        assert!(update.ics.contains("RRULE:FREQ=DAILY;COUNT=5"));
+
+        // A proposal about the previous version is outdated, and declined
+        let proposal = updated.check_counter(&received).unwrap();
+        assert!(proposal.outdated);
+        let declined = updated.decline_counter(&proposal, Some("The meeting has been moved already")).unwrap();
+        assert_eq!(declined.recipients, vec!["mailto:john@example.com"]);
+        match &SchedulingMessage::parse(&declined.ics).unwrap()[..] {
+            [SchedulingMessage::DeclineCounter(decline)] => {
+                assert_eq!(decline.sequence, 2);
+                assert_eq!(decline.attendees.len(), 1);
+            },
+            other => panic!("Unexpected messages {:?}", other),
+        }
+
+        // Proposals about a version that does not exist yet, or by someone who is not invited, are invalid
+        let future = SchedulingMessage::parse(&counter.ics.replace("SEQUENCE:1", "SEQUENCE:5")).unwrap();
+        assert!(meeting.check_counter(future[0].meeting().unwrap()).is_err());
+        assert!(meeting.counter("someone@example.com", date("2021-11-08T10:00:00Z"), None, None).is_err());
+
+        // Proposals about an instance of a recurring meeting only reschedule this instance
+        let instance = Meeting{ recurrence_id: Some(date("2021-11-09T09:00:00Z")), ..received };
+        let proposal = meeting.check_counter(&instance).unwrap();
+        let update = meeting.accept_counter(&proposal).unwrap().ics.replace("\r\n ", "");
+        assert!(update.contains("RECURRENCE-ID:20211109T090000Z\r\n") && !update.contains("RRULE"));
+    }
+
+    #[test]
+    fn test_counter_proposals_compare_dates() {
+        // The meeting has a TZID, while proposals are written in UTC
+        let request = INVITATION.replace("DTSTART:20211108T090000Z\r\nDURATION:PT15M", "DTSTART;TZID=Europe/Paris:20211108T090000\r\nDTEND;TZID=Europe/Paris:20211108T091500");
+        let meeting = SchedulingMessage::parse(&request).unwrap()[0].meeting().unwrap().clone();
+
+        // Only proposing a longer meeting does not move it
+        let counter = meeting.counter("john@example.com", meeting.start.unwrap(), Some(date("2021-11-08T09:30:00Z")), None).unwrap();
+        let received = SchedulingMessage::parse(&counter.ics).unwrap()[0].meeting().unwrap().clone();
+        let proposal = meeting.check_counter(&received).unwrap();
+        assert_eq!(proposal.changes, vec![FieldChange{ field: ItemField::Property("DTEND".to_string()), old: Some("20211108T091500Z".to_string()), new: Some("20211108T093000Z".to_string()) }]);
+
+        // A proposal without an end keeps the duration of the meeting
+        let counter = meeting.counter("john@example.com", meeting.start.unwrap(), None, None).unwrap();
+        let received = SchedulingMessage::parse(&counter.ics).unwrap()[0].meeting().unwrap().clone();
+        assert!(meeting.check_counter(&received).unwrap().changes.is_empty());
+    }
+}
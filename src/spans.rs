@@ -0,0 +1,76 @@
+//! The `tracing` spans of syncs, calendars, item operations and HTTP requests (see the `tracing` feature)
+//!
+//! Without this feature, spans are no-ops, so that the code that creates them does not need to be feature-gated
+
+use reqwest::{Method, StatusCode};
+use url::Url;
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{Instrument, Span};
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use noop::{Instrument, Span};
+
+#[cfg(not(feature = "tracing"))]
+mod noop {
+    use std::future::Future;
+
+    #[derive(Clone, Debug)]
+    pub(crate) struct Span;
+
+    pub(crate) trait Instrument: Sized {
+        fn instrument(self, _span: Span) -> Self {
+            self
+        }
+    }
+
+    impl<F: Future> Instrument for F {}
+}
+
+/// A whole sync of a provider
+pub(crate) fn sync() -> Span {
+    #[cfg(feature = "tracing")]
+    { tracing::info_span!("sync") }
+    #[cfg(not(feature = "tracing"))]
+    { Span }
+}
+
+/// The sync of a calendar
+pub(crate) fn calendar(url: &Url, name: &str) -> Span {
+    #[cfg(feature = "tracing")]
+    { tracing::info_span!("calendar", url = %url, name = %name) }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (url, name);
+        Span
+    }
+}
+
+/// An operation on a remote item (e.g. `"add"`, `"update"`, `"delete"`)
+pub(crate) fn item(operation: &'static str, url: &Url, uid: Option<&str>) -> Span {
+    #[cfg(feature = "tracing")]
+    { tracing::info_span!("item", operation, url = %url, uid) }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (operation, url, uid);
+        Span
+    }
+}
+
+/// An HTTP request. Its status is set by [`record_status`]
+pub(crate) fn request(method: &Method, url: &Url) -> Span {
+    #[cfg(feature = "tracing")]
+    { tracing::debug_span!("request", method = %method, url = %url, status = tracing::field::Empty) }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (method, url);
+        Span
+    }
+}
+
+pub(crate) fn record_status(span: &Span, status: StatusCode) {
+    #[cfg(feature = "tracing")]
+    span.record("status", &status.as_u16());
+    #[cfg(not(feature = "tracing"))]
+    let _ = (span, status);
+}
@@ -0,0 +1,141 @@
+//! This module provides a source of read-only calendars that are published as plain iCal files, e.g. `webcal://` holiday feeds or sports schedules (see [`Subscriptions`])
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use csscolorparser::Color;
+use url::Url;
+
+use crate::resource::Resource;
+use crate::calendar::subscribed_calendar::SubscribedCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarTimezone;
+use crate::client::ServerCapabilities;
+use crate::client::transport::Transport;
+use crate::traits::CalDavSource;
+use crate::traits::DavCalendar;
+use crate::error::KFError;
+
+/// A set of subscribed calendars.
+///
+/// This source can be used instead of a [`Client`](crate::Client) in a [`Provider`](crate::provider::Provider) (see [`SubscriptionProvider`](crate::SubscriptionProvider)),
+/// so that every sync downloads the feeds again and updates their items in the local cache. \
+/// Subscribed calendars are read-only, and the list of subscriptions is not saved: apps should subscribe again every time they start.
+#[derive(Debug)]
+pub struct Subscriptions {
+    transport: Transport,
+    calendars: HashMap<Url, Arc<Mutex<SubscribedCalendar>>>,
+    refresh_interval: Option<Duration>,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self {
+            transport: Transport::default(),
+            calendars: HashMap::new(),
+            refresh_interval: None,
+        }
+    }
+
+    /// Set how long a downloaded feed is used before it is downloaded again (see [`SubscribedCalendar::set_refresh_interval`]).
+    ///
+    /// This applies to the current subscriptions, and to the later ones
+    pub fn set_refresh_interval(&mut self, interval: Duration) {
+        self.refresh_interval = Some(interval);
+        for calendar in self.calendars.values() {
+            calendar.lock().unwrap().set_refresh_interval(interval);
+        }
+    }
+
+    /// Subscribe to the iCal file at `url`. `webcal://` and `webcals://` URLs are downloaded over HTTPS
+    pub fn subscribe<S: AsRef<str>>(&mut self, url: S, name: String, color: Option<Color>) -> Result<Arc<Mutex<SubscribedCalendar>>, KFError> {
+        let url = subscription_url(url.as_ref())?;
+        let resource = Resource::new_with_transport(url.clone(), String::new(), String::new(), self.transport.clone());
+        let mut calendar = SubscribedCalendar::new(name, resource, SupportedComponents::TODO | SupportedComponents::EVENT, color);
+        if let Some(interval) = self.refresh_interval {
+            calendar.set_refresh_interval(interval);
+        }
+        let calendar = Arc::new(Mutex::new(calendar));
+        self.calendars.insert(url, calendar.clone());
+        Ok(calendar)
+    }
+
+    /// Stop syncing a subscribed calendar.
+    ///
+    /// Its local counterpart is not removed from the local source
+    pub fn unsubscribe(&mut self, url: &Url) -> Option<Arc<Mutex<SubscribedCalendar>>> {
+        self.calendars.remove(url)
+    }
+}
+
+/// The URL a feed is downloaded from
+fn subscription_url(url: &str) -> Result<Url, KFError> {
+    let url = url.trim();
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let url = match scheme.to_ascii_lowercase().as_str() {
+        "webcal" | "webcals" => format!("https://{}", rest),
+        _ => url.to_string(),
+    };
+    let parsed = Url::parse(&url).map_err(|err| format!("Invalid subscription URL {}: {}", url, err))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(parsed),
+        other => Err(format!("Unsupported scheme {} for subscription {}", other, url).into()),
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<SubscribedCalendar> for Subscriptions {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<SubscribedCalendar>>>, KFError> {
+        Ok(self.calendars.clone())
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<SubscribedCalendar>>> {
+        self.calendars.get(url).cloned()
+    }
+
+    async fn create_calendar(&mut self, url: Url, _name: String, _supported_components: SupportedComponents, _color: Option<Color>) -> Result<Arc<Mutex<SubscribedCalendar>>, KFError> {
+        Err(format!("Calendar {} cannot be created: subscribed calendars are read-only", url).into())
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<SubscribedCalendar>>, KFError> {
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        // Feeds are not served by CalDAV servers
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        Ok(())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription_url() {
+        assert_eq!(subscription_url("webcal://example.com/holidays.ics").unwrap().as_str(), "https://example.com/holidays.ics");
+        assert_eq!(subscription_url("WEBCALS://example.com/holidays.ics").unwrap().as_str(), "https://example.com/holidays.ics");
+        assert_eq!(subscription_url(" http://example.com/holidays.ics").unwrap().as_str(), "http://example.com/holidays.ics");
+        assert!(subscription_url("ftp://example.com/holidays.ics").is_err());
+        assert!(subscription_url("holidays.ics").is_err());
+    }
+}
@@ -1,13 +1,20 @@
 //! To-do tasks (iCal `VTODO` item)
 
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use ical::property::Property;
 use url::Url;
 
-use crate::item::SyncStatus;
-use crate::utils::random_url;
+use crate::Item;
+use crate::item::{FieldChange, ItemField, SyncStatus};
+use crate::calendar::{DefaultAlarms, SupportedComponents};
+use crate::alarm::Alarm;
+use crate::calendar::cached_calendar::CachedCalendar;
+use crate::traits::BaseCalendar;
+use crate::uid;
 
 /// RFC5545 defines the completion as several optional fields, yet some combinations make no sense.
 /// This enum provides an API that forbids such impossible combinations.
@@ -27,12 +34,70 @@ impl CompletionStatus {
             _ => false,
         }
     }
+
+    /// The value of the iCal `STATUS` property
+    pub fn ical_status(&self) -> &'static str {
+        match self {
+            CompletionStatus::Completed(_) => "COMPLETED",
+            CompletionStatus::Uncompleted => "NEEDS-ACTION",
+        }
+    }
+
+    pub fn completion_date(&self) -> Option<&DateTime<Utc>> {
+        match self {
+            CompletionStatus::Completed(date) => date.as_ref(),
+            CompletionStatus::Uncompleted => None,
+        }
+    }
+}
+
+/// Extra properties that only describe a revision of an item, rather than its content (see [`Task::content_eq`])
+const METADATA_PROPERTIES: [&str; 1] = ["SEQUENCE"];
+
+fn is_metadata(prop: &Property) -> bool {
+    METADATA_PROPERTIES.iter().any(|name| prop.name.eq_ignore_ascii_case(name))
+}
+
+pub(crate) fn same_content_properties(a: &[Property], b: &[Property]) -> bool {
+    let content = |properties: &[Property]| -> Vec<Property> {
+        properties.iter()
+            .filter(|prop| !is_metadata(prop))
+            .cloned()
+            .collect()
+    };
+    let (a, b) = (content(a), content(b));
+    a.len() == b.len() && a.iter().zip(&b).all(|(pa, pb)| pa.name.eq_ignore_ascii_case(&pb.name) && pa.value == pb.value && pa.params == pb.params)
+}
+
+/// The values of the content properties, by (uppercase) property name. Properties that appear several times (e.g. `CATEGORIES`) are joined by commas
+pub(crate) fn content_property_values(properties: &[Property]) -> BTreeMap<String, String> {
+    let mut values: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for prop in properties.iter().filter(|prop| !is_metadata(prop)) {
+        let mut value = String::new();
+        for (param, param_values) in prop.params.iter().flatten() {
+            value.push_str(&format!("{}={};", param, param_values.join(",")));
+        }
+        if !value.is_empty() {
+            // Like in iCal files, parameters are separated from the value by a colon
+            value.pop();
+            value.push(':');
+        }
+        value.push_str(prop.value.as_deref().unwrap_or_default());
+        values.entry(prop.name.to_uppercase()).or_default().push(value);
+    }
+    values.into_iter().map(|(name, values)| (name, values.join(", "))).collect()
+}
+
+/// Whether a property is a whole day (`DATE`) rather than a `DATE-TIME`
+fn is_date_value(prop: &Property) -> bool {
+    prop.params.iter().flatten().any(|(name, values)| name.eq_ignore_ascii_case("VALUE") && values.iter().any(|value| value.eq_ignore_ascii_case("DATE")))
+        || prop.value.as_ref().is_some_and(|value| value.len() == 8)
 }
 
 /// A to-do task
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Task {
-    /// The task URL
+    /// The task URL. It is unrelated to its UID, since servers may name their items as they like
     url: Url,
 
     /// Persistent, globally unique identifier for the calendar component
@@ -60,6 +125,15 @@ pub struct Task {
     /// Extra parameters that have not been parsed from the iCal file (because they're not supported (yet) by this crate).
     /// They are needed to serialize this item into an equivalent iCal file
     extra_parameters: Vec<Property>,
+
+    /// The `VALARM` sub-components of this task
+    #[serde(default)]
+    alarms: Vec<Alarm>,
+
+    /// The iCal file this task has been parsed from (e.g. as received from the server).
+    /// Only the properties that have changed since then are rewritten when this task is serialized again
+    #[serde(default)]
+    raw_ics: Option<Box<str>>,
 }
 
 
@@ -67,9 +141,9 @@ impl Task {
     /// Create a brand new Task that is not on a server yet.
     /// This will pick a new (random) task ID.
     pub fn new(name: String, completed: bool, parent_calendar_url: &Url) -> Self {
-        let new_url = random_url(parent_calendar_url);
+        let new_uid = uid::new_uid();
+        let new_url = uid::new_item_url(parent_calendar_url, &new_uid);
         let new_sync_status = SyncStatus::NotSynced;
-        let new_uid = Uuid::new_v4().to_hyphenated().to_string();
         let new_creation_date = Some(Utc::now());
         let new_last_modified = Utc::now();
         let new_completion_status = if completed {
@@ -97,6 +171,8 @@ impl Task {
             last_modified,
             ical_prod_id,
             extra_parameters,
+            alarms: Vec::new(),
+            raw_ics: None,
         }
     }
 
@@ -110,6 +186,9 @@ impl Task {
     pub fn creation_date(&self) -> Option<&DateTime<Utc>>   { self.creation_date.as_ref() }
     pub fn completion_status(&self) -> &CompletionStatus    { &self.completion_status }
     pub fn extra_parameters(&self) -> &[Property]           { &self.extra_parameters }
+    pub fn alarms(&self) -> &[Alarm]                        { &self.alarms }
+    /// The iCal file this task has been parsed from, if any (see [`crate::ical::parse`])
+    pub fn raw_ics(&self) -> Option<&str>                   { self.raw_ics.as_deref() }
 
     #[cfg(any(test, feature = "integration_tests"))]
     pub fn has_same_observable_content_as(&self, other: &Task) -> bool {
@@ -123,6 +202,88 @@ impl Task {
         // last modified dates are ignored (they are not totally mocked in integration tests)
     }
 
+    /// Returns whether both tasks have the same content, i.e. the same properties the user can see or edit.
+    ///
+    /// Unlike a field-by-field comparison, this ignores where tasks are stored and their sync metadata (URL, sync status, creation and modification dates, PRODID, `SEQUENCE`...),
+    /// so that a task is not considered as changed because it has only been synced
+    pub fn content_eq(&self, other: &Task) -> bool {
+           self.uid == other.uid
+        && self.name == other.name
+        && self.completion_status == other.completion_status
+        && same_content_properties(&self.extra_parameters, &other.extra_parameters)
+        && self.alarms == other.alarms
+    }
+
+    /// The fields that differ between this task and `other`, i.e. what has changed if `other` is a newer version of this task. \
+    /// Like [`Task::content_eq`], this ignores sync metadata
+    pub fn diff(&self, other: &Task) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        FieldChange::push_if_different(&mut changes, ItemField::Uid, Some(self.uid.clone()), Some(other.uid.clone()));
+        FieldChange::push_if_different(&mut changes, ItemField::Name, Some(self.name.clone()), Some(other.name.clone()));
+        FieldChange::push_if_different(&mut changes, ItemField::Status, Some(self.completion_status.ical_status().to_string()), Some(other.completion_status.ical_status().to_string()));
+        FieldChange::push_if_different(&mut changes, ItemField::CompletionDate,
+            self.completion_status.completion_date().map(|date| date.to_rfc3339()),
+            other.completion_status.completion_date().map(|date| date.to_rfc3339()));
+
+        let mut old_values = content_property_values(&self.extra_parameters);
+        let mut new_values = content_property_values(&other.extra_parameters);
+        let names: BTreeSet<String> = old_values.keys().chain(new_values.keys()).cloned().collect();
+        for name in names {
+            let (old, new) = (old_values.remove(&name), new_values.remove(&name));
+            FieldChange::push_if_different(&mut changes, ItemField::Property(name), old, new);
+        }
+        changes
+    }
+
+    /// A copy of this task, that can be added to the calendar at `calendar_url` (which may be the calendar of this task) as a new task. \
+    /// The copy has a new UID and URL, it has never been synced, and its `SEQUENCE` is reset
+    pub fn duplicate(&self, calendar_url: &Url) -> Task {
+        let extra_parameters = self.extra_parameters.iter()
+            .filter(|prop| !prop.name.eq_ignore_ascii_case("SEQUENCE"))
+            .cloned()
+            .collect();
+        let new_uid = uid::new_uid();
+        let new_url = uid::new_item_url(calendar_url, &new_uid);
+        let mut copy = Self::new_with_parameters(
+            self.name.clone(),
+            new_uid,
+            new_url,
+            self.completion_status.clone(),
+            SyncStatus::NotSynced,
+            Some(Utc::now()),
+            Utc::now(),
+            self.ical_prod_id.clone(),
+            extra_parameters,
+        );
+        copy.alarms = self.alarms.clone();
+        copy
+    }
+
+    /// Add a copy of this task (see [`Task::duplicate`]) to `calendar`. It will be uploaded at the next sync. \
+    /// Returns the URL of the copy
+    pub fn duplicate_into(&self, calendar: &mut CachedCalendar) -> Result<Url, Box<dyn Error>> {
+        if !calendar.supported_components().contains(SupportedComponents::TODO) {
+            return Err(format!("Calendar {} does not support tasks", calendar.url()).into());
+        }
+        let copy = self.duplicate(calendar.url());
+        let url = copy.url().clone();
+        calendar.add_item_sync(Item::Task(copy))?;
+        Ok(url)
+    }
+
+    /// A copy of this task at another URL (e.g. in another calendar), with the same UID, alarms and iCal data
+    pub(crate) fn moved_to(&self, new_url: Url) -> Task {
+        Task { url: new_url, ..self.clone() }
+    }
+
+    pub(crate) fn set_raw_ics(&mut self, raw_ics: Option<String>) {
+        self.raw_ics = raw_ics.map(String::into_boxed_str);
+    }
+
+    pub(crate) fn set_alarms(&mut self, alarms: Vec<Alarm>) {
+        self.alarms = alarms;
+    }
+
     pub fn set_sync_status(&mut self, new_status: SyncStatus) {
         self.sync_status = new_status;
     }
@@ -141,7 +302,7 @@ impl Task {
         }
     }
 
-    fn update_last_modified(&mut self) {
+    pub(crate) fn update_last_modified(&mut self) {
         self.last_modified = Utc::now();
     }
 
@@ -161,6 +322,116 @@ impl Task {
         self.name = new_name;
     }
 
+    /// Add an alarm to this task.
+    /// This updates its "last modified" field
+    pub fn add_alarm(&mut self, alarm: Alarm) {
+        self.update_sync_status();
+        self.update_last_modified();
+        self.alarms.push(alarm);
+    }
+
+    /// When the alarms of this task have last been dismissed or snoozed, as Thunderbird tells it (its `X-MOZ-LASTACK`). Alarms triggered before this date should not remind the user again
+    pub fn alarms_last_acknowledged(&self) -> Option<DateTime<Utc>> {
+        self.extra_parameters.iter()
+            .find(|prop| prop.name.eq_ignore_ascii_case("X-MOZ-LASTACK"))
+            .and_then(|prop| crate::ical::parse_date_value(prop.value.as_deref()?))
+    }
+
+    /// Dismiss the alarm at index `alarm` of [`Self::alarms`], once it has reminded the user.
+    ///
+    /// The alarm is acknowledged (see [`Alarm::acknowledged`]) and so is the task (for Thunderbird), so that other clients do not remind the user again either.
+    /// The snooze alarms of this alarm are removed; dismissing a snooze alarm dismisses the alarm it snoozes.
+    /// This updates its "last modified" field
+    pub fn dismiss(&mut self, alarm: usize) -> Result<(), Box<dyn Error>> {
+        let snoozed = self.alarms.get(alarm)
+            .ok_or_else(|| format!("Task {} has no alarm #{}", self.url, alarm))?
+            .snoozed_alarm().map(str::to_string);
+        self.update_sync_status();
+        self.update_last_modified();
+        let now = Utc::now();
+
+        let original = match snoozed {
+            None => Some(alarm),
+            Some(uid) => {
+                self.alarms.remove(alarm);
+                self.alarms.iter().position(|alarm| alarm.uid() == Some(uid.as_str()))
+            },
+        };
+        if let Some(original) = original {
+            self.alarms[original].acknowledge(now);
+            if let Some(uid) = self.alarms[original].uid().map(str::to_string) {
+                self.alarms.retain(|alarm| alarm.snoozed_alarm() != Some(uid.as_str()));
+            }
+        }
+        self.set_extra_parameter("X-MOZ-LASTACK", Some(now.format("%Y%m%dT%H%M%SZ").to_string()));
+        if self.alarms.iter().all(|alarm| alarm.snoozed_alarm().is_none()) {
+            self.set_extra_parameter("X-MOZ-SNOOZE-TIME", None);
+        }
+        Ok(())
+    }
+
+    /// Remind the user of the alarm at index `alarm` of [`Self::alarms`] again in `duration`, and returns when.
+    ///
+    /// The alarm is acknowledged, and a snooze alarm that refers to it is added (as described in [RFC 9074](https://www.rfc-editor.org/rfc/rfc9074)), replacing its previous snooze alarm if any.
+    /// The task also gets the `X-MOZ-LASTACK` and `X-MOZ-SNOOZE-TIME` Thunderbird uses.
+    /// This updates its "last modified" field
+    pub fn snooze(&mut self, alarm: usize, duration: Duration) -> Result<DateTime<Utc>, Box<dyn Error>> {
+        let snoozed = self.alarms.get(alarm)
+            .ok_or_else(|| format!("Task {} has no alarm #{}", self.url, alarm))?
+            .snoozed_alarm();
+        // Snoozing a snooze alarm snoozes the alarm it snoozes
+        let original = snoozed
+            .and_then(|uid| self.alarms.iter().position(|alarm| alarm.uid() == Some(uid)))
+            .unwrap_or(alarm);
+        self.update_sync_status();
+        self.update_last_modified();
+        let now = Utc::now();
+        let until = now + duration;
+
+        let uid = self.alarms[original].ensure_uid();
+        self.alarms[original].acknowledge(now);
+        let snooze = self.alarms[original].snoozed_until(Some(&uid), until);
+        self.alarms.retain(|alarm| alarm.snoozed_alarm() != Some(uid.as_str()));
+        self.alarms.push(snooze);
+        self.set_extra_parameter("X-MOZ-LASTACK", Some(now.format("%Y%m%dT%H%M%SZ").to_string()));
+        self.set_extra_parameter("X-MOZ-SNOOZE-TIME", Some(until.format("%Y%m%dT%H%M%SZ").to_string()));
+        Ok(until)
+    }
+
+    /// Replace the value of a property, add it if this task does not have it, or remove it (with `None`)
+    fn set_extra_parameter(&mut self, name: &str, value: Option<String>) {
+        let existing = self.extra_parameters.iter().position(|prop| prop.name.eq_ignore_ascii_case(name));
+        match (existing, value) {
+            (Some(index), Some(value)) => self.extra_parameters[index] = Property{ name: name.to_string(), params: None, value: Some(value) },
+            (None, Some(value)) => self.extra_parameters.push(Property{ name: name.to_string(), params: None, value: Some(value) }),
+            (Some(index), None) => { self.extra_parameters.remove(index); },
+            (None, None) => (),
+        }
+    }
+
+    /// Add the default alarm of its calendar to this task, unless it already has alarms. Returns whether an alarm has been added.
+    ///
+    /// Tasks that have neither a start nor a due date are left alone, since there would be nothing to trigger their alarms relative to
+    pub fn attach_default_alarm(&mut self, defaults: &DefaultAlarms) -> bool {
+        if !self.alarms.is_empty() {
+            return false;
+        }
+        let date = self.extra_parameters.iter()
+            .find(|prop| prop.name.eq_ignore_ascii_case("DUE") || prop.name.eq_ignore_ascii_case("DTSTART"));
+        let default = match date {
+            None => return false,
+            Some(date) if is_date_value(date) => &defaults.task_date,
+            Some(_) => &defaults.task_date_time,
+        };
+        match default {
+            None => false,
+            Some(alarm) => {
+                self.add_alarm(alarm.clone());
+                true
+            },
+        }
+    }
+
     /// Set the completion status
     pub fn set_completion_status(&mut self, new_completion_status: CompletionStatus) {
         self.update_sync_status();
@@ -0,0 +1,35 @@
+//! Clocks and timers that also work in browsers (wasm32), where neither `std::time::Instant` nor the timers of tokio are available
+
+use std::future::Future;
+use std::time::Duration;
+
+pub(crate) use web_time::{Instant, SystemTime};
+
+/// Wait for `duration`, without blocking the thread
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(duration).await;
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(duration).await;
+}
+
+/// Wait until `deadline` (or not at all if it is already over)
+pub(crate) async fn sleep_until(deadline: Instant) {
+    sleep(deadline.saturating_duration_since(Instant::now())).await
+}
+
+/// Run `future` to completion, unless this takes longer than `duration`, in which case it is dropped and `None` is returned
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return tokio::time::timeout(duration, future).await.ok();
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        use futures_util::future::{select, Either};
+
+        match select(std::pin::pin!(future), std::pin::pin!(sleep(duration))).await {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(_) => None,
+        }
+    }
+}
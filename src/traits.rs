@@ -1,32 +1,65 @@
 //! Traits used by multiple structs in this crate
 
-use std::error::Error;
 use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream};
 use csscolorparser::Color;
 use url::Url;
+use chrono::{DateTime, Utc};
 
-use crate::item::SyncStatus;
+use crate::item::{SyncStatus, SyncStatusKind};
 use crate::item::Item;
 use crate::item::VersionTag;
 use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarVersion;
+use crate::calendar::CalendarProperties;
+use crate::calendar::CalendarTimezone;
+use crate::calendar::DefaultAlarms;
+use crate::calendar::sharing::{ShareStatus, Sharing};
 use crate::resource::Resource;
+use crate::client::ServerCapabilities;
+use crate::provider::filter::SyncFilter;
+use crate::provider::journal::SyncJournal;
+use crate::provider::audit::AuditLog;
+use crate::provider::pending::PendingChange;
+use crate::error::KFError;
 
 /// This trait must be implemented by data sources (either local caches or remote CalDAV clients)
 ///
 /// Note that some concrete types (e.g. [`crate::cache::Cache`]) can also provide non-async versions of these functions
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait CalDavSource<T: BaseCalendar> {
     /// Returns the current calendars that this source contains
     /// This function may trigger an update (that can be a long process, or that can even fail, e.g. in case of a remote server)
-    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<T>>>, Box<dyn Error>>;
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<T>>>, KFError>;
     /// Returns the calendar matching the URL
     async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<T>>>;
     /// Create a calendar if it did not exist, and return it
     async fn create_calendar(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>)
-        -> Result<Arc<Mutex<T>>, Box<dyn Error>>;
+        -> Result<Arc<Mutex<T>>, KFError>;
+    /// Create a calendar if it did not exist, with a default time zone for its floating and all-day items, and return it
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, timezone: Option<CalendarTimezone>)
+        -> Result<Arc<Mutex<T>>, KFError>;
+
+    /// Returns the optional features this source supports, so that the best sync strategy can be used.
+    ///
+    /// Sources that do not know should return the baseline CalDAV features (i.e. [`ServerCapabilities::default`])
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError>;
+
+    /// Persist the current content of this source (e.g. to the disk), if it is backed by a storage.
+    ///
+    /// Syncs call this at several checkpoints, so that an interrupted sync can be resumed (see [`SyncJournal`]).
+    /// Sources that are not backed by a storage have nothing to do
+    async fn save(&self) -> Result<(), KFError>;
+
+    /// Persist the content of this source, knowing that only the calendar at `url` has changed since it was last persisted.
+    ///
+    /// Syncs call this instead of [`Self::save`] at their checkpoints, since they modify one calendar at a time.
+    /// Sources that store their calendars separately only have to write this one
+    async fn save_calendar(&self, url: &Url) -> Result<(), KFError>;
 
     // Removing a calendar is not supported yet
 }
@@ -34,7 +67,8 @@ pub trait CalDavSource<T: BaseCalendar> {
 /// This trait contains functions that are common to all calendars
 ///
 /// Note that some concrete types (e.g. [`crate::calendar::cached_calendar::CachedCalendar`]) can also provide non-async versions of these functions
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait BaseCalendar {
     /// Returns the calendar name
     fn name(&self) -> &str;
@@ -48,20 +82,95 @@ pub trait BaseCalendar {
     /// Returns the user-defined color of this calendar
     fn color(&self) -> Option<&Color>;
 
+    /// Returns the position of this calendar in lists of calendars (the lowest first), as set by the user in any client
+    fn order(&self) -> Option<u32> {
+        None
+    }
+
+    /// Returns the free-text description of this calendar, if any
+    fn description(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns the default time zone of this calendar, that applies to its floating and all-day items
+    fn timezone(&self) -> Option<&CalendarTimezone> {
+        None
+    }
+
+    /// Returns the alarms that are added to new items of this calendar, if any
+    fn default_alarms(&self) -> Option<&DefaultAlarms> {
+        None
+    }
+
+    /// Returns the privileges the current user has on this calendar.
+    ///
+    /// Calendars that do not know about privileges allow everything
+    fn privileges(&self) -> crate::calendar::Privileges {
+        crate::calendar::Privileges::all()
+    }
+
+    /// Returns the URL of the principal that owns this calendar, if known.
+    ///
+    /// This differs from the current user for calendars of delegated mailboxes, group calendars or shared calendars
+    fn owner(&self) -> Option<&Url> {
+        None
+    }
+
+    /// Returns whether this calendar is shared between users, and with whom, if the server tells it
+    fn sharing(&self) -> Option<&Sharing> {
+        None
+    }
+
+    /// Returns whether this calendar is shared with (or by) the current user
+    fn share_status(&self) -> ShareStatus {
+        self.sharing().map(|sharing| sharing.status).unwrap_or_default()
+    }
+
     /// Add an item into this calendar, and return its new sync status.
     /// For local calendars, the sync status is not modified.
     /// For remote calendars, the sync status is updated by the server
-    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, Box<dyn Error>>;
+    async fn add_item(&mut self, item: Item) -> Result<SyncStatus, KFError>;
 
     /// Update an item that already exists in this calendar and returns its new `SyncStatus`
     /// This replaces a given item at a given URL
-    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, Box<dyn Error>>;
+    ///
+    /// Remote calendars only update the item if it has not been modified since the version tag of its sync status.
+    /// Otherwise, they return a [`KFError::Conflict`](crate::error::KFError::Conflict)
+    async fn update_item(&mut self, item: Item) -> Result<SyncStatus, KFError>;
 
     /// Returns whether this calDAV calendar supports to-do items
-    fn supports_todo(&self) -> bool {
+    fn supports_tasks(&self) -> bool {
         self.supported_components().contains(crate::calendar::SupportedComponents::TODO)
     }
 
+    /// Returns whether this calDAV calendar supports to-do items
+    #[deprecated(note = "use supports_tasks instead")]
+    fn supports_todo(&self) -> bool {
+        self.supports_tasks()
+    }
+
+    /// Returns whether this calendar can contain an item of this kind
+    fn supports_item(&self, item: &Item) -> bool {
+        self.supported_components().contains(item.component())
+    }
+
+    /// Returns whether the current user is allowed to modify items in this calendar
+    fn can_write(&self) -> bool {
+        self.privileges().can_write()
+    }
+
+    /// Returns whether items of this calendar can neither be added, modified nor deleted.
+    ///
+    /// Local calendars refuse such changes with a [`KFError::ReadOnlyCalendar`](crate::error::KFError::ReadOnlyCalendar), rather than failing to push them at the next sync
+    fn is_read_only(&self) -> bool {
+        self.privileges().is_read_only()
+    }
+
+    /// Returns whether the current user is allowed to share this calendar with other users
+    fn can_share(&self) -> bool {
+        self.privileges().can_share()
+    }
+
     /// Returns whether this calDAV calendar supports calendar items
     fn supports_events(&self) -> bool {
         self.supported_components().contains(crate::calendar::SupportedComponents::EVENT)
@@ -72,34 +181,88 @@ pub trait BaseCalendar {
 /// Functions availabe for calendars that are backed by a CalDAV server
 ///
 /// Note that some concrete types (e.g. [`crate::calendar::cached_calendar::CachedCalendar`]) can also provide non-async versions of these functions
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait DavCalendar : BaseCalendar {
     /// Create a new calendar
     fn new(name: String, resource: Resource, supported_components: SupportedComponents, color: Option<Color>) -> Self;
 
     /// Get the URLs and the version tags of every item in this calendar
-    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, Box<dyn Error>>;
+    async fn get_item_version_tags(&self) -> Result<HashMap<Url, VersionTag>, KFError>;
+
+    /// Get the URLs and the version tags of the items that match a filter
+    async fn get_filtered_item_version_tags(&self, filter: &SyncFilter) -> Result<HashMap<Url, VersionTag>, KFError>;
 
     /// Returns a particular item
-    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, Box<dyn Error>>;
+    async fn get_item_by_url(&self, url: &Url) -> Result<Option<Item>, KFError>;
 
     /// Returns a set of items.
     /// This is usually faster than calling multiple consecutive [`get_item_by_url`], since it only issues one HTTP request.
-    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, Box<dyn Error>>;
+    async fn get_items_by_url(&self, urls: &[Url]) -> Result<Vec<Option<Item>>, KFError>;
 
     /// Delete an item
-    async fn delete_item(&mut self, item_url: &Url) -> Result<(), Box<dyn Error>>;
+    async fn delete_item(&mut self, item_url: &Url) -> Result<(), KFError>;
+
+    /// Move an item to another calendar of the same server (the item keeps its UID), and return its new sync status.
+    ///
+    /// If the server is not able to do it, a [`KFError::MoveNotSupported`](crate::error::KFError::MoveNotSupported) is returned, and nothing has changed
+    async fn move_item(&mut self, item_url: &Url, destination: &Url) -> Result<SyncStatus, KFError>;
+
+    /// Add several items (see [`BaseCalendar::add_item`]), with at most `max_concurrency` requests in flight at the same time.
+    /// Results are returned in the same order as `items`
+    async fn add_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>>;
+
+    /// Update several items (see [`BaseCalendar::update_item`]), with at most `max_concurrency` requests in flight at the same time.
+    /// Results are returned in the same order as `items`
+    async fn update_items(&mut self, items: Vec<Item>, max_concurrency: usize) -> Vec<Result<SyncStatus, KFError>>;
+
+    /// Delete several items, with at most `max_concurrency` requests in flight at the same time.
+    /// Results are returned in the same order as `item_urls`
+    async fn delete_items(&mut self, item_urls: &[Url], max_concurrency: usize) -> Vec<Result<(), KFError>>;
+
+    /// Returns the URL an item that has just been added (see [`BaseCalendar::add_item`]) has been stored at, if the source has chosen another one.
+    ///
+    /// This is the case of sources that assign their own IDs to new items (e.g. REST APIs). The [`Provider`](crate::provider::Provider) then moves the local item to this URL.
+    /// CalDAV servers store items at the URL they are uploaded to, so that they return `None`
+    fn take_assigned_url(&mut self, _url: &Url) -> Option<Url> {
+        None
+    }
 
     /// Get the URLs of all current items in this calendar
-    async fn get_item_urls(&self) -> Result<HashSet<Url>, Box<dyn Error>> {
+    async fn get_item_urls(&self) -> Result<HashSet<Url>, KFError> {
         let items = self.get_item_version_tags().await?;
         Ok(items.iter()
             .map(|(url, _tag)| url.clone())
             .collect())
     }
 
-    // Note: the CalDAV protocol could also enable to do this:
-    // fn get_current_version(&self) -> CTag
+    /// Get the current version of this calendar (i.e. its ctag and sync token), which changes whenever any of its items changes
+    async fn get_calendar_version(&self) -> Result<CalendarVersion, KFError>;
+
+    /// Start from the state this calendar had at the end of the last complete sync, so that only the changes made since are downloaded, rather than every item.
+    ///
+    /// `version` is what [`Self::get_calendar_version`] returned during this sync, and `version_tags` are the version tags its items had then.
+    /// The [`Provider`](crate::provider::Provider) calls this before each sync, since the state of this calendar is lost when the app is restarted. \
+    /// Calendars that do not list their changes this way (e.g. CalDAV calendars, whose items are always compared) ignore this
+    fn resume_from(&mut self, _version: &CalendarVersion, _version_tags: HashMap<Url, VersionTag>) {}
+
+    /// Change (or remove, with `None`) the color of this calendar
+    async fn update_color(&mut self, color: Option<Color>) -> Result<(), KFError>;
+
+    /// Change (or remove, with `None`) the display order of this calendar
+    async fn update_order(&mut self, order: Option<u32>) -> Result<(), KFError>;
+
+    /// Change (or remove, with `None`) the description of this calendar
+    async fn update_description(&mut self, description: Option<String>) -> Result<(), KFError>;
+
+    /// Change (or remove, with `None`) the default time zone of this calendar
+    async fn update_timezone(&mut self, timezone: Option<CalendarTimezone>) -> Result<(), KFError>;
+
+    /// Change the display name of this calendar
+    async fn update_name(&mut self, name: String) -> Result<(), KFError>;
+
+    /// Change (or remove, with `None`) the default alarms of this calendar
+    async fn update_default_alarms(&mut self, default_alarms: Option<DefaultAlarms>) -> Result<(), KFError>;
 }
 
 
@@ -108,19 +271,127 @@ pub trait DavCalendar : BaseCalendar {
 /// Usually, these are local calendars fully backed by a local folder
 ///
 /// Note that some concrete types (e.g. [`crate::calendar::cached_calendar::CachedCalendar`]) can also provide non-async versions of these functions
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait CompleteCalendar : BaseCalendar {
     /// Create a new calendar
     fn new(name: String, url: Url, supported_components: SupportedComponents, color: Option<Color>) -> Self;
 
+    /// Set the privileges the current user has on this calendar.
+    /// This is used to mirror the privileges of the remote counterpart of this calendar
+    fn set_privileges(&mut self, privileges: crate::calendar::Privileges);
+
+    /// Mark this calendar as read-only (or not), even if the server allows to modify it.
+    ///
+    /// Calendars are read-only anyway when their privileges do not allow any change (see [`BaseCalendar::is_read_only`])
+    fn set_read_only(&mut self, read_only: bool);
+
+    /// Set the sharing metadata of this calendar, as reported by the server
+    fn set_sharing(&mut self, sharing: Option<Sharing>);
+
+    /// Set the principal that owns this calendar.
+    /// This is used to mirror the owner of the remote counterpart of this calendar
+    fn set_owner(&mut self, owner: Option<Url>);
+
+    /// Change (or remove, with `None`) the color of this calendar. It is pushed to the server at the next sync
+    fn set_color(&mut self, color: Option<Color>);
+
+    /// Change (or remove, with `None`) the display order of this calendar. It is pushed to the server at the next sync
+    fn set_order(&mut self, order: Option<u32>);
+
+    /// Change (or remove, with `None`) the description of this calendar. It is pushed to the server at the next sync
+    fn set_description(&mut self, description: Option<String>);
+
+    /// Change (or remove, with `None`) the default time zone of this calendar. It is pushed to the server at the next sync
+    fn set_timezone(&mut self, timezone: Option<CalendarTimezone>);
+
+    /// Change the display name of this calendar. It is pushed to the server at the next sync (see also [`Provider::rename_calendar`](crate::provider::Provider::rename_calendar))
+    fn set_name(&mut self, name: String);
+
+    /// Change (or remove, with `None`) the default alarms of this calendar. They are pushed to the server at the next sync
+    fn set_default_alarms(&mut self, default_alarms: Option<DefaultAlarms>);
+
+    /// The properties of this calendar that have been changed locally, and that have not been pushed to the server yet
+    fn modified_properties(&self) -> CalendarProperties;
+
+    /// Forget that some properties have been changed locally. This is called by the [`Provider`](crate::provider::Provider) once they have been synced
+    fn clear_modified_properties(&mut self, properties: CalendarProperties);
+
+    /// The version of an item as it was after the last successful sync, if it is known.
+    /// This is the common ancestor used to merge changes that have been made both locally and on the server
+    fn base_version(&self, url: &Url) -> Option<&Item>;
+
+    /// Remember (or forget, with `None`) the version of an item after a successful sync
+    fn set_base_version(&mut self, url: &Url, base: Option<Item>);
+
+    /// The local changes that have not been pushed to the server yet, in the order they have been made
+    fn pending_changes(&self) -> Vec<PendingChange>;
+
+    /// The operations of an interrupted sync that remain to be done, if any
+    fn sync_journal(&self) -> Option<&SyncJournal>;
+
+    /// The operations of the current sync that remain to be done. This is set and updated by the [`Provider`](crate::provider::Provider) during syncs
+    fn sync_journal_mut(&mut self) -> &mut Option<SyncJournal>;
+
+    /// What the past syncs have done to the items of this calendar
+    fn audit_log(&self) -> &AuditLog;
+
+    /// The log the [`Provider`](crate::provider::Provider) records every sync operation into
+    fn audit_log_mut(&mut self) -> &mut AuditLog;
+
+    /// Remember that a sync of this calendar has ended at `at`, with a given number of errors.
+    /// This is called by the [`Provider`](crate::provider::Provider) after every sync
+    fn record_sync(&mut self, at: DateTime<Utc>, errors: usize);
+
+    /// The version of the remote calendar as of the last complete sync of this calendar, if it is known.
+    /// A calendar whose remote version has not changed since then, and that has no pending local change, does not need to be compared item by item
+    fn synced_remote_version(&self) -> Option<&CalendarVersion>;
+
+    /// Remember (or forget, with `None`) the version of the remote calendar. This is set by the [`Provider`](crate::provider::Provider) after every sync
+    fn set_synced_remote_version(&mut self, version: Option<CalendarVersion>);
+
+    /// The version tags of the items whose content has been dropped from this calendar to save space (see [`CachedCalendar::evict`](crate::calendar::cached_calendar::CachedCalendar::evict)).
+    /// These items are not downloaded again at the next syncs, unless they have changed on the server
+    fn evicted_version_tags(&self) -> HashMap<Url, VersionTag>;
+
     /// Get the URLs of all current items in this calendar
-    async fn get_item_urls(&self) -> Result<HashSet<Url>, Box<dyn Error>>;
+    async fn get_item_urls(&self) -> Result<HashSet<Url>, KFError>;
 
     /// Returns all items that this calendar contains
-    async fn get_items(&self) -> Result<HashMap<Url, &Item>, Box<dyn Error>>;
+    async fn get_items<'a>(&'a self) -> Result<HashMap<Url, &'a Item>, KFError>;
 
     /// Returns all items that this calendar contains
-    async fn get_items_mut(&mut self) -> Result<HashMap<Url, &mut Item>, Box<dyn Error>>;
+    async fn get_items_mut<'a>(&'a mut self) -> Result<HashMap<Url, &'a mut Item>, KFError>;
+
+    /// Returns the items that have a given sync status (e.g. the local changes that have not been pushed yet)
+    async fn get_items_with_status<'a>(&'a self, status: SyncStatusKind) -> Result<HashMap<Url, &'a Item>, KFError>;
+
+    /// Returns how many items have a given sync status. This is usually faster than [`Self::get_items_with_status`]
+    fn count_items_with_status(&self, status: SyncStatusKind) -> usize;
+
+    /// Iterate over the items of this calendar, without collecting them first (unlike [`Self::get_items`])
+    fn iter_items(&self) -> Box<dyn Iterator<Item = (&Url, &Item)> + Send + '_>;
+
+    /// Iterate over the items of this calendar, without collecting them first (unlike [`Self::get_items_mut`])
+    fn iter_items_mut(&mut self) -> Box<dyn Iterator<Item = (&Url, &mut Item)> + Send + '_>;
+
+    /// The items of this calendar, as a stream. This is meant for calendars that load their items asynchronously, and defaults to [`Self::iter_items`]
+    fn stream_items(&self) -> BoxStream<'_, (&Url, &Item)> {
+        Box::pin(stream::iter(self.iter_items()))
+    }
+
+    /// The items of this calendar, as a stream. This is meant for calendars that load their items asynchronously, and defaults to [`Self::iter_items_mut`]
+    fn stream_items_mut(&mut self) -> BoxStream<'_, (&Url, &mut Item)> {
+        Box::pin(stream::iter(self.iter_items_mut()))
+    }
+
+    /// Returns the events that happen between two dates.
+    ///
+    /// Recurrence rules are not evaluated: recurring events are returned if their recurrence set overlaps this range, even though none of their occurrences may happen in it
+    async fn get_events_between<'a>(&'a self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> Result<HashMap<Url, &'a Item>, KFError>;
+
+    /// Returns the tasks whose `DUE` date is between two dates (see [`ItemSummary::is_due_between`](crate::calendar::lazy_items::ItemSummary::is_due_between))
+    async fn get_tasks_due_between<'a>(&'a self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> Result<HashMap<Url, &'a Item>, KFError>;
 
     /// Returns a particular item
     async fn get_item_by_url<'a>(&'a self, url: &Url) -> Option<&'a Item>;
@@ -131,8 +402,8 @@ pub trait CompleteCalendar : BaseCalendar {
     /// Mark an item for deletion.
     /// This is required so that the upcoming sync will know it should also also delete this task from the server
     /// (and then call [`CompleteCalendar::immediately_delete_item`] once it has been successfully deleted on the server)
-    async fn mark_for_deletion(&mut self, item_id: &Url) -> Result<(), Box<dyn Error>>;
+    async fn mark_for_deletion(&mut self, item_id: &Url) -> Result<(), KFError>;
 
     /// Immediately remove an item. See [`CompleteCalendar::mark_for_deletion`]
-    async fn immediately_delete_item(&mut self, item_id: &Url) -> Result<(), Box<dyn Error>>;
+    async fn immediately_delete_item(&mut self, item_id: &Url) -> Result<(), KFError>;
 }
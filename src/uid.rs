@@ -0,0 +1,169 @@
+//! How the UIDs and URLs of new items are generated (see [`crate::config::UID_GENERATOR`] and [`crate::config::ITEM_URLS`])
+//!
+//! By default, UIDs are random UUIDs, and URLs are random names in their calendar, unrelated to the UIDs.
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use chrono::Utc;
+use url::Url;
+
+use crate::config::{ITEM_URLS, UID_GENERATOR};
+use crate::utils::random_url;
+
+/// Something that generates the UIDs of new items. Each UID must be globally unique
+pub trait UidGenerator: Send + Sync {
+    fn generate(&self) -> String;
+}
+
+/// Random UUIDs (version 4), e.g. `67e55044-10b1-426f-9247-bb680e5fe0c8`. This is the default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UuidV4;
+
+impl UidGenerator for UuidV4 {
+    fn generate(&self) -> String {
+        uuid::Uuid::new_v4().to_hyphenated().to_string()
+    }
+}
+
+/// Time-ordered UUIDs (version 7, see [RFC 9562](https://datatracker.ietf.org/doc/html/rfc9562#name-uuid-version-7)), that sort by creation date
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UuidV7;
+
+impl UidGenerator for UuidV7 {
+    fn generate(&self) -> String {
+        let millis = Utc::now().timestamp_millis() as u64;
+        // The random bits are taken from a v4 UUID
+        let mut bytes = *uuid::Uuid::new_v4().as_bytes();
+        bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+        bytes[6] = (bytes[6] & 0x0f) | 0x70;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        uuid::Uuid::from_bytes(bytes).to_hyphenated().to_string()
+    }
+}
+
+/// UIDs made of a timestamp, a random part and a domain name, as suggested by [RFC 5545](https://datatracker.ietf.org/doc/html/rfc5545#section-3.8.4.7),
+/// e.g. `20240501T080045Z-4000f192@example.com`
+#[derive(Clone, Debug)]
+pub struct TimestampAtDomain {
+    domain: String,
+}
+
+impl TimestampAtDomain {
+    /// `domain` should be a domain name the app owns
+    pub fn new(domain: &str) -> Self {
+        Self { domain: domain.to_string() }
+    }
+}
+
+impl UidGenerator for TimestampAtDomain {
+    fn generate(&self) -> String {
+        let random = uuid::Uuid::new_v4().to_simple().to_string();
+        format!("{}-{}@{}", Utc::now().format("%Y%m%dT%H%M%SZ"), &random[..8], self.domain)
+    }
+}
+
+/// UIDs of another generator, with an app-specific prefix (e.g. `myapp-67e55044-10b1-426f-9247-bb680e5fe0c8`)
+#[derive(Clone, Debug)]
+pub struct Prefixed<G: UidGenerator> {
+    prefix: String,
+    inner: G,
+}
+
+impl<G: UidGenerator> Prefixed<G> {
+    pub fn new(prefix: &str, inner: G) -> Self {
+        Self { prefix: prefix.to_string(), inner }
+    }
+}
+
+impl<G: UidGenerator> UidGenerator for Prefixed<G> {
+    fn generate(&self) -> String {
+        format!("{}{}", self.prefix, self.inner.generate())
+    }
+}
+
+
+/// A user-provided function that makes the URL of a new item, see [`ItemUrls::Custom`]
+pub type ItemUrlFn = Arc<dyn Fn(&Url, &str) -> Url + Send + Sync>;
+
+/// How the URL of a new item is derived from its calendar URL and its UID
+#[derive(Clone, Default)]
+pub enum ItemUrls {
+    /// A random name, unrelated to the UID. This is the default
+    #[default]
+    Random,
+    /// `<uid>.ics`, percent-encoded if needed
+    FromUid,
+    /// A user-provided function, that receives the calendar URL and the UID of the item. The URL it returns must be in the calendar
+    Custom(ItemUrlFn),
+}
+
+impl Debug for ItemUrls {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Random => write!(f, "Random"),
+            Self::FromUid => write!(f, "FromUid"),
+            Self::Custom(_) => write!(f, "Custom(<function>)"),
+        }
+    }
+}
+
+impl ItemUrls {
+    pub fn url(&self, calendar_url: &Url, uid: &str) -> Url {
+        match self {
+            Self::Random => random_url(calendar_url),
+            Self::FromUid => {
+                let mut url = calendar_url.clone();
+                url.path_segments_mut()
+                    .map(|mut segments| { segments.pop_if_empty().push(&format!("{}.ics", uid)); })
+                    .unwrap_or_else(|_| log::warn!("Calendar URL {} cannot have items", calendar_url));
+                url
+            },
+            Self::Custom(f) => f(calendar_url, uid),
+        }
+    }
+}
+
+
+/// A new UID, from the configured [`UID_GENERATOR`]
+pub fn new_uid() -> String {
+    UID_GENERATOR.lock().unwrap().generate()
+}
+
+/// The URL of a new item of the calendar at `calendar_url`, according to the configured [`ITEM_URLS`]
+pub fn new_item_url(calendar_url: &Url, uid: &str) -> Url {
+    ITEM_URLS.lock().unwrap().url(calendar_url, uid)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generators() {
+        let v7 = UuidV7.generate();
+        assert_eq!(v7.len(), 36);
+        assert_eq!(&v7[14..15], "7");
+        // The random part is not ordered
+        assert!(v7[..13] <= UuidV7.generate()[..13]);
+
+        let rfc = TimestampAtDomain::new("example.com").generate();
+        assert!(rfc.ends_with("@example.com"));
+        assert_eq!(rfc.find('-'), Some(16));
+
+        assert!(Prefixed::new("myapp-", UuidV4).generate().starts_with("myapp-"));
+        assert_ne!(UuidV4.generate(), UuidV4.generate());
+    }
+
+    #[test]
+    fn test_item_urls() {
+        let cal: Url = "https://some.server/cal/".parse().unwrap();
+        assert_eq!(ItemUrls::FromUid.url(&cal, "abc@example.com").as_str(), "https://some.server/cal/abc@example.com.ics");
+        assert_eq!(ItemUrls::FromUid.url(&cal, "a/b c").as_str(), "https://some.server/cal/a%2Fb%20c.ics");
+        assert!(ItemUrls::Random.url(&cal, "abc").as_str().starts_with(cal.as_str()));
+
+        let custom = ItemUrls::Custom(Arc::new(|cal, uid| cal.join(&format!("tasks-{}", uid)).unwrap()));
+        assert_eq!(custom.url(&cal, "abc").as_str(), "https://some.server/cal/tasks-abc");
+    }
+}
@@ -155,3 +155,58 @@ pub fn random_url(parent_calendar: &Url) -> Url {
     let random = uuid::Uuid::new_v4().to_hyphenated().to_string();
     parent_calendar.join(&random).unwrap(/* this cannot panic since we've just created a string that is a valid URL */)
 }
+
+/// Whether two URLs point to the same resource, even though they may percent-encode their paths differently
+/// (e.g. `https://server/cal/a%40b.ics` and `https://server/cal/a@b.ics`). \
+/// Servers are free to name their items, and some of them do not return hrefs the way they have been requested
+pub fn same_resource(a: &Url, b: &Url) -> bool {
+    a == b || (
+           a.scheme() == b.scheme()
+        && a.host_str() == b.host_str()
+        && a.port_or_known_default() == b.port_or_known_default()
+        && decoded_path(a) == decoded_path(b)
+    )
+}
+
+fn decoded_path(url: &Url) -> std::borrow::Cow<'_, str> {
+    percent_encoding::percent_decode_str(url.path()).decode_utf8_lossy()
+}
+
+/// Escape a text so that it can be put into an XML element or attribute
+pub fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_resource() {
+        let url = |s: &str| -> Url { s.parse().unwrap() };
+        assert!(same_resource(&url("https://server/cal/a%40b.ics"), &url("https://server/cal/a@b.ics")));
+        assert!(same_resource(&url("https://server:443/cal/item"), &url("https://server/cal/item")));
+        assert!(same_resource(&url("https://server/cal/%C3%A9t%C3%A9"), &url("https://server/cal/été")));
+        assert!(!same_resource(&url("https://server/cal/a.ics"), &url("https://server/cal/b.ics")));
+        assert!(!same_resource(&url("https://server/cal/a.ics"), &url("https://other.server/cal/a.ics")));
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(xml_escape("Groceries"), "Groceries");
+        assert_eq!(xml_escape(r#"<b>"Tom" & Jerry's</b>"#), "&lt;b&gt;&quot;Tom&quot; &amp; Jerry&apos;s&lt;/b&gt;");
+    }
+}
@@ -0,0 +1,159 @@
+//! This module handles conversion between vCard files (in their 3.0 or 4.0 versions) and [`Contact`]s
+
+
+use chrono::Utc;
+use ical::property::Property;
+use url::Url;
+
+use crate::contact::Contact;
+use crate::item::SyncStatus;
+use crate::error::KFError;
+
+/// Whether a file is a vCard rather than an iCal file
+pub(crate) fn is_vcard(content: &str) -> bool {
+    content.trim_start().get(..11).is_some_and(|start| start.eq_ignore_ascii_case("BEGIN:VCARD"))
+}
+
+/// Parse the first vCard of a file into a [`Contact`]
+///
+/// vCards that have no `UID` (which is optional in vCard 3.0) are given the name of their file as UID
+pub fn parse(content: &str, item_url: Url, sync_status: SyncStatus) -> Result<Contact, KFError> {
+    let mut reader = ical::VcardParser::new(content.as_bytes());
+    let card = match reader.next() {
+        None => return Err(KFError::ical_parse(&item_url, "no vCard found")),
+        Some(Err(err)) => return Err(KFError::from_parser(Some(&item_url), err)),
+        Some(Ok(card)) => card,
+    };
+
+    let mut name = None;
+    let mut uid = None;
+    let mut version = None;
+    let mut prod_id = None;
+    let mut last_modified = None;
+    let mut extra_parameters = Vec::new();
+    for prop in card.properties {
+        match prop.name.to_uppercase().as_str() {
+            "FN" => name = prop.value.as_deref().map(unescape),
+            "UID" => uid = prop.value,
+            "VERSION" => version = prop.value,
+            "PRODID" => prod_id = prop.value,
+            // vCard 3.0 allows ISO 8601 extended dates, e.g. `2021-03-01T10:00:00Z`
+            "REV" => last_modified = prop.value.map(|rev| rev.replace(&['-', ':'][..], "")).as_deref().and_then(crate::ical::parse_date_value),
+            _ => extra_parameters.push(prop),
+        }
+    }
+
+    let uid = match uid {
+        Some(uid) => uid,
+        None => item_url.path_segments().and_then(|mut segments| segments.next_back())
+            .map(|file_name| file_name.trim_end_matches(".vcf").to_string())
+            .ok_or_else(|| KFError::ical_parse(&item_url, "missing UID"))?,
+    };
+    Ok(Contact::new_with_parameters(
+        name.unwrap_or_default(),
+        uid,
+        item_url,
+        sync_status,
+        last_modified.unwrap_or_else(Utc::now),
+        version.unwrap_or_else(|| crate::contact::DEFAULT_VCARD_VERSION.to_string()),
+        prod_id.unwrap_or_else(crate::ical::default_prod_id),
+        extra_parameters,
+    ))
+}
+
+/// Create a vCard file from a contact
+pub fn build_from_contact(contact: &Contact) -> String {
+    let mut lines = vec![
+        "BEGIN:VCARD".to_string(),
+        format!("VERSION:{}", contact.version()),
+        format!("PRODID:{}", contact.ical_prod_id()),
+        format!("UID:{}", contact.uid()),
+        format!("FN:{}", escape(contact.name())),
+        format!("REV:{}", contact.last_modified().format("%Y%m%dT%H%M%SZ")),
+    ];
+    lines.extend(contact.extra_parameters().iter().map(property_line));
+    lines.push("END:VCARD".to_string());
+
+    let mut vcard = String::new();
+    for line in lines {
+        vcard.push_str(&fold(&line));
+        vcard.push_str("\r\n");
+    }
+    vcard
+}
+
+fn property_line(prop: &Property) -> String {
+    let mut line = prop.name.clone();
+    for (param, values) in prop.params.iter().flatten() {
+        line.push_str(&format!(";{}={}", param, values.join(",")));
+    }
+    line.push(':');
+    line.push_str(prop.value.as_deref().unwrap_or_default());
+    line
+}
+
+/// Split a line into lines of 75 octets at most, as required by vCard files
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut line_len = 0;
+    for c in line.chars() {
+        if line_len + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            line_len = 1;
+        }
+        folded.push(c);
+        line_len += c.len_utf8();
+    }
+    folded
+}
+
+/// Escape a text value
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(',', "\\,").replace(';', "\\;").replace('\n', "\\n")
+}
+
+/// Unescape a text value
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(other) => unescaped.push(other),
+                None => unescaped.push('\\'),
+            },
+            (c, false) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vcard_round_trip() {
+        let vcard = "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe;John;;;\r\nFN:Doe\\, John\r\nEMAIL;TYPE=INTERNET,HOME:john@example.com\r\nTEL;TYPE=CELL:+33 6 00 00 00 00\r\nREV:2021-03-01T10:00:00Z\r\nEND:VCARD\r\n";
+        let url: Url = "https://some.server/contacts/john-doe.vcf".parse().unwrap();
+        assert!(is_vcard(vcard));
+        let contact = parse(vcard, url, SyncStatus::NotSynced).unwrap();
+        assert_eq!(contact.uid(), "john-doe");
+        assert_eq!(contact.name(), "Doe, John");
+        assert_eq!(contact.version(), "3.0");
+        assert_eq!(contact.emails(), vec!["john@example.com"]);
+        assert_eq!(contact.phone_numbers(), vec!["+33 6 00 00 00 00"]);
+        assert_eq!(contact.last_modified(), &"2021-03-01T10:00:00Z".parse::<chrono::DateTime<Utc>>().unwrap());
+
+        let built = build_from_contact(&contact);
+        assert!(built.starts_with("BEGIN:VCARD\r\nVERSION:3.0\r\n"));
+        assert!(built.contains("\r\nFN:Doe\\, John\r\n"));
+        assert!(built.contains("\r\nEMAIL;TYPE=INTERNET,HOME:john@example.com\r\n"));
+        let reparsed = parse(&built, contact.url().clone(), SyncStatus::NotSynced).unwrap();
+        assert!(reparsed.content_eq(&contact));
+        assert_eq!(reparsed.last_modified(), contact.last_modified());
+    }
+}
@@ -0,0 +1,132 @@
+//! This module provides a source of calendars that are stored in a local [vdir](https://vdirsyncer.pimutils.org/en/stable/vdir.html) (see [`Vdir`])
+
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use csscolorparser::Color;
+use url::Url;
+
+use crate::calendar::vdir_calendar::VdirCalendar;
+use crate::calendar::SupportedComponents;
+use crate::calendar::CalendarTimezone;
+use crate::client::ServerCapabilities;
+use crate::traits::CalDavSource;
+use crate::traits::DavCalendar;
+use crate::error::KFError;
+
+/// A folder that contains one subfolder per calendar, each of them containing one iCal file per item.
+///
+/// This is the layout vdirsyncer uses, and that khal or todoman read. \
+/// This source can be used instead of a [`Client`](crate::Client) in a [`Provider`](crate::provider::Provider) (see [`VdirProvider`](crate::VdirProvider)),
+/// so that apps can work with local files only, and still have a local cache with sync statuses, history, etc.
+/// Files that are changed by other apps are synced like changes made on a server.
+///
+/// To write a CalDAV server to a vdir instead, use a [`Cache`](crate::Cache) with a [`CacheLayout::FilePerItem`](crate::cache::storage::CacheLayout::FilePerItem) layout.
+/// Only tasks are supported for now.
+///
+/// The files of items are named after their URLs, that must end with `.ics`: apps that create items should set [`config::ITEM_URLS`](crate::config::ITEM_URLS) to [`ItemUrls::FromUid`](crate::uid::ItemUrls::FromUid)
+#[derive(Debug)]
+pub struct Vdir {
+    folder: PathBuf,
+    calendars: Mutex<HashMap<Url, Arc<Mutex<VdirCalendar>>>>,
+}
+
+impl Vdir {
+    /// Open a vdir, that is created if it does not exist yet
+    pub fn new(folder: &Path) -> Result<Self, KFError> {
+        std::fs::create_dir_all(folder)?;
+        let folder = std::fs::canonicalize(folder)?;
+        let vdir = Self { folder, calendars: Mutex::new(HashMap::new()) };
+        vdir.scan()?;
+        Ok(vdir)
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// The URL of the calendar that is (or would be) stored in a given subfolder
+    pub fn calendar_url(&self, folder_name: &str) -> Url {
+        let folder = self.folder.join(sanitize_filename::sanitize(folder_name));
+        // The folder is absolute, since it has been canonicalized
+        Url::from_directory_path(folder).unwrap()
+    }
+
+    /// Look for calendars that have been added or removed by other apps
+    fn scan(&self) -> Result<(), KFError> {
+        let mut found = HashSet::new();
+        for dir_entry in std::fs::read_dir(&self.folder)? {
+            let path = dir_entry?.path();
+            let is_hidden = path.file_name().and_then(|name| name.to_str()).is_none_or(|name| name.starts_with('.'));
+            if !path.is_dir() || is_hidden {
+                continue;
+            }
+            let url = Url::from_directory_path(&path).map_err(|_| format!("Invalid folder name {:?}", path))?;
+            found.insert(url);
+        }
+
+        let mut calendars = self.calendars.lock().unwrap();
+        calendars.retain(|url, _| found.contains(url));
+        for url in found {
+            if let Entry::Vacant(entry) = calendars.entry(url.clone()) {
+                entry.insert(Arc::new(Mutex::new(VdirCalendar::load(url)?)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CalDavSource<VdirCalendar> for Vdir {
+    async fn get_calendars(&self) -> Result<HashMap<Url, Arc<Mutex<VdirCalendar>>>, KFError> {
+        self.scan()?;
+        Ok(self.calendars.lock().unwrap().clone())
+    }
+
+    async fn get_calendar(&self, url: &Url) -> Option<Arc<Mutex<VdirCalendar>>> {
+        self.calendars.lock().unwrap().get(url).cloned()
+    }
+
+    async fn create_calendar(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>) -> Result<Arc<Mutex<VdirCalendar>>, KFError> {
+        if let Some(calendar) = self.get_calendar(&url).await {
+            return Ok(calendar);
+        }
+        let folder = url.to_file_path().ok()
+            .filter(|folder| folder.parent() == Some(self.folder.as_path()))
+            .ok_or_else(|| format!("Calendar {} cannot be created in vdir {:?}: its URL must be one of its folders (see Vdir::calendar_url)", url, self.folder))?;
+        if !supported_components.contains(SupportedComponents::TODO) {
+            return Err(format!("Calendar {} cannot be created in a vdir: only tasks are supported", url).into());
+        }
+
+        std::fs::create_dir_all(&folder)?;
+        let mut calendar = VdirCalendar::load(url.clone())?;
+        calendar.update_name(name).await?;
+        calendar.update_color(color).await?;
+        let calendar = Arc::new(Mutex::new(calendar));
+        self.calendars.lock().unwrap().insert(url, calendar.clone());
+        Ok(calendar)
+    }
+
+    async fn create_calendar_with_timezone(&mut self, url: Url, name: String, supported_components: SupportedComponents, color: Option<Color>, _timezone: Option<CalendarTimezone>) -> Result<Arc<Mutex<VdirCalendar>>, KFError> {
+        // Time zones cannot be stored in a vdir
+        self.create_calendar(url, name, supported_components, color).await
+    }
+
+    async fn server_capabilities(&self) -> Result<ServerCapabilities, KFError> {
+        Ok(ServerCapabilities::default())
+    }
+
+    async fn save(&self) -> Result<(), KFError> {
+        // Every change is written right away
+        Ok(())
+    }
+
+    async fn save_calendar(&self, _url: &Url) -> Result<(), KFError> {
+        // Every change is written right away
+        Ok(())
+    }
+}
@@ -10,7 +10,6 @@
 
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use std::error::Error;
 use url::Url;
 
 use chrono::Utc;
@@ -29,6 +28,7 @@ use kitchen_fridge::calendar::cached_calendar::CachedCalendar;
 use kitchen_fridge::provider::Provider;
 use kitchen_fridge::mock_behaviour::MockBehaviour;
 use kitchen_fridge::utils::random_url;
+use kitchen_fridge::error::KFError;
 
 pub enum LocatedState {
     /// Item does not exist yet or does not exist anymore
@@ -51,6 +51,7 @@ pub struct ItemState {
     completed: bool,
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum ChangeToApply {
     Rename(String),
     SetCompletion(bool),
@@ -78,11 +79,12 @@ pub struct ItemScenario {
 ///     * server: A,    C, D,  E', F',  G✓, H , I',      K✓,    M✓, N , O, P✓,  Q
 ///
 /// Hence, here is the expected result after the sync:
-///     * both:   A,       D', E', F',  G✓, H✓, I',      K✓,   M, N, O, P', Q, R
+///     * both:   A,       D', E', F',  G✓, H✓, I'✓,     K✓,   M, N, O, P', Q, R
 ///
 /// Notes:
 /// * X': name has been modified since the last sync
 /// * X'/X'': name conflict
+/// * I'/I✓: conflict on distinct properties, that are merged
 /// * X✓: task has been marked as completed
 pub fn scenarii_basic() -> Vec<ItemScenario> {
     let mut tasks = Vec::new();
@@ -238,11 +240,11 @@ pub fn scenarii_basic() -> Vec<ItemScenario> {
             }),
             local_changes_to_apply: vec![ChangeToApply::SetCompletion(true)],
             remote_changes_to_apply: vec![ChangeToApply::Rename(String::from("Task I, remotely renamed"))],
-            // Conflict, the server wins
+            // Conflict on distinct properties: both changes are merged
             after_sync: LocatedState::BothSynced( ItemState{
                 calendar: second_cal.clone(),
                 name: String::from("Task I, remotely renamed"),
-                completed: false,
+                completed: true,
             }),
         }
     );
@@ -655,7 +657,10 @@ async fn populate_test_provider(scenarii: &[ItemScenario], mock_behaviour: Arc<M
                 get_or_insert_calendar(&mut remote, &s.calendar).await.unwrap().lock().unwrap().add_item(new_item).await.unwrap();
             },
             LocatedState::BothSynced(s) => {
-                get_or_insert_calendar(&mut local,  &s.calendar).await.unwrap().lock().unwrap().add_item(new_item.clone()).await.unwrap();
+                let local_cal = get_or_insert_calendar(&mut local,  &s.calendar).await.unwrap();
+                // The previous sync has recorded this version as the base of future merges
+                local_cal.lock().unwrap().set_base_version(&item.url, Some(new_item.clone()));
+                local_cal.lock().unwrap().add_item(new_item.clone()).await.unwrap();
                 get_or_insert_calendar(&mut remote, &s.calendar).await.unwrap().lock().unwrap().add_item(new_item).await.unwrap();
             },
         }
@@ -687,7 +692,7 @@ async fn apply_changes_on_provider(provider: &mut Provider<Cache, CachedCalendar
 }
 
 async fn get_or_insert_calendar(source: &mut Cache, url: &Url)
-    -> Result<Arc<Mutex<CachedCalendar>>, Box<dyn Error>>
+    -> Result<Arc<Mutex<CachedCalendar>>, KFError>
 {
     match source.get_calendar(url).await {
         Some(cal) => Ok(cal),
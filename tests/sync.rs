@@ -209,7 +209,7 @@ impl TestFlavour {
         self.mock_behaviour.lock().unwrap().resume();
         for attempt in 0..max_attempts {
             println!("\nSyncing...\n");
-            if provider.sync().await == true {
+            if provider.sync().await.is_success() {
                 println!("Sync complete after {} attempts (multiple attempts are due to forced errors in mocked behaviour)", attempt+1);
                 break
             }
@@ -338,6 +338,2833 @@ async fn test_errors_in_regular_sync12() {
     run_flavour(TestFlavour::normal_with_errors12(), 100).await;
 }
 
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_sync_progress_events() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use kitchen_fridge::provider::sync_progress::{progress_channel, ProgressEvent};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+
+        let (sender, mut receiver) = progress_channel();
+        assert!(provider.sync_with_progress(sender).await.is_success());
+
+        let mut events = Vec::new();
+        while let Some(event) = receiver.recv().await {
+            events.push(event);
+        }
+        let started = events.iter().filter(|ev| matches!(ev, ProgressEvent::CalendarStarted{..})).count();
+        let finished = events.iter().filter(|ev| matches!(ev, ProgressEvent::CalendarFinished{..})).count();
+        assert_eq!(started, 3);
+        assert_eq!(finished, 3);
+        assert!(events.iter().any(|ev| matches!(ev, ProgressEvent::ItemDownloaded{ bytes, .. } if *bytes > 0)));
+        assert!(events.iter().any(|ev| matches!(ev, ProgressEvent::ItemUploaded{ bytes, .. } if *bytes > 0)));
+        assert!(events.iter().any(|ev| matches!(ev, ProgressEvent::ItemDeleted{ remote: true, .. })));
+        assert!(events.iter().any(|ev| matches!(ev, ProgressEvent::ItemDeleted{ remote: false, .. })));
+        assert!(!events.iter().any(|ev| matches!(ev, ProgressEvent::Error{..})));
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_sync_result() {
+    #[cfg(feature = "integration_tests")]
+    {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+
+        let result = provider.sync().await;
+        assert!(result.is_success());
+        assert_eq!(result.calendars.len(), 3);
+        assert_eq!(result.conflicts(), 5);
+        let summary = result.to_string();
+        assert!(summary.starts_with("Sync succeeded in "));
+        assert!(summary.ends_with(", 5 conflict(s), 0 error(s)"));
+        assert!(result.local_changes().total() > 0);
+        assert!(result.remote_changes().total() > 0);
+        assert!(result.calendars.iter().all(|cal| cal.bytes_downloaded > 0));
+
+        let result = provider.sync().await;
+        assert!(result.is_success());
+        assert_eq!(result.local_changes().total() + result.remote_changes().total(), 0);
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_plan_sync() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use kitchen_fridge::provider::plan::SyncAction;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let scenarii = scenarii::scenarii_basic();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii, Arc::clone(&mock_behaviour)).await;
+
+        let plan = provider.plan_sync().await.unwrap();
+        assert_eq!(plan.calendars.len(), 3);
+        assert!(plan.actions().any(|action| matches!(action, SyncAction::Download(_))));
+        assert!(plan.actions().any(|action| matches!(action, SyncAction::Upload(_))));
+        assert!(plan.actions().any(|action| matches!(action, SyncAction::DeleteRemote(_))));
+        assert!(plan.actions().any(|action| matches!(action, SyncAction::Conflict(_))));
+
+        // Planning must not have changed anything
+        assert!(provider.sync().await.is_success());
+        let expected_provider = scenarii::populate_test_provider_after_sync(&scenarii, mock_behaviour).await;
+        assert!(provider.local().has_same_observable_content_as(expected_provider.local()).await.unwrap());
+
+        assert!(provider.plan_sync().await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_sync_filter() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use kitchen_fridge::provider::filter::SyncFilter;
+
+        async fn item_names(source: &Cache) -> Vec<String> {
+            let mut names = Vec::new();
+            for (_, cal) in source.get_calendars().await.unwrap() {
+                for (_, item) in cal.lock().unwrap().get_items_sync().unwrap() {
+                    names.push(item.name().to_string());
+                }
+            }
+            names
+        }
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+
+        // No calendar contains events: nothing is synced
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), Arc::clone(&mock_behaviour)).await;
+        let local_before = item_names(provider.local()).await;
+        provider.set_sync_filter(SyncFilter::events_only());
+        assert!(provider.sync().await.is_success());
+        assert_eq!(item_names(provider.local()).await.len(), local_before.len());
+
+        // Every remote item has been created before this time window: new remote items are not downloaded, but no data is deleted
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        provider.set_sync_filter(SyncFilter::tasks_only().since(chrono::Utc::now() + chrono::Duration::days(1)));
+        assert!(provider.sync().await.is_success());
+        let local_names = item_names(provider.local()).await;
+        let remote_names = item_names(provider.remote()).await;
+        assert!(!local_names.iter().any(|name| name.starts_with("Task Q")));
+        assert!(remote_names.iter().any(|name| name.starts_with("Task Q")));
+        assert!(remote_names.iter().any(|name| name.starts_with("Task R")));
+        assert!(local_names.iter().any(|name| name == "Task A"));
+        assert!(remote_names.iter().any(|name| name == "Task A"));
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_max_concurrent_transfers() {
+    #[cfg(feature = "integration_tests")]
+    {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let scenarii = scenarii::scenarii_basic();
+
+        // Whatever the number of concurrent transfers, the outcome must be the same
+        for max_concurrent_transfers in [1, 2, 100] {
+            let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+            let mut provider = scenarii::populate_test_provider_before_sync(&scenarii, Arc::clone(&mock_behaviour)).await;
+            provider.set_max_concurrent_transfers(max_concurrent_transfers);
+            assert!(provider.sync().await.is_success());
+
+            let expected_provider = scenarii::populate_test_provider_after_sync(&scenarii, mock_behaviour).await;
+            assert!(provider.local() .has_same_observable_content_as(expected_provider.local() ).await.unwrap());
+            assert!(provider.remote().has_same_observable_content_as(expected_provider.remote()).await.unwrap());
+        }
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_move_item() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+
+        fn items_of(source: &Cache, cal_url: &Url) -> Vec<Item> {
+            let cal = source.get_calendar_sync(cal_url).unwrap();
+            let cal = cal.lock().unwrap();
+            cal.get_items_sync().unwrap().into_values().cloned().collect()
+        }
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let second_cal: Url = "https://some.calend.ar/calendar-2/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        assert!(provider.sync().await.is_success());
+
+        // A synced item is moved on the server as well (the mocked server does not support MOVE, so the item is copied then deleted)
+        let item = items_of(provider.local(), &first_cal).pop().unwrap();
+        let new_url = provider.move_item(item.url(), &second_cal).await.unwrap();
+        assert!(new_url.as_str().starts_with(second_cal.as_str()));
+        for source in [provider.local(), provider.remote()] {
+            assert!(!items_of(source, &first_cal).iter().any(|i| i.uid() == item.uid()));
+            let moved: Vec<Item> = items_of(source, &second_cal).into_iter().filter(|i| i.uid() == item.uid()).collect();
+            assert_eq!(moved.len(), 1);
+            assert_eq!(moved[0].url(), &new_url);
+            assert_eq!(moved[0].name(), item.name());
+        }
+
+        // An item that has not been synced yet is only moved locally
+        let new_task = Item::Task(Task::new("A new task".to_string(), false, &first_cal));
+        let new_task_url = new_task.url().clone();
+        provider.local().get_calendar_sync(&first_cal).unwrap().lock().unwrap().add_item_sync(new_task).unwrap();
+        let new_url = provider.move_item(&new_task_url, &second_cal).await.unwrap();
+        assert!(!items_of(provider.remote(), &second_cal).iter().any(|i| i.url() == &new_url));
+
+        assert!(provider.sync().await.is_success());
+        assert!(items_of(provider.remote(), &second_cal).iter().any(|i| i.url() == &new_url));
+        assert!(provider.remote().has_same_observable_content_as(provider.local()).await.unwrap());
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_move_item_keeps_alarms() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::alarm::{Alarm, AlarmTrigger};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let second_cal: Url = "https://some.calend.ar/calendar-2/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        let mut task = Task::new("Task with an alarm".to_string(), false, &first_cal);
+        task.add_alarm(Alarm::display(AlarmTrigger::before_start(chrono::Duration::minutes(15)), "Soon"));
+        task.dismiss(0).unwrap();
+        let alarms = task.alarms().to_vec();
+        let task_url = task.url().clone();
+        provider.local().get_calendar_sync(&first_cal).unwrap().lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        assert!(provider.sync().await.is_success());
+
+        let new_url = provider.move_item(&task_url, &second_cal).await.unwrap();
+        assert!(provider.sync().await.is_success());
+        for source in [provider.local(), provider.remote()] {
+            let cal = source.get_calendar_sync(&second_cal).unwrap();
+            let cal = cal.lock().unwrap();
+            assert_eq!(cal.get_item_by_url_sync(&new_url).unwrap().unwrap_task().alarms(), &alarms[..]);
+        }
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_merge_duplicates() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::provider::duplicates::DuplicateCriteria;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        assert!(provider.sync().await.is_success());
+        let criteria = DuplicateCriteria::default();
+        assert!(provider.find_duplicates(&criteria).await.unwrap().is_empty());
+
+        // Create the same task twice, and sync both
+        let cal = provider.local().get_calendar_sync(&first_cal).unwrap();
+        for _ in 0..2 {
+            cal.lock().unwrap().add_item_sync(Item::Task(Task::new("Duplicated task".to_string(), false, &first_cal))).unwrap();
+        }
+        assert!(provider.sync().await.is_success());
+
+        let groups = provider.find_duplicates(&criteria).await.unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].duplicates.len(), 1);
+        assert_eq!(provider.merge_duplicates(&groups).await.unwrap(), 1);
+        assert!(provider.sync().await.is_success());
+
+        assert!(provider.find_duplicates(&criteria).await.unwrap().is_empty());
+        let remote_cal = provider.remote().get_calendar_sync(&first_cal).unwrap();
+        let remote_cal = remote_cal.lock().unwrap();
+        assert!(remote_cal.get_item_by_url_sync(&groups[0].keep).is_some());
+        assert!(remote_cal.get_item_by_url_sync(&groups[0].duplicates[0]).is_none());
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_resume_interrupted_sync() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::provider::journal::SyncJournal;
+        use kitchen_fridge::traits::CompleteCalendar;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        assert!(provider.sync().await.is_success());
+
+        // A task created on the server, and another one created locally
+        let mut remote_task = Task::new("Remote task".to_string(), false, &first_cal);
+        remote_task.set_sync_status(SyncStatus::random_synced());
+        let remote_url = remote_task.url().clone();
+        provider.remote().get_calendar_sync(&first_cal).unwrap()
+            .lock().unwrap().add_item_sync(Item::Task(remote_task)).unwrap();
+        let local_task = Task::new("Local task".to_string(), false, &first_cal);
+        let local_url = local_task.url().clone();
+        let local_cal = provider.local().get_calendar_sync(&first_cal).unwrap();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(local_task)).unwrap();
+
+        // A sync that has been interrupted before downloading the remote task
+        let mut journal = SyncJournal::new();
+        journal.remote_additions.insert(remote_url.clone());
+        *local_cal.lock().unwrap().sync_journal_mut() = Some(journal);
+
+        // Only the remaining operations are resumed
+        assert!(provider.sync().await.is_success());
+        assert!(local_cal.lock().unwrap().sync_journal().is_none());
+        assert!(local_cal.lock().unwrap().get_item_by_url_sync(&remote_url).is_some());
+        let remote_cal = provider.remote().get_calendar_sync(&first_cal).unwrap();
+        assert!(remote_cal.lock().unwrap().get_item_by_url_sync(&local_url).is_none());
+
+        // The next sync starts from scratch
+        assert!(provider.sync().await.is_success());
+        assert!(remote_cal.lock().unwrap().get_item_by_url_sync(&local_url).is_some());
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_read_only_calendar() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::traits::BaseCalendar;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        assert!(provider.sync().await.is_success());
+
+        provider.set_calendar_read_only(first_cal.clone(), true);
+        let task = Task::new("Local task".to_string(), false, &first_cal);
+        let task_url = task.url().clone();
+        let local_cal = provider.local().get_calendar_sync(&first_cal).unwrap();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+
+        // The local addition is kept locally
+        assert!(provider.sync().await.is_success());
+        assert!(!local_cal.lock().unwrap().can_write());
+        assert!(local_cal.lock().unwrap().get_item_by_url_sync(&task_url).is_some());
+        let remote_cal = provider.remote().get_calendar_sync(&first_cal).unwrap();
+        assert!(remote_cal.lock().unwrap().get_item_by_url_sync(&task_url).is_none());
+
+        // ...until the calendar is writable again
+        provider.set_calendar_read_only(first_cal.clone(), false);
+        assert!(provider.sync().await.is_success());
+        assert!(local_cal.lock().unwrap().can_write());
+        assert!(remote_cal.lock().unwrap().get_item_by_url_sync(&task_url).is_some());
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_read_only_flag() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::error::KFError;
+        use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&first_cal).unwrap();
+        let remote_cal = provider.remote().get_calendar_sync(&first_cal).unwrap();
+        let existing_url = local_cal.lock().unwrap().get_items_sync().unwrap().keys().next().unwrap().clone();
+
+        // Local changes cannot be staged
+        local_cal.lock().unwrap().set_read_only(true);
+        assert!(local_cal.lock().unwrap().is_read_only());
+        let err = local_cal.lock().unwrap().add_item_sync(Item::Task(Task::new("Local task".to_string(), false, &first_cal))).unwrap_err();
+        assert!(matches!(err, KFError::ReadOnlyCalendar{ .. }));
+        let err = local_cal.lock().unwrap().mark_for_deletion_sync(&existing_url).unwrap_err();
+        assert!(matches!(err, KFError::ReadOnlyCalendar{ .. }));
+
+        // Remote changes are still downloaded
+        let remote_task = Task::new("Remote task".to_string(), false, &first_cal);
+        let remote_url = remote_task.url().clone();
+        remote_cal.lock().unwrap().add_item_sync(Item::Task(remote_task)).unwrap();
+        assert!(provider.sync().await.is_success());
+        assert!(local_cal.lock().unwrap().get_item_by_url_sync(&remote_url).is_some());
+        assert!(local_cal.lock().unwrap().is_read_only());
+
+        local_cal.lock().unwrap().set_read_only(false);
+        assert!(local_cal.lock().unwrap().mark_for_deletion_sync(&existing_url).is_ok());
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_conflicted_copy() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::provider::conflict::ConflictResolution;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        provider.set_conflict_resolution(ConflictResolution::KeepBoth);
+        let task = Task::new("Original name".to_string(), false, &first_cal);
+        let task_url = task.url().clone();
+        let local_cal = provider.local().get_calendar_sync(&first_cal).unwrap();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        assert!(provider.sync().await.is_success());
+
+        // Rename the task on both sides
+        let remote_cal = provider.remote().get_calendar_sync(&first_cal).unwrap();
+        match remote_cal.lock().unwrap().get_item_by_url_mut_sync(&task_url).unwrap() {
+            Item::Task(task) => task.mock_remote_calendar_set_name("Remote name".to_string()),
+            _ => panic!("Unexpected item"),
+        }
+        match local_cal.lock().unwrap().get_item_by_url_mut_sync(&task_url).unwrap() {
+            Item::Task(task) => task.set_name("Local name".to_string()),
+            _ => panic!("Unexpected item"),
+        }
+        assert!(provider.sync().await.is_success());
+
+        // Both versions exist on both sides
+        for cal in [local_cal, remote_cal] {
+            let cal = cal.lock().unwrap();
+            assert_eq!(cal.get_item_by_url_sync(&task_url).unwrap().name(), "Remote name");
+            let copies: Vec<String> = cal.get_items_sync().unwrap().values()
+                .map(|item| item.name().to_string())
+                .filter(|name| name.starts_with("Local name (conflicted copy "))
+                .collect();
+            assert_eq!(copies.len(), 1);
+        }
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_merge_keeps_alarms() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::alarm::{Alarm, AlarmTrigger};
+        use kitchen_fridge::task::CompletionStatus;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        let mut task = Task::new("Task with alarms".to_string(), false, &first_cal);
+        task.add_alarm(Alarm::display(AlarmTrigger::before_start(chrono::Duration::minutes(15)), "Soon"));
+        task.add_alarm(Alarm::display(AlarmTrigger::before_start(chrono::Duration::minutes(5)), "Very soon"));
+        let task_url = task.url().clone();
+        let local_cal = provider.local().get_calendar_sync(&first_cal).unwrap();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        assert!(provider.sync().await.is_success());
+
+        // The task is renamed on the server, while one of its alarms is dismissed locally
+        let remote_cal = provider.remote().get_calendar_sync(&first_cal).unwrap();
+        match remote_cal.lock().unwrap().get_item_by_url_mut_sync(&task_url).unwrap() {
+            Item::Task(task) => task.mock_remote_calendar_set_name("Remote name".to_string()),
+            _ => panic!("Unexpected item"),
+        }
+        let alarms = match local_cal.lock().unwrap().get_item_by_url_mut_sync(&task_url).unwrap() {
+            Item::Task(task) => {
+                task.dismiss(0).unwrap();
+                task.set_completion_status(CompletionStatus::Completed(None));
+                task.alarms().to_vec()
+            },
+            _ => panic!("Unexpected item"),
+        };
+        assert!(alarms[0].acknowledged().is_some());
+        assert!(provider.sync().await.is_success());
+
+        for cal in [local_cal, remote_cal] {
+            let cal = cal.lock().unwrap();
+            let task = cal.get_item_by_url_sync(&task_url).unwrap().unwrap_task();
+            assert_eq!(task.name(), "Remote name");
+            assert!(task.completed());
+            assert_eq!(task.alarms(), &alarms[..]);
+            assert!(task.alarms_last_acknowledged().is_some());
+        }
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_pending_changes() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::provider::pending::PendingChangeKind;
+        use kitchen_fridge::provider::sync_result::ChangeStatus;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        assert!(provider.sync().await.is_success());
+        assert!(provider.pending_changes().await.unwrap().is_empty());
+
+        // Changes made while offline are queued
+        let local_cal = provider.local().get_calendar_sync(&first_cal).unwrap();
+        let deleted_url = local_cal.lock().unwrap().get_item_urls_sync().unwrap().into_iter().next().unwrap();
+        let mut expected = Vec::new();
+        for name in ["First task", "Second task"] {
+            let task = Task::new(name.to_string(), false, &first_cal);
+            expected.push((task.url().clone(), PendingChangeKind::Addition));
+            local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        }
+        local_cal.lock().unwrap().mark_for_deletion_sync(&deleted_url).unwrap();
+        expected.push((deleted_url, PendingChangeKind::Deletion));
+
+        let pending: Vec<_> = provider.pending_changes().await.unwrap().into_iter()
+            .map(|change| (change.item, change.kind))
+            .collect();
+        assert_eq!(pending, expected);
+
+        // ...and replayed at the next sync
+        let result = provider.sync().await;
+        assert!(result.is_success());
+        let outcomes: Vec<_> = result.change_outcomes()
+            .map(|outcome| (outcome.item.clone(), outcome.kind))
+            .collect();
+        assert_eq!(outcomes.len(), expected.len());
+        for change in &expected {
+            assert!(outcomes.contains(change));
+        }
+        assert!(result.change_outcomes().all(|outcome| outcome.status == ChangeStatus::Pushed));
+        assert!(provider.pending_changes().await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_sync_hooks() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        assert!(provider.sync().await.is_success());
+
+        // "Encrypt" names on the server, and veto private tasks
+        provider.add_before_upload_hook(Arc::new(|item| Box::pin(async move {
+            match item {
+                Item::Task(task) if task.name() == "Private" => None,
+                Item::Task(mut task) => {
+                    task.set_name(format!("[enc] {}", task.name()));
+                    Some(Item::Task(task))
+                },
+                other => Some(other),
+            }
+        })));
+        let secret = Task::new("Secret".to_string(), false, &first_cal);
+        let private = Task::new("Private".to_string(), false, &first_cal);
+        let (secret_url, private_url) = (secret.url().clone(), private.url().clone());
+        let local_cal = provider.local().get_calendar_sync(&first_cal).unwrap();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(secret)).unwrap();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(private)).unwrap();
+        assert!(provider.sync().await.is_success());
+
+        let remote_cal = provider.remote().get_calendar_sync(&first_cal).unwrap();
+        assert_eq!(remote_cal.lock().unwrap().get_item_by_url_sync(&secret_url).unwrap().name(), "[enc] Secret");
+        assert!(remote_cal.lock().unwrap().get_item_by_url_sync(&private_url).is_none());
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&secret_url).unwrap().name(), "Secret");
+
+        // "Decrypt" names of downloaded items
+        provider.add_after_download_hook(Arc::new(|item| Box::pin(async move {
+            match item {
+                Item::Task(mut task) => {
+                    let name = task.name().trim_start_matches("[enc] ").to_string();
+                    task.set_name(name);
+                    Some(Item::Task(task))
+                },
+                other => Some(other),
+            }
+        })));
+        let mut remote_task = Task::new("[enc] From the server".to_string(), false, &first_cal);
+        remote_task.set_sync_status(kitchen_fridge::item::SyncStatus::random_synced());
+        let remote_url = remote_task.url().clone();
+        remote_cal.lock().unwrap().add_item_sync(Item::Task(remote_task)).unwrap();
+        assert!(provider.sync().await.is_success());
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&remote_url).unwrap().name(), "From the server");
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_mirror() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use std::path::Path;
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::calendar::SupportedComponents;
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::provider::mirror::Mirror;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let source_cal_url: Url = "https://source.serv.er/calendars/work/".parse().unwrap();
+        let target_cal_url: Url = "https://target.serv.er/cals/work/".parse().unwrap();
+
+        let mut source = Cache::new(Path::new("test_cache/mirror_source"));
+        source.set_mock_behaviour(Some(mock_behaviour.clone()));
+        let source_cal = source.create_calendar(source_cal_url.clone(), "Work".to_string(), SupportedComponents::TODO, None).await.unwrap();
+        let mut urls = Vec::new();
+        for name in ["First task", "Second task"] {
+            let mut task = Task::new(name.to_string(), false, &source_cal_url);
+            task.set_sync_status(SyncStatus::random_synced());
+            urls.push(task.url().clone());
+            source_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        }
+        let mut target = Cache::new(Path::new("test_cache/mirror_target"));
+        target.set_mock_behaviour(Some(mock_behaviour));
+
+        let mut mirror = Mirror::new(source, target);
+        mirror.set_target_calendar_home("https://target.serv.er/cals/".parse().unwrap());
+        let result = mirror.sync().await;
+        assert!(result.is_success());
+        assert_eq!(result.remote_changes().added, 2);
+        let target_cal = mirror.target().get_calendar_sync(&target_cal_url).unwrap();
+        assert_eq!(target_cal.lock().unwrap().get_items_sync().unwrap().len(), 2);
+
+        // Changes made on the target are synced back
+        let target_url = target_cal_url.join(urls[0].path_segments().unwrap().next_back().unwrap()).unwrap();
+        match target_cal.lock().unwrap().get_item_by_url_mut_sync(&target_url).unwrap() {
+            Item::Task(task) => task.mock_remote_calendar_set_name("Renamed on the target".to_string()),
+            _ => panic!("Unexpected item"),
+        }
+        // ...and deletions from the source are mirrored
+        source_cal.lock().unwrap().immediately_delete_item_sync(&urls[1]).unwrap();
+
+        let result = mirror.sync().await;
+        assert!(result.is_success());
+        assert_eq!(source_cal.lock().unwrap().get_item_by_url_sync(&urls[0]).unwrap().name(), "Renamed on the target");
+        assert_eq!(target_cal.lock().unwrap().get_items_sync().unwrap().len(), 1);
+
+        // Nothing has changed since
+        let result = mirror.sync().await;
+        assert!(result.is_success());
+        assert_eq!(result.remote_changes().total() + result.local_changes().total(), 0);
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_unsupported_components() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::calendar::SupportedComponents;
+        use kitchen_fridge::error::KFError;
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::traits::BaseCalendar;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let cal_url: Url = "https://some.calend.ar/events/".parse().unwrap();
+        let mut remote = Cache::new_in_memory();
+        remote.create_calendar(cal_url.clone(), "Events".to_string(), SupportedComponents::EVENT, None).await.unwrap();
+
+        // Local calendars refuse items they do not support
+        let mut local = Cache::new_in_memory();
+        let events_only = local.create_calendar(cal_url.clone(), "Events".to_string(), SupportedComponents::EVENT, None).await.unwrap();
+        assert!(events_only.lock().unwrap().supports_events());
+        assert!(!events_only.lock().unwrap().supports_tasks());
+        let err = events_only.lock().unwrap().add_item_sync(Item::Task(Task::new("A task".to_string(), false, &cal_url))).unwrap_err();
+        assert!(matches!(err, KFError::UnsupportedComponent{ .. }));
+
+        // The provider does not push items the server does not support, e.g. when the local calendar is out of date
+        let mut local = Cache::new_in_memory();
+        let local_cal = local.create_calendar(cal_url.clone(), "Events".to_string(), SupportedComponents::EVENT | SupportedComponents::TODO, None).await.unwrap();
+        let task = Task::new("A task".to_string(), false, &cal_url);
+        let task_url = task.url().clone();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+
+        let mut provider = Provider::new(remote, local);
+        provider.sync().await;
+        let remote_cal = provider.remote().get_calendar_sync(&cal_url).unwrap();
+        assert!(remote_cal.lock().unwrap().get_item_by_url_sync(&task_url).is_none());
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&task_url).unwrap().sync_status(), &SyncStatus::NotSynced);
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_multi_provider() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use kitchen_fridge::provider::multi::MultiProvider;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut multi = MultiProvider::new();
+        for account in ["personal", "work"] {
+            let provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), Arc::new(Mutex::new(MockBehaviour::new()))).await;
+            multi.add_account(account.to_string(), provider).unwrap();
+        }
+        let duplicate = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), Arc::new(Mutex::new(MockBehaviour::new()))).await;
+        assert!(multi.add_account("work".to_string(), duplicate).is_err());
+        assert_eq!(multi.account_names().collect::<Vec<_>>(), vec!["personal", "work"]);
+
+        let result = multi.sync_all().await;
+        assert!(result.is_success(), "{}", result);
+        assert_eq!(result.accounts.len(), 2);
+        assert_eq!(result.all_errors().count(), 0);
+
+        let calendars = multi.calendars().await.unwrap();
+        let per_account = |name: &str| calendars.iter().filter(|cal| cal.account == name).count();
+        assert!(per_account("personal") > 0);
+        assert_eq!(per_account("personal"), per_account("work"));
+
+        assert!(multi.remove_account("work").is_some());
+        assert!(multi.account("work").is_none());
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_audit_log() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::traits::CompleteCalendar;
+        use kitchen_fridge::item::{FieldChange, ItemField};
+        use kitchen_fridge::provider::audit::{AuditDirection, AuditOperation, AuditOutcome, AuditQuery};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        assert!(provider.sync().await.is_success());
+        assert!(provider.local().stats().last_successful_sync().is_some());
+        let first_sync = provider.local().audit_log(&AuditQuery::new());
+        assert!(!first_sync.is_empty());
+        assert!(first_sync.iter().all(|entry| entry.outcome == AuditOutcome::Done));
+
+        let local_cal = provider.local().get_calendar_sync(&first_cal).unwrap();
+        let task = Task::new("New task".to_string(), false, &first_cal);
+        let task_url = task.url().clone();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        assert!(provider.sync().await.is_success());
+
+        let mut query = AuditQuery::new();
+        query.set_item(Some(task_url.clone()));
+        let entries = provider.local().audit_log(&query);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].operation, AuditOperation::Addition);
+        assert_eq!(entries[0].direction, AuditDirection::Upload);
+        assert_eq!(entries[0].old_etag, None);
+        assert!(entries[0].new_etag.is_some());
+
+        // Downloaded modifications tell which fields have changed
+        let remote_cal = provider.remote().get_calendar_sync(&first_cal).unwrap();
+        match remote_cal.lock().unwrap().get_item_by_url_mut_sync(&task_url).unwrap() {
+            Item::Task(task) => task.mock_remote_calendar_set_name("Renamed task".to_string()),
+            _ => panic!("Unexpected item"),
+        }
+        assert!(provider.sync().await.is_success());
+        let entries = provider.local().audit_log(&query);
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].changes.is_empty());
+        assert_eq!(entries[1].direction, AuditDirection::Download);
+        assert_eq!(entries[1].changes, vec![FieldChange{ field: ItemField::Name, old: Some("New task".to_string()), new: Some("Renamed task".to_string()) }]);
+
+        // Each calendar keeps its own log
+        let cal = local_cal.lock().unwrap();
+        let history: Vec<_> = cal.audit_log().item_history(&task_url).collect();
+        assert_eq!(history.len(), 2);
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_eviction() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use kitchen_fridge::cache::eviction::EvictionPolicy;
+        use kitchen_fridge::traits::CompleteCalendar;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        assert!(provider.sync().await.is_success());
+
+        // Completed tasks that have no date are evicted first
+        let mut policy = EvictionPolicy::new();
+        policy.set_max_items(Some(0));
+        let mut evicted = Vec::new();
+        for cal in provider.local().get_calendars_sync().unwrap().values() {
+            let mut cal = cal.lock().unwrap();
+            cal.evict(&policy);
+            evicted.extend(cal.evicted_items().keys().cloned());
+        }
+        assert!(!evicted.is_empty());
+
+        // Evicted items are not downloaded again...
+        let result = provider.sync().await;
+        assert!(result.is_success());
+        assert_eq!(result.local_changes().total(), 0);
+        for cal in provider.local().get_calendars_sync().unwrap().values() {
+            assert!(cal.lock().unwrap().evicted_version_tags().keys().all(|url| evicted.contains(url)));
+        }
+
+        // ...unless they are requested
+        provider.fetch_evicted_item(&evicted[0]).await.unwrap();
+        assert!(provider.fetch_evicted_item(&evicted[0]).await.is_err());
+        let cal_url = provider.local().get_calendars_sync().unwrap().into_iter()
+            .find(|(_, cal)| cal.lock().unwrap().get_item_by_url_sync(&evicted[0]).is_some())
+            .map(|(url, _)| url);
+        assert!(cal_url.is_some());
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_unchanged_calendars_are_skipped() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::{Item, Task};
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::traits::CompleteCalendar;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour.clone()).await;
+        assert!(provider.sync().await.is_success());
+        // The items this sync has uploaded have changed the remote calendars, that are compared again once
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&first_cal).unwrap();
+        assert!(local_cal.lock().unwrap().synced_remote_version().is_some());
+
+        // Items are not even listed when nothing has changed
+        *mock_behaviour.lock().unwrap() = MockBehaviour{
+            get_item_version_tags_behaviour: (0, 10),
+            ..MockBehaviour::default()
+        };
+        assert!(provider.sync().await.is_success());
+
+        // A change on the server is noticed...
+        let mut remote_task = Task::new("Remote task".to_string(), false, &first_cal);
+        remote_task.set_sync_status(SyncStatus::random_synced());
+        let remote_url = remote_task.url().clone();
+        provider.remote().get_calendar_sync(&first_cal).unwrap()
+            .lock().unwrap().add_item_sync(Item::Task(remote_task)).unwrap();
+        assert!(!provider.sync().await.is_success());
+
+        // ...and so is a local change
+        *mock_behaviour.lock().unwrap() = MockBehaviour::new();
+        assert!(provider.sync().await.is_success());
+        assert!(local_cal.lock().unwrap().get_item_by_url_sync(&remote_url).is_some());
+        local_cal.lock().unwrap().add_item_sync(Item::Task(Task::new("Local task".to_string(), false, &first_cal))).unwrap();
+        *mock_behaviour.lock().unwrap() = MockBehaviour{
+            get_item_version_tags_behaviour: (0, 10),
+            ..MockBehaviour::default()
+        };
+        assert!(!provider.sync().await.is_success());
+    }
+}
+
+#[test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+fn test_blocking_provider() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use kitchen_fridge::blocking;
+        use kitchen_fridge::provider::search::SearchOptions;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let scenarii = scenarii::scenarii_basic();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let provider = blocking::block_on(scenarii::populate_test_provider_before_sync(&scenarii, mock_behaviour));
+
+        // No async runtime is needed
+        let mut provider = blocking::Provider::from_async(provider);
+        assert!(!provider.pending_changes().unwrap().is_empty());
+        assert!(provider.sync().is_success());
+        assert!(provider.pending_changes().unwrap().is_empty());
+        assert!(provider.search("task", &SearchOptions::new()).unwrap().len() > 1);
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_metrics_sink() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use std::collections::HashMap;
+        use std::time::Duration;
+        use url::Url;
+        use kitchen_fridge::metrics::{ItemTransfer, MetricsSink};
+
+        #[derive(Default)]
+        struct Counters {
+            items: Mutex<HashMap<ItemTransfer, usize>>,
+            errors: Mutex<usize>,
+            syncs: Mutex<Vec<bool>>,
+        }
+        impl MetricsSink for Counters {
+            fn item_synced(&self, _calendar: &Url, transfer: ItemTransfer) {
+                *self.items.lock().unwrap().entry(transfer).or_default() += 1;
+            }
+            fn sync_error(&self, _calendar: Option<&Url>) {
+                *self.errors.lock().unwrap() += 1;
+            }
+            fn sync_finished(&self, _duration: Duration, success: bool) {
+                self.syncs.lock().unwrap().push(success);
+            }
+        }
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let counters = Arc::new(Counters::default());
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour.clone()).await;
+        provider.set_metrics_sink(Some(counters.clone()));
+        assert!(provider.sync().await.is_success());
+        {
+            let items = counters.items.lock().unwrap();
+            assert!(items[&ItemTransfer::Downloaded] > 0);
+            assert!(items[&ItemTransfer::Uploaded] > 0);
+            assert!(items[&ItemTransfer::DeletedLocally] > 0);
+            assert!(items[&ItemTransfer::DeletedRemotely] > 0);
+        }
+        assert_eq!(*counters.errors.lock().unwrap(), 0);
+
+        *mock_behaviour.lock().unwrap() = MockBehaviour::fail_now(10);
+        assert!(!provider.sync().await.is_success());
+        assert!(*counters.errors.lock().unwrap() > 0);
+        assert_eq!(*counters.syncs.lock().unwrap(), vec![true, false]);
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_calendar_properties() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use url::Url;
+        use kitchen_fridge::alarm::{Alarm, AlarmTrigger};
+        use kitchen_fridge::calendar::{CalendarProperties, CalendarTimezone, DefaultAlarms, SupportedComponents};
+        use kitchen_fridge::calendar::sharing::{ShareStatus, Sharing};
+        use kitchen_fridge::traits::{BaseCalendar, CompleteCalendar, DavCalendar};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let first_cal: Url = "https://some.calend.ar/calendar-1/".parse().unwrap();
+        let mock_behaviour = Arc::new(Mutex::new(MockBehaviour::new()));
+        let mut provider = scenarii::populate_test_provider_before_sync(&scenarii::scenarii_basic(), mock_behaviour).await;
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&first_cal).unwrap();
+        let remote_cal = provider.remote().get_calendar_sync(&first_cal).unwrap();
+
+        // Local changes are pushed
+        let orange = csscolorparser::parse("#ff8000").unwrap();
+        CompleteCalendar::set_color(&mut *local_cal.lock().unwrap(), Some(orange.clone()));
+        assert_eq!(local_cal.lock().unwrap().modified_properties(), CalendarProperties::COLOR);
+        assert!(provider.sync().await.is_success());
+        assert_eq!(remote_cal.lock().unwrap().color(), Some(&orange));
+        assert!(local_cal.lock().unwrap().modified_properties().is_empty());
+
+        // Remote changes are mirrored
+        let mut changed_remote = remote_cal.lock().unwrap().clone();
+        changed_remote.update_color(None).await.unwrap();
+        *remote_cal.lock().unwrap() = changed_remote;
+        assert!(provider.sync().await.is_success());
+        assert_eq!(local_cal.lock().unwrap().color(), None);
+        assert!(local_cal.lock().unwrap().modified_properties().is_empty());
+
+        // Other properties are synced the same way
+        local_cal.lock().unwrap().set_order(Some(3));
+        assert!(provider.sync().await.is_success());
+        assert_eq!(remote_cal.lock().unwrap().order(), Some(3));
+        assert!(local_cal.lock().unwrap().modified_properties().is_empty());
+
+        local_cal.lock().unwrap().set_description(Some("Things to buy".to_string()));
+        assert!(provider.sync().await.is_success());
+        assert_eq!(remote_cal.lock().unwrap().description(), Some("Things to buy"));
+        assert!(local_cal.lock().unwrap().modified_properties().is_empty());
+
+        // Calendars can be renamed right away
+        provider.rename_calendar(&first_cal, "Groceries".to_string()).await.unwrap();
+        assert_eq!(remote_cal.lock().unwrap().name(), "Groceries");
+        assert_eq!(local_cal.lock().unwrap().name(), "Groceries");
+        assert!(local_cal.lock().unwrap().modified_properties().is_empty());
+        assert!(provider.sync().await.is_success());
+        assert_eq!(local_cal.lock().unwrap().name(), "Groceries");
+
+        // So are default alarms
+        let alarm = Alarm::display(AlarmTrigger::before_start(chrono::Duration::minutes(10)), "Reminder");
+        let defaults = DefaultAlarms{ task_date_time: Some(alarm), ..DefaultAlarms::default() };
+        local_cal.lock().unwrap().set_default_alarms(Some(defaults.clone()));
+        assert!(provider.sync().await.is_success());
+        assert_eq!(remote_cal.lock().unwrap().default_alarms(), Some(&defaults));
+        let mut changed_remote = remote_cal.lock().unwrap().clone();
+        changed_remote.update_default_alarms(None).await.unwrap();
+        *remote_cal.lock().unwrap() = changed_remote;
+        assert!(provider.sync().await.is_success());
+        assert_eq!(local_cal.lock().unwrap().default_alarms(), None);
+
+        // Sharing metadata is read-only, and mirrored from the server
+        let sharing = Sharing{ status: ShareStatus::SharedWithMe, owner_display_name: Some("Alice".to_string()), sharees: Vec::new() };
+        remote_cal.lock().unwrap().set_sharing(Some(sharing.clone()));
+        assert!(provider.sync().await.is_success());
+        assert_eq!(local_cal.lock().unwrap().sharing(), Some(&sharing));
+        assert_eq!(local_cal.lock().unwrap().share_status(), ShareStatus::SharedWithMe);
+
+        // Time zones are also set on calendars that are created during a sync
+        let paris = CalendarTimezone::from_ical(&std::fs::read_to_string("tests/assets/timezone_europe_paris.ics").unwrap()).unwrap();
+        let new_cal: Url = "https://some.calend.ar/paris/".parse().unwrap();
+        let created = provider.local_mut().create_calendar(new_cal.clone(), "Paris".to_string(), SupportedComponents::TODO, None).await.unwrap();
+        created.lock().unwrap().set_timezone(Some(paris));
+        assert!(provider.sync().await.is_success());
+        let remote_created = provider.remote().get_calendar_sync(&new_cal).unwrap();
+        assert_eq!(remote_created.lock().unwrap().timezone().map(|tz| tz.tzid()), Some("Europe/Paris"));
+        assert!(created.lock().unwrap().modified_properties().is_empty());
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_subscribed_calendar() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use kitchen_fridge::SubscriptionProvider;
+        use kitchen_fridge::subscription::Subscriptions;
+        use kitchen_fridge::traits::BaseCalendar;
+        use kitchen_fridge::{Item, Task};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let feed = Arc::new(Mutex::new(std::fs::read_to_string("tests/assets/subscription.ics").unwrap()));
+        let statuses = Arc::new(Mutex::new(Vec::new()));
+        let feed_url = serve_feed(feed.clone(), statuses.clone());
+
+        let mut subscriptions = Subscriptions::new();
+        let subscribed = subscriptions.subscribe(feed_url.as_str(), "Chores".to_string(), None).unwrap();
+        let trash_url = subscribed.lock().unwrap().item_url("chore-take-out-the-trash@example.com");
+        let plants_url = subscribed.lock().unwrap().item_url("chore-water-the-plants@example.com");
+
+        let mut provider = SubscriptionProvider::new(subscriptions, Cache::new_in_memory());
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&feed_url).unwrap();
+        {
+            let cal = local_cal.lock().unwrap();
+            assert_eq!(cal.name(), "Chores");
+            assert!(cal.is_read_only());
+            // Events are not supported yet
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 2);
+            assert_eq!(cal.get_item_by_url_sync(&trash_url).unwrap().unwrap_task().name(), "Take out the trash");
+            assert!(cal.get_item_by_url_sync(&plants_url).unwrap().unwrap_task().completed());
+        }
+
+        // Subscribed items cannot be changed locally
+        let task = Task::new("A new chore".to_string(), false, &feed_url);
+        assert!(local_cal.lock().unwrap().add_item_sync(Item::Task(task)).is_err());
+
+        // The server does not send the feed again when it has not changed
+        assert!(provider.sync().await.is_success());
+        assert_eq!(*statuses.lock().unwrap(), vec![200, 304]);
+
+        // The next sync updates the local items with the new version of the feed
+        {
+            let mut feed = feed.lock().unwrap();
+            *feed = feed.replace("SUMMARY:Take out the trash", "SUMMARY:Take out the recycling")
+                .replace("UID:chore-water-the-plants@example.com", "UID:chore-feed-the-cat@example.com");
+        }
+        assert!(provider.sync().await.is_success());
+        assert_eq!(*statuses.lock().unwrap(), vec![200, 304, 200]);
+        {
+            let cal = local_cal.lock().unwrap();
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 2);
+            assert_eq!(cal.get_item_by_url_sync(&trash_url).unwrap().unwrap_task().name(), "Take out the recycling");
+            assert!(cal.get_item_by_url_sync(&plants_url).is_none());
+        }
+
+        // Within the refresh interval, syncs do not download the feed at all
+        subscribed.lock().unwrap().set_refresh_interval(std::time::Duration::from_secs(3600));
+        feed.lock().unwrap().push_str("\r\n");
+        assert!(provider.sync().await.is_success());
+        assert_eq!(statuses.lock().unwrap().len(), 3);
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_vdir_source() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use kitchen_fridge::VdirProvider;
+        use kitchen_fridge::vdir::Vdir;
+        use kitchen_fridge::calendar::SupportedComponents;
+        use kitchen_fridge::traits::{BaseCalendar, CalDavSource};
+        use kitchen_fridge::{Item, Task};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let folder = std::path::Path::new("test_cache/vdir_source");
+        let _ = std::fs::remove_dir_all(folder);
+        std::fs::create_dir_all(folder.join("chores")).unwrap();
+        std::fs::write(folder.join("chores/displayname"), "Chores").unwrap();
+        let trash_ics = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Other app//EN\r\nBEGIN:VTODO\r\nUID:trash\r\nDTSTAMP:20210101T100000Z\r\nSUMMARY:Take out the trash\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        std::fs::write(folder.join("chores/trash.ics"), trash_ics).unwrap();
+
+        let vdir = Vdir::new(folder).unwrap();
+        let cal_url = vdir.calendar_url("chores");
+        let trash_url = cal_url.join("trash.ics").unwrap();
+        let trash_path = trash_url.to_file_path().unwrap();
+        let mut provider = VdirProvider::new(vdir, Cache::new_in_memory());
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().name(), "Chores");
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&trash_url).unwrap().unwrap_task().name(), "Take out the trash");
+
+        // Local changes are written to the files
+        let task = Task::new_with_parameters("Water the plants".to_string(), "plants".to_string(), cal_url.join("plants.ics").unwrap(),
+            kitchen_fridge::task::CompletionStatus::Uncompleted, kitchen_fridge::item::SyncStatus::NotSynced, None, chrono::Utc::now(), "-//Test//EN".to_string(), Vec::new());
+        let plants_path = task.url().to_file_path().unwrap();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        local_cal.lock().unwrap().get_item_by_url_mut_sync(&trash_url).unwrap().unwrap_task_mut().set_name("Take out the recycling".to_string());
+        assert!(provider.sync().await.is_success());
+        assert!(std::fs::read_to_string(&plants_path).unwrap().contains("SUMMARY:Water the plants"));
+        assert!(std::fs::read_to_string(&trash_path).unwrap().contains("SUMMARY:Take out the recycling"));
+
+        // Changes made by other apps are synced like remote changes
+        std::fs::write(&trash_path, trash_ics.replace("Take out the trash", "Take out the glass")).unwrap();
+        std::fs::remove_file(&plants_path).unwrap();
+        assert!(provider.sync().await.is_success());
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&trash_url).unwrap().unwrap_task().name(), "Take out the glass");
+        assert_eq!(local_cal.lock().unwrap().get_item_urls_sync().unwrap().len(), 1);
+
+        // Calendars created locally become folders of the vdir
+        let groceries_url = provider.remote().calendar_url("groceries");
+        provider.local_mut().create_calendar(groceries_url.clone(), "Groceries".to_string(), SupportedComponents::TODO, None).await.unwrap();
+        assert!(provider.sync().await.is_success());
+        assert_eq!(std::fs::read_to_string(folder.join("groceries/displayname")).unwrap(), "Groceries");
+        assert_eq!(provider.remote().get_calendar(&groceries_url).await.unwrap().lock().unwrap().name(), "Groceries");
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature="integration_tests"), ignore)]
+async fn test_carddav() {
+    #[cfg(feature = "integration_tests")]
+    {
+        use kitchen_fridge::{CardDavProvider, Client, Contact, Item};
+        use kitchen_fridge::traits::BaseCalendar;
+        use kitchen_fridge::item::SyncStatus;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let contacts = Arc::new(Mutex::new(std::collections::BTreeMap::new()));
+        contacts.lock().unwrap().insert("john.vcf".to_string(),
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nFN:John Doe\r\nN:Doe;John;;;\r\nEMAIL;TYPE=HOME:john@example.com\r\nEND:VCARD\r\n".to_string());
+        let root = serve_carddav(contacts.clone());
+
+        let client = Client::new(root.as_str(), "user", "password").unwrap();
+        let book_url = root.join("addressbooks/user/contacts/").unwrap();
+        let john_url = book_url.join("john.vcf").unwrap();
+        let mut provider = CardDavProvider::new(client.address_books(), Cache::new_in_memory());
+        assert!(provider.sync().await.is_success());
+        let local_book = provider.local().get_calendar_sync(&book_url).unwrap();
+        assert_eq!(local_book.lock().unwrap().name(), "Contacts");
+        {
+            let book = local_book.lock().unwrap();
+            let john = book.get_item_by_url_sync(&john_url).unwrap().unwrap_contact();
+            assert_eq!(john.uid(), "john");
+            assert_eq!(john.name(), "John Doe");
+            assert_eq!(john.emails(), vec!["john@example.com"]);
+        }
+
+        // Local changes are uploaded as vCards
+        let mut jane = Contact::new("Jane Roe".to_string(), &book_url);
+        jane.set_values("EMAIL", &["jane@example.com"]);
+        let jane_url = jane.url().clone();
+        local_book.lock().unwrap().add_item_sync(Item::Contact(jane)).unwrap();
+        local_book.lock().unwrap().get_item_by_url_mut_sync(&john_url).unwrap().unwrap_contact_mut().set_name("John Smith".to_string());
+        assert!(provider.sync().await.is_success());
+        {
+            let contacts = contacts.lock().unwrap();
+            assert_eq!(contacts.len(), 2);
+            assert!(contacts["john.vcf"].contains("\r\nFN:John Smith\r\n"));
+            assert!(contacts["john.vcf"].contains("\r\nEMAIL;TYPE=HOME:john@example.com\r\n"));
+            let jane = contacts.values().find(|vcard| vcard.contains("FN:Jane Roe")).unwrap();
+            assert!(jane.starts_with("BEGIN:VCARD\r\nVERSION:4.0\r\n"));
+            assert!(jane.contains("\r\nEMAIL:jane@example.com\r\n"));
+        }
+        assert!(matches!(local_book.lock().unwrap().get_item_by_url_sync(&jane_url).unwrap().sync_status(), SyncStatus::Synced(_)));
+
+        // Remote changes are downloaded at the next sync
+        contacts.lock().unwrap().remove("john.vcf");
+        assert!(provider.sync().await.is_success());
+        let book = local_book.lock().unwrap();
+        assert_eq!(book.get_item_urls_sync().unwrap().len(), 1);
+        assert!(book.get_item_by_url_sync(&jane_url).unwrap().is_contact());
+    }
+}
+
+/// Serve a single address book (`/addressbooks/user/contacts/`) on a local port, and return the root URL of this fake CardDAV server
+///
+/// The ETag of a contact is a checksum of its vCard, and the CTag of the address book is a checksum of every ETag. Credentials are not checked
+#[cfg(feature = "integration_tests")]
+fn serve_carddav(contacts: Arc<Mutex<std::collections::BTreeMap<String, String>>>) -> url::Url {
+    fn etag(vcard: &str) -> String {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        vcard.hash(&mut hasher);
+        format!("\"{:x}\"", hasher.finish())
+    }
+    fn multistatus(responses: String) -> (u16, String) {
+        (207, format!(r#"<d:multistatus xmlns:d="DAV:" xmlns:card="urn:ietf:params:xml:ns:carddav" xmlns:cs="http://calendarserver.org/ns/">{}</d:multistatus>"#, responses))
+    }
+    const BOOK: &str = "/addressbooks/user/contacts/";
+
+    serve(move |head, body| {
+        let mut contacts = contacts.lock().unwrap();
+        let mut request_line = head.split_whitespace();
+        let (method, path) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+        let header = |name: &str| head.lines()
+            .find_map(|line| line.split_once(':').filter(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.trim().to_string()));
+        let file_name = path.strip_prefix(BOOK).unwrap_or_default().to_string();
+
+        match (method, path) {
+            ("OPTIONS", _) => (200, String::new()),
+            ("PROPFIND", "/") => multistatus(r#"<d:response><d:href>/</d:href><d:propstat><d:prop>
+                <d:current-user-principal><d:href>/principals/user/</d:href></d:current-user-principal></d:prop></d:propstat></d:response>"#.to_string()),
+            ("PROPFIND", "/principals/user/") => multistatus(r#"<d:response><d:href>/principals/user/</d:href><d:propstat><d:prop>
+                <card:addressbook-home-set><d:href>/addressbooks/user/</d:href></card:addressbook-home-set></d:prop></d:propstat></d:response>"#.to_string()),
+            ("PROPFIND", "/addressbooks/user/") => multistatus(format!(r#"
+                <d:response><d:href>/addressbooks/user/</d:href><d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat></d:response>
+                <d:response><d:href>{}</d:href><d:propstat><d:prop><d:displayname>Contacts</d:displayname>
+                    <d:resourcetype><d:collection/><card:addressbook/></d:resourcetype></d:prop></d:propstat></d:response>"#, BOOK)),
+            ("PROPFIND", BOOK) if body.contains("getctag") => {
+                let ctag = etag(&contacts.values().map(|vcard| etag(vcard)).collect::<String>());
+                multistatus(format!("<d:response><d:href>{}</d:href><d:propstat><d:prop><cs:getctag>{}</cs:getctag></d:prop></d:propstat></d:response>", BOOK, ctag))
+            },
+            ("PROPFIND", BOOK) => {
+                let items: String = contacts.iter()
+                    .map(|(name, vcard)| format!("<d:response><d:href>{}{}</d:href><d:propstat><d:prop><d:getetag>{}</d:getetag><d:resourcetype/></d:prop></d:propstat></d:response>", BOOK, name, etag(vcard)))
+                    .collect();
+                multistatus(format!("<d:response><d:href>{}</d:href><d:propstat><d:prop><d:resourcetype><d:collection/><card:addressbook/></d:resourcetype></d:prop></d:propstat></d:response>{}", BOOK, items))
+            },
+            ("REPORT", BOOK) => {
+                let items: String = body.split("<d:href>").skip(1)
+                    .filter_map(|rest| rest.split('<').next()?.strip_prefix(BOOK))
+                    .filter_map(|name| contacts.get(name).map(|vcard| (name, vcard)))
+                    .map(|(name, vcard)| format!("<d:response><d:href>{}{}</d:href><d:propstat><d:prop><d:getetag>{}</d:getetag><card:address-data>{}</card:address-data></d:prop></d:propstat></d:response>",
+                        BOOK, name, etag(vcard), vcard))
+                    .collect();
+                multistatus(items)
+            },
+            ("PUT", _) => {
+                let current = contacts.get(&file_name).map(|vcard| etag(vcard));
+                let allowed = match (header("If-Match"), header("If-None-Match")) {
+                    (Some(expected), _) => current == Some(expected),
+                    (_, Some(_)) => current.is_none(),
+                    _ => true,
+                };
+                if !allowed {
+                    return (412, String::new());
+                }
+                contacts.insert(file_name, body.to_string());
+                (201, String::new())
+            },
+            ("DELETE", _) => match contacts.remove(&file_name) {
+                None => (404, String::new()),
+                Some(_) => (204, String::new()),
+            },
+            _ => (405, String::new()),
+        }
+    })
+}
+
+#[tokio::test]
+#[cfg_attr(not(all(feature="integration_tests", feature="google")), ignore)]
+async fn test_google_tasks() {
+    #[cfg(all(feature = "integration_tests", feature = "google"))]
+    {
+        use kitchen_fridge::GoogleTasksProvider;
+        use kitchen_fridge::google::GoogleTasks;
+        use kitchen_fridge::resource::AccessToken;
+        use kitchen_fridge::traits::BaseCalendar;
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::{Item, Task};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let tasks = Arc::new(Mutex::new(vec![
+            serde_json::json!({ "id": "T0", "etag": "\"0\"", "title": "Water the plants", "status": "needsAction", "updated": "2021-03-01T10:00:00.000Z" }),
+        ]));
+        let base_url = serve_google_tasks(tasks.clone());
+
+        let source = GoogleTasks::with_base_url(base_url, AccessToken::new("token".to_string()));
+        let cal_url = source.task_list_url("L1");
+        let plants_url = cal_url.join("T0").unwrap();
+        let mut provider = GoogleTasksProvider::new(source, Cache::new_in_memory());
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().name(), "Chores");
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&plants_url).unwrap().unwrap_task().name(), "Water the plants");
+
+        // Google chooses the URLs of new tasks
+        let task = Task::new("Call Bob".to_string(), false, &cal_url);
+        let local_url = task.url().clone();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        local_cal.lock().unwrap().get_item_by_url_mut_sync(&plants_url).unwrap().unwrap_task_mut().set_completion_status(kitchen_fridge::task::CompletionStatus::Completed(None));
+        assert!(provider.sync().await.is_success());
+        {
+            let tasks = tasks.lock().unwrap();
+            assert_eq!(tasks[0]["status"], "completed");
+            assert_eq!(tasks[1]["title"], "Call Bob");
+        }
+        let bob_url = cal_url.join("T1").unwrap();
+        {
+            let cal = local_cal.lock().unwrap();
+            assert!(cal.get_item_by_url_sync(&local_url).is_none());
+            assert!(matches!(cal.get_item_by_url_sync(&bob_url).unwrap().sync_status(), SyncStatus::Synced(_)));
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 2);
+        }
+
+        // Remote changes are downloaded at the next sync
+        {
+            let mut tasks = tasks.lock().unwrap();
+            tasks[0]["deleted"] = true.into();
+            tasks[0]["etag"] = "\"deleted\"".into();
+            tasks[1]["title"] = "Call Alice".into();
+            tasks[1]["etag"] = "\"renamed\"".into();
+        }
+        assert!(provider.sync().await.is_success());
+        let cal = local_cal.lock().unwrap();
+        assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+        assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_task().name(), "Call Alice");
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(all(feature="integration_tests", feature="google")), ignore)]
+async fn test_non_idempotent_requests_are_not_retried() {
+    #[cfg(all(feature = "integration_tests", feature = "google"))]
+    {
+        use kitchen_fridge::GoogleTasksProvider;
+        use kitchen_fridge::google::GoogleTasks;
+        use kitchen_fridge::resource::AccessToken;
+        use kitchen_fridge::{Item, Task};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let list_requests = Arc::new(Mutex::new(0));
+        let posts = Arc::new(Mutex::new(0));
+        let (list_requests_, posts_) = (list_requests.clone(), posts.clone());
+        let root = serve(move |head, _body| {
+            let mut request_line = head.split_whitespace();
+            let method = request_line.next().unwrap_or_default();
+            let path = request_line.next().unwrap_or_default().split('?').next().unwrap_or_default();
+            match (method, path) {
+                ("GET", "/tasks/v1/users/@me/lists") => {
+                    // Fails once, then succeeds: safe requests are still retried
+                    let mut count = list_requests_.lock().unwrap();
+                    *count += 1;
+                    if *count == 1 {
+                        (503, String::new())
+                    } else {
+                        (200, serde_json::json!({ "items": [{ "id": "L1", "title": "Chores" }] }).to_string())
+                    }
+                },
+                ("GET", "/tasks/v1/lists/L1/tasks") => (200, serde_json::json!({ "items": [] }).to_string()),
+                ("POST", "/tasks/v1/lists/L1/tasks") => {
+                    // The server may have created the task, even though it replies with an error
+                    *posts_.lock().unwrap() += 1;
+                    (503, String::new())
+                },
+                _ => (404, String::new()),
+            }
+        });
+
+        let source = GoogleTasks::with_base_url(root.join("tasks/v1/").unwrap(), AccessToken::new("token".to_string()));
+        let cal_url = source.task_list_url("L1");
+        let mut provider = GoogleTasksProvider::new(source, Cache::new_in_memory());
+        assert!(provider.sync().await.is_success());
+        assert_eq!(*list_requests.lock().unwrap(), 2);
+
+        let task = Task::new("Call Bob".to_string(), false, &cal_url);
+        provider.local().get_calendar_sync(&cal_url).unwrap().lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        assert!(!provider.sync().await.is_success());
+        assert_eq!(*posts.lock().unwrap(), 1);
+    }
+}
+
+/// Serve the "Chores" task list of a fake Google Tasks API on a local port, and return the root URL of this API
+///
+/// This is only a small subset of the API, that does not check tokens, and that ignores most parameters
+#[cfg(all(feature = "integration_tests", feature = "google"))]
+fn serve_google_tasks(tasks: Arc<Mutex<Vec<serde_json::Value>>>) -> url::Url {
+    let root = serve(move |head, body| {
+        let mut request_line = head.split_whitespace();
+        let method = request_line.next().unwrap_or_default();
+        let path = request_line.next().unwrap_or_default().split('?').next().unwrap_or_default();
+        let mut tasks = tasks.lock().unwrap();
+        let find = |tasks: &[serde_json::Value], id: &str| tasks.iter().position(|task| task["id"] == id);
+        match (method, path.strip_prefix("/tasks/v1/")) {
+            ("GET", Some("users/@me/lists")) => (200, serde_json::json!({ "items": [{ "id": "L1", "title": "Chores" }] }).to_string()),
+            ("GET", Some("lists/L1/tasks")) => (200, serde_json::json!({ "items": *tasks }).to_string()),
+            ("POST", Some("lists/L1/tasks")) => {
+                let mut task: serde_json::Value = serde_json::from_str(body).unwrap();
+                task["id"] = format!("T{}", tasks.len()).into();
+                task["etag"] = "\"0\"".into();
+                task["updated"] = "2021-03-02T10:00:00.000Z".into();
+                tasks.push(task.clone());
+                (200, task.to_string())
+            },
+            ("PATCH", Some(path)) => match path.strip_prefix("lists/L1/tasks/").and_then(|id| find(&tasks, id)) {
+                None => (404, String::new()),
+                Some(i) if !head.to_ascii_lowercase().contains(&format!("if-match: {}", tasks[i]["etag"].as_str().unwrap())) => (412, String::new()),
+                Some(i) => {
+                    let patch: serde_json::Map<String, serde_json::Value> = serde_json::from_str(body).unwrap();
+                    for (field, value) in patch {
+                        tasks[i][field] = value;
+                    }
+                    tasks[i]["etag"] = "\"1\"".into();
+                    (200, tasks[i].to_string())
+                },
+            },
+            _ => (404, String::new()),
+        }
+    });
+    root.join("tasks/v1/").unwrap()
+}
+
+#[tokio::test]
+#[cfg_attr(not(all(feature="integration_tests", feature="google")), ignore)]
+async fn test_google_calendar() {
+    #[cfg(all(feature = "integration_tests", feature = "google"))]
+    {
+        use kitchen_fridge::GoogleCalendarProvider;
+        use kitchen_fridge::google::GoogleCalendars;
+        use kitchen_fridge::resource::AccessToken;
+        use kitchen_fridge::traits::BaseCalendar;
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::{Event, Item};
+        use std::path::Path;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let folder = Path::new("test_cache/google_calendar");
+        let _ = std::fs::remove_dir_all(folder);
+        let server = Arc::new(Mutex::new(MockEventServer::new("etag")));
+        server.lock().unwrap().change(serde_json::json!({ "id": "E0", "summary": "Water the plants", "iCalUID": "plants",
+            "start": { "dateTime": "2021-04-01T10:00:00Z" }, "end": { "dateTime": "2021-04-01T10:30:00Z" } }));
+        let base_url = serve_google_calendar(server.clone());
+
+        let source = GoogleCalendars::with_base_url(base_url.clone(), AccessToken::new("token".to_string()));
+        let cal_url = source.calendar_url("primary");
+        let plants_url = cal_url.join("E0").unwrap();
+        let mut provider = GoogleCalendarProvider::new(source, Cache::new(folder));
+        assert!(provider.sync().await.is_success());
+        // Calendars that only share their free/busy information are not synced
+        assert_eq!(provider.local().get_calendars_sync().unwrap().len(), 1);
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().name(), "Alice");
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&plants_url).unwrap().unwrap_event().uid(), "plants");
+
+        // Google chooses the URLs of new events
+        let date = |name: &str, value: &str| ical::property::Property{ name: name.to_string(), params: None, value: Some(value.to_string()) };
+        let event = Event::new("Call Bob".to_string(), date("DTSTART", "20210402T080000Z"), date("DTEND", "20210402T083000Z"), &cal_url);
+        let (local_url, bob_uid) = (event.url().clone(), event.uid().to_string());
+        local_cal.lock().unwrap().add_item_sync(Item::Event(event)).unwrap();
+        local_cal.lock().unwrap().get_item_by_url_mut_sync(&plants_url).unwrap().unwrap_event_mut().set_name("Water the cactus".to_string());
+        assert!(provider.sync().await.is_success());
+        {
+            let server = server.lock().unwrap();
+            assert_eq!(server.events[0]["summary"], "Water the cactus");
+            assert_eq!(server.events[1]["summary"], "Call Bob");
+            assert_eq!(server.events[1]["iCalUID"], bob_uid.as_str());
+        }
+        let bob_url = cal_url.join("E1").unwrap();
+        {
+            let cal = local_cal.lock().unwrap();
+            assert!(cal.get_item_by_url_sync(&local_url).is_none());
+            assert!(matches!(cal.get_item_by_url_sync(&bob_url).unwrap().sync_status(), SyncStatus::Synced(_)));
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 2);
+        }
+
+        // Remote changes are listed with the sync token of the previous sync
+        {
+            let mut server = server.lock().unwrap();
+            let mut plants = server.events[0].clone();
+            plants["status"] = "cancelled".into();
+            server.change(plants);
+            let mut bob = server.events[1].clone();
+            bob["summary"] = "Call Alice".into();
+            server.change(bob);
+            server.requests.clear();
+        }
+        assert!(provider.sync().await.is_success());
+        assert!(server.lock().unwrap().requests.iter().all(|request| request.contains("syncToken=")));
+        {
+            let cal = local_cal.lock().unwrap();
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+            assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Alice");
+        }
+
+        // The sync token is kept when the app is restarted
+        drop(local_cal);
+        drop(provider);
+        {
+            let mut server = server.lock().unwrap();
+            let mut bob = server.events[1].clone();
+            bob["summary"] = "Call Carol".into();
+            server.change(bob);
+            server.requests.clear();
+        }
+        let source = GoogleCalendars::with_base_url(base_url, AccessToken::new("token".to_string()));
+        let mut provider = GoogleCalendarProvider::new(source, Cache::from_folder(folder).unwrap());
+        assert!(provider.sync().await.is_success());
+        assert!(server.lock().unwrap().requests.iter().all(|request| request.contains("syncToken=")));
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Carol");
+
+        // Expired sync tokens are replaced by a full listing
+        {
+            let mut server = server.lock().unwrap();
+            server.expired = true;
+            let mut bob = server.events[1].clone();
+            bob["summary"] = "Call Dave".into();
+            server.change(bob);
+        }
+        assert!(provider.sync().await.is_success());
+        let cal = local_cal.lock().unwrap();
+        assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+        assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Dave");
+    }
+}
+
+/// The events of a mock calendar of a REST API
+#[cfg(all(feature = "integration_tests", any(feature = "google", feature = "graph", feature = "ews", feature = "jmap")))]
+struct MockEventServer {
+    /// The events, with the version at which they have last been changed (in their version tag)
+    events: Vec<serde_json::Value>,
+    /// The field of the version tags of events
+    tag_field: &'static str,
+    version: u32,
+    /// Whether every sync token has expired
+    expired: bool,
+    /// The listings of events (their query strings)
+    requests: Vec<String>,
+}
+
+#[cfg(all(feature = "integration_tests", any(feature = "google", feature = "graph", feature = "ews", feature = "jmap")))]
+impl MockEventServer {
+    fn new(tag_field: &'static str) -> Self {
+        Self { events: Vec::new(), tag_field, version: 0, expired: false, requests: Vec::new() }
+    }
+
+    fn change(&mut self, mut event: serde_json::Value) -> serde_json::Value {
+        self.version += 1;
+        event[self.tag_field] = format!("\"{}\"", self.version).into();
+        match self.events.iter().position(|existing| existing["id"] == event["id"]) {
+            Some(i) => self.events[i] = event.clone(),
+            None => self.events.push(event.clone()),
+        }
+        event
+    }
+
+    /// The events that have changed since a version
+    fn changed_since(&self, version: u32) -> Vec<serde_json::Value> {
+        self.events.iter()
+            .filter(|event| event[self.tag_field].as_str().unwrap().trim_matches('"').parse::<u32>().unwrap() > version)
+            .cloned()
+            .collect()
+    }
+
+    fn position(&self, id: &str) -> Option<usize> {
+        self.events.iter().position(|event| event["id"] == id)
+    }
+}
+
+#[cfg(all(feature = "integration_tests", feature = "google"))]
+fn serve_google_calendar(server: Arc<Mutex<MockEventServer>>) -> url::Url {
+    let root = serve(move |head, body| {
+        let mut request_line = head.split_whitespace();
+        let method = request_line.next().unwrap_or_default();
+        let target = request_line.next().unwrap_or_default();
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut server = server.lock().unwrap();
+        let sync_token = query.split('&').find_map(|pair| pair.strip_prefix("syncToken=")).map(str::to_string);
+        match (method, path.strip_prefix("/calendar/v3/")) {
+            ("GET", Some("users/me/calendarList")) => (200, serde_json::json!({ "items": [
+                { "id": "primary", "summary": "Alice", "backgroundColor": "#9fe1e7", "accessRole": "owner" },
+                { "id": "bob@example.com", "summary": "Bob", "accessRole": "freeBusyReader" },
+            ] }).to_string()),
+            ("GET", Some("calendars/primary/events")) => {
+                server.requests.push(query.to_string());
+                let events: Vec<_> = match sync_token {
+                    Some(_) if server.expired => return (410, String::new()),
+                    Some(token) => {
+                        server.changed_since(token.trim_start_matches('v').parse().unwrap())
+                    },
+                    None => server.events.iter().filter(|event| event["status"] != "cancelled").cloned().collect(),
+                };
+                server.expired = false;
+                (200, serde_json::json!({ "items": events, "nextSyncToken": format!("v{}", server.version), "timeZone": "Europe/Paris" }).to_string())
+            },
+            ("POST", Some("calendars/primary/events")) => {
+                let mut event: serde_json::Value = serde_json::from_str(body).unwrap();
+                event["id"] = format!("E{}", server.events.len()).into();
+                (200, server.change(event).to_string())
+            },
+            ("PATCH", Some(path)) => match path.strip_prefix("calendars/primary/events/").and_then(|id| server.position(id)) {
+                None => (404, String::new()),
+                Some(i) if !head.to_ascii_lowercase().contains(&format!("if-match: {}", server.events[i]["etag"].as_str().unwrap())) => (412, String::new()),
+                Some(i) => {
+                    let mut event = server.events[i].clone();
+                    let patch: serde_json::Map<String, serde_json::Value> = serde_json::from_str(body).unwrap();
+                    for (field, value) in patch {
+                        event[field] = value;
+                    }
+                    (200, server.change(event).to_string())
+                },
+            },
+            _ => (404, String::new()),
+        }
+    });
+    root.join("calendar/v3/").unwrap()
+}
+
+#[tokio::test]
+#[cfg_attr(not(all(feature="integration_tests", feature="graph")), ignore)]
+async fn test_graph_todo() {
+    #[cfg(all(feature = "integration_tests", feature = "graph"))]
+    {
+        use kitchen_fridge::GraphTodoProvider;
+        use kitchen_fridge::graph::GraphTodo;
+        use kitchen_fridge::resource::AccessToken;
+        use kitchen_fridge::traits::BaseCalendar;
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::{Item, Task};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let tasks = Arc::new(Mutex::new(vec![
+            serde_json::json!({ "id": "T0", "@odata.etag": "W/\"0\"", "title": "Water the plants", "status": "notStarted", "importance": "high" }),
+        ]));
+        let base_url = serve_graph_todo(tasks.clone());
+
+        let source = GraphTodo::with_base_url(base_url, AccessToken::new("token".to_string()));
+        let cal_url = source.task_list_url("L1");
+        let plants_url = cal_url.join("T0").unwrap();
+        let mut provider = GraphTodoProvider::new(source, Cache::new_in_memory());
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().name(), "Chores");
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&plants_url).unwrap().unwrap_task().name(), "Water the plants");
+
+        // Microsoft Graph chooses the URLs of new tasks
+        let task = Task::new("Call Bob".to_string(), false, &cal_url);
+        let local_url = task.url().clone();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        local_cal.lock().unwrap().get_item_by_url_mut_sync(&plants_url).unwrap().unwrap_task_mut().set_completion_status(kitchen_fridge::task::CompletionStatus::Completed(None));
+        assert!(provider.sync().await.is_success());
+        {
+            let tasks = tasks.lock().unwrap();
+            assert_eq!(tasks[0]["status"], "completed");
+            assert_eq!(tasks[0]["importance"], "high");
+            assert_eq!(tasks[1]["title"], "Call Bob");
+        }
+        let bob_url = cal_url.join("T1").unwrap();
+        {
+            let cal = local_cal.lock().unwrap();
+            assert!(cal.get_item_by_url_sync(&local_url).is_none());
+            assert!(matches!(cal.get_item_by_url_sync(&bob_url).unwrap().sync_status(), SyncStatus::Synced(_)));
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 2);
+        }
+
+        // Remote changes are downloaded at the next sync
+        {
+            let mut tasks = tasks.lock().unwrap();
+            tasks[0] = serde_json::json!({ "id": "T0", "@removed": { "reason": "deleted" } });
+            tasks[1]["title"] = "Call Alice".into();
+            tasks[1]["@odata.etag"] = "W/\"renamed\"".into();
+        }
+        assert!(provider.sync().await.is_success());
+        let cal = local_cal.lock().unwrap();
+        assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+        assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_task().name(), "Call Alice");
+    }
+}
+
+/// Serve the "Chores" task list of a fake Microsoft Graph API on a local port, and return the root URL of this API
+///
+/// This is only a small subset of the API, that does not check tokens, and whose delta queries always return every task
+#[cfg(all(feature = "integration_tests", feature = "graph"))]
+fn serve_graph_todo(tasks: Arc<Mutex<Vec<serde_json::Value>>>) -> url::Url {
+    let root = serve(move |head, body| {
+        let mut request_line = head.split_whitespace();
+        let method = request_line.next().unwrap_or_default();
+        let path = request_line.next().unwrap_or_default().split('?').next().unwrap_or_default();
+        let host = head.lines().find_map(|line| line.to_ascii_lowercase().strip_prefix("host:").map(|host| host.trim().to_string())).unwrap_or_default();
+        let mut tasks = tasks.lock().unwrap();
+        let find = |tasks: &[serde_json::Value], id: &str| tasks.iter().position(|task| task["id"] == id);
+        match (method, path.strip_prefix("/v1.0/me/todo/")) {
+            ("GET", Some("lists")) => (200, serde_json::json!({ "value": [{ "id": "L1", "displayName": "Chores" }] }).to_string()),
+            ("GET", Some("lists/L1/tasks/delta")) => {
+                let delta_link = format!("http://{}/v1.0/me/todo/lists/L1/tasks/delta?$deltatoken=1", host);
+                (200, serde_json::json!({ "value": *tasks, "@odata.deltaLink": delta_link }).to_string())
+            },
+            ("POST", Some("lists/L1/tasks")) => {
+                let mut task: serde_json::Value = serde_json::from_str(body).unwrap();
+                task["id"] = format!("T{}", tasks.len()).into();
+                task["@odata.etag"] = "W/\"0\"".into();
+                tasks.push(task.clone());
+                (201, task.to_string())
+            },
+            ("PATCH", Some(path)) => match path.strip_prefix("lists/L1/tasks/").and_then(|id| find(&tasks, id)) {
+                None => (404, String::new()),
+                Some(i) if !head.to_ascii_lowercase().contains(&format!("if-match: {}", tasks[i]["@odata.etag"].as_str().unwrap().to_ascii_lowercase())) => (412, String::new()),
+                Some(i) => {
+                    let patch: serde_json::Map<String, serde_json::Value> = serde_json::from_str(body).unwrap();
+                    for (field, value) in patch {
+                        tasks[i][field] = value;
+                    }
+                    tasks[i]["@odata.etag"] = "W/\"1\"".into();
+                    (200, tasks[i].to_string())
+                },
+            },
+            _ => (404, String::new()),
+        }
+    });
+    root.join("v1.0/").unwrap()
+}
+
+#[tokio::test]
+#[cfg_attr(not(all(feature="integration_tests", feature="graph")), ignore)]
+async fn test_graph_calendar() {
+    #[cfg(all(feature = "integration_tests", feature = "graph"))]
+    {
+        use kitchen_fridge::GraphCalendarProvider;
+        use kitchen_fridge::graph::GraphCalendars;
+        use kitchen_fridge::resource::AccessToken;
+        use kitchen_fridge::traits::BaseCalendar;
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::{Event, Item};
+        use std::path::Path;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let folder = Path::new("test_cache/graph_calendar");
+        let _ = std::fs::remove_dir_all(folder);
+        let server = Arc::new(Mutex::new(MockEventServer::new("@odata.etag")));
+        server.lock().unwrap().change(serde_json::json!({ "id": "E0", "subject": "Water the plants", "type": "singleInstance",
+            "start": { "dateTime": "2021-04-01T10:00:00.0000000", "timeZone": "UTC" }, "end": { "dateTime": "2021-04-01T10:30:00.0000000", "timeZone": "UTC" } }));
+        server.lock().unwrap().change(serde_json::json!({ "id": "E1", "subject": "Water the plants", "type": "occurrence" }));
+        let base_url = serve_graph_calendar(server.clone());
+
+        let source = GraphCalendars::with_base_url(base_url.clone(), AccessToken::new("token".to_string()));
+        let cal_url = source.calendar_url("C1");
+        let plants_url = cal_url.join("E0").unwrap();
+        let mut provider = GraphCalendarProvider::new(source, Cache::new(folder));
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().name(), "Calendar");
+        // Occurrences of recurring events are not synced
+        assert_eq!(local_cal.lock().unwrap().get_item_urls_sync().unwrap().len(), 1);
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&plants_url).unwrap().unwrap_event().name(), "Water the plants");
+
+        // Microsoft Graph chooses the URLs of new events
+        let date = |name: &str, value: &str| ical::property::Property{ name: name.to_string(), params: None, value: Some(value.to_string()) };
+        let event = Event::new("Call Bob".to_string(), date("DTSTART", "20210402T080000Z"), date("DTEND", "20210402T083000Z"), &cal_url);
+        let local_url = event.url().clone();
+        local_cal.lock().unwrap().add_item_sync(Item::Event(event)).unwrap();
+        local_cal.lock().unwrap().get_item_by_url_mut_sync(&plants_url).unwrap().unwrap_event_mut().set_name("Water the cactus".to_string());
+        assert!(provider.sync().await.is_success());
+        {
+            let server = server.lock().unwrap();
+            assert_eq!(server.events[0]["subject"], "Water the cactus");
+            assert_eq!(server.events[2]["subject"], "Call Bob");
+            assert_eq!(server.events[2]["start"]["dateTime"], "2021-04-02T08:00:00");
+        }
+        let bob_url = cal_url.join("E2").unwrap();
+        {
+            let cal = local_cal.lock().unwrap();
+            assert!(cal.get_item_by_url_sync(&local_url).is_none());
+            assert!(matches!(cal.get_item_by_url_sync(&bob_url).unwrap().sync_status(), SyncStatus::Synced(_)));
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 2);
+        }
+
+        // Remote changes are listed with the delta link of the previous sync
+        {
+            let mut server = server.lock().unwrap();
+            server.change(serde_json::json!({ "id": "E0", "@removed": { "reason": "deleted" } }));
+            let mut bob = server.events[2].clone();
+            bob["subject"] = "Call Alice".into();
+            server.change(bob);
+            server.requests.clear();
+        }
+        assert!(provider.sync().await.is_success());
+        assert!(server.lock().unwrap().requests.iter().all(|request| request.contains("deltatoken=")));
+        {
+            let cal = local_cal.lock().unwrap();
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+            assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Alice");
+        }
+
+        // The delta link is kept when the app is restarted
+        drop(local_cal);
+        drop(provider);
+        {
+            let mut server = server.lock().unwrap();
+            let mut bob = server.events[2].clone();
+            bob["subject"] = "Call Carol".into();
+            server.change(bob);
+            server.requests.clear();
+        }
+        let source = GraphCalendars::with_base_url(base_url, AccessToken::new("token".to_string()));
+        let mut provider = GraphCalendarProvider::new(source, Cache::from_folder(folder).unwrap());
+        assert!(provider.sync().await.is_success());
+        assert!(server.lock().unwrap().requests.iter().all(|request| request.contains("deltatoken=")));
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Carol");
+
+        // Expired delta links are replaced by a full listing
+        {
+            let mut server = server.lock().unwrap();
+            server.expired = true;
+            let mut bob = server.events[2].clone();
+            bob["subject"] = "Call Dave".into();
+            server.change(bob);
+        }
+        assert!(provider.sync().await.is_success());
+        {
+            let cal = local_cal.lock().unwrap();
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+            assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Dave");
+        }
+
+        // Local deletions are uploaded
+        local_cal.lock().unwrap().mark_for_deletion_sync(&bob_url).unwrap();
+        assert!(provider.sync().await.is_success());
+        assert!(server.lock().unwrap().events[2]["@removed"].is_object());
+        assert!(local_cal.lock().unwrap().get_item_urls_sync().unwrap().is_empty());
+    }
+}
+
+#[cfg(all(feature = "integration_tests", feature = "graph"))]
+fn serve_graph_calendar(server: Arc<Mutex<MockEventServer>>) -> url::Url {
+    let root = serve(move |head, body| {
+        let mut request_line = head.split_whitespace();
+        let method = request_line.next().unwrap_or_default();
+        let target = request_line.next().unwrap_or_default();
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let host = head.lines().find_map(|line| line.to_ascii_lowercase().strip_prefix("host:").map(|host| host.trim().to_string())).unwrap_or_default();
+        let mut server = server.lock().unwrap();
+        let delta_token = query.split('&').find_map(|pair| pair.strip_prefix("$deltatoken=")).map(str::to_string);
+        match (method, path.strip_prefix("/v1.0/me/")) {
+            ("GET", Some("calendars")) => (200, serde_json::json!({ "value": [{ "id": "C1", "name": "Calendar", "hexColor": "" }] }).to_string()),
+            ("GET", Some("calendars/C1/events/delta")) => {
+                server.requests.push(query.to_string());
+                let events = match delta_token {
+                    Some(_) if server.expired => return (410, String::new()),
+                    Some(token) => server.changed_since(token.parse().unwrap()),
+                    None => server.events.iter().filter(|event| event["@removed"].is_null()).cloned().collect(),
+                };
+                server.expired = false;
+                let delta_link = format!("http://{}/v1.0/me/calendars/C1/events/delta?$deltatoken={}", host, server.version);
+                (200, serde_json::json!({ "value": events, "@odata.deltaLink": delta_link }).to_string())
+            },
+            ("POST", Some("calendars/C1/events")) => {
+                let mut event: serde_json::Value = serde_json::from_str(body).unwrap();
+                event["id"] = format!("E{}", server.events.len()).into();
+                (201, server.change(event).to_string())
+            },
+            ("PATCH", Some(path)) => match path.strip_prefix("calendars/C1/events/").and_then(|id| server.position(id)) {
+                None => (404, String::new()),
+                Some(i) if !head.to_ascii_lowercase().contains(&format!("if-match: {}", server.events[i]["@odata.etag"].as_str().unwrap())) => (412, String::new()),
+                Some(i) => {
+                    let mut event = server.events[i].clone();
+                    let patch: serde_json::Map<String, serde_json::Value> = serde_json::from_str(body).unwrap();
+                    for (field, value) in patch {
+                        event[field] = value;
+                    }
+                    (200, server.change(event).to_string())
+                },
+            },
+            ("DELETE", Some(path)) => match path.strip_prefix("calendars/C1/events/").and_then(|id| server.position(id)) {
+                None => (404, String::new()),
+                Some(i) => {
+                    let id = server.events[i]["id"].clone();
+                    server.change(serde_json::json!({ "id": id, "@removed": { "reason": "deleted" } }));
+                    (204, String::new())
+                },
+            },
+            _ => (404, String::new()),
+        }
+    });
+    root.join("v1.0/").unwrap()
+}
+
+#[tokio::test]
+#[cfg_attr(not(all(feature="integration_tests", feature="ews")), ignore)]
+async fn test_ews() {
+    #[cfg(all(feature = "integration_tests", feature = "ews"))]
+    {
+        use kitchen_fridge::EwsProvider;
+        use kitchen_fridge::ews::Ews;
+        use kitchen_fridge::traits::BaseCalendar;
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::{Item, Task};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let tasks = Arc::new(Mutex::new(vec![
+            serde_json::json!({ "id": "AAMk/T0+", "change_key": "0", "subject": "Water the plants", "status": "NotStarted" }),
+        ]));
+        let endpoint = serve_ews(tasks.clone());
+
+        let source = Ews::new(endpoint.as_str(), "user", "password").unwrap();
+        let cal_url = source.task_folder_url("AAMk/F1+");
+        let mut provider = EwsProvider::new(source, Cache::new_in_memory());
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        let plants_url = local_cal.lock().unwrap().get_item_urls_sync().unwrap().into_iter().next().unwrap();
+        assert_eq!(plants_url.as_str(), format!("{}AAMk%2FT0+", cal_url));
+        assert_eq!(local_cal.lock().unwrap().name(), "Tasks");
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&plants_url).unwrap().unwrap_task().name(), "Water the plants");
+
+        // Exchange chooses the IDs of new items
+        let task = Task::new("Call Bob".to_string(), false, &cal_url);
+        let local_url = task.url().clone();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        local_cal.lock().unwrap().get_item_by_url_mut_sync(&plants_url).unwrap().unwrap_task_mut().set_completion_status(kitchen_fridge::task::CompletionStatus::Completed(None));
+        assert!(provider.sync().await.is_success());
+        {
+            let tasks = tasks.lock().unwrap();
+            assert_eq!(tasks[0]["status"], "Completed");
+            assert_eq!(tasks[1]["subject"], "Call Bob");
+        }
+        let bob_url = format!("{}AAMk%2FT1+", cal_url).parse().unwrap();
+        {
+            let cal = local_cal.lock().unwrap();
+            assert!(cal.get_item_by_url_sync(&local_url).is_none());
+            assert!(matches!(cal.get_item_by_url_sync(&bob_url).unwrap().sync_status(), SyncStatus::Synced(_)));
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 2);
+        }
+
+        // Remote changes are downloaded at the next sync
+        {
+            let mut tasks = tasks.lock().unwrap();
+            tasks[0]["deleted"] = true.into();
+            tasks[1]["subject"] = "Call Alice".into();
+            tasks[1]["change_key"] = "renamed".into();
+        }
+        assert!(provider.sync().await.is_success());
+        let cal = local_cal.lock().unwrap();
+        assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+        assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_task().name(), "Call Alice");
+    }
+}
+
+/// Serve the default task folder of a fake EWS endpoint on a local port, and return the URL of this endpoint
+///
+/// This is only a small subset of EWS, that does not check credentials, and whose SyncFolderItems replies always list every item
+#[cfg(all(feature = "integration_tests", feature = "ews"))]
+fn serve_ews(tasks: Arc<Mutex<Vec<serde_json::Value>>>) -> url::Url {
+    use ews_soap::{between, reply};
+    fn task_xml(task: &serde_json::Value) -> String {
+        format!(r#"<t:Task><t:ItemId Id="{}" ChangeKey="{}"/><t:Subject>{}</t:Subject><t:Status>{}</t:Status></t:Task>"#,
+            task["id"].as_str().unwrap(), task["change_key"].as_str().unwrap(), task["subject"].as_str().unwrap(), task["status"].as_str().unwrap())
+    }
+
+    let root = serve(move |_, body| {
+        let mut tasks = tasks.lock().unwrap();
+        let operation = ews_soap::operation(body);
+        let find = |tasks: &[serde_json::Value], id: &str| tasks.iter().position(|task| task["id"] == id && task["deleted"] != true);
+        match operation.as_str() {
+            "GetFolder" => reply("GetFolder", r#"<m:GetFolderResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode>
+                <m:Folders><t:TasksFolder><t:FolderId Id="AAMk/F1+"/><t:DisplayName>Tasks</t:DisplayName></t:TasksFolder></m:Folders></m:GetFolderResponseMessage>"#),
+            "FindFolder" => reply("FindFolder", r#"<m:FindFolderResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode>
+                <m:RootFolder TotalItemsInView="0" IncludesLastItemInRange="true"><t:Folders/></m:RootFolder></m:FindFolderResponseMessage>"#),
+            "SyncFolderItems" => {
+                let changes: String = tasks.iter().map(|task| match task["deleted"] == true {
+                    true => format!(r#"<t:Delete><t:ItemId Id="{}"/></t:Delete>"#, task["id"].as_str().unwrap()),
+                    false => format!("<t:Create>{}</t:Create>", task_xml(task)),
+                }).collect();
+                reply("SyncFolderItems", &format!(r#"<m:SyncFolderItemsResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode>
+                    <m:SyncState>1</m:SyncState><m:IncludesLastItemInRange>true</m:IncludesLastItemInRange><m:Changes>{}</m:Changes></m:SyncFolderItemsResponseMessage>"#, changes))
+            },
+            "GetItem" => {
+                let messages: String = body.split("<t:ItemId Id=\"").skip(1)
+                    .map(|rest| match find(&tasks, rest.split('"').next().unwrap()) {
+                        None => r#"<m:GetItemResponseMessage ResponseClass="Error"><m:ResponseCode>ErrorItemNotFound</m:ResponseCode></m:GetItemResponseMessage>"#.to_string(),
+                        Some(i) => format!(r#"<m:GetItemResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode><m:Items>{}</m:Items></m:GetItemResponseMessage>"#, task_xml(&tasks[i])),
+                    })
+                    .collect();
+                reply("GetItem", &messages)
+            },
+            "CreateItem" => {
+                let task = serde_json::json!({
+                    "id": format!("AAMk/T{}+", tasks.len()), "change_key": "0",
+                    "subject": between(body, "<t:Subject>", "<").unwrap(), "status": between(body, "<t:Status>", "<").unwrap(),
+                });
+                let message = format!(r#"<m:CreateItemResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode><m:Items>{}</m:Items></m:CreateItemResponseMessage>"#, task_xml(&task));
+                tasks.push(task);
+                reply("CreateItem", &message)
+            },
+            "UpdateItem" => {
+                let id = between(body, "<t:ItemId Id=\"", "\"").unwrap();
+                let message = match find(&tasks, id) {
+                    Some(i) if between(body, "ChangeKey=\"", "\"") == tasks[i]["change_key"].as_str() => {
+                        tasks[i]["subject"] = between(body, "<t:Subject>", "<").unwrap().into();
+                        tasks[i]["status"] = between(body, "<t:Status>", "<").unwrap().into();
+                        tasks[i]["change_key"] = "1".into();
+                        format!(r#"<m:UpdateItemResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode><m:Items>{}</m:Items></m:UpdateItemResponseMessage>"#, task_xml(&tasks[i]))
+                    },
+                    _ => r#"<m:UpdateItemResponseMessage ResponseClass="Error"><m:ResponseCode>ErrorIrresolvableConflict</m:ResponseCode></m:UpdateItemResponseMessage>"#.to_string(),
+                };
+                reply("UpdateItem", &message)
+            },
+            _ => (500, String::new()),
+        }
+    });
+    root.join("EWS/Exchange.asmx").unwrap()
+}
+
+/// Helpers to read the requests and to write the replies of fake EWS endpoints
+#[cfg(all(feature = "integration_tests", feature = "ews"))]
+mod ews_soap {
+    pub fn between<'a>(text: &'a str, start: &str, end: &str) -> Option<&'a str> {
+        let from = text.find(start)? + start.len();
+        text[from..].find(end).map(|len| &text[from..from + len])
+    }
+
+    /// The name of the operation of a request (e.g. `GetItem`)
+    pub fn operation(body: &str) -> String {
+        between(body, "<soap:Body><m:", ">").unwrap_or_default().split_whitespace().next().unwrap_or_default().to_string()
+    }
+
+    pub fn reply(operation: &str, messages: &str) -> (u16, String) {
+        (200, format!(r#"<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body>
+            <m:{0}Response xmlns:m="http://schemas.microsoft.com/exchange/services/2006/messages" xmlns:t="http://schemas.microsoft.com/exchange/services/2006/types">
+            <m:ResponseMessages>{1}</m:ResponseMessages></m:{0}Response></s:Body></s:Envelope>"#, operation, messages))
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(all(feature="integration_tests", feature="ews")), ignore)]
+async fn test_ews_calendar() {
+    #[cfg(all(feature = "integration_tests", feature = "ews"))]
+    {
+        use kitchen_fridge::EwsCalendarProvider;
+        use kitchen_fridge::ews::EwsCalendars;
+        use kitchen_fridge::traits::BaseCalendar;
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::{Event, Item};
+        use std::path::Path;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let folder = Path::new("test_cache/ews_calendar");
+        let _ = std::fs::remove_dir_all(folder);
+        let server = Arc::new(Mutex::new(MockEventServer::new("change_key")));
+        server.lock().unwrap().change(serde_json::json!({ "id": "AAMk/E0+", "subject": "Water the plants",
+            "start": "2021-04-01T10:00:00Z", "end": "2021-04-01T10:30:00Z" }));
+        let endpoint = serve_ews_calendar(server.clone());
+
+        let source = EwsCalendars::new(endpoint.as_str(), "user", "password").unwrap();
+        let cal_url = source.calendar_folder_url("AAMk/C1+");
+        let plants_url: url::Url = format!("{}AAMk%2FE0+", cal_url).parse().unwrap();
+        let mut provider = EwsCalendarProvider::new(source, Cache::new(folder));
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().name(), "Calendar");
+        assert_eq!(local_cal.lock().unwrap().get_item_urls_sync().unwrap().len(), 1);
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&plants_url).unwrap().unwrap_event().name(), "Water the plants");
+
+        // Exchange chooses the IDs of new items
+        let date = |name: &str, value: &str| ical::property::Property{ name: name.to_string(), params: None, value: Some(value.to_string()) };
+        let event = Event::new("Call Bob".to_string(), date("DTSTART", "20210402T080000Z"), date("DTEND", "20210402T083000Z"), &cal_url);
+        let local_url = event.url().clone();
+        let bob_uid = event.uid().to_string();
+        local_cal.lock().unwrap().add_item_sync(Item::Event(event)).unwrap();
+        local_cal.lock().unwrap().get_item_by_url_mut_sync(&plants_url).unwrap().unwrap_event_mut().set_name("Water the cactus".to_string());
+        assert!(provider.sync().await.is_success());
+        {
+            let server = server.lock().unwrap();
+            assert_eq!(server.events[0]["subject"], "Water the cactus");
+            assert_eq!(server.events[1]["subject"], "Call Bob");
+            assert_eq!(server.events[1]["start"], "2021-04-02T08:00:00Z");
+        }
+        let bob_url = format!("{}AAMk%2FE1+", cal_url).parse().unwrap();
+        {
+            let cal = local_cal.lock().unwrap();
+            assert!(cal.get_item_by_url_sync(&local_url).is_none());
+            let bob = cal.get_item_by_url_sync(&bob_url).unwrap();
+            assert!(matches!(bob.sync_status(), SyncStatus::Synced(_)));
+            assert_eq!(bob.uid(), bob_uid);
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 2);
+        }
+
+        // Remote changes are downloaded with the sync state of the previous sync
+        {
+            let mut server = server.lock().unwrap();
+            server.change(serde_json::json!({ "id": "AAMk/E0+", "deleted": true }));
+            let mut bob = server.events[1].clone();
+            bob["subject"] = "Call Alice".into();
+            server.change(bob);
+            server.requests.clear();
+        }
+        assert!(provider.sync().await.is_success());
+        assert!(server.lock().unwrap().requests.iter().all(|sync_state| !sync_state.is_empty()));
+        {
+            let cal = local_cal.lock().unwrap();
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+            assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Alice");
+        }
+
+        // The sync state is kept when the app is restarted
+        drop(local_cal);
+        drop(provider);
+        {
+            let mut server = server.lock().unwrap();
+            let mut bob = server.events[1].clone();
+            bob["subject"] = "Call Carol".into();
+            server.change(bob);
+            server.requests.clear();
+        }
+        let source = EwsCalendars::new(endpoint.as_str(), "user", "password").unwrap();
+        let mut provider = EwsCalendarProvider::new(source, Cache::from_folder(folder).unwrap());
+        assert!(provider.sync().await.is_success());
+        assert!(server.lock().unwrap().requests.iter().all(|sync_state| !sync_state.is_empty()));
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Carol");
+
+        // Sync states that are not valid any more are replaced by a full listing
+        {
+            let mut server = server.lock().unwrap();
+            server.expired = true;
+            let mut bob = server.events[1].clone();
+            bob["subject"] = "Call Dave".into();
+            server.change(bob);
+        }
+        assert!(provider.sync().await.is_success());
+        {
+            let cal = local_cal.lock().unwrap();
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+            assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Dave");
+        }
+
+        // Local deletions are uploaded
+        local_cal.lock().unwrap().mark_for_deletion_sync(&bob_url).unwrap();
+        assert!(provider.sync().await.is_success());
+        assert_eq!(server.lock().unwrap().events[1]["deleted"], true);
+        assert!(local_cal.lock().unwrap().get_item_urls_sync().unwrap().is_empty());
+    }
+}
+
+/// Serve the default calendar folder of a fake EWS endpoint on a local port, and return the URL of this endpoint
+///
+/// Sync states are the versions of the server, and change keys are the versions items have last been changed at
+#[cfg(all(feature = "integration_tests", feature = "ews"))]
+fn serve_ews_calendar(server: Arc<Mutex<MockEventServer>>) -> url::Url {
+    use ews_soap::{between, reply};
+    fn calendar_item_xml(event: &serde_json::Value) -> String {
+        let uid = event["uid"].as_str().map(|uid| format!("<t:UID>{}</t:UID>", uid)).unwrap_or_default();
+        format!(r#"<t:CalendarItem><t:ItemId Id="{}" ChangeKey="{}"/><t:Subject>{}</t:Subject>{}<t:Start>{}</t:Start><t:End>{}</t:End></t:CalendarItem>"#,
+            event["id"].as_str().unwrap(), event["change_key"].as_str().unwrap().trim_matches('"'), event["subject"].as_str().unwrap(), uid,
+            event["start"].as_str().unwrap(), event["end"].as_str().unwrap())
+    }
+
+    let root = serve(move |_, body| {
+        let mut server = server.lock().unwrap();
+        let operation = ews_soap::operation(body);
+        let find = |server: &MockEventServer, id: &str| server.position(id).filter(|i| server.events[*i]["deleted"] != true);
+        match operation.as_str() {
+            "GetFolder" => reply("GetFolder", r#"<m:GetFolderResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode>
+                <m:Folders><t:CalendarFolder><t:FolderId Id="AAMk/C1+"/><t:DisplayName>Calendar</t:DisplayName></t:CalendarFolder></m:Folders></m:GetFolderResponseMessage>"#),
+            "FindFolder" => reply("FindFolder", r#"<m:FindFolderResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode>
+                <m:RootFolder TotalItemsInView="0" IncludesLastItemInRange="true"><t:Folders/></m:RootFolder></m:FindFolderResponseMessage>"#),
+            "SyncFolderItems" => {
+                let sync_state = between(body, "<m:SyncState>", "<").map(str::to_string);
+                server.requests.push(sync_state.clone().unwrap_or_default());
+                let events = match sync_state {
+                    Some(_) if server.expired => return reply("SyncFolderItems", r#"<m:SyncFolderItemsResponseMessage ResponseClass="Error">
+                        <m:ResponseCode>ErrorInvalidSyncStateData</m:ResponseCode></m:SyncFolderItemsResponseMessage>"#),
+                    Some(sync_state) => server.changed_since(sync_state.parse().unwrap()),
+                    None => server.events.iter().filter(|event| event["deleted"] != true).cloned().collect(),
+                };
+                server.expired = false;
+                let changes: String = events.iter().map(|event| match event["deleted"] == true {
+                    true => format!(r#"<t:Delete><t:ItemId Id="{}"/></t:Delete>"#, event["id"].as_str().unwrap()),
+                    false => format!("<t:Create>{}</t:Create>", calendar_item_xml(event)),
+                }).collect();
+                reply("SyncFolderItems", &format!(r#"<m:SyncFolderItemsResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode>
+                    <m:SyncState>{}</m:SyncState><m:IncludesLastItemInRange>true</m:IncludesLastItemInRange><m:Changes>{}</m:Changes></m:SyncFolderItemsResponseMessage>"#,
+                    server.version, changes))
+            },
+            "GetItem" => {
+                let messages: String = body.split("<t:ItemId Id=\"").skip(1)
+                    .map(|rest| match find(&server, rest.split('"').next().unwrap()) {
+                        None => r#"<m:GetItemResponseMessage ResponseClass="Error"><m:ResponseCode>ErrorItemNotFound</m:ResponseCode></m:GetItemResponseMessage>"#.to_string(),
+                        Some(i) => format!(r#"<m:GetItemResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode><m:Items>{}</m:Items></m:GetItemResponseMessage>"#,
+                            calendar_item_xml(&server.events[i])),
+                    })
+                    .collect();
+                reply("GetItem", &messages)
+            },
+            "CreateItem" => {
+                let id = format!("AAMk/E{}+", server.events.len());
+                let event = server.change(serde_json::json!({
+                    "id": id, "uid": between(body, "<t:UID>", "<").unwrap(),
+                    "subject": between(body, "<t:Subject>", "<").unwrap(),
+                    "start": between(body, "<t:Start>", "<").unwrap(), "end": between(body, "<t:End>", "<").unwrap(),
+                }));
+                reply("CreateItem", &format!(r#"<m:CreateItemResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode>
+                    <m:Items>{}</m:Items></m:CreateItemResponseMessage>"#, calendar_item_xml(&event)))
+            },
+            "UpdateItem" => {
+                let id = between(body, "<t:ItemId Id=\"", "\"").unwrap();
+                let message = match find(&server, id) {
+                    Some(i) if between(body, "ChangeKey=\"", "\"") == server.events[i]["change_key"].as_str().map(|key| key.trim_matches('"')) => {
+                        let mut event = server.events[i].clone();
+                        for field in ["Subject", "Start", "End"] {
+                            event[field.to_ascii_lowercase()] = between(body, &format!("<t:{}>", field), "<").unwrap().into();
+                        }
+                        let event = server.change(event);
+                        format!(r#"<m:UpdateItemResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode><m:Items>{}</m:Items></m:UpdateItemResponseMessage>"#,
+                            calendar_item_xml(&event))
+                    },
+                    _ => r#"<m:UpdateItemResponseMessage ResponseClass="Error"><m:ResponseCode>ErrorIrresolvableConflict</m:ResponseCode></m:UpdateItemResponseMessage>"#.to_string(),
+                };
+                reply("UpdateItem", &message)
+            },
+            "DeleteItem" => {
+                let id = between(body, "<t:ItemId Id=\"", "\"").unwrap().to_string();
+                let message = match find(&server, &id) {
+                    None => r#"<m:DeleteItemResponseMessage ResponseClass="Error"><m:ResponseCode>ErrorItemNotFound</m:ResponseCode></m:DeleteItemResponseMessage>"#,
+                    Some(_) => {
+                        server.change(serde_json::json!({ "id": id, "deleted": true }));
+                        r#"<m:DeleteItemResponseMessage ResponseClass="Success"><m:ResponseCode>NoError</m:ResponseCode></m:DeleteItemResponseMessage>"#
+                    },
+                };
+                reply("DeleteItem", message)
+            },
+            _ => (500, String::new()),
+        }
+    });
+    root.join("EWS/Exchange.asmx").unwrap()
+}
+
+#[tokio::test]
+#[cfg_attr(not(all(feature="integration_tests", feature="jmap")), ignore)]
+async fn test_jmap() {
+    #[cfg(all(feature = "integration_tests", feature = "jmap"))]
+    {
+        use kitchen_fridge::JmapProvider;
+        use kitchen_fridge::jmap::Jmap;
+        use kitchen_fridge::traits::BaseCalendar;
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::{Item, Task};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let tasks = Arc::new(Mutex::new(vec![
+            serde_json::json!({ "id": "T0", "uid": "plants", "taskListId": "L1", "title": "Water the plants", "progress": "needs-action", "priority": 1, "modseq": 1 }),
+            serde_json::json!({ "id": "T1", "uid": "other", "taskListId": "L2", "title": "In another list", "progress": "needs-action", "modseq": 1 }),
+        ]));
+        let session_url = serve_jmap(tasks.clone());
+
+        let source = Jmap::new(session_url.as_str(), "user", "password").unwrap();
+        let cal_url = source.task_list_url("L1");
+        let plants_url = cal_url.join("T0").unwrap();
+        source.wait_for_changes().await.unwrap();
+        let mut provider = JmapProvider::new(source, Cache::new_in_memory());
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().name(), "Chores");
+        assert_eq!(local_cal.lock().unwrap().get_item_urls_sync().unwrap().len(), 1);
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&plants_url).unwrap().uid(), "plants");
+
+        // JMAP servers choose the IDs of new tasks
+        let task = Task::new("Call Bob".to_string(), false, &cal_url);
+        let local_url = task.url().clone();
+        let bob_uid = task.uid().to_string();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        local_cal.lock().unwrap().get_item_by_url_mut_sync(&plants_url).unwrap().unwrap_task_mut().set_completion_status(kitchen_fridge::task::CompletionStatus::Completed(None));
+        assert!(provider.sync().await.is_success());
+        {
+            let tasks = tasks.lock().unwrap();
+            assert_eq!(tasks[0]["progress"], "completed");
+            assert_eq!(tasks[0]["priority"], 1);
+            assert_eq!(tasks[2]["title"], "Call Bob");
+            assert_eq!(tasks[2]["uid"], bob_uid.as_str());
+        }
+        let bob_url = cal_url.join("T2").unwrap();
+        {
+            let cal = local_cal.lock().unwrap();
+            assert!(cal.get_item_by_url_sync(&local_url).is_none());
+            assert!(matches!(cal.get_item_by_url_sync(&bob_url).unwrap().sync_status(), SyncStatus::Synced(_)));
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 2);
+        }
+
+        // Remote changes are downloaded at the next sync (with Task/changes)
+        {
+            let mut tasks = tasks.lock().unwrap();
+            tasks[0]["destroyed"] = true.into();
+            tasks[0]["modseq"] = 10.into();
+            tasks[2]["title"] = "Call Alice".into();
+            tasks[2]["modseq"] = 10.into();
+        }
+        assert!(provider.sync().await.is_success());
+        let cal = local_cal.lock().unwrap();
+        assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+        assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_task().name(), "Call Alice");
+    }
+}
+
+/// Serve the "Chores" task list (`L1`) of a fake JMAP server on a local port, and return the URL of its session resource
+///
+/// This is only a small subset of JMAP, that does not check credentials. The state of the tasks is the highest `modseq` of the given tasks,
+/// and tasks are only flagged as `destroyed` when they are deleted
+#[cfg(all(feature = "integration_tests", feature = "jmap"))]
+fn serve_jmap(tasks: Arc<Mutex<Vec<serde_json::Value>>>) -> url::Url {
+    use serde_json::{json, Value};
+
+    let root = serve(move |head, body| {
+        let mut request_line = head.split_whitespace();
+        let (method, path) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+        let mut tasks = tasks.lock().unwrap();
+        match (method, path.split('?').next().unwrap_or_default()) {
+            ("GET", "/jmap/session") => return (200, json!({
+                "apiUrl": "/jmap/api", "eventSourceUrl": "/jmap/events?types={types}&closeafter={closeafter}&ping={ping}",
+                "primaryAccounts": { "urn:ietf:params:jmap:tasks": "A1" },
+            }).to_string()),
+            ("GET", "/jmap/events") => {
+                assert!(path.contains("closeafter=state"));
+                return (200, "event: state\ndata: {\"@type\":\"StateChange\",\"changed\":{}}\n\n".to_string());
+            },
+            ("POST", "/jmap/api") => (),
+            _ => return (404, String::new()),
+        }
+
+        let request: Value = serde_json::from_str(body).unwrap();
+        let mut responses: Vec<Value> = Vec::new();
+        for call in request["methodCalls"].as_array().unwrap() {
+            let (name, mut args) = (call[0].as_str().unwrap(), call[1].clone());
+            if let Some(reference) = args.get("#ids").cloned() {
+                let previous = responses.iter().find(|response| response[2] == reference["resultOf"]).unwrap();
+                let path: Vec<_> = reference["path"].as_str().unwrap().split('/').skip(1).collect();
+                args["ids"] = resolve(&previous[1], &path);
+            }
+            let state = tasks.iter().filter_map(|task| task["modseq"].as_u64()).max().unwrap_or(0);
+            let live = |task: &&Value| task["destroyed"] != true;
+            let result = match name {
+                "TaskList/get" => json!({ "list": [{ "id": "L1", "name": "Chores" }, { "id": "L2", "name": "Groceries" }] }),
+                "Task/query" => json!({ "ids": tasks.iter().filter(live).filter(|task| task["taskListId"] == args["filter"]["inTaskList"]).map(|task| task["id"].clone()).collect::<Vec<_>>() }),
+                "Task/get" => {
+                    let list: Vec<_> = tasks.iter().filter(live)
+                        .filter(|task| args["ids"].is_null() || args["ids"].as_array().unwrap().contains(&task["id"]))
+                        .map(|task| {
+                            let mut task = task.clone();
+                            task.as_object_mut().unwrap().remove("modseq");
+                            task
+                        })
+                        .collect();
+                    json!({ "state": state.to_string(), "list": list })
+                },
+                "Task/changes" => {
+                    let since: u64 = args["sinceState"].as_str().unwrap().parse().unwrap();
+                    let changed: Vec<_> = tasks.iter().filter(|task| task["modseq"].as_u64().unwrap() > since).collect();
+                    json!({
+                        "newState": state.to_string(), "hasMoreChanges": false, "created": [],
+                        "updated": changed.iter().filter(|task| live(task)).map(|task| task["id"].clone()).collect::<Vec<_>>(),
+                        "destroyed": changed.iter().filter(|task| !live(task)).map(|task| task["id"].clone()).collect::<Vec<_>>(),
+                    })
+                },
+                "Task/set" => {
+                    let mut created = serde_json::Map::new();
+                    for (creation_id, task) in args["create"].as_object().into_iter().flatten() {
+                        let id = format!("T{}", tasks.len());
+                        let mut task = task.clone();
+                        task["id"] = id.clone().into();
+                        task["modseq"] = (state + 1).into();
+                        tasks.push(task);
+                        created.insert(creation_id.clone(), json!({ "id": id }));
+                    }
+                    let mut not_updated = serde_json::Map::new();
+                    for (id, patch) in args["update"].as_object().into_iter().flatten() {
+                        match tasks.iter_mut().find(|task| &task["id"] == id && task["destroyed"] != true) {
+                            None => { not_updated.insert(id.clone(), json!({ "type": "notFound" })); },
+                            Some(task) => {
+                                for (property, value) in patch.as_object().unwrap() {
+                                    task[property] = value.clone();
+                                }
+                                task["modseq"] = (state + 1).into();
+                            },
+                        }
+                    }
+                    for id in args["destroy"].as_array().into_iter().flatten() {
+                        if let Some(task) = tasks.iter_mut().find(|task| &task["id"] == id) {
+                            task["destroyed"] = true.into();
+                            task["modseq"] = (state + 1).into();
+                        }
+                    }
+                    json!({ "created": created, "notUpdated": not_updated })
+                },
+                _ => { responses.push(json!(["error", { "type": "unknownMethod" }, call[2]])); continue; },
+            };
+            responses.push(json!([name, result, call[2]]));
+        }
+        (200, json!({ "methodResponses": responses }).to_string())
+    });
+    root.join("jmap/session").unwrap()
+}
+
+#[tokio::test]
+#[cfg_attr(not(all(feature="integration_tests", feature="jmap")), ignore)]
+async fn test_jmap_calendar() {
+    #[cfg(all(feature = "integration_tests", feature = "jmap"))]
+    {
+        use kitchen_fridge::JmapCalendarProvider;
+        use kitchen_fridge::jmap::JmapCalendars;
+        use kitchen_fridge::traits::BaseCalendar;
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::{Event, Item};
+        use std::path::Path;
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let folder = Path::new("test_cache/jmap_calendar");
+        let _ = std::fs::remove_dir_all(folder);
+        let server = Arc::new(Mutex::new(MockEventServer::new("modseq")));
+        server.lock().unwrap().change(serde_json::json!({ "id": "E0", "uid": "plants", "calendarIds": { "C1": true }, "title": "Water the plants",
+            "start": "2021-04-01T10:00:00", "timeZone": "Etc/UTC", "duration": "PT30M" }));
+        server.lock().unwrap().change(serde_json::json!({ "id": "E1", "uid": "other", "calendarIds": { "C2": true }, "title": "In another calendar",
+            "start": "2021-04-01T10:00:00", "duration": "PT30M" }));
+        let session_url = serve_jmap_calendar(server.clone());
+
+        let source = JmapCalendars::new(session_url.as_str(), "user", "password").unwrap();
+        let cal_url = source.calendar_url("C1");
+        let plants_url = cal_url.join("E0").unwrap();
+        let mut provider = JmapCalendarProvider::new(source, Cache::new(folder));
+        assert!(provider.sync().await.is_success());
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().name(), "Calendar");
+        assert_eq!(local_cal.lock().unwrap().get_item_urls_sync().unwrap().len(), 1);
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&plants_url).unwrap().unwrap_event().name(), "Water the plants");
+
+        // JMAP servers choose the IDs of new events
+        let date = |name: &str, value: &str| ical::property::Property{ name: name.to_string(), params: None, value: Some(value.to_string()) };
+        let event = Event::new("Call Bob".to_string(), date("DTSTART", "20210402T080000Z"), date("DTEND", "20210402T083000Z"), &cal_url);
+        let local_url = event.url().clone();
+        let bob_uid = event.uid().to_string();
+        local_cal.lock().unwrap().add_item_sync(Item::Event(event)).unwrap();
+        local_cal.lock().unwrap().get_item_by_url_mut_sync(&plants_url).unwrap().unwrap_event_mut().set_name("Water the cactus".to_string());
+        assert!(provider.sync().await.is_success());
+        {
+            let server = server.lock().unwrap();
+            assert_eq!(server.events[0]["title"], "Water the cactus");
+            assert_eq!(server.events[2]["title"], "Call Bob");
+            assert_eq!(server.events[2]["uid"], bob_uid.as_str());
+            assert_eq!(server.events[2]["calendarIds"]["C1"], true);
+            assert_eq!(server.events[2]["start"], "2021-04-02T08:00:00");
+            assert_eq!(server.events[2]["duration"], "PT30M");
+        }
+        let bob_url = cal_url.join("E2").unwrap();
+        {
+            let cal = local_cal.lock().unwrap();
+            assert!(cal.get_item_by_url_sync(&local_url).is_none());
+            assert!(matches!(cal.get_item_by_url_sync(&bob_url).unwrap().sync_status(), SyncStatus::Synced(_)));
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 2);
+        }
+
+        // Remote changes are listed with CalendarEvent/changes, from the state of the previous sync
+        {
+            let mut server = server.lock().unwrap();
+            server.change(serde_json::json!({ "id": "E0", "destroyed": true }));
+            let mut bob = server.events[2].clone();
+            bob["title"] = "Call Alice".into();
+            server.change(bob);
+            let mut other = server.events[1].clone();
+            other["title"] = "Still in another calendar".into();
+            server.change(other);
+            server.requests.clear();
+        }
+        assert!(provider.sync().await.is_success());
+        assert!(server.lock().unwrap().requests.iter().all(|request| request.starts_with("sinceState=")));
+        {
+            let cal = local_cal.lock().unwrap();
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+            assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Alice");
+        }
+
+        // The state is kept when the app is restarted
+        drop(local_cal);
+        drop(provider);
+        {
+            let mut server = server.lock().unwrap();
+            let mut bob = server.events[2].clone();
+            bob["title"] = "Call Carol".into();
+            server.change(bob);
+            server.requests.clear();
+        }
+        let source = JmapCalendars::new(session_url.as_str(), "user", "password").unwrap();
+        let mut provider = JmapCalendarProvider::new(source, Cache::from_folder(folder).unwrap());
+        assert!(provider.sync().await.is_success());
+        assert!(server.lock().unwrap().requests.iter().all(|request| request.starts_with("sinceState=")));
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Carol");
+
+        // States the server cannot compute changes from any more are replaced by a full listing
+        {
+            let mut server = server.lock().unwrap();
+            server.expired = true;
+            let mut bob = server.events[2].clone();
+            bob["title"] = "Call Dave".into();
+            server.change(bob);
+        }
+        assert!(provider.sync().await.is_success());
+        {
+            let cal = local_cal.lock().unwrap();
+            assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+            assert_eq!(cal.get_item_by_url_sync(&bob_url).unwrap().unwrap_event().name(), "Call Dave");
+        }
+
+        // Local deletions are uploaded
+        local_cal.lock().unwrap().mark_for_deletion_sync(&bob_url).unwrap();
+        assert!(provider.sync().await.is_success());
+        assert_eq!(server.lock().unwrap().events[2]["destroyed"], true);
+        assert!(local_cal.lock().unwrap().get_item_urls_sync().unwrap().is_empty());
+    }
+}
+
+/// Serve the calendars `C1` and `C2` of a fake JMAP server on a local port, and return the URL of its session resource
+///
+/// The state of the events is the version of the mock server, and events are only flagged as `destroyed` when they are deleted
+#[cfg(all(feature = "integration_tests", feature = "jmap"))]
+fn serve_jmap_calendar(server: Arc<Mutex<MockEventServer>>) -> url::Url {
+    use serde_json::{json, Value};
+
+    let root = serve(move |head, body| {
+        let mut request_line = head.split_whitespace();
+        let (method, path) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+        match (method, path) {
+            ("GET", "/jmap/session") => return (200, json!({
+                "apiUrl": "/jmap/api", "eventSourceUrl": "/jmap/events?types={types}&closeafter={closeafter}&ping={ping}",
+                "primaryAccounts": { "urn:ietf:params:jmap:calendars": "A1" },
+            }).to_string()),
+            ("POST", "/jmap/api") => (),
+            _ => return (404, String::new()),
+        }
+
+        let mut server = server.lock().unwrap();
+        let request: Value = serde_json::from_str(body).unwrap();
+        let mut responses: Vec<Value> = Vec::new();
+        for call in request["methodCalls"].as_array().unwrap() {
+            let (name, mut args) = (call[0].as_str().unwrap(), call[1].clone());
+            if let Some(reference) = args.get("#ids").cloned() {
+                let previous = responses.iter().find(|response| response[2] == reference["resultOf"]).unwrap();
+                if previous[0] == "error" {
+                    responses.push(json!(["error", { "type": "invalidResultReference" }, call[2]]));
+                    continue;
+                }
+                let path: Vec<_> = reference["path"].as_str().unwrap().split('/').skip(1).collect();
+                args["ids"] = resolve(&previous[1], &path);
+            }
+            let live = |event: &&Value| event["destroyed"] != true;
+            let result = match name {
+                "Calendar/get" => json!({ "list": [{ "id": "C1", "name": "Calendar" }, { "id": "C2", "name": "Work" }] }),
+                "CalendarEvent/query" => {
+                    server.requests.push("query".to_string());
+                    server.expired = false;
+                    let in_calendar = args["filter"]["inCalendar"].as_str().unwrap();
+                    json!({ "ids": server.events.iter().filter(live).filter(|event| event["calendarIds"][in_calendar] == true).map(|event| event["id"].clone()).collect::<Vec<_>>() })
+                },
+                "CalendarEvent/get" => {
+                    let list: Vec<_> = server.events.iter().filter(live)
+                        .filter(|event| args["ids"].as_array().unwrap().contains(&event["id"]))
+                        .map(|event| {
+                            let mut event = event.clone();
+                            event.as_object_mut().unwrap().remove("modseq");
+                            event
+                        })
+                        .collect();
+                    json!({ "state": server.version.to_string(), "list": list })
+                },
+                "CalendarEvent/changes" => {
+                    server.requests.push(format!("sinceState={}", args["sinceState"].as_str().unwrap()));
+                    if server.expired {
+                        responses.push(json!(["error", { "type": "cannotCalculateChanges" }, call[2]]));
+                        continue;
+                    }
+                    let changed = server.changed_since(args["sinceState"].as_str().unwrap().parse().unwrap());
+                    json!({
+                        "oldState": args["sinceState"], "newState": server.version.to_string(), "hasMoreChanges": false, "created": [],
+                        "updated": changed.iter().filter(live).map(|event| event["id"].clone()).collect::<Vec<_>>(),
+                        "destroyed": changed.iter().filter(|event| !live(event)).map(|event| event["id"].clone()).collect::<Vec<_>>(),
+                    })
+                },
+                "CalendarEvent/set" => {
+                    let mut created = serde_json::Map::new();
+                    for (creation_id, event) in args["create"].as_object().into_iter().flatten() {
+                        let id = format!("E{}", server.events.len());
+                        let mut event = event.clone();
+                        event["id"] = id.clone().into();
+                        server.change(event);
+                        created.insert(creation_id.clone(), json!({ "id": id }));
+                    }
+                    let mut not_updated = serde_json::Map::new();
+                    for (id, patch) in args["update"].as_object().into_iter().flatten() {
+                        match server.position(id).filter(|i| server.events[*i]["destroyed"] != true) {
+                            None => { not_updated.insert(id.clone(), json!({ "type": "notFound" })); },
+                            Some(i) => {
+                                let mut event = server.events[i].clone();
+                                for (property, value) in patch.as_object().unwrap() {
+                                    event[property] = value.clone();
+                                }
+                                server.change(event);
+                            },
+                        }
+                    }
+                    for id in args["destroy"].as_array().into_iter().flatten() {
+                        server.change(json!({ "id": id, "destroyed": true }));
+                    }
+                    json!({ "created": created, "notUpdated": not_updated })
+                },
+                _ => { responses.push(json!(["error", { "type": "unknownMethod" }, call[2]])); continue; },
+            };
+            responses.push(json!([name, result, call[2]]));
+        }
+        (200, json!({ "methodResponses": responses }).to_string())
+    });
+    root.join("jmap/session").unwrap()
+}
+
+/// Follow a JSON pointer of a result reference of a JMAP request, where `*` maps the rest of the pointer over the values of an array or an object
+#[cfg(all(feature = "integration_tests", feature = "jmap"))]
+fn resolve(value: &serde_json::Value, path: &[&str]) -> serde_json::Value {
+    use serde_json::Value;
+
+    match path.split_first() {
+        None => value.clone(),
+        Some((&"*", rest)) => match value {
+            Value::Object(map) => Value::Array(map.values().map(|value| resolve(value, rest)).collect()),
+            _ => Value::Array(value.as_array().into_iter().flatten().map(|value| resolve(value, rest)).collect()),
+        },
+        Some((key, rest)) => resolve(&value[*key], rest),
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(all(feature="integration_tests", feature="etebase")), ignore)]
+async fn test_etebase() {
+    #[cfg(all(feature = "integration_tests", feature = "etebase"))]
+    {
+        use kitchen_fridge::EtebaseProvider;
+        use kitchen_fridge::etebase::{Etebase, EtebaseCollection, EtebaseItem, TASKS_TYPE};
+        use kitchen_fridge::traits::BaseCalendar;
+        use kitchen_fridge::item::SyncStatus;
+        use kitchen_fridge::{Item, Task};
+
+        let _ = env_logger::builder().is_test(true).try_init();
+        let trash_ics = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//EteSync//EN\r\nBEGIN:VTODO\r\nUID:trash\r\nDTSTAMP:20210101T100000Z\r\nSUMMARY:Take out the trash\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        let account = Arc::new(FakeEtebaseAccount::default());
+        account.collections.lock().unwrap().push(EtebaseCollection{ uid: "col1".to_string(), collection_type: TASKS_TYPE.to_string(), name: "Chores".to_string(), color: Some("#ff0000".to_string()) });
+        account.collections.lock().unwrap().push(EtebaseCollection{ uid: "col2".to_string(), collection_type: "etebase.vcard".to_string(), name: "Contacts".to_string(), color: None });
+        account.items.lock().unwrap().push(EtebaseItem{ uid: "item0".to_string(), etag: "e0".to_string(), content: trash_ics.to_string(), deleted: false });
+
+        let source = Etebase::new("https://etebase.example.com/", account.clone()).unwrap();
+        let cal_url = source.collection_url("col1");
+        let trash_url = cal_url.join("item0").unwrap();
+        let mut provider = EtebaseProvider::new(source, Cache::new_in_memory());
+        assert!(provider.sync().await.is_success());
+        // Collections of other kinds of data are not synced
+        assert_eq!(provider.local().get_calendars_sync().unwrap().len(), 1);
+        let local_cal = provider.local().get_calendar_sync(&cal_url).unwrap();
+        assert_eq!(local_cal.lock().unwrap().name(), "Chores");
+        assert_eq!(local_cal.lock().unwrap().get_item_by_url_sync(&trash_url).unwrap().unwrap_task().name(), "Take out the trash");
+
+        // Etebase chooses the UIDs of new items
+        let task = Task::new("Water the plants".to_string(), false, &cal_url);
+        let local_url = task.url().clone();
+        local_cal.lock().unwrap().add_item_sync(Item::Task(task)).unwrap();
+        local_cal.lock().unwrap().get_item_by_url_mut_sync(&trash_url).unwrap().unwrap_task_mut().set_name("Take out the recycling".to_string());
+        assert!(provider.sync().await.is_success());
+        {
+            let items = account.items.lock().unwrap();
+            assert!(items[0].content.contains("SUMMARY:Take out the recycling"));
+            assert!(items[1].content.contains("SUMMARY:Water the plants"));
+        }
+        let plants_url = cal_url.join("item1").unwrap();
+        {
+            let cal = local_cal.lock().unwrap();
+            assert!(cal.get_item_by_url_sync(&local_url).is_none());
+            assert!(matches!(cal.get_item_by_url_sync(&plants_url).unwrap().sync_status(), SyncStatus::Synced(_)));
+        }
+
+        // Remote changes are downloaded at the next sync
+        account.update("item0", |item| item.deleted = true);
+        account.update("item1", |item| item.content = item.content.replace("Water the plants", "Feed the cat"));
+        assert!(provider.sync().await.is_success());
+        let cal = local_cal.lock().unwrap();
+        assert_eq!(cal.get_item_urls_sync().unwrap().len(), 1);
+        assert_eq!(cal.get_item_by_url_sync(&plants_url).unwrap().unwrap_task().name(), "Feed the cat");
+    }
+}
+
+/// An Etebase account that keeps the (unencrypted) items of a single collection in memory. Every item is returned as a change, whatever the sync token
+#[cfg(all(feature = "integration_tests", feature = "etebase"))]
+#[derive(Debug, Default)]
+struct FakeEtebaseAccount {
+    collections: Mutex<Vec<kitchen_fridge::etebase::EtebaseCollection>>,
+    items: Mutex<Vec<kitchen_fridge::etebase::EtebaseItem>>,
+}
+
+#[cfg(all(feature = "integration_tests", feature = "etebase"))]
+impl FakeEtebaseAccount {
+    fn update<F: FnOnce(&mut kitchen_fridge::etebase::EtebaseItem)>(&self, uid: &str, change: F) {
+        let mut items = self.items.lock().unwrap();
+        let item = items.iter_mut().find(|item| item.uid == uid).unwrap();
+        change(item);
+        item.etag = format!("{}-changed", item.etag);
+    }
+}
+
+#[cfg(all(feature = "integration_tests", feature = "etebase"))]
+#[async_trait::async_trait]
+impl kitchen_fridge::etebase::EtebaseAccount for FakeEtebaseAccount {
+    async fn list_collections(&self) -> Result<Vec<kitchen_fridge::etebase::EtebaseCollection>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.collections.lock().unwrap().clone())
+    }
+
+    async fn create_collection(&self, _collection_type: &str, _name: &str) -> Result<kitchen_fridge::etebase::EtebaseCollection, Box<dyn std::error::Error + Send + Sync>> {
+        Err("Not supported".into())
+    }
+
+    async fn fetch_changes(&self, _collection_uid: &str, _stoken: Option<&str>) -> Result<kitchen_fridge::etebase::EtebaseChanges, Box<dyn std::error::Error + Send + Sync>> {
+        let items = self.items.lock().unwrap().clone();
+        Ok(kitchen_fridge::etebase::EtebaseChanges{ stoken: Some(items.len().to_string()), items, done: true })
+    }
+
+    async fn create_item(&self, _collection_uid: &str, content: &str) -> Result<kitchen_fridge::etebase::EtebaseItem, Box<dyn std::error::Error + Send + Sync>> {
+        let mut items = self.items.lock().unwrap();
+        let item = kitchen_fridge::etebase::EtebaseItem{ uid: format!("item{}", items.len()), etag: "e0".to_string(), content: content.to_string(), deleted: false };
+        items.push(item.clone());
+        Ok(item)
+    }
+
+    async fn update_item(&self, _collection_uid: &str, item: &kitchen_fridge::etebase::EtebaseItem) -> Result<kitchen_fridge::etebase::EtebaseItem, Box<dyn std::error::Error + Send + Sync>> {
+        let mut items = self.items.lock().unwrap();
+        let current = items.iter_mut().find(|current| current.uid == item.uid).ok_or("Unknown item")?;
+        if current.etag != item.etag {
+            return Err("Conflict".into());
+        }
+        *current = kitchen_fridge::etebase::EtebaseItem{ etag: format!("{}-updated", item.etag), ..item.clone() };
+        Ok(current.clone())
+    }
+}
+
+/// Serve an iCal file over HTTP on a local port, and return its URL
+#[cfg(feature = "integration_tests")]
+fn serve_feed(feed: Arc<Mutex<String>>, statuses: Arc<Mutex<Vec<u16>>>) -> url::Url {
+    let root = serve_with_headers(move |head, _| {
+        let feed = feed.lock().unwrap();
+        let etag = {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            feed.hash(&mut hasher);
+            format!("\"{:x}\"", hasher.finish())
+        };
+        let status = match head.contains(&format!("if-none-match: {}", etag)) {
+            true => 304,
+            false => 200,
+        };
+        statuses.lock().unwrap().push(status);
+        match status {
+            304 => (304, String::new(), String::new()),
+            _ => (200, format!("ETag: {}\r\n", etag), feed.clone()),
+        }
+    });
+    root.join("feeds/chores.ics").unwrap()
+}
+
+/// Answer HTTP requests on a local port with `handler`, that gets the head and the body of every request, and returns the status and the body of the reply.
+/// Returns the root URL of this server
+#[cfg(feature = "integration_tests")]
+fn serve<F>(handler: F) -> url::Url
+    where F: Fn(&str, &str) -> (u16, String) + Send + 'static
+{
+    serve_with_headers(move |head, body| {
+        let (status, reply) = handler(head, body);
+        (status, String::new(), reply)
+    })
+}
+
+/// Like [`serve`], with handlers that also return the extra header lines of their replies (each one ending with `\r\n`)
+#[cfg(feature = "integration_tests")]
+fn serve_with_headers<F>(handler: F) -> url::Url
+    where F: Fn(&str, &str) -> (u16, String, String) + Send + 'static
+{
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap()).parse().unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Err(_) => continue,
+                Ok(stream) => stream,
+            };
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            let head_len = loop {
+                if let Some(pos) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                    break pos + 4;
+                }
+                match stream.read(&mut buffer) {
+                    Ok(0) | Err(_) => break request.len(),
+                    Ok(n) => request.extend_from_slice(&buffer[..n]),
+                }
+            };
+            let head = String::from_utf8_lossy(&request[..head_len]).to_string();
+            let content_length = head.to_ascii_lowercase().lines()
+                .find_map(|line| line.strip_prefix("content-length:").map(str::to_string))
+                .and_then(|length| length.trim().parse().ok())
+                .unwrap_or(0);
+            while request.len() < head_len + content_length {
+                match stream.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buffer[..n]),
+                }
+            }
+            let body = String::from_utf8_lossy(&request[head_len..]).to_string();
+            let (status, headers, reply) = handler(&head, &body);
+            let _ = write!(stream, "HTTP/1.1 {} Reply\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}", status, headers, reply.len(), reply);
+        }
+    });
+    url
+}
+
 #[cfg(feature = "integration_tests")]
 use kitchen_fridge::{traits::CalDavSource,
                provider::Provider,